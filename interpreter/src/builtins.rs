@@ -0,0 +1,487 @@
+//! The parts of the javascript standard library programs use most, so they run without node:
+//! `console`, `Math`, `String`, `Number`, `parseInt`, `parseFloat` and `isNaN`, and the methods
+//! of arrays, strings and numbers
+
+use std::cmp::Ordering;
+
+use crate::{
+    eval::{clamped_range, error, slice_value, Eval, InterpreterContext},
+    value::{number_to_string, Value},
+};
+
+/// `Math.PI`, and the functions of the globals, which are called through [`Value::Native`]
+pub fn native_member(global: &'static str, name: &str) -> Value {
+    match (global, name) {
+        ("Math", "PI") => Value::Number(std::f64::consts::PI),
+        ("Math", "E") => Value::Number(std::f64::consts::E),
+        ("Number", "MAX_SAFE_INTEGER") => Value::Number(oxidescript::parser::ast::MAX_SAFE_INTEGER),
+        ("Number", "EPSILON") => Value::Number(f64::EPSILON),
+        ("console", "log" | "error")
+        | (
+            "Math",
+            "floor" | "ceil" | "round" | "trunc" | "abs" | "sqrt" | "min" | "max" | "pow"
+            | "random" | "sign" | "sin" | "cos" | "log",
+        )
+        | ("Number", "isInteger" | "isNaN" | "parseFloat" | "parseInt") => {
+            Value::Native(native_name(global, name))
+        }
+        _ => Value::Undefined,
+    }
+}
+
+/// The `'static` name of `global.name`, which natives are identified by
+fn native_name(global: &str, name: &str) -> &'static str {
+    const NAMES: &[&str] = &[
+        "console.log",
+        "console.error",
+        "Math.floor",
+        "Math.ceil",
+        "Math.round",
+        "Math.trunc",
+        "Math.abs",
+        "Math.sqrt",
+        "Math.min",
+        "Math.max",
+        "Math.pow",
+        "Math.random",
+        "Math.sign",
+        "Math.sin",
+        "Math.cos",
+        "Math.log",
+        "Number.isInteger",
+        "Number.isNaN",
+        "Number.parseFloat",
+        "Number.parseInt",
+    ];
+    let qualified = format!("{}.{}", global, name);
+    NAMES
+        .iter()
+        .find(|native| **native == qualified)
+        .expect("every native member has a name")
+}
+
+impl InterpreterContext<'_> {
+    pub fn call_native(&mut self, native: &'static str, arguments: Vec<Value>) -> Eval {
+        let number = |index: usize| arguments.get(index).map_or(f64::NAN, Value::to_number);
+        let result = match native {
+            "console.log" => return self.print(&arguments, false),
+            "console.error" => return self.print(&arguments, true),
+            "Math.floor" => number(0).floor(),
+            "Math.ceil" => number(0).ceil(),
+            // `Math.round` rounds halves up, `f64::round` away from zero
+            "Math.round" => (number(0) + 0.5).floor(),
+            "Math.trunc" => number(0).trunc(),
+            "Math.abs" => number(0).abs(),
+            "Math.sqrt" => number(0).sqrt(),
+            "Math.sign" => match number(0) {
+                value if value == 0.0 || value.is_nan() => value,
+                value => value.signum(),
+            },
+            "Math.sin" => number(0).sin(),
+            "Math.cos" => number(0).cos(),
+            "Math.log" => number(0).ln(),
+            "Math.pow" => number(0).powf(number(1)),
+            "Math.min" => {
+                arguments
+                    .iter()
+                    .map(Value::to_number)
+                    .fold(f64::INFINITY, |min, value| {
+                        match value.is_nan() || min.is_nan() {
+                            true => f64::NAN,
+                            false => min.min(value),
+                        }
+                    })
+            }
+            "Math.max" => {
+                arguments
+                    .iter()
+                    .map(Value::to_number)
+                    .fold(f64::NEG_INFINITY, |max, value| {
+                        match value.is_nan() || max.is_nan() {
+                            true => f64::NAN,
+                            false => max.max(value),
+                        }
+                    })
+            }
+            "Math.random" => random(),
+            "String" => {
+                return Ok(Value::string(
+                    arguments.first().map_or(String::new(), Value::to_string),
+                ))
+            }
+            "Number" => arguments.first().map_or(0.0, Value::to_number),
+            "isNaN" => return Ok(Value::Boolean(number(0).is_nan())),
+            "Number.isNaN" => {
+                return Ok(Value::Boolean(matches!(
+                    arguments.first(),
+                    Some(Value::Number(value)) if value.is_nan()
+                )))
+            }
+            "Number.isInteger" => {
+                return Ok(Value::Boolean(matches!(
+                    arguments.first(),
+                    Some(Value::Number(value)) if value.is_finite() && value.fract() == 0.0
+                )))
+            }
+            "parseFloat" | "Number.parseFloat" => {
+                parse_float(&arguments.first().map_or(String::new(), Value::to_string))
+            }
+            "parseInt" | "Number.parseInt" => {
+                let radix = match arguments.get(1) {
+                    Some(radix) => radix.to_number() as u32,
+                    None => 10,
+                };
+                parse_int(
+                    &arguments.first().map_or(String::new(), Value::to_string),
+                    radix,
+                )
+            }
+            native => return Err(error(format!("`{}` is not a function", native))),
+        };
+        Ok(Value::Number(result))
+    }
+
+    /// The methods of arrays, strings and numbers
+    pub fn builtin_method(&mut self, receiver: &Value, name: &str, arguments: Vec<Value>) -> Eval {
+        let argument = |index: usize| arguments.get(index).cloned().unwrap_or(Value::Undefined);
+        let unknown = || {
+            error(format!(
+                "`{}` is not a function of `{}`",
+                name,
+                receiver.inspect()
+            ))
+        };
+        match receiver {
+            Value::Array(items) => {
+                let snapshot = || items.borrow().clone();
+                match name {
+                    "push" => {
+                        items.borrow_mut().extend(arguments);
+                        Ok(Value::Number(items.borrow().len() as f64))
+                    }
+                    "pop" => Ok(items.borrow_mut().pop().unwrap_or(Value::Undefined)),
+                    "shift" => {
+                        let mut items = items.borrow_mut();
+                        match items.is_empty() {
+                            true => Ok(Value::Undefined),
+                            false => Ok(items.remove(0)),
+                        }
+                    }
+                    // removes `count` items from `start`, or every item from it on, puts the other
+                    // arguments in their place and returns the removed items
+                    "splice" => {
+                        let length = items.borrow().len();
+                        let (start, _) = slice_bounds(&arguments, length);
+                        let end = match arguments.get(1) {
+                            None | Some(Value::Undefined) => length,
+                            Some(count) => start + count.to_number().trunc().max(0.0) as usize,
+                        };
+                        let removed = items
+                            .borrow_mut()
+                            .splice(start..end.min(length), arguments.into_iter().skip(2))
+                            .collect();
+                        Ok(Value::array(removed))
+                    }
+                    "includes" => Ok(Value::Boolean(snapshot().iter().any(|item| {
+                        item.strict_equals(&argument(0))
+                            || matches!((item, argument(0)), (Value::Number(a), Value::Number(b)) if a.is_nan() && b.is_nan())
+                    }))),
+                    "indexOf" => Ok(Value::Number(
+                        snapshot()
+                            .iter()
+                            .position(|item| item.strict_equals(&argument(0)))
+                            .map_or(-1.0, |index| index as f64),
+                    )),
+                    "join" => {
+                        let separator = match argument(0) {
+                            Value::Undefined => ",".to_string(),
+                            separator => separator.to_string(),
+                        };
+                        let items = snapshot()
+                            .iter()
+                            .map(|item| match item {
+                                Value::Undefined | Value::Null => String::new(),
+                                item => item.to_string(),
+                            })
+                            .collect::<Vec<_>>();
+                        Ok(Value::string(items.join(&separator)))
+                    }
+                    "slice" => {
+                        let (start, end) = slice_bounds(&arguments, items.borrow().len());
+                        Ok(slice_value(receiver, start, end))
+                    }
+                    "concat" => {
+                        let mut result = snapshot();
+                        for argument in arguments {
+                            match argument {
+                                Value::Array(other) => result.extend(other.borrow().clone()),
+                                argument => result.push(argument),
+                            }
+                        }
+                        Ok(Value::array(result))
+                    }
+                    "reverse" => {
+                        items.borrow_mut().reverse();
+                        Ok(receiver.clone())
+                    }
+                    "sort" => {
+                        let mut sorted = snapshot();
+                        let compare = argument(0);
+                        let mut failure = None;
+                        // `sort` without a function compares the items as strings
+                        sorted.sort_by(|a, b| {
+                            if failure.is_some() {
+                                return Ordering::Equal;
+                            }
+                            match &compare {
+                                Value::Undefined => a
+                                    .to_string()
+                                    .encode_utf16()
+                                    .cmp(b.to_string().encode_utf16()),
+                                compare => match self.call_value(compare, vec![a.clone(), b.clone()]) {
+                                    Ok(order) => order
+                                        .to_number()
+                                        .partial_cmp(&0.0)
+                                        .unwrap_or(Ordering::Equal),
+                                    Err(flow) => {
+                                        failure = Some(flow);
+                                        Ordering::Equal
+                                    }
+                                },
+                            }
+                        });
+                        if let Some(flow) = failure {
+                            return Err(flow);
+                        }
+                        *items.borrow_mut() = sorted;
+                        Ok(receiver.clone())
+                    }
+                    "map" | "filter" | "forEach" | "find" | "findIndex" | "some" | "every" => {
+                        self.iterate(receiver, name, &argument(0))
+                    }
+                    "reduce" => {
+                        let mut items = snapshot().into_iter().enumerate();
+                        let mut accumulator = match arguments.get(1) {
+                            Some(initial) => initial.clone(),
+                            None => match items.next() {
+                                Some((_, first)) => first,
+                                None => {
+                                    return Err(error("reduce of empty array with no initial value"))
+                                }
+                            },
+                        };
+                        for (index, item) in items {
+                            accumulator = self.call_value(
+                                &argument(0),
+                                vec![accumulator, item, Value::Number(index as f64)],
+                            )?;
+                        }
+                        Ok(accumulator)
+                    }
+                    "toString" => Ok(Value::string(receiver.to_string())),
+                    _ => Err(unknown()),
+                }
+            }
+            Value::String(text) => {
+                let string = |index: usize| argument(index).to_string();
+                match name {
+                    "startsWith" => Ok(Value::Boolean(text.starts_with(&string(0)))),
+                    "endsWith" => Ok(Value::Boolean(text.ends_with(&string(0)))),
+                    "includes" => Ok(Value::Boolean(text.contains(&string(0)))),
+                    "indexOf" => Ok(Value::Number(match text.find(&string(0)) {
+                        Some(index) => text[..index].encode_utf16().count() as f64,
+                        None => -1.0,
+                    })),
+                    "toUpperCase" => Ok(Value::string(text.to_uppercase())),
+                    "toLowerCase" => Ok(Value::string(text.to_lowercase())),
+                    "trim" => Ok(Value::string(text.trim())),
+                    "trimStart" => Ok(Value::string(text.trim_start())),
+                    "trimEnd" => Ok(Value::string(text.trim_end())),
+                    "repeat" => Ok(Value::string(
+                        text.repeat(argument(0).to_number().max(0.0) as usize),
+                    )),
+                    "slice" | "substring" => {
+                        let length = text.encode_utf16().count();
+                        let (start, end) = slice_bounds(&arguments, length);
+                        Ok(slice_value(receiver, start, end))
+                    }
+                    "charAt" => Ok(
+                        match self.index(receiver, &Value::Number(argument(0).to_number()))? {
+                            Value::Undefined => Value::string(""),
+                            char => char,
+                        },
+                    ),
+                    "charCodeAt" => Ok(Value::Number(
+                        text.encode_utf16()
+                            .nth(argument(0).to_number() as usize)
+                            .map_or(f64::NAN, f64::from),
+                    )),
+                    "split" => {
+                        let parts = match argument(0) {
+                            Value::Undefined => vec![Value::String(text.clone())],
+                            separator => match separator.to_string().as_str() {
+                                "" => text
+                                    .chars()
+                                    .map(|char| Value::string(char.to_string()))
+                                    .collect(),
+                                separator => text.split(separator).map(Value::string).collect(),
+                            },
+                        };
+                        Ok(Value::array(parts))
+                    }
+                    "replaceAll" => Ok(Value::string(text.replace(&string(0), &string(1)))),
+                    "replace" => Ok(Value::string(text.replacen(&string(0), &string(1), 1))),
+                    "padStart" | "padEnd" => {
+                        let width = argument(0).to_number().max(0.0) as usize;
+                        let fill = match argument(1) {
+                            Value::Undefined => " ".to_string(),
+                            fill => fill.to_string(),
+                        };
+                        let length = text.encode_utf16().count();
+                        if width <= length || fill.is_empty() {
+                            return Ok(receiver.clone());
+                        }
+                        let padding = fill
+                            .chars()
+                            .cycle()
+                            .take(width - length)
+                            .collect::<String>();
+                        Ok(Value::string(match name {
+                            "padStart" => format!("{}{}", padding, text),
+                            _ => format!("{}{}", text, padding),
+                        }))
+                    }
+                    "toString" => Ok(receiver.clone()),
+                    _ => Err(unknown()),
+                }
+            }
+            Value::Number(value) => match name {
+                "toString" => Ok(Value::string(number_to_string(*value))),
+                "toFixed" => {
+                    let digits = argument(0).to_number().max(0.0) as usize;
+                    Ok(Value::string(format!("{:.*}", digits, value)))
+                }
+                _ => Err(unknown()),
+            },
+            Value::Boolean(value) if name == "toString" => Ok(Value::string(value.to_string())),
+            Value::Native(global) => {
+                let member = native_member(global, name);
+                self.call_value(&member, arguments)
+            }
+            _ => Err(unknown()),
+        }
+    }
+
+    /// The methods of arrays that call a function with every item, its index and the array
+    fn iterate(&mut self, array: &Value, name: &str, callback: &Value) -> Eval {
+        let items = match array {
+            Value::Array(items) => items.borrow().clone(),
+            _ => unreachable!("only arrays are iterated"),
+        };
+        let mut output = vec![];
+        for (index, item) in items.into_iter().enumerate() {
+            let result = self.call_value(
+                callback,
+                vec![item.clone(), Value::Number(index as f64), array.clone()],
+            )?;
+            match name {
+                "map" => output.push(result),
+                "filter" if result.is_truthy() => output.push(item),
+                "find" if result.is_truthy() => return Ok(item),
+                "findIndex" if result.is_truthy() => return Ok(Value::Number(index as f64)),
+                "some" if result.is_truthy() => return Ok(Value::Boolean(true)),
+                "every" if !result.is_truthy() => return Ok(Value::Boolean(false)),
+                _ => {}
+            }
+        }
+        Ok(match name {
+            "map" | "filter" => Value::array(output),
+            "find" => Value::Undefined,
+            "findIndex" => Value::Number(-1.0),
+            "some" => Value::Boolean(false),
+            "every" => Value::Boolean(true),
+            _ => Value::Undefined,
+        })
+    }
+}
+
+/// The bounds of `slice(start, end)` on an array or a string of the length
+fn slice_bounds(arguments: &[Value], length: usize) -> (usize, usize) {
+    let bound = |index: usize| match arguments.get(index) {
+        None | Some(Value::Undefined) => None,
+        Some(bound) => Some(bound.to_number().trunc()),
+    };
+    clamped_range(bound(0), bound(1), length)
+}
+
+/// The number at the start of the text, `parseFloat("1.5px")` is `1.5`
+fn parse_float(text: &str) -> f64 {
+    let text = text.trim_start();
+    (1..=text.len())
+        .rev()
+        .filter(|end| text.is_char_boundary(*end))
+        .find_map(|end| {
+            let prefix = &text[..end];
+            // rust parses `inf` and `nan`, javascript only `Infinity`
+            match prefix.chars().last()?.is_ascii_digit() || prefix.ends_with('.') {
+                true => prefix.parse::<f64>().ok(),
+                false => prefix
+                    .trim_start_matches(['+', '-'])
+                    .eq("Infinity")
+                    .then(|| prefix.replace("Infinity", "inf").parse().ok())
+                    .flatten(),
+            }
+        })
+        .unwrap_or(f64::NAN)
+}
+
+/// The integer at the start of the text in the radix, `parseInt("12px")` is `12`
+fn parse_int(text: &str, radix: u32) -> f64 {
+    let text = text.trim_start();
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(text) => (-1.0, text),
+        None => (1.0, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (radix, text) = match (radix, text.get(..2)) {
+        (0 | 16, Some("0x" | "0X")) => (16, &text[2..]),
+        (0, _) => (10, text),
+        (radix, _) => (radix, text),
+    };
+    if !(2..=36).contains(&radix) {
+        return f64::NAN;
+    }
+    let digits = text
+        .chars()
+        .map_while(|char| char.to_digit(radix))
+        .collect::<Vec<_>>();
+    if digits.is_empty() {
+        return f64::NAN;
+    }
+    sign * digits
+        .into_iter()
+        .fold(0.0, |value, digit| value * radix as f64 + digit as f64)
+}
+
+/// A number from 0 up to 1, from a xorshift generator seeded by the clock
+fn random() -> f64 {
+    use std::{
+        cell::Cell,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0x2545_f491_4f6c_dd1d, |time| time.as_nanos() as u64)
+                | 1,
+        );
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
@@ -0,0 +1,1326 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    io::Write,
+    rc::Rc,
+};
+
+use oxidescript::parser::{
+    ast::{
+        AssignmentExpr, Block, CallExpr, Declaration, Expression, ForExpr, Identifier, IfExpr,
+        ImplDecl, ImplItem, InfixOperator, Literal, ModDecl, OptionalExpr, Pattern, SliceExpr,
+        Span, Statement, TraitDecl, UnaryOperator, WhileExpr, MAX_SAFE_INTEGER,
+    },
+    enums::{declares_enum, lower_enums},
+    optional::has_optional,
+    types::{impl_fn, type_as_literal, union_members},
+};
+
+use crate::{
+    value::{to_int32, Function, Promise, StructValue, Table, Value},
+    InterpreterOptions, RuntimeError,
+};
+
+/// How deep functions can call each other before the program fails, like javascript does when
+/// its call stack overflows
+const MAX_DEPTH: usize = 2_000;
+
+/// The variables of a block, function or module, and the scope around it
+#[derive(Default)]
+pub struct Scope {
+    variables: RefCell<HashMap<String, Value>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub fn new(parent: Option<&Rc<Scope>>) -> Rc<Scope> {
+        Rc::new(Scope {
+            variables: RefCell::default(),
+            parent: parent.cloned(),
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        match self.variables.borrow().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref()?.get(name),
+        }
+    }
+
+    fn declare(&self, name: &str, value: Value) {
+        self.variables.borrow_mut().insert(name.to_string(), value);
+    }
+
+    /// Returns `false` if no scope declares the name
+    fn assign(&self, name: &str, value: Value) -> bool {
+        if let Some(variable) = self.variables.borrow_mut().get_mut(name) {
+            *variable = value;
+            return true;
+        }
+        match &self.parent {
+            Some(parent) => parent.assign(name, value),
+            None => false,
+        }
+    }
+}
+
+/// Why evaluating stopped before there was a value
+pub enum Flow {
+    Return(Value),
+    Break,
+    Continue,
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Flow {
+    fn from(error: RuntimeError) -> Self {
+        Flow::Error(error)
+    }
+}
+
+pub type Eval = Result<Value, Flow>;
+
+pub fn error(message: impl Into<String>) -> Flow {
+    Flow::Error(RuntimeError {
+        message: message.into(),
+    })
+}
+
+pub struct InterpreterContext<'a> {
+    pub options: &'a InterpreterOptions,
+    /// Where `println` and `console.log` write to
+    pub out: &'a mut dyn Write,
+    /// Where `eprintln` and `console.error` write to
+    pub err: &'a mut dyn Write,
+    /// The impl tables by the name of their type, which methods called through struct values are
+    /// looked up in
+    types: HashMap<String, Rc<Table>>,
+    /// The traits declared so far, whose default methods impls that don't override them share
+    traits: HashMap<String, TraitDecl>,
+    depth: usize,
+    /// The promises of `async` calls whose task didn't run yet, oldest first
+    tasks: VecDeque<Rc<RefCell<Promise>>>,
+    /// Prelude functions the program declares itself, which are called instead of the intrinsics
+    pub shadowed_prelude: HashSet<String>,
+}
+
+impl<'a> InterpreterContext<'a> {
+    pub fn new(
+        options: &'a InterpreterOptions,
+        out: &'a mut dyn Write,
+        err: &'a mut dyn Write,
+    ) -> Self {
+        InterpreterContext {
+            options,
+            out,
+            err,
+            types: HashMap::new(),
+            traits: HashMap::new(),
+            depth: 0,
+            tasks: VecDeque::new(),
+            shadowed_prelude: HashSet::new(),
+        }
+    }
+
+    /// Runs the statements in the scope. Functions, impl blocks and traits are hoisted like in
+    /// javascript, so statements in front of them can call them
+    pub fn statements(&mut self, statements: &[Statement], scope: &Rc<Scope>) -> Result<(), Flow> {
+        if declares_enum(statements) {
+            return self.statements(&lower_enums(statements), scope);
+        }
+        let declarations = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::DeclarationStatement(declaration, _) => Some(declaration),
+                Statement::ExpressionStatement { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        for declaration in &declarations {
+            match declaration {
+                Declaration::FunctionDeclaration {
+                    name,
+                    parameters,
+                    body,
+                    is_async,
+                    ..
+                } => {
+                    let parameters = parameters
+                        .iter()
+                        .map(|parameter| parameter.name.0.clone())
+                        .collect();
+                    let function = function(&name.0, parameters, body, *is_async, scope);
+                    scope.declare(&name.0, function);
+                }
+                Declaration::TraitDeclaration(decl) => {
+                    self.traits.insert(decl.name.0.clone(), decl.clone());
+                    if decl.has_defaults() {
+                        let defaults = ImplDecl {
+                            target: decl.name.clone(),
+                            trait_: None,
+                            items: decl.defaults().into_iter().map(ImplItem::Method).collect(),
+                        };
+                        self.impl_table(&defaults, scope);
+                    }
+                }
+                _ => {}
+            }
+        }
+        // constants of impl blocks can call the functions of the other impl blocks
+        let impls = declarations
+            .iter()
+            .filter_map(|declaration| match declaration {
+                Declaration::ImplDeclaration(decl) => Some((decl, self.impl_table(decl, scope))),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (decl, (table, self_scope)) in impls {
+            for item in &decl.items {
+                if let ImplItem::Const(name, value) = item {
+                    let value = self.expression(value, &self_scope)?;
+                    table.items.borrow_mut().insert(name.0.clone(), value);
+                }
+            }
+        }
+        for statement in statements.iter().filter(|statement| !is_hoisted(statement)) {
+            self.statement(statement, scope)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement, scope: &Rc<Scope>) -> Result<(), Flow> {
+        let declaration = match statement {
+            Statement::ExpressionStatement { expression, .. } => {
+                return self.discarded(expression, scope)
+            }
+            Statement::DeclarationStatement(declaration, _) => declaration,
+        };
+        match declaration {
+            Declaration::LetDeclaration(name, value, _, _)
+            | Declaration::ConstDeclaration(name, value, _, _, _) => {
+                let value = self.expression(value, scope)?;
+                scope.declare(&name.0, value);
+            }
+            Declaration::DestructuringDeclaration(pattern, value, _, _) => {
+                let value = self.expression(value, scope)?;
+                self.destructure(pattern, &value, scope)?;
+            }
+            Declaration::ModDeclaration(decl) => {
+                let module = self.module(decl, scope)?;
+                scope.declare(&decl.name.0, module);
+            }
+            Declaration::ModFileDeclaration(name) => {
+                return Err(error(format!(
+                    "`mod {};` is not supported by the interpreter",
+                    name.0
+                )))
+            }
+            // struct values are created by their literals, types and externs only exist for
+            // checking and uses are lowered to constants
+            Declaration::StructDeclaration(_)
+            | Declaration::TypeDeclaration(_)
+            | Declaration::ExternDeclaration(_)
+            | Declaration::UseDeclaration(_) => {}
+            Declaration::EnumDeclaration(_) => unreachable!("enums are lowered to structs"),
+            Declaration::FunctionDeclaration { .. }
+            | Declaration::ImplDeclaration(_)
+            | Declaration::TraitDeclaration(_) => unreachable!("declarations are hoisted"),
+        }
+        Ok(())
+    }
+
+    /// The table of the impl block's type with the methods of the block, the one an earlier impl
+    /// block of the type in the same scope created or a new one. Returns the scope of the methods,
+    /// where `Self` is the table
+    fn impl_table(&mut self, decl: &ImplDecl, scope: &Rc<Scope>) -> (Rc<Table>, Rc<Scope>) {
+        let existing = match scope.variables.borrow().get(&decl.target.0) {
+            Some(Value::Table(table)) => Some(table.clone()),
+            _ => None,
+        };
+        let table = existing.unwrap_or_else(|| {
+            let table = Rc::new(Table {
+                name: decl.target.0.clone(),
+                ..Table::default()
+            });
+            scope.declare(&decl.target.0, Value::Table(table.clone()));
+            self.types.insert(decl.target.0.clone(), table.clone());
+            table
+        });
+        let self_scope = Scope::new(Some(scope));
+        self_scope.declare("Self", Value::Table(table.clone()));
+        for item in &decl.items {
+            let (method, items) = match item {
+                ImplItem::Method(method) => (method, &table.items),
+                ImplItem::Getter(method) => (method, &table.getters),
+                ImplItem::Setter(method) => (method, &table.setters),
+                ImplItem::Const(_, _) => continue,
+            };
+            let parameters = method
+                .has_self
+                .then(|| "self".to_string())
+                .into_iter()
+                .chain(
+                    method
+                        .parameters
+                        .iter()
+                        .map(|parameter| parameter.name.0.clone()),
+                )
+                .collect();
+            let function = function(
+                &method.name.0,
+                parameters,
+                &method.body,
+                method.is_async,
+                &self_scope,
+            );
+            items.borrow_mut().insert(method.name.0.clone(), function);
+        }
+        let inherited = decl
+            .trait_
+            .as_ref()
+            .and_then(|trait_| Some((self.traits.get(&trait_.0)?, scope.get(&trait_.0)?)));
+        if let Some((trait_, Value::Table(defaults))) = inherited {
+            for signature in trait_.inherited(decl) {
+                if let Some(method) = defaults.items.borrow().get(&signature.name.0) {
+                    table
+                        .items
+                        .borrow_mut()
+                        .insert(signature.name.0.clone(), method.clone());
+                }
+            }
+        }
+        (table, self_scope)
+    }
+
+    /// A table of the `pub` items of the module, the others are only visible inside of it
+    fn module(&mut self, decl: &ModDecl, scope: &Rc<Scope>) -> Eval {
+        let statements = decl
+            .items
+            .iter()
+            .map(|item| Statement::DeclarationStatement(item.declaration.clone(), item.span))
+            .collect::<Vec<_>>();
+        let module_scope = Scope::new(Some(scope));
+        self.statements(&statements, &module_scope)?;
+        let table = Table {
+            name: decl.name.0.clone(),
+            ..Table::default()
+        };
+        for item in decl.items.iter().filter(|item| item.is_pub) {
+            for name in declared_names(&item.declaration) {
+                if let Some(value) = module_scope.get(&name.0) {
+                    table.items.borrow_mut().insert(name.0.clone(), value);
+                }
+            }
+        }
+        Ok(Value::Table(Rc::new(table)))
+    }
+
+    /// Binds the names of a `let` pattern to the parts of the value
+    fn destructure(
+        &mut self,
+        pattern: &Pattern,
+        value: &Value,
+        scope: &Rc<Scope>,
+    ) -> Result<(), Flow> {
+        match pattern {
+            Pattern::Binding(name) => scope.declare(&name.0, value.clone()),
+            Pattern::Tuple(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    let part = self.index(value, &Value::Number(index as f64))?;
+                    self.destructure(item, &part, scope)?;
+                }
+            }
+            Pattern::Struct(_, fields) => {
+                for (name, field) in fields {
+                    let part = self.member(value, &name.0)?;
+                    self.destructure(field, &part, scope)?;
+                }
+            }
+            Pattern::Variant(_, fields) => {
+                let values = self.member(value, "values")?;
+                for (index, field) in fields.iter().enumerate() {
+                    let part = self.index(&values, &Value::Number(index as f64))?;
+                    self.destructure(field, &part, scope)?;
+                }
+            }
+            Pattern::Prefix(prefix, Some(name)) => {
+                let Value::String(text) = value else {
+                    return Err(error(format!("`{}` isn't a string", value)));
+                };
+                let rest = text.strip_prefix(prefix.as_str()).unwrap_or(text);
+                scope.declare(&name.0, Value::string(rest));
+            }
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Prefix(_, None) | Pattern::Or(_) => {
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the block in a scope of its own. The body of a `defer` runs once the rest of the
+    /// block is left, however it is left
+    pub fn block(&mut self, block: &Block, scope: &Rc<Scope>) -> Eval {
+        let scope = Scope::new(Some(scope));
+        if let Some(deferred) = block.split_at_defer() {
+            self.statements(&deferred.before, &scope)?;
+            let result = self.block(&deferred.rest, &scope);
+            self.block(&deferred.cleanup, &scope)?;
+            return result;
+        }
+        self.statements(&block.statements, &scope)?;
+        match &block.return_value {
+            Some(value) => self.expression(value, &scope),
+            None => Ok(Value::Undefined),
+        }
+    }
+
+    /// Evaluates an expression whose value isn't used, so loops don't collect the values of their
+    /// iterations
+    fn discarded(&mut self, expression: &Expression, scope: &Rc<Scope>) -> Result<(), Flow> {
+        match expression {
+            Expression::ForExpression(expr, _) => self.for_loop(expr, false, scope)?,
+            Expression::WhileExpression(expr, _) => self.while_loop(expr, false, scope)?,
+            expression => self.expression(expression, scope)?,
+        };
+        Ok(())
+    }
+
+    pub fn expression(&mut self, expression: &Expression, scope: &Rc<Scope>) -> Eval {
+        if has_optional(expression) {
+            return self.optional_chain(expression, scope);
+        }
+        match expression {
+            Expression::IdentifierExpression(name, _) => self.variable(&name.0, scope),
+            Expression::LiteralExpression(literal, _) => Ok(literal_value(literal)),
+            Expression::UnaryExpression(expr, _) => {
+                let value = self.expression(&expr.rhs, scope)?;
+                Ok(match expr.op {
+                    UnaryOperator::LogicalNot => Value::Boolean(!value.is_truthy()),
+                    UnaryOperator::BitwiseNot => Value::Number(!to_int32(value.to_number()) as f64),
+                    UnaryOperator::Minus => Value::Number(-value.to_number()),
+                    UnaryOperator::Plus => Value::Number(value.to_number()),
+                })
+            }
+            Expression::InfixExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, scope)?;
+                // the right side of `&&`, `||` and `??` is only evaluated if it is needed
+                let short_circuit = match expr.op {
+                    InfixOperator::LogicalAnd => !lhs.is_truthy(),
+                    InfixOperator::LogicalOr => lhs.is_truthy(),
+                    InfixOperator::NullishCoalesce => {
+                        !matches!(lhs, Value::Null | Value::Undefined)
+                    }
+                    _ => false,
+                };
+                if short_circuit {
+                    return Ok(lhs);
+                }
+                let rhs = self.expression(&expr.rhs, scope)?;
+                match expr.op {
+                    InfixOperator::LogicalAnd
+                    | InfixOperator::LogicalOr
+                    | InfixOperator::NullishCoalesce => Ok(rhs),
+                    _ => Ok(infix(&expr.op, lhs, rhs)),
+                }
+            }
+            Expression::AssignmentExpression(expr, _) => self.assignment(expr, scope),
+            Expression::ArrayExpression(items, _) | Expression::TupleExpression(items, _) => {
+                Ok(Value::array(self.expressions(items, scope)?))
+            }
+            Expression::IfExpression(expr, _) => self.if_expression(expr, scope),
+            Expression::ForExpression(expr, _) => self.for_loop(expr, true, scope),
+            Expression::WhileExpression(expr, _) => self.while_loop(expr, true, scope),
+            Expression::MatchExpression(expr, _) => self.expression(&expr.clone().into_if(), scope),
+            Expression::BlockExpression(block, _) => self.block(block, scope),
+            Expression::CallExpression(expr, _) => self.call(expr, scope),
+            Expression::TaggedTemplateExpression(expr, _) => {
+                self.expression(&expr.clone().into_call(), scope)
+            }
+            Expression::TemplateExpression(expr, _) => {
+                self.expression(&expr.clone().into_concatenation(), scope)
+            }
+            Expression::IndexExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, scope)?;
+                let index = self.expression(&expr.index, scope)?;
+                self.index(&lhs, &index)
+            }
+            Expression::SliceExpression(expr, _) => self.slice(expr, scope),
+            Expression::MemberAccessExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, scope)?;
+                self.member(&lhs, &expr.ident.0)
+            }
+            Expression::OptionalExpression(_, _) => {
+                unreachable!("optional chains are handled above")
+            }
+            Expression::StructExpression(expr, _) => {
+                let name = match (expr.name.0.as_str(), scope.get("Self")) {
+                    ("Self", Some(Value::Table(table))) => table.name.clone(),
+                    (name, _) => name.to_string(),
+                };
+                let mut fields = vec![];
+                for (field, value) in &expr.fields {
+                    fields.push((field.0.clone(), self.expression(value, scope)?));
+                }
+                Ok(Value::Struct(Rc::new(RefCell::new(StructValue {
+                    name,
+                    fields,
+                }))))
+            }
+            Expression::PathExpression(path, _) => self.path(&path.segments, scope),
+            Expression::ImportExpression(expr, _) => Err(error(format!(
+                "`import(\"{}\")` is not supported by the interpreter",
+                expr.path
+            ))),
+            Expression::ClosureExpression(expr, _) => {
+                let parameters = expr
+                    .parameters
+                    .iter()
+                    .map(|parameter| parameter.name.0.clone())
+                    .collect();
+                Ok(function(
+                    "",
+                    parameters,
+                    &expr.body_block(),
+                    expr.is_async,
+                    scope,
+                ))
+            }
+            // casts only change the type
+            Expression::CastExpression(expr, _) => self.expression(&expr.value, scope),
+            Expression::IsExpression(expr, _) => {
+                let value = self.expression(&expr.value, scope)?;
+                Ok(Value::Boolean(self.is_type(&value, &expr.type_.0)))
+            }
+            Expression::ReturnExpression(value, _) => Err(Flow::Return(match value {
+                Some(value) => self.expression(value, scope)?,
+                None => Value::Undefined,
+            })),
+            Expression::BreakExpression(_) => Err(Flow::Break),
+            Expression::ContinueExpression(_) => Err(Flow::Continue),
+            // blocks run their `defer` statements, the resolver rejects the others
+            Expression::DeferExpression(_, _) => {
+                Err(error("`defer` outside of a block is not supported"))
+            }
+            Expression::WithExpression(_, _) => {
+                unreachable!("`with` is lowered to a block with a `defer`")
+            }
+            Expression::AwaitExpression(value, _) => {
+                let value = self.expression(value, scope)?;
+                self.wait(value)
+            }
+            Expression::RangeExpression(expr, _) => {
+                let start = self.expression(&expr.start, scope)?.to_number();
+                let end = self.expression(&expr.end, scope)?.to_number();
+                let mut numbers = vec![];
+                let mut number = start;
+                while number < end || (expr.inclusive && number == end) {
+                    numbers.push(Value::Number(number));
+                    number += 1.0;
+                }
+                Ok(Value::array(numbers))
+            }
+            Expression::TupleIndexExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, scope)?;
+                self.index(&lhs, &Value::Number(expr.index as f64))
+            }
+        }
+    }
+
+    fn expressions(
+        &mut self,
+        expressions: &[Expression],
+        scope: &Rc<Scope>,
+    ) -> Result<Vec<Value>, Flow> {
+        expressions
+            .iter()
+            .map(|expression| self.expression(expression, scope))
+            .collect()
+    }
+
+    fn variable(&mut self, name: &str, scope: &Rc<Scope>) -> Eval {
+        if let Some(value) = scope.get(name) {
+            return Ok(value);
+        }
+        match name {
+            "null" => Ok(Value::Null),
+            "undefined" => Ok(Value::Undefined),
+            "NaN" => Ok(Value::Number(f64::NAN)),
+            "Infinity" => Ok(Value::Number(f64::INFINITY)),
+            name => match crate::GLOBALS.iter().find(|global| **global == name) {
+                Some(global) => Ok(Value::Native(global)),
+                None => Err(error(format!("`{}` is not defined", name))),
+            },
+        }
+    }
+
+    /// `Type::item` or `module::item`, `Self` is the table of the impl block
+    fn path(&mut self, segments: &[Identifier], scope: &Rc<Scope>) -> Eval {
+        let path = || {
+            segments
+                .iter()
+                .map(|segment| segment.0.as_str())
+                .collect::<Vec<_>>()
+                .join("::")
+        };
+        let mut value = scope
+            .get(&segments[0].0)
+            .ok_or_else(|| error(format!("`{}` is not defined", path())))?;
+        for segment in &segments[1..] {
+            let item = match &value {
+                Value::Table(table) => table.items.borrow().get(&segment.0).cloned(),
+                _ => None,
+            };
+            value = item.ok_or_else(|| error(format!("`{}` is not defined", path())))?;
+        }
+        Ok(value)
+    }
+
+    /// `a?.b.c` is `null` if `a` is, so the rest of the chain is only evaluated if it isn't
+    fn optional_chain(&mut self, expression: &Expression, scope: &Rc<Scope>) -> Eval {
+        let (receiver, rest) = OptionalExpr::split_chain(
+            expression,
+            &Expression::IdentifierExpression(
+                Identifier("__optional".to_string()),
+                Span::default(),
+            ),
+        )
+        .expect("the chain has a `?.` or `?[`");
+        let receiver = self.expression(&receiver, scope)?;
+        if let Value::Null | Value::Undefined = receiver {
+            return Ok(receiver);
+        }
+        let scope = Scope::new(Some(scope));
+        scope.declare("__optional", receiver);
+        self.expression(&rest, &scope)
+    }
+
+    fn if_expression(&mut self, expr: &IfExpr, scope: &Rc<Scope>) -> Eval {
+        if self.expression(&expr.condition, scope)?.is_truthy() {
+            return self.block(&expr.then_block, scope);
+        }
+        for else_if in &expr.else_if_blocks {
+            if self.expression(&else_if.condition, scope)?.is_truthy() {
+                return self.block(&else_if.then_block, scope);
+            }
+        }
+        match &expr.else_block {
+            Some(block) => self.block(block, scope),
+            None => Ok(Value::Undefined),
+        }
+    }
+
+    /// A loop used as a value is the array of the values of its iterations, like in the
+    /// javascript output. Ranges count without building an array, and arrays are read at every
+    /// iteration, so items pushed by the body are looped over as well
+    fn for_loop(&mut self, expr: &ForExpr, collect: bool, scope: &Rc<Scope>) -> Eval {
+        let mut output = collect.then(Vec::new);
+        let name = &expr.lhs.0;
+        match &*expr.rhs {
+            Expression::RangeExpression(range, _) => {
+                let mut number = self.expression(&range.start, scope)?.to_number();
+                let end = self.expression(&range.end, scope)?.to_number();
+                while number < end || (range.inclusive && number == end) {
+                    if !self.iteration(
+                        name,
+                        Value::Number(number),
+                        &expr.body,
+                        &mut output,
+                        scope,
+                    )? {
+                        break;
+                    }
+                    number += 1.0;
+                }
+            }
+            rhs => match self.expression(rhs, scope)? {
+                Value::Array(items) => {
+                    let mut index = 0;
+                    while let Some(item) = items.borrow().get(index).cloned() {
+                        if !self.iteration(name, item, &expr.body, &mut output, scope)? {
+                            break;
+                        }
+                        index += 1;
+                    }
+                }
+                // strings are looped over by their characters
+                Value::String(text) => {
+                    for char in text.chars() {
+                        let item = Value::string(char.to_string());
+                        if !self.iteration(name, item, &expr.body, &mut output, scope)? {
+                            break;
+                        }
+                    }
+                }
+                value => return Err(error(format!("`{}` is not iterable", value.inspect()))),
+            },
+        }
+        Ok(output.map_or(Value::Undefined, Value::array))
+    }
+
+    fn while_loop(&mut self, expr: &WhileExpr, collect: bool, scope: &Rc<Scope>) -> Eval {
+        let mut output = collect.then(Vec::new);
+        while self.expression(&expr.condition, scope)?.is_truthy() {
+            let scope = Scope::new(Some(scope));
+            match self.block(&expr.body, &scope) {
+                Ok(value) => output
+                    .iter_mut()
+                    .for_each(|output| output.push(value.clone())),
+                Err(Flow::Continue) => {}
+                Err(Flow::Break) => break,
+                Err(flow) => return Err(flow),
+            }
+        }
+        Ok(output.map_or(Value::Undefined, Value::array))
+    }
+
+    /// Runs the body of a `for` loop with the item. Returns `false` if the body used `break`
+    fn iteration(
+        &mut self,
+        name: &str,
+        item: Value,
+        body: &Block,
+        output: &mut Option<Vec<Value>>,
+        scope: &Rc<Scope>,
+    ) -> Result<bool, Flow> {
+        let scope = Scope::new(Some(scope));
+        scope.declare(name, item);
+        match self.block(body, &scope) {
+            Ok(value) => {
+                if let Some(output) = output {
+                    output.push(value);
+                }
+                Ok(true)
+            }
+            Err(Flow::Continue) => Ok(true),
+            Err(Flow::Break) => Ok(false),
+            Err(flow) => Err(flow),
+        }
+    }
+
+    /// Assigns to a variable, a member or an index, and evaluates to the assigned value
+    fn assignment(&mut self, expr: &AssignmentExpr, scope: &Rc<Scope>) -> Eval {
+        match &*expr.lhs {
+            Expression::IdentifierExpression(name, _) => {
+                let mut value = self.expression(&expr.rhs, scope)?;
+                if let Some(op) = &expr.op {
+                    let current = self.variable(&name.0, scope)?;
+                    value = infix(op, current, value);
+                }
+                if !scope.assign(&name.0, value.clone()) {
+                    return Err(error(format!("`{}` is not defined", name.0)));
+                }
+                Ok(value)
+            }
+            Expression::MemberAccessExpression(access, _) => {
+                let object = self.expression(&access.lhs, scope)?;
+                let mut value = self.expression(&expr.rhs, scope)?;
+                if let Some(op) = &expr.op {
+                    let current = self.member(&object, &access.ident.0)?;
+                    value = infix(op, current, value);
+                }
+                self.set_member(&object, &access.ident.0, value.clone())?;
+                Ok(value)
+            }
+            Expression::IndexExpression(access, _) => {
+                let object = self.expression(&access.lhs, scope)?;
+                let index = self.expression(&access.index, scope)?;
+                let mut value = self.expression(&expr.rhs, scope)?;
+                if let Some(op) = &expr.op {
+                    let current = self.index(&object, &index)?;
+                    value = infix(op, current, value);
+                }
+                self.set_index(&object, &index, value.clone())?;
+                Ok(value)
+            }
+            _ => Err(error(
+                "only variables, members and indexes can be assigned to",
+            )),
+        }
+    }
+
+    /// A field of a struct, the getter or the method of its type, an item of a table or the
+    /// `length` of an array or a string. Reading a member that doesn't exist is `undefined`, like
+    /// in javascript
+    pub fn member(&mut self, object: &Value, name: &str) -> Eval {
+        match object {
+            Value::Undefined | Value::Null => Err(error(format!(
+                "cannot read properties of {} (reading '{}')",
+                object, name
+            ))),
+            Value::Array(items) if name == "length" => {
+                Ok(Value::Number(items.borrow().len() as f64))
+            }
+            Value::String(text) if name == "length" => {
+                Ok(Value::Number(text.encode_utf16().count() as f64))
+            }
+            Value::Struct(value) => {
+                let (type_, field) = {
+                    let value = value.borrow();
+                    let field = value
+                        .fields
+                        .iter()
+                        .find(|(field, _)| field == name)
+                        .map(|(_, field)| field.clone());
+                    (value.name.clone(), field)
+                };
+                if let Some(field) = field {
+                    return Ok(field);
+                }
+                let Some(table) = self.types.get(&type_).cloned() else {
+                    return Ok(Value::Undefined);
+                };
+                let getter = table.getters.borrow().get(name).cloned();
+                if let Some(getter) = getter {
+                    return self.call_value(&getter, vec![object.clone()]);
+                }
+                let method = table.items.borrow().get(name).cloned();
+                Ok(method.unwrap_or(Value::Undefined))
+            }
+            Value::Table(table) => Ok(table
+                .items
+                .borrow()
+                .get(name)
+                .cloned()
+                .unwrap_or(Value::Undefined)),
+            Value::Native(global) => Ok(crate::builtins::native_member(global, name)),
+            _ => Ok(Value::Undefined),
+        }
+    }
+
+    /// Assigns a field of a struct, or calls the setter of its type. Fields that don't exist are
+    /// added, like in javascript
+    fn set_member(&mut self, object: &Value, name: &str, value: Value) -> Result<(), Flow> {
+        match object {
+            Value::Struct(fields) => {
+                let type_ = fields.borrow().name.clone();
+                if let Some((_, field)) = fields
+                    .borrow_mut()
+                    .fields
+                    .iter_mut()
+                    .find(|(field, _)| field == name)
+                {
+                    *field = value;
+                    return Ok(());
+                }
+                let setter = self
+                    .types
+                    .get(&type_)
+                    .and_then(|table| table.setters.borrow().get(name).cloned());
+                match setter {
+                    Some(setter) => {
+                        self.call_value(&setter, vec![object.clone(), value])?;
+                    }
+                    None => fields.borrow_mut().fields.push((name.to_string(), value)),
+                }
+                Ok(())
+            }
+            Value::Table(table) => {
+                table.items.borrow_mut().insert(name.to_string(), value);
+                Ok(())
+            }
+            object => Err(error(format!(
+                "cannot set properties of {} (setting '{}')",
+                object.inspect(),
+                name
+            ))),
+        }
+    }
+
+    /// An item of an array, a character of a string or a member named by a string. Indexes
+    /// outside of an array are `undefined`
+    pub fn index(&mut self, object: &Value, index: &Value) -> Eval {
+        match (object, index) {
+            (Value::Undefined | Value::Null, _) => Err(error(format!(
+                "cannot read properties of {} (reading '{}')",
+                object, index
+            ))),
+            (Value::Array(items), Value::Number(index)) => {
+                Ok(array_index(*index, items.borrow().len())
+                    .and_then(|index| items.borrow().get(index).cloned())
+                    .unwrap_or(Value::Undefined))
+            }
+            (Value::String(text), Value::Number(index)) => {
+                let units = text.encode_utf16().collect::<Vec<_>>();
+                Ok(array_index(*index, units.len())
+                    .map(|index| Value::string(String::from_utf16_lossy(&units[index..=index])))
+                    .unwrap_or(Value::Undefined))
+            }
+            (object, Value::String(name)) => self.member(object, name),
+            _ => Ok(Value::Undefined),
+        }
+    }
+
+    fn set_index(&mut self, object: &Value, index: &Value, value: Value) -> Result<(), Flow> {
+        match (object, index) {
+            (Value::Array(items), Value::Number(position))
+                if position.fract() == 0.0 && *position >= 0.0 =>
+            {
+                let position = *position as usize;
+                let mut items = items.borrow_mut();
+                if position >= items.len() {
+                    items.resize(position + 1, Value::Undefined);
+                }
+                items[position] = value;
+                Ok(())
+            }
+            (object, Value::String(name)) => self.set_member(object, name, value),
+            (object, index) => Err(error(format!(
+                "cannot set properties of {} (setting '{}')",
+                object.inspect(),
+                index
+            ))),
+        }
+    }
+
+    /// `value[start..end]`, with the bounds clamped like javascript's `slice`, or checked if
+    /// `bounds_checks` is on
+    fn slice(&mut self, expr: &SliceExpr, scope: &Rc<Scope>) -> Eval {
+        let value = self.expression(&expr.lhs, scope)?;
+        let mut bound = |bound: &Option<Box<Expression>>| -> Result<Option<f64>, Flow> {
+            match bound {
+                Some(bound) => Ok(Some(self.expression(bound, scope)?.to_number().trunc())),
+                None => Ok(None),
+            }
+        };
+        let (start, end) = (bound(&expr.start)?, bound(&expr.end)?);
+        let length = match &value {
+            Value::Array(items) => items.borrow().len(),
+            Value::String(text) => text.encode_utf16().count(),
+            value => {
+                return Err(error(format!(
+                    "only arrays and strings can be sliced, not `{}`",
+                    value.inspect()
+                )))
+            }
+        } as f64;
+        if self.options.bounds_checks && (start.is_some() || end.is_some()) {
+            let (first, last) = (start.unwrap_or(0.0), end.unwrap_or(length));
+            if first < 0.0 || first > last || last > length {
+                return Err(error(format!(
+                    "slice {}..{} is out of bounds of length {}",
+                    first, last, length
+                )));
+            }
+        }
+        let (start, end) = clamped_range(start, end, length as usize);
+        Ok(slice_value(&value, start, end))
+    }
+
+    /// Calls the intrinsics of the prelude like the other backends do, and methods through the
+    /// values they are called on
+    fn call(&mut self, call: &CallExpr, scope: &Rc<Scope>) -> Eval {
+        let prelude = self.options.prelude
+            && !matches!(&*call.lhs, Expression::IdentifierExpression(ident, _)
+                if self.shadowed_prelude.contains(&ident.0));
+        match (&*call.lhs, call.arguments.as_slice()) {
+            (Expression::IdentifierExpression(ident, _), [value])
+                if ident.0 == "clone" && scope.get("clone").is_none() =>
+            {
+                return Ok(self.expression(value, scope)?.deep_clone())
+            }
+            (Expression::MemberAccessExpression(expr, _), []) if expr.ident.0 == "clone" => {
+                return Ok(self.expression(&expr.lhs, scope)?.deep_clone())
+            }
+            (Expression::IdentifierExpression(ident, _), arguments) if prelude => {
+                match (ident.0.as_str(), arguments) {
+                    ("println", arguments) => {
+                        let arguments = self.expressions(arguments, scope)?;
+                        return self.print(&arguments, false);
+                    }
+                    ("eprintln", arguments) => {
+                        let arguments = self.expressions(arguments, scope)?;
+                        return self.print(&arguments, true);
+                    }
+                    ("panic", [message]) => {
+                        let message = self.expression(message, scope)?;
+                        return Err(error(message.to_string()));
+                    }
+                    (name, [lhs, rhs]) if InfixOperator::checked(name).is_some() => {
+                        let op = InfixOperator::checked(name).unwrap();
+                        let lhs = self.expression(lhs, scope)?;
+                        let rhs = self.expression(rhs, scope)?;
+                        return Ok(match infix(&op, lhs, rhs) {
+                            Value::Number(result)
+                                if !result.is_nan() && result.abs() <= MAX_SAFE_INTEGER =>
+                            {
+                                Value::Number(result)
+                            }
+                            _ => Value::Null,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            (Expression::PathExpression(path, _), arguments)
+                if prelude
+                    && path.segments.len() == 2
+                    && scope.get(&path.segments[0].0).is_none() =>
+            {
+                let arguments = self.expressions(arguments, scope)?;
+                match (
+                    path.segments[0].0.as_str(),
+                    path.segments[1].0.as_str(),
+                    arguments.as_slice(),
+                ) {
+                    ("Vec", "new", []) => return Ok(Value::array(vec![])),
+                    ("Vec", "from", [Value::Array(items)]) => {
+                        return Ok(Value::array(items.borrow().clone()))
+                    }
+                    ("array", "new_2d", [width, height, init]) => {
+                        let row = vec![init.clone(); width.to_number().max(0.0) as usize];
+                        let rows = (0..height.to_number().max(0.0) as usize)
+                            .map(|_| Value::array(row.clone()))
+                            .collect();
+                        return Ok(Value::array(rows));
+                    }
+                    ("promise", "new", [executor]) => {
+                        let promise = Rc::new(RefCell::new(Promise::default()));
+                        self.call_value(executor, vec![Value::Resolve(promise.clone())])?;
+                        return Ok(Value::Promise(promise));
+                    }
+                    (namespace @ ("worker" | "promise" | "task"), name, _) => {
+                        return Err(error(format!(
+                            "`{}::{}` is not supported by the interpreter",
+                            namespace, name
+                        )))
+                    }
+                    _ => {
+                        let callee = self.path(&path.segments, scope)?;
+                        return self.call_value(&callee, arguments);
+                    }
+                }
+            }
+            (Expression::MemberAccessExpression(expr, _), arguments) => {
+                let receiver = self.expression(&expr.lhs, scope)?;
+                let arguments = self.expressions(arguments, scope)?;
+                return self.call_method(&receiver, &expr.ident.0, arguments);
+            }
+            _ => {}
+        }
+        let callee = self.expression(&call.lhs, scope)?;
+        let arguments = self.expressions(&call.arguments, scope)?;
+        self.call_value(&callee, arguments)
+    }
+
+    /// `println` and `eprintln` write their arguments like `console.log`
+    pub fn print(&mut self, arguments: &[Value], error_output: bool) -> Eval {
+        let line = arguments
+            .iter()
+            .map(Value::inspect)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let output = if error_output {
+            &mut *self.err
+        } else {
+            &mut *self.out
+        };
+        writeln!(output, "{}", line).map_err(|err| error(err.to_string()))?;
+        Ok(Value::Undefined)
+    }
+
+    /// Calls a function of a struct field, the method of the struct's type with the struct as
+    /// `self`, a function of a table, or a builtin method of arrays, strings, numbers and globals
+    fn call_method(&mut self, receiver: &Value, name: &str, mut arguments: Vec<Value>) -> Eval {
+        match receiver {
+            Value::Struct(value) => {
+                let field = value
+                    .borrow()
+                    .fields
+                    .iter()
+                    .find(|(field, _)| field == name)
+                    .map(|(_, field)| field.clone());
+                if let Some(field) = field {
+                    return self.call_value(&field, arguments);
+                }
+                let type_ = value.borrow().name.clone();
+                let method = self
+                    .types
+                    .get(&type_)
+                    .and_then(|table| table.items.borrow().get(name).cloned());
+                match method {
+                    Some(method) => {
+                        arguments.insert(0, receiver.clone());
+                        self.call_value(&method, arguments)
+                    }
+                    None => Err(error(format!("`{}.{}` is not a function", type_, name))),
+                }
+            }
+            Value::Table(table) => {
+                let item = table.items.borrow().get(name).cloned();
+                match item {
+                    Some(item) => self.call_value(&item, arguments),
+                    None => Err(error(format!(
+                        "`{}.{}` is not a function",
+                        table.name, name
+                    ))),
+                }
+            }
+            Value::Undefined | Value::Null => Err(error(format!(
+                "cannot read properties of {} (reading '{}')",
+                receiver, name
+            ))),
+            receiver => self.builtin_method(receiver, name, arguments),
+        }
+    }
+
+    pub fn call_value(&mut self, callee: &Value, arguments: Vec<Value>) -> Eval {
+        match callee {
+            Value::Function(function) if function.is_async => {
+                let promise = Rc::new(RefCell::new(Promise {
+                    value: None,
+                    task: Some((function.clone(), arguments)),
+                }));
+                self.tasks.push_back(promise.clone());
+                Ok(Value::Promise(promise))
+            }
+            Value::Function(function) => self.call_function(function, arguments),
+            Value::Native(global) => self.call_native(global, arguments),
+            // resolving a promise again does nothing, like in javascript
+            Value::Resolve(promise) => {
+                let mut promise = promise.borrow_mut();
+                if promise.value.is_none() {
+                    promise.value = Some(arguments.into_iter().next().unwrap_or(Value::Undefined));
+                }
+                Ok(Value::Undefined)
+            }
+            callee => Err(error(format!("`{}` is not a function", callee.inspect()))),
+        }
+    }
+
+    fn call_function(&mut self, function: &Function, arguments: Vec<Value>) -> Eval {
+        if self.depth == MAX_DEPTH {
+            return Err(error("maximum call stack size exceeded"));
+        }
+        let scope = Scope::new(Some(&function.scope));
+        let mut arguments = arguments.into_iter();
+        for parameter in &function.parameters {
+            scope.declare(parameter, arguments.next().unwrap_or(Value::Undefined));
+        }
+        self.depth += 1;
+        let result = self.block(&function.body, &scope);
+        self.depth -= 1;
+        match result {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Break | Flow::Continue) => {
+                Err(error("`break` and `continue` can't leave a function"))
+            }
+            Err(flow) => Err(flow),
+        }
+    }
+
+    /// The value of a promise, `.await`. The task of the promise runs now if it didn't yet, other
+    /// tasks run in the order they were called until something resolves the promise. Fails if
+    /// none does, where javascript would wait forever
+    pub fn wait(&mut self, value: Value) -> Eval {
+        let Value::Promise(promise) = value else {
+            return Ok(value);
+        };
+        loop {
+            if let Some(value) = promise.borrow().value.clone() {
+                return Ok(value);
+            }
+            let next = match promise.borrow().task.is_some() {
+                true => promise.clone(),
+                false => match self.tasks.pop_front() {
+                    Some(next) => next,
+                    None => return Err(error("awaited a promise that is never resolved")),
+                },
+            };
+            self.run_task(&next)?;
+        }
+    }
+
+    /// Runs the tasks of the `async` calls nobody awaited, after the rest of the program
+    pub fn run_tasks(&mut self) -> Result<(), Flow> {
+        while let Some(promise) = self.tasks.pop_front() {
+            self.run_task(&promise)?;
+        }
+        Ok(())
+    }
+
+    /// Calls the `async` function of the promise and resolves it with what the call returns, or
+    /// what the promise it returns resolves to
+    fn run_task(&mut self, promise: &Rc<RefCell<Promise>>) -> Result<(), Flow> {
+        let Some((function, arguments)) = promise.borrow_mut().task.take() else {
+            return Ok(());
+        };
+        self.tasks.retain(|task| !Rc::ptr_eq(task, promise));
+        let value = self.call_function(&function, arguments)?;
+        let value = self.wait(value)?;
+        promise.borrow_mut().value = Some(value);
+        Ok(())
+    }
+
+    /// `value is type`. Struct values hold the struct they were created as, and `impl Fn` types
+    /// any function
+    fn is_type(&self, value: &Value, type_: &str) -> bool {
+        let members = union_members(type_);
+        if members.len() > 1 {
+            return members
+                .into_iter()
+                .any(|member| self.is_type(value, member));
+        }
+        if impl_fn(type_).is_some() {
+            return matches!(
+                value,
+                Value::Function(_) | Value::Native(_) | Value::Resolve(_)
+            );
+        }
+        match (type_, value) {
+            ("any", _) => true,
+            ("number", Value::Number(_))
+            | ("string", Value::String(_))
+            | ("boolean" | "bool", Value::Boolean(_))
+            | ("null", Value::Null)
+            | ("undefined", Value::Undefined) => true,
+            (type_, Value::Array(_)) if type_.starts_with('[') => true,
+            (type_, Value::Struct(value)) => value.borrow().name == type_,
+            (type_, value) => match type_as_literal(type_) {
+                Some(literal) => literal_value(&literal).strict_equals(value),
+                None => false,
+            },
+        }
+    }
+}
+
+fn function(
+    name: &str,
+    parameters: Vec<String>,
+    body: &Block,
+    is_async: bool,
+    scope: &Rc<Scope>,
+) -> Value {
+    Value::Function(Rc::new(Function {
+        name: name.to_string(),
+        parameters,
+        body: body.clone(),
+        is_async,
+        scope: scope.clone(),
+    }))
+}
+
+fn is_hoisted(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::DeclarationStatement(
+            Declaration::FunctionDeclaration { .. }
+                | Declaration::ImplDeclaration(_)
+                | Declaration::TraitDeclaration(_),
+            _
+        )
+    )
+}
+
+/// The names a declaration of a module declares, which are visible outside of the module if it is
+/// `pub`
+fn declared_names(declaration: &Declaration) -> Vec<&Identifier> {
+    match declaration {
+        Declaration::ConstDeclaration(name, ..)
+        | Declaration::LetDeclaration(name, _, _, _)
+        | Declaration::FunctionDeclaration { name, .. }
+        | Declaration::ModFileDeclaration(name) => vec![name],
+        Declaration::DestructuringDeclaration(pattern, value, _, _) => pattern
+            .bindings(value)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+        Declaration::EnumDeclaration(decl) => vec![&decl.name],
+        Declaration::ImplDeclaration(decl) => vec![&decl.target],
+        Declaration::ModDeclaration(decl) => vec![&decl.name],
+        Declaration::TraitDeclaration(decl) => vec![&decl.name],
+        Declaration::StructDeclaration(_)
+        | Declaration::TypeDeclaration(_)
+        | Declaration::ExternDeclaration(_)
+        | Declaration::UseDeclaration(_) => vec![],
+    }
+}
+
+pub fn literal_value(literal: &Literal) -> Value {
+    match literal {
+        Literal::StringLiteral(value) => Value::string(value.as_str()),
+        Literal::NumberLiteral(number) => {
+            Value::Number(f64::try_from(number.clone()).unwrap_or(f64::NAN))
+        }
+        Literal::BooleanLiteral(value) => Value::Boolean(*value),
+    }
+}
+
+/// The operators of javascript, `==` is `===`
+pub fn infix(op: &InfixOperator, lhs: Value, rhs: Value) -> Value {
+    use InfixOperator::*;
+    let number = |value: f64| Value::Number(value);
+    let int32 = |value: &Value| to_int32(value.to_number());
+    match op {
+        Plus => match (&lhs, &rhs) {
+            (Value::Number(lhs), Value::Number(rhs)) => number(lhs + rhs),
+            (Value::String(_), _)
+            | (_, Value::String(_))
+            | (Value::Array(_) | Value::Struct(_) | Value::Table(_) | Value::Function(_), _)
+            | (_, Value::Array(_) | Value::Struct(_) | Value::Table(_) | Value::Function(_)) => {
+                Value::string(format!("{}{}", lhs, rhs))
+            }
+            (lhs, rhs) => number(lhs.to_number() + rhs.to_number()),
+        },
+        Minus => number(lhs.to_number() - rhs.to_number()),
+        Multiply => number(lhs.to_number() * rhs.to_number()),
+        Divide => number(lhs.to_number() / rhs.to_number()),
+        // the remainder has the sign of the dividend, like `%` of javascript
+        Modulo => number(lhs.to_number() % rhs.to_number()),
+        Equal => Value::Boolean(lhs.strict_equals(&rhs)),
+        NotEqual => Value::Boolean(!lhs.strict_equals(&rhs)),
+        LessThan | LessThanEqual | GreaterThan | GreaterThanEqual => {
+            let ordering = match (&lhs, &rhs) {
+                // strings are compared by their UTF-16 code units
+                (Value::String(lhs), Value::String(rhs)) => {
+                    Some(lhs.encode_utf16().cmp(rhs.encode_utf16()))
+                }
+                (lhs, rhs) => lhs.to_number().partial_cmp(&rhs.to_number()),
+            };
+            Value::Boolean(match ordering {
+                Some(ordering) => match op {
+                    LessThan => ordering.is_lt(),
+                    LessThanEqual => ordering.is_le(),
+                    GreaterThan => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                },
+                // comparisons with `NaN` are false
+                None => false,
+            })
+        }
+        LogicalOr | LogicalAnd | NullishCoalesce => {
+            unreachable!("short circuiting operators are evaluated by the caller")
+        }
+        BitwiseOr => number((int32(&lhs) | int32(&rhs)) as f64),
+        BitwiseXor => number((int32(&lhs) ^ int32(&rhs)) as f64),
+        BitwiseAnd => number((int32(&lhs) & int32(&rhs)) as f64),
+        BitwiseLeftShift => number(int32(&lhs).wrapping_shl(int32(&rhs) as u32 & 31) as f64),
+        BitwiseRightShift => number((int32(&lhs) >> (int32(&rhs) as u32 & 31)) as f64),
+    }
+}
+
+/// The position of an item, if the index is a whole number inside of the array
+fn array_index(index: f64, length: usize) -> Option<usize> {
+    (index.fract() == 0.0 && index >= 0.0 && index < length as f64).then_some(index as usize)
+}
+
+/// The bounds of `slice(start, end)`, negative bounds count from the end
+pub fn clamped_range(start: Option<f64>, end: Option<f64>, length: usize) -> (usize, usize) {
+    let clamp = |bound: f64| {
+        let length = length as f64;
+        let bound = if bound < 0.0 { length + bound } else { bound };
+        bound.clamp(0.0, length) as usize
+    };
+    let start = start.map_or(0, clamp);
+    let end = end.map_or(length, clamp);
+    (start, end.max(start))
+}
+
+/// The items or UTF-16 code units of an array or a string from `start` up to `end`
+pub fn slice_value(value: &Value, start: usize, end: usize) -> Value {
+    match value {
+        Value::Array(items) => Value::array(items.borrow()[start..end].to_vec()),
+        Value::String(text) => {
+            let units = text.encode_utf16().collect::<Vec<_>>();
+            Value::string(String::from_utf16_lossy(&units[start..end]))
+        }
+        _ => Value::Undefined,
+    }
+}
@@ -0,0 +1,425 @@
+use std::{fmt, io::Write};
+
+use oxidescript::{
+    parser::{
+        ast::Program,
+        dispose::lower_with,
+        enums::lower_discriminants,
+        module::lower_uses,
+        types::{lower_index_operators, lower_iterators, lower_method_calls, lower_type_aliases},
+    },
+    resolve::shadowed_prelude,
+};
+
+mod builtins;
+mod eval;
+mod value;
+
+use eval::{Flow, InterpreterContext, Scope};
+
+#[derive(Clone, Debug)]
+pub struct InterpreterOptions {
+    /// Whether prelude items like `println` and `Vec::new` are in scope without being declared
+    pub prelude: bool,
+    /// Whether slices like `items[1..3]` fail if a bound is outside of the array or string,
+    /// instead of clamping it. On with the `debug` flag of `oxide.toml`
+    pub bounds_checks: bool,
+}
+
+impl Default for InterpreterOptions {
+    fn default() -> Self {
+        InterpreterOptions {
+            prelude: true,
+            bounds_checks: false,
+        }
+    }
+}
+
+/// The globals of javascript the interpreter implements, which programs can use without
+/// declaring them
+pub const GLOBALS: &[&str] = &[
+    "console",
+    "Math",
+    "Number",
+    "String",
+    "parseInt",
+    "parseFloat",
+    "isNaN",
+    "null",
+    "undefined",
+    "NaN",
+    "Infinity",
+];
+
+/// Why a program stopped: a `panic`, an uncaught error of javascript like calling a function that
+/// doesn't exist, or a feature the interpreter doesn't support
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Runs programs by walking their AST, so they run without node and without being compiled.
+///
+/// Values behave like in the javascript output: numbers are floats, arrays, structs and impl
+/// tables are shared by reference, and `==` compares them by identity. Calls of async functions
+/// run when they are awaited, when something awaits a promise they might resolve, or after the rest
+/// of the program, so the order of output can differ from node, which runs them until their first
+/// `.await` right away
+pub struct Interpreter {
+    options: InterpreterOptions,
+}
+
+impl Interpreter {
+    pub fn with_options(options: InterpreterOptions) -> Self {
+        Interpreter { options }
+    }
+
+    pub fn new() -> Self {
+        Self::with_options(InterpreterOptions::default())
+    }
+
+    /// Runs the program, writing `println` and `console.log` to `out` and `eprintln` and
+    /// `console.error` to `err`
+    pub fn run(
+        &self,
+        program: Program,
+        out: &mut dyn Write,
+        err: &mut dyn Write,
+    ) -> Result<(), RuntimeError> {
+        let mut ctx = InterpreterContext::new(&self.options, out, err);
+        ctx.shadowed_prelude = shadowed_prelude(&program);
+        let program = lower_uses(lower_type_aliases(lower_discriminants(lower_method_calls(
+            lower_iterators(lower_index_operators(lower_with(program))),
+        ))));
+        let result = ctx
+            .statements(&program, &Scope::new(None))
+            .and_then(|()| ctx.run_tasks());
+        match result {
+            Ok(()) => Ok(()),
+            Err(Flow::Error(error)) => Err(error),
+            Err(Flow::Return(_)) => Err(RuntimeError {
+                message: "`return` outside of a function".to_string(),
+            }),
+            Err(Flow::Break | Flow::Continue) => Err(RuntimeError {
+                message: "`break` and `continue` outside of a loop".to_string(),
+            }),
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oxidescript::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+        stack::with_stack,
+        stdlib::used_items,
+    };
+
+    use super::*;
+
+    fn run_with(options: InterpreterOptions, input: &str) -> Result<String, RuntimeError> {
+        with_stack(|| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            let program = used_items(&program, false)
+                .into_iter()
+                .chain(program)
+                .collect();
+            let mut out = vec![];
+            Interpreter::with_options(options).run(program, &mut out, &mut std::io::sink())?;
+            Ok(String::from_utf8(out).unwrap())
+        })
+    }
+
+    fn run(input: &str) -> String {
+        run_with(InterpreterOptions::default(), input).unwrap()
+    }
+
+    #[test]
+    fn functions_are_hoisted() {
+        assert_eq!(
+            run("println(double(21));
+                fn double(n: number) -> number {
+                    n * 2
+                }"),
+            "42\n"
+        );
+    }
+
+    #[test]
+    fn values_are_printed_like_console_log() {
+        assert_eq!(
+            run(r#"println("a", 1.5, [1, "b"], Point { x: 1, y: [2] }, null, 1 / 0);"#),
+            "a 1.5 [ 1, 'b' ] { x: 1, y: [ 2 ] } null Infinity\n"
+        );
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            run("let squares = for i in 0..5 {
+                    if i == 3 { continue; }
+                    i * i
+                };
+                let total = 0;
+                let n = 0;
+                while true {
+                    n += 1;
+                    if n > 10 { break; }
+                    total += n;
+                }
+                for char in \"ab\" { println(char); }
+                println(squares, total);"),
+            "a\nb\n[ 0, 1, 4, 16 ] 55\n"
+        );
+    }
+
+    #[test]
+    fn closures_capture_variables() {
+        assert_eq!(
+            run("fn counter() -> impl Fn() -> number {
+                    let count = 0;
+                    || { count += 1; count }
+                }
+                let next = counter();
+                next();
+                println(next(), [3, 1, 2].map(|n: number| n * 10).filter(|n: number| n > 10));"),
+            "2 [ 30, 20 ]\n"
+        );
+    }
+
+    #[test]
+    fn methods_and_traits() {
+        assert_eq!(
+            run("struct Point { x: number, y: number }
+                trait Describe {
+                    fn name(self) -> string;
+                    fn describe(self) -> string {
+                        f\"{self.name()} at {self.x}, {self.y}\"
+                    }
+                }
+                impl Point {
+                    const ORIGIN = Point { x: 0, y: 0 };
+                    fn new(x: number, y: number) -> Self {
+                        Point { x: x, y: y }
+                    }
+                    fn moved(self, dx: number) -> Point {
+                        Point::new(self.x + dx, self.y)
+                    }
+                    get sum() -> number {
+                        self.x + self.y
+                    }
+                }
+                impl Describe for Point {
+                    fn name(self) -> string { \"point\" }
+                }
+                let p = Point::new(1, 2).moved(2);
+                println(p.describe(), p.sum, Point::ORIGIN);"),
+            "point at 3, 2 5 { x: 0, y: 0 }\n"
+        );
+    }
+
+    #[test]
+    fn enums_and_match() {
+        assert_eq!(
+            run("enum Shape { Circle(number), Square(number), Empty }
+                fn area(shape: Shape) -> number {
+                    match shape {
+                        Shape::Circle(r) => 3 * r * r,
+                        Shape::Square(side) => side * side,
+                        Shape::Empty => 0,
+                    }
+                }
+                fn describe(n: number) -> string {
+                    match n {
+                        0 => \"zero\",
+                        1 | 2 => \"small\",
+                        _ => \"large\",
+                    }
+                }
+                println(area(Shape::Circle(2)), area(Shape::Square(3)), area(Shape::Empty));
+                println(describe(0), describe(2), describe(7));"),
+            "12 9 0\nzero small large\n"
+        );
+    }
+
+    #[test]
+    fn modules() {
+        assert_eq!(
+            run("mod math {
+                    pub fn double(n: number) -> number { helper(n) }
+                    fn helper(n: number) -> number { n * 2 }
+                }
+                println(math::double(4));"),
+            "8\n"
+        );
+    }
+
+    #[test]
+    fn defer_runs_when_the_block_is_left() {
+        assert_eq!(
+            run("fn work() -> number {
+                    defer { println(\"cleanup\"); }
+                    println(\"working\");
+                    return 1;
+                }
+                println(work());"),
+            "working\ncleanup\n1\n"
+        );
+    }
+
+    #[test]
+    fn destructuring_and_optional_chains() {
+        assert_eq!(
+            run("struct User { name: string, address: Address | null }
+                struct Address { city: string }
+                let (a, b) = (1, \"two\");
+                let Address { city } = Address { city: \"Oslo\" };
+                let user = User { name: \"ada\", address: null };
+                println(a, b, city, user.address?.city ?? \"unknown\");"),
+            "1 two Oslo unknown\n"
+        );
+    }
+
+    #[test]
+    fn javascript_semantics() {
+        assert_eq!(
+            run(r#"println(1 + "1", 7 % -3, -7 % 3, 5 / 2, "b" > "a", 0.1 + 0.2);
+                println([1, 2] == [1, 2], "a" == "a", -1 >> 1, 1 << 31);
+                let pair = [1, 2];
+                println(f"{pair} {null}", 1e21, [1, 2, 3][5], "abc".length);"#),
+            "11 1 -1 2.5 true 0.30000000000000004\nfalse true -1 -2147483648\n1,2 null 1e+21 undefined 3\n"
+        );
+    }
+
+    #[test]
+    fn clone_copies_nested_values() {
+        assert_eq!(
+            run("let grid = [[1], [2]];
+                let copy = clone(grid);
+                copy[0].push(3);
+                let shared = grid;
+                shared[1].push(4);
+                println(grid, copy);"),
+            "[ [ 1 ], [ 2, 4 ] ] [ [ 1, 3 ], [ 2 ] ]\n"
+        );
+    }
+
+    #[test]
+    fn panics_and_errors() {
+        let options = InterpreterOptions::default();
+        assert_eq!(
+            run_with(options.clone(), "println(1); panic(\"boom\");"),
+            Err(RuntimeError {
+                message: "boom".to_string()
+            })
+        );
+        assert_eq!(
+            run_with(options, "let x = null; x.y;"),
+            Err(RuntimeError {
+                message: "cannot read properties of null (reading 'y')".to_string()
+            })
+        );
+        assert_eq!(
+            run_with(
+                InterpreterOptions {
+                    bounds_checks: true,
+                    ..InterpreterOptions::default()
+                },
+                "let items = [1, 2]; items[1..4];"
+            ),
+            Err(RuntimeError {
+                message: "slice 1..4 is out of bounds of length 2".to_string()
+            })
+        );
+        assert_eq!(run("println([1, 2, 3][1..9]);"), "[ 2, 3 ]\n");
+    }
+
+    #[test]
+    fn recursion_is_limited() {
+        assert_eq!(
+            run_with(
+                InterpreterOptions::default(),
+                "fn forever(n: number) -> number { forever(n + 1) } forever(0);"
+            ),
+            Err(RuntimeError {
+                message: "maximum call stack size exceeded".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn async_functions_run_to_completion() {
+        assert_eq!(
+            run("async fn answer() -> number { 42 }
+                async fn main() {
+                    println(answer().await);
+                }
+                main();"),
+            "42\n"
+        );
+    }
+
+    #[test]
+    fn promises_resolve_when_awaited() {
+        assert_eq!(
+            run("let later = promise::new(|resolve| resolve(1));
+                let items = [1, 2, 3, 4];
+                async fn main() {
+                    println(later.await, items.splice(1, 2), items);
+                }
+                main();
+                println(\"first\");"),
+            "first\n1 [ 2, 3 ] [ 1, 4 ]\n"
+        );
+    }
+
+    #[test]
+    fn std_channel() {
+        assert_eq!(
+            run("async fn produce(channel: Channel<number>) {
+                    for i in [1, 2, 3] {
+                        channel.send(i);
+                    }
+                    channel.close();
+                }
+                async fn total(channel: Channel<number>) -> number {
+                    let sum = 0;
+                    for value in channel {
+                        sum = sum + value;
+                    }
+                    sum
+                }
+                async fn report(sum: Promise<number>) {
+                    println(sum.await);
+                }
+                let channel: Channel<number> = Channel::new();
+                let sum = total(channel);
+                produce(channel);
+                report(sum);"),
+            "6\n"
+        );
+        assert_eq!(
+            run_with(
+                InterpreterOptions::default(),
+                "async fn main() { Channel::new().recv().await; } main();"
+            ),
+            Err(RuntimeError {
+                message: "awaited a promise that is never resolved".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use oxidescript::parser::ast::Block;
+
+use crate::eval::Scope;
+
+/// A value of a running program. Arrays, structs and impl tables are shared between the names that
+/// refer to them, like objects in javascript
+#[derive(Clone, Debug)]
+pub enum Value {
+    /// What functions without a return value and missing array items evaluate to
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<str>),
+    /// Arrays and tuples
+    Array(Rc<RefCell<Vec<Value>>>),
+    Struct(Rc<RefCell<StructValue>>),
+    /// The functions and constants of impl blocks, the items of modules and the default methods of
+    /// traits
+    Table(Rc<Table>),
+    Function(Rc<Function>),
+    /// A global of the host like `Math` or `String`, see `builtins.rs`
+    Native(&'static str),
+    /// What `async` functions and `promise::new` return
+    Promise(Rc<RefCell<Promise>>),
+    /// The `resolve` function `promise::new` passes to its executor
+    Resolve(Rc<RefCell<Promise>>),
+}
+
+/// A promise is resolved once it has a value. The call of an `async` function is its task, which
+/// runs when the promise is awaited or after the rest of the program
+#[derive(Debug, Default)]
+pub struct Promise {
+    pub value: Option<Value>,
+    pub task: Option<(Rc<Function>, Vec<Value>)>,
+}
+
+#[derive(Debug)]
+pub struct StructValue {
+    /// The struct the value was created as, whose impl blocks have its methods
+    pub name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Table {
+    pub name: String,
+    pub items: RefCell<HashMap<String, Value>>,
+    /// `get name() { ... }` of an impl block
+    pub getters: RefCell<HashMap<String, Value>>,
+    /// `set name(value: type) { ... }` of an impl block
+    pub setters: RefCell<HashMap<String, Value>>,
+}
+
+pub struct Function {
+    /// Empty for closures
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub body: Block,
+    /// `async fn` and `async |x| ...`, whose calls return a promise
+    pub is_async: bool,
+    /// The scope the function was declared in, which it can read and assign to
+    pub scope: Rc<Scope>,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Function({})", self.name)
+    }
+}
+
+impl Value {
+    pub fn string(value: impl Into<Rc<str>>) -> Value {
+        Value::String(value.into())
+    }
+
+    pub fn array(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    /// `false`, `0`, `NaN`, `""`, `null` and `undefined` are false in conditions, like in
+    /// javascript
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Undefined | Value::Null => false,
+            Value::Boolean(value) => *value,
+            Value::Number(value) => *value != 0.0 && !value.is_nan(),
+            Value::String(value) => !value.is_empty(),
+            _ => true,
+        }
+    }
+
+    /// The number javascript turns the value into for arithmetic
+    pub fn to_number(&self) -> f64 {
+        match self {
+            Value::Undefined => f64::NAN,
+            Value::Null => 0.0,
+            Value::Boolean(value) => *value as u8 as f64,
+            Value::Number(value) => *value,
+            Value::String(value) => match value.trim() {
+                "" => 0.0,
+                text => text.parse().unwrap_or(f64::NAN),
+            },
+            _ => f64::NAN,
+        }
+    }
+
+    /// `===`, which compares arrays, structs and functions by their identity
+    pub fn strict_equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Undefined, Value::Undefined) | (Value::Null, Value::Null) => true,
+            (Value::Boolean(lhs), Value::Boolean(rhs)) => lhs == rhs,
+            (Value::Number(lhs), Value::Number(rhs)) => lhs == rhs,
+            (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
+            (Value::Array(lhs), Value::Array(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Struct(lhs), Value::Struct(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Table(lhs), Value::Table(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Function(lhs), Value::Function(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Native(lhs), Value::Native(rhs)) => lhs == rhs,
+            (Value::Promise(lhs), Value::Promise(rhs))
+            | (Value::Resolve(lhs), Value::Resolve(rhs)) => Rc::ptr_eq(lhs, rhs),
+            _ => false,
+        }
+    }
+
+    /// A copy of arrays and structs, and of the arrays and structs in them, for `clone`
+    pub fn deep_clone(&self) -> Value {
+        match self {
+            Value::Array(items) => {
+                Value::array(items.borrow().iter().map(Value::deep_clone).collect())
+            }
+            Value::Struct(value) => {
+                let value = value.borrow();
+                Value::Struct(Rc::new(RefCell::new(StructValue {
+                    name: value.name.clone(),
+                    fields: value
+                        .fields
+                        .iter()
+                        .map(|(name, field)| (name.clone(), field.deep_clone()))
+                        .collect(),
+                })))
+            }
+            value => value.clone(),
+        }
+    }
+
+    /// How `console.log` shows the value, strings without quotes unless they are inside of an
+    /// array or a struct
+    pub fn inspect(&self) -> String {
+        match self {
+            Value::String(value) => value.to_string(),
+            value => value.inspect_nested(0),
+        }
+    }
+
+    fn inspect_nested(&self, depth: usize) -> String {
+        match self {
+            Value::String(value) => format!("'{}'", value.replace('\'', "\\'")),
+            // `console.log` shows the sign of zero, turning it into a string doesn't
+            Value::Number(value) if *value == 0.0 && value.is_sign_negative() => "-0".to_string(),
+            Value::Array(_) | Value::Struct(_) | Value::Table(_) if depth > 2 => match self {
+                Value::Array(_) => "[Array]".to_string(),
+                _ => "[Object]".to_string(),
+            },
+            Value::Array(items) => {
+                let items = items.borrow();
+                if items.is_empty() {
+                    return "[]".to_string();
+                }
+                let items = items
+                    .iter()
+                    .map(|item| item.inspect_nested(depth + 1))
+                    .collect::<Vec<_>>();
+                format!("[ {} ]", items.join(", "))
+            }
+            Value::Struct(value) => {
+                let fields = value
+                    .borrow()
+                    .fields
+                    .iter()
+                    .map(|(name, field)| format!("{}: {}", name, field.inspect_nested(depth + 1)))
+                    .collect::<Vec<_>>();
+                match fields.is_empty() {
+                    true => "{}".to_string(),
+                    false => format!("{{ {} }}", fields.join(", ")),
+                }
+            }
+            Value::Table(table) => {
+                let items = table.items.borrow();
+                let mut names = items.keys().collect::<Vec<_>>();
+                names.sort();
+                let items = names
+                    .into_iter()
+                    .map(|name| format!("{}: {}", name, items[name].inspect_nested(depth + 1)))
+                    .collect::<Vec<_>>();
+                match items.is_empty() {
+                    true => "{}".to_string(),
+                    false => format!("{{ {} }}", items.join(", ")),
+                }
+            }
+            Value::Function(function) if function.name.is_empty() => {
+                "[Function (anonymous)]".to_string()
+            }
+            Value::Function(function) => format!("[Function: {}]", function.name),
+            Value::Promise(promise) => match &promise.borrow().value {
+                Some(value) => format!("Promise {{ {} }}", value.inspect_nested(depth + 1)),
+                None => "Promise { <pending> }".to_string(),
+            },
+            Value::Resolve(_) => "[Function (anonymous)]".to_string(),
+            value => value.to_string(),
+        }
+    }
+}
+
+/// The string javascript turns the value into, for `+` and templates
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Undefined => f.write_str("undefined"),
+            Value::Null => f.write_str("null"),
+            Value::Boolean(value) => value.fmt(f),
+            Value::Number(value) => f.write_str(&number_to_string(*value)),
+            Value::String(value) => f.write_str(value),
+            // `[1, null, [2, 3]]` is `1,,2,3`
+            Value::Array(items) => {
+                let items = items
+                    .borrow()
+                    .iter()
+                    .map(|item| match item {
+                        Value::Undefined | Value::Null => String::new(),
+                        item => item.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                f.write_str(&items.join(","))
+            }
+            Value::Struct(_) | Value::Table(_) => f.write_str("[object Object]"),
+            Value::Promise(_) => f.write_str("[object Promise]"),
+            Value::Function(function) => write!(f, "function {}() {{ [code] }}", function.name),
+            Value::Native(name) => write!(f, "function {}() {{ [native code] }}", name),
+            Value::Resolve(_) => f.write_str("function () { [native code] }"),
+        }
+    }
+}
+
+/// A number the way javascript writes it, without a fraction for integers and with an exponent
+/// for very large and very small numbers
+pub fn number_to_string(value: f64) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    if value.abs() >= 1e21 || value.abs() < 1e-6 {
+        // rust writes `1e21` and `1.5e-7`, javascript `1e+21` and `1.5e-7`
+        let text = format!("{:e}", value);
+        return match text.split_once('e') {
+            Some((mantissa, exponent)) if !exponent.starts_with('-') => {
+                format!("{}e+{}", mantissa, exponent)
+            }
+            _ => text,
+        };
+    }
+    value.to_string()
+}
+
+/// The 32 bit integer the bitwise operators of javascript work on
+pub fn to_int32(value: f64) -> i32 {
+    if !value.is_finite() {
+        return 0;
+    }
+    value.trunc().rem_euclid(4294967296.0) as u32 as i32
+}
@@ -1,56 +1,187 @@
 use oxc::{
     ast::{
         ast::{
-            Argument, ArrayExpressionElement, BindingRestElement, Expression, Program, Statement,
-            TSTypeAnnotation, TSTypeParameterDeclaration, TSTypeParameterInstantiation,
-            VariableDeclarator,
+            Argument, ArrayExpressionElement, ArrowFunctionExpression, AwaitExpression,
+            BindingRestElement, Expression, Function, Program, Statement, TSTypeAnnotation,
+            TSTypeParameterDeclaration, TSTypeParameterInstantiation, VariableDeclarator,
         },
-        AstBuilder,
+        AstBuilder, Visit,
     },
-    span::{SourceType, Span},
+    span::{GetSpanMut, SourceType, Span},
+    syntax::scope::ScopeFlags,
 };
 
+pub mod assignment;
 pub mod block;
+pub mod closure;
 pub mod conditional;
 pub mod function;
+pub mod guards;
 pub mod ident;
+pub mod import;
 pub mod index;
 pub mod infix;
+pub mod intrinsic;
+pub mod json;
+pub mod jump;
 pub mod literal;
 pub mod r#loop;
+pub mod r#match;
 pub mod member_access;
+pub mod module;
+pub mod optional;
+pub mod path;
+pub mod range;
+pub mod slice;
+pub mod structs;
+pub mod template;
+pub mod tuple;
+pub mod types;
 pub mod unary;
 
-use crate::{IntoOxc, JavascriptCompilerContext};
+use crate::{IntoOxc, JavascriptCompilerContext, OutputLanguage};
 
 impl<'c> IntoOxc<'c, Program<'c>> for oxidescript::parser::ast::Program {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Program {
-        AstBuilder::new(ctx.allocator).program(
-            Span::new(0, 0),
-            SourceType::default(),
-            "",
-            oxc::allocator::Vec::new_in(ctx.allocator),
-            None,
-            oxc::allocator::Vec::new_in(ctx.allocator),
-            oxc::allocator::Vec::from_iter_in(
-                self.into_iter().map(|statement| statement.into_oxc(ctx)),
-                ctx.allocator,
-            ),
-        )
+        let body = self
+            .into_iter()
+            .take_while(|_| !ctx.is_cancelled())
+            .flat_map(|statement| {
+                // the interface of a struct maps to the struct too
+                let span = statement.span().into_oxc(ctx);
+                let mut statements = top_level(statement, ctx);
+                for statement in &mut statements {
+                    *statement.span_mut() = span;
+                }
+                statements
+            })
+            .collect::<Vec<_>>();
+        program(body, ctx)
+    }
+}
+
+/// A top level statement together with the interface of its struct. `pub` items are exported
+/// where they are declared, or by a separate `export` if their name is renamed in javascript
+pub fn top_level<'c>(
+    statement: oxidescript::parser::ast::Statement,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Vec<Statement<'c>> {
+    let is_pub = module::is_pub(&statement);
+    let renamed = match is_pub {
+        true => module::exported_names(&statement)
+            .into_iter()
+            .filter(|name| ident::is_reserved(name))
+            .cloned()
+            .collect(),
+        false => vec![],
+    };
+    let export = |statement| match is_pub && renamed.is_empty() {
+        true => import::export_declaration(statement, ctx),
+        false => statement,
+    };
+    let result_guard = guards::extern_result(&statement, ctx);
+    let mut body = vec![];
+    // structs without impls only produce a binding that could be exported in typescript
+    if let Some(interface) = types::struct_interface(&statement, ctx) {
+        body.push(export(interface));
+    }
+    if let Some(statement) = guards::top_level(statement, ctx) {
+        body.push(export(statement));
+        if !renamed.is_empty() {
+            body.push(import::exports(renamed, ctx));
+        }
+    }
+    body.extend(result_guard);
+    body
+}
+
+pub fn program<'c>(
+    body: impl IntoIterator<Item = Statement<'c>>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Program<'c> {
+    AstBuilder::new(ctx.allocator).program(
+        Span::new(0, 0),
+        match ctx.options.output_language {
+            OutputLanguage::Javascript => SourceType::default(),
+            OutputLanguage::Typescript => SourceType::ts(),
+        },
+        "",
+        oxc::allocator::Vec::new_in(ctx.allocator),
+        None,
+        oxc::allocator::Vec::new_in(ctx.allocator),
+        oxc::allocator::Vec::from_iter_in(body, ctx.allocator),
+    )
+}
+
+/// Where a node is in the oxidescript source, which source maps map its javascript back to
+impl<'c> IntoOxc<'c, Span> for oxidescript::parser::ast::Span {
+    fn into_oxc(self, _ctx: &'c JavascriptCompilerContext<'c>) -> Span {
+        Span::new(self.start as u32, self.end as u32)
+    }
+}
+
+/// Gives the javascript of a node the span of the node. Nodes the compiler made itself have no
+/// span and are mapped by the node around them
+fn mapped<'c, T: GetSpanMut>(
+    mut node: T,
+    span: oxidescript::parser::ast::Span,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> T {
+    if !span.is_empty() {
+        *node.span_mut() = span.into_oxc(ctx);
     }
+    node
 }
 
-impl<'c> IntoOxc<'c, Statement<'c>> for oxidescript::parser::ast::Statement {
-    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Statement {
-        match self {
-            oxidescript::parser::ast::Statement::ExpressionStatement { expression, .. } => {
+/// Declarations that don't produce any javascript on their own compile to `None`
+impl<'c> IntoOxc<'c, Option<Statement<'c>>> for oxidescript::parser::ast::Statement {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Option<Statement<'c>> {
+        let span = self.span();
+        let statement = match self {
+            oxidescript::parser::ast::Statement::ExpressionStatement {
+                expression: oxidescript::parser::ast::Expression::WhileExpression(expr, _),
+                ..
+            } => Some(expr.into_oxc(ctx)),
+            oxidescript::parser::ast::Statement::ExpressionStatement {
+                expression: oxidescript::parser::ast::Expression::MatchExpression(expr, _),
+                ..
+            } => Some(expr.into_oxc(ctx)),
+            oxidescript::parser::ast::Statement::ExpressionStatement {
+                expression: oxidescript::parser::ast::Expression::ForExpression(expr, _),
+                ..
+            } => Some(expr.into_oxc(ctx)),
+            // `if`s and blocks as statements don't need an IIFE, so the jumps in them stay
+            // javascript statements
+            oxidescript::parser::ast::Statement::ExpressionStatement {
+                expression: oxidescript::parser::ast::Expression::IfExpression(expr, _),
+                ..
+            } => Some(conditional::discarded(expr, ctx)),
+            oxidescript::parser::ast::Statement::ExpressionStatement {
+                expression: oxidescript::parser::ast::Expression::BlockExpression(block, _),
+                ..
+            } => Some(
                 AstBuilder::new(ctx.allocator)
-                    .statement_expression(Span::new(0, 0), expression.into_oxc(ctx))
+                    .statement_block(Span::new(0, 0), block::discarded(*block, ctx)),
+            ),
+            oxidescript::parser::ast::Statement::ExpressionStatement { expression, .. }
+                if jump::is_jump(&expression) =>
+            {
+                Some(jump::statement(expression, ctx))
             }
-            oxidescript::parser::ast::Statement::DeclarationStatement(declaration) => {
+            oxidescript::parser::ast::Statement::ExpressionStatement { expression, .. } => Some(
+                AstBuilder::new(ctx.allocator)
+                    .statement_expression(Span::new(0, 0), expression.into_oxc(ctx)),
+            ),
+            oxidescript::parser::ast::Statement::DeclarationStatement(declaration, _) => {
                 match declaration {
-                    oxidescript::parser::ast::Declaration::ConstDeclaration(ident, expr) => {
-                        oxc::ast::ast::Statement::VariableDeclaration(oxc::allocator::Box::new_in(
+                    oxidescript::parser::ast::Declaration::ConstDeclaration(
+                        ident,
+                        expr,
+                        _,
+                        _,
+                        type_,
+                    ) => Some(oxc::ast::ast::Statement::VariableDeclaration(
+                        oxc::allocator::Box::new_in(
                             AstBuilder::new(ctx.allocator).variable_declaration(
                                 Span::new(0, 0),
                                 oxc::ast::ast::VariableDeclarationKind::Const,
@@ -58,7 +189,7 @@ impl<'c> IntoOxc<'c, Statement<'c>> for oxidescript::parser::ast::Statement {
                                     vec![VariableDeclarator {
                                         span: Span::new(0, 0),
                                         kind: oxc::ast::ast::VariableDeclarationKind::Const,
-                                        id: ident.into_oxc(ctx),
+                                        id: types::annotated(ident.into_oxc(ctx), type_, ctx),
                                         init: Some(expr.into_oxc(ctx)),
                                         definite: false,
                                     }],
@@ -67,10 +198,15 @@ impl<'c> IntoOxc<'c, Statement<'c>> for oxidescript::parser::ast::Statement {
                                 false,
                             ),
                             ctx.allocator,
-                        ))
-                    }
-                    oxidescript::parser::ast::Declaration::LetDeclaration(ident, expr) => {
-                        oxc::ast::ast::Statement::VariableDeclaration(oxc::allocator::Box::new_in(
+                        ),
+                    )),
+                    oxidescript::parser::ast::Declaration::LetDeclaration(
+                        ident,
+                        expr,
+                        _,
+                        type_,
+                    ) => Some(oxc::ast::ast::Statement::VariableDeclaration(
+                        oxc::allocator::Box::new_in(
                             AstBuilder::new(ctx.allocator).variable_declaration(
                                 Span::new(0, 0),
                                 oxc::ast::ast::VariableDeclarationKind::Let,
@@ -78,7 +214,7 @@ impl<'c> IntoOxc<'c, Statement<'c>> for oxidescript::parser::ast::Statement {
                                     vec![VariableDeclarator {
                                         span: Span::new(0, 0),
                                         kind: oxc::ast::ast::VariableDeclarationKind::Let,
-                                        id: ident.into_oxc(ctx),
+                                        id: types::annotated(ident.into_oxc(ctx), type_, ctx),
                                         init: Some(expr.into_oxc(ctx)),
                                         definite: false,
                                     }],
@@ -87,64 +223,164 @@ impl<'c> IntoOxc<'c, Statement<'c>> for oxidescript::parser::ast::Statement {
                                 false,
                             ),
                             ctx.allocator,
-                        ))
-                    }
+                        ),
+                    )),
+                    oxidescript::parser::ast::Declaration::DestructuringDeclaration(
+                        pattern,
+                        expr,
+                        _,
+                        type_,
+                    ) => Some(tuple::destructuring(pattern, expr, type_, ctx)),
                     oxidescript::parser::ast::Declaration::FunctionDeclaration {
                         name,
+                        type_parameters,
                         parameters,
+                        return_type,
+                        where_clause,
                         body,
-                    } => {
-                        oxc::ast::ast::Statement::FunctionDeclaration(oxc::allocator::Box::new_in(
+                        is_async,
+                        ..
+                    } => Some(oxc::ast::ast::Statement::FunctionDeclaration(
+                        oxc::allocator::Box::new_in(
                             oxc::ast::ast::Function {
                                 r#type: oxc::ast::ast::FunctionType::FunctionDeclaration,
                                 span: Span::new(0, 0),
                                 id: Some(name.into_oxc(ctx)),
                                 generator: false,
-                                r#async: false,
+                                r#async: is_async,
                                 declare: false,
-                                type_parameters: None,
+                                type_parameters: types::bounded_type_parameters(
+                                    &oxidescript::parser::ast::TypeParameter::with_where_clause(
+                                        &type_parameters,
+                                        &where_clause,
+                                    ),
+                                    ctx,
+                                ),
                                 this_param: None,
                                 params: oxc::allocator::Box::new_in(
                                     parameters.into_oxc(ctx),
                                     ctx.allocator,
                                 ),
                                 body: Some(body.into_oxc(ctx)),
-                                return_type: None,
+                                return_type: return_type.and_then(|return_type| {
+                                    types::type_annotation(
+                                        &structs::promised(is_async, return_type),
+                                        ctx,
+                                    )
+                                }),
                                 scope_id: None.into(),
                             },
                             ctx.allocator,
-                        ))
+                        ),
+                    )),
+                    oxidescript::parser::ast::Declaration::StructDeclaration(decl) => {
+                        decl.into_oxc(ctx)
+                    }
+                    oxidescript::parser::ast::Declaration::EnumDeclaration(decl) => {
+                        let lowered = ctx.register_enum(&decl);
+                        match decl.discriminants() {
+                            Some(_) => Some(structs::discriminant_object(lowered, ctx)),
+                            None => lowered.into_oxc(ctx),
+                        }
+                    }
+                    oxidescript::parser::ast::Declaration::ImplDeclaration(decl) => {
+                        Some(decl.into_oxc(ctx))
+                    }
+                    oxidescript::parser::ast::Declaration::ModDeclaration(decl) => {
+                        Some(decl.into_oxc(ctx))
+                    }
+                    oxidescript::parser::ast::Declaration::ModFileDeclaration(name) => {
+                        Some(import::mod_file(name, ctx))
+                    }
+                    oxidescript::parser::ast::Declaration::UseDeclaration(decl) => {
+                        import::use_declaration(decl, ctx)
+                    }
+                    // the typescript type of a `type` or trait comes with its interface
+                    oxidescript::parser::ast::Declaration::TypeDeclaration(_) => None,
+                    oxidescript::parser::ast::Declaration::TraitDeclaration(decl) => {
+                        structs::trait_object(decl, ctx)
+                    }
+                    oxidescript::parser::ast::Declaration::ExternDeclaration(decl) => {
+                        types::declare_function(decl, ctx)
                     }
                 }
             }
-        }
+        };
+        statement.map(|statement| mapped(statement, span, ctx))
     }
 }
 
 impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::Expression {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
-        match self {
-            oxidescript::parser::ast::Expression::IdentifierExpression(ident) => {
+        let span = self.span();
+        if !ctx.continues_chain.replace(false) && oxidescript::parser::optional::has_optional(&self)
+        {
+            return mapped(optional::chain(self, ctx), span, ctx);
+        }
+        let expression = match self {
+            oxidescript::parser::ast::Expression::IdentifierExpression(ident, _) => {
                 ident.into_oxc(ctx)
             }
-            oxidescript::parser::ast::Expression::LiteralExpression(literal) => {
+            oxidescript::parser::ast::Expression::LiteralExpression(literal, _) => {
                 literal.into_oxc(ctx)
             }
-            oxidescript::parser::ast::Expression::UnaryExpression(expr) => expr.into_oxc(ctx),
-            oxidescript::parser::ast::Expression::InfixExpression(expr) => expr.into_oxc(ctx),
-            oxidescript::parser::ast::Expression::ArrayExpression(exprs) => AstBuilder::new(
+            oxidescript::parser::ast::Expression::UnaryExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::InfixExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::AssignmentExpression(expr, _) => {
+                expr.into_oxc(ctx)
+            }
+            oxidescript::parser::ast::Expression::ArrayExpression(exprs, _) => AstBuilder::new(
+                ctx.allocator,
+            )
+            .expression_array(Span::new(0, 0), exprs.into_oxc(ctx), None),
+            // tuples are arrays with an item at every index of their type
+            oxidescript::parser::ast::Expression::TupleExpression(exprs, _) => AstBuilder::new(
                 ctx.allocator,
             )
             .expression_array(Span::new(0, 0), exprs.into_oxc(ctx), None),
-            oxidescript::parser::ast::Expression::IfExpression(expr) => expr.into_oxc(ctx),
-            oxidescript::parser::ast::Expression::BlockExpression(block) => block.into_oxc(ctx),
-            oxidescript::parser::ast::Expression::CallExpression(expr) => expr.into_oxc(ctx),
-            oxidescript::parser::ast::Expression::IndexExpression(expr) => expr.into_oxc(ctx),
-            oxidescript::parser::ast::Expression::MemberAccessExpression(expr) => {
+            oxidescript::parser::ast::Expression::TupleIndexExpression(expr, _) => {
                 expr.into_oxc(ctx)
             }
-            oxidescript::parser::ast::Expression::ForExpression(expr) => expr.into_oxc(ctx),
-        }
+            oxidescript::parser::ast::Expression::IfExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::BlockExpression(block, _) => block.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::CallExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::TaggedTemplateExpression(expr, _) => {
+                expr.into_oxc(ctx)
+            }
+            oxidescript::parser::ast::Expression::TemplateExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::IndexExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::SliceExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::RangeExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::MemberAccessExpression(expr, _) => {
+                expr.into_oxc(ctx)
+            }
+            oxidescript::parser::ast::Expression::OptionalExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::ForExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::WhileExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::MatchExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::StructExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::PathExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::ImportExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::ClosureExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::CastExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::IsExpression(expr, _) => expr.into_oxc(ctx),
+            oxidescript::parser::ast::Expression::AwaitExpression(value, _) => {
+                AstBuilder::new(ctx.allocator)
+                    .expression_await(Span::new(0, 0), value.into_oxc(ctx))
+            }
+            jump @ (oxidescript::parser::ast::Expression::ReturnExpression(_, _)
+            | oxidescript::parser::ast::Expression::BreakExpression(_)
+            | oxidescript::parser::ast::Expression::ContinueExpression(_)) => {
+                jump::expression(jump, ctx)
+            }
+            oxidescript::parser::ast::Expression::DeferExpression(_, _) => {
+                unreachable!("`defer` is only a statement of a block, which blocks compile")
+            }
+            oxidescript::parser::ast::Expression::WithExpression(_, _) => {
+                unreachable!("`with` is lowered to a block with a `defer`")
+            }
+        };
+        mapped(expression, span, ctx)
     }
 }
 
@@ -172,16 +408,20 @@ impl<'c> IntoOxc<'c, oxc::allocator::Vec<'c, Argument<'c>>>
     }
 }
 
+/// `(() => { body })()`, or `await (async () => { body })()` if the body awaits something
 pub fn iife<'c>(
     body: oxc::allocator::Vec<'c, Statement<'c>>,
     ctx: &'c JavascriptCompilerContext<'c>,
 ) -> Expression<'c> {
-    AstBuilder::new(ctx.allocator).expression_call(
+    let mut finder = FindAwait(false);
+    finder.visit_statements(&body);
+    let is_async = finder.0;
+    let call = AstBuilder::new(ctx.allocator).expression_call(
         Span::new(0, 0),
         AstBuilder::new(ctx.allocator).expression_arrow_function(
             Span::new(0, 0),
             false,
-            false,
+            is_async,
             None::<TSTypeParameterDeclaration>,
             AstBuilder::new(ctx.allocator).formal_parameters(
                 Span::new(0, 0),
@@ -199,5 +439,22 @@ pub fn iife<'c>(
         None::<TSTypeParameterInstantiation>,
         oxc::allocator::Vec::new_in(ctx.allocator),
         false,
-    )
+    );
+    match is_async {
+        true => AstBuilder::new(ctx.allocator).expression_await(Span::new(0, 0), call),
+        false => call,
+    }
+}
+
+/// Looks for an `await` outside of nested functions
+struct FindAwait(bool);
+
+impl<'a> Visit<'a> for FindAwait {
+    fn visit_await_expression(&mut self, _: &AwaitExpression<'a>) {
+        self.0 = true;
+    }
+
+    fn visit_function(&mut self, _: &Function<'a>, _: ScopeFlags) {}
+
+    fn visit_arrow_function_expression(&mut self, _: &ArrowFunctionExpression<'a>) {}
 }
@@ -0,0 +1,40 @@
+use oxc::{
+    ast::{
+        ast::{AssignmentOperator, Expression, SimpleAssignmentTarget},
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{AssignmentExpr, InfixOperator};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+impl<'c> IntoOxc<'c, Expression<'c>> for AssignmentExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let ast = AstBuilder::new(ctx.allocator);
+        let operator = match self.op {
+            None => AssignmentOperator::Assign,
+            Some(InfixOperator::Plus) => AssignmentOperator::Addition,
+            Some(InfixOperator::Minus) => AssignmentOperator::Subtraction,
+            Some(InfixOperator::Multiply) => AssignmentOperator::Multiplication,
+            Some(InfixOperator::Divide) => AssignmentOperator::Division,
+            Some(InfixOperator::Modulo) => AssignmentOperator::Remainder,
+            Some(op) => unreachable!("there is no `{:?}=`", op),
+        };
+        let target = match self.lhs.into_oxc(ctx) {
+            Expression::Identifier(ident) => {
+                SimpleAssignmentTarget::AssignmentTargetIdentifier(ident)
+            }
+            target if target.is_member_expression() => {
+                SimpleAssignmentTarget::from(target.into_member_expression())
+            }
+            _ => unreachable!("only variables, members and indexes can be assigned"),
+        };
+        ast.expression_assignment(
+            Span::new(0, 0),
+            operator,
+            target.into(),
+            self.rhs.into_oxc(ctx),
+        )
+    }
+}
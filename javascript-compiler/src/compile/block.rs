@@ -1,9 +1,6 @@
 use oxc::{
     ast::{
-        ast::{
-            BindingRestElement, Expression, FunctionBody, Statement, TSTypeAnnotation,
-            TSTypeParameterDeclaration, TSTypeParameterInstantiation,
-        },
+        ast::{Expression, FunctionBody, Statement},
         AstBuilder,
     },
     span::Span,
@@ -11,6 +8,13 @@ use oxc::{
 
 use crate::{IntoOxc, JavascriptCompilerContext};
 
+use super::{
+    conditional::is_ternary,
+    iife,
+    jump::{self, function_body, in_iife, is_jump},
+    types::struct_interface,
+};
+
 impl<'c> IntoOxc<'c, oxc::allocator::Box<'c, FunctionBody<'c>>>
     for oxidescript::parser::ast::Block
 {
@@ -22,7 +26,7 @@ impl<'c> IntoOxc<'c, oxc::allocator::Box<'c, FunctionBody<'c>>>
             AstBuilder::new(ctx.allocator).function_body(
                 Span::new(0, 0),
                 oxc::allocator::Vec::new_in(ctx.allocator),
-                self.into_oxc(ctx),
+                function_body(ctx, || self.into_oxc(ctx)),
             ),
             ctx.allocator,
         )
@@ -34,13 +38,22 @@ impl<'c> IntoOxc<'c, oxc::allocator::Vec<'c, Statement<'c>>> for oxidescript::pa
         self,
         ctx: &'c JavascriptCompilerContext<'c>,
     ) -> oxc::allocator::Vec<'c, Statement<'c>> {
-        let statements = self
-            .statements
-            .into_iter()
-            .map(|statement| statement.into_oxc(ctx));
-        let return_value = self.return_value.map(|return_value| {
-            AstBuilder::new(ctx.allocator)
-                .statement_return(Span::new(0, 0), Some(return_value.into_oxc(ctx)))
+        if let Some(split) = self.split_at_defer() {
+            return deferred(split, ctx);
+        }
+        let statements = self.statements.into_iter().flat_map(|statement| {
+            struct_interface(&statement, ctx)
+                .into_iter()
+                .chain(statement.into_oxc(ctx))
+        });
+        let return_value = self.return_value.map(|return_value| match return_value {
+            jump if is_jump(&jump) => jump::statement(jump, ctx),
+            // the branches return their values themselves, instead of an IIFE that returns them
+            oxidescript::parser::ast::Expression::IfExpression(expr, _) if !is_ternary(&expr) => {
+                expr.into_oxc(ctx)
+            }
+            return_value => AstBuilder::new(ctx.allocator)
+                .statement_return(Span::new(0, 0), Some(return_value.into_oxc(ctx))),
         });
         let mut vec = oxc::allocator::Vec::from_iter_in(statements, ctx.allocator);
         if let Some(return_value) = return_value {
@@ -52,26 +65,57 @@ impl<'c> IntoOxc<'c, oxc::allocator::Vec<'c, Statement<'c>>> for oxidescript::pa
 
 impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::Block {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
-        let callee = AstBuilder::new(ctx.allocator).expression_arrow_function(
-            Span::new(0, 0),
-            false,
-            false,
-            None::<TSTypeParameterDeclaration>,
-            AstBuilder::new(ctx.allocator).formal_parameters(
-                Span::new(0, 0),
-                oxc::ast::ast::FormalParameterKind::FormalParameter,
-                oxc::allocator::Vec::new_in(ctx.allocator),
-                None::<BindingRestElement>,
-            ),
-            None::<TSTypeAnnotation>,
-            <Self as IntoOxc<oxc::allocator::Box<FunctionBody>>>::into_oxc(self, ctx),
-        );
-        AstBuilder::new(ctx.allocator).expression_call(
+        let statements = in_iife(ctx, || self.into_oxc(ctx));
+        iife(statements, ctx)
+    }
+}
+
+/// The statements of a block whose value isn't used, with the value evaluated as a statement of its
+/// own
+pub fn discarded<'c>(
+    mut block: oxidescript::parser::ast::Block,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Vec<'c, Statement<'c>> {
+    discard(&mut block);
+    block.into_oxc(ctx)
+}
+
+pub fn discard(block: &mut oxidescript::parser::ast::Block) {
+    if let Some(value) = block.return_value.take() {
+        block
+            .statements
+            .push(oxidescript::parser::ast::Statement::ExpressionStatement {
+                expression: value,
+                has_semicolon: true,
+                span: oxidescript::parser::ast::Span::default(),
+            });
+    }
+}
+
+/// `...before; try { ...rest } finally { ...cleanup }` for a block with a `defer { ...cleanup }`
+fn deferred<'c>(
+    deferred: oxidescript::parser::ast::Deferred,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Vec<'c, Statement<'c>> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let before = oxidescript::parser::ast::Block {
+        statements: deferred.before,
+        return_value: None,
+        span: oxidescript::parser::ast::Span::default(),
+    };
+    let mut statements: oxc::allocator::Vec<'c, Statement<'c>> = before.into_oxc(ctx);
+    let cleanup = discarded(deferred.cleanup, ctx);
+    let rest = deferred.rest;
+    if rest.statements.is_empty() && rest.return_value.is_none() {
+        statements.extend(cleanup);
+    } else {
+        let rest = rest.into_oxc(ctx);
+        statements.push(ast.statement_try(
             Span::new(0, 0),
-            callee,
-            None::<TSTypeParameterInstantiation>,
-            oxc::allocator::Vec::new_in(ctx.allocator),
-            false,
-        )
+            ast.block_statement(Span::new(0, 0), rest),
+            None::<oxc::allocator::Box<oxc::ast::ast::CatchClause>>,
+            Some(ast.alloc_block_statement(Span::new(0, 0), cleanup)),
+        ));
     }
+    statements
 }
@@ -0,0 +1,47 @@
+use oxc::{
+    ast::{
+        ast::{Expression, Statement, TSTypeAnnotation, TSTypeParameterDeclaration},
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::ClosureExpr;
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+use super::jump;
+
+/// An arrow function. A block body becomes the body of the arrow function, so `|x| { x * 2 }` is
+/// `(x) => { return x * 2; }` instead of an arrow function that calls another one
+impl<'c> IntoOxc<'c, Expression<'c>> for ClosureExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let ast = AstBuilder::new(ctx.allocator);
+        let is_block = matches!(
+            *self.body,
+            oxidescript::parser::ast::Expression::BlockExpression(_, _)
+        );
+        let block = self.body_block();
+        let mut statements = jump::function_body(ctx, || block.into_oxc(ctx));
+        // bodies that aren't blocks are arrow functions with an expression body, unless they
+        // needed statements to catch a `return`
+        let expression = match statements.as_mut_slice() {
+            [Statement::ReturnStatement(statement)]
+                if !is_block && statement.argument.is_some() =>
+            {
+                let value = statement.argument.take().unwrap();
+                statements = ast.vec1(ast.statement_expression(Span::new(0, 0), value));
+                true
+            }
+            _ => false,
+        };
+        ast.expression_arrow_function(
+            Span::new(0, 0),
+            expression,
+            self.is_async,
+            None::<TSTypeParameterDeclaration>,
+            self.parameters.into_oxc(ctx),
+            None::<TSTypeAnnotation>,
+            ast.function_body(Span::new(0, 0), ast.vec(), statements),
+        )
+    }
+}
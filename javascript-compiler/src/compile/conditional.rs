@@ -5,16 +5,40 @@ use oxc::{
     },
     span::Span,
 };
+use oxidescript::parser::ast::Block;
 
 use crate::{IntoOxc, JavascriptCompilerContext};
 
-use super::iife;
+use super::{
+    block::discard,
+    iife,
+    jump::{in_iife, is_jump},
+};
 
+/// `if` expressions where every branch is just a value compile to a ternary, like
+/// `let x = if cond { 1 } else { 2 };` to `let x = cond ? 1 : 2;`. Branches with statements need
+/// an IIFE around an `if` statement
 impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::IfExpr {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
-        iife(
-            oxc::allocator::Vec::from_iter_in([self.into_oxc(ctx)], ctx.allocator),
-            ctx,
+        if !is_ternary(&self) {
+            let statement = in_iife(ctx, || self.into_oxc(ctx));
+            return iife(AstBuilder::new(ctx.allocator).vec1(statement), ctx);
+        }
+        let value = |block: Block| block.return_value.unwrap().into_oxc(ctx);
+        let mut alternate = value(*self.else_block.unwrap());
+        for else_if in self.else_if_blocks.into_iter().rev() {
+            alternate = AstBuilder::new(ctx.allocator).expression_conditional(
+                Span::new(0, 0),
+                else_if.condition.into_oxc(ctx),
+                value(else_if.then_block),
+                alternate,
+            );
+        }
+        AstBuilder::new(ctx.allocator).expression_conditional(
+            Span::new(0, 0),
+            self.condition.into_oxc(ctx),
+            value(*self.then_block),
+            alternate,
         )
     }
 }
@@ -88,3 +112,35 @@ impl<'c> IntoOxc<'c, Statement<'c>> for oxidescript::parser::ast::IfExpr {
         )
     }
 }
+
+/// Whether every branch is just a value. Jumps need to be statements, so branches with them aren't
+pub fn is_ternary(expr: &oxidescript::parser::ast::IfExpr) -> bool {
+    let is_value = |block: &Block| {
+        block.statements.is_empty()
+            && block
+                .return_value
+                .as_ref()
+                .is_some_and(|value| !is_jump(value))
+    };
+    expr.else_block.as_deref().is_some_and(is_value)
+        && is_value(&expr.then_block)
+        && expr
+            .else_if_blocks
+            .iter()
+            .all(|else_if| is_value(&else_if.then_block))
+}
+
+/// An `if` as a statement, with the values of the branches discarded
+pub fn discarded<'c>(
+    mut expr: oxidescript::parser::ast::IfExpr,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    discard(&mut expr.then_block);
+    for else_if in &mut expr.else_if_blocks {
+        discard(&mut else_if.then_block);
+    }
+    if let Some(else_block) = &mut expr.else_block {
+        discard(else_block);
+    }
+    expr.into_oxc(ctx)
+}
@@ -1,13 +1,20 @@
 use oxc::{
     ast::{
-        ast::{Expression, FormalParameter, FormalParameters, TSTypeParameterInstantiation},
+        ast::{
+            BindingPattern, Expression, FormalParameter, FormalParameters,
+            TSTypeParameterInstantiation,
+        },
         AstBuilder,
     },
     span::Span,
 };
 
+use oxidescript::parser::ast::{CallExpr, Identifier, MemberAccessExpr};
+
 use crate::{IntoOxc, JavascriptCompilerContext};
 
+use super::{guards, intrinsic::Intrinsic, optional::object, types::type_annotation};
+
 impl<'c> IntoOxc<'c, FormalParameters<'c>> for Vec<oxidescript::parser::ast::Parameter> {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> FormalParameters<'c> {
         FormalParameters {
@@ -24,9 +31,11 @@ impl<'c> IntoOxc<'c, FormalParameters<'c>> for Vec<oxidescript::parser::ast::Par
 
 impl<'c> IntoOxc<'c, FormalParameter<'c>> for oxidescript::parser::ast::Parameter {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> FormalParameter<'c> {
+        let mut pattern: BindingPattern = self.name.into_oxc(ctx);
+        pattern.type_annotation = type_annotation(&self.type_, ctx);
         FormalParameter {
             span: Span::new(0, 0),
-            pattern: self.name.into_oxc(ctx),
+            pattern,
             accessibility: None,
             readonly: false,
             r#override: false,
@@ -37,12 +46,67 @@ impl<'c> IntoOxc<'c, FormalParameter<'c>> for oxidescript::parser::ast::Paramete
 
 impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::CallExpr {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
-        AstBuilder::new(ctx.allocator).expression_call(
-            Span::new(0, 0),
-            self.lhs.into_oxc(ctx),
-            None::<TSTypeParameterInstantiation>,
-            self.arguments.into_oxc(ctx),
-            false,
-        )
+        // methods taking `self` are on the prototype of classes, so `Counter::get(c)` is `c.get()`,
+        // or `Counter.prototype.get.call(c)` if instances have a field `get`
+        if let oxidescript::parser::ast::Expression::PathExpression(path, span) = &*self.lhs {
+            if !self.arguments.is_empty()
+                && ctx.is_prototype_method(path)
+                && ctx.is_shadowed_method(path)
+            {
+                let member = |lhs, ident: &str| {
+                    oxidescript::parser::ast::Expression::MemberAccessExpression(
+                        MemberAccessExpr {
+                            lhs: Box::new(lhs),
+                            ident: Identifier(ident.to_string()),
+                        },
+                        *span,
+                    )
+                };
+                let type_ = ctx.resolve_self_type(path.segments[0].clone());
+                let class =
+                    oxidescript::parser::ast::Expression::IdentifierExpression(type_, *span);
+                let method = member(member(class, "prototype"), &path.segments[1].0);
+                return CallExpr {
+                    lhs: Box::new(member(method, "call")),
+                    arguments: self.arguments,
+                }
+                .into_oxc(ctx);
+            }
+            if !self.arguments.is_empty() && ctx.is_prototype_method(path) {
+                let mut arguments = self.arguments;
+                let receiver = arguments.remove(0);
+                let lhs = oxidescript::parser::ast::Expression::MemberAccessExpression(
+                    MemberAccessExpr {
+                        lhs: Box::new(receiver),
+                        ident: path.segments[1].clone(),
+                    },
+                    *span,
+                );
+                // a method, even if it is named like an intrinsic
+                return call(
+                    CallExpr {
+                        lhs: Box::new(lhs),
+                        arguments,
+                    },
+                    ctx,
+                );
+            }
+        }
+        let prelude = ctx.prelude_in(&self.lhs);
+        match Intrinsic::from_call(self, prelude) {
+            Ok(intrinsic) => intrinsic.into_oxc(ctx),
+            Err(expr) => call(expr, ctx),
+        }
     }
 }
+
+fn call<'c>(expr: CallExpr, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+    let compiled = AstBuilder::new(ctx.allocator).expression_call(
+        Span::new(0, 0),
+        object((*expr.lhs).clone(), ctx),
+        None::<TSTypeParameterInstantiation>,
+        expr.arguments.into_oxc(ctx),
+        false,
+    );
+    guards::checked_call(&expr.lhs, compiled, ctx)
+}
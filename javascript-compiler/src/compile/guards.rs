@@ -0,0 +1,468 @@
+use oxc::{
+    ast::{
+        ast::{
+            Argument, BinaryOperator, Expression, FunctionType, LogicalOperator, Statement,
+            TSTypeParameterInstantiation, UnaryOperator, VariableDeclarationKind,
+        },
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::{
+    ast::{Declaration, Field, Identifier, IsExpr, Parameter, Span as SourceSpan},
+    function::parameter_label,
+    types::{
+        alternatives, article, impl_fn, substitute, type_arguments, type_as_literal, union_members,
+    },
+};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+use super::{iife, jump::in_iife, structs::function_expression};
+
+/// Compiles a top level statement, with checks of the parameter types at the start of functions if
+/// [`type_guards`](crate::JavascriptCompilerOptions::type_guards) is set. Top level functions are
+/// the ones other scripts can call, with values the type annotations don't hold for
+pub fn top_level<'c>(
+    statement: oxidescript::parser::ast::Statement,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Statement<'c>> {
+    let guards = match &statement {
+        oxidescript::parser::ast::Statement::DeclarationStatement(
+            Declaration::FunctionDeclaration {
+                name, parameters, ..
+            },
+            _,
+        ) if ctx.options.type_guards => parameters
+            .iter()
+            .filter_map(|parameter| parameter_guard(name, parameter, ctx))
+            .collect(),
+        _ => vec![],
+    };
+    let mut compiled = statement.into_oxc(ctx);
+    if let Some(Statement::FunctionDeclaration(function)) = &mut compiled {
+        if let Some(body) = &mut function.body {
+            let statements =
+                std::mem::replace(&mut body.statements, AstBuilder::new(ctx.allocator).vec());
+            body.statements.extend(guards);
+            body.statements.extend(statements);
+        }
+    }
+    compiled
+}
+
+/// `function __checked_name(value) { if (...) throw new TypeError(...); return value; }` for an
+/// extern whose return type can be checked, if
+/// [`type_guards`](crate::JavascriptCompilerOptions::type_guards) is set. Externs return values of
+/// the host, which don't have to match the type annotations either, so their calls go through it
+pub fn extern_result<'c>(
+    statement: &oxidescript::parser::ast::Statement,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Statement<'c>> {
+    let oxidescript::parser::ast::Statement::DeclarationStatement(
+        Declaration::ExternDeclaration(decl),
+        _,
+    ) = statement
+    else {
+        return None;
+    };
+    let ast = AstBuilder::new(ctx.allocator);
+    let value = || ast.expression_identifier_reference(Span::new(0, 0), "value");
+    let (invalid, expected) = result_check(&decl.name.0, &value, ctx)?;
+    let guard = throw_type_error(
+        invalid,
+        format!(
+            "{}: expected the returned value to be {}",
+            decl.name.0, expected
+        ),
+        value(),
+        ctx,
+    );
+    let body = ast.function_body(
+        Span::new(0, 0),
+        ast.vec(),
+        ast.vec_from_array([guard, ast.statement_return(Span::new(0, 0), Some(value()))]),
+    );
+    let parameter = Parameter {
+        name: Identifier("value".to_string()),
+        type_: Identifier("any".to_string()),
+        span: SourceSpan::default(),
+    };
+    let mut function = function_expression(vec![parameter], decl.return_type.clone(), body, ctx);
+    function.r#type = FunctionType::FunctionDeclaration;
+    function.id = Some(Identifier(checked_extern(&decl.name.0)).into_oxc(ctx));
+    Some(Statement::FunctionDeclaration(function))
+}
+
+/// `__checked_name(call)` for a call of an extern whose returned value is checked, see
+/// [`extern_result`]
+pub fn checked_call<'c>(
+    callee: &oxidescript::parser::ast::Expression,
+    call: Expression<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    let oxidescript::parser::ast::Expression::IdentifierExpression(name, _) = callee else {
+        return call;
+    };
+    let ast = AstBuilder::new(ctx.allocator);
+    let value = || ast.expression_null_literal(Span::new(0, 0));
+    if result_check(&name.0, &value, ctx).is_none() {
+        return call;
+    }
+    ast.expression_call(
+        Span::new(0, 0),
+        ast.expression_identifier_reference(Span::new(0, 0), ast.atom(&checked_extern(&name.0))),
+        None::<TSTypeParameterInstantiation>,
+        ast.vec1(Argument::from(call)),
+        false,
+    )
+}
+
+/// The check of what the extern returns, with `--type-guards` and a return type that can be
+/// checked
+fn result_check<'c>(
+    name: &str,
+    value: &dyn Fn() -> Expression<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<(Expression<'c>, String)> {
+    if !ctx.options.type_guards {
+        return None;
+    }
+    let return_type = ctx.externs.borrow().get(name).cloned()?;
+    invalid_value(value, &return_type.0, ctx)
+}
+
+fn checked_extern(name: &str) -> String {
+    format!("__checked_{}", name)
+}
+
+/// `if (typeof x !== "number") throw new TypeError(...)`
+fn parameter_guard<'c>(
+    function: &Identifier,
+    parameter: &Parameter,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Statement<'c>> {
+    let value = || parameter.name.clone().into_oxc(ctx);
+    let (invalid, expected) = invalid_value(&value, &parameter.type_.0, ctx)?;
+    // destructured parameters only have a position
+    let name = match parameter_label(&parameter.name).parse::<usize>() {
+        Ok(position) => format!("argument {}", position),
+        Err(_) => parameter.name.0.clone(),
+    };
+    Some(throw_type_error(
+        invalid,
+        format!("{}: expected {} to be {}", function.0, name, expected),
+        value(),
+        ctx,
+    ))
+}
+
+/// A condition that is true if `value` doesn't hold a `type_`, and a description of the type.
+/// Arrays are checked with `Array.isArray`, but not their items. Struct values have to be objects
+/// with all of their fields, and the fields with primitive types have to have that type.
+/// `impl Fn` values only have to be functions. Values of any other type can't be checked
+pub fn invalid_value<'c>(
+    value: &dyn Fn() -> Expression<'c>,
+    type_: &str,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<(Expression<'c>, String)> {
+    check_type(value, type_, true, ctx)
+}
+
+/// The condition of `value is type`, see [`invalid_value`] for what is checked
+pub fn valid_value<'c>(
+    value: &dyn Fn() -> Expression<'c>,
+    type_: &str,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Expression<'c>> {
+    check_type(value, type_, false, ctx).map(|(valid, _)| valid)
+}
+
+/// A condition that is true if `value` holds a `type_`, or doesn't if `negated`. A value holds a
+/// union if it holds one of its members, and unions can only be checked if all of their members
+/// can
+fn check_type<'c>(
+    value: &dyn Fn() -> Expression<'c>,
+    type_: &str,
+    negated: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<(Expression<'c>, String)> {
+    let ast = AstBuilder::new(ctx.allocator);
+    // what a function takes and returns only shows when it is called
+    if impl_fn(type_).is_some() {
+        return Some((
+            is_type(value(), "function", negated, ctx),
+            "a function".to_string(),
+        ));
+    }
+    let members = union_members(type_)
+        .into_iter()
+        .map(|member| check_single_type(value, member, negated, ctx))
+        .collect::<Option<Vec<_>>>()?;
+    let operator = if negated {
+        LogicalOperator::And
+    } else {
+        LogicalOperator::Or
+    };
+    let (conditions, expected): (Vec<_>, Vec<_>) = members.into_iter().unzip();
+    let condition = conditions.into_iter().reduce(|condition, member| {
+        ast.expression_logical(Span::new(0, 0), condition, operator, member)
+    })?;
+    Some((condition, alternatives(&expected)))
+}
+
+fn check_single_type<'c>(
+    value: &dyn Fn() -> Expression<'c>,
+    type_: &str,
+    negated: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<(Expression<'c>, String)> {
+    let ast = AstBuilder::new(ctx.allocator);
+    // `value === null` is true if the value holds `null` or a literal, and `value !== null` if it
+    // can be an object
+    let (is_null, is_not_null, all) = if negated {
+        (
+            BinaryOperator::StrictInequality,
+            BinaryOperator::StrictEquality,
+            LogicalOperator::Or,
+        )
+    } else {
+        (
+            BinaryOperator::StrictEquality,
+            BinaryOperator::StrictInequality,
+            LogicalOperator::And,
+        )
+    };
+    if let "number" | "string" | "boolean" = type_ {
+        return Some((
+            is_type(value(), type_, negated, ctx),
+            format!("a {}", type_),
+        ));
+    }
+    if let "null" | "undefined" = type_ {
+        let empty = match type_ {
+            "null" => ast.expression_null_literal(Span::new(0, 0)),
+            _ => ast.expression_identifier_reference(Span::new(0, 0), "undefined"),
+        };
+        return Some((
+            ast.expression_binary(Span::new(0, 0), value(), is_null, empty),
+            type_.to_string(),
+        ));
+    }
+    if let Some(literal) = type_as_literal(type_) {
+        return Some((
+            ast.expression_binary(Span::new(0, 0), value(), is_null, literal.into_oxc(ctx)),
+            type_.to_string(),
+        ));
+    }
+    if type_.starts_with('[') {
+        let is_array = ast.expression_call(
+            Span::new(0, 0),
+            Expression::from(ast.member_expression_static(
+                Span::new(0, 0),
+                ast.expression_identifier_reference(Span::new(0, 0), "Array"),
+                ast.identifier_name(Span::new(0, 0), "isArray"),
+                false,
+            )),
+            None::<TSTypeParameterInstantiation>,
+            ast.vec1(Argument::from(value())),
+            false,
+        );
+        return Some((not(is_array, negated, ctx), "an array".to_string()));
+    }
+    let Some(fields) = struct_fields(type_, ctx) else {
+        let alias = ctx.types.borrow().get(type_)?.alias.clone();
+        return check_type(value, &alias?.0, negated, ctx);
+    };
+    let object = ast.expression_logical(
+        Span::new(0, 0),
+        is_type(value(), "object", negated, ctx),
+        all,
+        ast.expression_binary(
+            Span::new(0, 0),
+            value(),
+            is_not_null,
+            ast.expression_null_literal(Span::new(0, 0)),
+        ),
+    );
+    let condition = fields.iter().fold(object, |condition, field| {
+        let field_condition = match field.type_.0.as_str() {
+            type_ @ ("number" | "string" | "boolean") => is_type(
+                Expression::from(ast.member_expression_static(
+                    Span::new(0, 0),
+                    value(),
+                    field.name.clone().into_oxc(ctx),
+                    false,
+                )),
+                type_,
+                negated,
+                ctx,
+            ),
+            _ => not(
+                ast.expression_binary(
+                    Span::new(0, 0),
+                    string(&field.name.0, ctx),
+                    BinaryOperator::In,
+                    value(),
+                ),
+                negated,
+                ctx,
+            ),
+        };
+        ast.expression_logical(Span::new(0, 0), condition, all, field_condition)
+    });
+    Some((
+        condition,
+        format!("{} with {}", article(type_), field_list(&fields)),
+    ))
+}
+
+/// The fields of a struct or of a `type` with fields, with the type arguments of a generic struct in
+/// place of its type parameters
+pub fn struct_fields(type_: &str, ctx: &JavascriptCompilerContext) -> Option<Vec<Field>> {
+    let (name, arguments) = type_arguments(type_);
+    if let Some(info) = ctx.structs.borrow().get(name) {
+        return Some(
+            info.fields
+                .iter()
+                .map(|field| Field {
+                    type_: Identifier(substitute(
+                        &field.type_.0,
+                        &info.type_parameters,
+                        &arguments,
+                    )),
+                    ..field.clone()
+                })
+                .collect(),
+        );
+    }
+    match ctx.types.borrow().get(type_)? {
+        decl if decl.alias.is_none() => Some(decl.fields.clone()),
+        _ => None,
+    }
+}
+
+/// `value is type` checks the value like a type guard does. Values other than names are only
+/// evaluated once, by binding them to `__value` in an IIFE
+impl<'c> IntoOxc<'c, Expression<'c>> for IsExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let ast = AstBuilder::new(ctx.allocator);
+        if let oxidescript::parser::ast::Expression::IdentifierExpression(name, _) = *self.value {
+            let value = || name.clone().into_oxc(ctx);
+            return valid_value(&value, &self.type_.0, ctx)
+                .expect("the type checker only allows types that can be checked");
+        }
+        let name = Identifier("__value".to_string());
+        let value = || name.clone().into_oxc(ctx);
+        let valid = valid_value(&value, &self.type_.0, ctx)
+            .expect("the type checker only allows types that can be checked");
+        let declaration = in_iife(ctx, || {
+            ast.variable_declaration(
+                Span::new(0, 0),
+                VariableDeclarationKind::Const,
+                ast.vec1(ast.variable_declarator(
+                    Span::new(0, 0),
+                    VariableDeclarationKind::Const,
+                    name.clone().into_oxc(ctx),
+                    Some(self.value.into_oxc(ctx)),
+                    false,
+                )),
+                false,
+            )
+        });
+        iife(
+            ast.vec_from_array([
+                Statement::VariableDeclaration(ast.alloc(declaration)),
+                ast.statement_return(Span::new(0, 0), Some(valid)),
+            ]),
+            ctx,
+        )
+    }
+}
+
+/// `if (invalid) throw new TypeError("message, got " + typeof value);`
+pub fn throw_type_error<'c>(
+    invalid: Expression<'c>,
+    message: String,
+    value: Expression<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let message = ast.expression_binary(
+        Span::new(0, 0),
+        string(&format!("{}, got ", message), ctx),
+        BinaryOperator::Addition,
+        ast.expression_unary(Span::new(0, 0), UnaryOperator::Typeof, value),
+    );
+    throw_if(invalid, message, ctx)
+}
+
+/// `if (invalid) throw new TypeError(message);`
+pub fn throw_if<'c>(
+    invalid: Expression<'c>,
+    message: Expression<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let error = ast.expression_new(
+        Span::new(0, 0),
+        ast.expression_identifier_reference(Span::new(0, 0), "TypeError"),
+        ast.vec1(Argument::from(message)),
+        None::<TSTypeParameterInstantiation>,
+    );
+    ast.statement_if(
+        Span::new(0, 0),
+        invalid,
+        ast.statement_throw(Span::new(0, 0), error),
+        None,
+    )
+}
+
+fn field_list(fields: &[Field]) -> String {
+    fields
+        .iter()
+        .map(|field| format!("{}: {}", field.name.0, field.type_.0))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `typeof value === "type"`, or `!==` if `negated`
+pub fn is_type<'c>(
+    value: Expression<'c>,
+    type_: &str,
+    negated: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    ast.expression_binary(
+        Span::new(0, 0),
+        ast.expression_unary(Span::new(0, 0), UnaryOperator::Typeof, value),
+        if negated {
+            BinaryOperator::StrictInequality
+        } else {
+            BinaryOperator::StrictEquality
+        },
+        string(type_, ctx),
+    )
+}
+
+/// `!condition` if `negated`
+fn not<'c>(
+    condition: Expression<'c>,
+    negated: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    if !negated {
+        return condition;
+    }
+    AstBuilder::new(ctx.allocator).expression_unary(
+        Span::new(0, 0),
+        UnaryOperator::LogicalNot,
+        condition,
+    )
+}
+
+pub fn string<'c>(value: &str, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    ast.expression_string_literal(Span::new(0, 0), ast.atom(value), None)
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use oxc::{
     ast::{
         ast::{
@@ -11,6 +13,53 @@ use oxc::{
 
 use crate::{IntoOxc, JavascriptCompilerContext};
 
+/// Words that are reserved in ES modules but not in oxidescript. Variables, functions and other
+/// bindings named like them get a `_` appended, property names stay as they are
+const RESERVED_WORDS: &[&str] = &[
+    "await",
+    "case",
+    "catch",
+    "class",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "export",
+    "extends",
+    "finally",
+    "function",
+    "implements",
+    "instanceof",
+    "interface",
+    "new",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "static",
+    "super",
+    "switch",
+    "throw",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "yield",
+];
+
+/// The name a binding of the program has in javascript
+pub fn binding_name(name: &str) -> Cow<'_, str> {
+    match RESERVED_WORDS.contains(&name) {
+        true => Cow::Owned(format!("{}_", name)),
+        false => Cow::Borrowed(name),
+    }
+}
+
+/// Whether the binding is renamed in javascript, so it has to be exported under its own name
+pub fn is_reserved(name: &oxidescript::parser::ast::Identifier) -> bool {
+    RESERVED_WORDS.contains(&name.0.as_str())
+}
+
 impl<'c> IntoOxc<'c, BindingPattern<'c>> for oxidescript::parser::ast::Identifier {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> BindingPattern<'c> {
         BindingPattern {
@@ -25,7 +74,7 @@ impl<'c> IntoOxc<'c, BindingPattern<'c>> for oxidescript::parser::ast::Identifie
 
 impl<'c> IntoOxc<'c, BindingIdentifier<'c>> for oxidescript::parser::ast::Identifier {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> BindingIdentifier<'c> {
-        let name = AstBuilder::new(ctx.allocator).atom(&self.0.clone());
+        let name = AstBuilder::new(ctx.allocator).atom(&binding_name(&self.0));
         BindingIdentifier {
             span: Span::new(0, 0),
             name,
@@ -36,14 +85,18 @@ impl<'c> IntoOxc<'c, BindingIdentifier<'c>> for oxidescript::parser::ast::Identi
 
 impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::Identifier {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
-        let name = AstBuilder::new(ctx.allocator).atom(&self.0.clone());
+        if ctx.self_is_this.get() && self.0 == "self" {
+            return AstBuilder::new(ctx.allocator).expression_this(Span::new(0, 0));
+        }
+        let name = AstBuilder::new(ctx.allocator).atom(&binding_name(&self.0));
         AstBuilder::new(ctx.allocator).expression_identifier_reference(Span::new(0, 0), name)
     }
 }
 
 impl<'c> IntoOxc<'c, IdentifierReference<'c>> for oxidescript::parser::ast::Identifier {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> IdentifierReference {
-        AstBuilder::new(ctx.allocator).identifier_reference(Span::new(0, 0), self.0)
+        let name = AstBuilder::new(ctx.allocator).atom(&binding_name(&self.0));
+        AstBuilder::new(ctx.allocator).identifier_reference(Span::new(0, 0), name)
     }
 }
 
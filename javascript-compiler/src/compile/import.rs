@@ -0,0 +1,162 @@
+use oxc::{
+    ast::{
+        ast::{Expression, ImportOrExportKind, Statement, VariableDeclarationKind},
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{Declaration, Identifier, UseDecl};
+
+use crate::{compile::ident::binding_name, IntoOxc, JavascriptCompilerContext, OutputLanguage};
+
+/// Every module compiles to a `.js` file of the same name, so the extension is added to the path
+impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::ImportExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        AstBuilder::new(ctx.allocator).expression_import(
+            Span::new(0, 0),
+            AstBuilder::new(ctx.allocator).expression_string_literal(
+                Span::new(0, 0),
+                format!("{}.js", self.path).as_str(),
+                None,
+            ),
+            oxc::allocator::Vec::new_in(ctx.allocator),
+            None,
+        )
+    }
+}
+
+/// `mod name;` is `import * as name from "./name.js";`, so `name::item` paths become member
+/// accesses on the namespace like for inline modules
+pub fn mod_file<'c>(name: Identifier, ctx: &'c JavascriptCompilerContext<'c>) -> Statement<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let source = ast.string_literal(Span::new(0, 0), format!("./{}.js", name.0), None);
+    let specifier = ast.import_declaration_specifier_import_namespace_specifier(
+        Span::new(0, 0),
+        name.into_oxc(ctx),
+    );
+    ast.module_declaration_import_declaration(
+        Span::new(0, 0),
+        Some(ast.vec1(specifier)),
+        source,
+        None,
+        None::<oxc::allocator::Box<oxc::ast::ast::WithClause>>,
+        ImportOrExportKind::Value,
+    )
+    .into()
+}
+
+/// `use name::{a, b};` of a top level `mod name;` is `import { a, b } from "./name.js";`, other
+/// `use` declarations are `const a = name.a, b = name.b;`. Structs without impl blocks don't
+/// exist at runtime, so in javascript only the items used as values are imported
+pub fn use_declaration<'c>(
+    decl: UseDecl,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Statement<'c>> {
+    let ast = AstBuilder::new(ctx.allocator);
+    match decl.path.as_slice() {
+        [module] if ctx.file_modules.borrow().contains(&module.0) => {
+            let items = decl
+                .items
+                .into_iter()
+                .filter(|item| {
+                    ctx.options.output_language == OutputLanguage::Typescript
+                        || ctx.value_names.borrow().contains(&item.0)
+                })
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                return None;
+            }
+            let source = ast.string_literal(Span::new(0, 0), format!("./{}.js", module.0), None);
+            let specifiers = ast.vec_from_iter(items.into_iter().map(|item| {
+                ast.import_declaration_specifier_import_specifier(
+                    Span::new(0, 0),
+                    ast.module_export_name_identifier_name(Span::new(0, 0), item.0.clone()),
+                    item.into_oxc(ctx),
+                    ImportOrExportKind::Value,
+                )
+            }));
+            Some(
+                ast.module_declaration_import_declaration(
+                    Span::new(0, 0),
+                    Some(specifiers),
+                    source,
+                    None,
+                    None::<oxc::allocator::Box<oxc::ast::ast::WithClause>>,
+                    ImportOrExportKind::Value,
+                )
+                .into(),
+            )
+        }
+        _ => {
+            let declarators = ast.vec_from_iter(decl.bindings().into_iter().map(|binding| {
+                let Declaration::ConstDeclaration(name, value, _, _, _) = binding else {
+                    unreachable!("use declarations bind constants");
+                };
+                ast.variable_declarator(
+                    Span::new(0, 0),
+                    VariableDeclarationKind::Const,
+                    name.into_oxc(ctx),
+                    Some(value.into_oxc(ctx)),
+                    false,
+                )
+            }));
+            Some(Statement::VariableDeclaration(
+                ast.alloc_variable_declaration(
+                    Span::new(0, 0),
+                    VariableDeclarationKind::Const,
+                    declarators,
+                    false,
+                ),
+            ))
+        }
+    }
+}
+
+/// `export` in front of the declaration of a `pub` item
+pub fn export_declaration<'c>(
+    statement: Statement<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    if !statement.is_declaration() {
+        return statement;
+    }
+    AstBuilder::new(ctx.allocator)
+        .module_declaration_export_named_declaration(
+            Span::new(0, 0),
+            Some(statement.into_declaration()),
+            AstBuilder::new(ctx.allocator).vec(),
+            None,
+            ImportOrExportKind::Value,
+            None::<oxc::allocator::Box<oxc::ast::ast::WithClause>>,
+        )
+        .into()
+}
+
+/// `export { private_ as private };` for `pub` items whose names are renamed in javascript, so
+/// they are exported under their own name
+pub fn exports<'c>(
+    names: Vec<Identifier>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let specifiers = ast.vec_from_iter(names.into_iter().map(|name| {
+        ast.export_specifier(
+            Span::new(0, 0),
+            ast.module_export_name_identifier_reference(
+                Span::new(0, 0),
+                ast.atom(&binding_name(&name.0)),
+            ),
+            ast.module_export_name_identifier_name(Span::new(0, 0), name.0),
+            ImportOrExportKind::Value,
+        )
+    }));
+    ast.module_declaration_export_named_declaration(
+        Span::new(0, 0),
+        None,
+        specifiers,
+        None,
+        ImportOrExportKind::Value,
+        None::<oxc::allocator::Box<oxc::ast::ast::WithClause>>,
+    )
+    .into()
+}
@@ -89,6 +89,14 @@ impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::InfixExpr {
             oxidescript::parser::ast::InfixOperator::LogicalAnd => Expression::LogicalExpression(
                 logical_expr(ctx, *self.lhs, *self.rhs, LogicalOperator::And),
             ),
+            oxidescript::parser::ast::InfixOperator::NullishCoalesce => {
+                Expression::LogicalExpression(logical_expr(
+                    ctx,
+                    *self.lhs,
+                    *self.rhs,
+                    LogicalOperator::Coalesce,
+                ))
+            }
             oxidescript::parser::ast::InfixOperator::BitwiseOr => Expression::BinaryExpression(
                 binary_expr(ctx, *self.lhs, *self.rhs, BinaryOperator::BitwiseOR),
             ),
@@ -0,0 +1,661 @@
+use oxc::{
+    ast::{
+        ast::{
+            Argument, BinaryOperator, BindingRestElement, Expression, FormalParameterKind,
+            FormalParameters, FunctionType, LogicalOperator, PropertyKind, TSThisParameter,
+            TSTypeAnnotation, TSTypeParameterDeclaration, TSTypeParameterInstantiation,
+        },
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{
+    CallExpr, Identifier, InfixExpr, InfixOperator, Literal, MemberAccessExpr, Parameter, PathExpr,
+};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+use super::{guards::is_type, structs::object_property};
+
+/// Calls that are recognized by the compiler and lowered to builtin javascript instead of a
+/// regular function call
+pub enum Intrinsic {
+    /// `clone(value)` or `value.clone()`, deep-copies arrays, maps, sets and objects, see
+    /// [`deep_clone`]
+    Clone(oxidescript::parser::ast::Expression),
+    /// `println(...)` and `eprintln(...)` from the prelude, print to stdout or stderr
+    Print {
+        stderr: bool,
+        arguments: Vec<oxidescript::parser::ast::Expression>,
+    },
+    /// `Vec::new()` from the prelude, an empty array
+    VecNew,
+    /// `Vec::from(iterable)` from the prelude, copies any iterable into an array
+    VecFrom(oxidescript::parser::ast::Expression),
+    /// `worker::spawn("./path")` from the prelude, starts the module at the path relative to the
+    /// current file in a module web worker
+    WorkerSpawn(String),
+    /// `array::new_2d(width, height, init)` from the prelude, `height` rows of `width` items that
+    /// all start as `init`
+    ArrayNew2d {
+        width: oxidescript::parser::ast::Expression,
+        height: oxidescript::parser::ast::Expression,
+        init: oxidescript::parser::ast::Expression,
+    },
+    /// `promise::new(|resolve| ...)` from the prelude, a promise that the executor resolves
+    PromiseNew(oxidescript::parser::ast::Expression),
+    /// `task::spawn(f)` from the prelude, a promise of what `f` returns, called once the current
+    /// task waits for something
+    TaskSpawn(oxidescript::parser::ast::Expression),
+    /// `task::join_all(tasks)` or `task::race(tasks)` from the prelude, a promise of every result
+    /// or of the first one
+    TaskJoin {
+        race: bool,
+        tasks: oxidescript::parser::ast::Expression,
+    },
+    /// `checked_add(a, b)` and the other checked operations from the prelude, the result or
+    /// `None` if it is beyond `Number.MAX_SAFE_INTEGER`
+    Checked(InfixExpr),
+    /// `panic(message)` from the prelude, throws an `Error` with the message
+    Panic(oxidescript::parser::ast::Expression),
+}
+
+impl Intrinsic {
+    /// Returns the call unchanged if it isn't an intrinsic. Prelude items are only recognized if
+    /// the prelude is enabled, otherwise they are regular calls to user defined functions
+    pub fn from_call(call: CallExpr, prelude: bool) -> Result<Self, CallExpr> {
+        match (*call.lhs, call.arguments) {
+            (
+                oxidescript::parser::ast::Expression::IdentifierExpression(Identifier(name), _),
+                mut arguments,
+            ) if name == "clone" && arguments.len() == 1 => {
+                Ok(Intrinsic::Clone(arguments.remove(0)))
+            }
+            (
+                oxidescript::parser::ast::Expression::MemberAccessExpression(
+                    MemberAccessExpr {
+                        lhs,
+                        ident: Identifier(name),
+                    },
+                    _,
+                ),
+                arguments,
+            ) if name == "clone" && arguments.is_empty() => Ok(Intrinsic::Clone(*lhs)),
+            (
+                oxidescript::parser::ast::Expression::IdentifierExpression(Identifier(name), _),
+                arguments,
+            ) if prelude && (name == "println" || name == "eprintln") => Ok(Intrinsic::Print {
+                stderr: name == "eprintln",
+                arguments,
+            }),
+            (
+                oxidescript::parser::ast::Expression::IdentifierExpression(Identifier(name), _),
+                mut arguments,
+            ) if prelude && name == "panic" && arguments.len() == 1 => {
+                Ok(Intrinsic::Panic(arguments.remove(0)))
+            }
+            (
+                oxidescript::parser::ast::Expression::IdentifierExpression(Identifier(name), _),
+                arguments,
+            ) if prelude && InfixOperator::checked(&name).is_some() && arguments.len() == 2 => {
+                let [lhs, rhs] = <[_; 2]>::try_from(arguments).unwrap();
+                Ok(Intrinsic::Checked(InfixExpr {
+                    op: InfixOperator::checked(&name).unwrap(),
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                }))
+            }
+            (
+                oxidescript::parser::ast::Expression::PathExpression(PathExpr { segments }, _),
+                mut arguments,
+            ) if prelude && segments.len() == 2 && segments[0].0 == "Vec" => {
+                match (segments[1].0.as_str(), arguments.len()) {
+                    ("new", 0) => Ok(Intrinsic::VecNew),
+                    ("from", 1) => Ok(Intrinsic::VecFrom(arguments.remove(0))),
+                    _ => Err(CallExpr {
+                        lhs: Box::new(oxidescript::parser::ast::Expression::PathExpression(
+                            PathExpr { segments },
+                            oxidescript::parser::ast::Span::default(),
+                        )),
+                        arguments,
+                    }),
+                }
+            }
+            (
+                oxidescript::parser::ast::Expression::PathExpression(PathExpr { segments }, _),
+                mut arguments,
+            ) if prelude
+                && segments.len() == 2
+                && segments[0].0 == "worker"
+                && segments[1].0 == "spawn"
+                && arguments.len() == 1 =>
+            {
+                match arguments.remove(0) {
+                    oxidescript::parser::ast::Expression::LiteralExpression(
+                        Literal::StringLiteral(path),
+                        _,
+                    ) => Ok(Intrinsic::WorkerSpawn(path)),
+                    argument => Err(CallExpr {
+                        lhs: Box::new(oxidescript::parser::ast::Expression::PathExpression(
+                            PathExpr { segments },
+                            oxidescript::parser::ast::Span::default(),
+                        )),
+                        arguments: vec![argument],
+                    }),
+                }
+            }
+            (
+                oxidescript::parser::ast::Expression::PathExpression(PathExpr { segments }, _),
+                arguments,
+            ) if prelude
+                && segments.len() == 2
+                && segments[0].0 == "array"
+                && segments[1].0 == "new_2d"
+                && arguments.len() == 3 =>
+            {
+                let [width, height, init] = <[_; 3]>::try_from(arguments).unwrap();
+                Ok(Intrinsic::ArrayNew2d {
+                    width,
+                    height,
+                    init,
+                })
+            }
+            (
+                oxidescript::parser::ast::Expression::PathExpression(PathExpr { segments }, _),
+                mut arguments,
+            ) if prelude
+                && segments.len() == 2
+                && segments[0].0 == "promise"
+                && segments[1].0 == "new"
+                && arguments.len() == 1 =>
+            {
+                Ok(Intrinsic::PromiseNew(arguments.remove(0)))
+            }
+            (
+                oxidescript::parser::ast::Expression::PathExpression(PathExpr { segments }, _),
+                mut arguments,
+            ) if prelude
+                && segments.len() == 2
+                && segments[0].0 == "task"
+                && ["spawn", "join_all", "race"].contains(&segments[1].0.as_str())
+                && arguments.len() == 1 =>
+            {
+                Ok(match segments[1].0.as_str() {
+                    "spawn" => Intrinsic::TaskSpawn(arguments.remove(0)),
+                    name => Intrinsic::TaskJoin {
+                        race: name == "race",
+                        tasks: arguments.remove(0),
+                    },
+                })
+            }
+            (lhs, arguments) => Err(CallExpr {
+                lhs: Box::new(lhs),
+                arguments,
+            }),
+        }
+    }
+}
+
+impl<'c> IntoOxc<'c, Expression<'c>> for Intrinsic {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        match self {
+            Intrinsic::Clone(value) => AstBuilder::new(ctx.allocator).expression_call(
+                Span::new(0, 0),
+                deep_clone(ctx),
+                None::<TSTypeParameterInstantiation>,
+                vec![value].into_oxc(ctx),
+                false,
+            ),
+            Intrinsic::Print { stderr, arguments } => AstBuilder::new(ctx.allocator)
+                .expression_call(
+                    Span::new(0, 0),
+                    builtin_member("console", if stderr { "error" } else { "log" }, ctx),
+                    None::<TSTypeParameterInstantiation>,
+                    arguments.into_oxc(ctx),
+                    false,
+                ),
+            Intrinsic::VecNew => AstBuilder::new(ctx.allocator).expression_array(
+                Span::new(0, 0),
+                oxc::allocator::Vec::new_in(ctx.allocator),
+                None,
+            ),
+            Intrinsic::VecFrom(iterable) => AstBuilder::new(ctx.allocator).expression_call(
+                Span::new(0, 0),
+                builtin_member("Array", "from", ctx),
+                None::<TSTypeParameterInstantiation>,
+                vec![iterable].into_oxc(ctx),
+                false,
+            ),
+            // `new Worker(new URL("./path.js", import.meta.url), { type: "module" })`, like
+            // dynamic imports every module compiles to a `.js` file of the same name
+            Intrinsic::WorkerSpawn(path) => {
+                let ast = AstBuilder::new(ctx.allocator);
+                let url = ast.expression_new(
+                    Span::new(0, 0),
+                    Identifier("URL".to_string()).into_oxc(ctx),
+                    ast.vec_from_iter([
+                        ast.expression_string_literal(
+                            Span::new(0, 0),
+                            format!("{}.js", path).as_str(),
+                            None,
+                        )
+                        .into(),
+                        Expression::from(ast.member_expression_static(
+                            Span::new(0, 0),
+                            ast.expression_meta_property(
+                                Span::new(0, 0),
+                                ast.identifier_name(Span::new(0, 0), "import"),
+                                ast.identifier_name(Span::new(0, 0), "meta"),
+                            ),
+                            ast.identifier_name(Span::new(0, 0), "url"),
+                            false,
+                        ))
+                        .into(),
+                    ]),
+                    None::<TSTypeParameterInstantiation>,
+                );
+                let options = ast.expression_object(
+                    Span::new(0, 0),
+                    ast.vec1(object_property(
+                        Identifier("type".to_string()),
+                        ast.expression_string_literal(Span::new(0, 0), "module", None),
+                        PropertyKind::Init,
+                        false,
+                        ctx,
+                    )),
+                    None,
+                );
+                ast.expression_new(
+                    Span::new(0, 0),
+                    Identifier("Worker".to_string()).into_oxc(ctx),
+                    ast.vec_from_iter([url.into(), options.into()]),
+                    None::<TSTypeParameterInstantiation>,
+                )
+            }
+            Intrinsic::PromiseNew(executor) => AstBuilder::new(ctx.allocator).expression_new(
+                Span::new(0, 0),
+                Identifier("Promise".to_string()).into_oxc(ctx),
+                vec![executor].into_oxc(ctx),
+                None::<TSTypeParameterInstantiation>,
+            ),
+            // `Promise.resolve().then(f)`, which works for functions that aren't `async` as well
+            Intrinsic::TaskSpawn(f) => {
+                let ast = AstBuilder::new(ctx.allocator);
+                let resolved = ast.expression_call(
+                    Span::new(0, 0),
+                    builtin_member("Promise", "resolve", ctx),
+                    None::<TSTypeParameterInstantiation>,
+                    ast.vec(),
+                    false,
+                );
+                ast.expression_call(
+                    Span::new(0, 0),
+                    Expression::from(ast.member_expression_static(
+                        Span::new(0, 0),
+                        resolved,
+                        ast.identifier_name(Span::new(0, 0), "then"),
+                        false,
+                    )),
+                    None::<TSTypeParameterInstantiation>,
+                    vec![f].into_oxc(ctx),
+                    false,
+                )
+            }
+            Intrinsic::TaskJoin { race, tasks } => AstBuilder::new(ctx.allocator).expression_call(
+                Span::new(0, 0),
+                builtin_member("Promise", if race { "race" } else { "all" }, ctx),
+                None::<TSTypeParameterInstantiation>,
+                vec![tasks].into_oxc(ctx),
+                false,
+            ),
+            // `((result) => Math.abs(result) <= Number.MAX_SAFE_INTEGER ? result : null)(a + b)`
+            Intrinsic::Checked(expr) => {
+                let ast = AstBuilder::new(ctx.allocator);
+                let result = || Identifier("result".to_string()).into_oxc(ctx);
+                let in_range = ast.expression_binary(
+                    Span::new(0, 0),
+                    ast.expression_call(
+                        Span::new(0, 0),
+                        builtin_member("Math", "abs", ctx),
+                        None::<TSTypeParameterInstantiation>,
+                        ast.vec1(Argument::from(result())),
+                        false,
+                    ),
+                    BinaryOperator::LessEqualThan,
+                    builtin_member("Number", "MAX_SAFE_INTEGER", ctx),
+                );
+                let parameters = vec![Parameter {
+                    name: Identifier("result".to_string()),
+                    type_: Identifier("number".to_string()),
+                    span: oxidescript::parser::ast::Span::default(),
+                }];
+                ast.expression_call(
+                    Span::new(0, 0),
+                    arrow(
+                        parameters.into_oxc(ctx),
+                        ast.expression_conditional(
+                            Span::new(0, 0),
+                            in_range,
+                            result(),
+                            ast.expression_null_literal(Span::new(0, 0)),
+                        ),
+                        ctx,
+                    ),
+                    None::<TSTypeParameterInstantiation>,
+                    ast.vec1(Argument::from(expr.into_oxc(ctx))),
+                    false,
+                )
+            }
+            // `(() => { throw new Error(message); })()`, as `throw` is a statement
+            Intrinsic::Panic(message) => {
+                let ast = AstBuilder::new(ctx.allocator);
+                let error = ast.expression_new(
+                    Span::new(0, 0),
+                    Identifier("Error".to_string()).into_oxc(ctx),
+                    vec![message].into_oxc(ctx),
+                    None::<TSTypeParameterInstantiation>,
+                );
+                let thrower = ast.expression_arrow_function(
+                    Span::new(0, 0),
+                    false,
+                    false,
+                    None::<TSTypeParameterDeclaration>,
+                    ast.formal_parameters(
+                        Span::new(0, 0),
+                        FormalParameterKind::ArrowFormalParameters,
+                        ast.vec(),
+                        None::<BindingRestElement>,
+                    ),
+                    None::<TSTypeAnnotation>,
+                    ast.function_body(
+                        Span::new(0, 0),
+                        ast.vec(),
+                        ast.vec1(ast.statement_throw(Span::new(0, 0), error)),
+                    ),
+                );
+                ast.expression_call(
+                    Span::new(0, 0),
+                    thrower,
+                    None::<TSTypeParameterInstantiation>,
+                    ast.vec(),
+                    false,
+                )
+            }
+            // `Array.from({ length: height }, () => new Array(width).fill(init))`. The row is
+            // built once per row, so unless `width` and `init` are variables or literals the
+            // arguments are passed to an arrow function first to evaluate them once
+            Intrinsic::ArrayNew2d {
+                width,
+                height,
+                init,
+            } => {
+                if is_simple(&width) && is_simple(&init) {
+                    return grid(
+                        width.into_oxc(ctx),
+                        height.into_oxc(ctx),
+                        init.into_oxc(ctx),
+                        ctx,
+                    );
+                }
+                let ast = AstBuilder::new(ctx.allocator);
+                let parameters = [("width", "number"), ("height", "number"), ("init", "any")]
+                    .into_iter()
+                    .map(|(name, type_)| Parameter {
+                        name: Identifier(name.to_string()),
+                        type_: Identifier(type_.to_string()),
+                        span: oxidescript::parser::ast::Span::default(),
+                    })
+                    .collect::<Vec<_>>();
+                let ident = |name: &str| Identifier(name.to_string()).into_oxc(ctx);
+                ast.expression_call(
+                    Span::new(0, 0),
+                    arrow(
+                        parameters.into_oxc(ctx),
+                        grid(ident("width"), ident("height"), ident("init"), ctx),
+                        ctx,
+                    ),
+                    None::<TSTypeParameterInstantiation>,
+                    vec![width, height, init].into_oxc(ctx),
+                    false,
+                )
+            }
+        }
+    }
+}
+
+/// ```javascript
+/// function clone(value) {
+///     return typeof value !== "object" || value === null ? value
+///         : Array.isArray(value) ? value.map(clone)
+///         : value instanceof Map || value instanceof Set ? new value.constructor(Array.from(value, clone))
+///         : Object.assign(Object.create(Object.getPrototypeOf(value)), Object.fromEntries(Object.entries(value).map(clone)));
+/// }
+/// ```
+///
+/// Unlike `structuredClone`, copies of class instances keep their prototype, and functions like
+/// closures and the methods attached to struct values are shared instead of failing to be copied.
+/// The entries of maps and objects are arrays of a key and a value, which `clone` copies as well
+fn deep_clone<'c>(ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let ident = |name: &str| Identifier(name.to_string()).into_oxc(ctx);
+    let method = |object: Expression<'c>, name: &str, arguments: Vec<Expression<'c>>| {
+        ast.expression_call(
+            Span::new(0, 0),
+            Expression::from(ast.member_expression_static(
+                Span::new(0, 0),
+                object,
+                ast.identifier_name(Span::new(0, 0), ast.atom(name)),
+                false,
+            )),
+            None::<TSTypeParameterInstantiation>,
+            ast.vec_from_iter(arguments.into_iter().map(Argument::from)),
+            false,
+        )
+    };
+    let is_instance = |class: &str| {
+        ast.expression_binary(
+            Span::new(0, 0),
+            ident("value"),
+            BinaryOperator::Instanceof,
+            ident(class),
+        )
+    };
+
+    let primitive = ast.expression_logical(
+        Span::new(0, 0),
+        is_type(ident("value"), "object", true, ctx),
+        LogicalOperator::Or,
+        ast.expression_binary(
+            Span::new(0, 0),
+            ident("value"),
+            BinaryOperator::StrictEquality,
+            ast.expression_null_literal(Span::new(0, 0)),
+        ),
+    );
+    let collection = ast.expression_logical(
+        Span::new(0, 0),
+        is_instance("Map"),
+        LogicalOperator::Or,
+        is_instance("Set"),
+    );
+    let copied_collection = ast.expression_new(
+        Span::new(0, 0),
+        Expression::from(ast.member_expression_static(
+            Span::new(0, 0),
+            ident("value"),
+            ast.identifier_name(Span::new(0, 0), "constructor"),
+            false,
+        )),
+        ast.vec1(Argument::from(method(
+            ident("Array"),
+            "from",
+            vec![ident("value"), ident("clone")],
+        ))),
+        None::<TSTypeParameterInstantiation>,
+    );
+    let copied_object = method(
+        ident("Object"),
+        "assign",
+        vec![
+            method(
+                ident("Object"),
+                "create",
+                vec![method(
+                    ident("Object"),
+                    "getPrototypeOf",
+                    vec![ident("value")],
+                )],
+            ),
+            method(
+                ident("Object"),
+                "fromEntries",
+                vec![method(
+                    method(ident("Object"), "entries", vec![ident("value")]),
+                    "map",
+                    vec![ident("clone")],
+                )],
+            ),
+        ],
+    );
+    let copy = ast.expression_conditional(
+        Span::new(0, 0),
+        primitive,
+        ident("value"),
+        ast.expression_conditional(
+            Span::new(0, 0),
+            method(ident("Array"), "isArray", vec![ident("value")]),
+            method(ident("value"), "map", vec![ident("clone")]),
+            ast.expression_conditional(
+                Span::new(0, 0),
+                collection,
+                copied_collection,
+                copied_object,
+            ),
+        ),
+    );
+    let parameters = vec![Parameter {
+        name: Identifier("value".to_string()),
+        type_: Identifier("any".to_string()),
+        span: oxidescript::parser::ast::Span::default(),
+    }];
+    ast.expression_function(
+        Span::new(0, 0),
+        FunctionType::FunctionExpression,
+        Some(ast.binding_identifier(Span::new(0, 0), "clone")),
+        false,
+        false,
+        false,
+        None::<TSTypeParameterDeclaration>,
+        None::<TSThisParameter>,
+        parameters.into_oxc(ctx),
+        None::<TSTypeAnnotation>,
+        Some(ast.function_body(
+            Span::new(0, 0),
+            ast.vec(),
+            ast.vec1(ast.statement_return(Span::new(0, 0), Some(copy))),
+        )),
+    )
+}
+
+fn is_simple(expression: &oxidescript::parser::ast::Expression) -> bool {
+    matches!(
+        expression,
+        oxidescript::parser::ast::Expression::IdentifierExpression(_, _)
+            | oxidescript::parser::ast::Expression::LiteralExpression(_, _)
+            | oxidescript::parser::ast::Expression::PathExpression(_, _)
+    )
+}
+
+fn grid<'c>(
+    width: Expression<'c>,
+    height: Expression<'c>,
+    init: Expression<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let row = ast.expression_call(
+        Span::new(0, 0),
+        Expression::from(ast.member_expression_static(
+            Span::new(0, 0),
+            ast.expression_new(
+                Span::new(0, 0),
+                Identifier("Array".to_string()).into_oxc(ctx),
+                ast.vec1(Argument::from(width)),
+                None::<TSTypeParameterInstantiation>,
+            ),
+            Identifier("fill".to_string()).into_oxc(ctx),
+            false,
+        )),
+        None::<TSTypeParameterInstantiation>,
+        ast.vec1(Argument::from(init)),
+        false,
+    );
+    let length = ast.expression_object(
+        Span::new(0, 0),
+        ast.vec1(object_property(
+            Identifier("length".to_string()),
+            height,
+            PropertyKind::Init,
+            false,
+            ctx,
+        )),
+        None,
+    );
+    ast.expression_call(
+        Span::new(0, 0),
+        builtin_member("Array", "from", ctx),
+        None::<TSTypeParameterInstantiation>,
+        ast.vec_from_iter([
+            Argument::from(length),
+            Argument::from(arrow(
+                ast.formal_parameters(
+                    Span::new(0, 0),
+                    FormalParameterKind::ArrowFormalParameters,
+                    ast.vec(),
+                    None::<BindingRestElement>,
+                ),
+                row,
+                ctx,
+            )),
+        ]),
+        false,
+    )
+}
+
+/// `(parameters) => body`
+pub fn arrow<'c>(
+    parameters: FormalParameters<'c>,
+    body: Expression<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    ast.expression_arrow_function(
+        Span::new(0, 0),
+        true,
+        false,
+        None::<TSTypeParameterDeclaration>,
+        parameters,
+        None::<TSTypeAnnotation>,
+        ast.function_body(
+            Span::new(0, 0),
+            ast.vec(),
+            ast.vec1(ast.statement_expression(Span::new(0, 0), body)),
+        ),
+    )
+}
+
+/// `object.property` for javascript builtins like `console.log`
+pub fn builtin_member<'c>(
+    object: &str,
+    property: &str,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    AstBuilder::new(ctx.allocator)
+        .member_expression_static(
+            Span::new(0, 0),
+            Identifier(object.to_string()).into_oxc(ctx),
+            Identifier(property.to_string()).into_oxc(ctx),
+            false,
+        )
+        .into()
+}
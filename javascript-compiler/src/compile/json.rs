@@ -0,0 +1,495 @@
+use oxc::{
+    ast::{
+        ast::{
+            Argument, BinaryOperator, Function, FunctionType, LogicalOperator, Statement,
+            TSTypeParameterInstantiation, UnaryOperator, UpdateOperator, VariableDeclarationKind,
+        },
+        AstBuilder,
+    },
+    syntax::number::NumberBase,
+};
+use oxidescript::parser::{
+    ast::{
+        Block, CallExpr, Declaration, Expression, Field, Identifier, MemberAccessExpr, Method,
+        Parameter, Span, StructDecl, StructExpr,
+    },
+    types::article,
+};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+use super::{
+    guards::{invalid_value, is_type, string, struct_fields, throw_if},
+    structs::{function_expression, method_function, Derived},
+};
+
+/// The functions `#[derive(Serialize, Deserialize)]` adds to a struct: `to_json(self)` and
+/// `from_json(json)`
+pub fn derived_functions<'c>(
+    decl: &StructDecl,
+    self_is_this: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Vec<Derived<'c>> {
+    decl.derives
+        .iter()
+        .filter_map(|derive| match derive.0.as_str() {
+            "Serialize" => Some((
+                Identifier("to_json".to_string()),
+                true,
+                to_json(self_is_this, ctx),
+            )),
+            "Deserialize" => Some((
+                Identifier("from_json".to_string()),
+                false,
+                from_json(decl, ctx),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `to_json(self) { return JSON.stringify(self); }`. Methods are skipped, and so are getters
+/// of classes, but the getters of struct objects are serialized like fields
+fn to_json<'c>(
+    self_is_this: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Box<'c, Function<'c>> {
+    let method = Method {
+        name: Identifier("to_json".to_string()),
+        has_self: true,
+        is_async: false,
+        parameters: vec![],
+        return_type: Some(Identifier("string".to_string())),
+        body: Block {
+            statements: vec![],
+            return_value: Some(json_call(
+                "stringify",
+                Expression::IdentifierExpression(Identifier("self".to_string()), Span::default()),
+            )),
+            span: Span::default(),
+        },
+        span: Span::default(),
+    };
+    method_function(method, self_is_this, ctx)
+}
+
+/// `from_json(json) { const value = JSON.parse(json); if (...) throw ...; return Name { ... }; }`,
+/// which throws a `TypeError` naming the first part of the JSON that doesn't hold the type of its
+/// field, see [`JsonChecks`]. The value is built like any other struct value, so it gets the
+/// getters and setters of the struct or is an instance of its class
+fn from_json<'c>(
+    decl: &StructDecl,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Box<'c, Function<'c>> {
+    let value = Identifier("value".to_string());
+    let body = Block {
+        statements: vec![oxidescript::parser::ast::Statement::DeclarationStatement(
+            Declaration::ConstDeclaration(
+                value.clone(),
+                json_call(
+                    "parse",
+                    Expression::IdentifierExpression(
+                        Identifier("json".to_string()),
+                        Span::default(),
+                    ),
+                ),
+                false,
+                vec![],
+                None,
+            ),
+            Span::default(),
+        )],
+        return_value: Some(Expression::StructExpression(
+            StructExpr {
+                name: decl.name.clone(),
+                fields: decl
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let field_value = Expression::MemberAccessExpression(
+                            MemberAccessExpr {
+                                lhs: Box::new(Expression::IdentifierExpression(
+                                    value.clone(),
+                                    Span::default(),
+                                )),
+                                ident: field.name.clone(),
+                            },
+                            Span::default(),
+                        );
+                        (field.name.clone(), field_value)
+                    })
+                    .collect(),
+            },
+            Span::default(),
+        )),
+        span: Span::default(),
+    };
+    let mut statements: oxc::allocator::Vec<Statement> = body.into_oxc(ctx);
+    let value_expression = || value.clone().into_oxc(ctx);
+    let mut checks = JsonChecks {
+        function: format!("{}::from_json", decl.name.0),
+        functions: vec![],
+        ctx,
+    };
+    let checked = checks.value(&value_expression, None, &decl.name.0, 0);
+    let return_value = statements.pop();
+    statements.extend(checked);
+    statements.extend(checks.functions());
+    statements.extend(return_value);
+    function_expression(
+        vec![Parameter {
+            name: Identifier("json".to_string()),
+            type_: Identifier("string".to_string()),
+            span: Span::default(),
+        }],
+        Some(decl.name.clone()),
+        AstBuilder::new(ctx.allocator).function_body(
+            oxc::span::Span::new(0, 0),
+            oxc::allocator::Vec::new_in(ctx.allocator),
+            statements,
+        ),
+        ctx,
+    )
+}
+
+/// A piece of the path of a value in the parsed JSON, like `inner.tags[0]`
+#[derive(Clone)]
+enum PathPart {
+    Text(String),
+    /// A variable holding a piece of the path: an array index, or the path a check function got
+    Variable(String),
+}
+
+/// The checks of the parsed JSON against the type of a struct, which throw a `TypeError` with the
+/// path of the first value that doesn't hold the type of its field: the fields of nested structs
+/// and the items of arrays are checked too. Nested structs are checked by a `__check_Name(value,
+/// path)` function, which also checks structs that contain themselves. Values of types that can't
+/// be checked only have to be present
+struct JsonChecks<'c> {
+    /// `Name::from_json`, which the messages start with
+    function: String,
+    /// The struct types that need a check function
+    functions: Vec<String>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+}
+
+impl<'c> JsonChecks<'c> {
+    /// The checks of `value` against `type_`, where `path` is `None` for the parsed JSON itself
+    fn value(
+        &mut self,
+        value: &dyn Fn() -> oxc::ast::ast::Expression<'c>,
+        path: Option<&[PathPart]>,
+        type_: &str,
+        depth: usize,
+    ) -> Vec<Statement<'c>> {
+        let ast = AstBuilder::new(self.ctx.allocator);
+        if let Some(fields) = struct_fields(type_, self.ctx) {
+            let Some(path) = path else {
+                let invalid = not_object(value, self.ctx);
+                let message = format!("expected JSON of {}", article(type_));
+                let mut statements = vec![self.throw(invalid, &[PathPart::Text(message)], value)];
+                statements.extend(self.fields(value, None, &fields, depth));
+                return statements;
+            };
+            if !self.functions.iter().any(|checked| checked == type_) {
+                self.functions.push(type_.to_string());
+            }
+            let call = ast.expression_call(
+                oxc::span::Span::new(0, 0),
+                ast.expression_identifier_reference(
+                    oxc::span::Span::new(0, 0),
+                    ast.atom(&check_function(type_)),
+                ),
+                None::<TSTypeParameterInstantiation>,
+                ast.vec_from_array([Argument::from(value()), Argument::from(self.path(path))]),
+                false,
+            );
+            return vec![ast.statement_expression(oxc::span::Span::new(0, 0), call)];
+        }
+        let path = path.unwrap_or_default();
+        let alias = self
+            .ctx
+            .types
+            .borrow()
+            .get(type_)
+            .and_then(|decl| decl.alias.clone());
+        if let Some(alias) = alias {
+            return self.value(value, Some(path), &alias.0, depth);
+        }
+        let Some((invalid, expected)) = invalid_value(value, type_, self.ctx) else {
+            return vec![];
+        };
+        let mut statements = vec![self.throw(invalid, &self.expected(path, &expected), value)];
+        if let Some(item_type) = type_
+            .strip_prefix('[')
+            .and_then(|type_| type_.strip_suffix(']'))
+        {
+            // `for (let __index0 = 0; __index0 < value.length; __index0++) { ... }`
+            let index = format!("__index{}", depth);
+            let item = || {
+                oxc::ast::ast::Expression::from(ast.member_expression_computed(
+                    oxc::span::Span::new(0, 0),
+                    value(),
+                    ast.expression_identifier_reference(
+                        oxc::span::Span::new(0, 0),
+                        ast.atom(&index),
+                    ),
+                    false,
+                ))
+            };
+            let mut item_path = path.to_vec();
+            item_path.extend([
+                PathPart::Text("[".to_string()),
+                PathPart::Variable(index.clone()),
+                PathPart::Text("]".to_string()),
+            ]);
+            let item_checks = self.value(&item, Some(&item_path), item_type, depth + 1);
+            if !item_checks.is_empty() {
+                let length = ast.member_expression_static(
+                    oxc::span::Span::new(0, 0),
+                    value(),
+                    ast.identifier_name(oxc::span::Span::new(0, 0), "length"),
+                    false,
+                );
+                statements.push(ast.statement_for(
+                    oxc::span::Span::new(0, 0),
+                    Some(ast.for_statement_init_variable_declaration(
+                        oxc::span::Span::new(0, 0),
+                        VariableDeclarationKind::Let,
+                        ast.vec1(ast.variable_declarator(
+                            oxc::span::Span::new(0, 0),
+                            VariableDeclarationKind::Let,
+                            Identifier(index.clone()).into_oxc(self.ctx),
+                            Some(ast.expression_numeric_literal(
+                                oxc::span::Span::new(0, 0),
+                                0.0,
+                                None,
+                                NumberBase::Decimal,
+                            )),
+                            false,
+                        )),
+                        false,
+                    )),
+                    Some(ast.expression_binary(
+                        oxc::span::Span::new(0, 0),
+                        ast.expression_identifier_reference(
+                            oxc::span::Span::new(0, 0),
+                            ast.atom(&index),
+                        ),
+                        BinaryOperator::LessThan,
+                        length.into(),
+                    )),
+                    Some(ast.expression_update(
+                        oxc::span::Span::new(0, 0),
+                        UpdateOperator::Increment,
+                        false,
+                        ast.simple_assignment_target_identifier_reference(
+                            oxc::span::Span::new(0, 0),
+                            ast.atom(&index),
+                        ),
+                    )),
+                    ast.statement_block(oxc::span::Span::new(0, 0), ast.vec_from_iter(item_checks)),
+                ));
+            }
+        }
+        statements
+    }
+
+    /// The checks of the fields of a struct value, which are named by their path
+    fn fields(
+        &mut self,
+        value: &dyn Fn() -> oxc::ast::ast::Expression<'c>,
+        path: Option<&[PathPart]>,
+        fields: &[Field],
+        depth: usize,
+    ) -> Vec<Statement<'c>> {
+        let ast = AstBuilder::new(self.ctx.allocator);
+        let mut statements = vec![];
+        for field in fields {
+            let field_value = || {
+                oxc::ast::ast::Expression::from(ast.member_expression_static(
+                    oxc::span::Span::new(0, 0),
+                    value(),
+                    field.name.clone().into_oxc(self.ctx),
+                    false,
+                ))
+            };
+            let field_path = match path {
+                Some(path) => {
+                    let mut field_path = path.to_vec();
+                    field_path.push(PathPart::Text(format!(".{}", field.name.0)));
+                    field_path
+                }
+                None => vec![PathPart::Text(field.name.0.clone())],
+            };
+            let checks = self.value(&field_value, Some(&field_path), &field.type_.0, depth);
+            if checks.is_empty() {
+                // `!("field" in value)`
+                let missing = ast.expression_unary(
+                    oxc::span::Span::new(0, 0),
+                    UnaryOperator::LogicalNot,
+                    ast.expression_binary(
+                        oxc::span::Span::new(0, 0),
+                        string(&field.name.0, self.ctx),
+                        BinaryOperator::In,
+                        value(),
+                    ),
+                );
+                let mut expected = vec![PathPart::Text("expected ".to_string())];
+                expected.extend(field_path);
+                expected.push(PathPart::Text(" to be present".to_string()));
+                statements.push(self.throw(missing, &expected, &field_value));
+            }
+            statements.extend(checks);
+        }
+        statements
+    }
+
+    /// `function __check_Name(value, path) { ... }` for every struct type that is nested in the
+    /// JSON, including the ones nested in those
+    fn functions(&mut self) -> Vec<Statement<'c>> {
+        let ast = AstBuilder::new(self.ctx.allocator);
+        let value_expression =
+            || ast.expression_identifier_reference(oxc::span::Span::new(0, 0), "value");
+        let path = [PathPart::Variable("path".to_string())];
+        let mut functions = vec![];
+        let mut next = 0;
+        while let Some(type_) = self.functions.get(next).cloned() {
+            next += 1;
+            let fields = struct_fields(&type_, self.ctx).unwrap_or_default();
+            let mut statements = vec![self.throw(
+                not_object(&value_expression, self.ctx),
+                &self.expected(&path, &article(&type_)),
+                &value_expression,
+            )];
+            statements.extend(self.fields(&value_expression, Some(&path), &fields, 0));
+            let parameters = [("value", "any"), ("path", "string")]
+                .into_iter()
+                .map(|(name, type_)| Parameter {
+                    name: Identifier(name.to_string()),
+                    type_: Identifier(type_.to_string()),
+                    span: Span::default(),
+                })
+                .collect();
+            let mut function = function_expression(
+                parameters,
+                None,
+                ast.function_body(
+                    oxc::span::Span::new(0, 0),
+                    ast.vec(),
+                    ast.vec_from_iter(statements),
+                ),
+                self.ctx,
+            );
+            function.r#type = FunctionType::FunctionDeclaration;
+            function.id = Some(Identifier(check_function(&type_)).into_oxc(self.ctx));
+            functions.push(Statement::FunctionDeclaration(function));
+        }
+        functions
+    }
+
+    /// The parts of `expected {path} to be {expected}`
+    fn expected(&self, path: &[PathPart], expected: &str) -> Vec<PathPart> {
+        let mut parts = vec![PathPart::Text("expected ".to_string())];
+        parts.extend(path.iter().cloned());
+        parts.push(PathPart::Text(format!(" to be {}", expected)));
+        parts
+    }
+
+    /// `if (invalid) throw new TypeError("Name::from_json: " + ... + ", got " + typeof value);`
+    fn throw(
+        &self,
+        invalid: oxc::ast::ast::Expression<'c>,
+        message: &[PathPart],
+        value: &dyn Fn() -> oxc::ast::ast::Expression<'c>,
+    ) -> Statement<'c> {
+        let ast = AstBuilder::new(self.ctx.allocator);
+        let mut parts = vec![PathPart::Text(format!("{}: ", self.function))];
+        parts.extend(message.iter().cloned());
+        parts.push(PathPart::Text(", got ".to_string()));
+        let message = ast.expression_binary(
+            oxc::span::Span::new(0, 0),
+            self.path(&parts),
+            BinaryOperator::Addition,
+            ast.expression_unary(oxc::span::Span::new(0, 0), UnaryOperator::Typeof, value()),
+        );
+        throw_if(invalid, message, self.ctx)
+    }
+
+    /// The parts joined with `+`, with the neighbouring texts in one string
+    fn path(&self, parts: &[PathPart]) -> oxc::ast::ast::Expression<'c> {
+        let ast = AstBuilder::new(self.ctx.allocator);
+        let mut joined: Vec<PathPart> = vec![];
+        for part in parts {
+            match (joined.last_mut(), part) {
+                (Some(PathPart::Text(text)), PathPart::Text(next)) => text.push_str(next),
+                _ => joined.push(part.clone()),
+            }
+        }
+        joined
+            .into_iter()
+            .map(|part| match part {
+                PathPart::Text(text) => string(&text, self.ctx),
+                PathPart::Variable(name) => {
+                    ast.expression_identifier_reference(oxc::span::Span::new(0, 0), ast.atom(&name))
+                }
+            })
+            .reduce(|joined, part| {
+                ast.expression_binary(
+                    oxc::span::Span::new(0, 0),
+                    joined,
+                    BinaryOperator::Addition,
+                    part,
+                )
+            })
+            .unwrap_or_else(|| string("", self.ctx))
+    }
+}
+
+/// `typeof value !== "object" || value === null`
+fn not_object<'c>(
+    value: &dyn Fn() -> oxc::ast::ast::Expression<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::ast::ast::Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    ast.expression_logical(
+        oxc::span::Span::new(0, 0),
+        is_type(value(), "object", true, ctx),
+        LogicalOperator::Or,
+        ast.expression_binary(
+            oxc::span::Span::new(0, 0),
+            value(),
+            BinaryOperator::StrictEquality,
+            ast.expression_null_literal(oxc::span::Span::new(0, 0)),
+        ),
+    )
+}
+
+/// The name of the function that checks values of a struct type, which can be generic
+fn check_function(type_: &str) -> String {
+    let name: String = type_
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("__check_{}", name.trim_end_matches('_'))
+}
+
+/// `JSON.function(argument)`
+fn json_call(function: &str, argument: Expression) -> Expression {
+    Expression::CallExpression(
+        CallExpr {
+            lhs: Box::new(Expression::MemberAccessExpression(
+                MemberAccessExpr {
+                    lhs: Box::new(Expression::IdentifierExpression(
+                        Identifier("JSON".to_string()),
+                        Span::default(),
+                    )),
+                    ident: Identifier(function.to_string()),
+                },
+                Span::default(),
+            )),
+            arguments: vec![argument],
+        },
+        Span::default(),
+    )
+}
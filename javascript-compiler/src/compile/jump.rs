@@ -0,0 +1,225 @@
+use oxc::{
+    ast::{
+        ast::{BinaryOperator, ChainElement, Expression, PropertyKind, Statement},
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::Identifier;
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+use super::{iife, structs::object_property};
+
+/// Whether `return`, `break` and `continue` have to throw to leave the code being compiled, and
+/// whether one of them did. Blocks, `if`s and loops used as values compile to IIFEs, which a
+/// `return` would only leave the IIFE of, so they throw `{ __oxidescript_jump: "return", value }`
+/// instead. The function around them catches it and returns the value, and loops catch `break`
+/// and `continue` the same way
+#[derive(Clone, Copy, Default)]
+pub struct Jumps {
+    /// Set inside of an IIFE in the current function
+    return_throws: bool,
+    /// Set once a `return` threw, so that the function catches it
+    return_thrown: bool,
+    /// Set inside of an IIFE in the current loop body
+    loop_throws: bool,
+    /// Set once a `break` or `continue` threw, so that the loop catches it
+    loop_thrown: bool,
+}
+
+/// Compiles the body of an IIFE, which jumps can only leave by throwing
+pub fn in_iife<'c, T>(ctx: &'c JavascriptCompilerContext<'c>, body: impl FnOnce() -> T) -> T {
+    let outer = ctx.jumps.get();
+    ctx.jumps.set(Jumps {
+        return_throws: true,
+        loop_throws: true,
+        ..outer
+    });
+    let result = body();
+    ctx.jumps.set(Jumps {
+        return_throws: outer.return_throws,
+        loop_throws: outer.loop_throws,
+        ..ctx.jumps.get()
+    });
+    result
+}
+
+/// The statements of a function body, in a `try` that returns the value of a thrown `return`
+/// if one of its IIFEs has one
+pub fn function_body<'c>(
+    ctx: &'c JavascriptCompilerContext<'c>,
+    body: impl FnOnce() -> oxc::allocator::Vec<'c, Statement<'c>>,
+) -> oxc::allocator::Vec<'c, Statement<'c>> {
+    let outer = ctx.jumps.replace(Jumps::default());
+    let statements = body();
+    let thrown = ctx.jumps.get().return_thrown;
+    ctx.jumps.set(outer);
+    if !thrown {
+        return statements;
+    }
+    let ast = AstBuilder::new(ctx.allocator);
+    let value = ast
+        .member_expression_static(
+            Span::new(0, 0),
+            signal(ctx),
+            Identifier("value".to_string()).into_oxc(ctx),
+            false,
+        )
+        .into();
+    catching(
+        statements,
+        [("return", ast.statement_return(Span::new(0, 0), Some(value)))],
+        ctx,
+    )
+}
+
+/// The statements of a loop body, in a `try` that catches `break` and `continue` thrown by its
+/// IIFEs
+pub fn loop_body<'c>(
+    ctx: &'c JavascriptCompilerContext<'c>,
+    body: impl FnOnce() -> oxc::allocator::Vec<'c, Statement<'c>>,
+) -> oxc::allocator::Vec<'c, Statement<'c>> {
+    let outer = ctx.jumps.get();
+    ctx.jumps.set(Jumps {
+        loop_throws: false,
+        loop_thrown: false,
+        ..outer
+    });
+    let statements = body();
+    let inner = ctx.jumps.get();
+    ctx.jumps.set(Jumps {
+        loop_throws: outer.loop_throws,
+        loop_thrown: outer.loop_thrown,
+        ..inner
+    });
+    if !inner.loop_thrown {
+        return statements;
+    }
+    let ast = AstBuilder::new(ctx.allocator);
+    catching(
+        statements,
+        [
+            ("break", ast.statement_break(Span::new(0, 0), None)),
+            ("continue", ast.statement_continue(Span::new(0, 0), None)),
+        ],
+        ctx,
+    )
+}
+
+/// `return value;`, `break;` or `continue;`, or the `throw` that leaves the IIFEs around it
+pub fn statement<'c>(
+    jump: oxidescript::parser::ast::Expression,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let (kind, value) = match jump {
+        oxidescript::parser::ast::Expression::ReturnExpression(value, _) => {
+            ("return", value.map(|value| value.into_oxc(ctx)))
+        }
+        oxidescript::parser::ast::Expression::BreakExpression(_) => ("break", None),
+        oxidescript::parser::ast::Expression::ContinueExpression(_) => ("continue", None),
+        _ => unreachable!("only jumps are compiled to jump statements"),
+    };
+    let mut jumps = ctx.jumps.get();
+    match kind {
+        "return" if !jumps.return_throws => return ast.statement_return(Span::new(0, 0), value),
+        "break" if !jumps.loop_throws => return ast.statement_break(Span::new(0, 0), None),
+        "continue" if !jumps.loop_throws => return ast.statement_continue(Span::new(0, 0), None),
+        "return" => jumps.return_thrown = true,
+        _ => jumps.loop_thrown = true,
+    }
+    ctx.jumps.set(jumps);
+    let mut properties = ast.vec1(object_property(
+        Identifier("__oxidescript_jump".to_string()),
+        ast.expression_string_literal(Span::new(0, 0), kind, None),
+        PropertyKind::Init,
+        false,
+        ctx,
+    ));
+    if let Some(value) = value {
+        properties.push(object_property(
+            Identifier("value".to_string()),
+            value,
+            PropertyKind::Init,
+            false,
+            ctx,
+        ));
+    }
+    ast.statement_throw(
+        Span::new(0, 0),
+        ast.expression_object(Span::new(0, 0), properties, None),
+    )
+}
+
+/// Jumps used as values, like `value ?? return`, can't be javascript statements, so they always
+/// throw out of an IIFE
+pub fn expression<'c>(
+    jump: oxidescript::parser::ast::Expression,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    let statement = in_iife(ctx, || statement(jump, ctx));
+    iife(AstBuilder::new(ctx.allocator).vec1(statement), ctx)
+}
+
+/// `return`, `break` or `continue`
+pub fn is_jump(expression: &oxidescript::parser::ast::Expression) -> bool {
+    matches!(
+        expression,
+        oxidescript::parser::ast::Expression::ReturnExpression(_, _)
+            | oxidescript::parser::ast::Expression::BreakExpression(_)
+            | oxidescript::parser::ast::Expression::ContinueExpression(_)
+    )
+}
+
+/// `try { statements } catch (__jump) { if (__jump?.__oxidescript_jump === "kind") handler; ...
+/// throw __jump; }`, other errors are thrown again
+fn catching<'c, const N: usize>(
+    statements: oxc::allocator::Vec<'c, Statement<'c>>,
+    handlers: [(&str, Statement<'c>); N],
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Vec<'c, Statement<'c>> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let mut caught = ast.vec_from_iter(handlers.into_iter().map(|(kind, handler)| {
+        let jump_kind = ast.expression_chain(
+            Span::new(0, 0),
+            ChainElement::from(ast.member_expression_static(
+                Span::new(0, 0),
+                signal(ctx),
+                Identifier("__oxidescript_jump".to_string()).into_oxc(ctx),
+                true,
+            )),
+        );
+        ast.statement_if(
+            Span::new(0, 0),
+            ast.expression_binary(
+                Span::new(0, 0),
+                jump_kind,
+                BinaryOperator::StrictEquality,
+                ast.expression_string_literal(Span::new(0, 0), kind, None),
+            ),
+            handler,
+            None,
+        )
+    }));
+    caught.push(ast.statement_throw(Span::new(0, 0), signal(ctx)));
+    let parameter = ast.catch_parameter(
+        Span::new(0, 0),
+        Identifier("__jump".to_string()).into_oxc(ctx),
+    );
+    ast.vec1(ast.statement_try(
+        Span::new(0, 0),
+        ast.block_statement(Span::new(0, 0), statements),
+        Some(ast.alloc_catch_clause(
+            Span::new(0, 0),
+            Some(parameter),
+            ast.block_statement(Span::new(0, 0), caught),
+        )),
+        None::<oxc::allocator::Box<oxc::ast::ast::BlockStatement>>,
+    ))
+}
+
+/// The caught value in [`catching`]
+fn signal<'c>(ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+    Identifier("__jump".to_string()).into_oxc(ctx)
+}
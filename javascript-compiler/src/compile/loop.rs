@@ -1,43 +1,178 @@
 use oxc::{
     ast::{
-        ast::{Expression, Statement, TSTypeParameterInstantiation, VariableDeclarationKind},
+        ast::{
+            Expression, Statement, TSTypeParameterInstantiation, UpdateOperator,
+            VariableDeclarationKind,
+        },
         AstBuilder,
     },
     span::Span,
 };
-use oxidescript::parser::ast::ForExpr;
+use oxidescript::parser::ast::{Block, ForExpr, Identifier, InfixOperator, WhileExpr};
 use rand::{distributions::Alphanumeric, Rng};
 
-use crate::{compile::iife, IntoOxc};
+use crate::{
+    compile::{
+        block::discarded,
+        ident::binding_name,
+        iife,
+        jump::{self, in_iife, is_jump, loop_body},
+        range::{identifier, infix},
+    },
+    IntoOxc,
+};
 
 impl<'c> IntoOxc<'c, Expression<'c>> for ForExpr {
     fn into_oxc(self, ctx: &'c crate::JavascriptCompilerContext<'c>) -> Expression<'c> {
-        let output_id = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(8)
-            .map(char::from)
-            .collect::<String>();
-        let output = AstBuilder::new(ctx.allocator).variable_declaration(
+        let lhs = self.lhs;
+        let rhs = self.rhs;
+        collect_iterations(*self.body, |body| for_loop(lhs, *rhs, body, ctx), ctx)
+    }
+}
+
+/// `for (const item of rhs)`, or `for (let i = start; i < end; i++)` over a range, which doesn't
+/// build an array of the numbers first. An end that isn't a name or a literal is evaluated once,
+/// in a second variable of the loop
+fn for_loop<'c>(
+    lhs: Identifier,
+    rhs: oxidescript::parser::ast::Expression,
+    body: Statement<'c>,
+    ctx: &'c crate::JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let oxidescript::parser::ast::Expression::RangeExpression(range, _) = rhs else {
+        return ast.statement_for_of(
             Span::new(0, 0),
-            VariableDeclarationKind::Let,
-            oxc::allocator::Vec::from_iter_in(
-                [AstBuilder::new(ctx.allocator).variable_declarator(
-                    Span::new(0, 0),
-                    VariableDeclarationKind::Let,
-                    oxidescript::parser::ast::Identifier(output_id.clone()).into_oxc(ctx),
-                    Some(AstBuilder::new(ctx.allocator).expression_array(
-                        Span::new(0, 0),
-                        oxc::allocator::Vec::new_in(ctx.allocator),
-                        None,
-                    )),
-                    false,
-                )],
-                ctx.allocator,
-            ),
             false,
+            lhs.into_oxc(ctx),
+            rhs.into_oxc(ctx),
+            body,
         );
-        let inner_statements = oxc::allocator::Vec::from_iter_in(
-            [AstBuilder::new(ctx.allocator).statement_expression(
+    };
+    let declarator = |name: Identifier, init: oxidescript::parser::ast::Expression| {
+        ast.variable_declarator(
+            Span::new(0, 0),
+            VariableDeclarationKind::Let,
+            name.into_oxc(ctx),
+            Some(init.into_oxc(ctx)),
+            false,
+        )
+    };
+    let mut declarations = ast.vec1(declarator(lhs.clone(), *range.start));
+    let end = match *range.end {
+        end @ (oxidescript::parser::ast::Expression::IdentifierExpression(_, _)
+        | oxidescript::parser::ast::Expression::LiteralExpression(_, _)) => end,
+        end => {
+            let name = Identifier(format!("__end_{}", random_suffix()));
+            declarations.push(declarator(name.clone(), end));
+            identifier(&name.0)
+        }
+    };
+    let op = match range.inclusive {
+        true => InfixOperator::LessThanEqual,
+        false => InfixOperator::LessThan,
+    };
+    ast.statement_for(
+        Span::new(0, 0),
+        Some(ast.for_statement_init_variable_declaration(
+            Span::new(0, 0),
+            VariableDeclarationKind::Let,
+            declarations,
+            false,
+        )),
+        Some(infix(op, identifier(&lhs.0), end).into_oxc(ctx)),
+        Some(ast.expression_update(
+            Span::new(0, 0),
+            UpdateOperator::Increment,
+            false,
+            ast.simple_assignment_target_identifier_reference(
+                Span::new(0, 0),
+                ast.atom(&binding_name(&lhs.0)),
+            ),
+        )),
+        body,
+    )
+}
+
+/// Eight random letters and digits for the names of generated variables. The random part can
+/// start with a digit, so it can't be a name on its own
+fn random_suffix() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect()
+}
+
+impl<'c> IntoOxc<'c, Expression<'c>> for WhileExpr {
+    fn into_oxc(self, ctx: &'c crate::JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let condition = self.condition;
+        collect_iterations(
+            *self.body,
+            |body| {
+                AstBuilder::new(ctx.allocator).statement_while(
+                    Span::new(0, 0),
+                    condition.into_oxc(ctx),
+                    body,
+                )
+            },
+            ctx,
+        )
+    }
+}
+
+/// Loops used as values evaluate to an array of the values of their body:
+/// `(() => { let output = []; loop { ...statements; output.push(value); } return output; })()`.
+/// `r#loop` builds the loop around the block that pushes the values
+fn collect_iterations<'c>(
+    body: Block,
+    r#loop: impl FnOnce(Statement<'c>) -> Statement<'c>,
+    ctx: &'c crate::JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    let statements = in_iife(ctx, || iterations(body, r#loop, ctx));
+    iife(statements, ctx)
+}
+
+fn iterations<'c>(
+    mut body: Block,
+    r#loop: impl FnOnce(Statement<'c>) -> Statement<'c>,
+    ctx: &'c crate::JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Vec<'c, Statement<'c>> {
+    let output_id = format!("__output_{}", random_suffix());
+    let output = AstBuilder::new(ctx.allocator).variable_declaration(
+        Span::new(0, 0),
+        VariableDeclarationKind::Let,
+        oxc::allocator::Vec::from_iter_in(
+            [AstBuilder::new(ctx.allocator).variable_declarator(
+                Span::new(0, 0),
+                VariableDeclarationKind::Let,
+                oxidescript::parser::ast::Identifier(output_id.clone()).into_oxc(ctx),
+                Some(AstBuilder::new(ctx.allocator).expression_array(
+                    Span::new(0, 0),
+                    oxc::allocator::Vec::new_in(ctx.allocator),
+                    None,
+                )),
+                false,
+            )],
+            ctx.allocator,
+        ),
+        false,
+    );
+    let value = body.return_value.take();
+    // the statements of the body are part of the loop, so `break` and `continue` stay statements
+    let inner_statements = loop_body(ctx, || {
+        let mut statements: oxc::allocator::Vec<Statement> = body.into_oxc(ctx);
+        let value = match value {
+            Some(jump) if is_jump(&jump) => {
+                statements.push(jump::statement(jump, ctx));
+                return statements;
+            }
+            Some(value) => value.into_oxc(ctx),
+            None => AstBuilder::new(ctx.allocator)
+                .expression_identifier_reference(Span::new(0, 0), "undefined"),
+        };
+        statements.push(
+            AstBuilder::new(ctx.allocator).statement_expression(
                 Span::new(0, 0),
                 AstBuilder::new(ctx.allocator).expression_call(
                     Span::new(0, 0),
@@ -53,50 +188,49 @@ impl<'c> IntoOxc<'c, Expression<'c>> for ForExpr {
                         )
                         .into(),
                     None::<TSTypeParameterInstantiation>,
-                    oxc::allocator::Vec::from_iter_in(
-                        [iife(self.body.into_oxc(ctx), ctx).into()],
-                        ctx.allocator,
-                    ),
+                    AstBuilder::new(ctx.allocator).vec1(value.into()),
                     false,
                 ),
-            )],
-            ctx.allocator,
-        );
-        let r#loop = AstBuilder::new(ctx.allocator).statement_for_of(
-            Span::new(0, 0),
-            false,
-            self.lhs.into_oxc(ctx),
-            self.rhs.into_oxc(ctx),
-            AstBuilder::new(ctx.allocator).statement_block(Span::new(0, 0), inner_statements),
+            ),
         );
-        iife(
-            oxc::allocator::Vec::from_iter_in(
-                [
-                    oxc::ast::ast::Statement::VariableDeclaration(oxc::allocator::Box::new_in(
-                        output,
-                        ctx.allocator,
-                    )),
-                    r#loop,
-                    AstBuilder::new(ctx.allocator).statement_return(
-                        Span::new(0, 0),
-                        Some(oxidescript::parser::ast::Identifier(output_id).into_oxc(ctx)),
-                    ),
-                ],
+        statements
+    });
+    let r#loop =
+        r#loop(AstBuilder::new(ctx.allocator).statement_block(Span::new(0, 0), inner_statements));
+    oxc::allocator::Vec::from_iter_in(
+        [
+            oxc::ast::ast::Statement::VariableDeclaration(oxc::allocator::Box::new_in(
+                output,
                 ctx.allocator,
+            )),
+            r#loop,
+            AstBuilder::new(ctx.allocator).statement_return(
+                Span::new(0, 0),
+                Some(oxidescript::parser::ast::Identifier(output_id).into_oxc(ctx)),
             ),
+        ],
+        ctx.allocator,
+    )
+}
+
+impl<'c> IntoOxc<'c, Statement<'c>> for ForExpr {
+    fn into_oxc(self, ctx: &'c crate::JavascriptCompilerContext<'c>) -> Statement<'c> {
+        let inner_statements = loop_body(ctx, || discarded(*self.body, ctx));
+        for_loop(
+            self.lhs,
+            *self.rhs,
+            AstBuilder::new(ctx.allocator).statement_block(Span::new(0, 0), inner_statements),
             ctx,
         )
     }
 }
 
-impl<'c> IntoOxc<'c, Statement<'c>> for ForExpr {
+impl<'c> IntoOxc<'c, Statement<'c>> for WhileExpr {
     fn into_oxc(self, ctx: &'c crate::JavascriptCompilerContext<'c>) -> Statement<'c> {
-        let inner_statements: oxc::allocator::Vec<Statement> = self.body.into_oxc(ctx);
-        AstBuilder::new(ctx.allocator).statement_for_of(
+        let inner_statements = loop_body(ctx, || discarded(*self.body, ctx));
+        AstBuilder::new(ctx.allocator).statement_while(
             Span::new(0, 0),
-            false,
-            self.lhs.into_oxc(ctx),
-            self.rhs.into_oxc(ctx),
+            self.condition.into_oxc(ctx),
             AstBuilder::new(ctx.allocator).statement_block(Span::new(0, 0), inner_statements),
         )
     }
@@ -0,0 +1,124 @@
+use oxc::{
+    ast::{ast::Statement, AstBuilder},
+    span::Span,
+};
+use oxidescript::parser::{
+    ast::{Expression, MatchExpr, Pattern},
+    visit::walk_expression,
+};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+use super::{block::discarded, conditional::discarded as discarded_if};
+
+/// Matches used as values are lowered to `if` expressions, which become ternaries when every arm is
+/// just a value
+impl<'c> IntoOxc<'c, oxc::ast::ast::Expression<'c>> for MatchExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> oxc::ast::ast::Expression<'c> {
+        self.into_if().into_oxc(ctx)
+    }
+}
+
+/// `switch (value) { case 1: case 2: ...; break; default: ... }`. `switch` compares with `===`
+/// like `==` does, so the arms match the same values as in the `if` chain of other backends.
+/// Guards, string prefixes and enum variants can't be `case`s, so matches with them stay an `if`
+/// chain. So do matches with a `break` in an arm, which would leave the `switch` instead of the loop
+impl<'c> IntoOxc<'c, Statement<'c>> for MatchExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Statement<'c> {
+        let ast = AstBuilder::new(ctx.allocator);
+        if self
+            .arms
+            .iter()
+            .any(|arm| arm.guard.is_some() || !is_switchable(&arm.pattern) || breaks(&arm.body))
+        {
+            let statements = match self.into_if() {
+                // the value is stored first
+                Expression::BlockExpression(mut block, _) => {
+                    let lowered = block.return_value.take();
+                    let mut statements: oxc::allocator::Vec<Statement> = block.into_oxc(ctx);
+                    statements.extend(lowered.map(|lowered| if_statement(lowered, ctx)));
+                    statements
+                }
+                lowered => ast.vec1(if_statement(lowered, ctx)),
+            };
+            return ast.statement_block(Span::new(0, 0), statements);
+        }
+        let binds = self
+            .arms
+            .iter()
+            .any(|arm| matches!(arm.pattern, Pattern::Binding(_)));
+        // only bindings read the value again
+        let (value, store) = if binds {
+            MatchExpr::stored_value(*self.value)
+        } else {
+            (*self.value, None)
+        };
+        let reachable = self
+            .arms
+            .iter()
+            .position(|arm| arm.pattern.is_catch_all())
+            .map_or(self.arms.len(), |index| index + 1);
+        let mut cases = ast.vec();
+        for (index, arm) in self.arms.into_iter().take(reachable).enumerate() {
+            let mut body = discarded(MatchExpr::arm_body(&arm.pattern, arm.body, &value), ctx);
+            if index + 1 < reachable {
+                body.push(ast.statement_break(Span::new(0, 0), None));
+            }
+            let body = ast.vec1(ast.statement_block(Span::new(0, 0), body));
+            if arm.pattern.is_catch_all() {
+                cases.push(ast.switch_case(Span::new(0, 0), None, body));
+                continue;
+            }
+            let mut literals = vec![];
+            arm.pattern.literals(&mut literals);
+            // `case 1: case 2: body`
+            let last = literals
+                .pop()
+                .expect("patterns without a catch-all have literals");
+            for literal in literals {
+                cases.push(ast.switch_case(
+                    Span::new(0, 0),
+                    Some(literal.clone().into_oxc(ctx)),
+                    ast.vec(),
+                ));
+            }
+            cases.push(ast.switch_case(Span::new(0, 0), Some(last.clone().into_oxc(ctx)), body));
+        }
+        let switch = ast.statement_switch(Span::new(0, 0), value.into_oxc(ctx), cases);
+        match store {
+            Some(store) => {
+                let mut statements = ast.vec_from_iter(store.into_oxc(ctx));
+                statements.push(switch);
+                ast.statement_block(Span::new(0, 0), statements)
+            }
+            None => switch,
+        }
+    }
+}
+
+/// The `if` chain of a match as a statement, with the values of the arms discarded
+fn if_statement<'c>(lowered: Expression, ctx: &'c JavascriptCompilerContext<'c>) -> Statement<'c> {
+    match lowered {
+        Expression::IfExpression(expr, _) => discarded_if(expr, ctx),
+        lowered => AstBuilder::new(ctx.allocator)
+            .statement_expression(Span::new(0, 0), lowered.into_oxc(ctx)),
+    }
+}
+
+/// Whether a `switch` can compare the value with the pattern, which only compares with `===`
+fn is_switchable(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Prefix(..) | Pattern::Variant(..) | Pattern::Tuple(_) => false,
+        Pattern::Or(patterns) => patterns.iter().all(is_switchable),
+        _ => true,
+    }
+}
+
+/// Whether there is a `break` anywhere in the expression, even in loops of its own
+fn breaks(expression: &Expression) -> bool {
+    let mut breaks = false;
+    walk_expression(expression, &mut |expression| {
+        breaks |= matches!(expression, Expression::BreakExpression(_));
+    });
+    breaks
+}
@@ -3,6 +3,7 @@ use oxc::{
     span::Span,
 };
 
+use super::optional::object;
 use crate::IntoOxc;
 
 impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::MemberAccessExpr {
@@ -10,7 +11,7 @@ impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::MemberAccessE
         AstBuilder::new(ctx.allocator)
             .member_expression_static(
                 Span::new(0, 0),
-                self.lhs.into_oxc(ctx),
+                object(*self.lhs, ctx),
                 self.ident.into_oxc(ctx),
                 false,
             )
@@ -0,0 +1,133 @@
+use oxc::{
+    ast::{
+        ast::{PropertyKind, Statement, VariableDeclarator},
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{Declaration, Identifier, ModDecl};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+use super::{iife, structs::object_property, types::struct_interface};
+
+/// `const name = (() => { ...items; return { ...pub items }; })();`, so private items stay scoped
+/// to the module and `name::item` paths become member accesses on the returned object
+impl<'c> IntoOxc<'c, Statement<'c>> for ModDecl {
+    fn into_oxc(mut self, ctx: &'c JavascriptCompilerContext<'c>) -> Statement<'c> {
+        // `pub use` items are exported like the constants they bind
+        self.lower_uses();
+        let pub_names = self
+            .items
+            .iter()
+            .filter(|item| item.is_pub)
+            .flat_map(|item| declared_names(&item.declaration))
+            .cloned()
+            .collect::<Vec<_>>();
+        let statements = ctx.collect_structs(
+            self.items
+                .into_iter()
+                .map(|item| {
+                    oxidescript::parser::ast::Statement::DeclarationStatement(
+                        item.declaration,
+                        oxidescript::parser::ast::Span::default(),
+                    )
+                })
+                .collect(),
+        );
+
+        let mut exports = vec![];
+        let mut body = oxc::allocator::Vec::new_in(ctx.allocator);
+        for statement in statements {
+            let names = exported_names(&statement)
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            body.extend(struct_interface(&statement, ctx));
+            // structs without impls don't produce a binding that could be exported
+            if let Some(statement) = statement.into_oxc(ctx) {
+                body.push(statement);
+                exports.extend(names.into_iter().filter(|name| pub_names.contains(name)));
+            }
+        }
+        body.push(AstBuilder::new(ctx.allocator).statement_return(
+            Span::new(0, 0),
+            Some(AstBuilder::new(ctx.allocator).expression_object(
+                Span::new(0, 0),
+                oxc::allocator::Vec::from_iter_in(
+                    exports.into_iter().map(|name| {
+                        let value = name.clone().into_oxc(ctx);
+                        object_property(name, value, PropertyKind::Init, false, ctx)
+                    }),
+                    ctx.allocator,
+                ),
+                None,
+            )),
+        ));
+
+        Statement::VariableDeclaration(oxc::allocator::Box::new_in(
+            AstBuilder::new(ctx.allocator).variable_declaration(
+                Span::new(0, 0),
+                oxc::ast::ast::VariableDeclarationKind::Const,
+                oxc::allocator::Vec::from_iter_in(
+                    vec![VariableDeclarator {
+                        span: Span::new(0, 0),
+                        kind: oxc::ast::ast::VariableDeclarationKind::Const,
+                        id: self.name.into_oxc(ctx),
+                        init: Some(iife(body, ctx)),
+                        definite: false,
+                    }],
+                    ctx.allocator,
+                ),
+                false,
+            ),
+            ctx.allocator,
+        ))
+    }
+}
+
+/// The bindings a declaration introduces in its module, impl blocks don't introduce one, and
+/// destructurings can introduce several. Imports with `mod` and `use` aren't exported again, and
+/// externs belong to the javascript environment. `type`s and traits only exist in typescript
+fn declared_names(declaration: &Declaration) -> Vec<&Identifier> {
+    match declaration {
+        Declaration::ConstDeclaration(name, ..) | Declaration::LetDeclaration(name, _, _, _) => {
+            vec![name]
+        }
+        Declaration::DestructuringDeclaration(pattern, value, _, _) => pattern
+            .bindings(value)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+        Declaration::FunctionDeclaration { name, .. } => vec![name],
+        Declaration::StructDeclaration(decl) => vec![&decl.name],
+        Declaration::EnumDeclaration(decl) => vec![&decl.name],
+        Declaration::ModDeclaration(decl) => vec![&decl.name],
+        Declaration::TypeDeclaration(decl) => vec![&decl.name],
+        Declaration::TraitDeclaration(decl) => vec![&decl.name],
+        Declaration::ImplDeclaration(_)
+        | Declaration::ModFileDeclaration(_)
+        | Declaration::UseDeclaration(_)
+        | Declaration::ExternDeclaration(_) => vec![],
+    }
+}
+
+/// The names a top level statement of a module file is exported as
+pub fn exported_names(statement: &oxidescript::parser::ast::Statement) -> Vec<&Identifier> {
+    match statement {
+        oxidescript::parser::ast::Statement::DeclarationStatement(declaration, _) => {
+            declared_names(declaration)
+        }
+        oxidescript::parser::ast::Statement::ExpressionStatement { .. } => vec![],
+    }
+}
+
+/// Whether a top level statement declares a `pub` function, constant or struct
+pub fn is_pub(statement: &oxidescript::parser::ast::Statement) -> bool {
+    match statement {
+        oxidescript::parser::ast::Statement::DeclarationStatement(declaration, _) => {
+            declaration.is_pub()
+        }
+        oxidescript::parser::ast::Statement::ExpressionStatement { .. } => false,
+    }
+}
@@ -0,0 +1,59 @@
+use oxc::{
+    ast::{
+        ast::{ChainElement, Expression, LogicalOperator},
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{OptionalAccess, OptionalExpr};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+/// `lhs?.ident` or `lhs?.[index]`. The whole chain around it is wrapped by [`chain`]
+impl<'c> IntoOxc<'c, Expression<'c>> for OptionalExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let ast = AstBuilder::new(ctx.allocator);
+        let lhs = object(*self.lhs, ctx);
+        match self.access {
+            OptionalAccess::Member(ident) => ast
+                .member_expression_static(Span::new(0, 0), lhs, ident.into_oxc(ctx), true)
+                .into(),
+            OptionalAccess::Index(index) => ast
+                .member_expression_computed(Span::new(0, 0), lhs, index.into_oxc(ctx), true)
+                .into(),
+        }
+    }
+}
+
+/// The object of a call, member access or index, which continues the chain it is part of instead
+/// of being wrapped on its own
+pub fn object<'c>(
+    expression: oxidescript::parser::ast::Expression,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    ctx.continues_chain.set(true);
+    expression.into_oxc(ctx)
+}
+
+/// `(a?.b.c ?? null)`. Optional chains evaluate to `undefined` when they stop, but `None` is
+/// `null` everywhere else
+pub fn chain<'c>(
+    expression: oxidescript::parser::ast::Expression,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let element = match object(expression, ctx) {
+        Expression::CallExpression(call) => ChainElement::CallExpression(call),
+        expression if expression.is_member_expression() => {
+            ChainElement::from(expression.into_member_expression())
+        }
+        // intrinsics like `value?.field.clone()` wrap the chain they are called on themselves
+        expression => return expression,
+    };
+    ast.expression_logical(
+        Span::new(0, 0),
+        ast.expression_chain(Span::new(0, 0), element),
+        LogicalOperator::Coalesce,
+        ast.expression_null_literal(Span::new(0, 0)),
+    )
+}
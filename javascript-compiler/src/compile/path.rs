@@ -0,0 +1,26 @@
+use oxc::{
+    ast::{ast::Expression, AstBuilder},
+    span::Span,
+};
+
+use crate::IntoOxc;
+
+/// `Type::item` and `module::item` are lowered to a member access on the type's namespace object
+/// or class, or on the module object. Inside impl blocks `Self` resolves to the implemented type
+impl<'c> IntoOxc<'c, Expression<'c>> for oxidescript::parser::ast::PathExpr {
+    fn into_oxc(self, ctx: &'c crate::JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let mut segments = self.segments.into_iter();
+        let first = ctx
+            .resolve_self_type(
+                segments
+                    .next()
+                    .expect("path expressions have at least two segments"),
+            )
+            .into_oxc(ctx);
+        segments.fold(first, |lhs, segment| {
+            AstBuilder::new(ctx.allocator)
+                .member_expression_static(Span::new(0, 0), lhs, segment.into_oxc(ctx), false)
+                .into()
+        })
+    }
+}
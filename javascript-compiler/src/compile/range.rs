@@ -0,0 +1,143 @@
+use oxc::{
+    ast::{
+        ast::{Argument, Expression, PropertyKind, TSTypeParameterInstantiation},
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{
+    Identifier, InfixExpr, InfixOperator, Literal, Number, NumberBase, Parameter, RangeExpr,
+};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+use super::{
+    intrinsic::{arrow, builtin_member},
+    structs::object_property,
+};
+
+/// A range used as a value is an array of its numbers,
+/// `Array.from({ length: end - start }, (_, __index) => start + __index)`. A start that isn't a
+/// name or a literal is evaluated once, as the parameter of an arrow function around it. `for`
+/// loops over ranges count instead, see [`r#loop`](super::r#loop)
+impl<'c> IntoOxc<'c, Expression<'c>> for RangeExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let ast = AstBuilder::new(ctx.allocator);
+        let simple = matches!(
+            *self.start,
+            oxidescript::parser::ast::Expression::IdentifierExpression(_, _)
+                | oxidescript::parser::ast::Expression::LiteralExpression(_, _)
+        );
+        let start = match simple {
+            true => (*self.start).clone(),
+            false => identifier("__start"),
+        };
+        let array = ast.expression_call(
+            Span::new(0, 0),
+            builtin_member("Array", "from", ctx),
+            None::<TSTypeParameterInstantiation>,
+            ast.vec_from_iter([
+                Argument::from(ast.expression_object(
+                    Span::new(0, 0),
+                    ast.vec1(object_property(
+                        Identifier("length".to_string()),
+                        length(start.clone(), *self.end, self.inclusive).into_oxc(ctx),
+                        PropertyKind::Init,
+                        false,
+                        ctx,
+                    )),
+                    None,
+                )),
+                Argument::from(arrow(
+                    vec![parameter("_", "any"), parameter("__index", "number")].into_oxc(ctx),
+                    infix(InfixOperator::Plus, start, identifier("__index")).into_oxc(ctx),
+                    ctx,
+                )),
+            ]),
+            false,
+        );
+        if simple {
+            return array;
+        }
+        ast.expression_call(
+            Span::new(0, 0),
+            arrow(
+                vec![parameter("__start", "number")].into_oxc(ctx),
+                array,
+                ctx,
+            ),
+            None::<TSTypeParameterInstantiation>,
+            ast.vec1(Argument::from(self.start.into_oxc(ctx))),
+            false,
+        )
+    }
+}
+
+/// `end - start`, or `end - start + 1` if the range includes its end. Folded when both bounds
+/// are integer literals
+fn length(
+    start: oxidescript::parser::ast::Expression,
+    end: oxidescript::parser::ast::Expression,
+    inclusive: bool,
+) -> oxidescript::parser::ast::Expression {
+    if let (Some(start), Some(end)) = (integer(&start), integer(&end)) {
+        if let Ok(length) = i32::try_from((end - start + inclusive as i64).max(0)) {
+            return number(length);
+        }
+    }
+    let length = infix(InfixOperator::Minus, end, start);
+    match inclusive {
+        true => infix(InfixOperator::Plus, length, number(1)),
+        false => length,
+    }
+}
+
+fn integer(expression: &oxidescript::parser::ast::Expression) -> Option<i64> {
+    match expression {
+        oxidescript::parser::ast::Expression::LiteralExpression(
+            Literal::NumberLiteral(Number::I { value, .. }),
+            _,
+        ) => Some(i64::from(*value)),
+        _ => None,
+    }
+}
+
+fn number(value: i32) -> oxidescript::parser::ast::Expression {
+    oxidescript::parser::ast::Expression::LiteralExpression(
+        Literal::NumberLiteral(Number::I {
+            base: NumberBase::Dec,
+            value,
+        }),
+        oxidescript::parser::ast::Span::default(),
+    )
+}
+
+pub fn infix(
+    op: InfixOperator,
+    lhs: oxidescript::parser::ast::Expression,
+    rhs: oxidescript::parser::ast::Expression,
+) -> oxidescript::parser::ast::Expression {
+    oxidescript::parser::ast::Expression::InfixExpression(
+        InfixExpr {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+        oxidescript::parser::ast::Span::default(),
+    )
+}
+
+pub fn identifier(name: &str) -> oxidescript::parser::ast::Expression {
+    oxidescript::parser::ast::Expression::IdentifierExpression(
+        Identifier(name.to_string()),
+        oxidescript::parser::ast::Span::default(),
+    )
+}
+
+fn parameter(name: &str, type_: &str) -> Parameter {
+    Parameter {
+        name: Identifier(name.to_string()),
+        type_: Identifier(type_.to_string()),
+        span: oxidescript::parser::ast::Span::default(),
+    }
+}
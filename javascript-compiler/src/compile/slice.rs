@@ -0,0 +1,190 @@
+use oxc::{
+    ast::{
+        ast::{
+            Argument, BinaryOperator, Expression, LogicalOperator, NumberBase, TSTypeAnnotation,
+            TSTypeParameterDeclaration, TSTypeParameterInstantiation,
+        },
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{Identifier, Parameter, SliceExpr};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+/// `lhs.slice(start, end)`, which works the same for arrays and strings. With
+/// [`bounds_checks`](crate::JavascriptCompilerOptions::bounds_checks) the slice is taken in an
+/// arrow function that throws a `RangeError` if a bound is outside of `lhs`, instead of clamping it
+impl<'c> IntoOxc<'c, Expression<'c>> for SliceExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        if ctx.options.bounds_checks && (self.start.is_some() || self.end.is_some()) {
+            return checked_slice(self, ctx);
+        }
+        slice(
+            self.lhs.into_oxc(ctx),
+            self.start.map(|start| start.into_oxc(ctx)),
+            self.end.map(|end| end.into_oxc(ctx)),
+            ctx,
+        )
+    }
+}
+
+/// ```javascript
+/// ((value, start, end) => {
+///     if (start < 0 || start > value.length || end < 0 || end > value.length || start > end)
+///         throw new RangeError("slice " + start + ".." + end + " is out of bounds of length " + value.length);
+///     return value.slice(start, end);
+/// })(lhs, start, end)
+/// ```
+///
+/// The parameters are only there for the bounds the slice has, and evaluate every operand once
+fn checked_slice<'c>(expr: SliceExpr, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let ident = |name: &str| Identifier(name.to_string()).into_oxc(ctx);
+    let length = || -> Expression<'c> {
+        ast.member_expression_static(
+            Span::new(0, 0),
+            ident("value"),
+            Identifier("length".to_string()).into_oxc(ctx),
+            false,
+        )
+        .into()
+    };
+    let (bounds, values): (Vec<_>, Vec<_>) = [("start", expr.start), ("end", expr.end)]
+        .into_iter()
+        .filter_map(|(name, bound)| Some((name, *bound?)))
+        .unzip();
+
+    let mut parameters = vec![("value", "any")];
+    parameters.extend(bounds.iter().map(|name| (*name, "number")));
+    let parameters = parameters
+        .into_iter()
+        .map(|(name, type_)| Parameter {
+            name: Identifier(name.to_string()),
+            type_: Identifier(type_.to_string()),
+            span: oxidescript::parser::ast::Span::default(),
+        })
+        .collect::<Vec<_>>();
+    let arguments = [*expr.lhs]
+        .into_iter()
+        .chain(values)
+        .map(|argument| Argument::from(argument.into_oxc(ctx)));
+
+    let compare = |lhs: Expression<'c>, op: BinaryOperator, rhs: Expression<'c>| {
+        ast.expression_binary(Span::new(0, 0), lhs, op, rhs)
+    };
+    let mut conditions = vec![];
+    for name in &bounds {
+        conditions.push(compare(ident(name), BinaryOperator::LessThan, zero(ctx)));
+        conditions.push(compare(ident(name), BinaryOperator::GreaterThan, length()));
+    }
+    if bounds.len() == 2 {
+        conditions.push(compare(
+            ident("start"),
+            BinaryOperator::GreaterThan,
+            ident("end"),
+        ));
+    }
+    let invalid = conditions
+        .into_iter()
+        .reduce(|invalid, condition| {
+            ast.expression_logical(Span::new(0, 0), invalid, LogicalOperator::Or, condition)
+        })
+        .unwrap();
+
+    let string =
+        |value: &str| ast.expression_string_literal(Span::new(0, 0), ast.atom(value), None);
+    let has = |name: &str| bounds.contains(&name);
+    let mut message = vec![];
+    let mut text = "slice ".to_string();
+    for (name, after) in [("start", ".."), ("end", "")] {
+        if has(name) {
+            message.push(string(&text));
+            message.push(ident(name));
+            text.clear();
+        }
+        text.push_str(after);
+    }
+    text.push_str(" is out of bounds of length ");
+    message.push(string(&text));
+    message.push(length());
+    let message = message
+        .into_iter()
+        .reduce(|message, part| compare(message, BinaryOperator::Addition, part))
+        .unwrap();
+    let error = ast.expression_new(
+        Span::new(0, 0),
+        ast.expression_identifier_reference(Span::new(0, 0), "RangeError"),
+        ast.vec1(Argument::from(message)),
+        None::<TSTypeParameterInstantiation>,
+    );
+
+    let body = ast.vec_from_array([
+        ast.statement_if(
+            Span::new(0, 0),
+            invalid,
+            ast.statement_throw(Span::new(0, 0), error),
+            None,
+        ),
+        ast.statement_return(
+            Span::new(0, 0),
+            Some(slice(
+                ident("value"),
+                has("start").then(|| ident("start")),
+                has("end").then(|| ident("end")),
+                ctx,
+            )),
+        ),
+    ]);
+    ast.expression_call(
+        Span::new(0, 0),
+        ast.expression_arrow_function(
+            Span::new(0, 0),
+            false,
+            false,
+            None::<TSTypeParameterDeclaration>,
+            parameters.into_oxc(ctx),
+            None::<TSTypeAnnotation>,
+            ast.function_body(Span::new(0, 0), ast.vec(), body),
+        ),
+        None::<TSTypeParameterInstantiation>,
+        ast.vec_from_iter(arguments),
+        false,
+    )
+}
+
+/// `value.slice(start, end)`, starting at `0` if there only is an `end`
+fn slice<'c>(
+    value: Expression<'c>,
+    start: Option<Expression<'c>>,
+    end: Option<Expression<'c>>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Expression<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let start = match (start, &end) {
+        (None, Some(_)) => Some(zero(ctx)),
+        (start, _) => start,
+    };
+    ast.expression_call(
+        Span::new(0, 0),
+        ast.member_expression_static(
+            Span::new(0, 0),
+            value,
+            Identifier("slice".to_string()).into_oxc(ctx),
+            false,
+        )
+        .into(),
+        None::<TSTypeParameterInstantiation>,
+        ast.vec_from_iter(start.into_iter().chain(end).map(Argument::from)),
+        false,
+    )
+}
+
+fn zero<'c>(ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+    AstBuilder::new(ctx.allocator).expression_numeric_literal(
+        Span::new(0, 0),
+        0.0,
+        None,
+        NumberBase::Decimal,
+    )
+}
@@ -0,0 +1,526 @@
+use oxc::{
+    ast::{
+        ast::{
+            Argument, AssignmentOperator, AssignmentTarget, Class, ClassBody, ClassElement,
+            ClassType, Expression, Function, FunctionType, MethodDefinition, MethodDefinitionKind,
+            MethodDefinitionType, ObjectProperty, ObjectPropertyKind, PropertyDefinition,
+            PropertyDefinitionType, PropertyKey, PropertyKind, SimpleAssignmentTarget, Statement,
+            TSTypeParameterInstantiation, VariableDeclarator,
+        },
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{
+    Block, Identifier, ImplDecl, ImplItem, Method, Parameter, StructDecl, StructExpr, TraitDecl,
+};
+
+use crate::{IntoOxc, JavascriptCompilerContext, OutputLanguage, StructEmission};
+
+use super::{
+    json::derived_functions,
+    jump,
+    types::{type_annotation, type_parameters},
+};
+
+/// A function a derive adds to a struct, with whether it takes `self`
+pub type Derived<'c> = (Identifier, bool, oxc::allocator::Box<'c, Function<'c>>);
+
+/// In object mode a struct only produces javascript if it has impl blocks or derives
+impl<'c> IntoOxc<'c, Option<Statement<'c>>> for StructDecl {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Option<Statement<'c>> {
+        let items = ctx
+            .structs
+            .borrow_mut()
+            .get_mut(&self.name.0)
+            .map(|info| std::mem::take(&mut info.items))
+            .unwrap_or_default();
+        match ctx.options.struct_emission {
+            StructEmission::Objects => {
+                // getters and setters are attached to the struct values instead
+                let items = items
+                    .into_iter()
+                    .filter(|item| matches!(item, ImplItem::Method(_) | ImplItem::Const(..)))
+                    .collect::<Vec<_>>();
+                let derived =
+                    ctx.with_self_type(self.name.clone(), || derived_functions(&self, false, ctx));
+                if items.is_empty() && derived.is_empty() {
+                    None
+                } else {
+                    Some(namespace_object(self.name, items, derived, false, ctx))
+                }
+            }
+            StructEmission::Classes => {
+                let derived =
+                    ctx.with_self_type(self.name.clone(), || derived_functions(&self, true, ctx));
+                Some(class_declaration(self, items, derived, ctx))
+            }
+        }
+    }
+}
+
+/// Impl blocks for structs declared in the same program are emitted with the struct, so this only
+/// handles impls of unknown types, which always use object mode
+impl<'c> IntoOxc<'c, Statement<'c>> for ImplDecl {
+    fn into_oxc(mut self, ctx: &'c JavascriptCompilerContext<'c>) -> Statement<'c> {
+        ctx.inherit_defaults(&mut self);
+        namespace_object(self.target, self.items, vec![], false, ctx)
+    }
+}
+
+/// `const Trait = { method(self, ...) { ... } };` with the default methods of a trait, which the
+/// impls that inherit them call instead of having a copy of the body
+pub fn trait_object<'c>(
+    decl: TraitDecl,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Statement<'c>> {
+    ctx.register_trait(&decl);
+    let defaults = decl.defaults();
+    if defaults.is_empty() {
+        return None;
+    }
+    let items = defaults.into_iter().map(ImplItem::Method).collect();
+    Some(namespace_object(decl.name, items, vec![], false, ctx))
+}
+
+/// Enums with discriminants are `const Name = Object.freeze({ Variant: discriminant, ... });` with
+/// any struct emission, since their values are the discriminants and not instances of a class
+pub fn discriminant_object<'c>(
+    decl: StructDecl,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let items = ctx
+        .structs
+        .borrow_mut()
+        .get_mut(&decl.name.0)
+        .map(|info| std::mem::take(&mut info.items))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|item| matches!(item, ImplItem::Method(_) | ImplItem::Const(..)))
+        .collect();
+    namespace_object(decl.name, items, vec![], true, ctx)
+}
+
+impl<'c> IntoOxc<'c, Expression<'c>> for StructExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let name = ctx.resolve_self_type(self.name);
+        let info = ctx
+            .structs
+            .borrow()
+            .get(&name.0)
+            .map(|info| (info.fields.clone(), info.attached.clone()));
+        match (ctx.options.struct_emission, info) {
+            (StructEmission::Classes, Some((field_order, _))) => {
+                let mut values = self.fields;
+                let arguments = field_order
+                    .into_iter()
+                    .map(|field| {
+                        values
+                            .iter()
+                            .position(|(name, _)| *name == field.name)
+                            .map(|index| values.remove(index).1)
+                            .unwrap_or_else(|| {
+                                oxidescript::parser::ast::Expression::IdentifierExpression(
+                                    Identifier("undefined".to_string()),
+                                    oxidescript::parser::ast::Span::default(),
+                                )
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                AstBuilder::new(ctx.allocator).expression_new(
+                    Span::new(0, 0),
+                    name.into_oxc(ctx),
+                    arguments.into_oxc(ctx),
+                    None::<TSTypeParameterInstantiation>,
+                )
+            }
+            (_, info) => {
+                let fields = self.fields.into_iter().map(|(name, value)| {
+                    object_property(name, value.into_oxc(ctx), PropertyKind::Init, false, ctx)
+                });
+                let attached = info
+                    .map(|(_, attached)| attached)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|item| match item {
+                        ImplItem::Getter(method) => (PropertyKind::Get, method),
+                        ImplItem::Setter(method) => (PropertyKind::Set, method),
+                        ImplItem::Method(method) => (PropertyKind::Init, method),
+                        ImplItem::Const(..) => unreachable!("constants aren't attached to values"),
+                    })
+                    .map(|(kind, method)| {
+                        let method_name = method.name.clone();
+                        let function =
+                            ctx.with_self_type(name.clone(), || method_function(method, true, ctx));
+                        let shorthand = kind == PropertyKind::Init
+                            && ctx.options.output_language == OutputLanguage::Javascript;
+                        object_property(
+                            method_name,
+                            Expression::FunctionExpression(function),
+                            kind,
+                            shorthand,
+                            ctx,
+                        )
+                    });
+                AstBuilder::new(ctx.allocator).expression_object(
+                    Span::new(0, 0),
+                    oxc::allocator::Vec::from_iter_in(fields.chain(attached), ctx.allocator),
+                    None,
+                )
+            }
+        }
+    }
+}
+
+/// `const Name = { CONST: value, method(self, ...) { ... } };`, getters and setters become
+/// `get_name(self)` and `set_name(self, value)` functions. A frozen object is wrapped in
+/// `Object.freeze(...)`
+fn namespace_object<'c>(
+    name: Identifier,
+    items: Vec<ImplItem>,
+    derived: Vec<Derived<'c>>,
+    frozen: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let self_type = name.clone();
+    let properties = items.into_iter().map(|item| {
+        let (name, method) = match item {
+            ImplItem::Const(name, value) => {
+                return object_property(name, value.into_oxc(ctx), PropertyKind::Init, false, ctx);
+            }
+            ImplItem::Method(method) => (method.name.clone(), method),
+            ImplItem::Getter(method) => (Identifier(format!("get_{}", method.name.0)), method),
+            ImplItem::Setter(method) => (Identifier(format!("set_{}", method.name.0)), method),
+        };
+        method_property(name, method_function(method, false, ctx), ctx)
+    });
+    let derived = derived
+        .into_iter()
+        .map(|(name, _, function)| method_property(name, function, ctx));
+    let properties = properties.chain(derived);
+    let properties = ctx.with_self_type(self_type, || {
+        oxc::allocator::Vec::from_iter_in(properties, ctx.allocator)
+    });
+    let ast = AstBuilder::new(ctx.allocator);
+    let mut object = ast.expression_object(Span::new(0, 0), properties, None);
+    if frozen {
+        object = ast.expression_call(
+            Span::new(0, 0),
+            ast.member_expression_static(
+                Span::new(0, 0),
+                Identifier("Object".to_string()).into_oxc(ctx),
+                Identifier("freeze".to_string()).into_oxc(ctx),
+                false,
+            )
+            .into(),
+            None::<TSTypeParameterInstantiation>,
+            ast.vec1(Argument::from(object)),
+            false,
+        );
+    }
+    Statement::VariableDeclaration(oxc::allocator::Box::new_in(
+        AstBuilder::new(ctx.allocator).variable_declaration(
+            Span::new(0, 0),
+            oxc::ast::ast::VariableDeclarationKind::Const,
+            oxc::allocator::Vec::from_iter_in(
+                vec![VariableDeclarator {
+                    span: Span::new(0, 0),
+                    kind: oxc::ast::ast::VariableDeclarationKind::Const,
+                    id: name.into_oxc(ctx),
+                    init: Some(object),
+                    definite: false,
+                }],
+                ctx.allocator,
+            ),
+            false,
+        ),
+        ctx.allocator,
+    ))
+}
+
+fn method_property<'c>(
+    name: Identifier,
+    function: oxc::allocator::Box<'c, Function<'c>>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> ObjectPropertyKind<'c> {
+    // the codegen drops the return type of method shorthands, so typescript output uses
+    // `name: function(): T {}` instead
+    object_property(
+        name,
+        Expression::FunctionExpression(function),
+        PropertyKind::Init,
+        ctx.options.output_language == OutputLanguage::Javascript,
+        ctx,
+    )
+}
+
+/// `class Name { constructor(...fields) { ... } method() { ... } static function() { ... } }`,
+/// associated constants become static properties. The type parameters of a generic struct are
+/// the ones of the class
+fn class_declaration<'c>(
+    decl: StructDecl,
+    items: Vec<ImplItem>,
+    derived: Vec<Derived<'c>>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let constructor_body = oxc::allocator::Vec::from_iter_in(
+        decl.fields.iter().map(|field| {
+            let this_field = AstBuilder::new(ctx.allocator).member_expression_static(
+                Span::new(0, 0),
+                AstBuilder::new(ctx.allocator).expression_this(Span::new(0, 0)),
+                field.name.clone().into_oxc(ctx),
+                false,
+            );
+            AstBuilder::new(ctx.allocator).statement_expression(
+                Span::new(0, 0),
+                AstBuilder::new(ctx.allocator).expression_assignment(
+                    Span::new(0, 0),
+                    AssignmentOperator::Assign,
+                    AssignmentTarget::from(SimpleAssignmentTarget::from(this_field)),
+                    field.name.clone().into_oxc(ctx),
+                ),
+            )
+        }),
+        ctx.allocator,
+    );
+    let parameters = decl.type_parameters;
+    let constructor = ctx.with_type_parameters(parameters.clone(), || {
+        function_expression(
+            decl.fields
+                .into_iter()
+                .map(|field| Parameter {
+                    name: field.name,
+                    type_: field.type_,
+                    span: oxidescript::parser::ast::Span::default(),
+                })
+                .collect(),
+            None,
+            AstBuilder::new(ctx.allocator).function_body(
+                Span::new(0, 0),
+                oxc::allocator::Vec::new_in(ctx.allocator),
+                constructor_body,
+            ),
+            ctx,
+        )
+    });
+    let mut elements = vec![class_method(
+        Identifier("constructor".to_string()),
+        constructor,
+        MethodDefinitionKind::Constructor,
+        false,
+        ctx,
+    )];
+    ctx.with_self_type(decl.name.clone(), || {
+        for item in items {
+            let (kind, method) = match item {
+                ImplItem::Const(name, value) => {
+                    elements.push(static_property(name, value.into_oxc(ctx), ctx));
+                    continue;
+                }
+                ImplItem::Method(method) => (MethodDefinitionKind::Method, method),
+                ImplItem::Getter(method) => (MethodDefinitionKind::Get, method),
+                ImplItem::Setter(method) => (MethodDefinitionKind::Set, method),
+            };
+            let name = method.name.clone();
+            let is_static = !method.has_self;
+            // static methods can't use the type parameters of the class
+            let declared = if is_static {
+                vec![]
+            } else {
+                parameters.clone()
+            };
+            let function =
+                ctx.with_type_parameters(declared, || method_function(method, true, ctx));
+            elements.push(class_method(name, function, kind, is_static, ctx));
+        }
+    });
+    for (name, has_self, function) in derived {
+        elements.push(class_method(
+            name,
+            function,
+            MethodDefinitionKind::Method,
+            !has_self,
+            ctx,
+        ));
+    }
+
+    Statement::ClassDeclaration(oxc::allocator::Box::new_in(
+        Class {
+            r#type: ClassType::ClassDeclaration,
+            span: Span::new(0, 0),
+            decorators: oxc::allocator::Vec::new_in(ctx.allocator),
+            id: Some(decl.name.into_oxc(ctx)),
+            type_parameters: type_parameters(&parameters, ctx),
+            super_class: None,
+            super_type_parameters: None,
+            implements: None,
+            body: oxc::allocator::Box::new_in(
+                ClassBody {
+                    span: Span::new(0, 0),
+                    body: oxc::allocator::Vec::from_iter_in(elements, ctx.allocator),
+                },
+                ctx.allocator,
+            ),
+            r#abstract: false,
+            declare: false,
+            scope_id: None.into(),
+        },
+        ctx.allocator,
+    ))
+}
+
+/// Class methods and accessors use `this` for `self`, everywhere else `self` is passed as the
+/// first parameter
+pub fn method_function<'c>(
+    method: Method,
+    self_is_this: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Box<'c, Function<'c>> {
+    let mut parameters = method.parameters;
+    if method.has_self && !self_is_this {
+        parameters.insert(
+            0,
+            Parameter {
+                name: Identifier("self".to_string()),
+                type_: Identifier("Self".to_string()),
+                span: oxidescript::parser::ast::Span::default(),
+            },
+        );
+    }
+    let outer_self_is_this = ctx.self_is_this.replace(self_is_this && method.has_self);
+    let body = function_body(method.body, ctx);
+    ctx.self_is_this.set(outer_self_is_this);
+    let return_type = method
+        .return_type
+        .map(|return_type| promised(method.is_async, return_type));
+    let mut function = function_expression(parameters, return_type, body, ctx);
+    function.r#async = method.is_async;
+    function
+}
+
+/// The typescript return type of a function, a `Promise` of it for an `async` function
+pub fn promised(is_async: bool, return_type: Identifier) -> Identifier {
+    match is_async {
+        true => Identifier(format!("Promise<{}>", return_type.0)),
+        false => return_type,
+    }
+}
+
+fn function_body<'c>(
+    block: Block,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::ast::ast::FunctionBody<'c> {
+    AstBuilder::new(ctx.allocator).function_body(
+        Span::new(0, 0),
+        oxc::allocator::Vec::new_in(ctx.allocator),
+        jump::function_body(ctx, || block.into_oxc(ctx)),
+    )
+}
+
+pub fn function_expression<'c>(
+    parameters: Vec<Parameter>,
+    return_type: Option<Identifier>,
+    body: oxc::ast::ast::FunctionBody<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Box<'c, Function<'c>> {
+    oxc::allocator::Box::new_in(
+        Function {
+            r#type: FunctionType::FunctionExpression,
+            span: Span::new(0, 0),
+            id: None,
+            generator: false,
+            r#async: false,
+            declare: false,
+            type_parameters: None,
+            this_param: None,
+            params: oxc::allocator::Box::new_in(parameters.into_oxc(ctx), ctx.allocator),
+            body: Some(oxc::allocator::Box::new_in(body, ctx.allocator)),
+            return_type: return_type.and_then(|return_type| type_annotation(&return_type, ctx)),
+            scope_id: None.into(),
+        },
+        ctx.allocator,
+    )
+}
+
+fn class_method<'c>(
+    name: Identifier,
+    function: oxc::allocator::Box<'c, Function<'c>>,
+    kind: MethodDefinitionKind,
+    is_static: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> ClassElement<'c> {
+    ClassElement::MethodDefinition(oxc::allocator::Box::new_in(
+        MethodDefinition {
+            r#type: MethodDefinitionType::MethodDefinition,
+            span: Span::new(0, 0),
+            decorators: oxc::allocator::Vec::new_in(ctx.allocator),
+            key: property_key(name, ctx),
+            value: function,
+            kind,
+            computed: false,
+            r#static: is_static,
+            r#override: false,
+            optional: false,
+            accessibility: None,
+        },
+        ctx.allocator,
+    ))
+}
+
+/// `static NAME = value;`
+fn static_property<'c>(
+    name: Identifier,
+    value: Expression<'c>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> ClassElement<'c> {
+    ClassElement::PropertyDefinition(oxc::allocator::Box::new_in(
+        PropertyDefinition {
+            r#type: PropertyDefinitionType::PropertyDefinition,
+            span: Span::new(0, 0),
+            decorators: oxc::allocator::Vec::new_in(ctx.allocator),
+            key: property_key(name, ctx),
+            value: Some(value),
+            computed: false,
+            r#static: true,
+            declare: false,
+            r#override: false,
+            optional: false,
+            definite: false,
+            readonly: false,
+            type_annotation: None,
+            accessibility: None,
+        },
+        ctx.allocator,
+    ))
+}
+
+pub fn object_property<'c>(
+    name: Identifier,
+    value: Expression<'c>,
+    kind: PropertyKind,
+    method: bool,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> ObjectPropertyKind<'c> {
+    ObjectPropertyKind::ObjectProperty(oxc::allocator::Box::new_in(
+        ObjectProperty {
+            span: Span::new(0, 0),
+            kind,
+            key: property_key(name, ctx),
+            value,
+            method,
+            shorthand: false,
+            computed: false,
+        },
+        ctx.allocator,
+    ))
+}
+
+pub fn property_key<'c>(
+    name: Identifier,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> PropertyKey<'c> {
+    PropertyKey::StaticIdentifier(oxc::allocator::Box::new_in(
+        name.into_oxc(ctx),
+        ctx.allocator,
+    ))
+}
@@ -0,0 +1,64 @@
+use oxc::{
+    allocator::Box,
+    ast::{
+        ast::{Expression, TSTypeParameterInstantiation, TemplateElementValue, TemplateLiteral},
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{TaggedTemplateExpr, TemplateExpr};
+
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+/// ``tag`text ${value} text` ``
+impl<'c> IntoOxc<'c, Expression<'c>> for TaggedTemplateExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let ast = AstBuilder::new(ctx.allocator);
+        let tag = self.tag.into_oxc(ctx);
+        ast.expression_tagged_template(
+            Span::new(0, 0),
+            tag,
+            template_literal(self.strings, self.values, ctx),
+            None::<TSTypeParameterInstantiation>,
+        )
+    }
+}
+
+/// `` `text ${value} text` ``
+impl<'c> IntoOxc<'c, Expression<'c>> for TemplateExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        Expression::TemplateLiteral(Box::new_in(
+            template_literal(self.strings, self.values, ctx),
+            ctx.allocator,
+        ))
+    }
+}
+
+/// The strings are already unescaped, so the raw strings only have to escape what would end the
+/// string or start an interpolation in javascript, and carriage returns, which javascript reads as
+/// newlines in templates
+fn template_literal<'c>(
+    strings: Vec<String>,
+    values: Vec<oxidescript::parser::ast::Expression>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> TemplateLiteral<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let tail = strings.len() - 1;
+    let quasis = ast.vec_from_iter(strings.into_iter().enumerate().map(|(index, string)| {
+        let raw = string
+            .replace('\\', "\\\\")
+            .replace('`', "\\`")
+            .replace("${", "\\${")
+            .replace('\r', "\\r");
+        ast.template_element(
+            Span::new(0, 0),
+            index == tail,
+            TemplateElementValue {
+                raw: ast.atom(&raw),
+                cooked: Some(ast.atom(&string)),
+            },
+        )
+    }));
+    let expressions = ast.vec_from_iter(values.into_iter().map(|value| value.into_oxc(ctx)));
+    ast.template_literal(Span::new(0, 0), quasis, expressions)
+}
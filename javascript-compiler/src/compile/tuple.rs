@@ -0,0 +1,120 @@
+use oxc::{
+    ast::{
+        ast::{
+            BindingPattern, BindingRestElement, Expression, Statement, TSTypeAnnotation,
+            VariableDeclarationKind, VariableDeclarator,
+        },
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::ast::{Identifier, Pattern, TupleIndexExpr};
+
+use super::{optional::object, structs::property_key, types::annotated};
+use crate::{IntoOxc, JavascriptCompilerContext};
+
+/// `tuple[0]`, tuples are arrays
+impl<'c> IntoOxc<'c, Expression<'c>> for TupleIndexExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let ast = AstBuilder::new(ctx.allocator);
+        ast.member_expression_computed(
+            Span::new(0, 0),
+            object(*self.lhs, ctx),
+            ast.expression_numeric_literal(
+                Span::new(0, 0),
+                self.index as f64,
+                None,
+                oxc::syntax::number::NumberBase::Decimal,
+            ),
+            false,
+        )
+        .into()
+    }
+}
+
+/// `let [a, [, c]] = value;` for `let (a, (_, c)) = value;`, `let { x, y } = value;` for
+/// `let Point { x, y } = value;`, and only the value for `let _ = value;`
+pub fn destructuring<'c>(
+    pattern: Pattern,
+    value: oxidescript::parser::ast::Expression,
+    type_: Option<Identifier>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Statement<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    if pattern == Pattern::Wildcard {
+        return ast.statement_expression(Span::new(0, 0), value.into_oxc(ctx));
+    }
+    Statement::VariableDeclaration(ast.alloc(ast.variable_declaration(
+        Span::new(0, 0),
+        VariableDeclarationKind::Let,
+        ast.vec1(VariableDeclarator {
+            span: Span::new(0, 0),
+            kind: VariableDeclarationKind::Let,
+            id: annotated(
+                binding(pattern, ctx).expect("a destructuring binds the whole value"),
+                type_,
+                ctx,
+            ),
+            init: Some(value.into_oxc(ctx)),
+            definite: false,
+        }),
+        false,
+    )))
+}
+
+/// The binding of a pattern that matches every value, `None` for the holes `_` leaves in arrays
+fn binding<'c>(
+    pattern: Pattern,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<BindingPattern<'c>> {
+    let ast = AstBuilder::new(ctx.allocator);
+    match pattern {
+        Pattern::Binding(name) => Some(name.into_oxc(ctx)),
+        Pattern::Tuple(items) => {
+            let mut items = items
+                .into_iter()
+                .map(|item| binding(item, ctx))
+                .collect::<Vec<_>>();
+            // `[a]` instead of `[a, ,]`
+            while items.last().is_some_and(Option::is_none) {
+                items.pop();
+            }
+            Some(ast.binding_pattern(
+                ast.binding_pattern_kind_array_pattern(
+                    Span::new(0, 0),
+                    ast.vec_from_iter(items),
+                    None::<oxc::allocator::Box<BindingRestElement>>,
+                ),
+                None::<oxc::allocator::Box<TSTypeAnnotation>>,
+                false,
+            ))
+        }
+        // `{ x, y: [a, b] }`, fields matched by `_` are left out
+        Pattern::Struct(_, fields) => {
+            let properties = fields.into_iter().filter_map(|(name, field)| {
+                let value = binding(field, ctx)?;
+                Some(ast.binding_property(
+                    Span::new(0, 0),
+                    property_key(name, ctx),
+                    value,
+                    false,
+                    false,
+                ))
+            });
+            Some(ast.binding_pattern(
+                ast.binding_pattern_kind_object_pattern(
+                    Span::new(0, 0),
+                    ast.vec_from_iter(properties),
+                    None::<oxc::allocator::Box<BindingRestElement>>,
+                ),
+                None::<oxc::allocator::Box<TSTypeAnnotation>>,
+                false,
+            ))
+        }
+        // alternatives bind nothing
+        Pattern::Wildcard | Pattern::Or(_) => None,
+        Pattern::Literal(_) | Pattern::Prefix(..) | Pattern::Variant(..) => {
+            unreachable!("destructurings only have patterns that match every value")
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use oxc::{
+    ast::{
+        ast::{
+            BindingPattern, Expression, FunctionBody, FunctionType, Statement, TSLiteral,
+            TSMethodSignatureKind, TSSignature, TSThisParameter, TSType, TSTypeAnnotation,
+            TSTypeParameterDeclaration,
+        },
+        AstBuilder,
+    },
+    span::Span,
+};
+use oxidescript::parser::{
+    ast::{
+        CastExpr, Declaration, ExternDecl, Field, Identifier, ImplItem, Literal, MethodSignature,
+        Parameter, StructDecl, TypeParameter,
+    },
+    types::{impl_fn, trait_object, tuple_items, type_arguments, type_as_literal, union_members},
+};
+
+use crate::{IntoOxc, JavascriptCompilerContext, OutputLanguage, StructEmission};
+
+use super::structs::property_key;
+
+/// The annotation of a parameter, field or return type, only emitted when compiling to typescript
+pub fn type_annotation<'c>(
+    type_: &Identifier,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<oxc::allocator::Box<'c, TSTypeAnnotation<'c>>> {
+    if ctx.options.output_language != OutputLanguage::Typescript {
+        return None;
+    }
+    let ast = AstBuilder::new(ctx.allocator);
+    Some(ast.alloc_ts_type_annotation(Span::new(0, 0), ts_type(type_, ctx)))
+}
+
+/// The binding of `let name: type = value`, with the annotation in typescript output
+pub fn annotated<'c>(
+    mut binding: BindingPattern<'c>,
+    type_: Option<Identifier>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> BindingPattern<'c> {
+    binding.type_annotation = type_.and_then(|type_| type_annotation(&type_, ctx));
+    binding
+}
+
+/// `value as type` only exists in typescript output, javascript gets the value
+impl<'c> IntoOxc<'c, Expression<'c>> for CastExpr {
+    fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> Expression<'c> {
+        let value = self.value.into_oxc(ctx);
+        if ctx.options.output_language != OutputLanguage::Typescript {
+            return value;
+        }
+        AstBuilder::new(ctx.allocator).expression_ts_as(
+            Span::new(0, 0),
+            value,
+            ts_type(&self.type_, ctx),
+        )
+    }
+}
+
+/// Primitive types become keywords, literals literal types, `dyn Trait` and `impl Trait` the
+/// interface of the trait, `impl Fn(number) -> number` a function type, `[item]` an array of the
+/// item type, `(number, string)` the tuple type `[number, string]`, unions a union of their members and everything else a reference to the type of that
+/// name, with `Self` resolved to the type of the surrounding impl block and the type arguments of
+/// generic types
+fn ts_type<'c>(type_: &Identifier, ctx: &'c JavascriptCompilerContext<'c>) -> TSType<'c> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let members = union_members(&type_.0);
+    if members.len() > 1 {
+        return ast.ts_type_union_type(
+            Span::new(0, 0),
+            ast.vec_from_iter(
+                members
+                    .into_iter()
+                    .map(|member| ts_type(&Identifier(member.to_string()), ctx)),
+            ),
+        );
+    }
+    match type_.0.as_str() {
+        "number" => ast.ts_type_number_keyword(Span::new(0, 0)),
+        "string" => ast.ts_type_string_keyword(Span::new(0, 0)),
+        "boolean" => ast.ts_type_boolean_keyword(Span::new(0, 0)),
+        "any" => ast.ts_type_any_keyword(Span::new(0, 0)),
+        "null" => ast.ts_type_null_keyword(Span::new(0, 0)),
+        literal if type_as_literal(literal).is_some() => {
+            let literal = match type_as_literal(literal).unwrap() {
+                Literal::StringLiteral(string) => {
+                    ast.ts_literal_string_literal(Span::new(0, 0), string, None)
+                }
+                Literal::NumberLiteral(number) => {
+                    TSLiteral::NumericLiteral(ast.alloc(number.into_oxc(ctx)))
+                }
+                Literal::BooleanLiteral(value) => {
+                    ast.ts_literal_boolean_literal(Span::new(0, 0), value)
+                }
+            };
+            ast.ts_type_literal_type(Span::new(0, 0), literal)
+        }
+        "undefined" => ast.ts_type_undefined_keyword(Span::new(0, 0)),
+        // `(arg0: number) => number`
+        function if impl_fn(function).is_some() => {
+            let (parameters, returns) = impl_fn(function).unwrap();
+            let parameters = parameters
+                .into_iter()
+                .enumerate()
+                .map(|(index, type_)| Parameter {
+                    name: Identifier(format!("arg{}", index)),
+                    type_: Identifier(type_.to_string()),
+                    span: Default::default(),
+                })
+                .collect::<Vec<_>>();
+            let returns = match returns {
+                Some(returns) => ts_type(&Identifier(returns.to_string()), ctx),
+                None => ast.ts_type_void_keyword(Span::new(0, 0)),
+            };
+            ast.ts_type_function_type(
+                Span::new(0, 0),
+                None::<TSTypeParameterDeclaration>,
+                None::<TSThisParameter>,
+                ast.alloc(parameters.into_oxc(ctx)),
+                ast.alloc_ts_type_annotation(Span::new(0, 0), returns),
+            )
+        }
+        // the interface of the trait
+        object if trait_object(object).is_some() => {
+            ts_type(&Identifier(trait_object(object).unwrap().to_string()), ctx)
+        }
+        tuple if tuple_items(tuple).is_some() => ast.ts_type_tuple_type(
+            Span::new(0, 0),
+            ast.vec_from_iter(
+                tuple_items(tuple)
+                    .unwrap()
+                    .into_iter()
+                    .map(|item| ts_type(&Identifier(item.to_string()), ctx).into()),
+            ),
+        ),
+        array if array.starts_with('[') => {
+            let item = ts_type(&Identifier(array[1..array.len() - 1].to_string()), ctx);
+            // `(string | number)[]`, the codegen doesn't add the parentheses itself
+            let item = match item {
+                TSType::TSUnionType(_) => ast.ts_type_parenthesized_type(Span::new(0, 0), item),
+                item => item,
+            };
+            ast.ts_type_array_type(Span::new(0, 0), item)
+        }
+        _ => {
+            let (name, arguments) = type_arguments(&type_.0);
+            let name = ctx.resolve_self_type(Identifier(name.to_string()));
+            let declared = ctx.type_parameters.borrow().clone();
+            let structs = ctx.structs.borrow();
+            let parameters = |name: &Identifier| {
+                structs
+                    .get(&name.0)
+                    .map(|info| info.type_parameters.clone())
+                    .unwrap_or_default()
+            };
+            // outside of the interface and class of a generic struct, like in its static methods,
+            // typescript doesn't know its type parameters
+            if let Some(self_type) = &*ctx.self_type.borrow() {
+                if parameters(self_type).contains(&name) && !declared.contains(&name) {
+                    return ast.ts_type_any_keyword(Span::new(0, 0));
+                }
+            }
+            // a generic type without type arguments, like `Self`, is an instance with the type
+            // parameters that are declared here, or with `any`
+            let arguments = if arguments.is_empty() {
+                parameters(&name)
+                    .into_iter()
+                    .map(|parameter| {
+                        if declared.contains(&parameter) {
+                            parameter.0
+                        } else {
+                            "any".to_string()
+                        }
+                    })
+                    .collect()
+            } else {
+                arguments
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            };
+            drop(structs);
+            let arguments = (!arguments.is_empty()).then(|| {
+                ast.alloc_ts_type_parameter_instantiation(
+                    Span::new(0, 0),
+                    ast.vec_from_iter(
+                        arguments
+                            .into_iter()
+                            .map(|argument| ts_type(&Identifier(argument), ctx)),
+                    ),
+                )
+            });
+            ast.ts_type_type_reference(
+                Span::new(0, 0),
+                ast.ts_type_name_identifier_reference(Span::new(0, 0), name.0),
+                arguments,
+            )
+        }
+    }
+}
+
+/// `<A, B>` after the name of a generic struct or enum, only emitted when compiling to typescript
+pub fn type_parameters<'c>(
+    parameters: &[Identifier],
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<oxc::allocator::Box<'c, TSTypeParameterDeclaration<'c>>> {
+    let parameters = parameters
+        .iter()
+        .map(|name| TypeParameter {
+            name: name.clone(),
+            bounds: vec![],
+        })
+        .collect::<Vec<_>>();
+    bounded_type_parameters(&parameters, ctx)
+}
+
+/// `<T extends Ord & Display>` after the name of a generic function, with the interfaces of the
+/// traits of its bounds
+pub fn bounded_type_parameters<'c>(
+    parameters: &[TypeParameter],
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<oxc::allocator::Box<'c, TSTypeParameterDeclaration<'c>>> {
+    if parameters.is_empty() || ctx.options.output_language != OutputLanguage::Typescript {
+        return None;
+    }
+    let ast = AstBuilder::new(ctx.allocator);
+    Some(ast.alloc_ts_type_parameter_declaration(
+        Span::new(0, 0),
+        ast.vec_from_iter(parameters.iter().map(|parameter| {
+            let mut bounds = parameter
+                .bounds
+                .iter()
+                .map(|bound| ts_type(bound, ctx))
+                .collect::<Vec<_>>();
+            let constraint = match bounds.len() {
+                0 => None,
+                1 => bounds.pop(),
+                _ => {
+                    Some(ast.ts_type_intersection_type(Span::new(0, 0), ast.vec_from_iter(bounds)))
+                }
+            };
+            ast.ts_type_parameter(
+                Span::new(0, 0),
+                parameter.name.clone().into_oxc(ctx),
+                constraint,
+                None,
+                false,
+                false,
+                false,
+            )
+        })),
+    ))
+}
+
+/// `interface Name { field: type; }` in front of a struct or enum, which merges with the namespace object
+/// or class of the same name, with the type parameters of generic ones. Enums with discriminants get `type Name = 200 | 404;` instead, and
+/// flags enums `type Name = number;`, since their flags combine to other numbers. A `type` stays
+/// `type Name = { field: type; };`, and an alias `type Name = type;`. A trait becomes an interface
+/// of its methods, which the interfaces of structs in object mode include for the values they are
+/// attached to
+pub fn struct_interface<'c>(
+    statement: &oxidescript::parser::ast::Statement,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Statement<'c>> {
+    let oxidescript::parser::ast::Statement::DeclarationStatement(declaration, _) = statement
+    else {
+        return None;
+    };
+    if ctx.options.output_language != OutputLanguage::Typescript {
+        return None;
+    }
+    let lowered;
+    let StructDecl {
+        name,
+        type_parameters: parameters,
+        fields,
+        ..
+    } = match declaration {
+        Declaration::StructDeclaration(decl) => decl,
+        Declaration::EnumDeclaration(decl) => match decl.discriminants() {
+            Some(_) if decl.flags => {
+                let ast = AstBuilder::new(ctx.allocator);
+                return Some(Statement::from(ast.declaration_ts_type_alias(
+                    Span::new(0, 0),
+                    decl.name.clone().into_oxc(ctx),
+                    None::<oxc::allocator::Box<TSTypeParameterDeclaration>>,
+                    ast.ts_type_number_keyword(Span::new(0, 0)),
+                    false,
+                )));
+            }
+            Some(discriminants) => return discriminant_type(&decl.name, discriminants, ctx),
+            None => {
+                lowered = decl.lowered().0;
+                &lowered
+            }
+        },
+        Declaration::TypeDeclaration(decl) => {
+            let ast = AstBuilder::new(ctx.allocator);
+            let type_ = match &decl.alias {
+                Some(alias) => ts_type(alias, ctx),
+                None => ast
+                    .ts_type_type_literal(Span::new(0, 0), property_signatures(&decl.fields, ctx)),
+            };
+            return Some(Statement::from(ast.declaration_ts_type_alias(
+                Span::new(0, 0),
+                decl.name.clone().into_oxc(ctx),
+                None::<oxc::allocator::Box<TSTypeParameterDeclaration>>,
+                type_,
+                false,
+            )));
+        }
+        Declaration::TraitDeclaration(decl) => {
+            let ast = AstBuilder::new(ctx.allocator);
+            let body = ctx.with_self_type(decl.name.clone(), || {
+                ast.ts_interface_body(Span::new(0, 0), method_signatures(&decl.methods, ctx))
+            });
+            return Some(Statement::from(ast.declaration_ts_interface(
+                Span::new(0, 0),
+                decl.name.clone().into_oxc(ctx),
+                None,
+                None::<oxc::allocator::Box<TSTypeParameterDeclaration>>,
+                body,
+                false,
+            )));
+        }
+        _ => return None,
+    };
+    let ast = AstBuilder::new(ctx.allocator);
+    // classes declare the methods themselves
+    let methods = match ctx.options.struct_emission {
+        StructEmission::Objects => ctx
+            .structs
+            .borrow()
+            .get(&name.0)
+            .map(|info| {
+                info.attached
+                    .iter()
+                    .filter_map(|item| match item {
+                        ImplItem::Method(method) => Some(MethodSignature {
+                            name: method.name.clone(),
+                            has_self: true,
+                            parameters: method.parameters.clone(),
+                            return_type: method.return_type.clone(),
+                            default: None,
+                            span: method.span,
+                        }),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default(),
+        StructEmission::Classes => vec![],
+    };
+    let body = ctx.with_type_parameters(parameters.clone(), || {
+        let mut signatures = property_signatures(fields, ctx);
+        ctx.with_self_type(name.clone(), || {
+            signatures.extend(method_signatures(&methods, ctx))
+        });
+        ast.ts_interface_body(Span::new(0, 0), signatures)
+    });
+    Some(Statement::from(ast.declaration_ts_interface(
+        Span::new(0, 0),
+        name.clone().into_oxc(ctx),
+        None,
+        type_parameters(parameters, ctx),
+        body,
+        false,
+    )))
+}
+
+/// `field: type;` for every field
+fn property_signatures<'c>(
+    fields: &[Field],
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Vec<'c, TSSignature<'c>> {
+    let ast = AstBuilder::new(ctx.allocator);
+    ast.vec_from_iter(fields.iter().map(|field| {
+        ast.ts_signature_property_signature(
+            Span::new(0, 0),
+            false,
+            false,
+            false,
+            property_key(field.name.clone(), ctx),
+            type_annotation(&field.type_, ctx),
+        )
+    }))
+}
+
+/// `name(parameter: type): type;` for every method, without `self`
+fn method_signatures<'c>(
+    methods: &[MethodSignature],
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> oxc::allocator::Vec<'c, TSSignature<'c>> {
+    let ast = AstBuilder::new(ctx.allocator);
+    ast.vec_from_iter(methods.iter().map(|method| {
+        ast.ts_signature_method_signature(
+            Span::new(0, 0),
+            property_key(method.name.clone(), ctx),
+            false,
+            false,
+            TSMethodSignatureKind::Method,
+            None::<oxc::allocator::Box<TSTypeParameterDeclaration>>,
+            None::<oxc::allocator::Box<TSThisParameter>>,
+            ast.alloc(method.parameters.clone().into_oxc(ctx)),
+            method
+                .return_type
+                .as_ref()
+                .and_then(|return_type| type_annotation(return_type, ctx)),
+        )
+    }))
+}
+
+/// `declare function name(parameter: type): type;` for an extern in typescript, javascript calls
+/// the function of the environment without declaring it
+pub fn declare_function<'c>(
+    decl: ExternDecl,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Statement<'c>> {
+    if ctx.options.output_language != OutputLanguage::Typescript {
+        return None;
+    }
+    let ast = AstBuilder::new(ctx.allocator);
+    Some(Statement::FunctionDeclaration(
+        ast.alloc_function(
+            Span::new(0, 0),
+            FunctionType::TSDeclareFunction,
+            Some(decl.name.into_oxc(ctx)),
+            false,
+            false,
+            true,
+            None::<oxc::allocator::Box<TSTypeParameterDeclaration>>,
+            None::<oxc::allocator::Box<TSThisParameter>>,
+            ast.alloc(decl.parameters.into_oxc(ctx)),
+            decl.return_type
+                .and_then(|return_type| type_annotation(&return_type, ctx)),
+            None::<oxc::allocator::Box<FunctionBody>>,
+        ),
+    ))
+}
+
+/// The union of the discriminants of an enum, which are its values
+fn discriminant_type<'c>(
+    name: &Identifier,
+    discriminants: Vec<Literal>,
+    ctx: &'c JavascriptCompilerContext<'c>,
+) -> Option<Statement<'c>> {
+    let ast = AstBuilder::new(ctx.allocator);
+    let types = ast.vec_from_iter(discriminants.into_iter().map(|discriminant| {
+        let literal = match discriminant {
+            Literal::NumberLiteral(number) => {
+                TSLiteral::NumericLiteral(ast.alloc(number.into_oxc(ctx)))
+            }
+            Literal::StringLiteral(string) => {
+                ast.ts_literal_string_literal(Span::new(0, 0), string, None)
+            }
+            Literal::BooleanLiteral(_) => unreachable!("discriminants are numbers or strings"),
+        };
+        ast.ts_type_literal_type(Span::new(0, 0), literal)
+    }));
+    Some(Statement::from(ast.declaration_ts_type_alias(
+        Span::new(0, 0),
+        name.clone().into_oxc(ctx),
+        None::<oxc::allocator::Box<TSTypeParameterDeclaration>>,
+        ast.ts_type_union_type(Span::new(0, 0), types),
+        false,
+    )))
+}
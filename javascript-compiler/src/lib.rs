@@ -1,39 +1,1385 @@
-use oxc::allocator::Allocator;
-use oxc_codegen::Codegen;
-use oxidescript::compiler::Compiler;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use oxc::{allocator::Allocator, parser::Parser, span::SourceType};
+use oxc_codegen::{Codegen, CodegenOptions};
+use oxc_mangler::{MangleOptions, Mangler};
+use oxc_sourcemap::{ConcatSourceMapBuilder, SourceMap};
+use oxidescript::{
+    cancellation::{CancellationToken, Cancelled},
+    compiler::Compiler,
+    lexer::token::Trivia,
+    parser::{
+        ast::{
+            Block, CallExpr, Declaration, EnumDecl, Expression, Field, Identifier, ImplDecl,
+            ImplItem, MemberAccessExpr, Method, PathExpr, Statement, StructDecl,
+        },
+        dispose::lower_with,
+        enums::lower_discriminants,
+        types::{
+            lower_index_operators, lower_iterators, lower_method_calls, lower_method_calls_where,
+        },
+        visit::walk_program,
+    },
+    resolve::shadowed_prelude,
+};
 
 mod compile;
+mod peephole;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StructEmission {
+    /// Struct values are plain objects, impl blocks become an object of functions taking `self`
+    /// as their first parameter
+    #[default]
+    Objects,
+    /// Structs and their impl blocks are emitted as ES classes
+    Classes,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputLanguage {
+    #[default]
+    Javascript,
+    /// Keeps the type annotations of parameters and methods and declares an interface for every
+    /// struct, for gradually migrating to the generated code
+    Typescript,
+}
+
+/// Renames identifiers to short names after compiling. Which names are safe to rename comes from
+/// the scopes oxc resolves for the generated javascript, so a name is only ever replaced together
+/// with every reference to it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mangling {
+    #[default]
+    Off,
+    /// Everything except top level names, which other scripts and `export`s can refer to
+    Locals,
+    /// Top level names as well, for bundles that don't export anything
+    All,
+}
+
+#[derive(Clone, Debug)]
+pub struct JavascriptCompilerOptions {
+    pub struct_emission: StructEmission,
+    pub output_language: OutputLanguage,
+    pub mangling: Mangling,
+    /// Whether prelude items like `println` and `Vec::new` are in scope without being declared
+    pub prelude: bool,
+    /// Whether the generated javascript is cleaned up, see [`PEEPHOLE_PASS`]
+    pub peephole: bool,
+    /// Whether top level functions check that their arguments match the types of their parameters,
+    /// and calls of externs that the returned values match their return type, and throw a
+    /// `TypeError` if they don't, to catch values from other scripts early
+    pub type_guards: bool,
+    /// Whether slices like `items[1..3]` throw a `RangeError` if a bound is outside of the array or
+    /// string, instead of clamping it like `slice` does. On with the `debug` flag of `oxide.toml`
+    pub bounds_checks: bool,
+}
+
+/// Name of the pass that collapses trivial IIFEs, merges variable declarations and removes
+/// redundant blocks in the generated javascript
+pub const PEEPHOLE_PASS: &str = "peephole";
+
+/// Names of browsers and javascript runtimes that programs can use without declaring them
+pub const GLOBALS: &[&str] = &[
+    "globalThis",
+    "window",
+    "document",
+    "navigator",
+    "location",
+    "history",
+    "console",
+    "null",
+    "undefined",
+    "NaN",
+    "Infinity",
+    "Math",
+    "JSON",
+    "Intl",
+    "Reflect",
+    "Proxy",
+    "Object",
+    "Function",
+    "Array",
+    "String",
+    "Number",
+    "Boolean",
+    "BigInt",
+    "Symbol",
+    "Date",
+    "RegExp",
+    "Error",
+    "TypeError",
+    "RangeError",
+    "SyntaxError",
+    "Promise",
+    "Map",
+    "Set",
+    "WeakMap",
+    "WeakSet",
+    "ArrayBuffer",
+    "DataView",
+    "Int8Array",
+    "Uint8Array",
+    "Uint8ClampedArray",
+    "Int16Array",
+    "Uint16Array",
+    "Int32Array",
+    "Uint32Array",
+    "Float32Array",
+    "Float64Array",
+    "BigInt64Array",
+    "BigUint64Array",
+    "WebAssembly",
+    "parseInt",
+    "parseFloat",
+    "isNaN",
+    "isFinite",
+    "encodeURI",
+    "encodeURIComponent",
+    "decodeURI",
+    "decodeURIComponent",
+    "atob",
+    "btoa",
+    "setTimeout",
+    "clearTimeout",
+    "setInterval",
+    "clearInterval",
+    "queueMicrotask",
+    "requestAnimationFrame",
+    "cancelAnimationFrame",
+    "structuredClone",
+    "fetch",
+    "Request",
+    "Response",
+    "Headers",
+    "URL",
+    "URLSearchParams",
+    "TextEncoder",
+    "TextDecoder",
+    "Blob",
+    "File",
+    "FormData",
+    "AbortController",
+    "Event",
+    "EventTarget",
+    "CustomEvent",
+    "Worker",
+    "WebSocket",
+    "performance",
+    "crypto",
+    "localStorage",
+    "sessionStorage",
+    "alert",
+    "confirm",
+    "prompt",
+    "addEventListener",
+    "removeEventListener",
+    "postMessage",
+    "process",
+    "require",
+    "Bun",
+    "Deno",
+];
+
+impl Default for JavascriptCompilerOptions {
+    fn default() -> Self {
+        JavascriptCompilerOptions {
+            struct_emission: StructEmission::default(),
+            output_language: OutputLanguage::default(),
+            mangling: Mangling::default(),
+            prelude: true,
+            peephole: true,
+            type_guards: false,
+            bounds_checks: false,
+        }
+    }
+}
+
+/// The oxidescript file the spans of a program point into, for source maps
+pub struct SourceFile<'a> {
+    /// How the source map refers to the file, relative to the map
+    pub path: &'a Path,
+    pub text: &'a str,
+}
+
+/// Compiled javascript, with a source map back to the oxidescript file it was compiled from if it
+/// was compiled with `compile_with_source_map`
+pub struct Compiled {
+    pub code: String,
+    source_map: Option<SourceMap>,
+}
+
+impl Compiled {
+    /// Puts `prefix` in front of the code and moves the mappings down by its lines. `prefix` has
+    /// to end with a newline
+    pub fn prepend(&mut self, prefix: &str) {
+        let lines = prefix.matches('\n').count() as u32;
+        self.code.insert_str(0, prefix);
+        if let Some(source_map) = &mut self.source_map {
+            *source_map =
+                ConcatSourceMapBuilder::from_sourcemaps(&[(source_map, lines)]).into_sourcemap();
+        }
+    }
+
+    /// The source map as json
+    pub fn source_map(&self) -> Option<String> {
+        self.source_map.as_ref().map(SourceMap::to_json_string)
+    }
+}
+
+impl From<String> for Compiled {
+    fn from(code: String) -> Self {
+        Compiled {
+            code,
+            source_map: None,
+        }
+    }
+}
 
 pub struct JavascriptCompiler {
-    allocator: Allocator,
+    /// Reset at the start of every compilation instead of being recreated, so long running
+    /// processes like watch mode keep reusing the memory of the arena
+    allocator: RefCell<Allocator>,
+    options: JavascriptCompilerOptions,
 }
 
-impl Compiler for JavascriptCompiler {
-    fn new() -> Self {
+impl JavascriptCompiler {
+    pub fn with_options(options: JavascriptCompilerOptions) -> Self {
         JavascriptCompiler {
-            allocator: Allocator::default(),
+            allocator: RefCell::new(Allocator::default()),
+            options,
         }
     }
 
+    /// Changes the options for following compilations, keeping the allocated memory around
+    pub fn set_options(&mut self, options: JavascriptCompilerOptions) {
+        self.options = options;
+    }
+}
+
+impl Compiler for JavascriptCompiler {
+    fn new() -> Self {
+        Self::with_options(JavascriptCompilerOptions::default())
+    }
+
     fn compile(&self, program: oxidescript::parser::ast::Program) -> String {
-        let ctx = JavascriptCompilerContext::new(&self.allocator);
-        let compiled_ast = program.into_oxc(&ctx);
-        let code_gen = Codegen::new();
+        self.compile_with(program, None, None)
+            .expect("compilation without cancellation token was cancelled")
+            .0
+    }
+
+    /// Checks for cancellation between top level statements and skips codegen once cancelled
+    fn compile_cancellable(
+        &self,
+        program: oxidescript::parser::ast::Program,
+        cancellation: &CancellationToken,
+    ) -> Result<String, Cancelled> {
+        self.compile_with(program, Some(cancellation), None)
+            .map(|(code, _)| code)
+    }
+}
+
+impl JavascriptCompiler {
+    /// Like `compile`, with a source map that maps the javascript of every statement and
+    /// expression back to its span in `source`
+    pub fn compile_with_source_map(
+        &self,
+        program: oxidescript::parser::ast::Program,
+        source: &SourceFile,
+    ) -> Compiled {
+        let (code, source_map) = self
+            .compile_with(program, None, Some(source))
+            .expect("compilation without cancellation token was cancelled");
+        Compiled { code, source_map }
+    }
+
+    fn compile_with(
+        &self,
+        program: oxidescript::parser::ast::Program,
+        cancellation: Option<&CancellationToken>,
+        source: Option<&SourceFile>,
+    ) -> Result<(String, Option<SourceMap>), Cancelled> {
+        let mut allocator = self.allocator.borrow_mut();
+        allocator.reset();
+        let ctx = JavascriptCompilerContext::new(&allocator, &self.options, cancellation);
+        let program = lower_discriminants(
+            ctx.lower_method_calls(lower_iterators(lower_index_operators(lower_with(program)))),
+        );
+        ctx.register_modules(&program);
+        ctx.register_structs(&program);
+        ctx.shadowed_prelude.replace(shadowed_prelude(&program));
+        let statements = program
+            .into_iter()
+            .filter_map(|statement| ctx.collect_impl(statement))
+            .collect::<Vec<_>>();
+        let mut compiled_ast = statements.into_oxc(&ctx);
+        if let Some(source) = source {
+            compiled_ast.source_text = allocator.alloc_str(source.text);
+        }
+        if ctx.is_cancelled() {
+            return Err(Cancelled);
+        }
+        if self.options.peephole {
+            peephole::optimize(&mut compiled_ast, &allocator);
+        }
+        let mangler = match self.options.mangling {
+            Mangling::Off => None,
+            Mangling::Locals | Mangling::All => Some(
+                Mangler::new()
+                    .with_options(MangleOptions {
+                        top_level: self.options.mangling == Mangling::All,
+                        debug: false,
+                    })
+                    .build(&compiled_ast),
+            ),
+        };
+        let code_gen = Codegen::new()
+            .with_options(CodegenOptions {
+                source_map_path: source.map(|source| source.path.to_path_buf()),
+                ..CodegenOptions::default()
+            })
+            .with_mangler(mangler);
         let code = code_gen.build(&compiled_ast);
-        code.code
+        Ok((code.code, code.map))
+    }
+
+    /// Compiles every top level statement on its own and keeps the comments and blank lines
+    /// between them, for generated code that is committed and reviewed. `trivia[i]` is in front of
+    /// statement `i`, the one after the last statement is the end of the file.
+    ///
+    /// Comments inside of statements are dropped, and impl blocks are still emitted together with
+    /// their struct. Names are never mangled, since every statement is generated on its own
+    pub fn compile_readable(
+        &self,
+        program: oxidescript::parser::ast::Program,
+        trivia: &[Vec<Trivia>],
+    ) -> String {
+        let mut allocator = self.allocator.borrow_mut();
+        allocator.reset();
+        let ctx = JavascriptCompilerContext::new(&allocator, &self.options, None);
+        let program = lower_discriminants(
+            ctx.lower_method_calls(lower_iterators(lower_index_operators(lower_with(program)))),
+        );
+        ctx.register_structs(&program);
+        ctx.register_modules(&program);
+        ctx.shadowed_prelude.replace(shadowed_prelude(&program));
+        // every impl block is collected before the struct it belongs to is compiled
+        let statements = program
+            .into_iter()
+            .map(|statement| ctx.collect_impl(statement))
+            .collect::<Vec<_>>();
+        let mut code = String::new();
+        let mut trivia = trivia.iter();
+        // trailing comments of impl blocks that moved into their struct get their own line
+        let mut previous_emitted = false;
+        for statement in statements {
+            push_trivia(
+                &mut code,
+                trivia.next().into_iter().flatten(),
+                previous_emitted,
+            );
+            previous_emitted = statement.is_some();
+            let Some(statement) = statement else {
+                continue;
+            };
+            let body = compile::top_level(statement, &ctx);
+            let mut program = compile::program(body, &ctx);
+            if self.options.peephole {
+                peephole::optimize(&mut program, &allocator);
+            }
+            code.push_str(&Codegen::new().build(&program).code);
+        }
+        push_trivia(&mut code, trivia.flatten(), previous_emitted);
+        code
+    }
+}
+
+impl JavascriptCompiler {
+    /// Like `compile`, for a program whose top level statements are split into parts: `parts[i]`
+    /// is the number of statements in part `i`. The whole program is lowered and its declarations
+    /// are registered, but every part is generated and mangled on its own, and only if
+    /// `compile_part` returns true for it, so code that didn't change can be kept by the caller.
+    /// Joined, the parts are the program, except that declarations aren't merged across parts
+    pub fn compile_parts(
+        &self,
+        program: oxidescript::parser::ast::Program,
+        parts: &[usize],
+        compile_part: impl Fn(usize) -> bool,
+    ) -> Vec<Option<String>> {
+        let mut allocator = self.allocator.borrow_mut();
+        allocator.reset();
+        let ctx = JavascriptCompilerContext::new(&allocator, &self.options, None);
+        let program = lower_discriminants(
+            ctx.lower_method_calls(lower_iterators(lower_index_operators(lower_with(program)))),
+        );
+        ctx.register_modules(&program);
+        ctx.register_structs(&program);
+        ctx.shadowed_prelude.replace(shadowed_prelude(&program));
+        // every impl block is collected before the struct it belongs to is compiled
+        let statements = program
+            .into_iter()
+            .map(|statement| ctx.collect_impl(statement))
+            .collect::<Vec<_>>();
+        let mut statements = statements.into_iter();
+        parts
+            .iter()
+            .enumerate()
+            .map(|(index, length)| {
+                let part = statements
+                    .by_ref()
+                    .take(*length)
+                    .flatten()
+                    .collect::<Vec<_>>();
+                if !compile_part(index) {
+                    return None;
+                }
+                let body = part
+                    .into_iter()
+                    .flat_map(|statement| compile::top_level(statement, &ctx))
+                    .collect::<Vec<_>>();
+                let mut program = compile::program(body, &ctx);
+                if self.options.peephole {
+                    peephole::optimize(&mut program, &allocator);
+                }
+                let mangler = match self.options.mangling {
+                    Mangling::Off => None,
+                    Mangling::Locals | Mangling::All => Some(
+                        Mangler::new()
+                            .with_options(MangleOptions {
+                                top_level: self.options.mangling == Mangling::All,
+                                debug: false,
+                            })
+                            .build(&program),
+                    ),
+                };
+                Some(Codegen::new().with_mangler(mangler).build(&program).code)
+            })
+            .collect()
     }
 }
 
+// a server compiles several documents at once with one compiler per thread, on programs that
+// were parsed on another thread
+const _: fn() = || {
+    fn send<T: Send>() {}
+    fn send_sync<T: Send + Sync>() {}
+    send::<JavascriptCompiler>();
+    send_sync::<oxidescript::parser::ast::Program>();
+    send_sync::<oxidescript::lexer::token::Token>();
+};
+
+/// Parses generated code again, to catch code generation bugs before the output is run. The errors
+/// are formatted as `line:column: message`
+pub fn check(code: &str, output_language: OutputLanguage) -> Result<(), String> {
+    let allocator = Allocator::default();
+    // files without imports and exports can be run as scripts
+    let source_type =
+        SourceType::unambiguous().with_typescript(output_language == OutputLanguage::Typescript);
+    let parsed = Parser::new(&allocator, code, source_type).parse();
+    if parsed.errors.is_empty() {
+        return Ok(());
+    }
+    let errors = parsed.errors.iter().map(|error| {
+        let offset = error
+            .labels
+            .iter()
+            .flatten()
+            .next()
+            .map_or(0, |label| label.offset());
+        let line = code[..offset].matches('\n').count() + 1;
+        let column = offset - code[..offset].rfind('\n').map_or(0, |index| index + 1) + 1;
+        format!("{}:{}: {}", line, column, error.message)
+    });
+    Err(errors.collect::<Vec<_>>().join("\n"))
+}
+
+fn push_trivia<'a>(
+    code: &mut String,
+    trivia: impl IntoIterator<Item = &'a Trivia<'a>>,
+    after_statement: bool,
+) {
+    for trivia in trivia {
+        match trivia {
+            Trivia::BlankLine if !code.is_empty() => code.push('\n'),
+            Trivia::BlankLine => {}
+            Trivia::Comment { text, trailing } => {
+                if *trailing && after_statement && code.ends_with('\n') {
+                    code.pop();
+                    code.push(' ');
+                }
+                code.push_str(text);
+                code.push('\n');
+            }
+        }
+    }
+}
+
+struct StructInfo {
+    fields: Vec<Field>,
+    /// The type parameters of a generic struct or enum
+    type_parameters: Vec<Identifier>,
+    /// Items of all impl blocks for this struct, emitted together with the struct declaration
+    items: Vec<ImplItem>,
+    /// Getters, setters and the methods of traits, which in object mode are attached to every
+    /// struct value
+    attached: Vec<ImplItem>,
+    /// Names of the methods taking `self`, which classes have on their prototype
+    receivers: HashSet<String>,
+}
+
 struct JavascriptCompilerContext<'a> {
     allocator: &'a Allocator,
+    options: &'a JavascriptCompilerOptions,
+    structs: RefCell<HashMap<String, StructInfo>>,
+    /// Every `type`, which only exists to check values against
+    types: RefCell<HashMap<String, oxidescript::parser::ast::TypeDecl>>,
+    /// Every trait, whose default methods are inherited by the impls that don't override them
+    traits: RefCell<HashMap<String, oxidescript::parser::ast::TraitDecl>>,
+    /// The return types of the externs, which calls of them are checked against with
+    /// `type_guards`
+    externs: RefCell<HashMap<String, Identifier>>,
+    /// Names of the top level `mod name;` declarations, which `use` imports from
+    file_modules: RefCell<HashSet<String>>,
+    /// Names the program refers to as values, other imported names are only used as types
+    value_names: RefCell<HashSet<String>>,
+    /// Set while compiling a method body in which `self` refers to `this`
+    self_is_this: Cell<bool>,
+    /// The type `Self` refers to while compiling the items of an impl block
+    self_type: RefCell<Option<Identifier>>,
+    /// The type parameters the typescript output declares around the code being compiled, which
+    /// are the ones of a generic struct inside of its interface and class
+    type_parameters: RefCell<Vec<Identifier>>,
+    /// Set while compiling the object of a call, member access or index, which continues an
+    /// optional chain instead of ending it
+    continues_chain: Cell<bool>,
+    /// How `return`, `break` and `continue` leave the code being compiled
+    jumps: Cell<compile::jump::Jumps>,
+    /// Prelude functions the program declares itself, which are called instead of compiled
+    shadowed_prelude: RefCell<HashSet<String>>,
+    cancellation: Option<&'a CancellationToken>,
 }
 
 impl<'ctx> JavascriptCompilerContext<'ctx> {
-    fn new(allocator: &'ctx Allocator) -> Self {
-        JavascriptCompilerContext { allocator }
+    fn new(
+        allocator: &'ctx Allocator,
+        options: &'ctx JavascriptCompilerOptions,
+        cancellation: Option<&'ctx CancellationToken>,
+    ) -> Self {
+        JavascriptCompilerContext {
+            allocator,
+            options,
+            structs: RefCell::new(HashMap::new()),
+            types: RefCell::new(HashMap::new()),
+            traits: RefCell::new(HashMap::new()),
+            externs: RefCell::new(HashMap::new()),
+            file_modules: RefCell::new(HashSet::new()),
+            value_names: RefCell::new(HashSet::new()),
+            self_is_this: Cell::new(false),
+            self_type: RefCell::new(None),
+            type_parameters: RefCell::new(vec![]),
+            continues_chain: Cell::new(false),
+            jumps: Cell::new(compile::jump::Jumps::default()),
+            shadowed_prelude: RefCell::new(HashSet::new()),
+            cancellation,
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .is_some_and(|cancellation| cancellation.is_cancelled())
+    }
+
+    /// Whether prelude functions are intrinsics in a call of `lhs`, which they aren't if the
+    /// program declares its own function or binding with the name
+    fn prelude_in(&self, lhs: &Expression) -> bool {
+        self.options.prelude
+            && !matches!(lhs, Expression::IdentifierExpression(ident, _)
+                if self.shadowed_prelude.borrow().contains(&ident.0))
+    }
+
+    fn with_self_type<T>(&self, self_type: Identifier, f: impl FnOnce() -> T) -> T {
+        let outer = self.self_type.replace(Some(self_type));
+        let result = f();
+        self.self_type.replace(outer);
+        result
+    }
+
+    fn with_type_parameters<T>(
+        &self,
+        type_parameters: Vec<Identifier>,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        let outer = self.type_parameters.replace(type_parameters);
+        let result = f();
+        self.type_parameters.replace(outer);
+        result
+    }
+
+    /// Replaces `Self` with the type of the surrounding impl block
+    fn resolve_self_type(&self, ident: Identifier) -> Identifier {
+        match &*self.self_type.borrow() {
+            Some(self_type) if ident.0 == "Self" => self_type.clone(),
+            _ => ident,
+        }
+    }
+
+    /// Struct values of the object emission are plain objects without the methods of their impl
+    /// blocks, so methods are called on the namespace object of the type
+    fn lower_method_calls(
+        &self,
+        program: oxidescript::parser::ast::Program,
+    ) -> oxidescript::parser::ast::Program {
+        match self.options.struct_emission {
+            StructEmission::Objects => lower_method_calls(program),
+            // instances have the methods, but calls of `clone` methods are lowered to tell them
+            // apart from the clone intrinsic
+            StructEmission::Classes => {
+                lower_method_calls_where(program, |method| method.0 == "clone")
+            }
+        }
+    }
+
+    /// Whether `Type::method` is a method taking `self`, which classes only have on their
+    /// prototype
+    fn is_prototype_method(&self, path: &PathExpr) -> bool {
+        let [type_, method] = &path.segments[..] else {
+            return false;
+        };
+        if self.options.struct_emission != StructEmission::Classes {
+            return false;
+        }
+        let type_ = self.resolve_self_type(type_.clone());
+        self.structs
+            .borrow()
+            .get(&type_.0)
+            .is_some_and(|info| info.receivers.contains(&method.0))
+    }
+
+    /// Whether the struct of `Type::method` has a field named like the method, which class
+    /// instances have as their own property in place of the method on the prototype
+    fn is_shadowed_method(&self, path: &PathExpr) -> bool {
+        let [type_, method] = &path.segments[..] else {
+            return false;
+        };
+        let type_ = self.resolve_self_type(type_.clone());
+        self.structs
+            .borrow()
+            .get(&type_.0)
+            .is_some_and(|info| info.fields.iter().any(|field| field.name == *method))
+    }
+
+    /// Registers all top level structs and moves the impl blocks belonging to them out of the
+    /// program, so they can be emitted in one place
+    fn collect_structs(
+        &self,
+        program: oxidescript::parser::ast::Program,
+    ) -> oxidescript::parser::ast::Program {
+        self.register_structs(&program);
+        program
+            .into_iter()
+            .filter_map(|statement| self.collect_impl(statement))
+            .collect()
+    }
+
+    fn register_structs(&self, program: &oxidescript::parser::ast::Program) {
+        let mut structs = self.structs.borrow_mut();
+        for statement in program.iter() {
+            match statement {
+                Statement::DeclarationStatement(Declaration::StructDeclaration(decl), _) => {
+                    structs.insert(
+                        decl.name.0.clone(),
+                        StructInfo {
+                            fields: decl.fields.clone(),
+                            type_parameters: decl.type_parameters.clone(),
+                            items: vec![],
+                            attached: vec![],
+                            receivers: HashSet::new(),
+                        },
+                    );
+                }
+                Statement::DeclarationStatement(Declaration::EnumDeclaration(decl), _) => {
+                    let (lowered, constructors) = decl.lowered();
+                    structs.insert(
+                        decl.name.0.clone(),
+                        StructInfo {
+                            fields: lowered.fields,
+                            type_parameters: lowered.type_parameters,
+                            items: constructors,
+                            attached: vec![],
+                            receivers: HashSet::new(),
+                        },
+                    );
+                }
+                Statement::DeclarationStatement(Declaration::TypeDeclaration(decl), _) => {
+                    self.types
+                        .borrow_mut()
+                        .insert(decl.name.0.clone(), decl.clone());
+                }
+                Statement::DeclarationStatement(Declaration::TraitDeclaration(decl), _) => {
+                    self.register_trait(decl);
+                }
+                Statement::DeclarationStatement(Declaration::ExternDeclaration(decl), _) => {
+                    if let Some(return_type) = &decl.return_type {
+                        self.externs
+                            .borrow_mut()
+                            .insert(decl.name.0.clone(), return_type.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Registers the top level `mod name;` declarations and the names used as values, which
+    /// decide what `use` declarations import
+    fn register_modules(&self, program: &oxidescript::parser::ast::Program) {
+        let mut file_modules = self.file_modules.borrow_mut();
+        file_modules.extend(program.iter().filter_map(|statement| match statement {
+            Statement::DeclarationStatement(Declaration::ModFileDeclaration(name), _) => {
+                Some(name.0.clone())
+            }
+            _ => None,
+        }));
+        if file_modules.is_empty() {
+            return;
+        }
+        let mut value_names = self.value_names.borrow_mut();
+        walk_program(program, &mut |expression| match expression {
+            Expression::IdentifierExpression(ident, _) => {
+                value_names.insert(ident.0.clone());
+            }
+            Expression::PathExpression(path, _) => {
+                value_names.insert(path.segments[0].0.clone());
+            }
+            _ => {}
+        });
+    }
+
+    /// The struct an enum is emitted as. Enums declared in blocks aren't registered up front, so
+    /// their constructors are registered here
+    fn register_enum(&self, decl: &EnumDecl) -> StructDecl {
+        let (lowered, constructors) = decl.lowered();
+        self.structs
+            .borrow_mut()
+            .entry(decl.name.0.clone())
+            .or_insert_with(|| StructInfo {
+                fields: lowered.fields.clone(),
+                type_parameters: lowered.type_parameters.clone(),
+                items: constructors,
+                attached: vec![],
+                receivers: HashSet::new(),
+            });
+        lowered
+    }
+
+    /// Traits declared in blocks and modules aren't registered up front, so they are registered
+    /// when they are compiled
+    fn register_trait(&self, decl: &oxidescript::parser::ast::TraitDecl) {
+        self.traits
+            .borrow_mut()
+            .entry(decl.name.0.clone())
+            .or_insert_with(|| decl.clone());
+    }
+
+    /// Adds a method for every default of the trait the impl doesn't override, which calls the
+    /// shared function of the default as `Trait.method(self, ...)`
+    fn inherit_defaults(&self, decl: &mut ImplDecl) {
+        let Some(trait_) = &decl.trait_ else {
+            return;
+        };
+        let traits = self.traits.borrow();
+        let Some(trait_decl) = traits.get(&trait_.0) else {
+            return;
+        };
+        let span = oxidescript::parser::ast::Span::default();
+        let inherited = trait_decl
+            .inherited(decl)
+            .into_iter()
+            .map(|signature| {
+                let arguments = signature
+                    .has_self
+                    .then(|| Identifier("self".to_string()))
+                    .into_iter()
+                    .chain(
+                        signature
+                            .parameters
+                            .iter()
+                            .map(|parameter| parameter.name.clone()),
+                    )
+                    .map(|name| Expression::IdentifierExpression(name, span))
+                    .collect();
+                let function = Expression::MemberAccessExpression(
+                    MemberAccessExpr {
+                        lhs: Box::new(Expression::IdentifierExpression(trait_.clone(), span)),
+                        ident: signature.name.clone(),
+                    },
+                    span,
+                );
+                ImplItem::Method(Method {
+                    name: signature.name.clone(),
+                    has_self: signature.has_self,
+                    is_async: false,
+                    parameters: signature.parameters.clone(),
+                    return_type: signature.return_type.clone(),
+                    body: Block {
+                        statements: vec![],
+                        return_value: Some(Expression::CallExpression(
+                            CallExpr {
+                                lhs: Box::new(function),
+                                arguments,
+                            },
+                            span,
+                        )),
+                        span,
+                    },
+                    span: signature.span,
+                })
+            })
+            .collect::<Vec<_>>();
+        decl.items.extend(inherited);
+    }
+
+    /// Moves the items of an impl block for a registered struct into its `StructInfo`
+    fn collect_impl(&self, statement: Statement) -> Option<Statement> {
+        match statement {
+            Statement::DeclarationStatement(Declaration::ImplDeclaration(mut decl), _)
+                if self.structs.borrow().contains_key(&decl.target.0) =>
+            {
+                self.inherit_defaults(&mut decl);
+                let mut structs = self.structs.borrow_mut();
+                let info = structs.get_mut(&decl.target.0).unwrap();
+                // generic functions call the methods of traits on the values they are passed
+                let is_trait = decl.trait_.is_some();
+                info.attached.extend(
+                    decl.items
+                        .iter()
+                        .filter(|item| match item {
+                            ImplItem::Getter(_) | ImplItem::Setter(_) => true,
+                            ImplItem::Method(method) => is_trait && method.has_self,
+                            ImplItem::Const(..) => false,
+                        })
+                        .cloned(),
+                );
+                info.receivers
+                    .extend(decl.items.iter().filter_map(|item| match item {
+                        ImplItem::Method(method) if method.has_self => Some(method.name.0.clone()),
+                        _ => None,
+                    }));
+                info.items.extend(decl.items);
+                None
+            }
+            statement => Some(statement),
+        }
     }
 }
 
 trait IntoOxc<'c, T> {
     fn into_oxc(self, ctx: &'c JavascriptCompilerContext<'c>) -> T;
 }
+
+#[cfg(test)]
+mod tests {
+    use oxidescript::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+        stack::with_stack,
+    };
+
+    use super::*;
+
+    fn compile(input: &str, options: JavascriptCompilerOptions) -> String {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        JavascriptCompiler::with_options(options).compile(program)
+    }
+
+    fn typescript() -> JavascriptCompilerOptions {
+        JavascriptCompilerOptions {
+            output_language: OutputLanguage::Typescript,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn let_annotations() {
+        let input = r#"let total: number = 0;
+            const NAMES: [string] = [];
+            let (a, b): (number, string) = (1, "b");"#;
+        assert_eq!(
+            compile(input, typescript()),
+            "let total: number = 0;
+const NAMES: string[] = [];
+let [a, b]: [number, string] = [1, \"b\"];
+"
+        );
+        assert_eq!(
+            compile(input, JavascriptCompilerOptions::default()),
+            "let total = 0;
+const NAMES = [];
+let [a, b] = [1, \"b\"];
+"
+        );
+    }
+
+    #[test]
+    fn struct_emission() {
+        let input = "struct Point { x: number, y: number }
+            impl Point {
+                fn new(x: number, y: number) -> Self { Point { x: x, y: y } }
+                fn sum(self) -> number { self.x + self.y }
+            }
+            let p = Point::new(1, 2);
+            println(p.sum());";
+        // methods are functions of an object named like the struct, taking the value as `self`
+        assert_eq!(
+            compile(input, JavascriptCompilerOptions::default()),
+            "const Point = {
+\tnew(x, y) {
+\t\treturn {
+\t\t\tx,
+\t\t\ty
+\t\t};
+\t},
+\tsum(self) {
+\t\treturn self.x + self.y;
+\t}
+};
+let p = Point.new(1, 2);
+console.log(Point.sum(p));
+"
+        );
+        assert_eq!(
+            compile(
+                input,
+                JavascriptCompilerOptions {
+                    struct_emission: StructEmission::Classes,
+                    ..Default::default()
+                }
+            ),
+            "class Point {
+\tconstructor(x, y) {
+\t\tthis.x = x;
+\t\tthis.y = y;
+\t}
+\tstatic new(x, y) {
+\t\treturn new Point(x, y);
+\t}
+\tsum() {
+\t\treturn this.x + this.y;
+\t}
+}
+let p = Point.new(1, 2);
+console.log(p.sum());
+"
+        );
+    }
+
+    #[test]
+    fn typescript_output() {
+        assert_eq!(
+            compile(
+                "struct Point { x: number, y: number }
+                fn len(p: Point, scale: number) -> number { (p.x + p.y) * scale }
+                let items: [string] = [];",
+                typescript()
+            ),
+            "interface Point {
+\tx: number;
+\ty: number;
+}
+function len(p: Point, scale: number): number {
+\treturn (p.x + p.y) * scale;
+}
+let items: string[] = [];
+"
+        );
+    }
+
+    #[test]
+    fn clone_intrinsic() {
+        assert_eq!(
+            compile(
+                "let grid = [[1], [2]];
+                let copy = clone(grid);",
+                JavascriptCompilerOptions::default()
+            ),
+            "let grid = [[1], [2]], copy = function clone(value) {
+\treturn typeof value !== \"object\" || value === null ? value : Array.isArray(value) ? value.map(clone) : value instanceof Map || value instanceof Set ? new value.constructor(Array.from(value, clone)) : Object.assign(Object.create(Object.getPrototypeOf(value)), Object.fromEntries(Object.entries(value).map(clone)));
+}(grid);
+"
+        );
+        // methods named `clone` are called instead, and classes call them on the instance
+        let program = "struct Counter { n: number }
+            impl Counter {
+                fn clone(self) -> Counter { Counter { n: 0 } }
+            }
+            let counter = Counter { n: 1 };
+            let copy = counter.clone();";
+        assert_eq!(
+            compile(program, JavascriptCompilerOptions::default()),
+            "const Counter = { clone(self) {
+\treturn { n: 0 };
+} };
+let counter = { n: 1 }, copy = Counter.clone(counter);
+"
+        );
+        let classes = JavascriptCompilerOptions {
+            struct_emission: StructEmission::Classes,
+            ..JavascriptCompilerOptions::default()
+        };
+        assert!(compile(program, classes).ends_with("copy = counter.clone();\n"));
+    }
+
+    #[test]
+    fn accessors() {
+        assert_eq!(
+            compile(
+                "struct Circle { r: number }
+                impl Circle {
+                    get area() -> number { 3 * self.r * self.r }
+                    set radius(value: number) { self.r = value; }
+                }
+                let c = Circle { r: 1 };
+                c.radius = 2;
+                println(c.area);",
+                JavascriptCompilerOptions::default()
+            ),
+            "let c = {
+\tr: 1,
+\tget area() {
+\t\treturn 3 * this.r * this.r;
+\t},
+\tset radius(value) {
+\t\tthis.r = value;
+\t}
+};
+c.radius = 2;
+console.log(c.area);
+"
+        );
+    }
+
+    #[test]
+    fn associated_items() {
+        assert_eq!(
+            compile(
+                "struct Circle { r: number }
+                impl Circle {
+                    const PI = 3.14159;
+                    fn new(r: number) -> Circle { Circle { r: r } }
+                    fn area(self) -> number { Circle::PI * self.r * self.r }
+                }
+                println(Circle::new(2).area());",
+                JavascriptCompilerOptions::default()
+            ),
+            "const Circle = {
+\tPI: 3.14159,
+\tnew(r) {
+\t\treturn { r };
+\t},
+\tarea(self) {
+\t\treturn Circle.PI * self.r * self.r;
+\t}
+};
+console.log(Circle.area(Circle.new(2)));
+"
+        );
+    }
+
+    #[test]
+    fn mangling() {
+        let input = "fn total(values: [number]) -> number {
+                let sum = 0;
+                for value in values { sum += value; }
+                sum
+            }
+            println(total([1, 2]));";
+        let mangled = |mangling| {
+            compile(
+                input,
+                JavascriptCompilerOptions {
+                    mangling,
+                    ..Default::default()
+                },
+            )
+        };
+        // top level names stay unless the bundle doesn't export anything
+        assert_eq!(
+            mangled(Mangling::Locals),
+            "function total(b) {
+\tlet c = 0;
+\tfor (const d of b) {
+\t\tc += d;
+\t}
+\treturn c;
+}
+console.log(total([1, 2]));
+"
+        );
+        assert_eq!(
+            mangled(Mangling::All),
+            "function a(b) {
+\tlet c = 0;
+\tfor (const d of b) {
+\t\tc += d;
+\t}
+\treturn c;
+}
+console.log(a([1, 2]));
+"
+        );
+    }
+
+    #[test]
+    fn peephole() {
+        let input = "let a = 1;
+            let b = { let c = a + 1; c * 2 };
+            { println(b); }";
+        // the declarations are merged and the block around the statement is removed
+        assert_eq!(
+            compile(input, JavascriptCompilerOptions::default()),
+            "let a = 1, b = (() => {
+\tlet c = a + 1;
+\treturn c * 2;
+})();
+console.log(b);
+"
+        );
+        assert_eq!(
+            compile(
+                input,
+                JavascriptCompilerOptions {
+                    peephole: false,
+                    ..Default::default()
+                }
+            ),
+            "let a = 1;
+let b = (() => {
+\tlet c = a + 1;
+\treturn c * 2;
+})();
+{
+\tconsole.log(b);
+}
+"
+        );
+    }
+
+    #[test]
+    fn self_check() {
+        assert_eq!(check("let a = 1;\n", OutputLanguage::Javascript), Ok(()));
+        assert_eq!(
+            check("let a: number = 1;\n", OutputLanguage::Typescript),
+            Ok(())
+        );
+        assert_eq!(
+            check("let a = 1;\nlet b = \"x;\n", OutputLanguage::Javascript),
+            Err("2:9: Unterminated string".to_string())
+        );
+    }
+
+    #[test]
+    fn type_guards() {
+        assert_eq!(
+            compile(
+                "struct Point { x: number, y: number }
+                fn area(width: number, p: Point, label: string | null) -> number { width * p.x }",
+                JavascriptCompilerOptions {
+                    type_guards: true,
+                    ..Default::default()
+                }
+            ),
+            "function area(width, p, label) {
+\tif (typeof width !== \"number\") throw new TypeError(\"area: expected width to be a number, got \" + typeof width);
+\tif (typeof p !== \"object\" || p === null || typeof p.x !== \"number\" || typeof p.y !== \"number\") throw new TypeError(\"area: expected p to be a Point with x: number, y: number, got \" + typeof p);
+\tif (typeof label !== \"string\" && label !== null) throw new TypeError(\"area: expected label to be a string or null, got \" + typeof label);
+\treturn width * p.x;
+}
+"
+        );
+    }
+
+    #[test]
+    fn if_expressions() {
+        assert_eq!(
+            compile(
+                "fn size(n: number) -> string {
+                    let size = if n > 10 { \"big\" } else if n > 5 { \"medium\" } else { \"small\" };
+                    let logged = if n > 10 { println(n); 1 } else { 2 };
+                    size
+                }",
+                JavascriptCompilerOptions::default()
+            ),
+            "function size(n) {
+\tlet size = n > 10 ? \"big\" : n > 5 ? \"medium\" : \"small\", logged = (() => {
+\t\tif (n > 10) {
+\t\t\tconsole.log(n);
+\t\t\treturn 1;
+\t\t} else {
+\t\t\treturn 2;
+\t\t}
+\t})();
+\treturn size;
+}
+"
+        );
+    }
+
+    #[test]
+    fn reused_compiler() {
+        let program = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            Parser::parse(Tokens::new(&tokens)).unwrap().1
+        };
+        let mut compiler = JavascriptCompiler::with_options(JavascriptCompilerOptions::default());
+        assert_eq!(
+            compiler.compile(program("let total: number = 1;")),
+            "let total = 1;\n"
+        );
+        // the second compilation starts from a reset arena and uses the new options
+        compiler.set_options(typescript());
+        assert_eq!(
+            compiler.compile(program("fn f(x: number) -> number { x }")),
+            "function f(x: number): number {\n\treturn x;\n}\n"
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 1000;
+        let arrays = with_stack(|| {
+            compile(
+                &format!("let a = {}1{};", "[".repeat(depth), "]".repeat(depth)),
+                JavascriptCompilerOptions::default(),
+            )
+        });
+        assert_eq!(
+            arrays,
+            format!("let a = {}1{};\n", "[".repeat(depth), "]".repeat(depth))
+        );
+        let ifs = with_stack(|| {
+            let code = compile(
+                &format!(
+                    "let x = true; let a = {}1{};",
+                    "if x { ".repeat(depth),
+                    " } else { 0 }".repeat(depth)
+                ),
+                JavascriptCompilerOptions::default(),
+            );
+            check(&code, OutputLanguage::Javascript).unwrap();
+            code
+        });
+        assert_eq!(ifs.matches("x ? ").count(), depth, "{}", ifs);
+    }
+
+    #[test]
+    fn threads() {
+        let programs = (0..4)
+            .map(|index| {
+                let input = format!("fn f(x: number) -> number {{ x * {} }}", index);
+                let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+                Parser::parse(Tokens::new(&tokens)).unwrap().1
+            })
+            .collect::<Vec<_>>();
+        let compiled = std::thread::scope(|scope| {
+            let threads = programs
+                .into_iter()
+                .map(|program| {
+                    scope.spawn(|| {
+                        let compiler =
+                            JavascriptCompiler::with_options(JavascriptCompilerOptions::default());
+                        compiler.compile(program)
+                    })
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for (index, code) in compiled.iter().enumerate() {
+            assert_eq!(
+                *code,
+                format!("function f(x) {{\n\treturn x * {};\n}}\n", index)
+            );
+        }
+    }
+
+    #[test]
+    fn from_json_paths() {
+        let output = compile(
+            "struct Inner { n: number }
+            #[derive(Deserialize)]
+            struct Outer { inner: Inner, tags: [string], children: [Outer] }",
+            JavascriptCompilerOptions::default(),
+        );
+        for line in [
+            "throw new TypeError(\"Outer::from_json: expected JSON of an Outer, got \" + typeof value);",
+            "__check_Inner(value.inner, \"inner\");",
+            "throw new TypeError(\"Outer::from_json: expected tags[\" + __index0 + \"] to be a string, got \" + typeof value.tags[__index0]);",
+            "__check_Outer(value.children[__index0], \"children[\" + __index0 + \"]\");",
+            "throw new TypeError(\"Outer::from_json: expected \" + path + \".n to be a number, got \" + typeof value.n);",
+        ] {
+            assert!(output.contains(line), "{}", output);
+        }
+    }
+
+    #[test]
+    fn extern_results() {
+        let input = "type Config = { host: string, port: number };
+            extern fn load_config(path: string) -> Config;
+            extern fn log(message: string);
+            log(load_config(\"app.json\").host);";
+        assert_eq!(
+            compile(
+                input,
+                JavascriptCompilerOptions {
+                    type_guards: true,
+                    ..Default::default()
+                }
+            ),
+            "function __checked_load_config(value) {
+\tif (typeof value !== \"object\" || value === null || typeof value.host !== \"string\" || typeof value.port !== \"number\") throw new TypeError(\"load_config: expected the returned value to be a Config with host: string, port: number, got \" + typeof value);
+\treturn value;
+}
+log(__checked_load_config(load_config(\"app.json\")).host);
+"
+        );
+        assert_eq!(
+            compile(input, JavascriptCompilerOptions::default()),
+            "log(load_config(\"app.json\").host);\n"
+        );
+    }
+
+    #[test]
+    fn reserved_words() {
+        // bindings are renamed, fields and `pub` items keep their name outside of the module
+        assert_eq!(
+            compile(
+                "struct Box { private: number }
+                pub fn private(x: number) -> number { x }
+                fn f(static: number) { for default in 0..static { println(default); }; }
+                let b = Box { private: private(1) };
+                println(b.private);",
+                JavascriptCompilerOptions::default()
+            ),
+            "function private_(x) {
+\treturn x;
+}
+export { private_ as private };
+function f(static_) {
+\tfor (let default_ = 0; default_ < static_; default_++) {
+\t\tconsole.log(default_);
+\t}
+}
+let b = { private: private_(1) };
+console.log(b.private);
+"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use oxc::{
+    allocator::{Allocator, Vec},
+    ast::{
+        ast::{
+            ArrowFunctionExpression, BindingPatternKind, CallExpression, Expression,
+            FormalParameters, Function, FunctionBody, Program, ReturnStatement, Statement,
+        },
+        visit::walk_mut,
+        AstBuilder, Visit, VisitMut,
+    },
+    span::Span,
+    syntax::scope::ScopeFlags,
+};
+
+/// Cleans up the javascript the expressions compile to, after everything is generated:
+///
+/// - `(() => { return value; })()` becomes `value`
+/// - `(() => { ... })();` as a statement becomes a block, or just its statements
+/// - blocks without declarations are merged into the surrounding statements
+/// - `let a = 1; let b = 2;` becomes `let a = 1, b = 2;`
+/// - `function f(__arg1) { let [a, b] = __arg1; ... }` becomes `function f([a, b]) { ... }`, the
+///   way destructured parameters are written in javascript
+pub fn optimize<'a>(program: &mut Program<'a>, allocator: &'a Allocator) {
+    Peephole {
+        ast: AstBuilder::new(allocator),
+    }
+    .visit_program(program);
+}
+
+struct Peephole<'a> {
+    ast: AstBuilder<'a>,
+}
+
+impl<'a> VisitMut<'a> for Peephole<'a> {
+    fn visit_expression(&mut self, it: &mut Expression<'a>) {
+        walk_mut::walk_expression(self, it);
+        let value = match iife_body(it).map(|body| body.as_mut_slice()) {
+            Some([Statement::ReturnStatement(statement)]) => match &mut statement.argument {
+                Some(value) => self.ast.move_expression(value),
+                None => return,
+            },
+            _ => return,
+        };
+        *it = value;
+    }
+
+    fn visit_function(&mut self, it: &mut Function<'a>, flags: ScopeFlags) {
+        walk_mut::walk_function(self, it, flags);
+        if let Some(body) = &mut it.body {
+            self.destructure_parameters(&mut it.params, body);
+        }
+    }
+
+    fn visit_arrow_function_expression(&mut self, it: &mut ArrowFunctionExpression<'a>) {
+        walk_mut::walk_arrow_function_expression(self, it);
+        if !self.destructure_parameters(&mut it.params, &mut it.body) || it.expression {
+            return;
+        }
+        // the destructurings were the only reason for a block body
+        if let [Statement::ReturnStatement(statement)] = it.body.statements.as_mut_slice() {
+            if let Some(value) = statement.argument.take() {
+                it.body.statements = self
+                    .ast
+                    .vec1(self.ast.statement_expression(Span::new(0, 0), value));
+                it.expression = true;
+            }
+        }
+    }
+
+    fn visit_statements(&mut self, it: &mut Vec<'a, Statement<'a>>) {
+        walk_mut::walk_statements(self, it);
+        let statements = std::mem::replace(it, self.ast.vec());
+        for statement in statements {
+            self.push(it, statement);
+        }
+    }
+}
+
+impl<'a> Peephole<'a> {
+    fn push(&self, statements: &mut Vec<'a, Statement<'a>>, mut statement: Statement<'a>) {
+        match &mut statement {
+            Statement::ExpressionStatement(expression) => {
+                if let Some(body) = iife_body(&mut expression.expression) {
+                    // a `return` in the body would return from the surrounding function instead
+                    if !returns(body) {
+                        let body = std::mem::replace(body, self.ast.vec());
+                        self.push(statements, self.ast.statement_block(Span::new(0, 0), body));
+                        return;
+                    }
+                }
+            }
+            Statement::BlockStatement(block)
+                if !block.body.iter().any(Statement::is_declaration) =>
+            {
+                let body = std::mem::replace(&mut block.body, self.ast.vec());
+                for statement in body {
+                    self.push(statements, statement);
+                }
+                return;
+            }
+            Statement::VariableDeclaration(declaration) => {
+                if let Some(Statement::VariableDeclaration(previous)) = statements.last_mut() {
+                    if previous.kind == declaration.kind && !previous.declare {
+                        let declarations =
+                            std::mem::replace(&mut declaration.declarations, self.ast.vec());
+                        previous.declarations.extend(declarations);
+                        return;
+                    }
+                }
+            }
+            _ => {}
+        }
+        statements.push(statement);
+    }
+
+    /// Moves the patterns of the destructured parameters the body starts with into the
+    /// parameters, and returns whether there were any
+    fn destructure_parameters(
+        &self,
+        parameters: &mut FormalParameters<'a>,
+        body: &mut FunctionBody<'a>,
+    ) -> bool {
+        let Some(Statement::VariableDeclaration(declaration)) = body.statements.first_mut() else {
+            return false;
+        };
+        let mut moved = 0;
+        for declarator in declaration.declarations.iter_mut() {
+            let Some(Expression::Identifier(value)) = &declarator.init else {
+                break;
+            };
+            if !value.name.starts_with("__arg")
+                || !matches!(
+                    declarator.id.kind,
+                    BindingPatternKind::ArrayPattern(_) | BindingPatternKind::ObjectPattern(_)
+                )
+            {
+                break;
+            }
+            let Some(parameter) = parameters.items.iter_mut().find(|parameter| {
+                matches!(
+                    &parameter.pattern.kind,
+                    BindingPatternKind::BindingIdentifier(name) if name.name == value.name
+                )
+            }) else {
+                break;
+            };
+            parameter.pattern.kind = std::mem::replace(
+                &mut declarator.id.kind,
+                self.ast
+                    .binding_pattern_kind_binding_identifier(Span::new(0, 0), "_"),
+            );
+            moved += 1;
+        }
+        declaration.declarations.drain(..moved);
+        if declaration.declarations.is_empty() {
+            body.statements.remove(0);
+        }
+        moved > 0
+    }
+}
+
+/// The statements of `(() => { ... })()`
+fn iife_body<'e, 'a>(expression: &'e mut Expression<'a>) -> Option<&'e mut Vec<'a, Statement<'a>>> {
+    let Expression::CallExpression(call) = expression else {
+        return None;
+    };
+    let CallExpression {
+        callee, arguments, ..
+    } = &mut **call;
+    let Expression::ArrowFunctionExpression(function) = callee else {
+        return None;
+    };
+    let trivial = arguments.is_empty()
+        && !function.r#async
+        && !function.expression
+        && function.params.items.is_empty()
+        && function.params.rest.is_none()
+        && function.body.directives.is_empty();
+    trivial.then_some(&mut function.body.statements)
+}
+
+fn returns<'a>(statements: &Vec<'a, Statement<'a>>) -> bool {
+    let mut finder = FindReturn(false);
+    finder.visit_statements(statements);
+    finder.0
+}
+
+/// Looks for a `return` outside of nested functions
+struct FindReturn(bool);
+
+impl<'a> Visit<'a> for FindReturn {
+    fn visit_return_statement(&mut self, _: &ReturnStatement<'a>) {
+        self.0 = true;
+    }
+
+    fn visit_function(&mut self, _: &Function<'a>, _: ScopeFlags) {}
+
+    fn visit_arrow_function_expression(&mut self, _: &ArrowFunctionExpression<'a>) {}
+}
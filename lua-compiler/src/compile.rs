@@ -0,0 +1,1428 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use oxidescript::lexer::quote_string;
+use oxidescript::parser::ast::{
+    AssignmentExpr, Block, CallExpr, ClosureExpr, Declaration, Deferred, Expression, ForExpr,
+    Identifier, IfExpr, ImplDecl, ImplItem, InfixOperator, Literal, Method, ModDecl, Number,
+    OptionalExpr, RangeExpr, Span, Statement, TraitDecl, UnaryOperator, WhileExpr,
+};
+use oxidescript::parser::enums::{declares_enum, lower_enums};
+use oxidescript::parser::optional::has_optional;
+use oxidescript::parser::types::{type_as_literal, union_members};
+
+use crate::LuaCompilerOptions;
+
+const INDENT: &str = "    ";
+
+/// Identifiers that are keywords in Lua but not in oxidescript get a `_` appended
+const LUA_KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Lua globals the compiled code calls. Programs can declare these names, which then get a `_`
+/// appended like keywords, so their locals don't hide the globals from the compiled code
+pub const LUA_BUILTINS: &[&str] = &[
+    "print",
+    "type",
+    "tostring",
+    "tonumber",
+    "ipairs",
+    "pairs",
+    "error",
+    "pcall",
+    "require",
+    "setmetatable",
+    "getmetatable",
+    "table",
+    "string",
+    "math",
+    "io",
+];
+
+/// Functions emitted in front of the program when the compiled code uses them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Helper {
+    /// Converts a value to a string like javascript, numbers with an integer value are written
+    /// without `.0`
+    ToString,
+    /// Whether a value is true in a condition, `0`, `""` and NaN are false like in javascript
+    Truthy,
+    /// `+` concatenates if either side is a string, like in javascript. Has to come after
+    /// [`Helper::ToString`], which it calls
+    Add,
+    /// `clone(value)`, deep-copies tables
+    Clone,
+    /// `value[start..end]` of tables and strings, with the bounds clamped like javascript's `slice`
+    Slice,
+    /// `value[start..end]` with bounds checks, which fails instead of clamping the bounds. Has to
+    /// come after [`Helper::Slice`], which it calls
+    CheckedSlice,
+    /// `array::new_2d(width, height, init)`, a table of `height` rows of `width` items
+    New2d,
+    /// The result of a checked operation, or `nil` if it is beyond `Number.MAX_SAFE_INTEGER`
+    Checked,
+    /// `start..end` used as a value, a table of the numbers from `first` to `last`
+    Range,
+}
+
+impl Helper {
+    pub fn definition(self) -> &'static str {
+        match self {
+            Helper::ToString => {
+                r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+"#
+            }
+            Helper::Truthy => {
+                r#"local function __oxidescript_truthy(value)
+    return value ~= nil and value ~= false and value ~= 0 and value ~= "" and value == value
+end
+"#
+            }
+            Helper::Add => {
+                r#"local function __oxidescript_add(a, b)
+    if type(a) == "string" or type(b) == "string" then
+        return __oxidescript_tostring(a) .. __oxidescript_tostring(b)
+    end
+    return a + b
+end
+"#
+            }
+            Helper::Clone => {
+                r#"local function __oxidescript_clone(value)
+    if type(value) ~= "table" then
+        return value
+    end
+    local copy = {}
+    for key, item in pairs(value) do
+        copy[key] = __oxidescript_clone(item)
+    end
+    return setmetatable(copy, getmetatable(value))
+end
+"#
+            }
+            Helper::Slice => {
+                r#"local function __oxidescript_slice(value, start, stop)
+    local length = #value
+    start = start or 0
+    stop = stop or length
+    if start < 0 then
+        start = math.max(length + start, 0)
+    end
+    if stop < 0 then
+        stop = math.max(length + stop, 0)
+    end
+    stop = math.min(stop, length)
+    if type(value) == "string" then
+        return string.sub(value, start + 1, stop)
+    end
+    return table.move(value, start + 1, stop, 1, {})
+end
+"#
+            }
+            Helper::CheckedSlice => {
+                r#"local function __oxidescript_checked_slice(value, start, stop)
+    start = start or 0
+    stop = stop or #value
+    if start < 0 or start > stop or stop > #value then
+        error("slice " .. start .. ".." .. stop .. " is out of bounds of length " .. #value)
+    end
+    return __oxidescript_slice(value, start, stop)
+end
+"#
+            }
+            Helper::Checked => {
+                r#"local function __oxidescript_checked(result)
+    if result ~= result or math.abs(result) > 9007199254740991 then
+        return nil
+    end
+    return result
+end
+"#
+            }
+            Helper::New2d => {
+                r#"local function __oxidescript_new_2d(width, height, init)
+    local grid = {}
+    for y = 1, height do
+        local row = {}
+        for x = 1, width do
+            row[x] = init
+        end
+        grid[y] = row
+    end
+    return grid
+end
+"#
+            }
+            Helper::Range => {
+                r#"local function __oxidescript_range(first, last)
+    local numbers = {}
+    for i = first, last do
+        numbers[#numbers + 1] = i
+    end
+    return numbers
+end
+"#
+            }
+        }
+    }
+}
+
+/// What happens with the value of the last expression of a block
+#[derive(Clone, Copy)]
+enum Tail {
+    Return,
+    Discard,
+}
+
+/// Where `return`, `break` and `continue` can jump to from the code being compiled. They can't
+/// leave the `(function() ... end)()` around expressions
+#[derive(Clone, Copy, Default)]
+struct Jumps {
+    /// Set in a function body
+    returns: bool,
+    /// The number of the innermost loop, which names the `::continue_n::` label at the end of its
+    /// body
+    r#loop: Option<usize>,
+}
+
+pub struct LuaCompilerContext<'a> {
+    options: &'a LuaCompilerOptions,
+    pub helpers: BTreeSet<Helper>,
+    /// The type `Self` refers to while compiling the items of an impl block
+    self_type: Option<Identifier>,
+    /// The traits compiled so far, whose default methods impls that don't override them share
+    traits: HashMap<String, TraitDecl>,
+    jumps: Jumps,
+    /// The number of loops compiled so far
+    loops: usize,
+    /// The loops with a `continue`, which need a label at the end of their body
+    continued: BTreeSet<usize>,
+    /// Prelude functions the program declares itself, which are called instead of compiled
+    pub shadowed_prelude: HashSet<String>,
+    /// The [`LUA_BUILTINS`] the program declares itself, which are renamed
+    pub shadowed_builtins: HashSet<String>,
+}
+
+impl<'a> LuaCompilerContext<'a> {
+    pub fn new(options: &'a LuaCompilerOptions) -> Self {
+        LuaCompilerContext {
+            options,
+            helpers: BTreeSet::new(),
+            self_type: None,
+            traits: HashMap::new(),
+            jumps: Jumps::default(),
+            loops: 0,
+            continued: BTreeSet::new(),
+            shadowed_prelude: HashSet::new(),
+            shadowed_builtins: HashSet::new(),
+        }
+    }
+
+    /// The name of a variable, which can't hide the [`LUA_BUILTINS`] the compiled code calls.
+    /// Fields and methods only need [`name`]
+    fn variable(&self, ident: &Identifier) -> String {
+        if self.shadowed_builtins.contains(&ident.0) {
+            format!("{}_", ident.0)
+        } else {
+            name(ident)
+        }
+    }
+
+    /// Functions are hoisted in javascript but Lua locals are only visible after their
+    /// declaration, so if a list of statements declares functions or impls every name is declared
+    /// up front and the functions and impls are defined before the other statements
+    pub fn statements(&mut self, statements: &[Statement], indent: usize) -> String {
+        if declares_enum(statements) {
+            return self.statements(&lower_enums(statements), indent);
+        }
+        let hoisted = statements
+            .iter()
+            .filter(|statement| is_hoisted(statement))
+            .collect::<Vec<_>>();
+        if hoisted.is_empty() {
+            return statements
+                .iter()
+                .map(|statement| self.statement(statement, false, indent))
+                .collect();
+        }
+
+        let mut names = vec![];
+        for statement in statements {
+            if let Statement::DeclarationStatement(declaration, _) = statement {
+                for declared in declared_names(declaration)
+                    .into_iter()
+                    .map(|ident| self.variable(ident))
+                {
+                    if !names.contains(&declared) {
+                        names.push(declared);
+                    }
+                }
+            }
+        }
+        let mut code = line(indent, &format!("local {}", names.join(", ")));
+
+        // impl blocks of the same type share one table, which is defined after the tables of
+        // the traits whose defaults it inherits
+        let mut impls: Vec<(&Identifier, Vec<&ImplDecl>)> = vec![];
+        for statement in &hoisted {
+            match statement {
+                Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), _) => {
+                    match impls
+                        .iter_mut()
+                        .find(|(existing, _)| **existing == decl.target)
+                    {
+                        Some((_, existing)) => existing.push(decl),
+                        None => impls.push((&decl.target, vec![decl])),
+                    }
+                }
+                statement => code.push_str(&self.statement(statement, true, indent)),
+            }
+        }
+        for (target, decls) in impls {
+            code.push_str(&self.impl_table(target, &decls, indent));
+        }
+        for statement in statements.iter().filter(|statement| !is_hoisted(statement)) {
+            code.push_str(&self.statement(statement, true, indent));
+        }
+        code
+    }
+
+    fn statement(&mut self, statement: &Statement, predeclared: bool, indent: usize) -> String {
+        let local = if predeclared { "" } else { "local " };
+        match statement {
+            Statement::ExpressionStatement { expression, .. } => {
+                self.tail(expression, Tail::Discard, indent)
+            }
+            Statement::DeclarationStatement(declaration, _) => match declaration {
+                Declaration::LetDeclaration(ident, value, _, _) => {
+                    let value = self.expression(value, indent);
+                    line(
+                        indent,
+                        &format!("{}{} = {}", local, self.variable(ident), value),
+                    )
+                }
+                // `local a, c = t[1], t[2][2]`, with a value that isn't a name in a local first
+                Declaration::DestructuringDeclaration(pattern, value, _, _) => {
+                    let (mut code, value) = match value {
+                        Expression::IdentifierExpression(_, _) => (String::new(), value.clone()),
+                        value => (
+                            line(
+                                indent,
+                                &format!("local __value = {}", self.expression(value, indent)),
+                            ),
+                            Expression::IdentifierExpression(
+                                Identifier("__value".to_string()),
+                                Span::default(),
+                            ),
+                        ),
+                    };
+                    let (names, values): (Vec<_>, Vec<_>) = pattern
+                        .bindings(&value)
+                        .into_iter()
+                        .map(|(ident, value)| {
+                            (self.variable(ident), self.expression(&value, indent))
+                        })
+                        .unzip();
+                    if !names.is_empty() {
+                        code.push_str(&line(
+                            indent,
+                            &format!("{}{} = {}", local, names.join(", "), values.join(", ")),
+                        ));
+                    }
+                    code
+                }
+                Declaration::ConstDeclaration(ident, value, _, _, _) => {
+                    let value = self.expression(value, indent);
+                    let attribute = if predeclared { "" } else { " <const>" };
+                    line(
+                        indent,
+                        &format!("{}{}{} = {}", local, self.variable(ident), attribute, value),
+                    )
+                }
+                Declaration::FunctionDeclaration {
+                    name: ident,
+                    parameters,
+                    body,
+                    ..
+                } => {
+                    let parameters = parameters
+                        .iter()
+                        .map(|parameter| self.variable(&parameter.name))
+                        .collect::<Vec<_>>();
+                    self.function(&self.variable(ident), parameters, body, indent)
+                }
+                // struct values are plain tables, only impl blocks produce code
+                // types and externs only exist for checking, externs are globals of the host
+                Declaration::StructDeclaration(_)
+                | Declaration::TypeDeclaration(_)
+                | Declaration::ExternDeclaration(_) => String::new(),
+                // traits only produce a table for their default methods
+                Declaration::TraitDeclaration(decl) => {
+                    self.traits.insert(decl.name.0.clone(), decl.clone());
+                    if !decl.has_defaults() {
+                        return String::new();
+                    }
+                    let defaults = ImplDecl {
+                        target: decl.name.clone(),
+                        trait_: None,
+                        items: decl.defaults().into_iter().map(ImplItem::Method).collect(),
+                    };
+                    self.impl_table(&decl.name, &[&defaults], indent)
+                }
+                Declaration::EnumDeclaration(_) => unreachable!("enums are lowered to structs"),
+                Declaration::ImplDeclaration(decl) => {
+                    self.impl_table(&decl.target, &[decl], indent)
+                }
+                Declaration::ModDeclaration(decl) => {
+                    let module = self.module(decl, indent);
+                    line(
+                        indent,
+                        &format!("{}{} = {}", local, self.variable(&decl.name), module),
+                    )
+                }
+                Declaration::ModFileDeclaration(ident) => line(
+                    indent,
+                    &format!(
+                        "{}{} = require({})",
+                        local,
+                        self.variable(ident),
+                        quote_string(&ident.0)
+                    ),
+                ),
+                Declaration::UseDeclaration(_) => {
+                    unreachable!("use declarations are lowered to constants")
+                }
+            },
+        }
+    }
+
+    fn function(
+        &mut self,
+        name: &str,
+        parameters: Vec<String>,
+        body: &Block,
+        indent: usize,
+    ) -> String {
+        let mut code = line(
+            indent,
+            &format!("function {}({})", name, parameters.join(", ")),
+        );
+        code.push_str(&self.function_body(body, indent + 1));
+        code.push_str(&line(indent, "end"));
+        code
+    }
+
+    fn function_body(&mut self, body: &Block, indent: usize) -> String {
+        let outer = std::mem::replace(
+            &mut self.jumps,
+            Jumps {
+                returns: true,
+                r#loop: None,
+            },
+        );
+        let code = self.block(body, Tail::Return, indent);
+        self.jumps = outer;
+        code
+    }
+
+    /// Compiles the body of a `(function() ... end)()`
+    fn iife(&mut self, indent: usize, body: impl FnOnce(&mut Self) -> String) -> String {
+        let outer = std::mem::take(&mut self.jumps);
+        let body = body(self);
+        self.jumps = outer;
+        iife(body, indent)
+    }
+
+    /// `function(x) return x * 2 end`, on one line if the body is a single statement
+    fn closure(&mut self, expr: &ClosureExpr, indent: usize) -> String {
+        let parameters = expr
+            .parameters
+            .iter()
+            .map(|parameter| self.variable(&parameter.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let body = self.function_body(&expr.body_block(), indent + 1);
+        let statement = body
+            .strip_prefix(&INDENT.repeat(indent + 1))
+            .and_then(|statement| statement.strip_suffix('\n'))
+            .filter(|statement| !statement.contains('\n'));
+        match statement {
+            Some(statement) => format!("function({}) {} end", parameters, statement),
+            None if body.is_empty() => format!("function({}) end", parameters),
+            None => format!(
+                "function({})\n{}{}end",
+                parameters,
+                body,
+                INDENT.repeat(indent)
+            ),
+        }
+    }
+
+    /// `Target = {}` followed by the items, which are always predeclared since impls are hoisted.
+    /// Inherited default methods are the functions of the trait table, `Target.method =
+    /// Trait.method`
+    fn impl_table(&mut self, target: &Identifier, decls: &[&ImplDecl], indent: usize) -> String {
+        let outer = self.self_type.replace(target.clone());
+        let target = self.variable(target);
+        let mut code = line(indent, &format!("{} = {{}}", target));
+        for item in decls.iter().flat_map(|decl| &decl.items) {
+            match item {
+                ImplItem::Method(method) | ImplItem::Getter(method) | ImplItem::Setter(method) => {
+                    let Method {
+                        name: ident,
+                        has_self,
+                        parameters,
+                        body,
+                        ..
+                    } = method;
+                    let parameters = has_self
+                        .then(|| "self".to_string())
+                        .into_iter()
+                        .chain(
+                            parameters
+                                .iter()
+                                .map(|parameter| self.variable(&parameter.name)),
+                        )
+                        .collect();
+                    code.push_str(&self.function(
+                        &format!("{}.{}", target, name(ident)),
+                        parameters,
+                        body,
+                        indent,
+                    ));
+                }
+                ImplItem::Const(ident, value) => {
+                    let value = self.expression(value, indent);
+                    code.push_str(&line(
+                        indent,
+                        &format!("{}.{} = {}", target, name(ident), value),
+                    ));
+                }
+            }
+        }
+        for decl in decls {
+            let Some(trait_) = decl
+                .trait_
+                .as_ref()
+                .and_then(|trait_| self.traits.get(&trait_.0))
+            else {
+                continue;
+            };
+            for signature in trait_.inherited(decl) {
+                code.push_str(&line(
+                    indent,
+                    &format!(
+                        "{}.{} = {}.{}",
+                        target,
+                        name(&signature.name),
+                        self.variable(&trait_.name),
+                        name(&signature.name)
+                    ),
+                ));
+            }
+        }
+        self.self_type = outer;
+        code
+    }
+
+    /// `(function() ...items; return { ...pub items } end)()`, so private items stay scoped to
+    /// the module, like in the javascript compiler
+    fn module(&mut self, decl: &ModDecl, indent: usize) -> String {
+        let statements = decl
+            .items
+            .iter()
+            .map(|item| Statement::DeclarationStatement(item.declaration.clone(), Span::default()))
+            .collect::<Vec<_>>();
+        let exports = decl
+            .items
+            .iter()
+            .filter(|item| item.is_pub)
+            .flat_map(|item| declared_names(&item.declaration))
+            .map(|ident| format!("{} = {}", name(ident), self.variable(ident)))
+            .collect::<Vec<_>>();
+        let mut body = self.statements(&statements, indent + 1);
+        body.push_str(&line(
+            indent + 1,
+            &format!("return {{ {} }}", exports.join(", ")),
+        ));
+        iife(body, indent)
+    }
+
+    fn block(&mut self, block: &Block, tail: Tail, indent: usize) -> String {
+        if let Some(deferred) = block.split_at_defer() {
+            return self.deferred(deferred, tail, indent);
+        }
+        // a `return` has to be the last statement of a Lua block, which it is here
+        if let (
+            Some((
+                Statement::ExpressionStatement {
+                    expression: jump @ Expression::ReturnExpression(_, _),
+                    ..
+                },
+                statements,
+            )),
+            None,
+        ) = (block.statements.split_last(), &block.return_value)
+        {
+            let mut code = self.statements(statements, indent);
+            code.push_str(&self.jump(jump, true, indent));
+            return code;
+        }
+        let mut code = self.statements(&block.statements, indent);
+        match &block.return_value {
+            Some(jump @ Expression::ReturnExpression(_, _)) => {
+                code.push_str(&self.jump(jump, true, indent))
+            }
+            Some(return_value) => code.push_str(&self.tail(return_value, tail, indent)),
+            None => {}
+        }
+        code
+    }
+
+    /// Runs the rest of a block with a `defer` in a `pcall`, then the cleanup, then rethrows what
+    /// the rest threw. A `return` in the rest returns from the `pcall` function, which only leaves
+    /// the function around it if the block is what the function returns. Other jumps out of the
+    /// rest are errors, like jumps out of a `(function() ... end)()`
+    fn deferred(&mut self, deferred: Deferred, tail: Tail, indent: usize) -> String {
+        let mut code = self.statements(&deferred.before, indent);
+        code.push_str(&line(
+            indent,
+            "local __deferred_ok, __deferred_value = pcall(function()",
+        ));
+        let outer = self.jumps;
+        self.jumps = Jumps {
+            returns: outer.returns && matches!(tail, Tail::Return),
+            r#loop: None,
+        };
+        code.push_str(&self.block(&deferred.rest, tail, indent + 1));
+        self.jumps = outer;
+        code.push_str(&line(indent, "end)"));
+        code.push_str(&self.block(&deferred.cleanup, Tail::Discard, indent));
+        code.push_str(&line(
+            indent,
+            "if not __deferred_ok then error(__deferred_value, 0) end",
+        ));
+        if let Tail::Return = tail {
+            code.push_str(&line(indent, "return __deferred_value"));
+        }
+        code
+    }
+
+    /// Lowers expressions in statement position. Lua only allows calls as expression statements,
+    /// other discarded values are assigned to `_`
+    fn tail(&mut self, expression: &Expression, tail: Tail, indent: usize) -> String {
+        match (expression, tail) {
+            (
+                Expression::ReturnExpression(_, _)
+                | Expression::BreakExpression(_)
+                | Expression::ContinueExpression(_),
+                _,
+            ) => self.jump(expression, false, indent),
+            (Expression::IfExpression(expr, _), tail) => self.if_statement(expr, tail, indent),
+            (Expression::BlockExpression(block, _), tail) => {
+                let mut code = line(indent, "do");
+                code.push_str(&self.block(block, tail, indent + 1));
+                code.push_str(&line(indent, "end"));
+                code
+            }
+            (Expression::ForExpression(expr, _), Tail::Discard) => {
+                self.for_statement(expr, None, indent)
+            }
+            (Expression::WhileExpression(expr, _), Tail::Discard) => {
+                self.while_statement(expr, None, indent)
+            }
+            (Expression::MatchExpression(expr, _), tail) => {
+                self.tail(&expr.clone().into_if(), tail, indent)
+            }
+            (expression, Tail::Return) => {
+                let value = self.expression(expression, indent);
+                line(indent, &format!("return {}", value))
+            }
+            (Expression::AssignmentExpression(expr, _), Tail::Discard) => {
+                self.assignment(expr, false, indent)
+            }
+            (Expression::CallExpression(_, _), Tail::Discard) => {
+                let call = self.expression(expression, indent);
+                line(indent, &call)
+            }
+            (expression, Tail::Discard) => {
+                let value = self.expression(expression, indent);
+                line(indent, &format!("local _ = {}", value))
+            }
+        }
+    }
+
+    /// `return`, `break` or `goto continue_n`. A `return` that isn't the last statement of its
+    /// block is wrapped in `do ... end`. Jumps out of a `(function() ... end)()` are errors, since
+    /// they would only leave the function
+    fn jump(&mut self, jump: &Expression, last: bool, indent: usize) -> String {
+        let statement = match (jump, self.jumps) {
+            (Expression::ReturnExpression(value, _), Jumps { returns: true, .. }) => {
+                let statement = match value {
+                    Some(value) => format!("return {}", self.expression(value, indent)),
+                    None => "return".to_string(),
+                };
+                if last {
+                    statement
+                } else {
+                    format!("do {} end", statement)
+                }
+            }
+            (
+                Expression::BreakExpression(_),
+                Jumps {
+                    r#loop: Some(_), ..
+                },
+            ) => "break".to_string(),
+            (
+                Expression::ContinueExpression(_),
+                Jumps {
+                    r#loop: Some(r#loop),
+                    ..
+                },
+            ) => {
+                self.continued.insert(r#loop);
+                format!("goto continue_{}", r#loop)
+            }
+            (jump, _) => self.expression(jump, indent),
+        };
+        line(indent, &statement)
+    }
+
+    /// The body of a loop, with the label `continue` jumps to at its end if it has one
+    fn loop_body(&mut self, indent: usize, body: impl FnOnce(&mut Self) -> String) -> String {
+        let r#loop = self.loops;
+        self.loops += 1;
+        let outer = self.jumps;
+        self.jumps.r#loop = Some(r#loop);
+        let mut code = body(self);
+        self.jumps = outer;
+        if self.continued.remove(&r#loop) {
+            code.push_str(&line(indent, &format!("::continue_{}::", r#loop)));
+        }
+        code
+    }
+
+    fn if_statement(&mut self, expr: &IfExpr, tail: Tail, indent: usize) -> String {
+        let condition = self.condition(&expr.condition, indent);
+        let mut code = line(indent, &format!("if {} then", condition));
+        code.push_str(&self.block(&expr.then_block, tail, indent + 1));
+        for else_if in &expr.else_if_blocks {
+            let condition = self.condition(&else_if.condition, indent);
+            code.push_str(&line(indent, &format!("elseif {} then", condition)));
+            code.push_str(&self.block(&else_if.then_block, tail, indent + 1));
+        }
+        if let Some(else_block) = &expr.else_block {
+            code.push_str(&line(indent, "else"));
+            code.push_str(&self.block(else_block, tail, indent + 1));
+        }
+        code.push_str(&line(indent, "end"));
+        code
+    }
+
+    /// Appends the value of every iteration to `output` if given, like the javascript compiler
+    /// collects them into an array when a `for` is used as a value
+    /// Ranges are numeric `for` loops, which evaluate their bounds once
+    fn for_statement(&mut self, expr: &ForExpr, output: Option<&str>, indent: usize) -> String {
+        let header = match &*expr.rhs {
+            Expression::RangeExpression(range, _) => {
+                let (first, last) = self.range_bounds(range, indent);
+                format!("for {} = {}, {} do", self.variable(&expr.lhs), first, last)
+            }
+            rhs => {
+                let iterable = self.expression(rhs, indent);
+                format!(
+                    "for _, {} in ipairs({}) do",
+                    self.variable(&expr.lhs),
+                    iterable
+                )
+            }
+        };
+        let mut code = line(indent, &header);
+        let body = loop_block(&expr.body);
+        code.push_str(&self.loop_body(indent + 1, |ctx| {
+            let mut code = ctx.statements(&body.statements, indent + 1);
+            match (&body.return_value, output) {
+                (Some(value), Some(output)) => {
+                    let value = ctx.expression(value, indent + 1);
+                    code.push_str(&line(
+                        indent + 1,
+                        &format!("{output}[#{output} + 1] = {}", value),
+                    ));
+                }
+                (Some(value), None) => code.push_str(&ctx.tail(value, Tail::Discard, indent + 1)),
+                (None, _) => {}
+            }
+            code
+        }));
+        code.push_str(&line(indent, "end"));
+        code
+    }
+
+    /// The first and the last number of a range, the end of `start..end` is left out
+    fn range_bounds(&mut self, range: &RangeExpr, indent: usize) -> (String, String) {
+        let first = self.expression(&range.start, indent);
+        let last = match (&*range.end, range.inclusive) {
+            (end, true) => self.expression(end, indent),
+            (
+                Expression::LiteralExpression(Literal::NumberLiteral(Number::I { value, .. }), _),
+                false,
+            ) => (value - 1).to_string(),
+            (end, false) => format!("{} - 1", self.expression(end, indent)),
+        };
+        (first, last)
+    }
+
+    /// Like [`Self::for_statement`]
+    fn while_statement(&mut self, expr: &WhileExpr, output: Option<&str>, indent: usize) -> String {
+        let condition = self.condition(&expr.condition, indent);
+        let mut code = line(indent, &format!("while {} do", condition));
+        let body = loop_block(&expr.body);
+        code.push_str(&self.loop_body(indent + 1, |ctx| {
+            let mut code = ctx.statements(&body.statements, indent + 1);
+            match (&body.return_value, output) {
+                (Some(value), Some(output)) => {
+                    let value = ctx.expression(value, indent + 1);
+                    code.push_str(&line(
+                        indent + 1,
+                        &format!("{output}[#{output} + 1] = {}", value),
+                    ));
+                }
+                (Some(value), None) => code.push_str(&ctx.tail(value, Tail::Discard, indent + 1)),
+                (None, _) => {}
+            }
+            code
+        }));
+        code.push_str(&line(indent, "end"));
+        code
+    }
+
+    /// Only `nil` and `false` are false in Lua, so conditions that aren't known to be booleans go
+    /// through [`Helper::Truthy`]
+    fn condition(&mut self, condition: &Expression, indent: usize) -> String {
+        let code = self.expression(condition, indent);
+        if is_boolean(condition) {
+            return code;
+        }
+        self.helpers.insert(Helper::Truthy);
+        format!("__oxidescript_truthy({})", code)
+    }
+
+    /// A value converted to a string, numbers go through [`Helper::ToString`]
+    fn string(&mut self, value: &Expression, indent: usize) -> String {
+        let code = self.expression(value, indent);
+        if is_string(value) {
+            return code;
+        }
+        self.helpers.insert(Helper::ToString);
+        format!("__oxidescript_tostring({})", code)
+    }
+
+    fn expression(&mut self, expression: &Expression, indent: usize) -> String {
+        if has_optional(expression) {
+            return self.optional_chain(expression, indent);
+        }
+        match expression {
+            Expression::IdentifierExpression(ident, _) => self.variable(ident),
+            Expression::LiteralExpression(literal, _) => match literal {
+                // rust escapes are valid in Lua strings as well
+                Literal::StringLiteral(value) => quote_string(value),
+                Literal::NumberLiteral(number) => float(number),
+                Literal::BooleanLiteral(value) => value.to_string(),
+            },
+            Expression::UnaryExpression(expr, _) => {
+                let rhs = match expr.op {
+                    UnaryOperator::LogicalNot => self.condition(&expr.rhs, indent),
+                    _ => self.expression(&expr.rhs, indent),
+                };
+                match expr.op {
+                    UnaryOperator::LogicalNot => format!("not {}", rhs),
+                    UnaryOperator::BitwiseNot => format!("~{}", rhs),
+                    // `--` would start a comment
+                    UnaryOperator::Minus if rhs.starts_with('-') => format!("-({})", rhs),
+                    UnaryOperator::Minus => format!("-{}", rhs),
+                    UnaryOperator::Plus => format!("tonumber({})", rhs),
+                }
+            }
+            Expression::AssignmentExpression(expr, _) => self.assignment(expr, true, indent),
+            Expression::InfixExpression(expr, _) if expr.op == InfixOperator::NullishCoalesce => {
+                let lhs = self.expression(&expr.lhs, indent + 1);
+                let mut body = line(indent + 1, &format!("local __value = {}", lhs));
+                body.push_str(&line(indent + 1, "if __value == nil then"));
+                let rhs = self.expression(&expr.rhs, indent + 2);
+                body.push_str(&line(indent + 2, &format!("return {}", rhs)));
+                body.push_str(&line(indent + 1, "end"));
+                body.push_str(&line(indent + 1, "return __value"));
+                iife(body, indent)
+            }
+            Expression::InfixExpression(expr, _)
+                if expr.op == InfixOperator::Plus
+                    && (is_string(&expr.lhs) || is_string(&expr.rhs))
+                    && (is_string(&expr.lhs) || is_number(&expr.lhs))
+                    && (is_string(&expr.rhs) || is_number(&expr.rhs)) =>
+            {
+                let lhs = self.string(&expr.lhs, indent);
+                let rhs = self.string(&expr.rhs, indent);
+                format!("({} .. {})", lhs, rhs)
+            }
+            Expression::InfixExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, indent);
+                let rhs = self.expression(&expr.rhs, indent);
+                let op = match expr.op {
+                    InfixOperator::Plus if is_number(&expr.lhs) && is_number(&expr.rhs) => "+",
+                    InfixOperator::Plus => {
+                        self.helpers.insert(Helper::ToString);
+                        self.helpers.insert(Helper::Add);
+                        return format!("__oxidescript_add({}, {})", lhs, rhs);
+                    }
+                    InfixOperator::Minus => "-",
+                    InfixOperator::Multiply => "*",
+                    InfixOperator::Divide => "/",
+                    // `math.fmod` has the sign of `lhs` like `%` in javascript, Lua's `%` has the
+                    // sign of `rhs`
+                    InfixOperator::Modulo => return format!("math.fmod({}, {})", lhs, rhs),
+                    InfixOperator::Equal => "==",
+                    InfixOperator::NotEqual => "~=",
+                    InfixOperator::GreaterThan => ">",
+                    InfixOperator::LessThan => "<",
+                    InfixOperator::GreaterThanEqual => ">=",
+                    InfixOperator::LessThanEqual => "<=",
+                    InfixOperator::LogicalOr => "or",
+                    InfixOperator::LogicalAnd => "and",
+                    InfixOperator::NullishCoalesce => unreachable!("`??` is handled above"),
+                    InfixOperator::BitwiseOr => "|",
+                    InfixOperator::BitwiseXor => "~",
+                    InfixOperator::BitwiseAnd => "&",
+                    InfixOperator::BitwiseLeftShift => "<<",
+                    InfixOperator::BitwiseRightShift => ">>",
+                };
+                format!("({} {} {})", lhs, op, rhs)
+            }
+            Expression::ArrayExpression(elements, _) | Expression::TupleExpression(elements, _) => {
+                format!("{{{}}}", self.expressions(elements, indent).join(", "))
+            }
+            Expression::TupleIndexExpression(expr, _) => {
+                format!("{}[{}]", self.prefix(&expr.lhs, indent), expr.index + 1)
+            }
+            Expression::IfExpression(expr, _) => {
+                self.iife(indent, |ctx| ctx.if_statement(expr, Tail::Return, indent + 1))
+            }
+            Expression::BlockExpression(block, _) => {
+                self.iife(indent, |ctx| ctx.block(block, Tail::Return, indent + 1))
+            }
+            Expression::ForExpression(expr, _) => self.iife(indent, |ctx| {
+                let mut body = line(indent + 1, "local __output = {}");
+                body.push_str(&ctx.for_statement(expr, Some("__output"), indent + 1));
+                body.push_str(&line(indent + 1, "return __output"));
+                body
+            }),
+            Expression::WhileExpression(expr, _) => self.iife(indent, |ctx| {
+                let mut body = line(indent + 1, "local __output = {}");
+                body.push_str(&ctx.while_statement(expr, Some("__output"), indent + 1));
+                body.push_str(&line(indent + 1, "return __output"));
+                body
+            }),
+            Expression::MatchExpression(expr, _) => self.expression(&expr.clone().into_if(), indent),
+            Expression::CallExpression(call, _) => self.call(call, indent),
+            Expression::TaggedTemplateExpression(expr, _) => {
+                self.expression(&expr.clone().into_call(), indent)
+            }
+            Expression::TemplateExpression(expr, _) => {
+                self.expression(&expr.clone().into_concatenation(), indent)
+            }
+            // arrays start at 1 in Lua
+            Expression::IndexExpression(expr, _) => {
+                let lhs = self.prefix(&expr.lhs, indent);
+                let index = match &*expr.index {
+                    Expression::LiteralExpression(Literal::NumberLiteral(Number::I {
+                        value,
+                        ..
+                    }), _) => (value + 1).to_string(),
+                    index => format!("{} + 1", self.expression(index, indent)),
+                };
+                format!("{}[{}]", lhs, index)
+            }
+            Expression::ClosureExpression(expr, _) => self.closure(expr, indent),
+            Expression::ReturnExpression(_, _) => {
+                "error(\"return inside of an expression is not supported by the lua target\")"
+                    .to_string()
+            }
+            Expression::BreakExpression(_) | Expression::ContinueExpression(_) => {
+                "error(\"break and continue inside of an expression are not supported by the lua target\")"
+                    .to_string()
+            }
+            // blocks compile their `defer` statements, the resolver rejects the others
+            Expression::DeferExpression(_, _) => {
+                "error(\"defer outside of a block is not supported by the lua target\")".to_string()
+            }
+            Expression::WithExpression(_, _) => {
+                unreachable!("`with` is lowered to a block with a `defer`")
+            }
+            Expression::AwaitExpression(_, _) => {
+                "error(\".await is not supported by the lua target\")".to_string()
+            }
+            Expression::RangeExpression(expr, _) => {
+                self.helpers.insert(Helper::Range);
+                let (first, last) = self.range_bounds(expr, indent);
+                format!("__oxidescript_range({}, {})", first, last)
+            }
+            Expression::SliceExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, indent);
+                let mut bound = |bound: &Option<Box<Expression>>| match bound {
+                    Some(bound) => self.expression(bound, indent),
+                    None => "nil".to_string(),
+                };
+                let (start, end) = (bound(&expr.start), bound(&expr.end));
+                self.helpers.insert(Helper::Slice);
+                let checked =
+                    self.options.bounds_checks && (expr.start.is_some() || expr.end.is_some());
+                if checked {
+                    self.helpers.insert(Helper::CheckedSlice);
+                }
+                format!(
+                    "__oxidescript_{}slice({}, {}, {})",
+                    if checked { "checked_" } else { "" },
+                    lhs,
+                    start,
+                    end
+                )
+            }
+            Expression::MemberAccessExpression(expr, _) if expr.ident.0 == "length" => {
+                format!("#{}", self.prefix(&expr.lhs, indent))
+            }
+            Expression::MemberAccessExpression(expr, _) => {
+                format!("{}.{}", self.prefix(&expr.lhs, indent), name(&expr.ident))
+            }
+            Expression::OptionalExpression(_, _) => {
+                unreachable!("optional chains are handled above")
+            }
+            // casts only change the type
+            Expression::CastExpression(expr, _) => self.expression(&expr.value, indent),
+            Expression::IsExpression(expr, _) => match &*expr.value {
+                Expression::IdentifierExpression(ident, _) => type_check(&self.variable(ident), &expr.type_.0),
+                value => self.iife(indent, |ctx| {
+                    let mut body = line(
+                        indent + 1,
+                        &format!("local __value = {}", ctx.expression(value, indent + 1)),
+                    );
+                    body.push_str(&line(
+                        indent + 1,
+                        &format!("return {}", type_check("__value", &expr.type_.0)),
+                    ));
+                    body
+                }),
+            },
+            Expression::StructExpression(expr, _) => {
+                let fields = expr
+                    .fields
+                    .iter()
+                    .map(|(field, value)| {
+                        format!("{} = {}", name(field), self.expression(value, indent))
+                    })
+                    .collect::<Vec<_>>();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            Expression::PathExpression(expr, _) => expr
+                .segments
+                .iter()
+                .enumerate()
+                .map(|(index, segment)| match &self.self_type {
+                    Some(self_type) if index == 0 && segment.0 == "Self" => self.variable(self_type),
+                    _ if index == 0 => self.variable(segment),
+                    _ => name(segment),
+                })
+                .collect::<Vec<_>>()
+                .join("."),
+            // Lua has no dynamic imports, the module is loaded synchronously through its module
+            // name instead of evaluating to a promise
+            Expression::ImportExpression(expr, _) => format!(
+                "require({})",
+                quote_string(&expr.path.trim_start_matches("./").replace('/', "."))
+            ),
+        }
+    }
+
+    /// Assignments are statements in Lua, so an assignment used as a value is wrapped in a function
+    /// returning the target. The locals binding its object and index are scoped with `do ... end`
+    fn assignment(&mut self, expr: &AssignmentExpr, result: bool, indent: usize) -> String {
+        let mut bindings = vec![];
+        let (target, value) = expr.clone().spelled_out(|value| {
+            let name = Identifier(format!("__target_{}", bindings.len()));
+            bindings.push((name.clone(), value));
+            Expression::IdentifierExpression(name, Span::default())
+        });
+        let scoped = result || !bindings.is_empty();
+        let inner = if scoped { indent + 1 } else { indent };
+        let mut code = String::new();
+        for (name, value) in &bindings {
+            let value = self.expression(value, inner);
+            code.push_str(&line(inner, &format!("local {} = {}", name.0, value)));
+        }
+        let target = self.expression(&target, inner);
+        let value = self.expression(&value, inner);
+        code.push_str(&line(inner, &format!("{} = {}", target, value)));
+        if result {
+            code.push_str(&line(inner, &format!("return {}", target)));
+            iife(code, indent)
+        } else if scoped {
+            format!("{}{}{}", line(indent, "do"), code, line(indent, "end"))
+        } else {
+            code
+        }
+    }
+
+    /// `a?.b.c` is `nil` if `a` is, so the rest of the chain is only evaluated if it isn't
+    fn optional_chain(&mut self, expression: &Expression, indent: usize) -> String {
+        let (receiver, rest) = OptionalExpr::split_chain(
+            expression,
+            &Expression::IdentifierExpression(
+                Identifier("__optional".to_string()),
+                Span::default(),
+            ),
+        )
+        .expect("the chain has a `?.` or `?[`");
+        let receiver = self.expression(&receiver, indent + 1);
+        let mut body = line(indent + 1, &format!("local __optional = {}", receiver));
+        body.push_str(&line(indent + 1, "if __optional == nil then"));
+        body.push_str(&line(indent + 2, "return nil"));
+        body.push_str(&line(indent + 1, "end"));
+        let rest = self.expression(&rest, indent + 1);
+        body.push_str(&line(indent + 1, &format!("return {}", rest)));
+        iife(body, indent)
+    }
+
+    fn expressions(&mut self, expressions: &[Expression], indent: usize) -> Vec<String> {
+        expressions
+            .iter()
+            .map(|expression| self.expression(expression, indent))
+            .collect()
+    }
+
+    /// Calls, indexing and member accesses need a variable, call or parenthesized expression on
+    /// their left side
+    fn prefix(&mut self, expression: &Expression, indent: usize) -> String {
+        let code = self.expression(expression, indent);
+        match expression {
+            Expression::IdentifierExpression(_, _)
+            | Expression::PathExpression(_, _)
+            | Expression::MemberAccessExpression(_, _)
+            | Expression::OptionalExpression(_, _)
+            | Expression::AssignmentExpression(_, _)
+            | Expression::IndexExpression(_, _)
+            | Expression::TupleIndexExpression(_, _)
+            | Expression::SliceExpression(_, _)
+            | Expression::RangeExpression(_, _)
+            | Expression::CallExpression(_, _)
+            | Expression::TaggedTemplateExpression(_, _)
+            | Expression::InfixExpression(_, _)
+            | Expression::IfExpression(_, _)
+            | Expression::BlockExpression(_, _)
+            | Expression::ForExpression(_, _)
+            | Expression::WhileExpression(_, _)
+            | Expression::MatchExpression(_, _) => code,
+            _ => format!("({})", code),
+        }
+    }
+
+    /// The same calls as the intrinsics of the javascript compiler are lowered to Lua builtins
+    fn call(&mut self, call: &CallExpr, indent: usize) -> String {
+        let prelude = self.options.prelude
+            && !matches!(&*call.lhs, Expression::IdentifierExpression(ident, _)
+                if self.shadowed_prelude.contains(&ident.0));
+        match (&*call.lhs, call.arguments.as_slice()) {
+            (Expression::IdentifierExpression(ident, _), [value]) if ident.0 == "clone" => {
+                self.clone_value(value, indent)
+            }
+            (Expression::MemberAccessExpression(expr, _), []) if expr.ident.0 == "clone" => {
+                self.clone_value(&expr.lhs, indent)
+            }
+            // what string prefix patterns of `match` are lowered to
+            (Expression::MemberAccessExpression(expr, _), [prefix])
+                if expr.ident.0 == "startsWith" =>
+            {
+                let lhs = self.expression(&expr.lhs, indent);
+                let prefix = self.expression(prefix, indent);
+                format!("(string.sub({}, 1, #{}) == {})", lhs, prefix, prefix)
+            }
+            (Expression::MemberAccessExpression(expr, _), [start]) if expr.ident.0 == "slice" => {
+                let lhs = self.expression(&expr.lhs, indent);
+                let start = self.expression(start, indent);
+                format!("string.sub({}, {} + 1)", lhs, start)
+            }
+            (Expression::IdentifierExpression(ident, _), arguments)
+                if prelude && ident.0 == "println" =>
+            {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.string(argument, indent))
+                    .collect::<Vec<_>>();
+                format!("print({})", arguments.join(", "))
+            }
+            (Expression::IdentifierExpression(ident, _), arguments)
+                if prelude && ident.0 == "eprintln" =>
+            {
+                let mut parts = arguments
+                    .iter()
+                    .map(|argument| self.string(argument, indent))
+                    .collect::<Vec<_>>()
+                    .join(", \" \", ");
+                if !parts.is_empty() {
+                    parts.push_str(", ");
+                }
+                format!("io.stderr:write({}\"\\n\")", parts)
+            }
+            // a level of 0 leaves out the position, which would point into the compiled code
+            (Expression::IdentifierExpression(ident, _), [message])
+                if prelude && ident.0 == "panic" =>
+            {
+                format!("error({}, 0)", self.expression(message, indent))
+            }
+            // integers wrap around in Lua, so the operation is done on floats
+            (Expression::IdentifierExpression(ident, _), [lhs, rhs])
+                if prelude && InfixOperator::checked(&ident.0).is_some() =>
+            {
+                let op = match InfixOperator::checked(&ident.0).unwrap() {
+                    InfixOperator::Plus => "+",
+                    InfixOperator::Minus => "-",
+                    InfixOperator::Multiply => "*",
+                    _ => "/",
+                };
+                self.helpers.insert(Helper::Checked);
+                format!(
+                    "__oxidescript_checked({} * 1.0 {} {})",
+                    self.prefix(lhs, indent),
+                    op,
+                    self.prefix(rhs, indent)
+                )
+            }
+            (Expression::PathExpression(path, _), arguments)
+                if prelude && path.segments.len() == 2 =>
+            {
+                match (
+                    path.segments[0].0.as_str(),
+                    path.segments[1].0.as_str(),
+                    arguments,
+                ) {
+                    ("Vec", "new", []) => "{}".to_string(),
+                    ("Vec", "from", [iterable]) => {
+                        format!("{{table.unpack({})}}", self.expression(iterable, indent))
+                    }
+                    ("array", "new_2d", [_, _, _]) => {
+                        self.helpers.insert(Helper::New2d);
+                        format!(
+                            "__oxidescript_new_2d({})",
+                            self.expressions(arguments, indent).join(", ")
+                        )
+                    }
+                    ("worker", "spawn", [_]) => {
+                        "error(\"worker::spawn is not supported by the lua target\")".to_string()
+                    }
+                    ("promise", "new", [_]) => {
+                        "error(\"promise::new is not supported by the lua target\")".to_string()
+                    }
+                    ("task", name @ ("spawn" | "join_all" | "race"), [_]) => format!(
+                        "error(\"task::{} is not supported by the lua target\")",
+                        name
+                    ),
+                    _ => self.regular_call(call, indent),
+                }
+            }
+            _ => self.regular_call(call, indent),
+        }
+    }
+
+    fn regular_call(&mut self, call: &CallExpr, indent: usize) -> String {
+        let lhs = self.prefix(&call.lhs, indent);
+        let arguments = self.expressions(&call.arguments, indent);
+        format!("{}({})", lhs, arguments.join(", "))
+    }
+
+    fn clone_value(&mut self, value: &Expression, indent: usize) -> String {
+        self.helpers.insert(Helper::Clone);
+        format!("__oxidescript_clone({})", self.expression(value, indent))
+    }
+}
+
+/// The body of a loop, whose statements and value are compiled on their own. A body with a
+/// `defer` becomes a block as its value, as the `defer` needs the whole body
+fn loop_block(body: &Block) -> Cow<'_, Block> {
+    match body.split_at_defer() {
+        Some(_) => Cow::Owned(Block {
+            statements: vec![],
+            return_value: Some(Expression::BlockExpression(
+                Box::new(body.clone()),
+                body.span,
+            )),
+            span: body.span,
+        }),
+        None => Cow::Borrowed(body),
+    }
+}
+
+fn is_hoisted(statement: &Statement) -> bool {
+    match statement {
+        Statement::DeclarationStatement(Declaration::TraitDeclaration(decl), _) => {
+            decl.has_defaults()
+        }
+        statement => matches!(
+            statement,
+            Statement::DeclarationStatement(
+                Declaration::FunctionDeclaration { .. } | Declaration::ImplDeclaration(_),
+                _
+            )
+        ),
+    }
+}
+
+/// The locals a declaration introduces, impl blocks introduce the table of their type and traits
+/// with default methods the table of the defaults. Destructurings can introduce several
+fn declared_names(declaration: &Declaration) -> Vec<&Identifier> {
+    match declaration {
+        Declaration::ConstDeclaration(name, ..) | Declaration::LetDeclaration(name, _, _, _) => {
+            vec![name]
+        }
+        Declaration::DestructuringDeclaration(pattern, value, _, _) => pattern
+            .bindings(value)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+        Declaration::FunctionDeclaration { name, .. } => vec![name],
+        Declaration::TraitDeclaration(decl) => decl
+            .has_defaults()
+            .then_some(&decl.name)
+            .into_iter()
+            .collect(),
+        Declaration::StructDeclaration(_)
+        | Declaration::TypeDeclaration(_)
+        | Declaration::ExternDeclaration(_) => vec![],
+        Declaration::EnumDeclaration(decl) => vec![&decl.name],
+        Declaration::ImplDeclaration(decl) => vec![&decl.target],
+        Declaration::ModDeclaration(decl) => vec![&decl.name],
+        Declaration::ModFileDeclaration(name) => vec![name],
+        Declaration::UseDeclaration(_) => vec![],
+    }
+}
+
+fn name(ident: &Identifier) -> String {
+    if LUA_KEYWORDS.contains(&ident.0.as_str()) {
+        format!("{}_", ident.0)
+    } else {
+        ident.0.clone()
+    }
+}
+
+/// Number literals are written as floats, since Lua integers wrap around where the numbers of
+/// javascript lose precision
+fn float(number: &Number) -> String {
+    match number {
+        Number::I { value, .. } => format!("{}.0", value),
+        Number::F(value) if value.contains(['.', 'e', 'E']) => value.clone(),
+        Number::F(value) => format!("{}.0", value),
+    }
+}
+
+/// Whether the expression is known to be a boolean, which Lua conditions can use as is
+fn is_boolean(expression: &Expression) -> bool {
+    match expression {
+        Expression::LiteralExpression(Literal::BooleanLiteral(_), _)
+        | Expression::IsExpression(_, _) => true,
+        Expression::UnaryExpression(expr, _) => expr.op == UnaryOperator::LogicalNot,
+        // what string prefix patterns of `match` are lowered to
+        Expression::CallExpression(call, _) => matches!(
+            &*call.lhs,
+            Expression::MemberAccessExpression(expr, _) if expr.ident.0 == "startsWith"
+        ),
+        Expression::InfixExpression(expr, _) => match expr.op {
+            InfixOperator::Equal
+            | InfixOperator::NotEqual
+            | InfixOperator::GreaterThan
+            | InfixOperator::LessThan
+            | InfixOperator::GreaterThanEqual
+            | InfixOperator::LessThanEqual => true,
+            InfixOperator::LogicalAnd | InfixOperator::LogicalOr => {
+                is_boolean(&expr.lhs) && is_boolean(&expr.rhs)
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether the expression is known to be a number without knowing the types of variables, so `+`
+/// can be emitted without the helper
+fn is_number(expression: &Expression) -> bool {
+    match expression {
+        Expression::LiteralExpression(Literal::NumberLiteral(_), _) => true,
+        Expression::UnaryExpression(expr, _) => {
+            matches!(expr.op, UnaryOperator::Minus | UnaryOperator::Plus)
+        }
+        Expression::InfixExpression(expr, _) => match expr.op {
+            InfixOperator::Plus => is_number(&expr.lhs) && is_number(&expr.rhs),
+            InfixOperator::Minus
+            | InfixOperator::Multiply
+            | InfixOperator::Divide
+            | InfixOperator::Modulo => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_string(expression: &Expression) -> bool {
+    match expression {
+        Expression::LiteralExpression(Literal::StringLiteral(_), _)
+        | Expression::TemplateExpression(_, _) => true,
+        Expression::InfixExpression(expr, _) => {
+            expr.op == InfixOperator::Plus && (is_string(&expr.lhs) || is_string(&expr.rhs))
+        }
+        _ => false,
+    }
+}
+
+/// The condition of `value is type`. Literals are compared with `==`, and arrays, structs and every
+/// other type that isn't a primitive are tables
+fn type_check(value: &str, type_: &str) -> String {
+    let members = union_members(type_);
+    if members.len() > 1 {
+        let mut checks = members
+            .into_iter()
+            .map(|member| type_check(value, member))
+            .collect::<Vec<_>>();
+        // `null` and `undefined` are checked the same way
+        checks.dedup();
+        if let [check] = &checks[..] {
+            return check.clone();
+        }
+        return format!("({})", checks.join(" or "));
+    }
+    match type_ {
+        "number" | "string" | "boolean" => format!("(type({}) == \"{}\")", value, type_),
+        "bool" => format!("(type({}) == \"boolean\")", value),
+        "null" | "undefined" => format!("({} == nil)", value),
+        literal if type_as_literal(literal).is_some() => {
+            let literal = match type_as_literal(literal).unwrap() {
+                Literal::StringLiteral(string) => quote_string(&string),
+                Literal::NumberLiteral(number) => number.to_string(),
+                Literal::BooleanLiteral(value) => value.to_string(),
+            };
+            format!("({} == {})", value, literal)
+        }
+        _ => format!("(type({}) == \"table\")", value),
+    }
+}
+
+fn line(indent: usize, code: &str) -> String {
+    format!("{}{}\n", INDENT.repeat(indent), code)
+}
+
+/// `(function() ... end)()`, the body is already indented one level deeper than `indent`
+fn iife(body: String, indent: usize) -> String {
+    format!("(function()\n{}{}end)()", body, INDENT.repeat(indent))
+}
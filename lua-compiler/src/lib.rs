@@ -0,0 +1,1297 @@
+use oxidescript::{
+    compiler::Compiler,
+    parser::{
+        ast::Program,
+        dispose::lower_with,
+        enums::lower_discriminants,
+        module::lower_uses,
+        types::{lower_index_operators, lower_iterators, lower_method_calls, lower_type_aliases},
+    },
+    resolve::{shadowed_names, shadowed_prelude},
+};
+
+mod compile;
+
+use compile::{LuaCompilerContext, LUA_BUILTINS};
+
+#[derive(Clone, Debug)]
+pub struct LuaCompilerOptions {
+    /// Whether prelude items like `println` and `Vec::new` are in scope without being declared
+    pub prelude: bool,
+    /// Whether slices like `items[1..3]` raise an error if a bound is outside of the table or
+    /// string, instead of clamping it. On with the `debug` flag of `oxide.toml`
+    pub bounds_checks: bool,
+}
+
+impl Default for LuaCompilerOptions {
+    fn default() -> Self {
+        LuaCompilerOptions {
+            prelude: true,
+            bounds_checks: false,
+        }
+    }
+}
+
+/// Names of the Lua standard library that programs can use without declaring them
+pub const GLOBALS: &[&str] = &[
+    "print",
+    "type",
+    "tostring",
+    "tonumber",
+    "pairs",
+    "ipairs",
+    "next",
+    "select",
+    "error",
+    "assert",
+    "pcall",
+    "xpcall",
+    "require",
+    "setmetatable",
+    "getmetatable",
+    "rawget",
+    "rawset",
+    "rawequal",
+    "rawlen",
+    "table",
+    "string",
+    "math",
+    "os",
+    "io",
+    "utf8",
+    "coroutine",
+];
+
+/// Compiles to Lua 5.4 source, for embedding oxidescript in hosts that run Lua.
+///
+/// Values map to their closest Lua equivalent: arrays are sequences starting at index 1, structs
+/// are tables and impl blocks are tables of functions taking `self` as their first parameter, like
+/// the object mode of the javascript compiler. Getters and setters become regular functions of the
+/// impl table
+pub struct LuaCompiler {
+    options: LuaCompilerOptions,
+}
+
+impl LuaCompiler {
+    pub fn with_options(options: LuaCompilerOptions) -> Self {
+        LuaCompiler { options }
+    }
+}
+
+impl Compiler for LuaCompiler {
+    fn new() -> Self {
+        Self::with_options(LuaCompilerOptions::default())
+    }
+
+    fn compile(&self, program: Program) -> String {
+        let mut ctx = LuaCompilerContext::new(&self.options);
+        ctx.shadowed_prelude = shadowed_prelude(&program);
+        ctx.shadowed_builtins = shadowed_names(&program, LUA_BUILTINS);
+        let body = ctx.statements(
+            &lower_uses(lower_type_aliases(lower_discriminants(lower_method_calls(
+                lower_iterators(lower_index_operators(lower_with(program))),
+            )))),
+            0,
+        );
+        // helpers are only emitted if the program needs them
+        let mut code = ctx
+            .helpers
+            .iter()
+            .map(|helper| helper.definition())
+            .collect::<String>();
+        code.push_str(&body);
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use oxidescript::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+        stack::with_stack,
+    };
+
+    use super::*;
+
+    fn compile(input: &str) -> String {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        LuaCompiler::new().compile(program)
+    }
+
+    #[test]
+    fn functions_are_hoisted() {
+        assert_eq!(
+            compile(
+                "let x = double(2);
+                fn double(n: number) {
+                    n * 2
+                }"
+            ),
+            "local x, double
+function double(n)
+    return (n * 2.0)
+end
+x = double(2.0)
+"
+        );
+    }
+
+    #[test]
+    fn locals_and_operators() {
+        assert_eq!(
+            compile(
+                r#"let a = [1, 2, 3];
+                const b = a[0] + a.length;
+                let c = !(b != 2) && "x" + "y" == "xy";
+                let d = b + c;
+                let e = db.sql`SELECT {d} FROM t`;"#
+            ),
+            r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+local function __oxidescript_add(a, b)
+    if type(a) == "string" or type(b) == "string" then
+        return __oxidescript_tostring(a) .. __oxidescript_tostring(b)
+    end
+    return a + b
+end
+local a = {1.0, 2.0, 3.0}
+local b <const> = __oxidescript_add(a[1], #a)
+local c = (not (b ~= 2.0) and (("x" .. "y") == "xy"))
+local d = __oxidescript_add(b, c)
+local e = db.sql({"SELECT ", " FROM t"}, d)
+"#
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_eq!(
+            compile(
+                r#"fn sign(n: number) {
+                    if n < 0 { -1 } else if n == 0 { 0 } else { 1 }
+                }
+                for n in [1, 2] {
+                    println(n, sign(n));
+                }
+                let doubled = for n in [1, 2] { n * 2 };
+                let y = if true { 1 } else { 2 };
+                if y > 1 { eprintln("big"); }
+                while y > 1 { eprintln("loop"); }
+                match y { 1 | 2 => println("small"), n => println(n) }
+                match path { "/users/" .. id => println(id), _ => println("?") }"#
+            ),
+            r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+local sign, doubled, y
+function sign(n)
+    if (n < 0.0) then
+        return -1.0
+    elseif (n == 0.0) then
+        return 0.0
+    else
+        return 1.0
+    end
+end
+for _, n in ipairs({1.0, 2.0}) do
+    print(__oxidescript_tostring(n), __oxidescript_tostring(sign(n)))
+end
+doubled = (function()
+    local __output = {}
+    for _, n in ipairs({1.0, 2.0}) do
+        __output[#__output + 1] = (n * 2.0)
+    end
+    return __output
+end)()
+y = (function()
+    if true then
+        return 1.0
+    else
+        return 2.0
+    end
+end)()
+if (y > 1.0) then
+    io.stderr:write("big", "\n")
+end
+while (y > 1.0) do
+    io.stderr:write("loop", "\n")
+end
+if ((y == 1.0) or (y == 2.0)) then
+    print("small")
+else
+    local n <const> = y
+    print(__oxidescript_tostring(n))
+end
+if (string.sub(path, 1, #"/users/") == "/users/") then
+    local id <const> = string.sub(path, #("/users/") + 1)
+    print(__oxidescript_tostring(id))
+else
+    print("?")
+end
+"#
+        );
+    }
+
+    #[test]
+    fn structs_and_modules() {
+        assert_eq!(
+            compile(
+                "struct Point { x: number, y: number }
+                impl Point {
+                    const ORIGIN = Point { x: 0, y: 0 };
+                    fn new(x: number, y: number) { Self { x: x, y: y } }
+                    fn sum(self) { self.x + self.y }
+                }
+                mod geometry {
+                    const SCALE = 2;
+                    pub fn scale(p: Point) { Point::new(p.x * SCALE, p.y * SCALE) }
+                }
+                let p = geometry::scale(Point::new(1, 2));
+                let end = clone(p).x;"
+            ),
+            r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+local function __oxidescript_add(a, b)
+    if type(a) == "string" or type(b) == "string" then
+        return __oxidescript_tostring(a) .. __oxidescript_tostring(b)
+    end
+    return a + b
+end
+local function __oxidescript_clone(value)
+    if type(value) ~= "table" then
+        return value
+    end
+    local copy = {}
+    for key, item in pairs(value) do
+        copy[key] = __oxidescript_clone(item)
+    end
+    return setmetatable(copy, getmetatable(value))
+end
+local Point, geometry, p, end_
+Point = {}
+Point.ORIGIN = { x = 0.0, y = 0.0 }
+function Point.new(x, y)
+    return { x = x, y = y }
+end
+function Point.sum(self)
+    return __oxidescript_add(self.x, self.y)
+end
+geometry = (function()
+    local SCALE, scale
+    function scale(p)
+        return Point.new((p.x * SCALE), (p.y * SCALE))
+    end
+    SCALE = 2.0
+    return { scale = scale }
+end)()
+p = geometry.scale(Point.new(1.0, 2.0))
+end_ = __oxidescript_clone(p).x
+"#
+        );
+    }
+
+    #[test]
+    fn enums() {
+        assert_eq!(
+            compile(
+                "enum Shape { Circle(number), Empty }
+                impl Shape {
+                    fn area(self) {
+                        match self {
+                            Self::Circle(r) if r > 0 => r * r,
+                            Self::Circle(_) | Self::Empty => 0,
+                        }
+                    }
+                }
+                let a = Shape::area(Shape::Circle(2));"
+            ),
+            r#"local Shape, a
+Shape = {}
+function Shape.Circle(value_0)
+    return { tag = "Circle", values = {value_0} }
+end
+Shape.Empty = { tag = "Empty", values = {} }
+function Shape.area(self)
+    if ((self.tag == "Circle") and (self.values[1] > 0.0)) then
+        local r <const> = self.values[1]
+        return (r * r)
+    else
+        return 0.0
+    end
+end
+a = Shape.area(Shape.Circle(2.0))
+"#
+        );
+    }
+
+    #[test]
+    fn recursive_data_types() {
+        assert_eq!(
+            compile(
+                "enum Tree { Leaf(number), Node(Tree, Tree) }
+                fn sum(tree: Tree) -> number {
+                    match tree {
+                        Tree::Leaf(value) => value,
+                        Tree::Node(left, right) => sum(left) + sum(right),
+                    }
+                }
+                let total = sum(Tree::Node(Tree::Leaf(1), Tree::Node(Tree::Leaf(2), Tree::Leaf(3))));"
+            ),
+            r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+local function __oxidescript_add(a, b)
+    if type(a) == "string" or type(b) == "string" then
+        return __oxidescript_tostring(a) .. __oxidescript_tostring(b)
+    end
+    return a + b
+end
+local Tree, sum, total
+function sum(tree)
+    if (tree.tag == "Leaf") then
+        local value <const> = tree.values[1]
+        return value
+    else
+        local left <const> = tree.values[1]
+        local right <const> = tree.values[2]
+        return __oxidescript_add(sum(left), sum(right))
+    end
+end
+Tree = {}
+function Tree.Leaf(value_0)
+    return { tag = "Leaf", values = {value_0} }
+end
+function Tree.Node(value_0, value_1)
+    return { tag = "Node", values = {value_0, value_1} }
+end
+total = sum(Tree.Node(Tree.Leaf(1.0), Tree.Node(Tree.Leaf(2.0), Tree.Leaf(3.0))))
+"#
+        );
+    }
+
+    #[test]
+    fn method_calls_through_values() {
+        // struct values are plain tables, so methods are called on the type with the value as
+        // `self`
+        assert_eq!(
+            compile(
+                "struct Counter { count: number }
+                impl Counter {
+                    fn new(count: number) -> Counter { Counter { count: count } }
+                    fn get(self) -> number { self.count }
+                    fn scaled(self, by: number) -> Counter { Counter::new(self.get() * by) }
+                }
+                let counter = Counter::new(2);
+                let scaled = counter.scaled(3);
+                let count = scaled.scaled(2).get();
+                let get = |value: Counter| value.get();
+                println(get(scaled));"
+            ),
+            r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+local Counter, counter, scaled, count, get
+Counter = {}
+function Counter.new(count)
+    return { count = count }
+end
+function Counter.get(self)
+    return self.count
+end
+function Counter.scaled(self, by)
+    return Counter.new((Counter.get(self) * by))
+end
+counter = Counter.new(2.0)
+scaled = Counter.scaled(counter, 3.0)
+count = Counter.get(Counter.scaled(scaled, 2.0))
+get = function(value) return Counter.get(value) end
+print(__oxidescript_tostring(get(scaled)))
+"#
+        );
+    }
+
+    #[test]
+    fn builder_chains() {
+        // methods returning `Self` have the type of the impl block, so their results are called
+        // through it too
+        assert_eq!(
+            compile(
+                "struct Query { depth: number }
+                impl Query {
+                    fn new() -> Self { Self { depth: 0 } }
+                    fn and(self) -> Self { Self { depth: self.depth - 1 } }
+                    fn twice(self) -> Self { self.and().and() }
+                }
+                let depth = Query::new().and().twice().depth;"
+            ),
+            r#"local Query, depth
+Query = {}
+function Query.new()
+    return { depth = 0.0 }
+end
+function Query.and_(self)
+    return { depth = (self.depth - 1.0) }
+end
+function Query.twice(self)
+    return Query.and_(Query.and_(self))
+end
+depth = Query.twice(Query.and_(Query.new())).depth
+"#
+        );
+    }
+
+    #[test]
+    fn inferred_method_receivers() {
+        // functions and methods without a return type return the type of their last value, so
+        // methods are called through the type on their results and on arrays of them too
+        assert_eq!(
+            compile(
+                "struct Point { x: number, y: number }
+                impl Point {
+                    fn area(self) { self.x * self.y }
+                    fn scaled(self, by: number) { Point { x: self.x * by, y: self.y * by } }
+                }
+                fn at(x: number) { Point { x: x, y: 4 } }
+                let area = at(3).scaled(2).area();
+                for point in [at(1), at(2)] { println(point.area()); }"
+            ),
+            r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+local Point, at, area
+function at(x)
+    return { x = x, y = 4.0 }
+end
+Point = {}
+function Point.area(self)
+    return (self.x * self.y)
+end
+function Point.scaled(self, by)
+    return { x = (self.x * by), y = (self.y * by) }
+end
+area = Point.area(Point.scaled(at(3.0), 2.0))
+for _, point in ipairs({at(1.0), at(2.0)}) do
+    print(__oxidescript_tostring(Point.area(point)))
+end
+"#
+        );
+    }
+
+    #[test]
+    fn methods_named_like_fields() {
+        // `value.area` is the field, the method is called through the type
+        assert_eq!(
+            compile(
+                "struct Shape { area: number, side: number }
+                impl Shape {
+                    fn area(self) -> number { self.side * self.side }
+                }
+                let shape = Shape { area: 0, side: 3 };
+                let stored = shape.area;
+                let computed = Shape::area(shape);"
+            ),
+            r#"local Shape, shape, stored, computed
+Shape = {}
+function Shape.area(self)
+    return (self.side * self.side)
+end
+shape = { area = 0.0, side = 3.0 }
+stored = shape.area
+computed = Shape.area(shape)
+"#
+        );
+    }
+
+    #[test]
+    fn index_operator() {
+        // indexing a type implementing `Index` calls its `get`, arrays inside `get` are still 1-based tables
+        assert_eq!(
+            compile(
+                "struct Rounds { points: [number] }
+                impl Index for Rounds {
+                    fn get(self, round: number) -> number { self.points[round - 1] }
+                }
+                let rounds = Rounds { points: [3, 5] };
+                let first = rounds[1];"
+            ),
+            r#"local Rounds, rounds, first
+Rounds = {}
+function Rounds.get(self, round)
+    return self.points[(round - 1.0) + 1]
+end
+rounds = { points = {3.0, 5.0} }
+first = Rounds.get(rounds, 1.0)
+"#
+        );
+    }
+
+    #[test]
+    fn iterators() {
+        // `for` over an `Iterator` calls `next` until it returns nothing
+        assert_eq!(
+            compile(
+                "struct Countdown { left: number }
+                impl Iterator for Countdown {
+                    fn next(self) -> number | null {
+                        if self.left == 0 { return; }
+                        self.left = self.left - 1;
+                        self.left * 10
+                    }
+                }
+                for n in Countdown { left: 3 } { print(n); }"
+            ),
+            r#"local Countdown
+Countdown = {}
+function Countdown.next(self)
+    if (self.left == 0.0) then
+        return
+    end
+    self.left = (self.left - 1.0)
+    return (self.left * 10.0)
+end
+do
+    local __iterator_0 <const> = { left = 3.0 }
+    while true do
+        local n = Countdown.next(__iterator_0)
+        if (n == nil) then
+            break
+        end
+        print(n)
+    end
+end
+"#
+        );
+    }
+
+    #[test]
+    fn defer() {
+        // the rest of the block runs before the `defer`, even if it returns early or fails
+        assert_eq!(
+            compile(
+                "fn read(file: number) -> number {
+                    defer { close(file); }
+                    if file == 0 { return 0; }
+                    file * 2
+                }
+                for file in files {
+                    defer { close(file); }
+                    print(file);
+                }"
+            ),
+            r#"local read
+function read(file)
+    local __deferred_ok, __deferred_value = pcall(function()
+        if (file == 0.0) then
+            return 0.0
+        end
+        return (file * 2.0)
+    end)
+    close(file)
+    if not __deferred_ok then error(__deferred_value, 0) end
+    return __deferred_value
+end
+for _, file in ipairs(files) do
+    do
+        local __deferred_ok, __deferred_value = pcall(function()
+            print(file)
+        end)
+        close(file)
+        if not __deferred_ok then error(__deferred_value, 0) end
+    end
+end
+"#
+        );
+    }
+
+    #[test]
+    fn panic() {
+        assert_eq!(
+            compile("let size = if n > 0 { n } else { panic(\"negative\") };"),
+            r#"local size = (function()
+    if (n > 0.0) then
+        return n
+    else
+        return error("negative", 0)
+    end
+end)()
+"#
+        );
+    }
+
+    #[test]
+    fn shadowed_prelude() {
+        assert_eq!(
+            compile("fn println(x: number) { x } println(1); eprintln(2);"),
+            r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+local println
+function println(x)
+    return x
+end
+println(1.0)
+io.stderr:write(__oxidescript_tostring(2.0), "\n")
+"#
+        );
+    }
+
+    #[test]
+    fn with_blocks() {
+        // the value is closed when the body is left, like with a `defer`
+        assert_eq!(
+            compile(
+                "fn first_line(path: string) -> string {
+                    with file = open(path) {
+                        file.read_line()
+                    }
+                }"
+            ),
+            r#"local first_line
+function first_line(path)
+    do
+        local file = open(path)
+        local __deferred_ok, __deferred_value = pcall(function()
+            return file.read_line()
+        end)
+        file.close()
+        if not __deferred_ok then error(__deferred_value, 0) end
+        return __deferred_value
+    end
+end
+"#
+        );
+    }
+
+    #[test]
+    fn async_functions() {
+        // `async` functions compile to regular functions, there is nothing to wait with
+        assert_eq!(
+            compile("async fn load(path: string) -> string { fetch(path).await }"),
+            r#"local load
+function load(path)
+    return error(".await is not supported by the lua target")
+end
+"#
+        );
+    }
+
+    #[test]
+    fn generic_data_types() {
+        assert_eq!(
+            compile(
+                r#"struct Pair<A, B> { first: A, second: B }
+                enum Option<T> { Some(T), None }
+                fn first(o: Option<Pair<number, string>>) {
+                    match o {
+                        Option::Some(pair) => pair.first,
+                        Option::None => 0,
+                    }
+                }
+                let a = first(Option::Some(Pair { first: 1, second: "a" }));"#
+            ),
+            r#"local Option, first, a
+function first(o)
+    if (o.tag == "Some") then
+        local pair <const> = o.values[1]
+        return pair.first
+    else
+        return 0.0
+    end
+end
+Option = {}
+function Option.Some(value_0)
+    return { tag = "Some", values = {value_0} }
+end
+Option.None = { tag = "None", values = {} }
+a = first(Option.Some({ first = 1.0, second = "a" }))
+"#
+        );
+    }
+
+    #[test]
+    fn traits() {
+        assert_eq!(
+            compile(
+                r#"trait Ord { fn cmp(self, other: Self) -> number; }
+                struct Version { major: number }
+                impl Ord for Version {
+                    fn cmp(self, other: Version) -> number { self.major - other.major }
+                }
+                fn max<T: Ord>(a: T, b: T) -> T {
+                    if a.cmp(b) > 0 { a } else { b }
+                }"#
+            ),
+            r#"local Version, max
+function max(a, b)
+    if (a.cmp(b) > 0.0) then
+        return a
+    else
+        return b
+    end
+end
+Version = {}
+function Version.cmp(self, other)
+    return (self.major - other.major)
+end
+"#
+        );
+    }
+
+    #[test]
+    fn default_trait_methods() {
+        assert_eq!(
+            compile(
+                r#"trait Describe {
+                    fn name(self) -> string;
+                    fn describe(self) -> string { self.name() }
+                }
+                struct Cat { age: number }
+                struct Dog { age: number }
+                impl Describe for Cat {
+                    fn name(self) -> string { "cat" }
+                }
+                impl Describe for Dog {
+                    fn name(self) -> string { "dog" }
+                    fn describe(self) -> string { "a good dog" }
+                }"#
+            ),
+            r#"local Describe, Cat, Dog
+Describe = {}
+function Describe.describe(self)
+    return self.name()
+end
+Cat = {}
+function Cat.name(self)
+    return "cat"
+end
+Cat.describe = Describe.describe
+Dog = {}
+function Dog.name(self)
+    return "dog"
+end
+function Dog.describe(self)
+    return "a good dog"
+end
+"#
+        );
+    }
+
+    #[test]
+    fn mod_and_use_declarations() {
+        assert_eq!(
+            compile(
+                "mod util;
+                use util::double;
+                mod geometry { pub const SCALE = 2; }
+                fn scaled(x: number) {
+                    use geometry::SCALE;
+                    double(x) * SCALE
+                }"
+            ),
+            r#"local util, double, geometry, scaled
+function scaled(x)
+    local SCALE <const> = geometry.SCALE
+    return (double(x) * SCALE)
+end
+util = require("util")
+double = util.double
+geometry = (function()
+    local SCALE <const> = 2.0
+    return { SCALE = SCALE }
+end)()
+"#
+        );
+    }
+
+    #[test]
+    fn enum_discriminants() {
+        assert_eq!(
+            compile(
+                "enum Status { Ok = 200, NotFound = 404 }
+                let code = match s { Status::Ok => Status::Ok as number, Status::NotFound => 0 };"
+            ),
+            r#"local Status, code
+Status = {}
+Status.Ok = 200.0
+Status.NotFound = 404.0
+code = (function()
+    if (s == 200.0) then
+        return Status.Ok
+    else
+        return 0.0
+    end
+end)()
+"#
+        );
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            compile(
+                "#[derive(Flags)] enum Mode { Fast, Safe }
+                let safe = Mode::contains(Mode::all(), Mode::Safe);"
+            ),
+            r#"local Mode, safe
+Mode = {}
+Mode.Fast = 1.0
+Mode.Safe = 2.0
+function Mode.empty()
+    return 0.0
+end
+function Mode.all()
+    return 3.0
+end
+function Mode.contains(flags, other)
+    return ((flags & other) == other)
+end
+safe = Mode.contains(Mode.all(), Mode.Safe)
+"#
+        );
+    }
+
+    #[test]
+    fn optional_chains() {
+        assert_eq!(
+            compile(
+                "fn first(items: any, map: any) {
+                    let x = items?[0];
+                    map?.get(x)
+                }"
+            ),
+            r#"local first
+function first(items, map)
+    local x = (function()
+        local __optional = items
+        if __optional == nil then
+            return nil
+        end
+        return __optional[1]
+    end)()
+    return (function()
+        local __optional = map
+        if __optional == nil then
+            return nil
+        end
+        return __optional.get(x)
+    end)()
+end
+"#
+        );
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(
+            compile(r#"let greeting = f"hello {name}, {count + 1} times";"#),
+            r#"local function __oxidescript_tostring(value)
+    if math.type(value) == "float" and value % 1 == 0 and math.abs(value) < 1e21 then
+        return string.format("%.0f", value)
+    end
+    return tostring(value)
+end
+local function __oxidescript_add(a, b)
+    if type(a) == "string" or type(b) == "string" then
+        return __oxidescript_tostring(a) .. __oxidescript_tostring(b)
+    end
+    return a + b
+end
+local greeting = (__oxidescript_add((__oxidescript_add("hello ", name) .. ", "), __oxidescript_add(count, 1.0)) .. " times")
+"#
+        );
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(
+            compile(
+                "fn main() {
+                    let count = 0;
+                    fn reset() {
+                        count = 0;
+                    }
+                    lookup(key).value *= 2;
+                    let last = count -= 1;
+                }"
+            ),
+            "local main
+function main()
+    local count, reset, last
+    function reset()
+        count = 0.0
+    end
+    count = 0.0
+    do
+        local __target_0 = lookup(key)
+        __target_0.value = (__target_0.value * 2.0)
+    end
+    last = (function()
+        count = (count - 1.0)
+        return count
+    end)()
+end
+"
+        );
+    }
+
+    #[test]
+    fn slices() {
+        let input = "let middle = items[1..n]; let tail = name[2..];";
+        assert_eq!(
+            compile(input),
+            r#"local function __oxidescript_slice(value, start, stop)
+    local length = #value
+    start = start or 0
+    stop = stop or length
+    if start < 0 then
+        start = math.max(length + start, 0)
+    end
+    if stop < 0 then
+        stop = math.max(length + stop, 0)
+    end
+    stop = math.min(stop, length)
+    if type(value) == "string" then
+        return string.sub(value, start + 1, stop)
+    end
+    return table.move(value, start + 1, stop, 1, {})
+end
+local middle = __oxidescript_slice(items, 1.0, n)
+local tail = __oxidescript_slice(name, 2.0, nil)
+"#
+        );
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        let checked = LuaCompiler::with_options(LuaCompilerOptions {
+            prelude: true,
+            bounds_checks: true,
+        });
+        assert!(checked.compile(program).ends_with(
+            "local middle = __oxidescript_checked_slice(items, 1.0, n)
+local tail = __oxidescript_checked_slice(name, 2.0, nil)
+"
+        ));
+    }
+
+    #[test]
+    fn ranges() {
+        // numeric `for` loops include their last number
+        assert_eq!(
+            compile("for i in 0..n { f(i); } for i in 1..3 { f(i); } let all = 0..=n;"),
+            r#"local function __oxidescript_range(first, last)
+    local numbers = {}
+    for i = first, last do
+        numbers[#numbers + 1] = i
+    end
+    return numbers
+end
+for i = 0.0, n - 1 do
+    f(i)
+end
+for i = 1.0, 2 do
+    f(i)
+end
+local all = __oxidescript_range(0.0, n)
+"#
+        );
+    }
+
+    #[test]
+    fn tuples() {
+        assert_eq!(
+            compile(
+                "let pair = (1, \"a\"); let (first, second) = pair; let ((a, _), c) = f(); \
+                 log(pair.1, (5,).0);"
+            ),
+            r#"local pair = {1.0, "a"}
+local first, second = pair[1], pair[2]
+local __value = f()
+local a, c = __value[1][1], __value[2]
+log(pair[2], ({5.0})[1])
+"#
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        assert_eq!(
+            compile(
+                "let Point { x, y: (a, _) } = p; let Point { x: left, .. } = f(); \
+                 fn area(Point { x, y }: Point) -> number { x * y }"
+            ),
+            r#"local x, a, left, area
+function area(__arg1)
+    local x, y = __arg1.x, __arg1.y
+    return (x * y)
+end
+x, a = p.x, p.y[1]
+local __value = f()
+left = __value.x
+"#
+        );
+    }
+
+    #[test]
+    fn grids() {
+        assert_eq!(
+            compile("let grid = array::new_2d(width, height, 0); grid[y][x] = 1;"),
+            r#"local function __oxidescript_new_2d(width, height, init)
+    local grid = {}
+    for y = 1, height do
+        local row = {}
+        for x = 1, width do
+            row[x] = init
+        end
+        grid[y] = row
+    end
+    return grid
+end
+local grid = __oxidescript_new_2d(width, height, 0.0)
+do
+    local __target_0 = grid[y + 1]
+    __target_0[x + 1] = 1.0
+end
+"#
+        );
+    }
+
+    #[test]
+    fn closures() {
+        assert_eq!(
+            compile(
+                "fn counter() {
+                    let count = 0;
+                    let take = |n: number| { count -= n; count };
+                    sort(|a, b| a - b)
+                }"
+            ),
+            "local counter
+function counter()
+    local count = 0.0
+    local take = function(n)
+        count = (count - n)
+        return count
+    end
+    return sort(function(a, b) return (a - b) end)
+end
+"
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(
+            compile(
+                "fn first_positive(items: [number]) {
+                    for item in items {
+                        if item < 0 { continue; }
+                        if item == 0 { break; }
+                        return item;
+                    }
+                    let fallback = items[0] ?? return;
+                    fallback
+                }"
+            ),
+            "local first_positive
+function first_positive(items)
+    for _, item in ipairs(items) do
+        if (item < 0.0) then
+            goto continue_0
+        end
+        if (item == 0.0) then
+            break
+        end
+        do return item end
+        ::continue_0::
+    end
+    local fallback = (function()
+        local __value = items[1]
+        if __value == nil then
+            return error(\"return inside of an expression is not supported by the lua target\")
+        end
+        return __value
+    end)()
+    return fallback
+end
+"
+        );
+    }
+
+    #[test]
+    fn remainder_has_sign_of_dividend() {
+        assert_eq!(
+            compile("let last = index % length;"),
+            "local last = math.fmod(index, length)
+"
+        );
+    }
+
+    #[test]
+    fn checked_operations() {
+        assert_eq!(
+            compile("let total = checked_add(a, b); let ratio = checked_div(a, -b);"),
+            "local function __oxidescript_checked(result)
+    if result ~= result or math.abs(result) > 9007199254740991 then
+        return nil
+    end
+    return result
+end
+local total = __oxidescript_checked(a * 1.0 + b)
+local ratio = __oxidescript_checked(a * 1.0 / (-b))
+"
+        );
+    }
+
+    #[test]
+    fn nullish_coalescing() {
+        assert_eq!(
+            compile(r#"let port = config.port ?? 8080; let name = name ?? "anonymous";"#),
+            r#"local port = (function()
+    local __value = config.port
+    if __value == nil then
+        return 8080.0
+    end
+    return __value
+end)()
+local name = (function()
+    local __value = name
+    if __value == nil then
+        return "anonymous"
+    end
+    return __value
+end)()
+"#
+        );
+    }
+
+    #[test]
+    fn escaped_strings() {
+        assert_eq!(
+            compile(r#"let s = "say \"hi\"\n\tC:\\ \u{1F496}";"#),
+            r#"local s = "say \"hi\"\n\tC:\\ 💖"
+"#
+        );
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(
+            compile(
+                "let a = 1_000; let b = 0xFF; let c = 0b1010; let d = 1e9; let e = 2.5e-3;
+                let f = 0xFFFF_FFFF;"
+            ),
+            "local a = 1000.0\nlocal b = 255.0\nlocal c = 10.0\nlocal d = 1000000000.0\nlocal e = 0.0025\nlocal f = 4294967295.0\n"
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 1000;
+        let arrays = with_stack(|| {
+            compile(&format!(
+                "let a = {}1{};",
+                "[".repeat(depth),
+                "]".repeat(depth)
+            ))
+        });
+        assert_eq!(
+            arrays,
+            format!("local a = {}1.0{}\n", "{".repeat(depth), "}".repeat(depth))
+        );
+        let ifs = with_stack(|| {
+            compile(&format!(
+                "let a = {}1{};",
+                "if x { ".repeat(depth),
+                " } else { 0 }".repeat(depth)
+            ))
+        });
+        assert!(ifs.contains(&format!("\n{}return 1.0\n", "    ".repeat(depth + 1))));
+    }
+
+    #[test]
+    fn is_expressions() {
+        assert_eq!(
+            compile(
+                r#"let a = value is string | null;
+                let b = items[0] is number;
+                let c = config is [Config];
+                type Method = "GET" | "POST";
+                let d = method is Method | 404;"#
+            ),
+            r#"local a = ((type(value) == "string") or (value == nil))
+local b = (function()
+    local __value = items[1]
+    return (type(__value) == "number")
+end)()
+local c = (type(config) == "table")
+local d = ((method == "GET") or (method == "POST") or (method == 404))
+"#
+        );
+    }
+
+    #[test]
+    fn types_and_externs() {
+        assert_eq!(
+            compile(
+                r#"type Config = { host: string, port: number };
+                extern fn load_config(path: string) -> Config;
+                let port = load_config("app.json").port;"#
+            ),
+            "local port = load_config(\"app.json\").port\n"
+        );
+    }
+
+    /// Numbers, conditions and names of Lua globals behave like in javascript. Needs a Lua
+    /// interpreter, so it is skipped without one
+    #[test]
+    fn runs_with_lua() {
+        let code = compile(
+            r#"let print = 5;
+            fn ipairs(value: number) -> number { value * 2 }
+            let tostring = "s";
+            let big = 4611686018427388000;
+            println(big * 4 > big);
+            if 0 { println("zero"); } else { println("no zero"); }
+            if "" { println("empty"); } else { println("no empty"); }
+            println(tostring + print + ipairs(3));
+            println(7 / 2);
+            println(f"{2 + 2} items");"#,
+        );
+        assert!(code.contains("\nprint_ = 5.0\n"), "{}", code);
+        let Ok(output) = Command::new("lua").arg("-e").arg(&code).output() else {
+            return;
+        };
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "true\nno zero\nno empty\ns56\n3.5\n4 items\n",
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
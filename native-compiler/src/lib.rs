@@ -0,0 +1,851 @@
+use std::{collections::HashMap, fmt::Display, path::Path, process::Command};
+
+use cranelift_codegen::{
+    ir::{condcodes::FloatCC, condcodes::IntCC, types, AbiParam, InstBuilder, MemFlags, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_module::{default_libcall_names, DataDescription, DataId, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use oxidescript::parser::ast::{
+    Block, CallExpr, Declaration, Expression, InfixOperator, Literal, Program, Statement,
+    UnaryOperator,
+};
+
+/// Printing, arrays and strings are implemented in C and compiled together with the program
+const RUNTIME: &str = include_str!("runtime.c");
+
+#[derive(Debug)]
+pub struct NativeError {
+    /// `None` for top level code
+    pub function: Option<String>,
+    pub message: String,
+}
+
+impl Display for NativeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "function {}: {}", function, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl NativeError {
+    fn new(message: impl Display) -> Self {
+        NativeError {
+            function: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Compiles a program to an object file for the host, with the top level code as `main`.
+///
+/// This is an experiment for scripts that should run without a javascript runtime, so only a
+/// subset of the language is supported: numbers, booleans, strings and arrays of numbers, `let`,
+/// `if`, `for` over arrays, top level functions and the `println` and `eprintln` of the prelude.
+/// Functions take numbers, booleans or strings and return a number, or `0` if they have no value
+pub fn compile(program: &Program, prelude: bool) -> Result<Vec<u8>, NativeError> {
+    let mut flags = settings::builder();
+    flags.set("is_pic", "true").map_err(NativeError::new)?;
+    flags.set("opt_level", "speed").map_err(NativeError::new)?;
+    let isa = cranelift_native::builder()
+        .map_err(NativeError::new)?
+        .finish(settings::Flags::new(flags))
+        .map_err(NativeError::new)?;
+    let mut module = ObjectModule::new(
+        ObjectBuilder::new(isa, "oxidescript", default_libcall_names())
+            .map_err(NativeError::new)?,
+    );
+    let runtime = Runtime::declare(&mut module)?;
+
+    let mut functions = HashMap::new();
+    let mut declarations = vec![];
+    let mut top_level = vec![];
+    for statement in program {
+        match statement {
+            Statement::DeclarationStatement(
+                Declaration::FunctionDeclaration {
+                    name,
+                    parameters,
+                    body,
+                    ..
+                },
+                _,
+            ) => {
+                let error = |message: String| NativeError {
+                    function: Some(name.0.clone()),
+                    message,
+                };
+                let parameter_types = parameters
+                    .iter()
+                    .map(|parameter| {
+                        Type::from_annotation(&parameter.type_.0).ok_or_else(|| {
+                            error(format!(
+                                "parameter {} is a {}, only numbers, booleans and strings are supported",
+                                parameter.name.0, parameter.type_.0
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut signature = module.make_signature();
+                signature.params.extend(
+                    parameter_types
+                        .iter()
+                        .map(|type_| AbiParam::new(type_.ir(&module))),
+                );
+                signature.returns.push(AbiParam::new(types::F64));
+                // prefixed, so functions like `exit` don't collide with the C library
+                let id = module
+                    .declare_function(
+                        &format!("oxidescript_{}", name.0),
+                        Linkage::Local,
+                        &signature,
+                    )
+                    .map_err(|err| error(err.to_string()))?;
+                if functions
+                    .insert(name.0.clone(), (id, parameter_types))
+                    .is_some()
+                {
+                    return Err(error(
+                        "there is another function of the same name".to_string(),
+                    ));
+                }
+                declarations.push((name, parameters, body, id, signature));
+            }
+            Statement::DeclarationStatement(
+                Declaration::StructDeclaration(_)
+                | Declaration::EnumDeclaration(_)
+                | Declaration::ImplDeclaration(_)
+                | Declaration::ModDeclaration(_)
+                | Declaration::TypeDeclaration(_)
+                | Declaration::ExternDeclaration(_),
+                _,
+            ) => return Err(NativeError::new(
+                "structs, enums, impl blocks, modules, types and externs are not supported by the \
+                 native target",
+            )),
+            statement => top_level.push(statement.clone()),
+        }
+    }
+
+    let mut ctx = module.make_context();
+    let mut builder_ctx = FunctionBuilderContext::new();
+    let mut strings = HashMap::new();
+    for (name, parameters, body, id, signature) in declarations {
+        let error = |message: String| NativeError {
+            function: Some(name.0.clone()),
+            message,
+        };
+        ctx.func.signature = signature;
+        let mut translator = Translator::new(
+            FunctionBuilder::new(&mut ctx.func, &mut builder_ctx),
+            &mut module,
+            &runtime,
+            &functions,
+            &mut strings,
+            prelude,
+        );
+        let entry = translator.builder.current_block().unwrap();
+        let scope = parameters
+            .iter()
+            .zip(&functions[&name.0].1)
+            .zip(translator.builder.block_params(entry).to_vec())
+            .map(|((parameter, type_), value)| (parameter.name.0.clone(), (*type_, value)))
+            .collect();
+        translator.scopes.push(scope);
+        let return_value = match translator.block(body).map_err(error)? {
+            Some((Type::Number, value)) => value,
+            None => translator.builder.ins().f64const(0.0),
+            Some((type_, _)) => {
+                return Err(error(format!(
+                    "it returns a {:?}, only numbers can be returned",
+                    type_
+                )))
+            }
+        };
+        translator.builder.ins().return_(&[return_value]);
+        translator.builder.finalize();
+        module
+            .define_function(id, &mut ctx)
+            .map_err(|err| error(err.to_string()))?;
+        module.clear_context(&mut ctx);
+    }
+
+    let mut signature = module.make_signature();
+    signature.returns.push(AbiParam::new(types::I32));
+    let main = module
+        .declare_function("main", Linkage::Export, &signature)
+        .map_err(NativeError::new)?;
+    ctx.func.signature = signature;
+    let mut translator = Translator::new(
+        FunctionBuilder::new(&mut ctx.func, &mut builder_ctx),
+        &mut module,
+        &runtime,
+        &functions,
+        &mut strings,
+        prelude,
+    );
+    translator.scopes.push(HashMap::new());
+    for statement in &top_level {
+        translator.statement(statement).map_err(NativeError::new)?;
+    }
+    let exit_code = translator.builder.ins().iconst(types::I32, 0);
+    translator.builder.ins().return_(&[exit_code]);
+    translator.builder.finalize();
+    module
+        .define_function(main, &mut ctx)
+        .map_err(NativeError::new)?;
+
+    module.finish().emit().map_err(NativeError::new)
+}
+
+/// Links the object file and the runtime into an executable with the system C compiler, `cc` or
+/// the one in `CC`
+pub fn link(object: &[u8], output: &Path) -> Result<(), NativeError> {
+    let dir = tempfile::tempdir().map_err(NativeError::new)?;
+    let object_path = dir.path().join("program.o");
+    let runtime_path = dir.path().join("runtime.c");
+    std::fs::write(&object_path, object).map_err(NativeError::new)?;
+    std::fs::write(&runtime_path, RUNTIME).map_err(NativeError::new)?;
+
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .arg("-O2")
+        .arg(&runtime_path)
+        .arg(&object_path)
+        .arg("-o")
+        .arg(output)
+        .arg("-lm")
+        .status()
+        .map_err(|err| NativeError::new(format!("Unable to run {}: {}", cc, err)))?;
+    if !status.success() {
+        return Err(NativeError::new(format!("{} failed with {}", cc, status)));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Type {
+    /// `f64`, like javascript numbers
+    Number,
+    /// `i8` that is either 0 or 1
+    Boolean,
+    /// Pointer to a NUL terminated string
+    String,
+    /// Pointer to the length, followed by the numbers
+    Array,
+}
+
+impl Type {
+    /// Arrays can't be parameters, there is no annotation for them yet
+    fn from_annotation(annotation: &str) -> Option<Type> {
+        match annotation {
+            "number" => Some(Type::Number),
+            "boolean" => Some(Type::Boolean),
+            "string" => Some(Type::String),
+            _ => None,
+        }
+    }
+
+    fn ir(self, module: &ObjectModule) -> types::Type {
+        match self {
+            Type::Number => types::F64,
+            Type::Boolean => types::I8,
+            Type::String | Type::Array => module.target_config().pointer_type(),
+        }
+    }
+}
+
+/// The functions of `runtime.c` and the C library
+struct Runtime {
+    print_number: FuncId,
+    print_string: FuncId,
+    print_boolean: FuncId,
+    print_array: FuncId,
+    print_separator: FuncId,
+    array_new: FuncId,
+    array_get: FuncId,
+    string_concat: FuncId,
+    string_equals: FuncId,
+    fmod: FuncId,
+}
+
+impl Runtime {
+    fn declare(module: &mut ObjectModule) -> Result<Runtime, NativeError> {
+        let pointer = module.target_config().pointer_type();
+        let mut declare = |name: &str, params: &[types::Type], returns: &[types::Type]| {
+            let mut signature = module.make_signature();
+            signature
+                .params
+                .extend(params.iter().map(|type_| AbiParam::new(*type_)));
+            signature
+                .returns
+                .extend(returns.iter().map(|type_| AbiParam::new(*type_)));
+            module
+                .declare_function(name, Linkage::Import, &signature)
+                .map_err(NativeError::new)
+        };
+        Ok(Runtime {
+            print_number: declare("os_print_number", &[types::I32, types::F64], &[])?,
+            print_string: declare("os_print_string", &[types::I32, pointer], &[])?,
+            print_boolean: declare("os_print_boolean", &[types::I32, types::I8], &[])?,
+            print_array: declare("os_print_array", &[types::I32, pointer], &[])?,
+            print_separator: declare("os_print_separator", &[types::I32, types::I8], &[])?,
+            array_new: declare("os_array_new", &[types::I64], &[pointer])?,
+            array_get: declare("os_array_get", &[pointer, types::F64], &[types::F64])?,
+            string_concat: declare("os_string_concat", &[pointer, pointer], &[pointer])?,
+            string_equals: declare("os_string_equals", &[pointer, pointer], &[types::I8])?,
+            fmod: declare("fmod", &[types::F64, types::F64], &[types::F64])?,
+        })
+    }
+}
+
+/// Offset of the first number of an array, after its length
+const ARRAY_ITEMS_OFFSET: i32 = 8;
+
+struct Translator<'t> {
+    builder: FunctionBuilder<'t>,
+    module: &'t mut ObjectModule,
+    runtime: &'t Runtime,
+    /// Declaration and parameter types of every function
+    functions: &'t HashMap<String, (FuncId, Vec<Type>)>,
+    /// String literals are only stored once per program
+    strings: &'t mut HashMap<String, DataId>,
+    prelude: bool,
+    /// Variables can't be reassigned, so they are the values they were initialized with
+    scopes: Vec<HashMap<String, (Type, Value)>>,
+}
+
+impl<'t> Translator<'t> {
+    fn new(
+        mut builder: FunctionBuilder<'t>,
+        module: &'t mut ObjectModule,
+        runtime: &'t Runtime,
+        functions: &'t HashMap<String, (FuncId, Vec<Type>)>,
+        strings: &'t mut HashMap<String, DataId>,
+        prelude: bool,
+    ) -> Self {
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        Translator {
+            builder,
+            module,
+            runtime,
+            functions,
+            strings,
+            prelude,
+            scopes: vec![],
+        }
+    }
+
+    fn call(&mut self, function: FuncId, arguments: &[Value]) -> Option<Value> {
+        let callee = self
+            .module
+            .declare_func_in_func(function, self.builder.func);
+        let call = self.builder.ins().call(callee, arguments);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    fn string_literal(&mut self, value: &str) -> Result<Value, String> {
+        let data = match self.strings.get(value) {
+            Some(data) => *data,
+            None => {
+                let data = self
+                    .module
+                    .declare_anonymous_data(false, false)
+                    .map_err(|err| err.to_string())?;
+                let mut description = DataDescription::new();
+                description.define([value.as_bytes(), b"\0"].concat().into_boxed_slice());
+                self.module
+                    .define_data(data, &description)
+                    .map_err(|err| err.to_string())?;
+                self.strings.insert(value.to_string(), data);
+                data
+            }
+        };
+        let global = self.module.declare_data_in_func(data, self.builder.func);
+        let pointer = self.module.target_config().pointer_type();
+        Ok(self.builder.ins().symbol_value(pointer, global))
+    }
+
+    fn block(&mut self, block: &Block) -> Result<Option<(Type, Value)>, String> {
+        self.scopes.push(HashMap::new());
+        for statement in &block.statements {
+            self.statement(statement)?;
+        }
+        let value = match &block.return_value {
+            Some(return_value) => self.expression(return_value)?,
+            None => None,
+        };
+        self.scopes.pop();
+        Ok(value)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::ExpressionStatement { expression, .. } => {
+                self.expression(expression)?;
+            }
+            Statement::DeclarationStatement(
+                Declaration::LetDeclaration(name, value, _, _)
+                | Declaration::ConstDeclaration(name, value, _, _, _),
+                _,
+            ) => {
+                let value = self.value(value)?;
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(name.0.clone(), value);
+            }
+            Statement::DeclarationStatement(_, _) => {
+                return Err("only let and const declarations are supported in blocks".to_string())
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, expression: &Expression) -> Result<(Type, Value), String> {
+        self.expression(expression)?
+            .ok_or_else(|| format!("{:?} has no value", expression))
+    }
+
+    fn expect(&mut self, expression: &Expression, expected: Type) -> Result<Value, String> {
+        match self.value(expression)? {
+            (type_, value) if type_ == expected => Ok(value),
+            (type_, _) => Err(format!("expected a {:?}, found a {:?}", expected, type_)),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<Option<(Type, Value)>, String> {
+        let value = match expression {
+            Expression::LiteralExpression(Literal::NumberLiteral(number), _) => {
+                let value = f64::try_from(number.clone())
+                    .map_err(|_| format!("invalid number {}", number))?;
+                (Type::Number, self.builder.ins().f64const(value))
+            }
+            Expression::LiteralExpression(Literal::BooleanLiteral(value), _) => (
+                Type::Boolean,
+                self.builder.ins().iconst(types::I8, *value as i64),
+            ),
+            Expression::LiteralExpression(Literal::StringLiteral(value), _) => {
+                (Type::String, self.string_literal(value)?)
+            }
+            Expression::IdentifierExpression(ident, _) => *self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(&ident.0))
+                .ok_or_else(|| format!("{} is not a parameter or local", ident.0))?,
+            Expression::UnaryExpression(expr, _) => {
+                let (type_, value) = self.value(&expr.rhs)?;
+                let value = match (&expr.op, type_) {
+                    (UnaryOperator::Plus, Type::Number) => value,
+                    (UnaryOperator::Minus, Type::Number) => self.builder.ins().fneg(value),
+                    (UnaryOperator::LogicalNot, Type::Boolean) => {
+                        self.builder.ins().bxor_imm(value, 1)
+                    }
+                    (op, type_) => return Err(format!("{:?} of {:?} is not supported", op, type_)),
+                };
+                (type_, value)
+            }
+            Expression::InfixExpression(expr, _) => self.infix(&expr.op, &expr.lhs, &expr.rhs)?,
+            Expression::ArrayExpression(elements, _) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.expect(element, Type::Number))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let length = self.builder.ins().iconst(types::I64, elements.len() as i64);
+                let array = self.call(self.runtime.array_new, &[length]).unwrap();
+                for (index, element) in elements.into_iter().enumerate() {
+                    self.builder.ins().store(
+                        MemFlags::trusted(),
+                        element,
+                        array,
+                        ARRAY_ITEMS_OFFSET + 8 * index as i32,
+                    );
+                }
+                (Type::Array, array)
+            }
+            Expression::IfExpression(expr, _) => {
+                let else_ifs = expr
+                    .else_if_blocks
+                    .iter()
+                    .map(|else_if| (&*else_if.condition, &else_if.then_block))
+                    .collect::<Vec<_>>();
+                return self.if_chain(
+                    &expr.condition,
+                    &expr.then_block,
+                    &else_ifs,
+                    expr.else_block.as_deref(),
+                );
+            }
+            Expression::BlockExpression(block, _) => return self.block(block),
+            Expression::ForExpression(expr, _) => {
+                let array = self.expect(&expr.rhs, Type::Array)?;
+                let length = self
+                    .builder
+                    .ins()
+                    .load(types::I64, MemFlags::trusted(), array, 0);
+                let header = self.builder.create_block();
+                let index = self.builder.append_block_param(header, types::I64);
+                let body = self.builder.create_block();
+                let exit = self.builder.create_block();
+                let zero = self.builder.ins().iconst(types::I64, 0);
+                self.builder.ins().jump(header, &[zero]);
+
+                self.builder.switch_to_block(header);
+                let in_bounds = self
+                    .builder
+                    .ins()
+                    .icmp(IntCC::SignedLessThan, index, length);
+                self.builder.ins().brif(in_bounds, body, &[], exit, &[]);
+
+                self.builder.switch_to_block(body);
+                self.builder.seal_block(body);
+                let offset = self.builder.ins().imul_imm(index, 8);
+                let address = self.builder.ins().iadd(array, offset);
+                let item = self.builder.ins().load(
+                    types::F64,
+                    MemFlags::trusted(),
+                    address,
+                    ARRAY_ITEMS_OFFSET,
+                );
+                self.scopes
+                    .push(HashMap::from([(expr.lhs.0.clone(), (Type::Number, item))]));
+                self.block(&expr.body)?;
+                self.scopes.pop();
+                let next = self.builder.ins().iadd_imm(index, 1);
+                self.builder.ins().jump(header, &[next]);
+                self.builder.seal_block(header);
+
+                self.builder.switch_to_block(exit);
+                self.builder.seal_block(exit);
+                return Ok(None);
+            }
+            Expression::WhileExpression(expr, _) => {
+                let header = self.builder.create_block();
+                let body = self.builder.create_block();
+                let exit = self.builder.create_block();
+                self.builder.ins().jump(header, &[]);
+
+                self.builder.switch_to_block(header);
+                let condition = self.expect(&expr.condition, Type::Boolean)?;
+                self.builder.ins().brif(condition, body, &[], exit, &[]);
+
+                self.builder.switch_to_block(body);
+                self.builder.seal_block(body);
+                self.block(&expr.body)?;
+                self.builder.ins().jump(header, &[]);
+                self.builder.seal_block(header);
+
+                self.builder.switch_to_block(exit);
+                self.builder.seal_block(exit);
+                return Ok(None);
+            }
+            Expression::MatchExpression(expr, _) => {
+                return self.expression(&expr.clone().into_if())
+            }
+            Expression::CallExpression(call, _) => return self.call_expression(call),
+            Expression::IndexExpression(expr, _) => {
+                let array = self.expect(&expr.lhs, Type::Array)?;
+                let index = self.expect(&expr.index, Type::Number)?;
+                let item = self.call(self.runtime.array_get, &[array, index]).unwrap();
+                (Type::Number, item)
+            }
+            Expression::MemberAccessExpression(expr, _) if expr.ident.0 == "length" => {
+                let array = self.expect(&expr.lhs, Type::Array)?;
+                let length = self
+                    .builder
+                    .ins()
+                    .load(types::I64, MemFlags::trusted(), array, 0);
+                (
+                    Type::Number,
+                    self.builder.ins().fcvt_from_sint(types::F64, length),
+                )
+            }
+            _ => return Err(format!("{:?} is not supported", expression)),
+        };
+        Ok(Some(value))
+    }
+
+    fn call_expression(&mut self, call: &CallExpr) -> Result<Option<(Type, Value)>, String> {
+        let Expression::IdentifierExpression(name, _) = &*call.lhs else {
+            return Err("only functions of the program can be called".to_string());
+        };
+        if self.prelude
+            && !self.functions.contains_key(&name.0)
+            && (name.0 == "println" || name.0 == "eprintln")
+        {
+            let stream = self
+                .builder
+                .ins()
+                .iconst(types::I32, if name.0 == "println" { 1 } else { 2 });
+            for (index, argument) in call.arguments.iter().enumerate() {
+                if index > 0 {
+                    let space = self.builder.ins().iconst(types::I8, 0);
+                    self.call(self.runtime.print_separator, &[stream, space]);
+                }
+                let (type_, value) = self.value(argument)?;
+                let print = match type_ {
+                    Type::Number => self.runtime.print_number,
+                    Type::Boolean => self.runtime.print_boolean,
+                    Type::String => self.runtime.print_string,
+                    Type::Array => self.runtime.print_array,
+                };
+                self.call(print, &[stream, value]);
+            }
+            let newline = self.builder.ins().iconst(types::I8, 1);
+            self.call(self.runtime.print_separator, &[stream, newline]);
+            return Ok(None);
+        }
+
+        let (function, parameters) = self
+            .functions
+            .get(&name.0)
+            .ok_or_else(|| format!("{} is not a function of the program", name.0))?;
+        if call.arguments.len() != parameters.len() {
+            return Err(format!(
+                "{} takes {} arguments, but {} were given",
+                name.0,
+                parameters.len(),
+                call.arguments.len()
+            ));
+        }
+        let arguments = call
+            .arguments
+            .iter()
+            .zip(parameters)
+            .map(|(argument, type_)| self.expect(argument, *type_))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = self.call(*function, &arguments).unwrap();
+        Ok(Some((Type::Number, result)))
+    }
+
+    fn infix(
+        &mut self,
+        op: &InfixOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+    ) -> Result<(Type, Value), String> {
+        // short circuiting, like in javascript
+        if matches!(op, InfixOperator::LogicalAnd | InfixOperator::LogicalOr) {
+            let lhs = self.expect(lhs, Type::Boolean)?;
+            let evaluate_rhs = self.builder.create_block();
+            let merge = self.builder.create_block();
+            let result = self.builder.append_block_param(merge, types::I8);
+            if *op == InfixOperator::LogicalAnd {
+                self.builder
+                    .ins()
+                    .brif(lhs, evaluate_rhs, &[], merge, &[lhs]);
+            } else {
+                self.builder
+                    .ins()
+                    .brif(lhs, merge, &[lhs], evaluate_rhs, &[]);
+            }
+            self.builder.switch_to_block(evaluate_rhs);
+            self.builder.seal_block(evaluate_rhs);
+            let rhs = self.expect(rhs, Type::Boolean)?;
+            self.builder.ins().jump(merge, &[rhs]);
+            self.builder.switch_to_block(merge);
+            self.builder.seal_block(merge);
+            return Ok((Type::Boolean, result));
+        }
+
+        let (type_, lhs) = self.value(lhs)?;
+        let rhs = self.expect(rhs, type_)?;
+        let ins = self.builder.ins();
+        Ok(match (op, type_) {
+            (InfixOperator::Plus, Type::Number) => (Type::Number, ins.fadd(lhs, rhs)),
+            (InfixOperator::Minus, Type::Number) => (Type::Number, ins.fsub(lhs, rhs)),
+            (InfixOperator::Multiply, Type::Number) => (Type::Number, ins.fmul(lhs, rhs)),
+            (InfixOperator::Divide, Type::Number) => (Type::Number, ins.fdiv(lhs, rhs)),
+            (InfixOperator::Modulo, Type::Number) => (
+                Type::Number,
+                self.call(self.runtime.fmod, &[lhs, rhs]).unwrap(),
+            ),
+            (InfixOperator::Equal, Type::Number) => {
+                (Type::Boolean, ins.fcmp(FloatCC::Equal, lhs, rhs))
+            }
+            (InfixOperator::NotEqual, Type::Number) => {
+                (Type::Boolean, ins.fcmp(FloatCC::NotEqual, lhs, rhs))
+            }
+            (InfixOperator::LessThan, Type::Number) => {
+                (Type::Boolean, ins.fcmp(FloatCC::LessThan, lhs, rhs))
+            }
+            (InfixOperator::GreaterThan, Type::Number) => {
+                (Type::Boolean, ins.fcmp(FloatCC::GreaterThan, lhs, rhs))
+            }
+            (InfixOperator::LessThanEqual, Type::Number) => {
+                (Type::Boolean, ins.fcmp(FloatCC::LessThanOrEqual, lhs, rhs))
+            }
+            (InfixOperator::GreaterThanEqual, Type::Number) => (
+                Type::Boolean,
+                ins.fcmp(FloatCC::GreaterThanOrEqual, lhs, rhs),
+            ),
+            (InfixOperator::Equal, Type::Boolean) => {
+                (Type::Boolean, ins.icmp(IntCC::Equal, lhs, rhs))
+            }
+            (InfixOperator::NotEqual, Type::Boolean) => {
+                (Type::Boolean, ins.icmp(IntCC::NotEqual, lhs, rhs))
+            }
+            (InfixOperator::Plus, Type::String) => (
+                Type::String,
+                self.call(self.runtime.string_concat, &[lhs, rhs]).unwrap(),
+            ),
+            (InfixOperator::Equal | InfixOperator::NotEqual, Type::String) => {
+                let equal = self.call(self.runtime.string_equals, &[lhs, rhs]).unwrap();
+                if *op == InfixOperator::Equal {
+                    (Type::Boolean, equal)
+                } else {
+                    (Type::Boolean, self.builder.ins().bxor_imm(equal, 1))
+                }
+            }
+            (op, type_) => return Err(format!("{:?} of {:?} is not supported", op, type_)),
+        })
+    }
+
+    /// `else if`s are nested into the else branch. Branches without a value make the whole `if`
+    /// have no value
+    fn if_chain(
+        &mut self,
+        condition: &Expression,
+        then_block: &Block,
+        else_ifs: &[(&Expression, &Block)],
+        else_block: Option<&Block>,
+    ) -> Result<Option<(Type, Value)>, String> {
+        let condition = self.expect(condition, Type::Boolean)?;
+        let then_branch = self.builder.create_block();
+        let else_branch = self.builder.create_block();
+        let merge = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, then_branch, &[], else_branch, &[]);
+
+        self.builder.switch_to_block(then_branch);
+        self.builder.seal_block(then_branch);
+        let has_else = !else_ifs.is_empty() || else_block.is_some();
+        let then_value = self.block(then_block)?.filter(|_| has_else);
+        match then_value {
+            Some((type_, value)) => {
+                let ir_type = type_.ir(self.module);
+                self.builder.append_block_param(merge, ir_type);
+                self.builder.ins().jump(merge, &[value]);
+            }
+            None => {
+                self.builder.ins().jump(merge, &[]);
+            }
+        }
+
+        self.builder.switch_to_block(else_branch);
+        self.builder.seal_block(else_branch);
+        let else_value = match (else_ifs.split_first(), else_block) {
+            (Some(((condition, then_block), rest)), _) => {
+                self.if_chain(condition, then_block, rest, else_block)?
+            }
+            (None, Some(else_block)) => self.block(else_block)?,
+            (None, None) => None,
+        };
+        match (then_value, else_value) {
+            (Some((then_type, _)), Some((else_type, value))) if then_type == else_type => {
+                self.builder.ins().jump(merge, &[value]);
+            }
+            (Some((then_type, _)), else_value) => {
+                return Err(format!(
+                    "the branches of an if are a {:?} and a {:?}",
+                    then_type,
+                    else_value.map(|(type_, _)| type_)
+                ))
+            }
+            (None, _) => {
+                self.builder.ins().jump(merge, &[]);
+            }
+        }
+
+        self.builder.switch_to_block(merge);
+        self.builder.seal_block(merge);
+        Ok(then_value.map(|(type_, _)| (type_, self.builder.block_params(merge)[0])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oxidescript::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    const PROGRAM: &str = r#"
+        fn fibonacci(n: number) {
+            match n { 0 | 1 => n, _ => fibonacci(n - 1) + fibonacci(n - 2) }
+        }
+
+        fn greet(name: string, loud: boolean) {
+            if loud && !(name == "") { println("Hello " + name + "!"); } else { println(name); }
+        }
+
+        let numbers = [1, 2.5, -3];
+        for n in numbers {
+            println(n, n % 2, n > 0);
+        }
+        while numbers.length > 3 {
+            println("unreachable");
+        }
+        greet("world", true);
+        println(fibonacci(20), numbers.length, numbers[1], numbers, 0.1 + 0.2, 1 / 3, 1000000000000000000000);
+        eprintln("done");
+    "#;
+
+    #[test]
+    fn compiles_object() {
+        let object = compile(&parse(PROGRAM), true).unwrap();
+        assert!(!object.is_empty());
+    }
+
+    /// Needs a C compiler to link, so it is skipped without one
+    #[test]
+    fn runs_executable() {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let executable = dir.path().join("program");
+        link(&compile(&parse(PROGRAM), true).unwrap(), &executable).unwrap();
+        let output = Command::new(&executable).output().unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "1 1 true\n\
+             2.5 0.5 true\n\
+             -3 -1 false\n\
+             Hello world!\n\
+             6765 3 2.5 [ 1, 2.5, -3 ] 0.30000000000000004 0.3333333333333333 1e+21\n"
+        );
+        assert_eq!(String::from_utf8(output.stderr).unwrap(), "done\n");
+    }
+
+    #[test]
+    fn unsupported_programs() {
+        for input in [
+            "struct Point { x: number }",
+            "fn greet(names: any) { 1 }",
+            "fn greet() { \"hello\" }",
+            "console.log(1);",
+            "let x = if true { 1 } else { \"one\" };",
+            "fn f() { 1 } fn f() { 2 }",
+            "undefined_function(1);",
+        ] {
+            assert!(compile(&parse(input), true).is_err(), "{}", input);
+        }
+    }
+}
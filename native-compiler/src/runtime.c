@@ -0,0 +1,115 @@
+// Runtime of natively compiled oxidescript programs, linked next to the compiled object
+
+#include <math.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct {
+    int64_t length;
+    double items[];
+} os_array;
+
+static FILE *os_stream(int32_t stream) {
+    return stream == 2 ? stderr : stdout;
+}
+
+// The shortest representation that reads back as the same number, like javascript prints them
+static void os_format_number(char *buffer, size_t size, double value) {
+    if (isnan(value)) {
+        snprintf(buffer, size, "NaN");
+    } else if (isinf(value)) {
+        snprintf(buffer, size, value < 0 ? "-Infinity" : "Infinity");
+    } else if (value == trunc(value) && fabs(value) < 1e21) {
+        snprintf(buffer, size, "%.0f", value);
+    } else {
+        for (int precision = 1; precision <= 17; precision++) {
+            snprintf(buffer, size, "%.*g", precision, value);
+            if (strtod(buffer, NULL) == value) {
+                break;
+            }
+        }
+        // `1e-07` is `1e-7` in javascript
+        char *exponent = strchr(buffer, 'e');
+        if (exponent != NULL) {
+            char *digits = exponent + 2;
+            char *significant = digits;
+            while (*significant == '0' && significant[1] != '\0') {
+                significant++;
+            }
+            memmove(digits, significant, strlen(significant) + 1);
+        }
+    }
+}
+
+void os_print_number(int32_t stream, double value) {
+    char buffer[64];
+    os_format_number(buffer, sizeof buffer, value);
+    fputs(buffer, os_stream(stream));
+}
+
+void os_print_string(int32_t stream, const char *value) {
+    fputs(value, os_stream(stream));
+}
+
+void os_print_boolean(int32_t stream, int8_t value) {
+    fputs(value ? "true" : "false", os_stream(stream));
+}
+
+void os_print_array(int32_t stream, const os_array *array) {
+    if (array->length == 0) {
+        fputs("[]", os_stream(stream));
+        return;
+    }
+    fputs("[ ", os_stream(stream));
+    for (int64_t index = 0; index < array->length; index++) {
+        if (index > 0) {
+            fputs(", ", os_stream(stream));
+        }
+        os_print_number(stream, array->items[index]);
+    }
+    fputs(" ]", os_stream(stream));
+}
+
+void os_print_separator(int32_t stream, int8_t newline) {
+    fputc(newline ? '\n' : ' ', os_stream(stream));
+}
+
+os_array *os_array_new(int64_t length) {
+    os_array *array = malloc(sizeof(os_array) + length * sizeof(double));
+    if (array == NULL) {
+        fputs("out of memory\n", stderr);
+        exit(1);
+    }
+    array->length = length;
+    return array;
+}
+
+double os_array_get(const os_array *array, double index) {
+    if (index != trunc(index) || index < 0 || index >= (double)array->length) {
+        char buffer[64];
+        os_format_number(buffer, sizeof buffer, index);
+        fprintf(stderr, "index %s is out of bounds for an array of length %lld\n", buffer,
+                (long long)array->length);
+        exit(1);
+    }
+    return array->items[(int64_t)index];
+}
+
+const char *os_string_concat(const char *lhs, const char *rhs) {
+    size_t lhs_length = strlen(lhs);
+    size_t rhs_length = strlen(rhs);
+    char *result = malloc(lhs_length + rhs_length + 1);
+    if (result == NULL) {
+        fputs("out of memory\n", stderr);
+        exit(1);
+    }
+    memcpy(result, lhs, lhs_length);
+    memcpy(result + lhs_length, rhs, rhs_length + 1);
+    return result;
+}
+
+int8_t os_string_equals(const char *lhs, const char *rhs) {
+    return strcmp(lhs, rhs) == 0;
+}
@@ -0,0 +1,500 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+use oxidescript::parser::{
+    ast::{
+        CallExpr, Declaration, Expression, Identifier, Literal, ModDecl, ModItem, PathExpr,
+        Program, Span, Statement,
+    },
+    module::lower_uses,
+    visit::walk_program_mut,
+};
+use oxidescript_javascript_compiler::Mangling;
+use serde::Serialize;
+
+use crate::{
+    compile_parts, compile_program,
+    graph::{dependencies, module_names, unknown_items},
+    modules::declares_file_modules,
+    parent_dir,
+    shake::tree_shake,
+    source_files, Context,
+};
+
+/// Entry module of a project directory
+pub const ENTRY_FILE_NAME: &str = "main.os";
+
+/// A whole project compiled into one file. Every module except the entry becomes a nested
+/// `pub mod`, so `geometry/shapes.os` is reachable as `geometry::shapes`. Modules loaded with
+/// `import(...)` or started as web workers are split off into their own chunks. Functions, structs
+/// and constants that the entry module doesn't use are left out
+#[derive(Serialize)]
+pub struct Bundle {
+    #[serde(skip)]
+    pub code: String,
+    /// Output bytes attributed to every module, in bundle order
+    pub module_sizes: Vec<ModuleSize>,
+    pub chunks: Vec<Chunk>,
+}
+
+/// A module loaded with `import(...)` or started with `worker::spawn(...)` together with the
+/// modules it uses, written next to the bundle
+#[derive(Serialize)]
+pub struct Chunk {
+    pub file_name: String,
+    #[serde(skip)]
+    pub code: String,
+    pub module_sizes: Vec<ModuleSize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModuleSize {
+    pub module: String,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum ReportFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+impl From<&str> for ReportFormat {
+    fn from(value: &str) -> Self {
+        match value {
+            "table" => Self::Table,
+            "json" => Self::Json,
+            _ => Self::default(),
+        }
+    }
+}
+
+impl ReportFormat {
+    /// Prelude items like `println` compile to inline builtin calls, so there are no std shims
+    /// that would need their own rows
+    pub fn render(&self, bundle: &Bundle) -> String {
+        match self {
+            Self::Table => {
+                let mut report = render_table(&bundle.code, &bundle.module_sizes);
+                for chunk in &bundle.chunks {
+                    report += &format!(
+                        "\n\n{}\n{}",
+                        chunk.file_name,
+                        render_table(&chunk.code, &chunk.module_sizes)
+                    );
+                }
+                report
+            }
+            Self::Json => serde_json::to_string_pretty(bundle).unwrap(),
+        }
+    }
+}
+
+/// Bundles the project directory, or the directory containing `input` with `input` as entry
+pub fn bundle(input: &Path, ctx: &Context) -> Result<Bundle, Vec<String>> {
+    let (dir, entry) = if input.is_dir() {
+        (input, input.join(ENTRY_FILE_NAME))
+    } else {
+        let dir = parent_dir(input);
+        (dir, dir.join(input.file_name().unwrap()))
+    };
+    if !entry.is_file() {
+        return Err(vec![format!("Entry module {} not found", entry.display())]);
+    }
+    let sources = source_files(dir).map_err(|err| vec![err])?;
+    let names = module_names(dir, &sources);
+    let entry_name = &names[sources.iter().position(|source| *source == entry).unwrap()];
+    *ctx.project_modules.borrow_mut() = names.clone();
+
+    let mut programs = vec![];
+    let mut chunks = BTreeSet::new();
+    let mut errors = vec![];
+    for (source, name) in sources.iter().zip(&names) {
+        match ctx.sources.parse(source, true, ctx) {
+            Ok(program) if declares_file_modules(&program) => errors.push(format!(
+                "{}: `mod name;` declarations can't be bundled, every file of a project already \
+                 is a module",
+                source.display()
+            )),
+            Ok(program) => {
+                // the paths of `use` declarations link modules like any other path
+                let mut program = lower_uses(program);
+                match link_chunks(&mut program, source, &sources, &names, ctx) {
+                    Ok(linked) => chunks.extend(linked),
+                    Err(err) => errors.push(err),
+                }
+                programs.push((source, name.clone(), program));
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+    let modules = programs
+        .iter()
+        .map(|(source, name, program)| (source.as_path(), name.as_str(), program))
+        .collect::<Vec<_>>();
+    errors.extend(unknown_items(&modules));
+    for (source, name, program) in &programs {
+        let is_entry = name == entry_name || chunks.contains(&(name.clone(), ChunkKind::Worker));
+        if !is_entry && has_top_level_code(program) {
+            errors.push(format!(
+                "{}: only entry modules can contain top level expressions",
+                source.display()
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let programs = programs
+        .into_iter()
+        .map(|(_, name, program)| (name, program))
+        .collect::<Vec<_>>();
+
+    let (code, module_sizes) =
+        link(entry_name, &programs, &names, false, ctx).map_err(|err| vec![err])?;
+    let chunks = chunks
+        .into_iter()
+        .map(|(module, kind)| {
+            let (code, module_sizes) =
+                link(&module, &programs, &names, kind == ChunkKind::Import, ctx)?;
+            Ok(Chunk {
+                file_name: format!("{}.{}", chunk_name(&module), ctx.emit.extension()),
+                code,
+                module_sizes,
+            })
+        })
+        .collect::<Result<_, String>>()
+        .map_err(|err| vec![err])?;
+    Ok(Bundle {
+        code,
+        module_sizes,
+        chunks,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ChunkKind {
+    /// Loaded with `import(...)`, the `pub` items of the module are the exports of the chunk
+    Import,
+    /// Started with `worker::spawn(...)`, an entry module of its own that can contain top level
+    /// expressions
+    Worker,
+}
+
+/// Chunks are flat next to the bundle, named after their module with `.` as separator
+fn chunk_name(module: &str) -> String {
+    module.replace("::", ".")
+}
+
+/// Points the `import(...)` and `worker::spawn(...)` paths at the chunk of the referenced module
+/// and returns the referenced modules
+fn link_chunks(
+    program: &mut Program,
+    source: &Path,
+    sources: &[PathBuf],
+    names: &[String],
+    ctx: &Context,
+) -> Result<Vec<(String, ChunkKind)>, String> {
+    let mut chunks = vec![];
+    let mut result = Ok(());
+    walk_program_mut(program, &mut |expression| {
+        let (path, kind) = match expression {
+            Expression::ImportExpression(expr, _) => (&mut expr.path, ChunkKind::Import),
+            Expression::CallExpression(CallExpr { lhs, arguments }, _)
+                if ctx.config.prelude && is_worker_spawn(lhs) =>
+            {
+                match arguments.as_mut_slice() {
+                    [Expression::LiteralExpression(Literal::StringLiteral(path), _)] => {
+                        (path, ChunkKind::Worker)
+                    }
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+        let resolved = source
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(&*path)
+            .with_extension("os");
+        let index = resolved.canonicalize().ok().and_then(|resolved| {
+            sources
+                .iter()
+                .position(|source| source.canonicalize().is_ok_and(|source| source == resolved))
+        });
+        match index {
+            Some(index) => {
+                *path = format!("./{}", chunk_name(&names[index]));
+                chunks.push((names[index].clone(), kind));
+            }
+            None => result = Err(format!("{}: module {} not found", source.display(), path)),
+        }
+    });
+    result.map(|_| chunks)
+}
+
+pub fn is_worker_spawn(lhs: &Expression) -> bool {
+    matches!(lhs, Expression::PathExpression(PathExpr { segments }, _)
+        if segments.len() == 2 && segments[0].0 == "worker" && segments[1].0 == "spawn")
+}
+
+/// Compiles `root` and every module it uses through paths into one file. The items of `root` are
+/// at the top level, its `pub` items are the exports of the file. With `exported` the file is
+/// loaded by `import(...)`, which keeps the top level names
+fn link(
+    root: &str,
+    programs: &[(String, Program)],
+    names: &[String],
+    exported: bool,
+    ctx: &Context,
+) -> Result<(String, Vec<ModuleSize>), String> {
+    let has_pub = programs.iter().any(|(name, program)| {
+        name == root
+            && program.iter().any(|statement| {
+                matches!(statement, Statement::DeclarationStatement(declaration, _) if declaration.is_pub())
+            })
+    });
+    let mangling = ctx.mangling(!exported && !has_pub);
+    let used = with_dependencies(vec![root.to_string()], programs, names);
+    let programs = programs
+        .iter()
+        .filter(|(name, _)| used.contains(name))
+        .cloned()
+        .collect();
+
+    let programs = tree_shake(programs, names, root);
+    // the code of modules depends on the declarations of the modules they use, like the fields of
+    // their structs
+    let compiled_from = |modules: Vec<String>| {
+        let modules = with_dependencies(modules, &programs, names);
+        programs
+            .iter()
+            .filter(|(name, _)| modules.contains(name))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let (mut root_program, modules): (Vec<_>, Vec<_>) = dependency_order(programs.clone(), names)
+        .into_iter()
+        .partition(|(name, _)| name == root);
+    let (_, root_program) = root_program.remove(0);
+
+    let mut module_sizes = vec![];
+    let mut items = vec![];
+    for (name, program) in modules {
+        let id = format!("module {} {:?}", name, mangling);
+        let code = ctx
+            .outputs
+            .module(id, (name.clone(), program.clone()), |program| {
+                compile_program(program, None, None, mangling, ctx)
+                    .map(|compiled| compiled.code)
+                    .map_err(|err| format!("{}: {}", name, err))
+            })?;
+        module_sizes.push(ModuleSize {
+            module: name.clone(),
+            bytes: code.len(),
+        });
+        let path = name.split("::").map(str::to_string).collect::<Vec<_>>();
+        insert_module(&mut items, &path, program);
+    }
+    let id = format!("module {} {:?}", root, mangling);
+    let code = ctx
+        .outputs
+        .module(id, (root.to_string(), root_program.clone()), |program| {
+            compile_program(program, None, None, mangling, ctx)
+                .map(|compiled| compiled.code)
+                .map_err(|err| format!("{}: {}", root, err))
+        })?;
+    module_sizes.push(ModuleSize {
+        module: root.to_string(),
+        bytes: code.len(),
+    });
+
+    // every top level `pub mod` and the root are compiled on their own, so a rebuild only
+    // compiles the ones with modules that changed. Mangled names are only consistent in the whole
+    // file, which is compiled at once
+    let mut parts = items
+        .iter()
+        .map(|item| match &item.declaration {
+            Declaration::ModDeclaration(decl) => (decl.name.0.clone(), 1),
+            _ => unreachable!("modules are inserted as `pub mod` blocks"),
+        })
+        .chain([(root.to_string(), root_program.len())])
+        .collect::<Vec<_>>();
+    if mangling != Mangling::Off {
+        parts = vec![(root.to_string(), items.len() + root_program.len())];
+    }
+    let parts = parts
+        .into_iter()
+        .map(|(part, length)| {
+            let modules = match mangling {
+                Mangling::Off => programs
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .filter(|name| {
+                        *name == part || (part != root && name.starts_with(&format!("{}::", part)))
+                    })
+                    .collect(),
+                _ => vec![root.to_string()],
+            };
+            let id = format!("part {} of {} {:?}", part, root, mangling);
+            // the one part of a mangled file has every module
+            let own = match mangling {
+                Mangling::Off => modules.len(),
+                _ => programs.len(),
+            };
+            let modules = compiled_from(modules);
+            let cached = ctx.outputs.get(&id, &modules);
+            (id, length, own, modules, cached)
+        })
+        .collect::<Vec<_>>();
+
+    let program = items
+        .into_iter()
+        .map(|item| Statement::DeclarationStatement(item.declaration, Span::default()))
+        .chain(root_program)
+        .collect();
+    let lengths = parts
+        .iter()
+        .map(|(_, length, ..)| *length)
+        .collect::<Vec<_>>();
+    let cached = parts
+        .iter()
+        .map(|(.., cached)| cached.clone())
+        .collect::<Vec<_>>();
+    let (compiled, code) = compile_parts(program, &lengths, &cached, mangling, ctx)
+        .map_err(|err| format!("{}: {}", root, err))?;
+    for ((id, _, own, modules, cached), compiled) in parts.into_iter().zip(compiled) {
+        if cached.is_none() {
+            ctx.outputs.add_compiled(own);
+            ctx.outputs.insert(id, modules, compiled);
+        }
+    }
+    // everything that isn't the code of a module itself, like the module objects around them
+    let attributed = module_sizes.iter().map(|size| size.bytes).sum::<usize>();
+    module_sizes.push(ModuleSize {
+        module: "(module wrappers)".to_string(),
+        bytes: code.len().saturating_sub(attributed),
+    });
+    Ok((code, module_sizes))
+}
+
+/// The modules and every module they use through paths, directly or through other modules
+fn with_dependencies(
+    mut modules: Vec<String>,
+    programs: &[(String, Program)],
+    names: &[String],
+) -> Vec<String> {
+    let mut index = 0;
+    while index < modules.len() {
+        if let Some((name, program)) = programs.iter().find(|(name, _)| *name == modules[index]) {
+            for dependency in dependencies(program, name, names) {
+                if !modules.contains(&dependency) {
+                    modules.push(dependency);
+                }
+            }
+        }
+        index += 1;
+    }
+    modules
+}
+
+/// Largest modules first, with their share of the whole bundle
+fn render_table(code: &str, module_sizes: &[ModuleSize]) -> String {
+    let mut sizes = module_sizes.iter().collect::<Vec<_>>();
+    sizes.sort_by_key(|size| std::cmp::Reverse(size.bytes));
+    let width = sizes
+        .iter()
+        .map(|size| size.module.len())
+        .chain(["module".len()])
+        .max()
+        .unwrap();
+    let total = code.len().max(1);
+
+    let mut table = format!("{:<width$}  {:>8}  {:>6}\n", "module", "bytes", "share");
+    for size in sizes {
+        table += &format!(
+            "{:<width$}  {:>8}  {:>5.1}%\n",
+            size.module,
+            size.bytes,
+            size.bytes as f64 * 100.0 / total as f64
+        );
+    }
+    table += &format!("{:<width$}  {:>8}", "total", code.len());
+    table
+}
+
+fn has_top_level_code(program: &Program) -> bool {
+    program
+        .iter()
+        .any(|statement| matches!(statement, Statement::ExpressionStatement { .. }))
+}
+
+/// Modules are initialized in order, so dependencies have to come before the modules using them.
+/// Cycles are broken at the module that was visited first
+fn dependency_order(programs: Vec<(String, Program)>, names: &[String]) -> Vec<(String, Program)> {
+    fn visit(
+        index: usize,
+        programs: &[(String, Program)],
+        names: &[String],
+        visited: &mut HashSet<usize>,
+        order: &mut Vec<usize>,
+    ) {
+        if !visited.insert(index) {
+            return;
+        }
+        let (name, program) = &programs[index];
+        for dependency in dependencies(program, name, names) {
+            if let Some(dependency) = programs.iter().position(|(name, _)| *name == dependency) {
+                visit(dependency, programs, names, visited, order);
+            }
+        }
+        order.push(index);
+    }
+
+    let mut visited = HashSet::new();
+    let mut order = vec![];
+    for index in 0..programs.len() {
+        visit(index, &programs, names, &mut visited, &mut order);
+    }
+    let mut programs = programs.into_iter().map(Some).collect::<Vec<_>>();
+    order
+        .into_iter()
+        .map(|index| programs[index].take().unwrap())
+        .collect()
+}
+
+/// Adds the declarations of the module at `path` to the `pub mod` blocks for its directories
+fn insert_module(items: &mut Vec<ModItem>, path: &[String], program: Program) {
+    let Some((first, rest)) = path.split_first() else {
+        items.extend(program.into_iter().filter_map(|statement| match statement {
+            Statement::DeclarationStatement(declaration, _) => Some(ModItem {
+                is_pub: true,
+                declaration,
+                span: Span::default(),
+            }),
+            Statement::ExpressionStatement { .. } => None,
+        }));
+        return;
+    };
+    let index = items
+        .iter()
+        .position(|item| {
+            matches!(&item.declaration, Declaration::ModDeclaration(decl) if decl.name.0 == *first)
+        })
+        .unwrap_or_else(|| {
+            items.push(ModItem {
+                is_pub: true,
+                declaration: Declaration::ModDeclaration(ModDecl {
+                    name: Identifier(first.clone()),
+                    items: vec![],
+                }), span: Span::default(),
+            });
+            items.len() - 1
+        });
+    if let Declaration::ModDeclaration(decl) = &mut items[index].declaration {
+        insert_module(&mut decl.items, rest, program);
+    }
+}
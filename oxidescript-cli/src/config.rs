@@ -0,0 +1,41 @@
+use std::{collections::HashMap, fs::read_to_string, path::Path, process::exit};
+
+use serde::Deserialize;
+
+const CONFIG_FILE_NAME: &str = "oxide.toml";
+
+/// Project settings from an `oxide.toml` next to the input file
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Set to `false` to require every item, including `println` and `Vec`, to be declared
+    pub prelude: bool,
+    /// Flags for `cfg::name` conditions, branches that can't be taken with them are left out
+    pub cfg: HashMap<String, bool>,
+    /// Names the host provides besides the ones every target knows, like the globals of a
+    /// library loaded next to the compiled code. They can be used without being declared
+    pub globals: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            prelude: true,
+            cfg: HashMap::new(),
+            globals: vec![],
+        }
+    }
+}
+
+impl Config {
+    pub fn load(dir: &Path) -> Config {
+        let path = dir.join(CONFIG_FILE_NAME);
+        let Ok(contents) = read_to_string(&path) else {
+            return Config::default();
+        };
+        toml::from_str(&contents).unwrap_or_else(|err| {
+            println!("Invalid config {}: {}", path.to_string_lossy(), err);
+            exit(1);
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use std::{io::IsTerminal, path::Path};
+
+use codespan_reporting::{
+    diagnostic::{Diagnostic as Report, Label as ReportLabel, Severity as ReportSeverity},
+    files::SimpleFile,
+    term::{self, termcolor::Buffer},
+};
+use oxidescript::diagnostic::{Diagnostic, Severity};
+
+/// The diagnostics of a file with the lines of the source their labels point at, colored if the
+/// output goes to a terminal and `NO_COLOR` isn't set. Diagnostics without labels name the file in
+/// a note instead
+pub fn render(path: &Path, source: &str, diagnostics: &[Diagnostic]) -> String {
+    let file = SimpleFile::new(path.display().to_string(), source);
+    let mut buffer = if std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none() {
+        Buffer::ansi()
+    } else {
+        Buffer::no_color()
+    };
+    let config = term::Config::default();
+    for diagnostic in diagnostics {
+        let labels = diagnostic
+            .labels
+            .iter()
+            .enumerate()
+            .map(|(index, label)| {
+                let range = label.span.start..label.span.end;
+                match index {
+                    0 => ReportLabel::primary((), range),
+                    _ => ReportLabel::secondary((), range),
+                }
+                .with_message(&label.message)
+            })
+            .collect::<Vec<_>>();
+        let mut notes = vec![];
+        if labels.is_empty() {
+            notes.push(format!("in {}", path.display()));
+        }
+        notes.extend(diagnostic.help.iter().map(|help| format!("help: {}", help)));
+        let severity = match diagnostic.severity {
+            Severity::Error => ReportSeverity::Error,
+            Severity::Warning => ReportSeverity::Warning,
+        };
+        let report = Report::new(severity)
+            .with_message(&diagnostic.message)
+            .with_labels(labels)
+            .with_notes(notes);
+        term::emit(&mut buffer, &config, &file, &report).expect("writing to a buffer can't fail");
+    }
+    String::from_utf8_lossy(buffer.as_slice())
+        .trim_end()
+        .to_string()
+}
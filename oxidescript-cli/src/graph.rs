@@ -0,0 +1,308 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use oxidescript::{
+    diagnostic::Diagnostic,
+    parser::{
+        ast::{CallExpr, Declaration, Expression, Literal, Program, Statement},
+        visit::walk_program,
+    },
+};
+use serde::Serialize;
+
+use crate::{
+    bundle::is_worker_spawn, compile_program, diagnostics, load_file, parent_dir, parse_source,
+    source_files, Context,
+};
+
+#[derive(Clone, Debug, Default)]
+pub enum GraphFormat {
+    #[default]
+    Dot,
+    Json,
+}
+
+impl From<&str> for GraphFormat {
+    fn from(value: &str) -> Self {
+        match value {
+            "dot" => Self::Dot,
+            "json" => Self::Json,
+            _ => Self::default(),
+        }
+    }
+}
+
+impl GraphFormat {
+    pub fn render(&self, graph: &[ModuleNode]) -> String {
+        match self {
+            Self::Dot => render_dot(graph),
+            Self::Json => serde_json::to_string_pretty(graph).unwrap(),
+        }
+    }
+}
+
+/// One source file. Modules are named after their path relative to the project directory, with
+/// `::` as separator, so `geometry/shapes.os` is `geometry::shapes`
+#[derive(Debug, Serialize)]
+pub struct ModuleNode {
+    pub name: String,
+    pub source_size: usize,
+    /// `None` if the module failed to parse or compile
+    pub compiled_size: Option<usize>,
+    pub exports: Vec<String>,
+    /// Modules referenced through paths like `geometry::shapes::area`
+    pub dependencies: BTreeSet<String>,
+    /// Modules loaded with `import(...)` or started with `worker::spawn(...)`, which the bundler
+    /// splits off into chunks
+    pub loads: BTreeSet<String>,
+}
+
+pub fn module_graph(input: &Path, ctx: &Context) -> Result<Vec<ModuleNode>, String> {
+    let (dir, sources) = if input.is_dir() {
+        (input, source_files(input)?)
+    } else {
+        let dir = parent_dir(input);
+        (dir, vec![dir.join(input.file_name().unwrap())])
+    };
+    let names = module_names(dir, &sources);
+    *ctx.project_modules.borrow_mut() = names.clone();
+
+    Ok(sources
+        .iter()
+        .zip(&names)
+        .map(|(source, name)| {
+            let loaded_file = load_file(source);
+            let program = parse_source(source, &loaded_file, true, ctx)
+                .inspect_err(|err| eprintln!("{}", err))
+                .ok();
+            ModuleNode {
+                name: name.clone(),
+                source_size: loaded_file.len(),
+                exports: program.as_ref().map(exports).unwrap_or_default(),
+                dependencies: program
+                    .as_ref()
+                    .map(|program| dependencies(program, name, &names))
+                    .unwrap_or_default(),
+                loads: program
+                    .as_ref()
+                    .map(|program| loads(program, name, &names))
+                    .unwrap_or_default(),
+                compiled_size: program
+                    .and_then(|program| {
+                        compile_program(program, None, None, ctx.mangling(false), ctx)
+                            .inspect_err(|err| eprintln!("{}: {}", source.display(), err))
+                            .ok()
+                    })
+                    .map(|compiled| compiled.code.len()),
+            }
+        })
+        .collect())
+}
+
+pub fn module_names(dir: &Path, sources: &[PathBuf]) -> Vec<String> {
+    sources
+        .iter()
+        .map(|source| {
+            source
+                .strip_prefix(dir)
+                .unwrap()
+                .with_extension("")
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("::")
+        })
+        .collect()
+}
+
+/// The `pub` items of a module, which the compiled javascript exports
+pub fn exports(program: &Program) -> Vec<String> {
+    let public = program
+        .iter()
+        .filter(|statement| {
+            matches!(statement, Statement::DeclarationStatement(declaration, _) if declaration.is_pub())
+        })
+        .cloned()
+        .collect();
+    items(&public)
+}
+
+/// Every top level declaration is visible to other modules of the project, `pub` only marks what
+/// the compiled javascript exports. Externs belong to the javascript environment
+pub fn items(program: &Program) -> Vec<String> {
+    program
+        .iter()
+        .flat_map(|statement| match statement {
+            Statement::DeclarationStatement(declaration, _) => match declaration {
+                Declaration::ConstDeclaration(name, ..)
+                | Declaration::LetDeclaration(name, _, _, _)
+                | Declaration::FunctionDeclaration { name, .. } => vec![name.0.clone()],
+                Declaration::DestructuringDeclaration(pattern, value, _, _) => pattern
+                    .bindings(value)
+                    .into_iter()
+                    .map(|(name, _)| name.0.clone())
+                    .collect(),
+                Declaration::StructDeclaration(decl) => vec![decl.name.0.clone()],
+                Declaration::EnumDeclaration(decl) => vec![decl.name.0.clone()],
+                Declaration::ModDeclaration(decl) => vec![decl.name.0.clone()],
+                Declaration::TypeDeclaration(decl) => vec![decl.name.0.clone()],
+                Declaration::TraitDeclaration(decl) => vec![decl.name.0.clone()],
+                Declaration::ImplDeclaration(_)
+                | Declaration::ModFileDeclaration(_)
+                | Declaration::UseDeclaration(_)
+                | Declaration::ExternDeclaration(_) => vec![],
+            },
+            Statement::ExpressionStatement { .. } => vec![],
+        })
+        .collect()
+}
+
+/// Errors for the paths of the modules of a project that go through another module to an item it
+/// doesn't declare, with the file, name and program of every module. A module that declares a
+/// name itself uses it instead of the module of the same name
+pub fn unknown_items(modules: &[(&Path, &str, &Program)]) -> Vec<String> {
+    let mut errors = vec![];
+    for (path, name, program) in modules {
+        let declared = items(program);
+        let mut diagnostics = vec![];
+        walk_program(program, &mut |expression| {
+            let Expression::PathExpression(path, span) = expression else {
+                return;
+            };
+            if declared.contains(&path.segments[0].0) {
+                return;
+            }
+            let segments = path
+                .segments
+                .iter()
+                .map(|segment| segment.0.as_str())
+                .collect::<Vec<_>>();
+            let Some((length, (_, _, module))) = (1..segments.len()).rev().find_map(|length| {
+                let prefix = segments[..length].join("::");
+                let module = modules.iter().find(|(_, other, _)| **other == prefix)?;
+                Some((length, module)).filter(|_| prefix != *name)
+            }) else {
+                return;
+            };
+            let item = segments[length];
+            if !items(module).iter().any(|declared| declared == item) {
+                let module = segments[..length].join("::");
+                diagnostics.push(
+                    Diagnostic::error(format!("the module `{}` has no item `{}`", module, item))
+                        .with_label(*span, format!("not found in `{}`", module)),
+                );
+            }
+        });
+        if !diagnostics.is_empty() {
+            errors.push(diagnostics::render(path, &load_file(path), &diagnostics));
+        }
+    }
+    errors
+}
+
+/// The longest leading segments of every path that name another module, and the files loaded with
+/// `mod name;`
+pub fn dependencies(program: &Program, name: &str, modules: &[String]) -> BTreeSet<String> {
+    let mut dependencies = BTreeSet::new();
+    let dependency = |segments: Vec<&str>| {
+        (1..segments.len())
+            .rev()
+            .map(|length| segments[..length].join("::"))
+            .find(|prefix| prefix != name && modules.contains(prefix))
+    };
+    walk_program(program, &mut |expression| {
+        if let Expression::PathExpression(path, _) = expression {
+            let segments = path
+                .segments
+                .iter()
+                .map(|segment| segment.0.as_str())
+                .collect::<Vec<_>>();
+            dependencies.extend(dependency(segments));
+        }
+    });
+    // `mod name;` in `geometry/shapes.os` is the module `geometry::name`
+    let parent = name.rsplit_once("::").map(|(parent, _)| parent);
+    for statement in program {
+        match statement {
+            Statement::DeclarationStatement(Declaration::ModFileDeclaration(module), _) => {
+                let module = match parent {
+                    Some(parent) => format!("{}::{}", parent, module.0),
+                    None => module.0.clone(),
+                };
+                dependencies.extend(modules.contains(&module).then_some(module));
+            }
+            Statement::DeclarationStatement(Declaration::UseDeclaration(decl), _) => {
+                let segments = decl.path.iter().chain(&decl.items[..1]);
+                dependencies.extend(dependency(
+                    segments.map(|segment| segment.0.as_str()).collect(),
+                ));
+            }
+            _ => {}
+        }
+    }
+    dependencies
+}
+
+/// The modules loaded with `import("./path")` or started with `worker::spawn("./path")`, whose
+/// paths are relative to the directory of the module
+pub fn loads(program: &Program, name: &str, modules: &[String]) -> BTreeSet<String> {
+    let mut loads = BTreeSet::new();
+    walk_program(program, &mut |expression| {
+        let path = match expression {
+            Expression::ImportExpression(expr, _) => &expr.path,
+            Expression::CallExpression(CallExpr { lhs, arguments }, _) if is_worker_spawn(lhs) => {
+                match arguments.as_slice() {
+                    [Expression::LiteralExpression(Literal::StringLiteral(path), _)] => path,
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+        let mut segments = name.split("::").collect::<Vec<_>>();
+        segments.pop();
+        for component in path.split('/') {
+            match component {
+                "." | "" => {}
+                ".." => {
+                    segments.pop();
+                }
+                component => segments.push(component),
+            }
+        }
+        let module = segments.join("::");
+        if modules.contains(&module) {
+            loads.insert(module);
+        }
+    });
+    loads
+}
+
+fn render_dot(graph: &[ModuleNode]) -> String {
+    let mut dot = String::from("digraph modules {\n");
+    for module in graph {
+        let size = match module.compiled_size {
+            Some(compiled_size) => format!("{} B -> {} B", module.source_size, compiled_size),
+            None => format!("{} B, failed to compile", module.source_size),
+        };
+        dot.push_str(&format!(
+            "    \"{}\" [label=\"{}\\n{}\\nexports: {}\"];\n",
+            module.name,
+            module.name,
+            size,
+            module.exports.join(", ")
+        ));
+        for dependency in &module.dependencies {
+            dot.push_str(&format!("    \"{}\" -> \"{}\";\n", module.name, dependency));
+        }
+        for load in &module.loads {
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [style=dashed];\n",
+                module.name, load
+            ));
+        }
+    }
+    dot.push('}');
+    dot
+}
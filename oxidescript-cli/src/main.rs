@@ -1,26 +1,107 @@
 use std::{
+    cell::RefCell,
+    collections::HashSet,
     fs::read_to_string,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     process::{exit, Command},
 };
 
 use clap::Parser as ClapParser;
 use oxidescript::{
     compiler::Compiler,
-    lexer::{tokens::Tokens, Lexer},
-    parser::Parser,
+    consteval::check_constants,
+    diagnostic::Diagnostic,
+    lexer::{
+        token::{Token, Trivia},
+        tokens::Tokens,
+        Lexer,
+    },
+    lint::lint,
+    optimizer::PassManager,
+    parser::{
+        ast::Program, enums::check_enum_matches, types::check_types_with_imports, Parsed, Parser,
+    },
+    resolve::check_names,
+    stack,
+    stdlib::{check_browser_items, used_items},
+};
+use oxidescript_interpreter::{Interpreter, InterpreterOptions};
+use oxidescript_javascript_compiler::{
+    check, Compiled, JavascriptCompiler, JavascriptCompilerOptions, Mangling, OutputLanguage,
+    SourceFile, StructEmission, GLOBALS, PEEPHOLE_PASS,
 };
-use oxidescript_javascript_compiler::JavascriptCompiler;
+use oxidescript_lua_compiler::{LuaCompiler, LuaCompilerOptions};
+use oxidescript_python_compiler::{PythonCompiler, PythonCompilerOptions};
+
+use bundle::ReportFormat;
+use config::Config;
+use graph::{module_names, unknown_items, GraphFormat};
+use watch::{OutputCache, SourceCache};
+
+mod bundle;
+mod config;
+mod diagnostics;
+mod graph;
+mod modules;
+mod shake;
+mod watch;
 
 #[derive(clap::Parser, Debug)]
 #[command(version)]
 struct Args {
-    #[arg(short, long)]
-    input: PathBuf,
+    /// A file, project directory or glob pattern like `src/**/*.os`. `compile` and `check` take it
+    /// more than once, the other commands only once
+    #[arg(short, long, required = true)]
+    input: Vec<String>,
 
     #[arg(short, long, action = clap::ArgAction::SetTrue)]
     verbose: bool,
 
+    /// Emit structs and their impl blocks as ES classes
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    classes: bool,
+
+    /// `js`, or `ts` to keep the type annotations of parameters and methods and declare an
+    /// interface for every struct. `compile` also takes `ast` and `tokens`, which write the
+    /// syntax tree or the tokens of every file instead, for debugging the compiler
+    #[arg(long)]
+    emit: Option<Emit>,
+
+    /// Keep comments and blank lines between top level statements in the output of `compile` and
+    /// `run`, for generated code that is committed and reviewed
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    readable: bool,
+
+    /// Rename local variables, functions and modules to short names. Bundles that don't export
+    /// anything rename top level names too
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "readable")]
+    mangle: bool,
+
+    /// Turn optimization passes on and off, like `-simplify,+name`. The passes are
+    /// `const-eval`, `dead-branches`, `simplify`, `loop-invariants`, `dead-code` and `peephole` on
+    /// the generated javascript
+    #[arg(long, allow_hyphen_values = true)]
+    passes: Option<String>,
+
+    /// Check the arguments of top level functions against the types of their parameters and the
+    /// values externs return against their return type at runtime, so values from other scripts
+    /// that don't match throw a `TypeError` right away
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    type_guards: bool,
+
+    /// Parse the generated javascript again and fail if it isn't valid, to catch compiler bugs
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    self_check: bool,
+
+    /// Write a source map next to every file `compile` and `run` output, which maps the top level
+    /// statements of the javascript back to the oxidescript source
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "readable")]
+    source_map: bool,
+
+    /// Print the program after the named passes, for debugging the compiler
+    #[arg(long, value_delimiter = ',')]
+    print_after: Vec<String>,
+
     #[command(subcommand)]
     command: OxideCommand,
 }
@@ -29,15 +110,51 @@ struct Args {
 enum OxideCommand {
     /// Compile files
     Compile {
-        #[arg(short, long)]
+        #[arg(short, long, visible_alias = "out-dir")]
         outdir: Option<PathBuf>,
     },
+    /// Parse and check files and the modules they load without writing anything
+    Check,
+    /// Compile a file and run it with bun or node
     Run {
         #[arg(short, long)]
         with: Option<JavascriptRuntime>,
 
         #[arg(short, long)]
         devdir: Option<PathBuf>,
+
+        /// Run the entry module with the interpreter instead of compiling it, without bun or node
+        #[arg(long, conflicts_with_all = ["with", "devdir"])]
+        interpret: bool,
+    },
+    /// Bundle a project directory into a single file
+    Build {
+        /// Defaults to the name of the entry module with a `.js` or `.ts` extension
+        #[arg(short, long)]
+        outfile: Option<PathBuf>,
+
+        /// Print how many bytes of the bundle every module takes up, as `table` or `json`
+        #[arg(long, num_args = 0..=1, default_missing_value = "table")]
+        analyze: Option<ReportFormat>,
+
+        /// `js`, `ts` for a typescript bundle like `--emit ts`, `browser` for a javascript bundle
+        /// that can use the DOM bindings of the standard library, `lua` or `python` to compile the
+        /// entry module to Lua 5.4 or Python 3, or `native` for an experimental executable of the
+        /// entry module, which can only use numbers, booleans, strings and arrays
+        #[arg(short, long)]
+        target: Option<BuildTarget>,
+
+        /// Keep running and build again whenever a source file changes. Files that didn't change
+        /// aren't parsed again, and modules are only compiled again if they or the modules they
+        /// use changed
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        watch: bool,
+    },
+    /// Print the module dependency graph of a file or project directory
+    Graph {
+        /// `dot` or `json`
+        #[arg(short, long)]
+        format: Option<GraphFormat>,
     },
 }
 
@@ -54,7 +171,12 @@ impl JavascriptRuntime {
             Self::Bun => "bun",
             Self::Node => "node",
         };
-        Command::new(cmd).arg(path).spawn()
+        let mut command = Command::new(cmd);
+        if let Self::Node = self {
+            // bun maps stack traces with the source maps of `--source-map` on its own
+            command.arg("--enable-source-maps");
+        }
+        command.arg(path).spawn()
     }
 }
 
@@ -68,19 +190,224 @@ impl From<&str> for JavascriptRuntime {
     }
 }
 
+#[derive(Clone, Debug, Default)]
+enum BuildTarget {
+    #[default]
+    Js,
+    Ts,
+    Browser,
+    Native,
+    Lua,
+    Python,
+}
+
+impl From<&str> for BuildTarget {
+    fn from(value: &str) -> Self {
+        match value {
+            "js" => Self::Js,
+            "ts" => Self::Ts,
+            "browser" => Self::Browser,
+            "native" => Self::Native,
+            "lua" => Self::Lua,
+            "python" => Self::Python,
+            _ => Self::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Emit {
+    #[default]
+    Js,
+    Ts,
+    Ast,
+    Tokens,
+}
+
+impl Emit {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Js => "js",
+            Self::Ts => "ts",
+            Self::Ast => "ast",
+            Self::Tokens => "tokens",
+        }
+    }
+
+    /// `ast` and `tokens` are no code that can be run or bundled
+    fn is_code(&self) -> bool {
+        matches!(self, Self::Js | Self::Ts)
+    }
+}
+
+impl From<&str> for Emit {
+    fn from(value: &str) -> Self {
+        match value {
+            "js" => Self::Js,
+            "ts" => Self::Ts,
+            "ast" => Self::Ast,
+            "tokens" => Self::Tokens,
+            _ => Self::default(),
+        }
+    }
+}
+
 struct Context {
     verbose: bool,
+    struct_emission: StructEmission,
+    emit: Emit,
+    readable: bool,
+    mangle: bool,
+    passes: PassManager,
+    self_check: bool,
+    type_guards: bool,
+    source_map: bool,
+    config: Config,
+    sources: SourceCache,
+    outputs: OutputCache,
+    /// The names of the target and the config that are in scope without being declared
+    globals: HashSet<String>,
+    /// The modules of the project directory being bundled or checked, like `geometry::shapes`,
+    /// which the paths of its files can start with
+    project_modules: RefCell<Vec<String>>,
+    /// Whether the DOM bindings of the standard library are in scope, with `--target browser`
+    browser: bool,
+    /// The one javascript compiler every file and every rebuild of watch mode is compiled with,
+    /// which keeps the memory of its arena between them
+    compiler: RefCell<JavascriptCompiler>,
+    /// The warnings that were printed already. Files are parsed once to find the modules they
+    /// load and again to compile them, a warning is printed the first time
+    warned: RefCell<HashSet<String>>,
+}
+
+impl Context {
+    fn output_language(&self) -> OutputLanguage {
+        match self.emit {
+            Emit::Ts => OutputLanguage::Typescript,
+            _ => OutputLanguage::Javascript,
+        }
+    }
+
+    /// Top level names are only renamed where no other file can refer to them
+    fn mangling(&self, top_level: bool) -> Mangling {
+        match (self.mangle, top_level) {
+            (false, _) => Mangling::Off,
+            (true, false) => Mangling::Locals,
+            (true, true) => Mangling::All,
+        }
+    }
 }
 
 const DEFAULT_DEVDIR: &str = "./.oxidescript-tmp";
 
 fn main() {
+    // deeply nested programs need a larger stack than the main thread has
+    stack::with_stack(run)
+}
+
+fn run() {
     let args = Args::parse();
+    let inputs = expand_inputs(&args.input).unwrap_or_else(|err| {
+        println!("{}", err);
+        exit(1);
+    });
+    let input = &inputs[0];
 
+    let config = Config::load(if input.is_dir() {
+        input
+    } else {
+        parent_dir(input)
+    });
+    let target_globals = match &args.command {
+        OxideCommand::Build {
+            target: Some(BuildTarget::Lua),
+            ..
+        } => oxidescript_lua_compiler::GLOBALS,
+        OxideCommand::Build {
+            target: Some(BuildTarget::Python),
+            ..
+        } => oxidescript_python_compiler::GLOBALS,
+        OxideCommand::Build {
+            target: Some(BuildTarget::Native),
+            ..
+        } => &[],
+        OxideCommand::Run {
+            interpret: true, ..
+        } => oxidescript_interpreter::GLOBALS,
+        _ => GLOBALS,
+    };
+    let globals = target_globals
+        .iter()
+        .map(|name| name.to_string())
+        .chain(config.globals.iter().cloned())
+        .collect();
+    // `--target ts` is a typescript bundle, the same as `--emit ts`
+    let ts_target = matches!(
+        args.command,
+        OxideCommand::Build {
+            target: Some(BuildTarget::Ts),
+            ..
+        }
+    );
+    let emit = match args.emit {
+        Some(emit) if ts_target && emit != Emit::Ts => {
+            println!("--target ts can't emit {}", emit.extension());
+            exit(1);
+        }
+        _ if ts_target => Emit::Ts,
+        emit => emit.unwrap_or_default(),
+    };
     let ctx = Context {
         verbose: args.verbose,
+        struct_emission: if args.classes {
+            StructEmission::Classes
+        } else {
+            StructEmission::Objects
+        },
+        emit,
+        readable: args.readable,
+        mangle: args.mangle,
+        passes: PassManager::new(args.passes.as_deref().unwrap_or_default(), &[PEEPHOLE_PASS])
+            .and_then(|passes| passes.print_after(&args.print_after))
+            .unwrap_or_else(|err| {
+                println!("{}", err);
+                exit(1);
+            }),
+        self_check: args.self_check,
+        source_map: args.source_map,
+        type_guards: args.type_guards,
+        config,
+        sources: SourceCache::default(),
+        outputs: OutputCache::default(),
+        compiler: RefCell::new(JavascriptCompiler::with_options(Default::default())),
+        warned: RefCell::default(),
+        globals,
+        project_modules: RefCell::default(),
+        browser: matches!(
+            args.command,
+            OxideCommand::Build {
+                target: Some(BuildTarget::Browser),
+                ..
+            }
+        ),
     };
 
+    match &args.command {
+        OxideCommand::Compile { .. } | OxideCommand::Check => {}
+        _ if inputs.len() > 1 => {
+            println!("Only compile and check take more than one input");
+            exit(1);
+        }
+        OxideCommand::Run { .. } | OxideCommand::Build { .. } if !ctx.emit.is_code() => {
+            println!(
+                "--emit {} is only supported by compile",
+                ctx.emit.extension()
+            );
+            exit(1);
+        }
+        _ => {}
+    }
+
     match args.command {
         OxideCommand::Compile { outdir } => {
             let outdir = if let Some(outdir) = outdir {
@@ -110,12 +437,29 @@ fn main() {
                 PathBuf::from(".")
             };
 
-            let compiled = compile_file(&args.input, &ctx);
-            let new_file_name = format!("{}.js", args.input.file_stem().unwrap().to_str().unwrap());
-            let compiled_path = outdir.join(new_file_name);
-            std::fs::write(compiled_path, compiled).unwrap();
+            for input in &inputs {
+                if input.is_dir() {
+                    compile_project(input, &outdir, &ctx);
+                } else {
+                    compile_modules(input, &outdir, &ctx).unwrap_or_else(|errors| {
+                        for err in errors {
+                            println!("{}", err);
+                        }
+                        exit(1);
+                    });
+                }
+            }
+        }
+        OxideCommand::Check => check_inputs(&inputs, &ctx),
+        OxideCommand::Run {
+            interpret: true, ..
+        } => {
+            if let Err(err) = interpret(input, &ctx) {
+                println!("{}", err);
+                exit(1);
+            }
         }
-        OxideCommand::Run { with, devdir } => {
+        OxideCommand::Run { with, devdir, .. } => {
             let devdir = devdir.as_deref().unwrap_or(DEFAULT_DEVDIR.as_ref());
             if devdir.exists() {
                 std::fs::remove_dir_all(devdir).unwrap();
@@ -123,41 +467,664 @@ fn main() {
             std::fs::create_dir_all(devdir).unwrap();
             let with = with.unwrap_or_default();
 
-            let compiled = compile_file(&args.input, &ctx);
-            let new_file_name = format!("{}.js", args.input.file_stem().unwrap().to_str().unwrap());
-            let compiled_path = devdir.join(new_file_name);
-            std::fs::write(&compiled_path, compiled).unwrap();
+            let compiled_path = compile_modules(input, devdir, &ctx).unwrap_or_else(|errors| {
+                for err in errors {
+                    println!("{}", err);
+                }
+                exit(1);
+            });
 
             with.run(&compiled_path).unwrap().wait().unwrap();
         }
+        OxideCommand::Build {
+            analyze: Some(_),
+            target: Some(BuildTarget::Native | BuildTarget::Lua | BuildTarget::Python),
+            ..
+        } => {
+            println!("--analyze is only supported for javascript bundles");
+            exit(1);
+        }
+        OxideCommand::Build {
+            target: None | Some(BuildTarget::Js | BuildTarget::Ts | BuildTarget::Browser),
+            ..
+        } if ctx.source_map => {
+            println!("--source-map is only supported by compile and run");
+            exit(1);
+        }
+        OxideCommand::Build {
+            outfile,
+            analyze,
+            target,
+            watch,
+        } => {
+            let target = target.unwrap_or_default();
+            let build = || build(input, outfile.as_deref(), analyze.as_ref(), &target, &ctx);
+            if watch {
+                let dir = if input.is_dir() {
+                    input.as_path()
+                } else {
+                    parent_dir(input)
+                };
+                watch::watch(dir, || match build() {
+                    Ok(outfile) => println!(
+                        "Built {} after parsing {} changed files and compiling {} modules",
+                        outfile.display(),
+                        ctx.sources.take_parsed(),
+                        ctx.outputs.take_compiled()
+                    ),
+                    Err(errors) => {
+                        for err in errors {
+                            println!("{}", err);
+                        }
+                    }
+                })
+                .unwrap_or_else(|err| {
+                    println!("Unable to watch {}: {}", dir.display(), err);
+                    exit(1);
+                });
+            } else if let Err(errors) = build() {
+                for err in errors {
+                    println!("{}", err);
+                }
+                exit(1);
+            }
+        }
+        OxideCommand::Graph { format } => {
+            let graph = graph::module_graph(input, &ctx).unwrap_or_else(|err| {
+                println!("{}", err);
+                exit(1);
+            });
+            println!("{}", format.unwrap_or_default().render(&graph));
+        }
     }
 }
 
-fn compile_file(path: &Path, ctx: &Context) -> String {
+/// Bundles the project, or compiles the entry module for the other targets. Returns the path of the
+/// written file
+fn build(
+    input: &Path,
+    outfile: Option<&Path>,
+    analyze: Option<&ReportFormat>,
+    target: &BuildTarget,
+    ctx: &Context,
+) -> Result<PathBuf, Vec<String>> {
+    let entry = if input.is_dir() {
+        input.join(bundle::ENTRY_FILE_NAME)
+    } else {
+        input.to_path_buf()
+    };
+    let outfile = outfile.map(Path::to_path_buf).unwrap_or_else(|| {
+        output_name(input).with_extension(match target {
+            BuildTarget::Js | BuildTarget::Ts | BuildTarget::Browser => ctx.emit.extension(),
+            BuildTarget::Native => "",
+            BuildTarget::Lua => "lua",
+            BuildTarget::Python => "py",
+        })
+    });
+    if let Some(parent) = outfile.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| vec![format!("Unable to create {}: {}", parent.display(), err)])?;
+    }
+    let bounds_checks = ctx.config.cfg.get("debug").copied().unwrap_or_default();
+    let result = match target {
+        BuildTarget::Js | BuildTarget::Ts | BuildTarget::Browser => {
+            let bundle = bundle::bundle(input, ctx)?;
+            write_output(&outfile, &bundle.code).and_then(|()| {
+                for chunk in &bundle.chunks {
+                    write_output(&outfile.with_file_name(&chunk.file_name), &chunk.code)?;
+                }
+                if let Some(analyze) = analyze {
+                    println!("{}", analyze.render(&bundle));
+                }
+                Ok(())
+            })
+        }
+        BuildTarget::Native => build_native(&entry, &outfile, ctx),
+        BuildTarget::Lua => build_script(
+            &entry,
+            &outfile,
+            LuaCompiler::with_options(LuaCompilerOptions {
+                prelude: ctx.config.prelude,
+                bounds_checks,
+            }),
+            ctx,
+        ),
+        BuildTarget::Python => build_script(
+            &entry,
+            &outfile,
+            PythonCompiler::with_options(PythonCompilerOptions {
+                prelude: ctx.config.prelude,
+                bounds_checks,
+            }),
+            ctx,
+        ),
+    };
+    result.map_err(|err| vec![err])?;
+    Ok(outfile)
+}
+
+/// The paths of the inputs, with glob patterns replaced by the paths they match
+fn expand_inputs(inputs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut paths = vec![];
+    for input in inputs {
+        if !input.contains(['*', '?', '[']) {
+            paths.push(PathBuf::from(input));
+            continue;
+        }
+        let matches = glob::glob(input)
+            .map_err(|err| format!("Invalid pattern {}: {}", input, err))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| err.to_string())?;
+        if matches.is_empty() {
+            return Err(format!("No files match {}", input));
+        }
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+/// Parses and checks every input, the files they load and every `.os` file of project
+/// directories. All errors are reported at the end
+fn check_inputs(inputs: &[PathBuf], ctx: &Context) {
+    let mut files = vec![];
+    let mut errors = vec![];
+    let mut checked = HashSet::new();
+    for input in inputs {
+        if input.is_dir() {
+            let sources = match source_files(input) {
+                Ok(sources) => sources,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            let names = module_names(input, &sources);
+            *ctx.project_modules.borrow_mut() = names.clone();
+            let mut programs = vec![];
+            for (source, name) in sources.iter().zip(&names) {
+                match parse_source(source, &load_file(source), true, ctx) {
+                    Ok(program) => programs.push((source.as_path(), name.as_str(), program)),
+                    Err(err) => errors.push(err),
+                }
+            }
+            let programs = programs
+                .iter()
+                .map(|(source, name, program)| (*source, *name, program))
+                .collect::<Vec<_>>();
+            errors.extend(unknown_items(&programs));
+            ctx.project_modules.borrow_mut().clear();
+            checked.extend(sources);
+            continue;
+        }
+        match modules::resolve(input, ctx) {
+            Ok(resolved) => files.extend(resolved),
+            Err(resolve_errors) => errors.extend(resolve_errors),
+        }
+    }
+    files.sort();
+    files.dedup();
+    files.retain(|file| !checked.contains(file));
+    for file in &files {
+        if let Err(err) = parse_source(file, &load_file(file), true, ctx) {
+            errors.push(err);
+        }
+    }
+    for err in &errors {
+        println!("{}", err);
+    }
+    if !errors.is_empty() {
+        exit(1);
+    }
+    println!("Checked {} modules", files.len() + checked.len());
+}
+
+/// Compiles every `.os` file below `dir` into the same relative path in `outdir`. A module that
+/// fails to compile doesn't stop the others, all errors are reported at the end
+fn compile_project(dir: &Path, outdir: &Path, ctx: &Context) {
+    let sources = source_files(dir).unwrap_or_else(|err| {
+        println!("{}", err);
+        exit(1);
+    });
+    let mut errors = vec![];
+    for source in &sources {
+        let compiled_path = outdir.join(
+            source
+                .strip_prefix(dir)
+                .unwrap()
+                .with_extension(ctx.emit.extension()),
+        );
+        std::fs::create_dir_all(compiled_path.parent().unwrap()).unwrap();
+        if let Err(err) = compile_file(source, &compiled_path, ctx) {
+            errors.push(err);
+        }
+    }
+    for err in &errors {
+        println!("{}", err);
+    }
+    println!(
+        "Compiled {} of {} modules",
+        sources.len() - errors.len(),
+        sources.len()
+    );
+    if !errors.is_empty() {
+        exit(1);
+    }
+}
+
+fn source_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let unreadable = |err: std::io::Error| format!("Unable to read {}: {}", dir.display(), err);
+    let mut sources = vec![];
+    for entry in dir.read_dir().map_err(unreadable)? {
+        let path = entry.map_err(unreadable)?.path();
+        if path.is_dir() {
+            sources.extend(source_files(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "os") {
+            sources.push(path);
+        }
+    }
+    sources.sort();
+    Ok(sources)
+}
+
+fn write_output(path: &Path, code: &str) -> Result<(), String> {
+    std::fs::write(path, code).map_err(|err| format!("Unable to write {}: {}", path.display(), err))
+}
+
+/// The directory containing the file `input`, `.` for a bare file name
+fn parent_dir(input: &Path) -> &Path {
+    match input.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Compiles the file and the files loaded by its `mod name;` declarations into `outdir`, each to
+/// its own ES module. Returns the path of the compiled file
+fn compile_modules(path: &Path, outdir: &Path, ctx: &Context) -> Result<PathBuf, Vec<String>> {
+    let files = modules::resolve(path, ctx)?;
+    let mut errors = vec![];
+    for file in &files {
+        let compiled_path = outdir
+            .join(output_name(file))
+            .with_extension(ctx.emit.extension());
+        if let Err(err) = compile_file(file, &compiled_path, ctx) {
+            errors.push(err);
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(outdir
+        .join(output_name(path))
+        .with_extension(ctx.emit.extension()))
+}
+
+/// Compiles the file to `compiled_path`, with its source map next to it for `--source-map`
+fn compile_file(path: &Path, compiled_path: &Path, ctx: &Context) -> Result<(), String> {
     let loaded_file = load_file(path);
     if ctx.verbose {
         println!("Loaded file: {:?}", &loaded_file);
     }
+    match ctx.emit {
+        Emit::Tokens => {
+            let (_, tokens) = Lexer::lex_tokens(loaded_file.as_bytes()).map_err(|err| {
+                diagnostics::render(path, &loaded_file, &[Diagnostic::error(err.to_string())])
+            })?;
+            std::fs::write(compiled_path, format!("{:#?}\n", tokens)).unwrap();
+            return Ok(());
+        }
+        Emit::Ast => {
+            let ast = parse_source(path, &loaded_file, false, ctx)?;
+            std::fs::write(compiled_path, format!("{:#?}\n", ast)).unwrap();
+            return Ok(());
+        }
+        Emit::Js | Emit::Ts => {}
+    }
+
+    let (ast, trivia) = parse_source_with_trivia(path, &loaded_file, false, ctx)?;
+    let source_path = relative_path(compiled_path.parent().unwrap(), path);
+    let source = SourceFile {
+        path: &source_path,
+        text: &loaded_file,
+    };
+    let compiled = compile_program(
+        ast,
+        ctx.readable.then_some(&trivia),
+        ctx.source_map.then_some(&source),
+        ctx.mangling(false),
+        ctx,
+    )
+    .map_err(|err| diagnostics::render(path, &loaded_file, &[Diagnostic::error(err)]))?;
+
+    let source_map = compiled.source_map();
+    let mut code = compiled.code;
+    if let Some(source_map) = source_map {
+        let mut map_name = compiled_path.file_name().unwrap().to_owned();
+        map_name.push(".map");
+        code.push_str(&format!(
+            "//# sourceMappingURL={}\n",
+            map_name.to_string_lossy()
+        ));
+        std::fs::write(compiled_path.with_file_name(map_name), source_map).unwrap();
+    }
+    std::fs::write(compiled_path, code).unwrap();
+    Ok(())
+}
+
+/// The checked and optimized program of the file. `exports` is whether other modules can use its
+/// top level items, otherwise the ones nothing uses are left out
+fn parse_source(
+    path: &Path,
+    loaded_file: &str,
+    exports: bool,
+    ctx: &Context,
+) -> Result<Program, String> {
+    parse_source_with_trivia(path, loaded_file, exports, ctx).map(|(ast, _)| ast)
+}
 
-    let (unlexed, tokens) = Lexer::lex_tokens(loaded_file.as_bytes()).unwrap();
+/// A program with the comments and blank lines in front of every top level statement, followed by
+/// the ones at the end of the file, and the span of every statement
+/// Like `parse_source`, but also returns the trivia of the top level statements
+fn parse_source_with_trivia<'a>(
+    path: &Path,
+    loaded_file: &'a str,
+    exports: bool,
+    ctx: &Context,
+) -> Result<(Program, Vec<Vec<Trivia<'a>>>), String> {
+    let fail = |diagnostics: &[Diagnostic]| diagnostics::render(path, loaded_file, diagnostics);
+    let (unlexed, (tokens, spans)) = Lexer::lex_tokens_with_spans(loaded_file.as_bytes())
+        .map_err(|err| fail(&[Diagnostic::error(err.to_string())]))?;
     if ctx.verbose {
         println!("Unlexed: {:?}", unlexed);
         println!("Tokens: {:#?}", tokens);
     }
 
-    let (unparsed, ast) = Parser::parse(Tokens::new(&tokens)).unwrap();
+    let Parsed {
+        program: ast,
+        indices,
+        errors,
+    } = Parser::parse_recovering(Tokens::with_spans(&tokens, &spans, 0));
+    // every statement that failed to parse is reported at the token it went wrong at
+    let errors = errors
+        .into_iter()
+        .map(|index| {
+            let span = oxidescript::parser::ast::Span {
+                start: spans[index].start,
+                end: spans[index].end,
+                file_id: 0,
+            };
+            match tokens[index] {
+                Token::EOF => {
+                    let end = loaded_file.trim_end().len();
+                    let last = oxidescript::parser::ast::Span {
+                        start: loaded_file[..end]
+                            .char_indices()
+                            .next_back()
+                            .map_or(0, |(start, _)| start),
+                        end,
+                        file_id: 0,
+                    };
+                    Diagnostic::error("unexpected end of file")
+                        .with_label(last, "the file ends after this")
+                }
+                Token::Await => Diagnostic::error("unexpected `await`")
+                    .with_label(span, "the parser stopped here")
+                    .with_help("`.await` goes after the promise, like `fetch(url).await`"),
+                _ => Diagnostic::error(format!(
+                    "unexpected `{}`",
+                    &loaded_file[span.start..span.end]
+                ))
+                .with_label(span, "the parser stopped here"),
+            }
+        })
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return Err(fail(&errors));
+    }
     if ctx.verbose {
-        println!("Unparsed: {:?}", unparsed);
         println!("AST: {:#?}", &ast);
     }
+    // the standard library items the file uses go in front of it, without any trivia
+    let std = if ctx.config.prelude {
+        used_items(&ast, ctx.browser)
+    } else {
+        vec![]
+    };
+    let std_items = std.len();
+    let ast = std.into_iter().chain(ast).collect::<Program>();
+    let imports = modules::imported_modules(path, &ast).map_err(|errors| fail(&errors))?;
+    // the other modules of the project are checked when the paths to them are
+    let globals = ctx
+        .project_modules
+        .borrow()
+        .iter()
+        .filter_map(|module| module.split("::").next())
+        .map(str::to_string)
+        .chain(ctx.globals.iter().cloned())
+        .collect::<HashSet<_>>();
+    // the checks don't depend on each other, so every one of them reports its errors
+    let mut diagnostics = [
+        check_enum_matches(&ast).map_err(Diagnostic::from),
+        check_constants(&ast, ctx.config.prelude).map_err(Diagnostic::from),
+        match ctx.config.prelude && !ctx.browser {
+            true => check_browser_items(&ast).map_err(Diagnostic::from),
+            false => Ok(()),
+        },
+    ]
+    .into_iter()
+    .filter_map(Result::err)
+    .collect::<Vec<_>>();
+    if let Err(errors) = check_types_with_imports(&ast, &imports) {
+        diagnostics.extend(errors.into_iter().map(Diagnostic::from));
+    }
+    if let Err(errors) = check_names(&ast, &globals, ctx.config.prelude) {
+        diagnostics.extend(errors);
+    }
+    if !diagnostics.is_empty() {
+        return Err(fail(&diagnostics));
+    }
+    // warnings don't stop the compiler, and the standard library is nothing to warn about
+    for warning in lint(&ast[std_items..]) {
+        let warning = fail(&[warning]);
+        if ctx.warned.borrow_mut().insert(warning.clone()) {
+            eprintln!("{}", warning);
+        }
+    }
+
+    let mut token_trivia = Lexer::trivia(loaded_file.as_bytes(), &spans);
+    let end = token_trivia.pop().unwrap_or_default();
+    // the comments in front of statements that are left out move to the next statement
+    let mut program = vec![];
+    let mut trivia = vec![];
+    let mut pending = vec![];
+    let statements = ctx.passes.run(
+        ast,
+        &ctx.config.cfg,
+        ctx.config.prelude,
+        exports,
+        |pass, program| {
+            println!("After {} in {}: {:#?}", pass, path.display(), program);
+        },
+    );
+    let mut statements = statements.into_iter();
+    for statement in statements.by_ref().take(std_items).flatten() {
+        program.push(statement);
+        trivia.push(vec![]);
+    }
+    for (statement, index) in statements.zip(indices) {
+        pending.append(&mut token_trivia[index]);
+        if let Some(statement) = statement {
+            program.push(statement);
+            trivia.push(std::mem::take(&mut pending));
+        }
+    }
+    pending.extend(end);
+    trivia.push(pending);
+    Ok((program, trivia))
+}
+
+/// `#[wasm]` functions are compiled to an inlined WebAssembly module, the rest to javascript.
+/// With `trivia` the output keeps the comments and blank lines between top level statements, with
+/// `source` it comes with a source map
+fn compile_program(
+    mut ast: Program,
+    trivia: Option<&[Vec<Trivia>]>,
+    source: Option<&SourceFile>,
+    mangling: Mangling,
+    ctx: &Context,
+) -> Result<Compiled, String> {
+    let wasm = oxidescript_wasm_compiler::compile(&mut ast).map_err(|err| err.to_string())?;
+    let mut compiler = ctx.compiler.borrow_mut();
+    compiler.set_options(javascript_options(mangling, ctx));
+    let mut compiled = match (trivia, source) {
+        (Some(trivia), _) => compiler.compile_readable(ast, trivia).into(),
+        (None, Some(source)) => compiler.compile_with_source_map(ast, source),
+        (None, None) => Compiled::from(compiler.compile(ast)),
+    };
+    if let Some(wasm) = wasm {
+        compiled.prepend(&wasm.glue());
+    }
+    check_output(&compiled.code, ctx)?;
+    Ok(compiled)
+}
+
+/// Like `compile_program`, for a program made of parts that are compiled on their own, see
+/// [`JavascriptCompiler::compile_parts`]. The parts that have code in `cached` aren't compiled
+/// again. Returns the code of every part and of the whole file
+fn compile_parts(
+    mut ast: Program,
+    parts: &[usize],
+    cached: &[Option<String>],
+    mangling: Mangling,
+    ctx: &Context,
+) -> Result<(Vec<String>, String), String> {
+    let wasm = oxidescript_wasm_compiler::compile(&mut ast).map_err(|err| err.to_string())?;
+    let mut compiler = ctx.compiler.borrow_mut();
+    compiler.set_options(javascript_options(mangling, ctx));
+    let parts = compiler
+        .compile_parts(ast, parts, |index| cached[index].is_none())
+        .into_iter()
+        .zip(cached)
+        .map(|(compiled, cached)| compiled.or_else(|| cached.clone()).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let code = wasm.map(|wasm| wasm.glue()).unwrap_or_default() + &parts.concat();
+    check_output(&code, ctx)?;
+    Ok((parts, code))
+}
+
+fn javascript_options(mangling: Mangling, ctx: &Context) -> JavascriptCompilerOptions {
+    JavascriptCompilerOptions {
+        struct_emission: ctx.struct_emission,
+        output_language: ctx.output_language(),
+        mangling,
+        prelude: ctx.config.prelude,
+        peephole: ctx.passes.is_enabled(PEEPHOLE_PASS),
+        type_guards: ctx.type_guards,
+        bounds_checks: ctx.config.cfg.get("debug").copied().unwrap_or_default(),
+    }
+}
 
-    let compiler = JavascriptCompiler::new();
-    let compiled = compiler.compile(ast);
+/// Prints the generated code with `--verbose`, and parses it again with `--self-check`
+fn check_output(code: &str, ctx: &Context) -> Result<(), String> {
     if ctx.verbose {
-        println!("{}", compiled);
+        println!("{}", code);
+    }
+    if ctx.self_check {
+        check(code, ctx.output_language())
+            .map_err(|err| format!("the compiler generated invalid code:\n{}", err))?;
+    }
+    Ok(())
+}
+
+/// Name of the project directory or the file stem of the input, without extension
+fn output_name(input: &Path) -> PathBuf {
+    let name = if input.is_dir() {
+        input
+            .canonicalize()
+            .unwrap()
+            .file_name()
+            .unwrap()
+            .to_owned()
+    } else {
+        input.file_stem().unwrap().to_owned()
+    };
+    PathBuf::from(name)
+}
+
+/// Compiles only the entry module with one of the source to source backends besides javascript
+fn build_script(
+    entry: &Path,
+    outfile: &Path,
+    compiler: impl Compiler,
+    ctx: &Context,
+) -> Result<(), String> {
+    if !entry.is_file() {
+        return Err(format!("Entry module {} not found", entry.display()));
     }
+    let ast = ctx.sources.parse(entry, false, ctx)?;
+    reject_file_modules(entry, &ast)?;
+    std::fs::write(outfile, compiler.compile(ast)).map_err(|err| err.to_string())
+}
+
+/// Compiles the module to an object file and links it with the native runtime
+fn build_native(entry: &Path, outfile: &Path, ctx: &Context) -> Result<(), String> {
+    if !entry.is_file() {
+        return Err(format!("Entry module {} not found", entry.display()));
+    }
+    let ast = ctx.sources.parse(entry, false, ctx)?;
+    reject_file_modules(entry, &ast)?;
+    let object = oxidescript_native_compiler::compile(&ast, ctx.config.prelude)
+        .map_err(|err| format!("{}: {}", entry.display(), err))?;
+    oxidescript_native_compiler::link(&object, outfile).map_err(|err| err.to_string())
+}
+
+/// Runs the entry module with the interpreter. Like the other backends besides javascript it can't
+/// load other files
+fn interpret(input: &Path, ctx: &Context) -> Result<(), String> {
+    let entry = if input.is_dir() {
+        input.join(bundle::ENTRY_FILE_NAME)
+    } else {
+        input.to_path_buf()
+    };
+    if !entry.is_file() {
+        return Err(format!("Entry module {} not found", entry.display()));
+    }
+    let ast = ctx.sources.parse(&entry, false, ctx)?;
+    reject_file_modules(&entry, &ast)?;
+    let interpreter = Interpreter::with_options(InterpreterOptions {
+        prelude: ctx.config.prelude,
+        bounds_checks: ctx.config.cfg.get("debug").copied().unwrap_or_default(),
+    });
+    interpreter
+        .run(ast, &mut std::io::stdout(), &mut std::io::stderr())
+        .map_err(|err| format!("Error: {}", err))
+}
+
+/// The other backends compile only the entry module, so they can't load other files
+fn reject_file_modules(entry: &Path, ast: &Program) -> Result<(), String> {
+    if modules::declares_file_modules(ast) {
+        return Err(format!(
+            "{}: `mod name;` declarations are only supported when compiling to javascript",
+            entry.display()
+        ));
+    }
+    Ok(())
+}
 
-    compiled
+/// `to` relative to the directory `from`, like the sources of source maps are
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from = from.canonicalize().unwrap();
+    let to = to.canonicalize().unwrap();
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(from, to)| from == to)
+        .count();
+    from.components()
+        .skip(common)
+        .map(|_| Component::ParentDir)
+        .chain(to.components().skip(common))
+        .collect()
 }
 
 fn load_file(path: &Path) -> String {
@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+use oxidescript::{
+    diagnostic::Diagnostic,
+    lexer::{tokens::Tokens, Lexer},
+    parser::{
+        ast::{Declaration, Expression, ModDecl, Program, Statement},
+        visit::{walk_blocks_mut, walk_program},
+        Parser,
+    },
+};
+
+use crate::{
+    diagnostics,
+    graph::{exports, items},
+    load_file, parse_source, Context,
+};
+
+/// The entry file and every file loaded by its `mod name;` declarations, transitively. `mod name;`
+/// loads `name.os` next to the declaring file and is only allowed at the top level, and paths and
+/// `use` declarations of a loaded module have to name its `pub` items
+pub fn resolve(entry: &Path, ctx: &Context) -> Result<Vec<PathBuf>, Vec<String>> {
+    let mut files = vec![entry.to_path_buf()];
+    let mut programs = vec![];
+    let mut errors = vec![];
+    let mut index = 0;
+    while let Some(file) = files.get(index) {
+        index += 1;
+        let path = file.clone();
+        let program = match parse_source(&path, &load_file(&path), true, ctx) {
+            Ok(program) => program,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+        if nested_file_modules(&program) {
+            errors.push(format!(
+                "{}: `mod name;` declarations are only allowed at the top level",
+                path.display()
+            ));
+        }
+        for name in file_modules(&program) {
+            let module = sibling(&path, name);
+            if !module.is_file() {
+                errors.push(format!(
+                    "{}: module `{}` not found, expected {}",
+                    path.display(),
+                    name,
+                    module.display()
+                ));
+            } else if !files.contains(&module) {
+                files.push(module);
+            }
+        }
+        programs.push((path, program));
+    }
+
+    for (path, program) in &programs {
+        let modules = file_modules(program);
+        let module_program = |name: &str| {
+            let module = sibling(path, name);
+            programs
+                .iter()
+                .find(|(path, _)| *path == module)
+                .map(|(_, program)| program)
+        };
+        // paths through a loaded module name one of its items
+        let mut diagnostics = vec![];
+        walk_program(program, &mut |expression| {
+            let Expression::PathExpression(expr, span) = expression else {
+                return;
+            };
+            let (module, item) = (&expr.segments[0].0, &expr.segments[1].0);
+            if !modules.contains(&module.as_str()) {
+                return;
+            }
+            let Some(module_program) = module_program(module) else {
+                return;
+            };
+            if !items(module_program).contains(item) {
+                diagnostics.push(
+                    Diagnostic::error(format!("the module `{}` has no item `{}`", module, item))
+                        .with_label(
+                            *span,
+                            format!("not found in {}", sibling(path, module).display()),
+                        ),
+                );
+            } else if !exports(module_program).contains(item) {
+                diagnostics.push(
+                    Diagnostic::error(format!("`{}` is private to the module `{}`", item, module))
+                        .with_label(*span, "not `pub`"),
+                );
+            }
+        });
+        if !diagnostics.is_empty() {
+            errors.push(diagnostics::render(path, &load_file(path), &diagnostics));
+        }
+        for statement in program {
+            let Statement::DeclarationStatement(Declaration::UseDeclaration(decl), _) = statement
+            else {
+                continue;
+            };
+            if !modules.contains(&decl.path[0].0.as_str()) {
+                continue;
+            }
+            let module = sibling(path, &decl.path[0].0);
+            let Some((_, module_program)) = programs.iter().find(|(path, _)| *path == module)
+            else {
+                continue;
+            };
+            // `use name::inner::item;` is checked up to the first segment inside of the file
+            let names = match decl.path.get(1) {
+                Some(segment) => vec![segment],
+                None => decl.items.iter().collect(),
+            };
+            let declared = items(module_program);
+            let exported = exports(module_program);
+            errors.extend(names.into_iter().filter_map(|name| {
+                if !declared.contains(&name.0) {
+                    Some(format!(
+                        "{}: `{}` is not declared in {}",
+                        path.display(),
+                        name.0,
+                        module.display()
+                    ))
+                } else if !exported.contains(&name.0) {
+                    Some(format!(
+                        "{}: `{}` is not `pub` in {}",
+                        path.display(),
+                        name.0,
+                        module.display()
+                    ))
+                } else {
+                    None
+                }
+            }));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(files)
+}
+
+/// The programs of the modules the file loads with `import("./path")` by their path, for typing
+/// the module objects. The path is relative to the file, a module that doesn't exist is an error at
+/// the `import`. The modules are only parsed, they are checked where they are compiled, and one
+/// that fails to parse has no known type
+pub fn imported_modules(
+    path: &Path,
+    program: &Program,
+) -> Result<HashMap<String, Program>, Vec<Diagnostic>> {
+    let mut modules = HashMap::new();
+    let mut errors = vec![];
+    walk_program(program, &mut |expression| {
+        let Expression::ImportExpression(expr, span) = expression else {
+            return;
+        };
+        if modules.contains_key(&expr.path) {
+            return;
+        }
+        let relative = Path::new(&expr.path)
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .collect::<PathBuf>();
+        let module = path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(relative)
+            .with_extension("os");
+        if !module.is_file() {
+            errors.push(
+                Diagnostic::error(format!(
+                    "module `{}` not found, expected {}",
+                    expr.path,
+                    module.display()
+                ))
+                .with_label(*span, "imported here"),
+            );
+            return;
+        }
+        let loaded_file = load_file(&module);
+        let Ok((_, tokens)) = Lexer::lex_tokens(loaded_file.as_bytes()) else {
+            return;
+        };
+        if let Ok((_, parsed)) = Parser::parse(Tokens::new(&tokens)) {
+            modules.insert(expr.path.clone(), parsed);
+        }
+    });
+    match errors.is_empty() {
+        true => Ok(modules),
+        false => Err(errors),
+    }
+}
+
+/// Whether the program loads other files, which only compiling to ES modules supports
+pub fn declares_file_modules(program: &Program) -> bool {
+    !file_modules(program).is_empty() || nested_file_modules(program)
+}
+
+/// Names of the top level `mod name;` declarations
+fn file_modules(program: &Program) -> Vec<&str> {
+    program
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::DeclarationStatement(Declaration::ModFileDeclaration(name), _) => {
+                Some(name.0.as_str())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether a `mod name;` declaration is in a block or an inline module
+fn nested_file_modules(program: &Program) -> bool {
+    fn in_module(decl: &ModDecl) -> bool {
+        decl.items.iter().any(|item| match &item.declaration {
+            Declaration::ModFileDeclaration(_) => true,
+            Declaration::ModDeclaration(decl) => in_module(decl),
+            _ => false,
+        })
+    }
+
+    let mut nested = false;
+    for statement in program {
+        if let Statement::DeclarationStatement(Declaration::ModDeclaration(decl), _) = statement {
+            nested |= in_module(decl);
+        }
+        walk_blocks_mut(&mut statement.clone(), &mut |block| {
+            nested |= block.statements.iter().any(|statement| {
+                matches!(
+                    statement,
+                    Statement::DeclarationStatement(Declaration::ModFileDeclaration(_), _)
+                )
+            });
+        });
+    }
+    nested
+}
+
+fn sibling(path: &Path, name: &str) -> PathBuf {
+    path.with_file_name(format!("{}.os", name))
+}
@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use oxidescript::{
+    optimizer::dead_branches::is_pure,
+    parser::{
+        ast::{Declaration, Expression, Program, Statement},
+        visit::walk_statement,
+    },
+};
+
+/// A top level item, by the name of its module and its own name
+type Item = (String, String);
+
+/// Drops the functions, structs and constants of every module that can't be reached from the entry
+/// module. Top level expressions, `const`/`let`s whose initializer might have side effects and
+/// inline `mod` blocks are always kept, and everything they reference is reachable. Impl blocks are
+/// kept together with their struct. Std items like `println` compile to inline builtin calls, so
+/// there is nothing to drop for them. The `pub` items of the `root` module are kept, they are its
+/// exports
+pub fn tree_shake(
+    programs: Vec<(String, Program)>,
+    modules: &[String],
+    root: &str,
+) -> Vec<(String, Program)> {
+    let mut reachable = HashSet::new();
+    let mut pending = vec![];
+    for (module, program) in &programs {
+        for statement in program {
+            let is_pub = matches!(
+                statement,
+                Statement::DeclarationStatement(declaration, _) if declaration.is_pub()
+            );
+            if module == root && is_pub {
+                pending.extend(item_name(statement).map(|name| (module.clone(), name.to_string())));
+            }
+            if !is_shakeable(statement) {
+                pending.extend(references(statement, module, modules));
+            }
+        }
+    }
+    while let Some(item) = pending.pop() {
+        if !reachable.insert(item.clone()) {
+            continue;
+        }
+        let (module, name) = &item;
+        let Some((_, program)) = programs.iter().find(|(other, _)| other == module) else {
+            continue;
+        };
+        for statement in program {
+            if item_name(statement).is_some_and(|declared| declared == name) {
+                pending.extend(references(statement, module, modules));
+            }
+        }
+    }
+
+    programs
+        .into_iter()
+        .map(|(module, program)| {
+            let program = program
+                .into_iter()
+                .filter(|statement| match item_name(statement) {
+                    Some(name) if is_shakeable(statement) => {
+                        reachable.contains(&(module.clone(), name.to_string()))
+                    }
+                    _ => true,
+                })
+                .collect();
+            (module, program)
+        })
+        .collect()
+}
+
+fn is_shakeable(statement: &Statement) -> bool {
+    match statement {
+        Statement::DeclarationStatement(
+            Declaration::FunctionDeclaration { .. }
+            | Declaration::StructDeclaration(_)
+            | Declaration::EnumDeclaration(_)
+            | Declaration::ImplDeclaration(_),
+            _,
+        ) => true,
+        Statement::DeclarationStatement(
+            Declaration::ConstDeclaration(_, value, ..) | Declaration::LetDeclaration(_, value, ..),
+            _,
+        ) => is_pure(value),
+        _ => false,
+    }
+}
+
+/// Impl blocks go by the name of the struct they implement
+fn item_name(statement: &Statement) -> Option<&str> {
+    match statement {
+        Statement::DeclarationStatement(declaration, _) => match declaration {
+            Declaration::ConstDeclaration(name, ..)
+            | Declaration::LetDeclaration(name, _, _, _)
+            | Declaration::FunctionDeclaration { name, .. } => Some(&name.0),
+            Declaration::StructDeclaration(decl) => Some(&decl.name.0),
+            Declaration::EnumDeclaration(decl) => Some(&decl.name.0),
+            Declaration::ImplDeclaration(decl) => Some(&decl.target.0),
+            Declaration::ModDeclaration(decl) => Some(&decl.name.0),
+            Declaration::ModFileDeclaration(name) => Some(&name.0),
+            Declaration::TypeDeclaration(decl) => Some(&decl.name.0),
+            Declaration::TraitDeclaration(decl) => Some(&decl.name.0),
+            // destructurings bind several names and are always kept
+            Declaration::DestructuringDeclaration(..)
+            | Declaration::UseDeclaration(_)
+            | Declaration::ExternDeclaration(_) => None,
+        },
+        Statement::ExpressionStatement { .. } => None,
+    }
+}
+
+/// Items a statement might use. Locals shadowing an item keep it alive, which is wasteful but safe
+fn references(statement: &Statement, module: &str, modules: &[String]) -> Vec<Item> {
+    let mut references = vec![];
+    walk_statement(statement, &mut |expression| match expression {
+        Expression::IdentifierExpression(ident, _) => {
+            references.push((module.to_string(), ident.0.clone()));
+        }
+        Expression::StructExpression(expr, _) => {
+            references.push((module.to_string(), expr.name.0.clone()));
+        }
+        // `geometry::shapes::area` is `area` in `geometry::shapes`, `Point::new` is `Point` here
+        Expression::PathExpression(path, _) => {
+            let segments = path
+                .segments
+                .iter()
+                .map(|segment| segment.0.as_str())
+                .collect::<Vec<_>>();
+            let reference = (1..segments.len())
+                .rev()
+                .find(|length| modules.contains(&segments[..*length].join("::")))
+                .map_or_else(
+                    || (module.to_string(), segments[0].to_string()),
+                    |length| (segments[..length].join("::"), segments[length].to_string()),
+                );
+            references.push(reference);
+        }
+        _ => {}
+    });
+    references
+}
@@ -0,0 +1,131 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use notify::{Event, RecursiveMode, Watcher};
+use oxidescript::parser::ast::Program;
+
+use crate::{load_file, parse_source, Context};
+
+/// Editors save a file in several steps, events this close together belong to the same change
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// The checked programs of source files by path, with the hash of the contents they were parsed
+/// from. A file is only lexed, parsed and checked again once its contents change
+#[derive(Debug, Default)]
+pub struct SourceCache {
+    programs: RefCell<HashMap<PathBuf, (u64, Program)>>,
+    parsed: Cell<usize>,
+}
+
+impl SourceCache {
+    /// Like `parse_source`, but returns the cached program if the file didn't change since. A file
+    /// is always parsed with the same `exports`, by the bundler or by one of the other backends
+    pub fn parse(&self, path: &Path, exports: bool, ctx: &Context) -> Result<Program, String> {
+        let loaded_file = load_file(path);
+        let mut hasher = DefaultHasher::new();
+        loaded_file.hash(&mut hasher);
+        let hash = hasher.finish();
+        if let Some((cached, program)) = self.programs.borrow().get(path) {
+            if *cached == hash {
+                return Ok(program.clone());
+            }
+        }
+        self.parsed.set(self.parsed.get() + 1);
+        let program = parse_source(path, &loaded_file, exports, ctx)?;
+        self.programs
+            .borrow_mut()
+            .insert(path.to_path_buf(), (hash, program.clone()));
+        Ok(program)
+    }
+
+    /// How many files were parsed instead of taken from the cache since the last call
+    pub fn take_parsed(&self) -> usize {
+        self.parsed.take()
+    }
+}
+
+/// Programs of modules by their name
+type Modules = Vec<(String, Program)>;
+
+/// The code of the modules of bundles and of the parts bundles are compiled in, with the programs
+/// they were compiled from: the modules of the part and the ones they depend on, which the code of
+/// a part depends on. Code is only compiled again once one of these programs changes, so a rebuild
+/// only compiles the modules that changed and the ones using them
+#[derive(Debug, Default)]
+pub struct OutputCache {
+    outputs: RefCell<HashMap<String, (Modules, String)>>,
+    compiled: Cell<usize>,
+}
+
+impl OutputCache {
+    /// The code last stored for `id`, if it was compiled from the same programs
+    pub fn get(&self, id: &str, modules: &[(String, Program)]) -> Option<String> {
+        match self.outputs.borrow().get(id) {
+            Some((compiled_from, code)) if compiled_from == modules => Some(code.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&self, id: String, modules: Modules, code: String) {
+        self.outputs.borrow_mut().insert(id, (modules, code));
+    }
+
+    /// The code `compile` returns for a module, which is only called again once the module
+    /// changed since the last call with the same `id`
+    pub fn module(
+        &self,
+        id: String,
+        module: (String, Program),
+        compile: impl FnOnce(Program) -> Result<String, String>,
+    ) -> Result<String, String> {
+        let modules = vec![module];
+        if let Some(code) = self.get(&id, &modules) {
+            return Ok(code);
+        }
+        let code = compile(modules[0].1.clone())?;
+        self.insert(id, modules, code.clone());
+        Ok(code)
+    }
+
+    /// Counts modules whose part of a bundle was compiled again
+    pub fn add_compiled(&self, modules: usize) {
+        self.compiled.set(self.compiled.get() + modules);
+    }
+
+    /// How many modules were compiled instead of taken from the cache since the last call
+    pub fn take_compiled(&self) -> usize {
+        self.compiled.take()
+    }
+}
+
+/// Runs `build` once, and again every time a `.os` file below `dir` is created, changed or
+/// removed. Only returns if watching fails
+pub fn watch(dir: &Path, mut build: impl FnMut()) -> notify::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+    build();
+    while let Ok(event) = receiver.recv() {
+        if !changes_source(&event?) {
+            continue;
+        }
+        while receiver.recv_timeout(DEBOUNCE).is_ok() {}
+        build();
+    }
+    Ok(())
+}
+
+/// Output files are written next to the sources, so only `.os` files count
+fn changes_source(event: &Event) -> bool {
+    !event.kind.is_access()
+        && event
+            .paths
+            .iter()
+            .any(|path| path.extension().is_some_and(|extension| extension == "os"))
+}
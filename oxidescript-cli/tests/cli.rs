@@ -0,0 +1,558 @@
+//! Runs the `oxsc` binary on projects written to a temporary directory and compares what it prints
+//! and writes
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc,
+    time::Duration,
+};
+
+use tempfile::TempDir;
+
+/// A directory with the files, by their path relative to it
+fn project(files: &[(&str, &str)]) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for (path, source) in files {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+/// Whether `oxsc` succeeded with the arguments in `dir`, and what it printed
+fn oxsc(dir: &Path, args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_oxsc"))
+        .args(args)
+        .current_dir(dir)
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+    let printed = String::from_utf8_lossy(&output.stdout).to_string()
+        + &String::from_utf8_lossy(&output.stderr);
+    (output.status.success(), printed)
+}
+
+const CHARTS: &str = "pub fn draw(values: [number]) -> string { \"drawn\" }
+fn hidden() { 1 }
+";
+
+/// A bundle with modules in a directory and a worker, for the tests of `build`
+fn bundle() -> TempDir {
+    let dir = project(&[
+        (
+            "app/util.os",
+            "pub fn double(x: number) -> number { x * 2 }",
+        ),
+        (
+            "app/geo/shapes.os",
+            "pub fn area(w: number, h: number) -> number { w * h }",
+        ),
+        ("app/job.os", "println(\"working\");"),
+        (
+            "app/main.os",
+            "println(util::double(2));
+            println(geo::shapes::area(2, 3));
+            let job = worker::spawn(\"./job\");",
+        ),
+    ]);
+    fs::create_dir(dir.path().join("out")).unwrap();
+    dir
+}
+
+#[test]
+fn imports() {
+    let dir = project(&[
+        ("charts.os", CHARTS),
+        (
+            "main.os",
+            "async fn show() -> string {
+                let charts = import(\"./charts\").await;
+                charts.draw([1, 2])
+            }
+            let _ = show();",
+        ),
+        (
+            "call.os",
+            "async fn show() { import(\"./charts\").await.draw(1) }",
+        ),
+        (
+            "private.os",
+            "async fn show() { import(\"./charts\").await.hidden() }",
+        ),
+        ("missing.os", "let _ = import(\"./nope\");"),
+    ]);
+    assert!(oxsc(dir.path(), &["-i", "main.os", "check"]).0);
+
+    let (ok, printed) = oxsc(dir.path(), &["-i", "call.os", "check"]);
+    assert!(!ok);
+    assert!(
+        printed.contains("argument 1 of `draw` has to be a [number], not a number"),
+        "{}",
+        printed
+    );
+    let (ok, printed) = oxsc(dir.path(), &["-i", "private.os", "check"]);
+    assert!(!ok);
+    assert!(
+        printed.contains("the module `./charts` has no `pub` function or constant `hidden`"),
+        "{}",
+        printed
+    );
+    let (ok, printed) = oxsc(dir.path(), &["-i", "missing.os", "check"]);
+    assert!(!ok);
+    assert!(
+        printed.contains("module `./nope` not found, expected nope.os"),
+        "{}",
+        printed
+    );
+}
+
+#[test]
+fn import_chunks() {
+    let dir = project(&[
+        ("app/charts.os", CHARTS),
+        (
+            "app/main.os",
+            "async fn show() {
+                let charts = import(\"./charts\").await;
+                println(charts.draw([1, 2]));
+            }
+            let _ = show();",
+        ),
+    ]);
+    fs::create_dir(dir.path().join("out")).unwrap();
+    let (ok, printed) = oxsc(
+        dir.path(),
+        &["-i", "app", "build", "--outfile", "out/main.js"],
+    );
+    assert!(ok, "{}", printed);
+    let main = fs::read_to_string(dir.path().join("out/main.js")).unwrap();
+    assert!(main.contains("await import(\"./charts.js\")"), "{}", main);
+    // the chunk exports the `pub` items of its module and nothing else
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out/charts.js")).unwrap(),
+        "export function draw(values) {\n\treturn \"drawn\";\n}\n"
+    );
+}
+
+#[test]
+fn classes() {
+    let dir = project(&[
+        (
+            "app/shapes.os",
+            "pub struct Circle { r: number }
+            impl Circle {
+                get area() -> number { 3 * self.r * self.r }
+            }",
+        ),
+        (
+            "app/main.os",
+            "use shapes::Circle;
+            println(Circle { r: 2 }.area);",
+        ),
+    ]);
+    fs::create_dir(dir.path().join("out")).unwrap();
+    let (ok, printed) = oxsc(
+        dir.path(),
+        &[
+            "-i",
+            "app",
+            "--classes",
+            "build",
+            "--outfile",
+            "out/main.js",
+        ],
+    );
+    assert!(ok, "{}", printed);
+    let main = fs::read_to_string(dir.path().join("out/main.js")).unwrap();
+    // the struct of the other module is a class, which `new` builds
+    assert!(main.contains("class Circle {"), "{}", main);
+    assert!(main.contains("get area() {"), "{}", main);
+    assert!(main.contains("new Circle(2).area"), "{}", main);
+}
+
+#[test]
+fn analyze() {
+    let dir = bundle();
+    let build = [
+        "-i",
+        "app",
+        "build",
+        "--outfile",
+        "out/main.js",
+        "--analyze",
+    ];
+    let (ok, printed) = oxsc(dir.path(), &build);
+    assert!(ok, "{}", printed);
+    let rows = printed
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .collect::<Vec<_>>();
+    // a table for the bundle and one for the chunk of the worker
+    for row in ["main", "util", "geo::shapes", "total", "job.js", "job"] {
+        assert!(rows.contains(&row), "{}", printed);
+    }
+
+    let (ok, printed) = oxsc(dir.path(), &[&build[..], &["json"]].concat());
+    assert!(ok, "{}", printed);
+    let report: serde_json::Value = serde_json::from_str(&printed).unwrap();
+    let bytes = |sizes: &serde_json::Value| {
+        sizes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|size| size["bytes"].as_u64().unwrap())
+            .sum::<u64>()
+    };
+    // every byte of the bundle belongs to a module or the code wrapping them
+    let main = fs::read_to_string(dir.path().join("out/main.js")).unwrap();
+    assert_eq!(bytes(&report["module_sizes"]), main.len() as u64);
+    assert_eq!(report["chunks"][0]["file_name"], "job.js");
+    assert_eq!(report["chunks"][0]["module_sizes"][0]["module"], "job");
+}
+
+#[test]
+fn bare_input() {
+    let dir = project(&[
+        ("util.os", "pub fn double(x: number) -> number { x * 2 }"),
+        ("main.os", "println(util::double(2));"),
+    ]);
+    // the project of a file without a directory is the current directory
+    let build = ["-i", "main.os", "build", "--outfile", "main.js"];
+    let (ok, printed) = oxsc(dir.path(), &build);
+    assert!(ok, "{}", printed);
+    let main = fs::read_to_string(dir.path().join("main.js")).unwrap();
+    assert!(main.contains("x * 2"), "{}", main);
+
+    let (ok, printed) = oxsc(dir.path(), &[&build[..], &["--analyze"]].concat());
+    assert!(ok, "{}", printed);
+    assert!(
+        printed.lines().any(|line| line.starts_with("util")),
+        "{}",
+        printed
+    );
+
+    let (ok, printed) = oxsc(dir.path(), &["-i", "main.os", "graph"]);
+    assert!(ok, "{}", printed);
+}
+
+#[test]
+fn outfile_dirs() {
+    let dir = bundle();
+    // the directories of the outfile are created with the bundle and its chunks
+    let (ok, printed) = oxsc(
+        dir.path(),
+        &["-i", "app", "build", "--outfile", "dist/js/main.js"],
+    );
+    assert!(ok, "{}", printed);
+    assert!(dir.path().join("dist/js/main.js").is_file());
+    assert!(dir.path().join("dist/js/job.js").is_file());
+
+    fs::write(dir.path().join("dist/file"), "").unwrap();
+    let (ok, printed) = oxsc(
+        dir.path(),
+        &["-i", "app", "build", "--outfile", "dist/file/main.js"],
+    );
+    assert!(!ok);
+    assert!(
+        printed.contains("Unable to create dist/file"),
+        "{}",
+        printed
+    );
+}
+
+#[test]
+fn workers() {
+    let dir = bundle();
+    let (ok, printed) = oxsc(
+        dir.path(),
+        &["-i", "app", "build", "--outfile", "out/main.js"],
+    );
+    assert!(ok, "{}", printed);
+    let main = fs::read_to_string(dir.path().join("out/main.js")).unwrap();
+    assert!(
+        main.contains(
+            "let job = new Worker(new URL(\"./job.js\", import.meta.url), { type: \"module\" });"
+        ),
+        "{}",
+        main
+    );
+    // the spawned module is an entry chunk of its own rather than a module of the bundle
+    assert!(!main.contains("working"), "{}", main);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out/job.js")).unwrap(),
+        "console.log(\"working\");\n"
+    );
+}
+
+#[test]
+fn typescript_target() {
+    let dir = project(&[
+        (
+            "app/util.os",
+            "pub fn double(x: number) -> number { x * 2 }",
+        ),
+        ("app/charts.os", CHARTS),
+        (
+            "app/main.os",
+            "fn show(label: string, n: number) { println(label, n); }
+            show(\"double\", util::double(2));
+            async fn chart() { println(import(\"./charts\").await.draw([1])); }
+            let _ = chart();",
+        ),
+    ]);
+    let (ok, printed) = oxsc(dir.path(), &["-i", "app", "build", "--target", "ts"]);
+    assert!(ok, "{}", printed);
+    // the bundle and its chunks are typescript, named after the project by default
+    let bundle = fs::read_to_string(dir.path().join("app.ts")).unwrap();
+    assert!(
+        bundle.contains("function double(x: number): number {"),
+        "{}",
+        bundle
+    );
+    assert!(
+        bundle.contains("function show(label: string, n: number) {"),
+        "{}",
+        bundle
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("charts.ts")).unwrap(),
+        "export function draw(values: number[]): string {\n\treturn \"drawn\";\n}\n"
+    );
+
+    let (ok, printed) = oxsc(
+        dir.path(),
+        &["-i", "app", "--emit", "js", "build", "--target", "ts"],
+    );
+    assert!(!ok);
+    assert!(printed.contains("--target ts can't emit js"), "{}", printed);
+}
+
+#[test]
+fn parse_errors() {
+    let dir = project(&[(
+        "main.os",
+        "let x = ;\nlet y = 1;\nasync fn f() {\n    await f();\n}\n",
+    )]);
+    let (ok, printed) = oxsc(dir.path(), &["-i", "main.os", "check"]);
+    assert!(!ok);
+    // both statements are reported, each at the token the parser stopped at
+    assert!(printed.contains("unexpected `;`"), "{}", printed);
+    assert!(printed.contains("unexpected `await`"), "{}", printed);
+    assert!(printed.contains("main.os:4:5"), "{}", printed);
+    // one label for each error, not one printed for every diagnostic
+    assert_eq!(
+        printed.matches("the parser stopped here").count(),
+        2,
+        "{}",
+        printed
+    );
+}
+
+#[test]
+fn module_errors() {
+    let dir = project(&[
+        ("app/broken.os", "let x = ;"),
+        ("app/unknown.os", "println(undeclared);"),
+        ("app/main.os", "println(1);"),
+        (
+            "app/geo/math.os",
+            "pub fn double(x: number) -> number { x * 2 }",
+        ),
+    ]);
+    fs::create_dir(dir.path().join("out")).unwrap();
+    let (ok, printed) = oxsc(dir.path(), &["-i", "app", "compile", "--outdir", "out"]);
+    assert!(!ok);
+    // the errors of every broken module are reported, and the other modules are still compiled
+    assert!(printed.contains("broken.os:1:9"), "{}", printed);
+    assert!(
+        printed.contains("`undeclared` isn't declared"),
+        "{}",
+        printed
+    );
+    assert!(printed.contains("Compiled 2 of 4 modules"), "{}", printed);
+    assert!(dir.path().join("out/main.js").is_file());
+    assert!(dir.path().join("out/geo/math.js").is_file());
+    assert!(!dir.path().join("out/broken.js").exists());
+}
+
+#[test]
+fn project_paths() {
+    let dir = project(&[
+        ("app/util.os", "fn double(x: number) -> number { x * 2 }"),
+        ("app/main.os", "println(util::double(2));"),
+        ("broken/util.os", "fn double(x: number) -> number { x * 2 }"),
+        ("broken/main.os", "println(util::missing(2));"),
+        ("files/util.os", "fn double(x: number) -> number { x * 2 }"),
+        ("files/main.os", "mod util;\nprintln(util::missing(2));"),
+        ("single.os", "println(util::double(2));"),
+    ]);
+    assert!(oxsc(dir.path(), &["-i", "app", "check"]).0);
+    // the paths of a bundle go through the modules of the project
+    let (ok, printed) = oxsc(dir.path(), &["-i", "broken", "check"]);
+    assert!(!ok);
+    assert!(
+        printed.contains("the module `util` has no item `missing`"),
+        "{}",
+        printed
+    );
+    assert!(printed.contains("broken/main.os:1:9"), "{}", printed);
+    // and the ones of a file through the files it loads
+    let (ok, printed) = oxsc(dir.path(), &["-i", "files/main.os", "check"]);
+    assert!(!ok);
+    assert!(
+        printed.contains("the module `util` has no item `missing`"),
+        "{}",
+        printed
+    );
+    let (ok, printed) = oxsc(dir.path(), &["-i", "single.os", "check"]);
+    assert!(!ok);
+    assert!(printed.contains("`util` isn't declared"), "{}", printed);
+}
+
+#[test]
+fn module_file_exports() {
+    let dir = project(&[
+        (
+            "util.os",
+            "fn helper(x: number) -> number { x + 1 }
+            pub fn double(x: number) -> number { helper(x) * 2 }",
+        ),
+        (
+            "main.os",
+            "mod util;\nuse util::double;\nprintln(double(2));",
+        ),
+        (
+            "uses.os",
+            "mod util;\nuse util::helper;\nprintln(helper(2));",
+        ),
+        ("paths.os", "mod util;\nprintln(util::helper(2));"),
+    ]);
+    // only `pub` items are exported
+    fs::create_dir(dir.path().join("out")).unwrap();
+    let (ok, printed) = oxsc(dir.path(), &["-i", "main.os", "compile", "--outdir", "out"]);
+    assert!(ok, "{}", printed);
+    let util = fs::read_to_string(dir.path().join("out/util.js")).unwrap();
+    assert!(util.contains("export function double"), "{}", util);
+    assert!(!util.contains("export {"), "{}", util);
+
+    let (ok, printed) = oxsc(dir.path(), &["-i", "uses.os", "check"]);
+    assert!(!ok);
+    assert!(printed.contains("`helper` is not `pub` in"), "{}", printed);
+    let (ok, printed) = oxsc(dir.path(), &["-i", "paths.os", "check"]);
+    assert!(!ok);
+    assert!(
+        printed.contains("`helper` is private to the module `util`"),
+        "{}",
+        printed
+    );
+}
+
+#[test]
+fn watch() {
+    let dir = project(&[
+        (
+            "app/util.os",
+            "pub fn double(x: number) -> number { x * 2 }",
+        ),
+        (
+            "app/shapes.os",
+            "pub fn square(x: number) -> number { x * x }",
+        ),
+        (
+            "app/main.os",
+            "println(util::double(2));\nprintln(shapes::square(2));",
+        ),
+    ]);
+    fs::create_dir(dir.path().join("out")).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_oxsc"))
+        .args(["-i", "app", "build", "--outfile", "out/main.js", "--watch"])
+        .current_dir(dir.path())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let _ = sender.send(line.unwrap());
+        }
+    });
+    let next_build = || receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    let built = next_build();
+    assert!(built.contains("compiling 3 modules"), "{}", built);
+    // the module that changed and the entry module using it are compiled again
+    fs::write(
+        dir.path().join("app/util.os"),
+        "pub fn double(x: number) -> number { x + x }",
+    )
+    .unwrap();
+    let rebuilt = next_build();
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(
+        rebuilt.contains("parsing 1 changed files and compiling 2 modules"),
+        "{}",
+        rebuilt
+    );
+    assert!(fs::read_to_string(dir.path().join("out/main.js"))
+        .unwrap()
+        .contains("return x + x;"));
+}
+
+#[test]
+fn graph() {
+    let dir = project(&[
+        ("app/geo/charts.os", CHARTS),
+        ("app/job.os", "println(\"working\");"),
+        (
+            "app/main.os",
+            "async fn show() { println(import(\"./geo/charts\").await.draw([1])); }
+            let job = worker::spawn(\"./job\");
+            let _ = show();",
+        ),
+    ]);
+    let (ok, printed) = oxsc(dir.path(), &["-i", "app", "graph"]);
+    assert!(ok, "{}", printed);
+    // only the `pub` items are exports
+    assert!(printed.contains("exports: draw\"]"), "{}", printed);
+    assert!(
+        printed.contains("\"main\" -> \"geo::charts\" [style=dashed];"),
+        "{}",
+        printed
+    );
+    assert!(
+        printed.contains("\"main\" -> \"job\" [style=dashed];"),
+        "{}",
+        printed
+    );
+}
+
+#[test]
+fn tree_shaking() {
+    let dir = project(&[
+        (
+            "app/util.os",
+            "pub const SCALE = 3;
+            pub const FACTOR = 2;
+            let LOGGED = log(1);
+            fn log(x: number) -> number { println(x); x }
+            pub fn double(x: number) -> number { x * FACTOR }",
+        ),
+        ("app/main.os", "println(util::double(2));"),
+    ]);
+    fs::create_dir(dir.path().join("out")).unwrap();
+    let (ok, printed) = oxsc(
+        dir.path(),
+        &["-i", "app", "build", "--outfile", "out/main.js"],
+    );
+    assert!(ok, "{}", printed);
+    let main = fs::read_to_string(dir.path().join("out/main.js")).unwrap();
+    // unused constants are dropped unless their initializer might have side effects
+    assert!(!main.contains("SCALE"), "{}", main);
+    assert!(main.contains("FACTOR = 2"), "{}", main);
+    assert!(main.contains("LOGGED = log(1)"), "{}", main);
+}
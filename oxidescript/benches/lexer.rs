@@ -0,0 +1,38 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use oxidescript::lexer::Lexer;
+
+const SNIPPET: &str = r#"
+// Computes the area of every shape and logs the result
+fn total_area(shapes: Array, scale_factor: number) {
+    let accumulated_area = 0;
+    for shape in shapes {
+        /* block comments may span
+           several lines */
+        console.log("measuring shape with a rather long description", shape.name);
+        accumulated_area + shape.width * shape.height * scale_factor
+    }
+}
+
+const GREETING = "hello \"escaped\" world";
+let result = total_area([first_shape, second_shape, third_shape], 0x10);
+"#;
+
+/// Large generated inputs, which is where lexing used to dominate compile time
+fn generated_input(repetitions: usize) -> String {
+    SNIPPET.repeat(repetitions)
+}
+
+fn lexer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer");
+    for repetitions in [100, 1000] {
+        let input = generated_input(repetitions);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function(format!("{}_snippets", repetitions), |b| {
+            b.iter(|| Lexer::lex_tokens(black_box(input.as_bytes())).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lexer);
+criterion_main!(benches);
@@ -1,6 +1,27 @@
-use crate::parser::ast::Program;
+use crate::{
+    cancellation::{CancellationToken, Cancelled},
+    parser::ast::Program,
+};
 
 pub trait Compiler {
     fn new() -> Self;
     fn compile(&self, program: Program) -> String;
+
+    /// Compilers that can stop in the middle of a compilation should override this, by default
+    /// cancellation is only checked before and after compiling
+    fn compile_cancellable(
+        &self,
+        program: Program,
+        cancellation: &CancellationToken,
+    ) -> Result<String, Cancelled> {
+        if cancellation.is_cancelled() {
+            return Err(Cancelled);
+        }
+        let compiled = self.compile(program);
+        if cancellation.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(compiled)
+        }
+    }
 }
@@ -0,0 +1,1066 @@
+//! Evaluates constants at compile time. The initializers of `const`s and the bodies of `const fn`s
+//! can only call other `const fn`s, the enum variants and `array::new_2d`, which keeps them to a
+//! pure subset of the language. The `const-eval` pass replaces the parts of top level and
+//! associated constants whose value is known with it, so `const N = 2 * 1024;` becomes
+//! `const N = 2048;`.
+//!
+//! Evaluating follows javascript: numbers are floats, `+` concatenates if either side is a string
+//! and the bitwise operators work on 32 bit integers. Anything the evaluator doesn't know, like a
+//! variable that isn't a constant, a `match` or a closure, is left to the runtime instead of
+//! failing
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+};
+
+use crate::cancellation::{CancellationToken, Cancelled};
+use crate::parser::{
+    ast::{
+        Block, Declaration, Expression, Identifier, ImplItem, InfixOperator, Literal, ModItem,
+        Number, NumberBase, Parameter, Program, Span, Statement, UnaryExpr, UnaryOperator,
+    },
+    types::TypeError,
+    visit::{walk_blocks_mut, walk_expression},
+};
+
+/// How many expressions evaluating a constant can take, so an endless loop in a `const fn` fails
+/// instead of hanging the compiler
+const MAX_STEPS: usize = 1_000_000;
+
+/// How deep `const fn`s can call each other
+const MAX_DEPTH: usize = 256;
+
+/// Grids of `array::new_2d` with more items than this are only built at runtime
+const MAX_ITEMS: f64 = 100_000.0;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Number(f64),
+    String(String),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Tuple(Vec<Value>),
+    /// The name of the struct and the values of its fields
+    Struct(String, Vec<(String, Value)>),
+    /// What loops and blocks without a value evaluate to
+    Nothing,
+}
+
+impl Value {
+    /// Arrays, tuples and structs can be changed through any name that refers to them
+    fn is_scalar(&self) -> bool {
+        matches!(
+            self,
+            Value::Number(_) | Value::String(_) | Value::Boolean(_)
+        )
+    }
+
+    /// The literal of a number, string or boolean. Numbers that aren't finite have no literal, and
+    /// negative ones are negated literals
+    fn literal(&self, span: Span) -> Option<Expression> {
+        let literal = match self {
+            Value::Number(value) if !value.is_finite() => return None,
+            Value::Number(value) if value.is_sign_negative() && *value != 0.0 => {
+                return Some(Expression::UnaryExpression(
+                    UnaryExpr {
+                        op: UnaryOperator::Minus,
+                        rhs: Box::new(Value::Number(-value).literal(span)?),
+                    },
+                    span,
+                ));
+            }
+            Value::Number(value) if value.fract() == 0.0 && *value <= i32::MAX as f64 => {
+                Literal::NumberLiteral(Number::I {
+                    base: NumberBase::Dec,
+                    value: *value as i32,
+                })
+            }
+            Value::Number(value) => Literal::NumberLiteral(Number::F(value.to_string())),
+            Value::String(value) => Literal::StringLiteral(value.clone()),
+            Value::Boolean(value) => Literal::BooleanLiteral(*value),
+            Value::Array(_) | Value::Tuple(_) | Value::Struct(_, _) | Value::Nothing => {
+                return None
+            }
+        };
+        Some(Expression::LiteralExpression(literal, span))
+    }
+
+    /// The string javascript turns the value into when concatenating it. Numbers javascript writes
+    /// with an exponent aren't converted
+    fn concatenated(&self) -> Option<String> {
+        match self {
+            Value::String(value) => Some(value.clone()),
+            Value::Boolean(value) => Some(value.to_string()),
+            Value::Number(value) if *value == 0.0 => Some("0".to_string()),
+            Value::Number(value) if value.is_finite() && (1e-6..1e21).contains(&value.abs()) => {
+                Some(value.to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Why evaluating stopped before there was a value
+enum Stop {
+    /// The value is only known at runtime
+    Unknown,
+    Error(String),
+    Return(Value),
+    Break,
+    Continue,
+}
+
+type Evaluated = Result<Value, Stop>;
+
+/// The variables of the `const fn` being evaluated, innermost block last
+#[derive(Default)]
+struct Frame {
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+impl Frame {
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), value);
+        }
+    }
+
+    fn assign(&mut self, name: &str, value: Value) -> Result<(), Stop> {
+        let variable = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .ok_or(Stop::Unknown)?;
+        *variable = value;
+        Ok(())
+    }
+}
+
+/// Evaluates expressions using the constants and `const fn`s at the top level of a program
+pub struct Evaluator<'a> {
+    prelude: bool,
+    functions: HashMap<&'a str, (&'a [Parameter], &'a Block)>,
+    constants: HashMap<String, &'a Expression>,
+    /// The values of the constants evaluated so far, by their name or `Type::NAME`. `Err(None)`
+    /// is a constant whose value isn't known, which a constant is while it is evaluated, so one
+    /// using itself isn't evaluated forever
+    values: RefCell<HashMap<String, Result<Value, Option<String>>>>,
+    steps: Cell<usize>,
+    depth: Cell<usize>,
+}
+
+impl<'a> Evaluator<'a> {
+    /// `prelude` is whether `array::new_2d` is the one of the prelude
+    pub fn new(program: &'a Program, prelude: bool) -> Self {
+        let mut functions = HashMap::new();
+        let mut constants = HashMap::new();
+        for statement in program {
+            match statement {
+                Statement::DeclarationStatement(
+                    Declaration::FunctionDeclaration {
+                        is_const: true,
+                        name,
+                        parameters,
+                        body,
+                        ..
+                    },
+                    _,
+                ) => {
+                    functions.insert(name.0.as_str(), (parameters.as_slice(), body));
+                }
+                Statement::DeclarationStatement(
+                    Declaration::ConstDeclaration(name, value, ..),
+                    _,
+                ) => {
+                    constants.insert(name.0.clone(), value);
+                }
+                Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), _) => {
+                    for item in &decl.items {
+                        if let ImplItem::Const(name, value) = item {
+                            constants.insert(format!("{}::{}", decl.target.0, name.0), value);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Evaluator {
+            prelude,
+            functions,
+            constants,
+            values: RefCell::new(HashMap::new()),
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+        }
+    }
+
+    /// Replaces the parts of the initializer of a top level or associated constant whose value is
+    /// known with literals, the whole initializer if it is a number, string or boolean. Arrays,
+    /// tuples, structs and calls keep their shape, with their known items and arguments replaced
+    pub fn fold(&self, expression: &mut Expression) {
+        if !matches!(expression, Expression::LiteralExpression(_, _)) {
+            if let Some(literal) = self
+                .value(expression)
+                .ok()
+                .and_then(|value| value.literal(expression.span()))
+            {
+                *expression = literal;
+                return;
+            }
+        }
+        match expression {
+            Expression::ArrayExpression(items, _) | Expression::TupleExpression(items, _) => {
+                for item in items {
+                    self.fold(item);
+                }
+            }
+            Expression::StructExpression(expr, _) => {
+                for (_, value) in &mut expr.fields {
+                    self.fold(value);
+                }
+            }
+            Expression::CallExpression(expr, _) => {
+                for argument in &mut expr.arguments {
+                    self.fold(argument);
+                }
+            }
+            Expression::CastExpression(expr, _) => self.fold(&mut expr.value),
+            _ => {}
+        }
+    }
+
+    /// The value of an expression outside of any function. `Err(None)` if it is only known at
+    /// runtime, `Err(Some(message))` if evaluating it failed
+    pub fn value(&self, expression: &Expression) -> Result<Value, Option<String>> {
+        self.steps.set(0);
+        self.depth.set(0);
+        self.evaluate_outside(expression)
+    }
+
+    fn evaluate_outside(&self, expression: &Expression) -> Result<Value, Option<String>> {
+        match self.evaluate(expression, &mut Frame::default()) {
+            Ok(value) => Ok(value),
+            Err(Stop::Error(message)) => Err(Some(message)),
+            Err(_) => Err(None),
+        }
+    }
+
+    /// The value of the constant with the name, or `Type::NAME` for an associated one. Only
+    /// numbers, strings and booleans, the items of arrays and the fields of structs can be changed
+    fn constant(&self, name: &str) -> Evaluated {
+        let cached = self.values.borrow().get(name).cloned();
+        let result = match cached {
+            Some(result) => result,
+            None => {
+                let Some(initializer) = self.constants.get(name) else {
+                    return Err(Stop::Unknown);
+                };
+                self.values.borrow_mut().insert(name.to_string(), Err(None));
+                let result =
+                    self.evaluate_outside(initializer)
+                        .and_then(|value| match value.is_scalar() {
+                            true => Ok(value),
+                            false => Err(None),
+                        });
+                self.values
+                    .borrow_mut()
+                    .insert(name.to_string(), result.clone());
+                result
+            }
+        };
+        result.map_err(|message| message.map_or(Stop::Unknown, Stop::Error))
+    }
+
+    fn step(&self) -> Result<(), Stop> {
+        self.steps.set(self.steps.get() + 1);
+        match self.steps.get() > MAX_STEPS {
+            true => Err(Stop::Error(format!(
+                "evaluating it takes more than {} steps",
+                MAX_STEPS
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    fn evaluate(&self, expression: &Expression, frame: &mut Frame) -> Evaluated {
+        self.step()?;
+        match expression {
+            Expression::LiteralExpression(literal, _) => Ok(match literal {
+                Literal::NumberLiteral(number) => {
+                    Value::Number(f64::try_from(number.clone()).map_err(|_| Stop::Unknown)?)
+                }
+                Literal::StringLiteral(value) => Value::String(value.clone()),
+                Literal::BooleanLiteral(value) => Value::Boolean(*value),
+            }),
+            Expression::IdentifierExpression(name, _) => match frame.lookup(&name.0) {
+                Some(value) => Ok(value.clone()),
+                None => self.constant(&name.0),
+            },
+            Expression::PathExpression(path, _) => match &path.segments[..] {
+                [type_, name] => self.constant(&format!("{}::{}", type_.0, name.0)),
+                _ => Err(Stop::Unknown),
+            },
+            Expression::UnaryExpression(expr, _) => {
+                let value = self.evaluate(&expr.rhs, frame)?;
+                match (&expr.op, value) {
+                    (UnaryOperator::Minus, Value::Number(value)) => Ok(Value::Number(-value)),
+                    (UnaryOperator::Plus, Value::Number(value)) => Ok(Value::Number(value)),
+                    (UnaryOperator::BitwiseNot, Value::Number(value)) => {
+                        Ok(Value::Number(!to_int32(value) as f64))
+                    }
+                    (UnaryOperator::LogicalNot, Value::Boolean(value)) => {
+                        Ok(Value::Boolean(!value))
+                    }
+                    _ => Err(Stop::Unknown),
+                }
+            }
+            Expression::InfixExpression(expr, _) => {
+                let lhs = self.evaluate(&expr.lhs, frame)?;
+                // the right side of `&&`, `||` and `??` is only evaluated if it is needed
+                match (&expr.op, &lhs) {
+                    (InfixOperator::LogicalAnd, Value::Boolean(false))
+                    | (InfixOperator::LogicalOr, Value::Boolean(true)) => return Ok(lhs),
+                    (InfixOperator::NullishCoalesce, _) => return Ok(lhs),
+                    _ => {}
+                }
+                let rhs = self.evaluate(&expr.rhs, frame)?;
+                infix(&expr.op, lhs, rhs)
+            }
+            Expression::AssignmentExpression(expr, _) => {
+                let Expression::IdentifierExpression(name, _) = &*expr.lhs else {
+                    return Err(Stop::Unknown);
+                };
+                let mut value = self.evaluate(&expr.rhs, frame)?;
+                if let Some(op) = &expr.op {
+                    let current = frame.lookup(&name.0).cloned().ok_or(Stop::Unknown)?;
+                    value = infix(op, current, value)?;
+                }
+                frame.assign(&name.0, value.clone())?;
+                Ok(value)
+            }
+            Expression::ArrayExpression(items, _) => Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.evaluate(item, frame))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expression::TupleExpression(items, _) => Ok(Value::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.evaluate(item, frame))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expression::StructExpression(expr, _) => Ok(Value::Struct(
+                expr.name.0.clone(),
+                expr.fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.0.clone(), self.evaluate(value, frame)?)))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expression::TemplateExpression(expr, _) => {
+                let mut text = expr.strings[0].clone();
+                for (value, string) in expr.values.iter().zip(&expr.strings[1..]) {
+                    let value = self.evaluate(value, frame)?;
+                    text.push_str(&value.concatenated().ok_or(Stop::Unknown)?);
+                    text.push_str(string);
+                }
+                Ok(Value::String(text))
+            }
+            Expression::MemberAccessExpression(expr, _) => {
+                match (self.evaluate(&expr.lhs, frame)?, expr.ident.0.as_str()) {
+                    (Value::String(value), "length") => {
+                        Ok(Value::Number(value.encode_utf16().count() as f64))
+                    }
+                    (Value::Array(items), "length") => Ok(Value::Number(items.len() as f64)),
+                    (Value::Struct(_, fields), name) => fields
+                        .into_iter()
+                        .find(|(field, _)| field == name)
+                        .map(|(_, value)| value)
+                        .ok_or(Stop::Unknown),
+                    _ => Err(Stop::Unknown),
+                }
+            }
+            Expression::IndexExpression(expr, _) => {
+                let lhs = self.evaluate(&expr.lhs, frame)?;
+                let index = self.evaluate(&expr.index, frame)?;
+                match (lhs, index) {
+                    (Value::Array(mut items), Value::Number(index))
+                        if index.fract() == 0.0 && (0.0..items.len() as f64).contains(&index) =>
+                    {
+                        Ok(items.swap_remove(index as usize))
+                    }
+                    _ => Err(Stop::Unknown),
+                }
+            }
+            Expression::TupleIndexExpression(expr, _) => match self.evaluate(&expr.lhs, frame)? {
+                Value::Tuple(mut items) if expr.index < items.len() => {
+                    Ok(items.swap_remove(expr.index))
+                }
+                _ => Err(Stop::Unknown),
+            },
+            // `as` only changes the type
+            Expression::CastExpression(expr, _) => self.evaluate(&expr.value, frame),
+            Expression::BlockExpression(block, _) => self.evaluate_block(block, frame),
+            Expression::IfExpression(expr, _) => {
+                if self.condition(&expr.condition, frame)? {
+                    return self.evaluate_block(&expr.then_block, frame);
+                }
+                for else_if in &expr.else_if_blocks {
+                    if self.condition(&else_if.condition, frame)? {
+                        return self.evaluate_block(&else_if.then_block, frame);
+                    }
+                }
+                match &expr.else_block {
+                    Some(block) => self.evaluate_block(block, frame),
+                    None => Ok(Value::Nothing),
+                }
+            }
+            Expression::WhileExpression(expr, _) => {
+                while self.condition(&expr.condition, frame)? {
+                    match self.evaluate_block(&expr.body, frame) {
+                        Ok(_) | Err(Stop::Continue) => {}
+                        Err(Stop::Break) => break,
+                        Err(stop) => return Err(stop),
+                    }
+                }
+                Ok(Value::Nothing)
+            }
+            Expression::ForExpression(expr, _) => {
+                let items = match &*expr.rhs {
+                    Expression::RangeExpression(range, _) => {
+                        let (Value::Number(start), Value::Number(end)) = (
+                            self.evaluate(&range.start, frame)?,
+                            self.evaluate(&range.end, frame)?,
+                        ) else {
+                            return Err(Stop::Unknown);
+                        };
+                        let end = if range.inclusive { end + 1.0 } else { end };
+                        if end - start > MAX_STEPS as f64 {
+                            return Err(Stop::Unknown);
+                        }
+                        let mut items = vec![];
+                        let mut item = start;
+                        while item < end {
+                            items.push(Value::Number(item));
+                            item += 1.0;
+                        }
+                        items
+                    }
+                    rhs => match self.evaluate(rhs, frame)? {
+                        Value::Array(items) => items,
+                        Value::String(value) => value
+                            .chars()
+                            .map(|char| Value::String(char.to_string()))
+                            .collect(),
+                        _ => return Err(Stop::Unknown),
+                    },
+                };
+                for item in items {
+                    frame
+                        .scopes
+                        .push(HashMap::from([(expr.lhs.0.clone(), item)]));
+                    let result = self.evaluate_block(&expr.body, frame);
+                    frame.scopes.pop();
+                    match result {
+                        Ok(_) | Err(Stop::Continue) => {}
+                        Err(Stop::Break) => break,
+                        Err(stop) => return Err(stop),
+                    }
+                }
+                Ok(Value::Nothing)
+            }
+            Expression::ReturnExpression(value, _) => Err(Stop::Return(match value {
+                Some(value) => self.evaluate(value, frame)?,
+                None => Value::Nothing,
+            })),
+            Expression::BreakExpression(_) => Err(Stop::Break),
+            Expression::ContinueExpression(_) => Err(Stop::Continue),
+            Expression::CallExpression(expr, _) => {
+                let arguments = expr
+                    .arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument, frame))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match &*expr.lhs {
+                    Expression::IdentifierExpression(name, _)
+                        if frame.lookup(&name.0).is_none() =>
+                    {
+                        self.call(&name.0, arguments)
+                    }
+                    Expression::PathExpression(path, _)
+                        if self.prelude && is_path(&path.segments, "array", "new_2d") =>
+                    {
+                        new_2d(arguments)
+                    }
+                    _ => Err(Stop::Unknown),
+                }
+            }
+            _ => Err(Stop::Unknown),
+        }
+    }
+
+    fn condition(&self, condition: &Expression, frame: &mut Frame) -> Result<bool, Stop> {
+        match self.evaluate(condition, frame)? {
+            Value::Boolean(value) => Ok(value),
+            _ => Err(Stop::Unknown),
+        }
+    }
+
+    fn evaluate_block(&self, block: &Block, frame: &mut Frame) -> Evaluated {
+        frame.scopes.push(HashMap::new());
+        let result = self.evaluate_statements(block, frame);
+        frame.scopes.pop();
+        result
+    }
+
+    fn evaluate_statements(&self, block: &Block, frame: &mut Frame) -> Evaluated {
+        for statement in &block.statements {
+            match statement {
+                Statement::ExpressionStatement { expression, .. } => {
+                    self.evaluate(expression, frame)?;
+                }
+                Statement::DeclarationStatement(
+                    Declaration::LetDeclaration(name, value, _, _)
+                    | Declaration::ConstDeclaration(name, value, _, _, _),
+                    _,
+                ) => {
+                    let value = self.evaluate(value, frame)?;
+                    frame.declare(&name.0, value);
+                }
+                Statement::DeclarationStatement(_, _) => return Err(Stop::Unknown),
+            }
+        }
+        match &block.return_value {
+            Some(value) => self.evaluate(value, frame),
+            None => Ok(Value::Nothing),
+        }
+    }
+
+    /// Calls the `const fn` with the name
+    fn call(&self, name: &str, arguments: Vec<Value>) -> Evaluated {
+        let Some((parameters, body)) = self.functions.get(name) else {
+            return Err(Stop::Unknown);
+        };
+        if parameters.len() != arguments.len() {
+            return Err(Stop::Unknown);
+        }
+        if self.depth.get() == MAX_DEPTH {
+            return Err(Stop::Error(format!(
+                "`const fn`s call each other more than {} levels deep",
+                MAX_DEPTH
+            )));
+        }
+        let mut frame = Frame {
+            scopes: vec![parameters
+                .iter()
+                .map(|parameter| parameter.name.0.clone())
+                .zip(arguments)
+                .collect()],
+        };
+        self.depth.set(self.depth.get() + 1);
+        let result = self.evaluate_statements(body, &mut frame);
+        self.depth.set(self.depth.get() - 1);
+        match result {
+            Ok(value) | Err(Stop::Return(value)) => Ok(value),
+            Err(Stop::Break | Stop::Continue) => Err(Stop::Unknown),
+            Err(stop) => Err(stop),
+        }
+    }
+}
+
+fn infix(op: &InfixOperator, lhs: Value, rhs: Value) -> Evaluated {
+    use InfixOperator::*;
+    let value = match (op, lhs, rhs) {
+        (Plus, Value::Number(lhs), Value::Number(rhs)) => Value::Number(lhs + rhs),
+        (Plus, lhs @ Value::String(_), rhs) | (Plus, lhs, rhs @ Value::String(_)) => Value::String(
+            lhs.concatenated().ok_or(Stop::Unknown)? + &rhs.concatenated().ok_or(Stop::Unknown)?,
+        ),
+        (Minus, Value::Number(lhs), Value::Number(rhs)) => Value::Number(lhs - rhs),
+        (Multiply, Value::Number(lhs), Value::Number(rhs)) => Value::Number(lhs * rhs),
+        (Divide, Value::Number(lhs), Value::Number(rhs)) => Value::Number(lhs / rhs),
+        // the remainder has the sign of the dividend, like `%` of javascript
+        (Modulo, Value::Number(lhs), Value::Number(rhs)) => Value::Number(lhs % rhs),
+        (BitwiseAnd, Value::Number(lhs), Value::Number(rhs)) => {
+            Value::Number((to_int32(lhs) & to_int32(rhs)) as f64)
+        }
+        (BitwiseOr, Value::Number(lhs), Value::Number(rhs)) => {
+            Value::Number((to_int32(lhs) | to_int32(rhs)) as f64)
+        }
+        (BitwiseXor, Value::Number(lhs), Value::Number(rhs)) => {
+            Value::Number((to_int32(lhs) ^ to_int32(rhs)) as f64)
+        }
+        (BitwiseLeftShift, Value::Number(lhs), Value::Number(rhs)) => {
+            Value::Number(to_int32(lhs).wrapping_shl(to_int32(rhs) as u32 & 31) as f64)
+        }
+        (BitwiseRightShift, Value::Number(lhs), Value::Number(rhs)) => {
+            Value::Number((to_int32(lhs) >> (to_int32(rhs) as u32 & 31)) as f64)
+        }
+        (LessThan, Value::Number(lhs), Value::Number(rhs)) => Value::Boolean(lhs < rhs),
+        (LessThanEqual, Value::Number(lhs), Value::Number(rhs)) => Value::Boolean(lhs <= rhs),
+        (GreaterThan, Value::Number(lhs), Value::Number(rhs)) => Value::Boolean(lhs > rhs),
+        (GreaterThanEqual, Value::Number(lhs), Value::Number(rhs)) => Value::Boolean(lhs >= rhs),
+        // javascript compares strings by their UTF-16 code units
+        (LessThan, Value::String(lhs), Value::String(rhs)) => {
+            Value::Boolean(lhs.encode_utf16().lt(rhs.encode_utf16()))
+        }
+        (LessThanEqual, Value::String(lhs), Value::String(rhs)) => {
+            Value::Boolean(lhs.encode_utf16().le(rhs.encode_utf16()))
+        }
+        (GreaterThan, Value::String(lhs), Value::String(rhs)) => {
+            Value::Boolean(lhs.encode_utf16().gt(rhs.encode_utf16()))
+        }
+        (GreaterThanEqual, Value::String(lhs), Value::String(rhs)) => {
+            Value::Boolean(lhs.encode_utf16().ge(rhs.encode_utf16()))
+        }
+        // `==` compares values with `===`, arrays and structs by their identity
+        (Equal | NotEqual, lhs, rhs) if lhs.is_scalar() && rhs.is_scalar() => {
+            Value::Boolean((lhs == rhs) == (*op == Equal))
+        }
+        (LogicalAnd | LogicalOr, Value::Boolean(_), rhs @ Value::Boolean(_)) => rhs,
+        _ => return Err(Stop::Unknown),
+    };
+    Ok(value)
+}
+
+/// The number as the 32 bit integer the bitwise operators of javascript work on
+fn to_int32(value: f64) -> i32 {
+    if !value.is_finite() {
+        return 0;
+    }
+    value.trunc().rem_euclid(4294967296.0) as u32 as i32
+}
+
+fn is_path(segments: &[Identifier], module: &str, name: &str) -> bool {
+    matches!(segments, [first, second] if first.0 == module && second.0 == name)
+}
+
+/// `array::new_2d(width, height, init)`, a grid of `height` rows with `width` items each
+fn new_2d(arguments: Vec<Value>) -> Evaluated {
+    let [width, height, init] = <[Value; 3]>::try_from(arguments).map_err(|_| Stop::Unknown)?;
+    let (Value::Number(width), Value::Number(height)) = (width, height) else {
+        return Err(Stop::Unknown);
+    };
+    for (size, value) in [("width", width), ("height", height)] {
+        if value < 0.0 || value.fract() != 0.0 {
+            return Err(Stop::Error(format!(
+                "the {} of `array::new_2d` has to be a whole number that isn't negative, not {}",
+                size, value
+            )));
+        }
+    }
+    if width * height > MAX_ITEMS {
+        return Err(Stop::Unknown);
+    }
+    let row = Value::Array(vec![init; width as usize]);
+    Ok(Value::Array(vec![row; height as usize]))
+}
+
+/// The `const-eval` pass, replaces the known parts of the initializer of a top level constant, or
+/// of the associated constants of an impl block, with their values
+pub fn fold_constants(statement: &mut Statement, evaluator: &Evaluator) {
+    let Statement::DeclarationStatement(declaration, _) = statement else {
+        return;
+    };
+    match declaration {
+        Declaration::ConstDeclaration(_, value, ..) => evaluator.fold(value),
+        Declaration::ImplDeclaration(decl) => {
+            for item in &mut decl.items {
+                if let ImplItem::Const(_, value) = item {
+                    evaluator.fold(value);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Checks that the initializers of constants and the bodies of `const fn`s only call `const fn`s,
+/// the variants of enums and `array::new_2d`, and evaluates the top level and associated constants
+/// to find the ones that fail, like a `const fn` that never returns. Calls in closures aren't made
+/// by the initializer, so they can call anything
+pub fn check_constants(program: &Program, prelude: bool) -> Result<(), TypeError> {
+    check_constants_with(program, prelude, None)
+        .expect("checking without cancellation token was cancelled")
+}
+
+/// Like [`check_constants`], but checks `cancellation` before every top level statement it
+/// checks and every constant it evaluates
+pub fn check_constants_cancellable(
+    program: &Program,
+    prelude: bool,
+    cancellation: &CancellationToken,
+) -> Result<Result<(), TypeError>, Cancelled> {
+    check_constants_with(program, prelude, Some(cancellation))
+}
+
+fn check_constants_with(
+    program: &Program,
+    prelude: bool,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Result<(), TypeError>, Cancelled> {
+    let cancelled = || cancellation.is_some_and(|cancellation| cancellation.is_cancelled());
+    let mut callable = Callable {
+        functions: HashSet::new(),
+        variants: HashSet::new(),
+        prelude,
+    };
+    for statement in program {
+        callable.declare(statement);
+        walk_blocks_mut(&mut statement.clone(), &mut |block| {
+            for statement in &block.statements {
+                callable.declare(statement);
+            }
+        });
+    }
+    for statement in program {
+        if cancelled() {
+            return Err(Cancelled);
+        }
+        let mut result = check_statement(statement, &callable);
+        walk_blocks_mut(&mut statement.clone(), &mut |block| {
+            for statement in &block.statements {
+                if result.is_ok() {
+                    result = check_statement(statement, &callable);
+                }
+            }
+        });
+        if result.is_err() {
+            return Ok(result);
+        }
+    }
+    let evaluator = Evaluator::new(program, prelude);
+    for statement in program {
+        let Statement::DeclarationStatement(declaration, span) = statement else {
+            continue;
+        };
+        let constants = match declaration {
+            Declaration::ConstDeclaration(name, value, ..) => vec![(name.0.clone(), value)],
+            Declaration::ImplDeclaration(decl) => decl
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    ImplItem::Const(name, value) => {
+                        Some((format!("{}::{}", decl.target.0, name.0), value))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        for (name, value) in constants {
+            if cancelled() {
+                return Err(Cancelled);
+            }
+            if let Err(Some(message)) = evaluator.value(value) {
+                return Ok(Err(TypeError {
+                    message: format!("can't evaluate `const {}`: {}", name, message),
+                    span: if value.span().is_empty() {
+                        *span
+                    } else {
+                        value.span()
+                    },
+                }));
+            }
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// What the initializer of a constant can call
+struct Callable {
+    functions: HashSet<String>,
+    /// `Enum::Variant` of the enums with a variant holding values
+    variants: HashSet<String>,
+    prelude: bool,
+}
+
+impl Callable {
+    fn declare(&mut self, statement: &Statement) {
+        let Statement::DeclarationStatement(declaration, _) = statement else {
+            return;
+        };
+        match declaration {
+            Declaration::FunctionDeclaration {
+                is_const: true,
+                name,
+                ..
+            } => {
+                self.functions.insert(name.0.clone());
+            }
+            Declaration::EnumDeclaration(decl) => {
+                self.variants.extend(
+                    decl.variants
+                        .iter()
+                        .map(|variant| format!("{}::{}", decl.name.0, variant.name.0)),
+                );
+            }
+            Declaration::ModDeclaration(decl) => {
+                for item in &decl.items {
+                    self.declare(&Statement::DeclarationStatement(
+                        item.declaration.clone(),
+                        item.span,
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn allows(&self, callee: &Expression) -> bool {
+        match callee {
+            Expression::IdentifierExpression(name, _) => self.functions.contains(&name.0),
+            Expression::PathExpression(path, _) => {
+                let path = path
+                    .segments
+                    .iter()
+                    .map(|segment| segment.0.as_str())
+                    .collect::<Vec<_>>()
+                    .join("::");
+                self.variants.contains(&path) || (self.prelude && path == "array::new_2d")
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Checks the constant or `const fn` a statement declares, and the ones of a `mod` block
+fn check_statement(statement: &Statement, callable: &Callable) -> Result<(), TypeError> {
+    let Statement::DeclarationStatement(declaration, _) = statement else {
+        return Ok(());
+    };
+    match declaration {
+        Declaration::ConstDeclaration(name, value, ..) => {
+            check_calls(value, &format!("const {}", name.0), callable)
+        }
+        Declaration::FunctionDeclaration {
+            is_const: true,
+            name,
+            body,
+            ..
+        } => check_calls(
+            &Expression::BlockExpression(Box::new(body.clone()), body.span),
+            &format!("const fn {}", name.0),
+            callable,
+        ),
+        Declaration::ImplDeclaration(decl) => {
+            for item in &decl.items {
+                if let ImplItem::Const(name, value) = item {
+                    let subject = format!("const {}::{}", decl.target.0, name.0);
+                    check_calls(value, &subject, callable)?;
+                }
+            }
+            Ok(())
+        }
+        Declaration::ModDeclaration(decl) => {
+            for ModItem {
+                declaration, span, ..
+            } in &decl.items
+            {
+                check_statement(
+                    &Statement::DeclarationStatement(declaration.clone(), *span),
+                    callable,
+                )?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The first call of something that isn't allowed in the expression, outside of closures
+fn check_calls(
+    expression: &Expression,
+    subject: &str,
+    callable: &Callable,
+) -> Result<(), TypeError> {
+    let mut closures = vec![];
+    let mut calls = vec![];
+    walk_expression(expression, &mut |expression| match expression {
+        Expression::ClosureExpression(_, span) => closures.push(*span),
+        Expression::CallExpression(expr, span) if !callable.allows(&expr.lhs) => {
+            calls.push((callee_name(&expr.lhs), *span))
+        }
+        Expression::TaggedTemplateExpression(expr, span) => {
+            calls.push((callee_name(&expr.tag), *span))
+        }
+        _ => {}
+    });
+    let call = calls.into_iter().find(|(_, span)| {
+        !closures
+            .iter()
+            .any(|closure| closure.start <= span.start && span.end <= closure.end)
+    });
+    match call {
+        Some((callee, span)) => Err(TypeError {
+            message: match callee {
+                Some(callee) => {
+                    format!("`{}` calls `{}`, which isn't a `const fn`", subject, callee)
+                }
+                None => format!("`{}` can only call `const fn`s", subject),
+            },
+            span,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// `name`, `Type::name` or `value.method` of a callee
+fn callee_name(callee: &Expression) -> Option<String> {
+    match callee {
+        Expression::IdentifierExpression(name, _) => Some(name.0.clone()),
+        Expression::PathExpression(path, _) => Some(
+            path.segments
+                .iter()
+                .map(|segment| segment.0.as_str())
+                .collect::<Vec<_>>()
+                .join("::"),
+        ),
+        Expression::MemberAccessExpression(expr, _) => {
+            Some(format!("{}.{}", callee_name(&expr.lhs)?, expr.ident.0))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    /// The program with its constants folded
+    fn folded(input: &str) -> Program {
+        let program = parse(input);
+        let evaluator = Evaluator::new(&program, true);
+        let mut folded = program.clone();
+        for statement in &mut folded {
+            fold_constants(statement, &evaluator);
+        }
+        folded
+    }
+
+    #[test]
+    fn folds_constants() {
+        let input = "const fn fib(n: number) -> number {
+                let a = 0;
+                let b = 1;
+                for i in 0..n {
+                    let next = a + b;
+                    a = b;
+                    b = next;
+                }
+                a
+            }
+            const SIZE = 2 * 1024;
+            const FIB = fib(SIZE / 128);
+            const NAME = f\"grid {SIZE}\" + \"!\";
+            const SMALL = SIZE < 10 || NAME.length == 10;
+            const HALF = -1 / 2;
+            const FLAGS = ~0 & 6 | 1 << 4;
+            const ITEMS = [SIZE, FIB + 1, now()];
+            impl Circle {
+                const TAU = 3.5 * 2;
+            }";
+        let expected = "const fn fib(n: number) -> number {
+                let a = 0;
+                let b = 1;
+                for i in 0..n {
+                    let next = a + b;
+                    a = b;
+                    b = next;
+                }
+                a
+            }
+            const SIZE = 2048;
+            const FIB = 987;
+            const NAME = \"grid 2048!\";
+            const SMALL = true;
+            const HALF = -0.5;
+            const FLAGS = 22;
+            const ITEMS = [2048, 988, now()];
+            impl Circle {
+                const TAU = 7;
+            }";
+        assert_eq!(folded(input), parse(expected));
+    }
+
+    #[test]
+    fn leaves_runtime_values() {
+        let input = "const START = Date.now();
+            const LATER = START + 1;
+            const ITEMS = [1, 2];
+            const FIRST = ITEMS[0];
+            const BIG = 1e21 + \"\";
+            const GRID = array::new_2d(1000, 1000, 0);";
+        assert_eq!(folded(input), parse(input));
+    }
+
+    #[test]
+    fn evaluates_grids() {
+        let program = parse("const GRID = array::new_2d(2, 1, false);");
+        let Statement::DeclarationStatement(Declaration::ConstDeclaration(_, value, ..), _) =
+            &program[0]
+        else {
+            unreachable!()
+        };
+        let row = Value::Array(vec![Value::Boolean(false); 2]);
+        assert_eq!(
+            Evaluator::new(&program, true).value(value),
+            Ok(Value::Array(vec![row]))
+        );
+        assert_eq!(Evaluator::new(&program, false).value(value), Err(None));
+    }
+
+    #[test]
+    fn checks_calls() {
+        let error = |input: &str| check_constants(&parse(input), true).unwrap_err().message;
+        assert_eq!(
+            error("fn compute() -> number { 1 } const N = compute();"),
+            "`const N` calls `compute`, which isn't a `const fn`"
+        );
+        assert_eq!(
+            error("const fn round(x: number) -> number { Math.floor(x) }"),
+            "`const fn round` calls `Math.floor`, which isn't a `const fn`"
+        );
+        assert_eq!(
+            error("impl Circle { const R = random(); }"),
+            "`const Circle::R` calls `random`, which isn't a `const fn`"
+        );
+        assert_eq!(
+            error("fn main() { const NOW = Date.now(); }"),
+            "`const NOW` calls `Date.now`, which isn't a `const fn`"
+        );
+        assert_eq!(
+            error("const fn spin() -> number { while true {} 1 } const X = spin();"),
+            "can't evaluate `const X`: evaluating it takes more than 1000000 steps"
+        );
+        assert_eq!(
+            error("const G = array::new_2d(1.5, 2, 0);"),
+            "can't evaluate `const G`: the width of `array::new_2d` has to be a whole number \
+             that isn't negative, not 1.5"
+        );
+
+        let valid = "enum Shape { Circle(number) }
+            const fn double(x: number) -> number { x * 2 }
+            const SHAPE = Shape::Circle(double(2));
+            const HANDLER = |x: number| console.log(x);
+            const GRID = array::new_2d(2, 2, 0);";
+        assert_eq!(check_constants(&parse(valid), true), Ok(()));
+        assert!(check_constants(&parse("const GRID = array::new_2d(2, 2, 0);"), false).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! Errors and warnings of every stage of the compiler, with the spans of the source they are about.
+//! The stages report them as plain values, printing them with the lines of the source is up to the
+//! tools using the compiler
+
+use std::fmt;
+
+use crate::parser::{ast::Span, types::TypeError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A span of the source with what it has to do with the diagnostic, like `not declared here`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// One error or warning. The first label is the primary one, the others point at code that
+/// explains it, like the bound an argument misses
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    /// How to fix the problem, if there is an obvious way
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            labels: vec![],
+            help: None,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message)
+        }
+    }
+
+    /// Adds a label, empty spans of code the parser didn't track are left out
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        if !span.is_empty() {
+            self.labels.push(Label {
+                span,
+                message: message.into(),
+            });
+        }
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+/// Errors of the checks that don't know where in the source they are
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Diagnostic::error(message)
+    }
+}
+
+impl From<TypeError> for Diagnostic {
+    fn from(err: TypeError) -> Self {
+        Diagnostic::error(err.message).with_label(err.span, "")
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(help) = &self.help {
+            write!(f, "\nhelp: {}", help)?;
+        }
+        Ok(())
+    }
+}
@@ -1,26 +1,25 @@
 pub mod token;
 pub mod tokens;
-pub mod utils;
 
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take};
-use nom::character::complete::{alpha1, alphanumeric1, digit1, multispace0};
-use nom::combinator::{map, map_res, recognize};
-use nom::multi::{many0, many1};
-use nom::sequence::{delimited, pair};
+use nom::bytes::complete::{tag, take, take_while, take_while1};
+use nom::character::complete::{alpha1, one_of};
+use nom::combinator::{map, map_res, not, opt, recognize};
+use nom::error::ErrorKind;
+use nom::sequence::{pair, terminated, tuple};
 use nom::*;
 
+use memchr::{memchr, memchr2, memmem};
+use std::borrow::Cow;
 use std::str;
-use std::str::FromStr;
 
-use self::token::Token;
-use self::utils::{concat_slice_vec, convert_vec_utf8};
+use self::token::{Span, Token, Trivia};
 
 // Inspired by: https://github.com/Rydgel/monkey-rust
 
 macro_rules! syntax {
     ($fn_name: ident, $tag_string: literal, $output_token: expr) => {
-        fn $fn_name(s: &[u8]) -> IResult<&[u8], Token> {
+        fn $fn_name(s: &[u8]) -> IResult<&[u8], Token<'_>> {
             map(tag($tag_string), |_| $output_token)(s)
         }
     };
@@ -34,6 +33,7 @@ syntax!(less_than_operator, "<", Token::LessThan);
 syntax!(logical_not_operator, "!", Token::LogicalNot);
 syntax!(logical_and_operator, "&&", Token::LogicalAnd);
 syntax!(logical_or_operator, "||", Token::LogicalOr);
+syntax!(nullish_coalesce_operator, "??", Token::NullishCoalesce);
 syntax!(bitwise_not_operator, "~", Token::BitwiseNot);
 syntax!(bitwise_and_operator, "&", Token::BitwiseAnd);
 syntax!(bitwise_or_operator, "|", Token::BitwiseOr);
@@ -48,25 +48,48 @@ syntax!(multiply_operator, "*", Token::Multiply);
 syntax!(divide_operator, "/", Token::Divide);
 syntax!(modulo_operator, "%", Token::Modulo);
 syntax!(assign_operator, "=", Token::Assign);
+syntax!(plus_assign_operator, "+=", Token::PlusAssign);
+syntax!(minus_assign_operator, "-=", Token::MinusAssign);
+syntax!(multiply_assign_operator, "*=", Token::MultiplyAssign);
+syntax!(divide_assign_operator, "/=", Token::DivideAssign);
+syntax!(modulo_assign_operator, "%=", Token::ModuloAssign);
 
-pub fn lex_operator(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_assign_operator(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     alt((
-        equal_operator,
-        not_equal_operator,
         assign_operator,
+        plus_assign_operator,
+        minus_assign_operator,
+        multiply_assign_operator,
+        divide_assign_operator,
+        modulo_assign_operator,
+    ))(input)
+}
+
+fn lex_logical_operator(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    alt((
         logical_not_operator,
         logical_and_operator,
         logical_or_operator,
+        nullish_coalesce_operator,
+    ))(input)
+}
+
+pub fn lex_operator(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    alt((
+        equal_operator,
+        not_equal_operator,
+        lex_assign_operator,
+        lex_logical_operator,
         bitwise_not_operator,
         bitwise_and_operator,
         bitwise_or_operator,
         bitwise_xor_operator,
         bitwise_left_shift_operator,
         bitwise_right_shift_operator,
-        greater_than_operator,
-        less_than_operator,
         greater_than_equal_operator,
         less_than_equal_operator,
+        greater_than_operator,
+        less_than_operator,
         plus_operator,
         minus_operator,
         multiply_operator,
@@ -77,7 +100,16 @@ pub fn lex_operator(input: &[u8]) -> IResult<&[u8], Token> {
 
 // punctuation
 syntax!(comma_punctuation, ",", Token::Comma);
+syntax!(
+    double_period_equal_punctuation,
+    "..=",
+    Token::DoublePeriodEqual
+);
+syntax!(double_period_punctuation, "..", Token::DoublePeriod);
 syntax!(period_punctuation, ".", Token::Period);
+syntax!(question_period_punctuation, "?.", Token::QuestionPeriod);
+syntax!(question_bracket_punctuation, "?[", Token::QuestionBracket);
+syntax!(double_colon_punctuation, "::", Token::DoubleColon);
 syntax!(colon_punctuation, ":", Token::Colon);
 syntax!(semi_colon_punctuation, ";", Token::SemiColon);
 syntax!(l_paren_punctuation, "(", Token::LParen);
@@ -86,11 +118,19 @@ syntax!(l_bracket_punctuation, "[", Token::LBracket);
 syntax!(r_bracket_punctuation, "]", Token::RBracket);
 syntax!(l_squirly_punctuation, "{", Token::LSquirly);
 syntax!(r_squirly_punctuation, "}", Token::RSquirly);
+syntax!(arrow_punctuation, "->", Token::Arrow);
+syntax!(fat_arrow_punctuation, "=>", Token::FatArrow);
+syntax!(hash_punctuation, "#", Token::Hash);
 
-pub fn lex_punctuation(input: &[u8]) -> IResult<&[u8], Token> {
+pub fn lex_punctuation(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     alt((
         comma_punctuation,
+        double_period_equal_punctuation,
+        double_period_punctuation,
         period_punctuation,
+        question_period_punctuation,
+        question_bracket_punctuation,
+        double_colon_punctuation,
         colon_punctuation,
         semi_colon_punctuation,
         l_paren_punctuation,
@@ -99,42 +139,198 @@ pub fn lex_punctuation(input: &[u8]) -> IResult<&[u8], Token> {
         r_bracket_punctuation,
         l_squirly_punctuation,
         r_squirly_punctuation,
+        arrow_punctuation,
+        fat_arrow_punctuation,
+        hash_punctuation,
     ))(input)
 }
 
 // Strings
-// TODO: understand
-fn parse_inside_string(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
-    let (rest1, found1) = take(1usize)(input)?;
-    match found1.as_bytes() {
-        b"\"" => Ok((input, vec![])),
-        b"\\" => {
-            // We found an \ escape character
-            let (rest2, found2) = take(1usize)(rest1)?;
-            parse_inside_string(rest2).map(|(slice, done)| (slice, concat_slice_vec(found2, done)))
+/// Borrows the contents of the string literal from the source, unless it contains escapes, see
+/// `escaped_char`
+fn lex_string(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (contents, _) = tag("\"")(input)?;
+    let mut end = 0;
+    let mut has_escapes = false;
+    // jump from one quote or backslash to the next instead of looking at every byte
+    loop {
+        match memchr2(b'"', b'\\', &contents[end..]) {
+            Some(found) if contents[end + found] == b'\\' => {
+                has_escapes = true;
+                end += found + 2;
+                if end > contents.len() {
+                    return Err(Err::Error(error_position!(input, ErrorKind::Tag)));
+                }
+            }
+            Some(found) => {
+                end += found;
+                break;
+            }
+            None => return Err(Err::Error(error_position!(input, ErrorKind::Tag))),
         }
-        c => parse_inside_string(rest1).map(|(slice, done)| (slice, concat_slice_vec(c, done))),
     }
+    let literal = str::from_utf8(&contents[..end])
+        .map_err(|_| Err::Error(error_position!(input, ErrorKind::Char)))?;
+    let literal = if has_escapes {
+        let mut unescaped = String::with_capacity(literal.len());
+        let mut chars = literal.chars();
+        while let Some(c) = chars.next() {
+            unescaped.push(match c {
+                '\\' => escaped_char(&mut chars)
+                    .ok_or_else(|| Err::Error(error_position!(input, ErrorKind::Escaped)))?,
+                c => c,
+            });
+        }
+        Cow::Owned(unescaped)
+    } else {
+        Cow::Borrowed(literal)
+    };
+    Ok((&contents[end + 1..], Token::StringLiteral(literal)))
 }
 
-fn string(input: &[u8]) -> IResult<&[u8], String> {
-    delimited(
-        tag("\""),
-        map_res(parse_inside_string, convert_vec_utf8),
-        tag("\""),
-    )(input)
+/// The character of the escape after a backslash: `\n`, `\r`, `\t` and `\0` like in javascript,
+/// `\x41` and `\u{1F600}` by their code, and any other escaped character, like `\"` or `\\`,
+/// taken literally. `None` for codes that aren't a character
+pub(crate) fn escaped_char(chars: &mut str::Chars) -> Option<char> {
+    let code = match chars.next()? {
+        'n' => return Some('\n'),
+        'r' => return Some('\r'),
+        't' => return Some('\t'),
+        '0' => return Some('\0'),
+        'x' => {
+            let code = chars.as_str().get(..2)?;
+            chars.nth(1);
+            code
+        }
+        'u' if chars.as_str().starts_with('{') => {
+            let end = chars.as_str().find('}')?;
+            let code = &chars.as_str()[1..end];
+            chars.nth(end);
+            code
+        }
+        c => return Some(c),
+    };
+    if !code.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(code, 16).ok()?)
+}
+
+/// `value` as a string literal, with the escapes javascript, Lua and Python all understand, so
+/// backends that write their output as text can share it
+pub fn quote_string(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_ascii_control() => literal.push_str(&format!("\\x{:02x}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Borrows everything between the backticks. Only the end of the template is looked for here, an
+/// escaped backtick doesn't end it
+fn lex_template(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    map(|input| lex_raw(input, "`", b'`'), Token::Template)(input)
+}
+
+/// `f"hello {name}"`, borrowed like a template
+fn lex_format_string(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    map(|input| lex_raw(input, "f\"", b'"'), Token::FormatString)(input)
 }
 
-fn lex_string(input: &[u8]) -> IResult<&[u8], Token> {
-    map(string, Token::StringLiteral)(input)
+/// Everything between `open` and the next unescaped `close`, with the escapes left in
+fn lex_raw<'a>(input: &'a [u8], open: &str, close: u8) -> IResult<&'a [u8], &'a str> {
+    let (contents, _) = tag(open)(input)?;
+    let mut end = 0;
+    loop {
+        match memchr2(close, b'\\', &contents[end..]) {
+            Some(found) if contents[end + found] == b'\\' => {
+                end += found + 2;
+                if end > contents.len() {
+                    return Err(Err::Error(error_position!(input, ErrorKind::Tag)));
+                }
+            }
+            Some(found) => {
+                end += found;
+                break;
+            }
+            None => return Err(Err::Error(error_position!(input, ErrorKind::Tag))),
+        }
+    }
+    let raw = str::from_utf8(&contents[..end])
+        .map_err(|_| Err::Error(error_position!(input, ErrorKind::Char)))?;
+    Ok((&contents[end + 1..], raw))
+}
+
+// Comments and whitespace
+/// Skips whitespace, `// line comments` and `/* block comments */`. An unterminated block comment
+/// runs until the end of the input
+fn skip_trivia(mut input: &[u8]) -> &[u8] {
+    loop {
+        let whitespace = input
+            .iter()
+            .position(|c| !c.is_ascii_whitespace())
+            .unwrap_or(input.len());
+        input = &input[whitespace..];
+        if input.starts_with(b"//") {
+            input = memchr(b'\n', input).map_or(&[][..], |newline| &input[newline + 1..]);
+        } else if input.starts_with(b"/*") {
+            input = memmem::find(&input[2..], b"*/").map_or(&[][..], |end| &input[end + 4..]);
+        } else {
+            return input;
+        }
+    }
+}
+
+/// The comments and blank lines between `start` and the next token, which is what `skip_trivia`
+/// skips. `start` is the end of the previous token, or 0 at the start of the file
+fn trivia(input: &[u8], start: usize) -> Vec<Trivia<'_>> {
+    let mut trivia = vec![];
+    let mut rest = &input[start..];
+    // a comment on the same line as the previous token belongs to it
+    let mut newlines = if start == 0 { 1 } else { 0 };
+    loop {
+        let whitespace = rest
+            .iter()
+            .position(|c| !c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        newlines += rest[..whitespace].iter().filter(|c| **c == b'\n').count();
+        rest = &rest[whitespace..];
+        // blank lines at the start of the file are dropped
+        if newlines > 1 && (start != 0 || !trivia.is_empty()) {
+            trivia.push(Trivia::BlankLine);
+        }
+        let end = if rest.starts_with(b"//") {
+            memchr(b'\n', rest).unwrap_or(rest.len())
+        } else if rest.starts_with(b"/*") {
+            memmem::find(&rest[2..], b"*/").map_or(rest.len(), |end| end + 4)
+        } else {
+            return trivia;
+        };
+        trivia.push(Trivia::Comment {
+            text: str::from_utf8(&rest[..end]).unwrap_or_default().trim_end(),
+            trailing: newlines == 0,
+        });
+        rest = &rest[end..];
+        newlines = 0;
+    }
 }
 
 // Identifiers
-fn lex_keyword_or_ident(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_keyword_or_ident(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     map_res(
         recognize(pair(
             alt((alpha1, tag("_"))),
-            many0(alt((alphanumeric1, tag("_")))),
+            take_while(|c: u8| c.is_ascii_alphanumeric() || c == b'_'),
         )),
         |s: &[u8]| {
             str::from_utf8(s).map(|syntax| match syntax {
@@ -149,62 +345,141 @@ fn lex_keyword_or_ident(input: &[u8]) -> IResult<&[u8], Token> {
                 "in" => Token::In,
                 "break" => Token::Break,
                 "continue" => Token::Continue,
+                "defer" => Token::Defer,
+                "with" => Token::With,
+                "async" => Token::Async,
+                "await" => Token::Await,
                 "true" => Token::BooleanLiteral(true),
                 "false" => Token::BooleanLiteral(false),
-                // "struct" => Token::Struct,
-                // "enum" => Token::Enum,
+                "struct" => Token::Struct,
+                "enum" => Token::Enum,
                 // "type" => Token::Type,
                 // "trait" => Token::Trait,
-                // "impl" => Token::Impl,
-                // "import" => Token::Import,
+                "impl" => Token::Impl,
+                "mod" => Token::Mod,
+                "pub" => Token::Pub,
+                "import" => Token::Import,
                 // "export" => Token::Export,
-                _ => Token::Ident(syntax.to_string()),
+                _ => Token::Ident(syntax),
             })
         },
     )(input)
 }
 
 // Numbers
-fn lex_number(input: &[u8]) -> IResult<&[u8], Token> {
+/// `1_000`, `1.5`, `1e9`, `0xFF`, `0b1010` or `0o17`. A `.` followed by another one isn't part of
+/// the number, `1..3` is a range, and an `e` without digits after it isn't either
+fn lex_number(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let digits = || take_while(|c: u8| c.is_ascii_digit() || c == b'_');
+    let prefixed = |prefix: &'static str, is_digit: fn(u8) -> bool| {
+        recognize(pair(
+            tag(prefix),
+            take_while1(move |c: u8| is_digit(c) || c == b'_'),
+        ))
+    };
+    let decimal = recognize(tuple((
+        digits(),
+        opt(pair(terminated(tag("."), not(tag("."))), digits())),
+        opt(tuple((
+            one_of("eE"),
+            opt(one_of("+-")),
+            take_while1(|c: u8| c.is_ascii_digit()),
+        ))),
+    )));
     map(
         map_res(
-            map_res(
-                recognize(many1(alt((digit1, tag("."), tag("_"))))),
-                str::from_utf8,
-            ),
-            FromStr::from_str,
+            alt((
+                prefixed("0x", |c| c.is_ascii_hexdigit()),
+                prefixed("0b", |c| matches!(c, b'0' | b'1')),
+                prefixed("0o", |c| matches!(c, b'0'..=b'7')),
+                decimal,
+            )),
+            str::from_utf8,
         ),
         Token::NumberLiteral,
     )(input)
 }
 
+/// The `0` of `tuple.0`, which is only digits, so `pair.0.1` isn't `pair` and `0.1`
+fn lex_tuple_index(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    map(
+        map_res(take_while1(|c: u8| c.is_ascii_digit()), str::from_utf8),
+        Token::NumberLiteral,
+    )(input)
+}
+
 // Illegal
-fn lex_illegal(input: &[u8]) -> IResult<&[u8], Token> {
-    // This just matches anything to Token::Illegal, because it is the last parser to be called in lex_token
-    map(take(1usize), |_| Token::Illegal)(input)
+fn lex_illegal(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    // This just matches anything to Token::Illegal, because it is the last parser to be called in lex_token.
+    // A character of several bytes is one token, so its span can be shown
+    map(
+        recognize(pair(take(1usize), take_while(|c: u8| c & 0xC0 == 0x80))),
+        |_| Token::Illegal,
+    )(input)
 }
 
-fn lex_token(input: &[u8]) -> IResult<&[u8], Token> {
-    alt((
-        lex_operator,
-        lex_punctuation,
-        lex_string,
-        lex_keyword_or_ident,
-        lex_number,
-        lex_illegal,
-    ))(input)
+fn lex_token(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    // dispatch on the first byte, so identifiers and numbers don't have to fail every operator
+    // and punctuation parser first
+    match input.first() {
+        Some(b'f') if input.get(1) == Some(&b'"') => alt((lex_format_string, lex_illegal))(input),
+        Some(c) if c.is_ascii_alphabetic() || *c == b'_' => lex_keyword_or_ident(input),
+        Some(c) if c.is_ascii_digit() => alt((lex_number, lex_illegal))(input),
+        Some(b'"') => alt((lex_string, lex_illegal))(input),
+        Some(b'`') => alt((lex_template, lex_illegal))(input),
+        _ => alt((lex_punctuation, lex_operator, lex_illegal))(input),
+    }
 }
 
-fn lex_tokens(input: &[u8]) -> IResult<&[u8], Vec<Token>> {
-    many0(delimited(multispace0, lex_token, multispace0))(input)
+fn lex_tokens(input: &[u8]) -> IResult<&[u8], (Vec<Token<'_>>, Vec<Span>)> {
+    let offset = |rest: &[u8]| input.len() - rest.len();
+    let mut tokens = vec![];
+    let mut spans = vec![];
+    let mut rest = skip_trivia(input);
+    while !rest.is_empty() {
+        let (after_token, token) = match (tokens.last(), rest.first()) {
+            (Some(Token::Period), Some(c)) if c.is_ascii_digit() => lex_tuple_index(rest)?,
+            _ => lex_token(rest)?,
+        };
+        tokens.push(token);
+        spans.push(Span {
+            start: offset(rest),
+            end: offset(after_token),
+        });
+        rest = skip_trivia(after_token);
+    }
+    tokens.push(Token::EOF);
+    spans.push(Span {
+        start: input.len(),
+        end: input.len(),
+    });
+    Ok((rest, (tokens, spans)))
 }
 
 pub struct Lexer;
 
 impl Lexer {
-    pub fn lex_tokens(bytes: &[u8]) -> IResult<&[u8], Vec<Token>> {
+    pub fn lex_tokens(bytes: &[u8]) -> IResult<&[u8], Vec<Token<'_>>> {
+        lex_tokens(bytes).map(|(rest, (tokens, _))| (rest, tokens))
+    }
+
+    /// Like `lex_tokens`, but also returns the byte offsets of every token in `bytes`
+    pub fn lex_tokens_with_spans(bytes: &[u8]) -> IResult<&[u8], (Vec<Token<'_>>, Vec<Span>)> {
         lex_tokens(bytes)
-            .map(|(slice, result)| (slice, [&result[..], &vec![Token::EOF][..]].concat()))
+    }
+
+    /// The comments and blank lines in front of every token, including the final `EOF`, from the
+    /// spans of `lex_tokens_with_spans`
+    pub fn trivia<'a>(bytes: &'a [u8], spans: &[Span]) -> Vec<Vec<Trivia<'a>>> {
+        let mut start = 0;
+        spans
+            .iter()
+            .map(|span| {
+                let trivia = trivia(&bytes[..span.start], start);
+                start = span.end;
+                trivia
+            })
+            .collect()
     }
 }
 
@@ -221,9 +496,9 @@ mod tests {
             tokens,
             vec![
                 Token::Let,
-                Token::Ident("x".to_string()),
+                Token::Ident("x"),
                 Token::Assign,
-                Token::NumberLiteral("1".to_string()),
+                Token::NumberLiteral("1"),
                 Token::SemiColon,
                 Token::EOF
             ]
@@ -232,7 +507,8 @@ mod tests {
 
     #[test]
     fn operators_punctuation() {
-        let input = b"=+/*%-()[]{},;:.<>!<<>>|&^||&&~";
+        // `/*` would start a block comment
+        let input = b"=+/ *%-()[]{},;:.<>!<<>>|&^||&&~->::=>..?.?[<=>=+=-=*=/=%=??";
         let (rest, tokens) = Lexer::lex_tokens(input).unwrap();
         assert_eq!(rest, b"");
         assert_eq!(
@@ -265,6 +541,20 @@ mod tests {
                 Token::LogicalOr,
                 Token::LogicalAnd,
                 Token::BitwiseNot,
+                Token::Arrow,
+                Token::DoubleColon,
+                Token::FatArrow,
+                Token::DoublePeriod,
+                Token::QuestionPeriod,
+                Token::QuestionBracket,
+                Token::LessThanEqual,
+                Token::GreaterThanEqual,
+                Token::PlusAssign,
+                Token::MinusAssign,
+                Token::MultiplyAssign,
+                Token::DivideAssign,
+                Token::ModuloAssign,
+                Token::NullishCoalesce,
                 Token::EOF,
             ]
         )
@@ -288,47 +578,47 @@ mod tests {
             tokens,
             vec![
                 Token::Const,
-                Token::Ident("stuff".to_string()),
+                Token::Ident("stuff"),
                 Token::Assign,
-                Token::NumberLiteral("1.0".to_string()),
+                Token::NumberLiteral("1.0"),
                 Token::SemiColon,
                 Token::Let,
-                Token::Ident("hello123".to_string()),
+                Token::Ident("hello123"),
                 Token::Assign,
-                Token::NumberLiteral("2.23".to_string()),
+                Token::NumberLiteral("2.23"),
                 Token::SemiColon,
                 Token::Function,
-                Token::Ident("test".to_string()),
+                Token::Ident("test"),
                 Token::LParen,
-                Token::Ident("a".to_string()),
+                Token::Ident("a"),
                 Token::Colon,
-                Token::Ident("number".to_string()),
+                Token::Ident("number"),
                 Token::Comma,
-                Token::Ident("b".to_string()),
+                Token::Ident("b"),
                 Token::Colon,
-                Token::Ident("number".to_string()),
+                Token::Ident("number"),
                 Token::RParen,
                 Token::LSquirly,
                 Token::Const,
-                Token::Ident("things".to_string()),
+                Token::Ident("things"),
                 Token::Assign,
-                Token::Ident("stuff".to_string()),
+                Token::Ident("stuff"),
                 Token::Plus,
-                Token::Ident("hello123".to_string()),
+                Token::Ident("hello123"),
                 Token::Multiply,
-                Token::Ident("a".to_string()),
+                Token::Ident("a"),
                 Token::Minus,
-                Token::Ident("b".to_string()),
+                Token::Ident("b"),
                 Token::SemiColon,
                 Token::Return,
-                Token::Ident("things".to_string()),
+                Token::Ident("things"),
                 Token::SemiColon,
                 Token::RSquirly,
-                Token::Ident("test".to_string()),
+                Token::Ident("test"),
                 Token::LParen,
-                Token::NumberLiteral("12".to_string()),
+                Token::NumberLiteral("12"),
                 Token::Comma,
-                Token::NumberLiteral("34".to_string()),
+                Token::NumberLiteral("34"),
                 Token::RParen,
                 Token::SemiColon,
                 Token::EOF
@@ -341,31 +631,31 @@ mod tests {
         let (_, result) = Lexer::lex_tokens(&b"\"foobar\""[..]).unwrap();
         assert_eq!(
             result,
-            vec![Token::StringLiteral("foobar".to_owned()), Token::EOF]
+            vec![Token::StringLiteral("foobar".into()), Token::EOF]
         );
 
         let (_, result) = Lexer::lex_tokens(&b"\"foo bar\""[..]).unwrap();
         assert_eq!(
             result,
-            vec![Token::StringLiteral("foo bar".to_owned()), Token::EOF]
+            vec![Token::StringLiteral("foo bar".into()), Token::EOF]
         );
 
         let (_, result) = Lexer::lex_tokens(&b"\"foo\nbar\""[..]).unwrap();
         assert_eq!(
             result,
-            vec![Token::StringLiteral("foo\nbar".to_owned()), Token::EOF]
+            vec![Token::StringLiteral("foo\nbar".into()), Token::EOF]
         );
 
         let (_, result) = Lexer::lex_tokens(&b"\"foo\tbar\""[..]).unwrap();
         assert_eq!(
             result,
-            vec![Token::StringLiteral("foo\tbar".to_owned()), Token::EOF]
+            vec![Token::StringLiteral("foo\tbar".into()), Token::EOF]
         );
 
         let (_, result) = Lexer::lex_tokens(&b"\"foo\\\"bar\""[..]).unwrap();
         assert_eq!(
             result,
-            vec![Token::StringLiteral("foo\"bar".to_owned()), Token::EOF]
+            vec![Token::StringLiteral("foo\"bar".into()), Token::EOF]
         );
 
         let (_, result) =
@@ -373,7 +663,131 @@ mod tests {
         assert_eq!(
             result,
             vec![
-                Token::StringLiteral("foo\"bar with 💖 emojis".to_owned()),
+                Token::StringLiteral("foo\"bar with 💖 emojis".into()),
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let (_, result) =
+            Lexer::lex_tokens(&br#""a\nb\r\t\0 \\ \" \x41 \u{1F496} \q""#[..]).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::StringLiteral("a\nb\r\t\0 \\ \" A 💖 q".into()),
+                Token::EOF
+            ]
+        );
+
+        for invalid in [&br#""\u{110000}""#[..], br#""\xZZ""#, br#""\u{12""#] {
+            let (_, result) = Lexer::lex_tokens(invalid).unwrap();
+            assert_eq!(result[0], Token::Illegal);
+        }
+
+        let value = "say \"hi\"\n\tC:\\ \x07 💖";
+        assert_eq!(quote_string(value), r#""say \"hi\"\n\tC:\\ \x07 💖""#);
+        let quoted = quote_string(value);
+        let (_, result) = Lexer::lex_tokens(quoted.as_bytes()).unwrap();
+        assert_eq!(result, vec![Token::StringLiteral(value.into()), Token::EOF]);
+    }
+
+    #[test]
+    fn templates() {
+        let (_, result) = Lexer::lex_tokens(&b"sql`SELECT \\` {id}`"[..]).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::Ident("sql"),
+                Token::Template("SELECT \\` {id}"),
+                Token::EOF
+            ]
+        );
+
+        let (_, result) = Lexer::lex_tokens(&b"`unterminated"[..]).unwrap();
+        assert_eq!(result[0], Token::Illegal);
+        let (_, (result, spans)) = Lexer::lex_tokens_with_spans("│ a".as_bytes()).unwrap();
+        assert_eq!(result, vec![Token::Illegal, Token::Ident("a"), Token::EOF]);
+        assert_eq!((spans[0].start, spans[0].end), (0, 3));
+    }
+
+    #[test]
+    fn format_strings() {
+        let (_, result) = Lexer::lex_tokens(&br#"f"hi \" {name}" f (f)"#[..]).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::FormatString(r#"hi \" {name}"#),
+                Token::Ident("f"),
+                Token::LParen,
+                Token::Ident("f"),
+                Token::RParen,
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let (_, result) = Lexer::lex_tokens(&b"1..3 1.5.. ..2_0 0..=n"[..]).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::NumberLiteral("1"),
+                Token::DoublePeriod,
+                Token::NumberLiteral("3"),
+                Token::NumberLiteral("1.5"),
+                Token::DoublePeriod,
+                Token::DoublePeriod,
+                Token::NumberLiteral("2_0"),
+                Token::NumberLiteral("0"),
+                Token::DoublePeriodEqual,
+                Token::Ident("n"),
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn tuple_indices() {
+        let (_, result) = Lexer::lex_tokens(&b"pair.0.1 0.5"[..]).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::Ident("pair"),
+                Token::Period,
+                Token::NumberLiteral("0"),
+                Token::Period,
+                Token::NumberLiteral("1"),
+                Token::NumberLiteral("0.5"),
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn number_literals() {
+        let (_, result) =
+            Lexer::lex_tokens(&b"1_000 0xFF 0b1010 0o17 1.5 1e9 2.5E-3 1.2.3 1else 0x"[..])
+                .unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::NumberLiteral("1_000"),
+                Token::NumberLiteral("0xFF"),
+                Token::NumberLiteral("0b1010"),
+                Token::NumberLiteral("0o17"),
+                Token::NumberLiteral("1.5"),
+                Token::NumberLiteral("1e9"),
+                Token::NumberLiteral("2.5E-3"),
+                Token::NumberLiteral("1.2"),
+                Token::Period,
+                Token::NumberLiteral("3"),
+                Token::NumberLiteral("1"),
+                Token::Else,
+                Token::NumberLiteral("0"),
+                Token::Ident("x"),
                 Token::EOF
             ]
         );
@@ -395,6 +809,11 @@ mod tests {
         continue
         true
         false
+        struct
+        enum
+        impl
+        mod
+        pub
         "
         .as_bytes();
 
@@ -416,8 +835,104 @@ mod tests {
                 Token::Continue,
                 Token::BooleanLiteral(true),
                 Token::BooleanLiteral(false),
+                Token::Struct,
+                Token::Enum,
+                Token::Impl,
+                Token::Mod,
+                Token::Pub,
+                Token::EOF
+            ]
+        )
+    }
+
+    #[test]
+    fn spans() {
+        let input = b"let name = \"oxide\";\n  name";
+        let (rest, (tokens, spans)) = Lexer::lex_tokens_with_spans(input).unwrap();
+        assert_eq!(rest, b"");
+        assert_eq!(tokens.len(), spans.len());
+        assert_eq!(
+            spans
+                .iter()
+                .map(|span| &input[span.start..span.end])
+                .collect::<Vec<_>>(),
+            vec![&b"let"[..], b"name", b"=", b"\"oxide\"", b";", b"name", b""]
+        );
+        assert!(matches!(
+            tokens[3],
+            Token::StringLiteral(Cow::Borrowed("oxide"))
+        ));
+    }
+
+    #[test]
+    fn comments() {
+        let input = b"// leading comment
+        let x = /* inline */ 1; // trailing comment
+        /* multi
+           line */
+        x / 2 /* unterminated";
+        let (rest, tokens) = Lexer::lex_tokens(input).unwrap();
+        assert_eq!(rest, b"");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Let,
+                Token::Ident("x"),
+                Token::Assign,
+                Token::NumberLiteral("1"),
+                Token::SemiColon,
+                Token::Ident("x"),
+                Token::Divide,
+                Token::NumberLiteral("2"),
                 Token::EOF
             ]
         )
     }
+
+    #[test]
+    fn trivia() {
+        let input = b"// leading comment
+let x = 1; // trailing comment
+
+/* block */
+let y = 2;
+
+
+// at the end
+";
+        let (_, (_, spans)) = Lexer::lex_tokens_with_spans(input).unwrap();
+        let trivia = Lexer::trivia(input, &spans);
+        assert_eq!(
+            trivia[0],
+            vec![Trivia::Comment {
+                text: "// leading comment",
+                trailing: false
+            }]
+        );
+        assert_eq!(
+            trivia[5],
+            vec![
+                Trivia::Comment {
+                    text: "// trailing comment",
+                    trailing: true
+                },
+                Trivia::BlankLine,
+                Trivia::Comment {
+                    text: "/* block */",
+                    trailing: false
+                }
+            ]
+        );
+        assert_eq!(
+            trivia[10],
+            vec![
+                Trivia::BlankLine,
+                Trivia::Comment {
+                    text: "// at the end",
+                    trailing: false
+                }
+            ]
+        );
+        assert!(trivia[1..5].iter().all(Vec::is_empty));
+    }
 }
@@ -1,12 +1,20 @@
+use std::borrow::Cow;
+
+/// Tokens borrow from the source, only string literals containing escapes own their contents
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub enum Token {
+pub enum Token<'a> {
     Illegal,
     EOF,
 
-    Ident(String),
+    Ident(&'a str),
     // Literals
-    StringLiteral(String),
-    NumberLiteral(String), // why not f64? because we can't Eq f64s
+    StringLiteral(Cow<'a, str>),
+    /// The source between the backticks of a template, with its escapes and `{interpolations}`,
+    /// which the parser splits up
+    Template(&'a str),
+    /// The source between the quotes of an `f"..."` string, split up like a template
+    FormatString(&'a str),
+    NumberLiteral(&'a str), // why not f64? because we can't Eq f64s
     BooleanLiteral(bool),
 
     // Operators
@@ -20,6 +28,7 @@ pub enum Token {
     BitwiseNot,
     LogicalAnd,
     LogicalOr,
+    NullishCoalesce,
     BitwiseAnd,
     BitwiseOr,
     BitwiseXor,
@@ -31,11 +40,21 @@ pub enum Token {
     Divide,
     Modulo,
     Assign,
+    PlusAssign,
+    MinusAssign,
+    MultiplyAssign,
+    DivideAssign,
+    ModuloAssign,
 
     // Punctuation
     Period,
+    DoublePeriod,
+    DoublePeriodEqual,
+    QuestionPeriod,
+    QuestionBracket,
     Comma,
     Colon,
+    DoubleColon,
     SemiColon,
     LParen,
     RParen,
@@ -43,6 +62,9 @@ pub enum Token {
     RSquirly,
     LBracket,
     RBracket,
+    Arrow,
+    FatArrow,
+    Hash,
 
     // Keywords
     Const,
@@ -56,6 +78,10 @@ pub enum Token {
     In,
     Break,
     Continue,
+    Defer,
+    With,
+    Async,
+    Await,
     Struct,
     Enum,
     Type,
@@ -63,4 +89,22 @@ pub enum Token {
     Impl,
     Import,
     Export,
+    Mod,
+    Pub,
+}
+
+/// Byte offsets of a token in the source, `end` is exclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// What the lexer skips in front of a token but readable output keeps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trivia<'a> {
+    /// One or more empty lines
+    BlankLine,
+    /// The full text of a `//` or `/* */` comment. `trailing` comments follow code on the same line
+    Comment { text: &'a str, trailing: bool },
 }
@@ -2,29 +2,53 @@ use std::iter::Enumerate;
 
 use nom::{InputIter, InputLength, InputTake};
 
-use super::token::Token;
+use super::token::{Span, Token};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Tokens<'a> {
-    pub tokens: &'a [Token],
+    pub tokens: &'a [Token<'a>],
+    /// Where each of `tokens` is in the source, empty if the tokens came without spans
+    pub spans: &'a [Span],
+    /// Which source file the tokens are from
+    pub file_id: usize,
     pub start: usize,
     pub end: usize,
 }
 
 impl<'a> Tokens<'a> {
-    pub fn new(tokens: &'a [Token]) -> Self {
+    pub fn new(tokens: &'a [Token<'a>]) -> Self {
         Self {
             tokens,
+            spans: &[],
+            file_id: 0,
             start: 0,
             end: tokens.len(),
         }
     }
+
+    /// Tokens with the spans of `Lexer::lex_tokens_with_spans`, so the parsed nodes know where
+    /// they are in the file `file_id`
+    pub fn with_spans(tokens: &'a [Token<'a>], spans: &'a [Span], file_id: usize) -> Self {
+        Self {
+            spans,
+            file_id,
+            ..Self::new(tokens)
+        }
+    }
+
+    fn split_spans(&self, count: usize) -> (&'a [Span], &'a [Span]) {
+        if self.spans.is_empty() {
+            (&[], &[])
+        } else {
+            self.spans.split_at(count)
+        }
+    }
 }
 
 impl<'a> InputIter for Tokens<'a> {
-    type Item = &'a Token;
-    type Iter = Enumerate<::std::slice::Iter<'a, Token>>;
-    type IterElem = ::std::slice::Iter<'a, Token>;
+    type Item = &'a Token<'a>;
+    type Iter = Enumerate<::std::slice::Iter<'a, Token<'a>>>;
+    type IterElem = ::std::slice::Iter<'a, Token<'a>>;
 
     fn iter_indices(&self) -> Self::Iter {
         self.tokens.iter().enumerate()
@@ -56,6 +80,8 @@ impl<'a> InputTake for Tokens<'a> {
     fn take(&self, count: usize) -> Self {
         Self {
             tokens: &self.tokens[0..count],
+            spans: self.split_spans(count).0,
+            file_id: self.file_id,
             start: 0,
             end: count,
         }
@@ -63,6 +89,7 @@ impl<'a> InputTake for Tokens<'a> {
 
     fn take_split(&self, count: usize) -> (Self, Self) {
         let (left, right) = self.tokens.split_at(count);
+        let (left_spans, right_spans) = self.split_spans(count);
         // println!(
         //     "Tokens.take_split: split at {} in {}: ({}|{})",
         //     &count,
@@ -73,11 +100,15 @@ impl<'a> InputTake for Tokens<'a> {
         (
             Self {
                 tokens: right,
+                spans: right_spans,
+                file_id: self.file_id,
                 start: 0,
                 end: right.len(),
             },
             Self {
                 tokens: left,
+                spans: left_spans,
+                file_id: self.file_id,
                 start: 0,
                 end: left.len(),
             },
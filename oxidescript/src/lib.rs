@@ -1,3 +1,11 @@
+pub mod cancellation;
 pub mod compiler;
+pub mod consteval;
+pub mod diagnostic;
 pub mod lexer;
+pub mod lint;
+pub mod optimizer;
 pub mod parser;
+pub mod resolve;
+pub mod stack;
+pub mod stdlib;
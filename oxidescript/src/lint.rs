@@ -0,0 +1,148 @@
+//! Warnings about programs that compile, but likely don't do what they look like. Unlike the
+//! checks, lints never stop the compiler
+
+use std::collections::HashSet;
+
+use crate::{
+    diagnostic::Diagnostic,
+    parser::{
+        ast::{Declaration, Expression, Statement},
+        types::{task_function, ProgramTypes},
+        visit::walk_statement,
+    },
+};
+
+/// The warnings about the statements of a program, in their order
+pub fn lint(program: &[Statement]) -> Vec<Diagnostic> {
+    let mut used = HashSet::new();
+    for statement in program {
+        walk_statement(statement, &mut |expression| {
+            if let Expression::IdentifierExpression(name, _) = expression {
+                used.insert(name.0.clone());
+            }
+        });
+    }
+    let mut warnings = vec![];
+    ProgramTypes::walk(program, |statement, types| {
+        lint_statement(statement, &used, types, &mut warnings)
+    });
+    warnings
+}
+
+fn lint_statement(
+    statement: &Statement,
+    used: &HashSet<String>,
+    types: &ProgramTypes,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    match statement {
+        Statement::ExpressionStatement {
+            expression, span, ..
+        } if is_spawn(expression) => warnings.push(unawaited_task(*span)),
+        Statement::ExpressionStatement {
+            expression, span, ..
+        } if types.is_promise(expression) => warnings.push(unawaited_promise(*span)),
+        // a task bound to a name nothing reads is dropped as well
+        Statement::DeclarationStatement(
+            Declaration::ConstDeclaration(name, value, _, _, _)
+            | Declaration::LetDeclaration(name, value, _, _),
+            span,
+        ) if is_spawn(value) && !used.contains(&name.0) => warnings.push(unawaited_task(*span)),
+        _ => {}
+    }
+}
+
+/// `task::spawn(f)` from the prelude
+fn is_spawn(expression: &Expression) -> bool {
+    matches!(expression, Expression::CallExpression(call, _) if task_function(call) == Some("spawn"))
+}
+
+fn unawaited_task(span: crate::parser::ast::Span) -> Diagnostic {
+    Diagnostic::warning("the task `task::spawn` starts is never awaited")
+        .with_label(span, "the task is dropped here")
+        .with_help(
+            "errors the task throws are lost, `.await` it or pass it to `task::join_all` \
+             with the other tasks",
+        )
+}
+
+fn unawaited_promise(span: crate::parser::ast::Span) -> Diagnostic {
+    Diagnostic::warning("this promise is never awaited")
+        .with_label(span, "the promise is dropped here")
+        .with_help(
+            "the code after it doesn't wait for it and errors it throws are lost, `.await` it \
+             or write `let _ = ...;` if nothing has to wait for it",
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diagnostic::Severity,
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+    };
+
+    fn warnings(input: &str) -> Vec<String> {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        lint(&program)
+            .into_iter()
+            .inspect(|warning| assert_eq!(warning.severity, Severity::Warning))
+            .map(|warning| warning.message)
+            .collect()
+    }
+
+    #[test]
+    fn unawaited_tasks() {
+        let unawaited = "the task `task::spawn` starts is never awaited".to_string();
+        assert_eq!(
+            warnings("async fn f() { task::spawn(work); }"),
+            vec![unawaited.clone()]
+        );
+        assert_eq!(
+            warnings("async fn f() { let task = task::spawn(work); }"),
+            vec![unawaited]
+        );
+        // awaited, joined and returned tasks are fine
+        assert!(warnings(
+            "async fn f() {
+                task::spawn(work).await;
+                let a = task::spawn(work);
+                let b = task::spawn(work);
+                task::join_all([a, b]).await;
+                task::spawn(work)
+            }"
+        )
+        .is_empty());
+        // unless they are dropped explicitly
+        assert!(warnings("async fn f() { let _ = task::spawn(work); }").is_empty());
+    }
+
+    #[test]
+    fn unawaited_promises() {
+        let unawaited = "this promise is never awaited".to_string();
+        let program = |body: &str| {
+            format!(
+                "async fn save() {{ 1 }}
+                extern fn fetch(url: string) -> Promise<any>;
+                struct Store {{}}
+                impl Store {{ async fn load(self) -> number {{ 1 }} }}
+                async fn f(store: Store) {{ {} }}",
+                body
+            )
+        };
+        assert_eq!(
+            warnings(&program(
+                "save(); fetch(\"/\"); store.load(); save().await;"
+            )),
+            vec![unawaited.clone(), unawaited.clone(), unawaited]
+        );
+        // awaited, kept, returned and explicitly dropped promises are fine
+        assert!(warnings(&program(
+            "save().await; let p = fetch(\"/\"); p.await; let _ = store.load(); save()"
+        ))
+        .is_empty());
+    }
+}
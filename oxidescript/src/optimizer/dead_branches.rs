@@ -0,0 +1,516 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::{
+    ast::{
+        Block, Declaration, ElseIfExpr, Expression, Identifier, IfExpr, InfixOperator, Literal,
+        MatchExpr, ModDecl, PathExpr, Pattern, Span, Statement, UnaryOperator,
+    },
+    visit::{walk_blocks_mut, walk_statement, walk_statement_mut},
+};
+
+/// Removes the branches of `if` expressions whose condition is known while compiling and the arms
+/// of `match` expressions on a known value that can't match, together with the local declarations
+/// that were only used by them. Conditions can combine literals and `cfg::name` flags with
+/// operators, flags missing from `cfg` are `false`.
+///
+/// Top level declarations are kept, since other modules can use them. Bundles drop the ones that
+/// became unused when tree shaking. Returns `false` if nothing is left of the statement
+pub fn eliminate_dead_branches(statement: &mut Statement, cfg: &HashMap<String, bool>) -> bool {
+    let referenced = referenced_names(statement);
+    walk_statement_mut(statement, &mut |expression| match expression {
+        Expression::PathExpression(path, span) => {
+            if let Some(Value::Boolean(value)) = cfg_flag(path, cfg) {
+                let span = *span;
+                *expression = Expression::LiteralExpression(Literal::BooleanLiteral(value), span);
+            }
+        }
+        Expression::IfExpression(expr, _) => {
+            if let Some(folded) = fold_if(expr, cfg) {
+                *expression = folded;
+            }
+        }
+        Expression::MatchExpression(expr, _) => {
+            if let Some(folded) = fold_match(expr, cfg) {
+                *expression = folded;
+            }
+        }
+        _ => {}
+    });
+    walk_blocks_mut(statement, &mut |block| {
+        block.statements.retain(|statement| !is_empty(statement));
+    });
+
+    // removing a declaration can make the ones it used unused as well
+    loop {
+        let still_referenced = referenced_names(statement);
+        let unused = referenced
+            .difference(&still_referenced)
+            .cloned()
+            .collect::<HashSet<_>>();
+        let mut removed = false;
+        walk_blocks_mut(statement, &mut |block| {
+            let length = block.statements.len();
+            block
+                .statements
+                .retain(|statement| !matches!(statement, Statement::DeclarationStatement(declaration, _) if is_unused(declaration, &unused)));
+            removed |= block.statements.len() != length;
+        });
+        if let Statement::DeclarationStatement(Declaration::ModDeclaration(decl), _) = statement {
+            removed |= remove_unused_items(decl, &unused);
+        }
+        if !removed {
+            break;
+        }
+    }
+    !is_empty(statement)
+}
+
+/// The branches of `if` that can still be taken, `None` if nothing changed
+fn fold_if(expr: &IfExpr, cfg: &HashMap<String, bool>) -> Option<Expression> {
+    let branches = [(&*expr.condition, &*expr.then_block)]
+        .into_iter()
+        .chain(
+            expr.else_if_blocks
+                .iter()
+                .map(|else_if| (&*else_if.condition, &else_if.then_block)),
+        )
+        .map(|(condition, block)| (condition, block, evaluate(condition, cfg)))
+        .collect::<Vec<_>>();
+
+    let mut kept = vec![];
+    let mut else_block = expr.else_block.as_deref().cloned();
+    for (condition, block, value) in branches {
+        match value {
+            Some(Value::Boolean(false)) => {}
+            // every following branch is unreachable
+            Some(Value::Boolean(true)) => {
+                else_block = Some(block.clone());
+                break;
+            }
+            _ => kept.push((simplify_condition(condition, cfg), block.clone())),
+        }
+    }
+    let mut kept = kept.into_iter();
+    let Some((condition, then_block)) = kept.next() else {
+        return Some(Expression::BlockExpression(
+            Box::new(else_block.unwrap_or(Block {
+                statements: vec![],
+                return_value: None,
+                span: Span::default(),
+            })),
+            Span::default(),
+        ));
+    };
+    let folded = IfExpr {
+        condition: Box::new(condition),
+        then_block: Box::new(then_block),
+        else_if_blocks: kept
+            .map(|(condition, then_block)| ElseIfExpr {
+                condition: Box::new(condition),
+                then_block,
+            })
+            .collect(),
+        else_block: else_block.map(Box::new),
+    };
+    (folded != *expr).then_some(Expression::IfExpression(folded, Span::default()))
+}
+
+/// The arms of `match` that can still match, or the body of the first arm if it's known to match,
+/// `None` if nothing changed
+fn fold_match(expr: &MatchExpr, cfg: &HashMap<String, bool>) -> Option<Expression> {
+    let value = evaluate(&expr.value, cfg)?;
+    let mut arms = vec![];
+    for arm in &expr.arms {
+        let matches = match arm_bindings(&arm.pattern, &expr.value, &value) {
+            Some(Some(bindings)) => match arm.guard.as_ref().map(|guard| evaluate(guard, cfg)) {
+                None | Some(Some(Value::Boolean(true))) => Some(bindings),
+                Some(Some(Value::Boolean(false))) => continue,
+                // guards can use the bindings, which aren't known here
+                Some(_) => None,
+            },
+            Some(None) => continue,
+            None => None,
+        };
+        match matches {
+            Some(bindings) if arms.is_empty() => {
+                return Some(Expression::BlockExpression(
+                    Box::new(Block {
+                        statements: bindings,
+                        return_value: Some(arm.body.clone()),
+                        span: Span::default(),
+                    }),
+                    Span::default(),
+                ))
+            }
+            // every following arm is unreachable
+            Some(_) => {
+                arms.push(arm.clone());
+                break;
+            }
+            None => arms.push(arm.clone()),
+        }
+    }
+    // the type checker makes sure some arm matches, but a match without arms isn't valid
+    if arms.is_empty() || arms == expr.arms {
+        return None;
+    }
+    Some(Expression::MatchExpression(
+        MatchExpr {
+            value: expr.value.clone(),
+            arms,
+        },
+        Span::default(),
+    ))
+}
+
+/// The `let` statements binding the names of the pattern if it matches the known value, `None`
+/// inside if it doesn't match and `None` if that isn't known
+fn arm_bindings(
+    pattern: &Pattern,
+    scrutinee: &Expression,
+    value: &Value,
+) -> Option<Option<Vec<Statement>>> {
+    let binding = |name: &Identifier, value: Expression| {
+        Statement::DeclarationStatement(
+            Declaration::LetDeclaration(name.clone(), value, vec![], None),
+            Span::default(),
+        )
+    };
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => Some(Some(vec![])),
+        (Pattern::Binding(name), _) => Some(Some(vec![binding(name, scrutinee.clone())])),
+        (Pattern::Literal(literal), value) => {
+            let literal = evaluate(
+                &Expression::LiteralExpression(literal.clone(), Span::default()),
+                &HashMap::new(),
+            )?;
+            // comparing different types isn't the same in every backend
+            same_type(&literal, value).then(|| (literal == *value).then(Vec::new))
+        }
+        (Pattern::Prefix(prefix, rest), Value::String(value)) => {
+            Some(value.strip_prefix(prefix.as_str()).map(|remaining| {
+                rest.iter()
+                    .map(|name| {
+                        let remaining = Literal::StringLiteral(remaining.to_string());
+                        binding(
+                            name,
+                            Expression::LiteralExpression(remaining, Span::default()),
+                        )
+                    })
+                    .collect()
+            }))
+        }
+        (Pattern::Or(patterns), value) => {
+            let mut unknown = false;
+            for pattern in patterns {
+                match arm_bindings(pattern, scrutinee, value) {
+                    Some(Some(bindings)) if !unknown => return Some(Some(bindings)),
+                    Some(None) => {}
+                    _ => unknown = true,
+                }
+            }
+            (!unknown).then_some(None)
+        }
+        _ => None,
+    }
+}
+
+/// `true && x` and `false || x` are as truthy as `x`
+fn simplify_condition(condition: &Expression, cfg: &HashMap<String, bool>) -> Expression {
+    match condition {
+        Expression::InfixExpression(expr, _)
+            if matches!(
+                (&expr.op, evaluate(&expr.lhs, cfg)),
+                (InfixOperator::LogicalAnd, Some(Value::Boolean(true)))
+                    | (InfixOperator::LogicalOr, Some(Value::Boolean(false)))
+            ) =>
+        {
+            simplify_condition(&expr.rhs, cfg)
+        }
+        condition => condition.clone(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Boolean(bool),
+    Number(f64),
+    String(String),
+}
+
+/// The value of an expression made of literals, `cfg` flags and operators. Only operators that
+/// behave the same in every backend are evaluated
+fn evaluate(expression: &Expression, cfg: &HashMap<String, bool>) -> Option<Value> {
+    match expression {
+        Expression::LiteralExpression(Literal::BooleanLiteral(value), _) => {
+            Some(Value::Boolean(*value))
+        }
+        Expression::LiteralExpression(Literal::NumberLiteral(number), _) => {
+            f64::try_from(number.clone()).ok().map(Value::Number)
+        }
+        Expression::LiteralExpression(Literal::StringLiteral(value), _) => {
+            Some(Value::String(value.clone()))
+        }
+        Expression::PathExpression(path, _) => cfg_flag(path, cfg),
+        Expression::UnaryExpression(expr, _) => match (&expr.op, evaluate(&expr.rhs, cfg)?) {
+            (UnaryOperator::LogicalNot, Value::Boolean(value)) => Some(Value::Boolean(!value)),
+            (UnaryOperator::Minus, Value::Number(value)) => Some(Value::Number(-value)),
+            (UnaryOperator::Plus, Value::Number(value)) => Some(Value::Number(value)),
+            _ => None,
+        },
+        Expression::InfixExpression(expr, _) => {
+            let lhs = evaluate(&expr.lhs, cfg);
+            // the right side isn't evaluated at runtime either, so it doesn't have to be known
+            match (&expr.op, &lhs) {
+                (InfixOperator::LogicalAnd, Some(Value::Boolean(false))) => {
+                    return Some(Value::Boolean(false))
+                }
+                (InfixOperator::LogicalOr, Some(Value::Boolean(true))) => {
+                    return Some(Value::Boolean(true))
+                }
+                // literals and flags are never `None`
+                (InfixOperator::NullishCoalesce, Some(value)) => return Some(value.clone()),
+                _ => {}
+            }
+            let value = match (&expr.op, lhs?, evaluate(&expr.rhs, cfg)?) {
+                (InfixOperator::LogicalAnd | InfixOperator::LogicalOr, _, Value::Boolean(rhs)) => {
+                    Value::Boolean(rhs)
+                }
+                // comparing different types isn't the same in every backend
+                (InfixOperator::Equal, lhs, rhs) if same_type(&lhs, &rhs) => {
+                    Value::Boolean(lhs == rhs)
+                }
+                (InfixOperator::NotEqual, lhs, rhs) if same_type(&lhs, &rhs) => {
+                    Value::Boolean(lhs != rhs)
+                }
+                (op, Value::Number(lhs), Value::Number(rhs)) => match op {
+                    InfixOperator::GreaterThan => Value::Boolean(lhs > rhs),
+                    InfixOperator::LessThan => Value::Boolean(lhs < rhs),
+                    InfixOperator::GreaterThanEqual => Value::Boolean(lhs >= rhs),
+                    InfixOperator::LessThanEqual => Value::Boolean(lhs <= rhs),
+                    InfixOperator::Plus => Value::Number(lhs + rhs),
+                    InfixOperator::Minus => Value::Number(lhs - rhs),
+                    InfixOperator::Multiply => Value::Number(lhs * rhs),
+                    // Python raises on division by zero, so those are left to the runtime. `%`
+                    // takes the sign of `lhs` in every backend, like `%` on `f64`
+                    InfixOperator::Divide if rhs != 0.0 => Value::Number(lhs / rhs),
+                    InfixOperator::Modulo if rhs != 0.0 => Value::Number(lhs % rhs),
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+fn same_type(lhs: &Value, rhs: &Value) -> bool {
+    std::mem::discriminant(lhs) == std::mem::discriminant(rhs)
+}
+
+/// `cfg::name`, the flags set for the build
+fn cfg_flag(path: &PathExpr, cfg: &HashMap<String, bool>) -> Option<Value> {
+    match path.segments.as_slice() {
+        [namespace, name] if namespace.0 == "cfg" => Some(Value::Boolean(
+            cfg.get(&name.0).copied().unwrap_or_default(),
+        )),
+        _ => None,
+    }
+}
+
+/// Names of the items and variables the statement uses, `Point` for `Point::new`
+pub(super) fn referenced_names(statement: &Statement) -> HashSet<String> {
+    let mut names = HashSet::new();
+    walk_statement(statement, &mut |expression| match expression {
+        Expression::IdentifierExpression(ident, _) => {
+            names.insert(ident.0.clone());
+        }
+        Expression::StructExpression(expr, _) => {
+            names.insert(expr.name.0.clone());
+        }
+        Expression::PathExpression(path, _) => {
+            names.insert(path.segments[0].0.clone());
+        }
+        _ => {}
+    });
+    names
+}
+
+fn is_empty(statement: &Statement) -> bool {
+    matches!(statement, Statement::ExpressionStatement {
+        expression: Expression::BlockExpression(block, _),
+        ..
+    } if block.statements.is_empty() && block.return_value.is_none())
+}
+
+/// Functions and variables without side effects in their initializer
+fn is_unused(declaration: &Declaration, unused: &HashSet<String>) -> bool {
+    match declaration {
+        Declaration::ConstDeclaration(name, value, _, _, _)
+        | Declaration::LetDeclaration(name, value, _, _) => {
+            unused.contains(&name.0) && is_pure(value)
+        }
+        Declaration::FunctionDeclaration { name, .. } => unused.contains(&name.0),
+        _ => false,
+    }
+}
+
+/// Private module items are only visible inside the module
+fn remove_unused_items(decl: &mut ModDecl, unused: &HashSet<String>) -> bool {
+    let length = decl.items.len();
+    decl.items
+        .retain(|item| item.is_pub || !is_unused(&item.declaration, unused));
+    let mut removed = decl.items.len() != length;
+    for item in &mut decl.items {
+        if let Declaration::ModDeclaration(decl) = &mut item.declaration {
+            removed |= remove_unused_items(decl, unused);
+        }
+    }
+    removed
+}
+
+/// Creating a closure doesn't run it, so closures are pure whatever their body does
+pub fn is_pure(expression: &Expression) -> bool {
+    match expression {
+        Expression::IdentifierExpression(_, _)
+        | Expression::LiteralExpression(_, _)
+        | Expression::PathExpression(_, _)
+        | Expression::ClosureExpression(_, _) => true,
+        Expression::UnaryExpression(expr, _) => is_pure(&expr.rhs),
+        Expression::InfixExpression(expr, _) => is_pure(&expr.lhs) && is_pure(&expr.rhs),
+        Expression::ArrayExpression(elements, _) => elements.iter().all(is_pure),
+        Expression::StructExpression(expr, _) => {
+            expr.fields.iter().all(|(_, value)| is_pure(value))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::{ast::Program, Parser},
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    fn eliminate(input: &str, cfg: &[(&str, bool)]) -> Program {
+        let cfg = cfg
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        let mut program = parse(input);
+        program.retain_mut(|statement| eliminate_dead_branches(statement, &cfg));
+        program
+    }
+
+    #[test]
+    fn constant_conditions() {
+        assert_eq!(
+            eliminate(
+                "if false { println(1); }
+                let a = if 1 > 2 { 1 } else if cfg::fast { 2 } else { 3 };
+                let b = if x { 1 } else if true || x { 2 } else { 3 };
+                let c = if !cfg::debug && x { 1 } else { 2 };
+                let d = if cfg::debug ?? x { 1 } else { 2 };
+                let e = if 7 / 2 == 3.5 && -7 % 2 == -1 { 1 } else { 2 };",
+                &[("fast", true)]
+            ),
+            parse(
+                "let a = { 2 };
+                let b = if x { 1 } else { 2 };
+                let c = if x { 1 } else { 2 };
+                let d = { 2 };
+                let e = { 1 };"
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_conditions() {
+        assert_eq!(
+            eliminate(
+                "let a = if x == 1 { 1 } else { 2 };
+                let b = if cfg::debug == 1 { 1 } else { 2 };
+                let c = if \"a\" + \"b\" == \"ab\" { 1 };
+                let d = if 1 % 0 == 0 { 1 };",
+                &[]
+            ),
+            parse(
+                "let a = if x == 1 { 1 } else { 2 };
+                let b = if false == 1 { 1 } else { 2 };
+                let c = if \"a\" + \"b\" == \"ab\" { 1 };
+                let d = if 1 % 0 == 0 { 1 };"
+            )
+        );
+    }
+
+    #[test]
+    fn unused_declarations() {
+        assert_eq!(
+            eliminate(
+                "fn main(x: number) {
+                    fn log(value: number) { println(value); }
+                    let message = x + 1;
+                    let side_effect = log(x);
+                    let used = 2;
+                    if cfg::debug {
+                        log(message);
+                    };
+                    used
+                }
+                mod m {
+                    fn helper() { 1 }
+                    pub fn exported() { 2 }
+                    pub fn run() { if cfg::debug { helper() } else { exported() } }
+                }",
+                &[]
+            ),
+            parse(
+                "fn main(x: number) {
+                    fn log(value: number) { println(value); }
+                    let side_effect = log(x);
+                    let used = 2;
+                    used
+                }
+                mod m {
+                    pub fn exported() { 2 }
+                    pub fn run() { { exported() } }
+                }"
+            )
+        );
+    }
+
+    #[test]
+    fn constant_matches() {
+        assert_eq!(
+            eliminate(
+                "let a = match 2 { 1 => \"one\", 2 => \"two\", _ => \"many\" };
+                let b = match cfg::debug { true => 1, false => 2 };
+                let c = match \"GET /users\" { \"POST \" .. path => 1, \"GET \" .. path => path, _ => 3 };
+                let d = match 3 { 1 | 2 => 1, n if n > 2 => n, _ => 0 };
+                let e = match 3 { 1 => 1, n if n > x => n, 3 => 2, _ => 0 };
+                let f = match x { 1 => 1, _ => 2 };
+                let g = match 1 { true => 1, _ => 2 };",
+                &[]
+            ),
+            parse(
+                "let a = { \"two\" };
+                let b = { 2 };
+                let c = { let path = \"/users\"; path };
+                let d = match 3 { n if n > 2 => n, _ => 0 };
+                let e = match 3 { n if n > x => n, 3 => 2 };
+                let f = match x { 1 => 1, _ => 2 };
+                let g = match 1 { true => 1, _ => 2 };"
+            )
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::ast::{Declaration, Statement};
+
+use super::dead_branches::{is_pure, referenced_names};
+
+/// The attribute that keeps a declaration nothing uses, for code only the environment calls
+pub const ALLOW_UNUSED: &str = "allow(unused)";
+
+/// The names of the top level `let`, `const` and `fn` declarations nothing in the program uses.
+/// Everything else is kept, and so is everything used by something that is kept. Declarations
+/// that are `pub`, have `#[allow(unused)]` or an initializer with side effects are always kept.
+/// Locals shadowing a declaration keep it alive, which is wasteful but safe
+pub fn unused_declarations<'a>(
+    program: impl IntoIterator<Item = &'a Statement>,
+) -> HashSet<String> {
+    let mut removable = HashMap::<&str, Vec<&Statement>>::new();
+    let mut pending = vec![];
+    for statement in program {
+        match removable_name(statement) {
+            Some(name) => removable.entry(name).or_default().push(statement),
+            None => pending.extend(referenced_names(statement)),
+        }
+    }
+    let mut used = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if used.contains(&name) {
+            continue;
+        }
+        for statement in removable.get(name.as_str()).into_iter().flatten() {
+            pending.extend(referenced_names(statement));
+        }
+        used.insert(name);
+    }
+    removable
+        .into_keys()
+        .filter(|name| !used.contains(*name))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Returns `false` for the declarations in `unused`, see `unused_declarations`
+pub fn eliminate_dead_code(statement: &Statement, unused: &HashSet<String>) -> bool {
+    !removable_name(statement).is_some_and(|name| unused.contains(name))
+}
+
+fn removable_name(statement: &Statement) -> Option<&str> {
+    let Statement::DeclarationStatement(declaration, _) = statement else {
+        return None;
+    };
+    let (name, attributes) = match declaration {
+        Declaration::ConstDeclaration(name, value, false, attributes, _)
+        | Declaration::LetDeclaration(name, value, attributes, _)
+            if is_pure(value) =>
+        {
+            (name, attributes)
+        }
+        Declaration::FunctionDeclaration {
+            is_pub: false,
+            name,
+            attributes,
+            ..
+        } => (name, attributes),
+        _ => return None,
+    };
+    (!attributes
+        .iter()
+        .any(|attribute| attribute.0 == ALLOW_UNUSED))
+    .then_some(&name.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::{ast::Program, Parser},
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    fn eliminate(input: &str) -> Program {
+        let mut program = parse(input);
+        let unused = unused_declarations(&program);
+        program.retain(|statement| eliminate_dead_code(statement, &unused));
+        program
+    }
+
+    #[test]
+    fn removes_unused_declarations() {
+        assert_eq!(
+            eliminate(
+                "fn square(x: number) { x * x }
+                fn cube(x: number) { x * square(x) }
+                fn unused() { cube(2) }
+                fn recursive(n: number) { if n > 0 { recursive(n - 1) } else { n } }
+                const SCALE = 2;
+                let offset = SCALE + 1;
+                let handler = |x: number| square(x);
+                println(cube(offset));"
+            ),
+            parse(
+                "fn square(x: number) { x * x }
+                fn cube(x: number) { x * square(x) }
+                const SCALE = 2;
+                let offset = SCALE + 1;
+                println(cube(offset));"
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_exported_allowed_and_effectful_declarations() {
+        let input = "fn helper() { 1 }
+            pub fn api() { helper() }
+            #[allow(unused)] fn callback() { 2 }
+            #[allow(unused)] let debug = true;
+            pub const VERSION = 3;
+            let started = now();
+            impl Point { fn norm(self) { helper() } }";
+        assert_eq!(eliminate(input), parse(input));
+    }
+}
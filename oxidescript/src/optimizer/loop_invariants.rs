@@ -0,0 +1,475 @@
+use std::collections::HashSet;
+
+use crate::parser::{
+    ast::{
+        Block, Declaration, Expression, Identifier, InfixOperator, OptionalAccess, Span, Statement,
+    },
+    visit::{walk_expression, walk_statement_mut},
+};
+
+/// Moves the calculations in `for` and `while` bodies that come out the same in every iteration in
+/// front of the loop. A calculation is the same if it only uses variables declared outside of the
+/// loop, which are never assigned in the program (`assigned`):
+///
+/// ```text
+/// for item in items { item * scale + offset * 2 }
+/// ```
+///
+/// becomes
+///
+/// ```text
+/// { const __invariant_0 = offset * 2; for item in items { item * scale + __invariant_0 } }
+/// ```
+///
+/// Only operators without side effects are moved, but they are evaluated even if the loop runs zero
+/// times, so `/` and `%` stay in the loop. The condition of a `while` is evaluated again before
+/// every iteration and stays as it is. Nested functions and closures are left alone
+pub fn hoist_loop_invariants(statement: &mut Statement, assigned: &HashSet<String>) {
+    // names have to be unique, an inner loop can use the invariants of an outer one
+    let mut count = 0;
+    walk_statement_mut(statement, &mut |expression| {
+        let (body, span, mut variant) = match expression {
+            Expression::ForExpression(expr, span) => {
+                (&mut expr.body, *span, HashSet::from([expr.lhs.0.clone()]))
+            }
+            Expression::WhileExpression(expr, span) => (&mut expr.body, *span, HashSet::new()),
+            _ => return,
+        };
+        declared_names(body, &mut variant);
+        variant.extend(assigned.iter().cloned());
+        let mut hoisted = vec![];
+        hoist_in_block(body, &variant, &mut hoisted, &mut count);
+        if hoisted.is_empty() {
+            return;
+        }
+        let statements = hoisted
+            .into_iter()
+            .map(|(value, name)| {
+                Statement::DeclarationStatement(
+                    Declaration::ConstDeclaration(name, value, false, vec![], None),
+                    Span::default(),
+                )
+            })
+            .collect();
+        let loop_ = std::mem::replace(
+            expression,
+            Expression::ArrayExpression(vec![], Span::default()),
+        );
+        *expression = Expression::BlockExpression(
+            Box::new(Block {
+                statements,
+                return_value: Some(loop_),
+                span,
+            }),
+            span,
+        );
+    });
+}
+
+/// Every name bound somewhere in the block, including nested scopes
+pub(super) fn declared_names(block: &Block, names: &mut HashSet<String>) {
+    for statement in &block.statements {
+        match statement {
+            Statement::DeclarationStatement(declaration, _) => match declaration {
+                Declaration::ConstDeclaration(name, value, _, _, _)
+                | Declaration::LetDeclaration(name, value, _, _) => {
+                    names.insert(name.0.clone());
+                    expression_declared_names(value, names);
+                }
+                Declaration::DestructuringDeclaration(pattern, value, _, _) => {
+                    names.extend(
+                        pattern
+                            .bindings(value)
+                            .into_iter()
+                            .map(|(name, _)| name.0.clone()),
+                    );
+                    expression_declared_names(value, names);
+                }
+                Declaration::FunctionDeclaration {
+                    name, parameters, ..
+                } => {
+                    names.insert(name.0.clone());
+                    names.extend(parameters.iter().map(|parameter| parameter.name.0.clone()));
+                }
+                Declaration::StructDeclaration(decl) => {
+                    names.insert(decl.name.0.clone());
+                }
+                Declaration::EnumDeclaration(decl) => {
+                    names.insert(decl.name.0.clone());
+                }
+                Declaration::ImplDeclaration(_) => {}
+                Declaration::ModDeclaration(decl) => {
+                    names.insert(decl.name.0.clone());
+                }
+                Declaration::ModFileDeclaration(name) => {
+                    names.insert(name.0.clone());
+                }
+                Declaration::UseDeclaration(decl) => {
+                    names.extend(decl.items.iter().map(|item| item.0.clone()));
+                }
+                Declaration::TypeDeclaration(_) | Declaration::TraitDeclaration(_) => {}
+                Declaration::ExternDeclaration(decl) => {
+                    names.insert(decl.name.0.clone());
+                }
+            },
+            Statement::ExpressionStatement { expression, .. } => {
+                expression_declared_names(expression, names)
+            }
+        }
+    }
+    if let Some(return_value) = &block.return_value {
+        expression_declared_names(return_value, names);
+    }
+}
+
+fn expression_declared_names(expression: &Expression, names: &mut HashSet<String>) {
+    walk_expression(expression, &mut |expression| match expression {
+        Expression::ForExpression(expr, _) => {
+            names.insert(expr.lhs.0.clone());
+            declared_names(&expr.body, names);
+        }
+        Expression::WhileExpression(expr, _) => declared_names(&expr.body, names),
+        Expression::WithExpression(expr, _) => {
+            names.insert(expr.name.0.clone());
+            declared_names(&expr.body, names);
+        }
+        Expression::MatchExpression(expr, _) => {
+            names.extend(expr.arms.iter().flat_map(|arm| {
+                arm.pattern
+                    .bindings(&expr.value)
+                    .into_iter()
+                    .map(|(name, _)| name.0.clone())
+            }));
+        }
+        Expression::IfExpression(expr, _) => {
+            declared_names(&expr.then_block, names);
+            for else_if in &expr.else_if_blocks {
+                declared_names(&else_if.then_block, names);
+            }
+            if let Some(else_block) = &expr.else_block {
+                declared_names(else_block, names);
+            }
+        }
+        Expression::BlockExpression(block, _) | Expression::DeferExpression(block, _) => {
+            declared_names(block, names)
+        }
+        Expression::ClosureExpression(expr, _) => {
+            names.extend(
+                expr.parameters
+                    .iter()
+                    .map(|parameter| parameter.name.0.clone()),
+            );
+        }
+        _ => {}
+    });
+}
+
+fn hoist_in_block(
+    block: &mut Block,
+    variant: &HashSet<String>,
+    hoisted: &mut Vec<(Expression, Identifier)>,
+    count: &mut usize,
+) {
+    for statement in &mut block.statements {
+        match statement {
+            Statement::ExpressionStatement { expression, .. }
+            | Statement::DeclarationStatement(
+                Declaration::ConstDeclaration(_, expression, _, _, _)
+                | Declaration::LetDeclaration(_, expression, _, _)
+                | Declaration::DestructuringDeclaration(_, expression, _, _),
+                _,
+            ) => hoist_in_expression(expression, variant, hoisted, count),
+            Statement::DeclarationStatement(_, _) => {}
+        }
+    }
+    if let Some(return_value) = &mut block.return_value {
+        hoist_in_expression(return_value, variant, hoisted, count);
+    }
+}
+
+fn hoist_in_expression(
+    expression: &mut Expression,
+    variant: &HashSet<String>,
+    hoisted: &mut Vec<(Expression, Identifier)>,
+    count: &mut usize,
+) {
+    if is_worth_hoisting(expression) && is_invariant(expression, variant) {
+        let name = match hoisted.iter().find(|(value, _)| value == expression) {
+            Some((_, name)) => name.clone(),
+            None => {
+                let name = Identifier(format!("__invariant_{}", count));
+                *count += 1;
+                hoisted.push((expression.clone(), name.clone()));
+                name
+            }
+        };
+        *expression = Expression::IdentifierExpression(name, expression.span());
+        return;
+    }
+    let mut recurse = |expression: &mut Expression| {
+        hoist_in_expression(expression, variant, hoisted, count);
+    };
+    match expression {
+        Expression::IdentifierExpression(_, _)
+        | Expression::LiteralExpression(_, _)
+        | Expression::PathExpression(_, _)
+        | Expression::ImportExpression(_, _)
+        | Expression::ClosureExpression(_, _)
+        | Expression::BreakExpression(_)
+        | Expression::ContinueExpression(_) => {}
+        Expression::UnaryExpression(expr, _) => recurse(&mut expr.rhs),
+        Expression::AwaitExpression(value, _) => recurse(value),
+        Expression::InfixExpression(expr, _) => {
+            recurse(&mut expr.lhs);
+            recurse(&mut expr.rhs);
+        }
+        Expression::AssignmentExpression(expr, _) => {
+            recurse(&mut expr.lhs);
+            recurse(&mut expr.rhs);
+        }
+        Expression::ArrayExpression(elements, _) | Expression::TupleExpression(elements, _) => {
+            elements.iter_mut().for_each(recurse)
+        }
+        Expression::IfExpression(expr, _) => {
+            recurse(&mut expr.condition);
+            for else_if in &mut expr.else_if_blocks {
+                recurse(&mut else_if.condition);
+            }
+            hoist_in_block(&mut expr.then_block, variant, hoisted, count);
+            for else_if in &mut expr.else_if_blocks {
+                hoist_in_block(&mut else_if.then_block, variant, hoisted, count);
+            }
+            if let Some(else_block) = &mut expr.else_block {
+                hoist_in_block(else_block, variant, hoisted, count);
+            }
+        }
+        Expression::ForExpression(expr, _) => {
+            recurse(&mut expr.rhs);
+            hoist_in_block(&mut expr.body, variant, hoisted, count);
+        }
+        Expression::WhileExpression(expr, _) => {
+            recurse(&mut expr.condition);
+            hoist_in_block(&mut expr.body, variant, hoisted, count);
+        }
+        Expression::WithExpression(expr, _) => {
+            recurse(&mut expr.value);
+            hoist_in_block(&mut expr.body, variant, hoisted, count);
+        }
+        Expression::MatchExpression(expr, _) => {
+            recurse(&mut expr.value);
+            for arm in &mut expr.arms {
+                if let Some(guard) = &mut arm.guard {
+                    recurse(guard);
+                }
+                recurse(&mut arm.body);
+            }
+        }
+        Expression::BlockExpression(block, _) | Expression::DeferExpression(block, _) => {
+            hoist_in_block(block, variant, hoisted, count)
+        }
+        Expression::CallExpression(expr, _) => {
+            recurse(&mut expr.lhs);
+            expr.arguments.iter_mut().for_each(recurse);
+        }
+        Expression::TaggedTemplateExpression(expr, _) => {
+            recurse(&mut expr.tag);
+            expr.values.iter_mut().for_each(recurse);
+        }
+        Expression::TemplateExpression(expr, _) => expr.values.iter_mut().for_each(recurse),
+        Expression::IndexExpression(expr, _) => {
+            recurse(&mut expr.lhs);
+            recurse(&mut expr.index);
+        }
+        Expression::SliceExpression(expr, _) => {
+            recurse(&mut expr.lhs);
+            for bound in [&mut expr.start, &mut expr.end].into_iter().flatten() {
+                recurse(bound);
+            }
+        }
+        Expression::RangeExpression(expr, _) => {
+            recurse(&mut expr.start);
+            recurse(&mut expr.end);
+        }
+        Expression::MemberAccessExpression(expr, _) => recurse(&mut expr.lhs),
+        Expression::TupleIndexExpression(expr, _) => recurse(&mut expr.lhs),
+        Expression::CastExpression(expr, _) => recurse(&mut expr.value),
+        Expression::IsExpression(expr, _) => recurse(&mut expr.value),
+        Expression::OptionalExpression(expr, _) => {
+            recurse(&mut expr.lhs);
+            if let OptionalAccess::Index(index) = &mut expr.access {
+                recurse(index);
+            }
+        }
+        Expression::StructExpression(expr, _) => {
+            expr.fields.iter_mut().for_each(|(_, value)| recurse(value));
+        }
+        Expression::ReturnExpression(value, _) => {
+            if let Some(value) = value {
+                recurse(value);
+            }
+        }
+    }
+}
+
+/// Calculations with at least one variable, anything else is as cheap as a variable itself
+fn is_worth_hoisting(expression: &Expression) -> bool {
+    let mut uses_variable = false;
+    walk_expression(expression, &mut |expression| {
+        uses_variable |= matches!(
+            expression,
+            Expression::IdentifierExpression(_, _) | Expression::PathExpression(_, _)
+        );
+    });
+    matches!(
+        expression,
+        Expression::UnaryExpression(_, _) | Expression::InfixExpression(_, _)
+    ) && uses_variable
+}
+
+/// Operators on literals and variables from outside of the loop. Member accesses could run a
+/// getter and arrays can change, so they are never invariant
+fn is_invariant(expression: &Expression, variant: &HashSet<String>) -> bool {
+    match expression {
+        Expression::IdentifierExpression(ident, _) => !variant.contains(&ident.0),
+        Expression::LiteralExpression(_, _) | Expression::PathExpression(_, _) => true,
+        Expression::UnaryExpression(expr, _) => is_invariant(&expr.rhs, variant),
+        Expression::InfixExpression(expr, _) => {
+            !matches!(expr.op, InfixOperator::Divide | InfixOperator::Modulo)
+                && is_invariant(&expr.lhs, variant)
+                && is_invariant(&expr.rhs, variant)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        optimizer::assigned_names,
+        parser::{ast::Program, Parser},
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    fn hoist(input: &str) -> Program {
+        let mut program = parse(input);
+        let assigned = assigned_names(&program);
+        for statement in &mut program {
+            hoist_loop_invariants(statement, &assigned);
+        }
+        program
+    }
+
+    #[test]
+    fn hoists_invariants() {
+        assert_eq!(
+            hoist(
+                "fn scale(items: any, factor: number, offset: number) {
+                    for item in items {
+                        let scaled = item * (factor * 2);
+                        println(scaled + offset * factor, factor * 2);
+                        scaled
+                    }
+                }"
+            ),
+            parse(
+                "fn scale(items: any, factor: number, offset: number) {
+                    {
+                        const __invariant_0 = factor * 2;
+                        const __invariant_1 = offset * factor;
+                        for item in items {
+                            let scaled = item * __invariant_0;
+                            println(scaled + __invariant_1, __invariant_0);
+                            scaled
+                        }
+                    }
+                }"
+            )
+        );
+    }
+
+    #[test]
+    fn nested_loops() {
+        assert_eq!(
+            hoist(
+                "for row in rows {
+                    for column in columns {
+                        println(width * height, row * width, column + row * width);
+                    }
+                }"
+            ),
+            parse(
+                "{
+                    const __invariant_0 = width * height;
+                    for row in rows {
+                        {
+                            const __invariant_1 = row * width;
+                            for column in columns {
+                                println(__invariant_0, __invariant_1, column + __invariant_1);
+                            }
+                        }
+                    }
+                }"
+            )
+        );
+    }
+
+    #[test]
+    fn while_loops() {
+        assert_eq!(
+            hoist(
+                "fn fill(size: number, step: number) {
+                    let i = 0;
+                    while i < size * 2 {
+                        let next = i + step * 2;
+                        println(next, size * 2);
+                        i = next;
+                    }
+                }"
+            ),
+            parse(
+                "fn fill(size: number, step: number) {
+                    let i = 0;
+                    {
+                        const __invariant_0 = step * 2;
+                        const __invariant_1 = size * 2;
+                        while i < size * 2 {
+                            let next = i + __invariant_0;
+                            println(next, __invariant_1);
+                            i = next;
+                        }
+                    }
+                }"
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_variant_and_effectful_expressions() {
+        let input = "for item in items {
+            let doubled = item * 2;
+            let x = offset + 1;
+            println(x * 2, total / count, list.length + 1, compute(offset) + 1, -1, doubled);
+            fn inner(offset: number) { offset * factor }
+        }";
+        assert_eq!(hoist(input), parse(input));
+    }
+
+    #[test]
+    fn keeps_assigned_variables() {
+        let input = "let total = 0;
+            fn reset() { scale = 1; }
+            for item in items {
+                total += item * factor;
+                println(total * 2, scale * 2);
+            }";
+        assert_eq!(hoist(input), parse(input));
+    }
+}
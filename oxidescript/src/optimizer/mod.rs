@@ -0,0 +1,276 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    consteval::{fold_constants, Evaluator},
+    parser::{
+        ast::{Expression, Program, Statement},
+        visit::walk_program,
+    },
+};
+
+pub mod dead_branches;
+pub mod dead_code;
+pub mod loop_invariants;
+pub mod simplify;
+
+/// An optimization of a top level statement
+pub struct Pass {
+    pub name: &'static str,
+    /// Whether the pass runs without being turned on with `+name`
+    pub default: bool,
+    /// Returns `false` if nothing is left of the statement
+    pub run: fn(&mut Statement, &PassContext) -> bool,
+}
+
+/// What a pass knows besides the statement it optimizes
+pub struct PassContext<'a> {
+    /// The `cfg` flags of the build
+    pub cfg: &'a HashMap<String, bool>,
+    /// Whether prelude items like `checked_add` are in scope without being declared
+    pub prelude: bool,
+    /// Every variable name assigned somewhere in the program. A function in another statement can
+    /// change a variable the statement uses
+    pub assigned: HashSet<String>,
+    /// The top level declarations nothing uses, found right before the `dead-code` pass. Empty if
+    /// other modules can use the top level items
+    pub unused: HashSet<String>,
+    /// The constants and `const fn`s of the program before any pass changed it
+    pub constants: &'a Evaluator<'a>,
+}
+
+/// Every pass, in the order they run
+pub const PASSES: &[Pass] = &[
+    Pass {
+        name: "const-eval",
+        default: true,
+        run: |statement, ctx| {
+            fold_constants(statement, ctx.constants);
+            true
+        },
+    },
+    Pass {
+        name: "dead-branches",
+        default: true,
+        run: |statement, ctx| dead_branches::eliminate_dead_branches(statement, ctx.cfg),
+    },
+    Pass {
+        name: "simplify",
+        default: true,
+        run: |statement, ctx| {
+            simplify::simplify_algebra(statement, ctx.prelude);
+            true
+        },
+    },
+    Pass {
+        name: "loop-invariants",
+        default: true,
+        run: |statement, ctx| {
+            loop_invariants::hoist_loop_invariants(statement, &ctx.assigned);
+            true
+        },
+    },
+    Pass {
+        name: "dead-code",
+        default: true,
+        run: |statement, ctx| dead_code::eliminate_dead_code(statement, &ctx.unused),
+    },
+];
+
+/// Which passes run, and after which of them the program is dumped for debugging the compiler
+#[derive(Clone, Debug)]
+pub struct PassManager {
+    enabled: HashSet<&'static str>,
+    print_after: HashSet<&'static str>,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager {
+            enabled: PASSES
+                .iter()
+                .filter(|pass| pass.default)
+                .map(|pass| pass.name)
+                .collect(),
+            print_after: HashSet::new(),
+        }
+    }
+}
+
+impl PassManager {
+    /// Changes the default passes with a comma separated list like `-simplify,+name`, where
+    /// `-name` turns a pass off and `+name` or `name` turns it on. `backend` are the names of the
+    /// passes a backend runs on its own output, which are on by default
+    pub fn new(passes: &str, backend: &[&'static str]) -> Result<PassManager, String> {
+        let mut manager = PassManager::default();
+        manager.enabled.extend(backend);
+        for item in passes
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let (enable, name) = match item.strip_prefix('-') {
+                Some(name) => (false, name),
+                None => (true, item.strip_prefix('+').unwrap_or(item)),
+            };
+            let name = find(name, backend)?;
+            if enable {
+                manager.enabled.insert(name);
+            } else {
+                manager.enabled.remove(name);
+            }
+        }
+        Ok(manager)
+    }
+
+    /// Dumps the program after each of the named passes when running them
+    pub fn print_after(mut self, names: &[String]) -> Result<PassManager, String> {
+        for name in names {
+            self.print_after.insert(find(name, &[])?);
+        }
+        Ok(self)
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.enabled.contains(name)
+    }
+
+    /// Runs the enabled passes over every statement, one pass after the other. Statements nothing
+    /// is left of become `None`, so the result still lines up with the input. `exports` is
+    /// whether other modules can use the top level items. `dump` gets the program after every pass
+    /// in `print_after`
+    pub fn run(
+        &self,
+        program: Program,
+        cfg: &HashMap<String, bool>,
+        prelude: bool,
+        exports: bool,
+        mut dump: impl FnMut(&str, &Program),
+    ) -> Vec<Option<Statement>> {
+        let original = program.clone();
+        let constants = Evaluator::new(&original, prelude);
+        // passes only remove assignments, so the names found before the first one are enough
+        let mut ctx = PassContext {
+            cfg,
+            prelude,
+            assigned: assigned_names(&program),
+            unused: HashSet::new(),
+            constants: &constants,
+        };
+        let mut statements = program.into_iter().map(Some).collect::<Vec<_>>();
+        for pass in PASSES.iter().filter(|pass| self.is_enabled(pass.name)) {
+            // the earlier passes can leave declarations unused, like the ones only a removed
+            // branch called
+            if pass.name == "dead-code" && !exports {
+                ctx.unused = dead_code::unused_declarations(statements.iter().flatten());
+            }
+            for slot in &mut statements {
+                if let Some(statement) = slot {
+                    if !(pass.run)(statement, &ctx) {
+                        *slot = None;
+                    }
+                }
+            }
+            if self.print_after.contains(pass.name) {
+                dump(pass.name, &statements.iter().flatten().cloned().collect());
+            }
+        }
+        statements
+    }
+}
+
+/// The variables `name = value` or `name += value` assign to, anywhere in the program
+pub fn assigned_names(program: &Program) -> HashSet<String> {
+    let mut names = HashSet::new();
+    walk_program(program, &mut |expression| {
+        insert_assigned_name(expression, &mut names)
+    });
+    names
+}
+
+/// Assigning a member or an index doesn't change the variable itself
+fn insert_assigned_name(expression: &Expression, names: &mut HashSet<String>) {
+    if let Expression::AssignmentExpression(expr, _) = expression {
+        if let Expression::IdentifierExpression(ident, _) = &*expr.lhs {
+            names.insert(ident.0.clone());
+        }
+    }
+}
+
+fn find(name: &str, backend: &[&'static str]) -> Result<&'static str, String> {
+    let names = PASSES
+        .iter()
+        .map(|pass| pass.name)
+        .chain(backend.iter().copied());
+    names.clone().find(|known| *known == name).ok_or_else(|| {
+        format!(
+            "Unknown pass `{}`, expected one of {}",
+            name,
+            names.collect::<Vec<_>>().join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    #[test]
+    fn pass_lists() {
+        let manager = PassManager::new("", &["peephole"]).unwrap();
+        assert!(manager.is_enabled("simplify"));
+        assert!(manager.is_enabled("peephole"));
+
+        let manager =
+            PassManager::new("-simplify, -peephole,+loop-invariants", &["peephole"]).unwrap();
+        assert!(!manager.is_enabled("simplify"));
+        assert!(!manager.is_enabled("peephole"));
+        assert!(manager.is_enabled("loop-invariants"));
+        assert!(manager.is_enabled("dead-branches"));
+
+        assert!(PassManager::new("-inline", &[]).is_err());
+        assert!(PassManager::new("peephole", &[]).is_err());
+        assert!(PassManager::default()
+            .print_after(&["peephole".to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn runs_enabled_passes() {
+        let cfg = HashMap::from([("debug".to_string(), false)]);
+        let input = "if cfg::debug { println(1) }
+            for item in items { item * (scale + 1) }";
+        let mut dumped = vec![];
+        let manager = PassManager::new("-loop-invariants", &[])
+            .unwrap()
+            .print_after(&["dead-branches".to_string(), "simplify".to_string()])
+            .unwrap();
+        let statements = manager.run(parse(input), &cfg, true, false, |name, program| {
+            dumped.push((name.to_string(), program.len()))
+        });
+        assert_eq!(
+            statements,
+            vec![
+                None,
+                parse("for item in items { item * (scale + 1) }").pop()
+            ]
+        );
+        assert_eq!(
+            dumped,
+            vec![
+                ("dead-branches".to_string(), 1),
+                ("simplify".to_string(), 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::{
+    ast::{
+        Block, Declaration, Expression, ImplItem, InfixExpr, InfixOperator, Literal, Number,
+        Parameter, Statement, UnaryOperator, MAX_SAFE_INTEGER,
+    },
+    visit::{
+        walk_block, walk_block_mut, walk_declaration_mut, walk_expression_mut, walk_statement_mut,
+    },
+};
+
+use super::{insert_assigned_name, loop_invariants::declared_names};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Type {
+    Number,
+    Boolean,
+}
+
+/// Removes operations that can't change their operand:
+///
+/// - `x * 1`, `1 * x` and `x - 0` become `x`
+/// - `- -x` and `+x` become `x`
+/// - `!!x` becomes `x`
+/// - `checked_add(2, 3)` of the prelude becomes `2 + 3`, and the same for the other checked
+///   operations, if the operands are literals and the result is in range
+///
+/// The passes don't get the types [`check_types`](crate::parser::types::check_types) found, and a
+/// value of `any` can be a string, so the operand has to be known to be a number (or a boolean for
+/// `!!`): a literal, the result of another operator or a parameter annotated with `number` or
+/// `boolean` that isn't shadowed in the function. `"3" * 1` is `3` and `!!"a"` is `true`, so anything else
+/// is left alone.
+///
+/// A few identities that look the same are not applied, because they differ for some numbers:
+/// `x + 0` turns `-0` into `0`, `x * 0` is `NaN` for infinities, `x / 1` turns integers into
+/// floats in Python and Lua, and `x * 2` can't become `x << 1` because shifts truncate to 32 bit
+/// integers
+pub fn simplify_algebra(statement: &mut Statement, prelude: bool) {
+    if prelude {
+        walk_statement_mut(statement, &mut inline_checked);
+    }
+    match statement {
+        Statement::DeclarationStatement(declaration, _) => simplify_declaration(declaration),
+        statement => walk_statement_mut(statement, &mut |expression| {
+            simplify_expression(expression, &HashMap::new())
+        }),
+    }
+}
+
+fn simplify_declaration(declaration: &mut Declaration) {
+    match declaration {
+        Declaration::FunctionDeclaration {
+            parameters, body, ..
+        } => {
+            let types = parameter_types(parameters, &rebound_names(body));
+            walk_block_mut(body, &mut |expression| {
+                simplify_expression(expression, &types)
+            });
+        }
+        Declaration::ImplDeclaration(decl) => {
+            for item in &mut decl.items {
+                match item {
+                    ImplItem::Method(method)
+                    | ImplItem::Getter(method)
+                    | ImplItem::Setter(method) => {
+                        let types =
+                            parameter_types(&method.parameters, &rebound_names(&method.body));
+                        walk_block_mut(&mut method.body, &mut |expression| {
+                            simplify_expression(expression, &types)
+                        });
+                    }
+                    ImplItem::Const(_, expression) => {
+                        walk_expression_mut(expression, &mut |expression| {
+                            simplify_expression(expression, &HashMap::new())
+                        })
+                    }
+                }
+            }
+        }
+        Declaration::ModDeclaration(decl) => {
+            for item in &mut decl.items {
+                simplify_declaration(&mut item.declaration);
+            }
+        }
+        declaration => walk_declaration_mut(declaration, &mut |expression| {
+            simplify_expression(expression, &HashMap::new())
+        }),
+    }
+}
+
+/// The fast path of the checked operations, without the range check of their result
+fn inline_checked(expression: &mut Expression) {
+    let Expression::CallExpression(call, span) = expression else {
+        return;
+    };
+    let span = *span;
+    let (Expression::IdentifierExpression(name, _), [lhs, rhs]) =
+        (&*call.lhs, call.arguments.as_slice())
+    else {
+        return;
+    };
+    let (Some(op), Some(a), Some(b)) = (
+        InfixOperator::checked(&name.0),
+        literal_value(lhs),
+        literal_value(rhs),
+    ) else {
+        return;
+    };
+    let result = match op {
+        InfixOperator::Plus => a + b,
+        InfixOperator::Minus => a - b,
+        InfixOperator::Multiply => a * b,
+        _ => a / b,
+    };
+    // also false for `NaN`
+    if result.abs() <= MAX_SAFE_INTEGER {
+        *expression = Expression::InfixExpression(
+            InfixExpr {
+                op,
+                lhs: Box::new(lhs.clone()),
+                rhs: Box::new(rhs.clone()),
+            },
+            span,
+        );
+    }
+}
+
+/// A number literal, or a negated one
+fn literal_value(expression: &Expression) -> Option<f64> {
+    match expression {
+        Expression::LiteralExpression(Literal::NumberLiteral(number), _) => {
+            f64::try_from(number.clone()).ok()
+        }
+        Expression::UnaryExpression(expr, _) if expr.op == UnaryOperator::Minus => {
+            literal_value(&expr.rhs).map(|value| -value)
+        }
+        _ => None,
+    }
+}
+
+/// Names declared or assigned in the body, a parameter with one of them can hold another type
+fn rebound_names(body: &Block) -> HashSet<String> {
+    let mut names = HashSet::new();
+    declared_names(body, &mut names);
+    walk_block(body, &mut |expression| {
+        insert_assigned_name(expression, &mut names)
+    });
+    names
+}
+
+/// The parameters with a known type. Nested scopes could declare the same name with another type
+/// and assignments could change it, so shadowed and assigned parameters are left out
+fn parameter_types(parameters: &[Parameter], shadowed: &HashSet<String>) -> HashMap<String, Type> {
+    parameters
+        .iter()
+        .filter(|parameter| !shadowed.contains(&parameter.name.0))
+        .filter_map(|parameter| {
+            let type_ = match parameter.type_.0.as_str() {
+                "number" => Type::Number,
+                "boolean" => Type::Boolean,
+                _ => return None,
+            };
+            Some((parameter.name.0.clone(), type_))
+        })
+        .collect()
+}
+
+fn simplify_expression(expression: &mut Expression, types: &HashMap<String, Type>) {
+    // the walk visits parents first, but `-(-(x * 1))` can only be simplified after `x * 1` is
+    match expression {
+        Expression::UnaryExpression(expr, _) => simplify_expression(&mut expr.rhs, types),
+        Expression::InfixExpression(expr, _) => {
+            simplify_expression(&mut expr.lhs, types);
+            simplify_expression(&mut expr.rhs, types);
+        }
+        _ => {}
+    }
+    while let Some(simplified) = simplify_once(expression, types) {
+        *expression = simplified;
+    }
+}
+
+fn simplify_once(expression: &Expression, types: &HashMap<String, Type>) -> Option<Expression> {
+    let number = |expression: &Expression| type_of(expression, types) == Some(Type::Number);
+    let simplified = match expression {
+        Expression::InfixExpression(expr, _) => match expr.op {
+            InfixOperator::Multiply if is_literal(&expr.rhs, 1.0) && number(&expr.lhs) => &expr.lhs,
+            InfixOperator::Multiply if is_literal(&expr.lhs, 1.0) && number(&expr.rhs) => &expr.rhs,
+            InfixOperator::Minus if is_literal(&expr.rhs, 0.0) && number(&expr.lhs) => &expr.lhs,
+            _ => return None,
+        },
+        Expression::UnaryExpression(expr, _) => match (&expr.op, &*expr.rhs) {
+            (UnaryOperator::Plus, rhs) if number(rhs) => &expr.rhs,
+            (UnaryOperator::Minus, Expression::UnaryExpression(inner, _))
+                if inner.op == UnaryOperator::Minus && number(&inner.rhs) =>
+            {
+                &inner.rhs
+            }
+            (UnaryOperator::LogicalNot, Expression::UnaryExpression(inner, _))
+                if inner.op == UnaryOperator::LogicalNot
+                    && type_of(&inner.rhs, types) == Some(Type::Boolean) =>
+            {
+                &inner.rhs
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((**simplified).clone())
+}
+
+fn is_literal(expression: &Expression, value: f64) -> bool {
+    match expression {
+        Expression::LiteralExpression(Literal::NumberLiteral(Number::I { value: int, .. }), _) => {
+            *int as f64 == value
+        }
+        Expression::LiteralExpression(Literal::NumberLiteral(Number::F(float)), _) => {
+            float.parse::<f64>().is_ok_and(|float| float == value)
+        }
+        _ => false,
+    }
+}
+
+/// The type of an expression if it is the same in every backend
+fn type_of(expression: &Expression, types: &HashMap<String, Type>) -> Option<Type> {
+    match expression {
+        Expression::LiteralExpression(Literal::NumberLiteral(_), _) => Some(Type::Number),
+        Expression::LiteralExpression(Literal::BooleanLiteral(_), _) => Some(Type::Boolean),
+        Expression::IdentifierExpression(ident, _) => types.get(&ident.0).copied(),
+        Expression::UnaryExpression(expr, _) => match expr.op {
+            UnaryOperator::LogicalNot => Some(Type::Boolean),
+            UnaryOperator::Minus | UnaryOperator::Plus => {
+                type_of(&expr.rhs, types).filter(|type_| *type_ == Type::Number)
+            }
+            UnaryOperator::BitwiseNot => None,
+        },
+        Expression::InfixExpression(expr, _) => match expr.op {
+            InfixOperator::Equal
+            | InfixOperator::NotEqual
+            | InfixOperator::GreaterThan
+            | InfixOperator::LessThan
+            | InfixOperator::GreaterThanEqual
+            | InfixOperator::LessThanEqual => Some(Type::Boolean),
+            InfixOperator::Plus
+            | InfixOperator::Minus
+            | InfixOperator::Multiply
+            | InfixOperator::Divide
+            | InfixOperator::Modulo => {
+                let lhs = type_of(&expr.lhs, types)?;
+                let rhs = type_of(&expr.rhs, types)?;
+                (lhs == Type::Number && rhs == Type::Number).then_some(Type::Number)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::{ast::Program, Parser},
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    fn simplify(input: &str) -> Program {
+        let mut program = parse(input);
+        program
+            .iter_mut()
+            .for_each(|statement| simplify_algebra(statement, true));
+        program
+    }
+
+    fn in_function(body: &str) -> String {
+        format!("fn f(x: number, b: boolean, s: string) {{ {} }}", body)
+    }
+
+    fn body(program: &Program) -> &Block {
+        let [Statement::DeclarationStatement(Declaration::FunctionDeclaration { body, .. }, _)] =
+            program.as_slice()
+        else {
+            panic!("expected a single function, got {:?}", program);
+        };
+        body
+    }
+
+    /// The number of `x` with javascript semantics, for the operators the simplifications touch
+    fn evaluate(expression: &Expression, x: f64) -> f64 {
+        match expression {
+            Expression::IdentifierExpression(ident, _) if ident.0 == "x" => x,
+            Expression::LiteralExpression(Literal::NumberLiteral(Number::I { value, .. }), _) => {
+                *value as f64
+            }
+            Expression::LiteralExpression(Literal::NumberLiteral(Number::F(float)), _) => {
+                float.parse().unwrap()
+            }
+            Expression::UnaryExpression(expr, _) => match expr.op {
+                UnaryOperator::Minus => -evaluate(&expr.rhs, x),
+                UnaryOperator::Plus => evaluate(&expr.rhs, x),
+                _ => unimplemented!("{:?}", expr.op),
+            },
+            Expression::InfixExpression(expr, _) => {
+                let lhs = evaluate(&expr.lhs, x);
+                let rhs = evaluate(&expr.rhs, x);
+                match expr.op {
+                    InfixOperator::Plus => lhs + rhs,
+                    InfixOperator::Minus => lhs - rhs,
+                    InfixOperator::Multiply => lhs * rhs,
+                    InfixOperator::Divide => lhs / rhs,
+                    InfixOperator::BitwiseLeftShift => ((lhs as i64 as i32) << (rhs as u32)) as f64,
+                    _ => unimplemented!("{:?}", expr.op),
+                }
+            }
+            _ => unimplemented!("{:?}", expression),
+        }
+    }
+
+    /// Same value, telling `0` and `-0` apart
+    fn same(a: f64, b: f64) -> bool {
+        (a.is_nan() && b.is_nan()) || a.to_bits() == b.to_bits()
+    }
+
+    const EDGE_CASES: [f64; 10] = [
+        0.0,
+        -0.0,
+        1.0,
+        -1.0,
+        0.5,
+        f64::NAN,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::MAX,
+        2147483647.0,
+    ];
+
+    #[test]
+    fn simplifies() {
+        let cases = [
+            ("x * 1", "x"),
+            ("1 * x", "x"),
+            ("x * 1.0", "x"),
+            ("x - 0", "x"),
+            ("x * 1 * 1 - 0", "x"),
+            ("- -x", "x"),
+            ("-(-(x * 1))", "x"),
+            ("+x", "x"),
+            ("(x + 2) * 1", "x + 2"),
+            ("!!b", "b"),
+            ("!!(x < 2)", "x < 2"),
+            ("!!!b", "!b"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                simplify(&in_function(input)),
+                parse(&in_function(expected)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_unknown_types() {
+        for input in [
+            "s * 1",
+            "y * 1",
+            "- -s",
+            "+s",
+            "!!x",
+            "!!s",
+            "x + s - 0",
+            "{ let x = \"a\"; x * 1 }",
+            "fn inner(x: string) { x * 1 }",
+            "|x| x * 1",
+            "{ x = \"a\"; x * 1 }",
+        ] {
+            let input = in_function(input);
+            assert_eq!(simplify(&input), parse(&input));
+        }
+        let input = "x * 1; - -x";
+        assert_eq!(simplify(input), parse(input));
+    }
+
+    #[test]
+    fn inlines_checked_operations() {
+        for (input, expected) in [
+            ("checked_add(2, 3)", "2 + 3"),
+            ("checked_mul(-4, 5)", "-4 * 5"),
+            ("checked_div(7, 2.5)", "7 / 2.5"),
+            ("checked_add(x, 1)", "checked_add(x, 1)"),
+            (
+                "checked_add(9007199254740991, 1)",
+                "checked_add(9007199254740991, 1)",
+            ),
+            ("checked_div(1, 0)", "checked_div(1, 0)"),
+            ("checked_div(0, 0)", "checked_div(0, 0)"),
+        ] {
+            assert_eq!(simplify(input), parse(expected), "{}", input);
+        }
+        let mut program = parse("checked_add(2, 3)");
+        simplify_algebra(&mut program[0], false);
+        assert_eq!(program, parse("checked_add(2, 3)"));
+    }
+
+    /// Every simplification gives the same number for every edge case
+    #[test]
+    fn equivalence_matrix() {
+        for input in [
+            "x * 1",
+            "1 * x",
+            "x - 0",
+            "- -x",
+            "+x",
+            "(x - 0) * 1 * -(-1)",
+        ] {
+            let program = simplify(&in_function(input));
+            let simplified = body(&program).return_value.as_ref().unwrap();
+            assert_ne!(program, parse(&in_function(input)), "{}", input);
+
+            let program = parse(&in_function(input));
+            let original = body(&program).return_value.as_ref().unwrap();
+            for x in EDGE_CASES {
+                let (a, b) = (evaluate(original, x), evaluate(simplified, x));
+                assert!(same(a, b), "{} with x = {}: {} != {}", input, x, a, b);
+            }
+        }
+    }
+
+    /// The identities that are left alone, each with a number where they would be wrong
+    #[test]
+    fn keeps_inexact_identities() {
+        for (input, identity, counterexample) in [
+            ("x + 0", "x", -0.0),
+            ("0 + x", "x", -0.0),
+            ("x * 0", "0", f64::INFINITY),
+            ("x * 0", "0", -1.0),
+            ("x * 2", "x << 1", 0.5),
+            ("x * 2", "x << 1", 2147483647.0),
+            ("x * 2", "x << 1", -0.0),
+        ] {
+            let source = in_function(input);
+            assert_eq!(simplify(&source), parse(&source));
+
+            let program = parse(&source);
+            let original = body(&program).return_value.as_ref().unwrap();
+            let program = parse(&in_function(identity));
+            let rewritten = body(&program).return_value.as_ref().unwrap();
+            assert!(!same(
+                evaluate(original, counterexample),
+                evaluate(rewritten, counterexample)
+            ));
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use nom::error::ErrorKind;
+use nom::{error_position, Err, IResult};
+
+use crate::lexer::tokens::Tokens;
+
+use super::ast::{
+    AssignmentExpr, Expression, IndexExpr, InfixExpr, MemberAccessExpr, Precedence, Span,
+};
+use super::atoms::assignment_operator;
+use super::optional::has_optional;
+use super::pratt_expression::parse_pratt_expression;
+use super::take_token;
+
+/// `left = value` or `left += value`. Assignments are right associative, `a = b = c` assigns `c`
+/// to both. Only variables, members and indexes can be assigned, without `?.` or `?[`
+pub fn parse_assignment_expression(input: Tokens, left: Expression) -> IResult<Tokens, Expression> {
+    let (rest, operator) = take_token(input)?;
+    let op = operator
+        .tokens
+        .first()
+        .and_then(assignment_operator)
+        .ok_or(Err::Error(error_position!(input, ErrorKind::Tag)))?;
+    let assignable = matches!(
+        left,
+        Expression::IdentifierExpression(_, _)
+            | Expression::MemberAccessExpression(_, _)
+            | Expression::IndexExpression(_, _)
+    );
+    if !assignable || has_optional(&left) {
+        return Err(Err::Error(error_position!(input, ErrorKind::Verify)));
+    }
+    let (rest, value) = parse_pratt_expression(rest, Precedence::PLowest)?;
+    Ok((
+        rest,
+        Expression::AssignmentExpression(
+            AssignmentExpr {
+                op,
+                lhs: Box::new(left),
+                rhs: Box::new(value),
+            },
+            Span::default(),
+        ),
+    ))
+}
+
+impl AssignmentExpr {
+    /// `(target, value)` with the operator spelled out, `a[i] += 1` assigns `a[i] + 1` to `a[i]`,
+    /// for backends where assignments are statements. The target is read again for the value and
+    /// for the result, so an object or index that isn't a variable or literal is passed to `bind`
+    /// first, which evaluates it once and returns what to use in its place
+    pub fn spelled_out(
+        self,
+        mut bind: impl FnMut(Expression) -> Expression,
+    ) -> (Expression, Expression) {
+        let mut evaluated = |expression: Box<Expression>| match *expression {
+            Expression::IdentifierExpression(_, _)
+            | Expression::LiteralExpression(_, _)
+            | Expression::PathExpression(_, _) => expression,
+            expression => Box::new(bind(expression)),
+        };
+        let target = match *self.lhs {
+            Expression::MemberAccessExpression(expr, _) => Expression::MemberAccessExpression(
+                MemberAccessExpr {
+                    lhs: evaluated(expr.lhs),
+                    ident: expr.ident,
+                },
+                Span::default(),
+            ),
+            Expression::IndexExpression(expr, _) => {
+                let lhs = evaluated(expr.lhs);
+                Expression::IndexExpression(
+                    IndexExpr {
+                        lhs,
+                        index: evaluated(expr.index),
+                    },
+                    Span::default(),
+                )
+            }
+            target => target,
+        };
+        let value = match self.op {
+            Some(op) => Expression::InfixExpression(
+                InfixExpr {
+                    op,
+                    lhs: Box::new(target.clone()),
+                    rhs: self.rhs,
+                },
+                Span::default(),
+            ),
+            None => *self.rhs,
+        };
+        (target, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::{
+        ast::{Identifier, Statement},
+        Parser,
+    };
+
+    use super::*;
+
+    fn expression(input: &str) -> Expression {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        match Parser::parse(Tokens::new(&tokens)).unwrap().1.pop() {
+            Some(Statement::ExpressionStatement { expression, .. }) => expression,
+            statement => panic!("expected an expression, got {:?}", statement),
+        }
+    }
+
+    fn spelled_out(input: &str) -> (Vec<Expression>, Expression, Expression) {
+        let Expression::AssignmentExpression(expr, _) = expression(input) else {
+            panic!("expected an assignment");
+        };
+        let mut bound = vec![];
+        let (target, value) = expr.spelled_out(|value| {
+            bound.push(value);
+            Expression::IdentifierExpression(
+                Identifier(format!("t{}", bound.len() - 1)),
+                Span::default(),
+            )
+        });
+        (bound, target, value)
+    }
+
+    #[test]
+    fn spelled_out_assignments() {
+        assert_eq!(
+            spelled_out("x = 1"),
+            (vec![], expression("x"), expression("1"))
+        );
+        assert_eq!(
+            spelled_out("point.x *= 2"),
+            (vec![], expression("point.x"), expression("point.x * 2"))
+        );
+        assert_eq!(
+            spelled_out("rows()[i + 1] += 1"),
+            (
+                vec![expression("rows()"), expression("i + 1")],
+                expression("t0[t1]"),
+                expression("t0[t1] + 1")
+            )
+        );
+        assert_eq!(
+            spelled_out("a.b.c = d = 2"),
+            (
+                vec![expression("a.b")],
+                expression("t0.c"),
+                expression("d = 2")
+            )
+        );
+    }
+}
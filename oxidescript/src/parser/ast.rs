@@ -1,30 +1,231 @@
-use std::{fmt::Display, num::ParseFloatError};
+use std::{fmt::Display, num::ParseFloatError, str::FromStr};
 
 pub type Program = Vec<Statement>;
 
+/// Where a node is in the source, as byte offsets into the file `file_id`. Nodes the compiler
+/// creates itself have the empty default span. Spans don't take part in comparisons, nodes that
+/// only differ in where they are are equal
+#[derive(Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub file_id: usize,
+}
+
+impl Span {
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// From the start of this span to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Span) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+impl std::fmt::Debug for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.file_id != 0 {
+            write!(f, "{}:", self.file_id)?;
+        }
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum Statement {
     ExpressionStatement {
         expression: Expression,
         has_semicolon: bool,
+        span: Span,
     },
-    DeclarationStatement(Declaration),
+    DeclarationStatement(Declaration, Span),
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::ExpressionStatement { span, .. }
+            | Statement::DeclarationStatement(_, span) => *span,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Statement::ExpressionStatement { span, .. }
+            | Statement::DeclarationStatement(_, span) => span,
+        }
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum Expression {
-    IdentifierExpression(Identifier),
-    LiteralExpression(Literal),
-    UnaryExpression(UnaryExpr),
-    InfixExpression(InfixExpr),
-    ArrayExpression(Vec<Expression>),
-    IfExpression(IfExpr),
-    ForExpression(ForExpr), // TODO
-    // MatchExpression(MatchExpr), // TODO
-    BlockExpression(Box<Block>),
-    CallExpression(CallExpr),
-    IndexExpression(IndexExpr),
-    MemberAccessExpression(MemberAccessExpr),
+    IdentifierExpression(Identifier, Span),
+    LiteralExpression(Literal, Span),
+    UnaryExpression(UnaryExpr, Span),
+    InfixExpression(InfixExpr, Span),
+    AssignmentExpression(AssignmentExpr, Span),
+    ArrayExpression(Vec<Expression>, Span),
+    IfExpression(IfExpr, Span),
+    ForExpression(ForExpr, Span), // TODO
+    WhileExpression(WhileExpr, Span),
+    MatchExpression(MatchExpr, Span),
+    BlockExpression(Box<Block>, Span),
+    CallExpression(CallExpr, Span),
+    TaggedTemplateExpression(TaggedTemplateExpr, Span),
+    TemplateExpression(TemplateExpr, Span),
+    IndexExpression(IndexExpr, Span),
+    SliceExpression(SliceExpr, Span),
+    MemberAccessExpression(MemberAccessExpr, Span),
+    OptionalExpression(OptionalExpr, Span),
+    StructExpression(StructExpr, Span),
+    PathExpression(PathExpr, Span),
+    ImportExpression(ImportExpr, Span),
+    ClosureExpression(ClosureExpr, Span),
+    CastExpression(CastExpr, Span),
+    IsExpression(IsExpr, Span),
+    /// `return` or `return value`, leaves the function around it
+    ReturnExpression(Option<Box<Expression>>, Span),
+    /// `break`, leaves the innermost loop
+    BreakExpression(Span),
+    /// `continue`, starts the next iteration of the innermost loop
+    ContinueExpression(Span),
+    /// `defer { ... }`, runs the block when the block it is a statement of is left
+    DeferExpression(Box<Block>, Span),
+    /// `with name = value { ... }`, calls `close` of the value when the block is left
+    WithExpression(WithExpr, Span),
+    /// `promise.await`, waits for the promise in an `async` function or closure
+    AwaitExpression(Box<Expression>, Span),
+    /// `start..end` or `start..=end`, the numbers from `start` up to `end`
+    RangeExpression(RangeExpr, Span),
+    /// `(1, "a", true)`, a fixed number of values that can have different types
+    TupleExpression(Vec<Expression>, Span),
+    /// `tuple.0`, the item of a tuple at the index
+    TupleIndexExpression(TupleIndexExpr, Span),
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::IdentifierExpression(_, span)
+            | Expression::LiteralExpression(_, span)
+            | Expression::UnaryExpression(_, span)
+            | Expression::InfixExpression(_, span)
+            | Expression::AssignmentExpression(_, span)
+            | Expression::ArrayExpression(_, span)
+            | Expression::IfExpression(_, span)
+            | Expression::ForExpression(_, span)
+            | Expression::WhileExpression(_, span)
+            | Expression::MatchExpression(_, span)
+            | Expression::BlockExpression(_, span)
+            | Expression::CallExpression(_, span)
+            | Expression::TaggedTemplateExpression(_, span)
+            | Expression::TemplateExpression(_, span)
+            | Expression::IndexExpression(_, span)
+            | Expression::SliceExpression(_, span)
+            | Expression::RangeExpression(_, span)
+            | Expression::TupleExpression(_, span)
+            | Expression::TupleIndexExpression(_, span)
+            | Expression::MemberAccessExpression(_, span)
+            | Expression::OptionalExpression(_, span)
+            | Expression::StructExpression(_, span)
+            | Expression::PathExpression(_, span)
+            | Expression::ImportExpression(_, span)
+            | Expression::ClosureExpression(_, span)
+            | Expression::CastExpression(_, span)
+            | Expression::IsExpression(_, span)
+            | Expression::ReturnExpression(_, span)
+            | Expression::BreakExpression(span)
+            | Expression::ContinueExpression(span)
+            | Expression::DeferExpression(_, span)
+            | Expression::WithExpression(_, span)
+            | Expression::AwaitExpression(_, span) => *span,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Expression::IdentifierExpression(_, span)
+            | Expression::LiteralExpression(_, span)
+            | Expression::UnaryExpression(_, span)
+            | Expression::InfixExpression(_, span)
+            | Expression::AssignmentExpression(_, span)
+            | Expression::ArrayExpression(_, span)
+            | Expression::IfExpression(_, span)
+            | Expression::ForExpression(_, span)
+            | Expression::WhileExpression(_, span)
+            | Expression::MatchExpression(_, span)
+            | Expression::BlockExpression(_, span)
+            | Expression::CallExpression(_, span)
+            | Expression::TaggedTemplateExpression(_, span)
+            | Expression::TemplateExpression(_, span)
+            | Expression::IndexExpression(_, span)
+            | Expression::SliceExpression(_, span)
+            | Expression::RangeExpression(_, span)
+            | Expression::TupleExpression(_, span)
+            | Expression::TupleIndexExpression(_, span)
+            | Expression::MemberAccessExpression(_, span)
+            | Expression::OptionalExpression(_, span)
+            | Expression::StructExpression(_, span)
+            | Expression::PathExpression(_, span)
+            | Expression::ImportExpression(_, span)
+            | Expression::ClosureExpression(_, span)
+            | Expression::CastExpression(_, span)
+            | Expression::IsExpression(_, span)
+            | Expression::ReturnExpression(_, span)
+            | Expression::BreakExpression(span)
+            | Expression::ContinueExpression(span)
+            | Expression::DeferExpression(_, span)
+            | Expression::WithExpression(_, span)
+            | Expression::AwaitExpression(_, span) => span,
+        }
+    }
+}
+
+/// `Type::item`
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PathExpr {
+    pub segments: Vec<Identifier>,
+}
+
+/// `value as type`, only changes the type of the value. Nothing is converted, the values of enums
+/// with discriminants already are their discriminants
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CastExpr {
+    pub value: Box<Expression>,
+    pub type_: Identifier,
+}
+
+/// `value is type`, checks the type of the value at runtime. The type can be a union like
+/// `string | number`, then the value has to be one of its members
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct IsExpr {
+    pub value: Box<Expression>,
+    pub type_: Identifier,
+}
+
+/// `import("./heavy")`, loads another module on demand and evaluates to a promise of its module
+/// object. The path is relative to the importing file and has no extension
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ImportExpr {
+    pub path: String,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct StructExpr {
+    pub name: Identifier,
+    pub fields: Vec<(Identifier, Expression)>,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -34,24 +235,158 @@ pub struct ForExpr {
     pub body: Box<Block>,
 }
 
+/// `with name = value { ... }`, the body can use the value as `name` and `name.close()` is called
+/// when the body is left, even by a `return` or an exception
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WithExpr {
+    pub name: Identifier,
+    pub value: Box<Expression>,
+    pub body: Box<Block>,
+}
+
+/// `while condition { ... }`, runs the body as long as the condition is true
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WhileExpr {
+    pub condition: Box<Expression>,
+    pub body: Box<Block>,
+}
+
+/// `match value { 1 | 2 => "small", n => n }`, the first arm with a matching pattern is
+/// evaluated. The parser only accepts matches that have an arm for every value
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MatchExpr {
+    pub value: Box<Expression>,
+    pub arms: Vec<MatchArm>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    /// `pattern if condition`, the arm only matches if the condition is true as well
+    pub guard: Option<Expression>,
+    pub body: Expression,
+    pub span: Span,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Pattern {
+    /// `_`, matches any value
+    Wildcard,
+    /// Matches any value and binds it to the name in the body of the arm
+    Binding(Identifier),
+    /// Matches values equal to the literal, like `==`
+    Literal(Literal),
+    /// `"GET /" .. rest`, matches strings starting with the prefix and binds the rest of the string
+    /// to the name if there is one
+    Prefix(String, Option<Identifier>),
+    /// `Shape::Circle(r)` or `Shape::Empty`, matches values of that variant whose values match the
+    /// patterns in the parentheses
+    Variant(Vec<Identifier>, Vec<Pattern>),
+    /// `1 | 2`, matches if any of the patterns does
+    Or(Vec<Pattern>),
+    /// `(x, _)`, matches tuples whose items match the patterns at the same index
+    Tuple(Vec<Pattern>),
+    /// `Point { x, y: (a, b) }`, only in destructurings. A field without a pattern binds the field
+    /// to its own name
+    Struct(Identifier, Vec<(Identifier, Pattern)>),
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct MemberAccessExpr {
     pub lhs: Box<Expression>,
     pub ident: Identifier,
 }
 
+/// `lhs?.ident` or `lhs?[index]`, which is `None` if `lhs` is. The rest of the chain after it,
+/// like the call in `map?.get(key)`, is skipped too
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct OptionalExpr {
+    pub lhs: Box<Expression>,
+    pub access: OptionalAccess,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum OptionalAccess {
+    Member(Identifier),
+    Index(Box<Expression>),
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct IndexExpr {
     pub lhs: Box<Expression>,
     pub index: Box<Expression>,
 }
 
+/// `|x, y| x + y`, an anonymous function. Parameters without a type annotation are `any`
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ClosureExpr {
+    /// `async |x| ...`, see `is_async` of functions
+    pub is_async: bool,
+    pub parameters: Vec<Parameter>,
+    pub body: Box<Expression>,
+}
+
+impl ClosureExpr {
+    /// The body as the block of a function, for backends that compile closures like functions
+    pub fn body_block(&self) -> Block {
+        match &*self.body {
+            Expression::BlockExpression(block, _) => (**block).clone(),
+            body => Block {
+                statements: vec![],
+                return_value: Some(body.clone()),
+                span: Span::default(),
+            },
+        }
+    }
+}
+
+/// `start..end`, the numbers from `start` up to but not including `end`, or up to and including
+/// it for `start..=end`. `for` loops over a range count instead of building an array
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RangeExpr {
+    pub start: Box<Expression>,
+    pub end: Box<Expression>,
+    pub inclusive: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TupleIndexExpr {
+    pub lhs: Box<Expression>,
+    pub index: usize,
+}
+
+/// `lhs[start..end]`, a new array or string with the items from `start` up to `end`. A missing
+/// `start` is the beginning and a missing `end` the end of `lhs`
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SliceExpr {
+    pub lhs: Box<Expression>,
+    pub start: Option<Box<Expression>>,
+    pub end: Option<Box<Expression>>,
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct CallExpr {
     pub lhs: Box<Expression>,
     pub arguments: Vec<Expression>,
 }
 
+/// ``sql`SELECT * WHERE id = {id}` ``, calls the tag with the strings between the interpolations
+/// and the interpolated values. There is always one more string than there are values
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TaggedTemplateExpr {
+    pub tag: Box<Expression>,
+    pub strings: Vec<String>,
+    pub values: Vec<Expression>,
+}
+
+/// `f"hello {name}"`, the strings between the interpolations joined with the interpolated values
+/// turned into strings. There is always one more string than there are values
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TemplateExpr {
+    pub strings: Vec<String>,
+    pub values: Vec<Expression>,
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct UnaryExpr {
     pub op: UnaryOperator,
@@ -65,15 +400,280 @@ pub struct InfixExpr {
     pub rhs: Box<Expression>,
 }
 
+/// `lhs = rhs` or `lhs += rhs`, where `lhs` is a variable, a member or an index. Evaluates to the
+/// assigned value
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct AssignmentExpr {
+    /// `None` for `=`, the operator in front of the `=` otherwise
+    pub op: Option<InfixOperator>,
+    pub lhs: Box<Expression>,
+    pub rhs: Box<Expression>,
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum Declaration {
-    ConstDeclaration(Identifier, Expression),
-    LetDeclaration(Identifier, Expression),
+    /// `const name = value;`, the flag is whether it's `pub`, see `is_pub` of functions. The
+    /// attributes are the `#[name]`s in front of it, like `#[allow(unused)]`, and the type is the
+    /// one of `const name: type = value;`
+    ConstDeclaration(
+        Identifier,
+        Expression,
+        bool,
+        Vec<Identifier>,
+        Option<Identifier>,
+    ),
+    /// `let name = value;` with the attributes in front of it and the type of
+    /// `let name: type = value;`
+    LetDeclaration(Identifier, Expression, Vec<Identifier>, Option<Identifier>),
+    /// `let (a, b) = value;` with the attributes in front of it, declares the names the pattern
+    /// binds to the parts of the value. The pattern has to match every value of its type, and
+    /// `let _ = value;` binds nothing. The type is the one of `let (a, b): type = value;`
+    DestructuringDeclaration(Pattern, Expression, Vec<Identifier>, Option<Identifier>),
     FunctionDeclaration {
+        /// `#[name]` attributes in front of the function, like `#[wasm]`
+        attributes: Vec<Identifier>,
+        /// `pub` in front of a top level function, which exports it from the compiled javascript
+        /// module. Items of a `mod` block use `is_pub` of their `ModItem` instead
+        is_pub: bool,
+        /// `async fn`, which returns a promise of its return type and can use `.await`
+        is_async: bool,
+        /// `const fn`, which the initializers of constants can call. Its body can only call other
+        /// `const fn`s, see `consteval.rs`
+        is_const: bool,
         name: Identifier,
+        /// `<T: Ord>` after the name, which the types of the parameters can use
+        type_parameters: Vec<TypeParameter>,
         parameters: Vec<Parameter>,
+        return_type: Option<Identifier>,
+        /// `where T: Ord + Display` after the return type, more bounds for the type parameters
+        where_clause: Vec<TypeParameter>,
         body: Block,
     },
+    StructDeclaration(StructDecl),
+    EnumDeclaration(EnumDecl),
+    ImplDeclaration(ImplDecl),
+    ModDeclaration(ModDecl),
+    /// `mod name;`, the module in the file `name.os` next to this one. Every top level item of
+    /// the file is visible to the module declaring it
+    ModFileDeclaration(Identifier),
+    UseDeclaration(UseDecl),
+    TypeDeclaration(TypeDecl),
+    ExternDeclaration(ExternDecl),
+    TraitDeclaration(TraitDecl),
+}
+
+/// `type Config = { host: string, port: number };`, the shape of plain objects like parsed JSON.
+/// Values are checked against it by their fields instead of by name, and it has no values of its
+/// own in the compiled javascript. `type Method = "GET" | "POST";` is another name for a type
+/// instead, with no fields
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TypeDecl {
+    pub name: Identifier,
+    pub fields: Vec<Field>,
+    pub alias: Option<Identifier>,
+}
+
+/// `extern fn load_config(path: string) -> Config;`, a function the javascript environment
+/// provides. Calls of it are checked against its parameters
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ExternDecl {
+    pub name: Identifier,
+    pub parameters: Vec<Parameter>,
+    pub return_type: Option<Identifier>,
+}
+
+/// `trait Ord { fn cmp(self, other: Self) -> number; }`, the methods a type has to have to
+/// implement the trait with `impl Ord for Point { ... }`
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TraitDecl {
+    pub name: Identifier,
+    pub methods: Vec<MethodSignature>,
+}
+
+impl TraitDecl {
+    /// Whether any method of the trait has a default body
+    pub fn has_defaults(&self) -> bool {
+        self.methods.iter().any(|method| method.default.is_some())
+    }
+
+    /// The methods with a default body, which backends emit once as functions of the trait
+    pub fn defaults(&self) -> Vec<Method> {
+        self.methods
+            .iter()
+            .filter_map(|signature| {
+                Some(Method {
+                    name: signature.name.clone(),
+                    has_self: signature.has_self,
+                    is_async: false,
+                    parameters: signature.parameters.clone(),
+                    return_type: signature.return_type.clone(),
+                    body: signature.default.clone()?,
+                    span: signature.span,
+                })
+            })
+            .collect()
+    }
+
+    /// The default methods an impl of the trait doesn't override
+    pub fn inherited<'a>(&'a self, decl: &ImplDecl) -> Vec<&'a MethodSignature> {
+        self.methods
+            .iter()
+            .filter(|signature| {
+                signature.default.is_some()
+                    && !decl.items.iter().any(|item| match item {
+                        ImplItem::Method(method) => method.name == signature.name,
+                        _ => false,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A method of a trait. Impls that don't define a method with a default body inherit it
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MethodSignature {
+    pub name: Identifier,
+    pub has_self: bool,
+    pub parameters: Vec<Parameter>,
+    pub return_type: Option<Identifier>,
+    pub default: Option<Block>,
+    pub span: Span,
+}
+
+/// `T` or `T: Ord + Display` in the type parameters of a function, the type arguments have to
+/// implement every trait of the bounds
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TypeParameter {
+    pub name: Identifier,
+    pub bounds: Vec<Identifier>,
+}
+
+impl TypeParameter {
+    /// The type parameters of a function with the bounds of its where clause added to their own
+    pub fn with_where_clause(
+        type_parameters: &[TypeParameter],
+        where_clause: &[TypeParameter],
+    ) -> Vec<TypeParameter> {
+        let mut bounded = type_parameters.to_vec();
+        for predicate in where_clause {
+            if let Some(type_parameter) = bounded
+                .iter_mut()
+                .find(|type_parameter| type_parameter.name == predicate.name)
+            {
+                type_parameter
+                    .bounds
+                    .extend(predicate.bounds.iter().cloned());
+            }
+        }
+        bounded
+    }
+}
+
+impl Declaration {
+    /// Whether the declaration is a top level `pub` function, constant or struct
+    pub fn is_pub(&self) -> bool {
+        match self {
+            Declaration::FunctionDeclaration { is_pub, .. }
+            | Declaration::ConstDeclaration(_, _, is_pub, _, _) => *is_pub,
+            Declaration::StructDeclaration(decl) => decl.is_pub,
+            _ => false,
+        }
+    }
+}
+
+/// `use module::item;` or `use module::{item, item};`, makes items of a module available without
+/// their path
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct UseDecl {
+    /// The segments in front of the items, the first one names a module
+    pub path: Vec<Identifier>,
+    pub items: Vec<Identifier>,
+}
+
+/// `mod name { ... }`, only items marked with `pub` are visible outside of the module
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ModDecl {
+    pub name: Identifier,
+    pub items: Vec<ModItem>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ModItem {
+    pub is_pub: bool,
+    pub declaration: Declaration,
+    pub span: Span,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct StructDecl {
+    pub name: Identifier,
+    /// `<A, B>` after the name, which the types of the fields can use
+    pub type_parameters: Vec<Identifier>,
+    pub fields: Vec<Field>,
+    /// Traits from `#[derive(Serialize, Deserialize)]` in front of the struct
+    pub derives: Vec<Identifier>,
+    /// `pub` in front of a top level struct, see `is_pub` of functions
+    pub is_pub: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Field {
+    pub name: Identifier,
+    pub type_: Identifier,
+    pub span: Span,
+}
+
+/// `enum Shape { Circle(number), Rect(number, number), Empty }`
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct EnumDecl {
+    pub name: Identifier,
+    /// `<T>` after the name, which the types of the variants can use
+    pub type_parameters: Vec<Identifier>,
+    pub variants: Vec<Variant>,
+    /// Whether the enum has `#[derive(Flags)]`, which makes its values bits that can be combined
+    pub flags: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Variant {
+    pub name: Identifier,
+    /// The types of the values the variant holds, empty for variants without a payload
+    pub fields: Vec<Identifier>,
+    /// The number or string after `=` in `Ok = 200`
+    pub discriminant: Option<Literal>,
+    pub span: Span,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ImplDecl {
+    pub target: Identifier,
+    /// `Ord` in `impl Ord for Point { ... }`
+    pub trait_: Option<Identifier>,
+    pub items: Vec<ImplItem>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ImplItem {
+    Method(Method),
+    /// `get name() { ... }`, always takes `self` and no parameters
+    Getter(Method),
+    /// `set name(value: type) { ... }`, always takes `self` and exactly one parameter
+    Setter(Method),
+    /// `const NAME = value;`
+    Const(Identifier, Expression),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Method {
+    pub name: Identifier,
+    /// Whether the method takes `self` as its first parameter
+    pub has_self: bool,
+    /// `async fn`, see `is_async` of functions
+    pub is_async: bool,
+    pub parameters: Vec<Parameter>,
+    pub return_type: Option<Identifier>,
+    pub body: Block,
+    pub span: Span,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -94,12 +694,69 @@ pub struct ElseIfExpr {
 pub struct Block {
     pub statements: Vec<Statement>,
     pub return_value: Option<Expression>,
+    pub span: Span,
+}
+
+/// A block split at its first `defer`, see [`Block::split_at_defer`]
+pub struct Deferred {
+    /// The statements before the `defer`, and the functions declared after it
+    pub before: Vec<Statement>,
+    /// The body of the `defer`
+    pub cleanup: Block,
+    /// The rest of the block, after which `cleanup` runs
+    pub rest: Block,
+}
+
+impl Block {
+    /// Splits the block at its first `defer` statement. Functions declared after it are moved in
+    /// front of it, as they are in scope in the whole block
+    pub fn split_at_defer(&self) -> Option<Deferred> {
+        let index = self.statements.iter().position(|statement| {
+            matches!(
+                statement,
+                Statement::ExpressionStatement {
+                    expression: Expression::DeferExpression(_, _),
+                    ..
+                }
+            )
+        })?;
+        let Statement::ExpressionStatement {
+            expression: Expression::DeferExpression(cleanup, _),
+            ..
+        } = &self.statements[index]
+        else {
+            unreachable!("the statement was found as a `defer`");
+        };
+        let (functions, rest): (Vec<_>, Vec<_>) = self.statements[index + 1..]
+            .iter()
+            .cloned()
+            .partition(|statement| {
+                matches!(
+                    statement,
+                    Statement::DeclarationStatement(Declaration::FunctionDeclaration { .. }, _)
+                )
+            });
+        Some(Deferred {
+            before: self.statements[..index]
+                .iter()
+                .cloned()
+                .chain(functions)
+                .collect(),
+            cleanup: (**cleanup).clone(),
+            rest: Block {
+                statements: rest,
+                return_value: self.return_value.clone(),
+                span: self.span,
+            },
+        })
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct Parameter {
     pub name: Identifier,
     pub type_: Identifier,
+    pub span: Span,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -109,11 +766,58 @@ pub enum Literal {
     BooleanLiteral(bool),
 }
 
+/// A number literal. Integers that fit into an `i32` keep the base they are written in, every
+/// other number is the decimal text of its value, like javascript writes it
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum Number {
     F(String),
     I { base: NumberBase, value: i32 },
 }
+impl FromStr for Number {
+    type Err = String;
+
+    /// Parses the text of a number token, `1_000`, `1.5`, `1e9`, `0xFF`, `0b1010` or `0o17`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let digits = text.replace('_', "");
+        let prefixed = [
+            ("0x", NumberBase::Hex, 16),
+            ("0b", NumberBase::Bin, 2),
+            ("0o", NumberBase::Oct, 8),
+        ]
+        .into_iter()
+        .find_map(|(prefix, base, radix)| Some((digits.strip_prefix(prefix)?, base, radix)));
+        if let Some((digits, base, radix)) = prefixed {
+            let value = u64::from_str_radix(digits, radix)
+                .map_err(|_| format!("`{}` doesn't fit into a number", text))?;
+            return Ok(match i32::try_from(value) {
+                Ok(value) => Number::I { base, value },
+                Err(_) => Number::F((value as f64).to_string()),
+            });
+        }
+        if let Ok(value) = digits.parse::<i32>() {
+            return Ok(Number::I {
+                base: NumberBase::Dec,
+                value,
+            });
+        }
+        let value = digits
+            .parse::<f64>()
+            .map_err(|_| format!("`{}` isn't a number", text))?;
+        if !value.is_finite() {
+            return Err(format!("`{}` doesn't fit into a number", text));
+        }
+        // `1e9` is an integer, but `1.0` is written as a float on purpose
+        Ok(match digits.contains(['e', 'E']) {
+            true if value.fract() == 0.0 && value.abs() <= i32::MAX as f64 => Number::I {
+                base: NumberBase::Dec,
+                value: value as i32,
+            },
+            true => Number::F(value.to_string()),
+            false if digits.contains('.') => Number::F(digits),
+            false => Number::F(value.to_string()),
+        })
+    }
+}
 impl Display for Number {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -168,6 +872,8 @@ pub enum InfixOperator {
     Modulo,
     LogicalOr,
     LogicalAnd,
+    /// `??`, the right side if the left side is `None`
+    NullishCoalesce,
     BitwiseOr,
     BitwiseXor,
     BitwiseAnd,
@@ -175,12 +881,57 @@ pub enum InfixOperator {
     BitwiseRightShift,
 }
 
+/// The largest integer every backend represents exactly, `Number.MAX_SAFE_INTEGER` in javascript
+pub const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+impl InfixOperator {
+    /// The operator of the prelude functions `checked_add`, `checked_sub`, `checked_mul` and
+    /// `checked_div`, which return `None` instead of a result beyond [`MAX_SAFE_INTEGER`]
+    pub fn checked(name: &str) -> Option<InfixOperator> {
+        match name {
+            "checked_add" => Some(InfixOperator::Plus),
+            "checked_sub" => Some(InfixOperator::Minus),
+            "checked_mul" => Some(InfixOperator::Multiply),
+            "checked_div" => Some(InfixOperator::Divide),
+            _ => None,
+        }
+    }
+
+    /// How the operator is written, `+` or `<<`
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            InfixOperator::Equal => "==",
+            InfixOperator::NotEqual => "!=",
+            InfixOperator::GreaterThan => ">",
+            InfixOperator::LessThan => "<",
+            InfixOperator::GreaterThanEqual => ">=",
+            InfixOperator::LessThanEqual => "<=",
+            InfixOperator::Plus => "+",
+            InfixOperator::Minus => "-",
+            InfixOperator::Multiply => "*",
+            InfixOperator::Divide => "/",
+            InfixOperator::Modulo => "%",
+            InfixOperator::LogicalOr => "||",
+            InfixOperator::LogicalAnd => "&&",
+            InfixOperator::NullishCoalesce => "??",
+            InfixOperator::BitwiseOr => "|",
+            InfixOperator::BitwiseXor => "^",
+            InfixOperator::BitwiseAnd => "&",
+            InfixOperator::BitwiseLeftShift => "<<",
+            InfixOperator::BitwiseRightShift => ">>",
+        }
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct Identifier(pub String);
 
 #[derive(Clone, Eq, PartialEq, Debug, PartialOrd)]
 pub enum Precedence {
     PLowest,
+    PAssign,       // =, +=, -=, *=, /=, %=
+    PRange,        // .., ..=
+    PNullish,      // ??
     PLogicalOr,    // ||
     PLogicalAnd,   // &&
     PBitwiseOr,    // |
@@ -191,6 +942,7 @@ pub enum Precedence {
     PBitwiseShift, // <<, >>
     PSum,          // +, -
     PProduct,      // *, /, %
+    PCast,         // as
     PCall,         // function call e.g. foo()
     PMemberAccess, // e.g. foo.bar
     PIndex,        // e.g. foo[0]
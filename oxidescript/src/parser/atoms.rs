@@ -1,13 +1,13 @@
 use crate::lexer::token::Token;
 use crate::lexer::tokens::Tokens;
-use nom::bytes::complete::take;
 use nom::combinator::verify;
 use nom::IResult;
 
 use super::ast::{InfixOperator, Precedence};
+use super::take_token;
 
 fn take1(input: Tokens) -> IResult<Tokens, Tokens> {
-    take(1usize)(input)
+    take_token(input)
 }
 
 macro_rules! tag_token (
@@ -25,10 +25,35 @@ tag_token!(let_tag, Token::Let);
 tag_token!(const_tag, Token::Const);
 tag_token!(function_tag, Token::Function);
 tag_token!(return_tag, Token::Return);
+tag_token!(break_tag, Token::Break);
+tag_token!(continue_tag, Token::Continue);
+tag_token!(defer_tag, Token::Defer);
+tag_token!(with_tag, Token::With);
+tag_token!(async_tag, Token::Async);
+tag_token!(await_tag, Token::Await);
 tag_token!(if_tag, Token::If);
 tag_token!(else_tag, Token::Else);
 tag_token!(for_tag, Token::For);
+tag_token!(while_tag, Token::While);
 tag_token!(in_tag, Token::In);
+tag_token!(struct_tag, Token::Struct);
+tag_token!(enum_tag, Token::Enum);
+tag_token!(impl_tag, Token::Impl);
+tag_token!(mod_tag, Token::Mod);
+tag_token!(pub_tag, Token::Pub);
+tag_token!(import_tag, Token::Import);
+tag_token!(self_tag, Token::Ident("self"));
+tag_token!(get_tag, Token::Ident("get"));
+tag_token!(set_tag, Token::Ident("set"));
+tag_token!(match_tag, Token::Ident("match"));
+tag_token!(as_tag, Token::Ident("as"));
+tag_token!(is_tag, Token::Ident("is"));
+tag_token!(use_tag, Token::Ident("use"));
+tag_token!(type_tag, Token::Ident("type"));
+tag_token!(extern_tag, Token::Ident("extern"));
+tag_token!(trait_tag, Token::Ident("trait"));
+tag_token!(where_tag, Token::Ident("where"));
+tag_token!(dyn_tag, Token::Ident("dyn"));
 
 tag_token!(assign_tag, Token::Assign);
 tag_token!(plus_tag, Token::Plus);
@@ -64,11 +89,32 @@ tag_token!(r_squirly_tag, Token::RSquirly);
 
 tag_token!(comma_tag, Token::Comma);
 tag_token!(colon_tag, Token::Colon);
+tag_token!(double_colon_tag, Token::DoubleColon);
 tag_token!(period_tag, Token::Period);
+tag_token!(double_period_tag, Token::DoublePeriod);
+tag_token!(double_period_equal_tag, Token::DoublePeriodEqual);
+tag_token!(question_period_tag, Token::QuestionPeriod);
+tag_token!(question_bracket_tag, Token::QuestionBracket);
+tag_token!(arrow_tag, Token::Arrow);
+tag_token!(fat_arrow_tag, Token::FatArrow);
+tag_token!(hash_tag, Token::Hash);
 
 tag_token!(semicolon_tag, Token::SemiColon);
 tag_token!(eof_tag, Token::EOF);
 
+/// `Some(None)` for `=`, `Some(Some(InfixOperator::Plus))` for `+=` and so on
+pub fn assignment_operator(token: &Token) -> Option<Option<InfixOperator>> {
+    match token {
+        Token::Assign => Some(None),
+        Token::PlusAssign => Some(Some(InfixOperator::Plus)),
+        Token::MinusAssign => Some(Some(InfixOperator::Minus)),
+        Token::MultiplyAssign => Some(Some(InfixOperator::Multiply)),
+        Token::DivideAssign => Some(Some(InfixOperator::Divide)),
+        Token::ModuloAssign => Some(Some(InfixOperator::Modulo)),
+        _ => None,
+    }
+}
+
 pub fn infix_operator(token: &Token) -> (Precedence, Option<InfixOperator>) {
     match token {
         Token::Equal => (Precedence::PEquals, Some(InfixOperator::Equal)),
@@ -87,6 +133,7 @@ pub fn infix_operator(token: &Token) -> (Precedence, Option<InfixOperator>) {
         Token::Modulo => (Precedence::PProduct, Some(InfixOperator::Modulo)),
         Token::LogicalAnd => (Precedence::PLogicalAnd, Some(InfixOperator::LogicalAnd)),
         Token::LogicalOr => (Precedence::PLogicalOr, Some(InfixOperator::LogicalOr)),
+        Token::NullishCoalesce => (Precedence::PNullish, Some(InfixOperator::NullishCoalesce)),
         Token::BitwiseAnd => (Precedence::PBitwiseAnd, Some(InfixOperator::BitwiseAnd)),
         Token::BitwiseOr => (Precedence::PBitwiseOr, Some(InfixOperator::BitwiseOr)),
         Token::BitwiseXor => (Precedence::PBitwiseXor, Some(InfixOperator::BitwiseXor)),
@@ -98,9 +145,17 @@ pub fn infix_operator(token: &Token) -> (Precedence, Option<InfixOperator>) {
             Precedence::PBitwiseShift,
             Some(InfixOperator::BitwiseRightShift),
         ),
-        Token::LParen => (Precedence::PCall, None),
-        Token::Period => (Precedence::PMemberAccess, None),
-        Token::LBracket => (Precedence::PIndex, None),
+        Token::Assign
+        | Token::PlusAssign
+        | Token::MinusAssign
+        | Token::MultiplyAssign
+        | Token::DivideAssign
+        | Token::ModuloAssign => (Precedence::PAssign, None),
+        Token::DoublePeriod | Token::DoublePeriodEqual => (Precedence::PRange, None),
+        Token::Ident("as") | Token::Ident("is") => (Precedence::PCast, None),
+        Token::LParen | Token::Template(_) => (Precedence::PCall, None),
+        Token::Period | Token::QuestionPeriod => (Precedence::PMemberAccess, None),
+        Token::LBracket | Token::QuestionBracket => (Precedence::PIndex, None),
         _ => (Precedence::PLowest, None),
     }
 }
@@ -1,26 +1,40 @@
 use nom::{
     branch::alt,
-    combinator::map,
-    sequence::{terminated, tuple},
+    combinator::{map, opt, verify},
+    multi::{many0, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
 use crate::lexer::tokens::Tokens;
 
 use super::{
-    ast::Declaration,
+    ast::{Declaration, Expression, Identifier, Pattern, TypeParameter},
     atoms::*,
+    enums::parse_enum_declaration,
     expression::parse_expression,
-    function::{parse_block, parse_parameters},
+    function::{
+        parse_block, parse_destructured_parameters, parse_return_type, parse_type,
+        with_destructurings,
+    },
+    module::{parse_mod_declaration, parse_use_declaration},
     parse_identifier,
+    pattern::parse_destructuring_pattern,
+    structs::{parse_impl_declaration, parse_struct_declaration},
 };
 
 pub fn parse_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
     // println!("parse_declaration");
     alt((
         parse_let_declaration,
+        parse_destructuring_declaration,
         parse_const_declaration,
         parse_function_declaration,
+        parse_struct_declaration,
+        parse_enum_declaration,
+        parse_impl_declaration,
+        parse_mod_declaration,
+        parse_use_declaration,
     ))(input)
 }
 
@@ -28,10 +42,15 @@ fn parse_const_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
     // println!("parse_const_declaration");
     terminated(
         map(
-            tuple((const_tag, parse_identifier, assign_tag, parse_expression)),
-            |(_, name, _, expression)| {
+            tuple((
+                many0(parse_attribute),
+                const_tag,
+                parse_identifier,
+                parse_annotated_value,
+            )),
+            |(attributes, _, name, (type_, expression))| {
                 // dbg!(&name, &expression);
-                Declaration::ConstDeclaration(name, expression)
+                Declaration::ConstDeclaration(name, expression, false, attributes, type_)
             },
         ),
         semicolon_tag,
@@ -42,36 +61,189 @@ fn parse_let_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
     // println!("parse_let_declaration");
     terminated(
         map(
-            tuple((let_tag, parse_identifier, assign_tag, parse_expression)),
-            |(_, name, _, expression)| {
+            tuple((
+                many0(parse_attribute),
+                let_tag,
+                // `let _ = value;` is a destructuring
+                verify(parse_identifier, |name: &Identifier| name.0 != "_"),
+                parse_annotated_value,
+            )),
+            |(attributes, _, name, (type_, expression))| {
                 // dbg!(&name, &expression);
-                Declaration::LetDeclaration(name, expression)
+                Declaration::LetDeclaration(name, expression, attributes, type_)
             },
         ),
         semicolon_tag,
     )(input)
 }
 
+/// `let (a, _) = value;` or `let Point { x, y } = value;`, where the pattern has to match every
+/// value, so it can't contain literals or variants, or `let _ = value;`, which evaluates the value
+/// and drops it
+fn parse_destructuring_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    terminated(
+        map(
+            tuple((
+                many0(parse_attribute),
+                let_tag,
+                verify(parse_destructuring_pattern, |pattern: &Pattern| {
+                    !matches!(pattern, Pattern::Binding(_))
+                }),
+                parse_annotated_value,
+            )),
+            |(attributes, _, pattern, (type_, expression))| {
+                Declaration::DestructuringDeclaration(pattern, expression, attributes, type_)
+            },
+        ),
+        semicolon_tag,
+    )(input)
+}
+
+/// `= value` or `: type = value`, with the type the value is annotated with
+fn parse_annotated_value(input: Tokens) -> IResult<Tokens, (Option<Identifier>, Expression)> {
+    map(
+        tuple((
+            opt(preceded(colon_tag, parse_type)),
+            assign_tag,
+            parse_expression,
+        )),
+        |(type_, _, value)| (type_, value),
+    )(input)
+}
+
 fn parse_function_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
     // println!("parse_function_declaration");
     map(
         tuple((
+            many0(parse_attribute),
+            // `async` or `const`, which can't be combined
+            opt(alt((map(async_tag, |_| true), map(const_tag, |_| false)))),
             function_tag,
             parse_identifier,
+            parse_function_type_parameters,
             l_paren_tag,
-            parse_parameters,
+            parse_destructured_parameters,
             r_paren_tag,
+            parse_return_type,
+            parse_where_clause,
             l_squirly_tag,
             parse_block,
             r_squirly_tag,
         )),
-        |(_, name, _, parameters, _, _, body, _)| {
+        |(
+            attributes,
+            qualifier,
+            _,
+            name,
+            type_parameters,
+            _,
+            (parameters, destructurings),
+            _,
+            return_type,
+            where_clause,
+            _,
+            body,
+            _,
+        )| {
             // dbg!(&name, &parameters, &body);
             Declaration::FunctionDeclaration {
+                attributes,
+                is_pub: false,
+                is_async: qualifier == Some(true),
+                is_const: qualifier == Some(false),
                 name,
+                type_parameters,
                 parameters,
-                body,
+                return_type,
+                where_clause,
+                body: with_destructurings(body, destructurings),
             }
         },
     )(input)
 }
+
+/// `<T, U: Ord + Display>` after the name of a function, or nothing
+fn parse_function_type_parameters(input: Tokens) -> IResult<Tokens, Vec<TypeParameter>> {
+    map(
+        opt(delimited(
+            less_than_tag,
+            terminated(
+                separated_list1(comma_tag, parse_type_parameter),
+                opt(comma_tag),
+            ),
+            greater_than_tag,
+        )),
+        Option::unwrap_or_default,
+    )(input)
+}
+
+fn parse_type_parameter(input: Tokens) -> IResult<Tokens, TypeParameter> {
+    map(
+        pair(
+            parse_identifier,
+            opt(preceded(
+                colon_tag,
+                separated_list1(plus_tag, parse_identifier),
+            )),
+        ),
+        |(name, bounds)| TypeParameter {
+            name,
+            bounds: bounds.unwrap_or_default(),
+        },
+    )(input)
+}
+
+/// `where T: Ord, U: Ord + Display` in front of the body of a function, or nothing
+fn parse_where_clause(input: Tokens) -> IResult<Tokens, Vec<TypeParameter>> {
+    map(
+        opt(preceded(
+            where_tag,
+            terminated(
+                separated_list1(
+                    comma_tag,
+                    map(
+                        separated_pair(
+                            parse_identifier,
+                            colon_tag,
+                            separated_list1(plus_tag, parse_identifier),
+                        ),
+                        |(name, bounds)| TypeParameter { name, bounds },
+                    ),
+                ),
+                opt(comma_tag),
+            ),
+        )),
+        Option::unwrap_or_default,
+    )(input)
+}
+
+/// `#[name]` or `#[name(argument, ...)]`, which is the identifier `name(argument, ...)`. Derives
+/// are parsed by `parse_derive` instead
+pub fn parse_attribute(input: Tokens) -> IResult<Tokens, Identifier> {
+    delimited(
+        pair(hash_tag, l_bracket_tag),
+        map(
+            pair(
+                verify(parse_identifier, |name: &Identifier| name.0 != "derive"),
+                opt(delimited(
+                    l_paren_tag,
+                    separated_list1(comma_tag, parse_identifier),
+                    r_paren_tag,
+                )),
+            ),
+            |(name, arguments)| match arguments {
+                Some(arguments) => Identifier(format!(
+                    "{}({})",
+                    name.0,
+                    arguments
+                        .iter()
+                        .map(|argument| argument.0.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+                None => name,
+            },
+        ),
+        r_bracket_tag,
+    )(input)
+}
@@ -0,0 +1,98 @@
+//! `with name = value { ... }` blocks, which close their value when they are left
+
+use nom::combinator::map;
+use nom::sequence::{delimited, tuple};
+use nom::IResult;
+
+use crate::lexer::tokens::Tokens;
+
+use super::{
+    ast::{
+        Block, CallExpr, Declaration, Expression, Identifier, MemberAccessExpr, Program, Span,
+        Statement, WithExpr,
+    },
+    atoms::*,
+    expression::parse_expression,
+    function::parse_block,
+    parse_identifier,
+    visit::walk_program_mut,
+};
+
+/// `with name = value { ... }`
+pub fn parse_with_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(
+        tuple((
+            with_tag,
+            parse_identifier,
+            assign_tag,
+            parse_expression,
+            delimited(l_squirly_tag, parse_block, r_squirly_tag),
+        )),
+        |(_with, name, _assign, value, body)| {
+            Expression::WithExpression(
+                WithExpr {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                },
+                Span::default(),
+            )
+        },
+    )(input)
+}
+
+impl WithExpr {
+    /// The block the `with` is short for, `{ let name = value; defer { name.close(); } ... }`
+    pub fn block(&self) -> Block {
+        let name = Expression::IdentifierExpression(self.name.clone(), self.body.span);
+        let close = Expression::CallExpression(
+            CallExpr {
+                lhs: Box::new(Expression::MemberAccessExpression(
+                    MemberAccessExpr {
+                        lhs: Box::new(name),
+                        ident: Identifier("close".to_string()),
+                    },
+                    self.body.span,
+                )),
+                arguments: vec![],
+            },
+            self.body.span,
+        );
+        let cleanup = Block {
+            statements: vec![Statement::ExpressionStatement {
+                expression: close,
+                has_semicolon: true,
+                span: self.body.span,
+            }],
+            return_value: None,
+            span: self.body.span,
+        };
+        let mut statements = vec![
+            Statement::DeclarationStatement(
+                Declaration::LetDeclaration(self.name.clone(), (*self.value).clone(), vec![], None),
+                self.value.span(),
+            ),
+            Statement::ExpressionStatement {
+                expression: Expression::DeferExpression(Box::new(cleanup), self.body.span),
+                has_semicolon: false,
+                span: self.body.span,
+            },
+        ];
+        statements.extend(self.body.statements.iter().cloned());
+        Block {
+            statements,
+            return_value: self.body.return_value.clone(),
+            span: self.body.span,
+        }
+    }
+}
+
+/// Replaces every `with` with the block it is short for, for backends that compile `defer`
+pub fn lower_with(mut program: Program) -> Program {
+    walk_program_mut(&mut program, &mut |expression| {
+        if let Expression::WithExpression(expr, span) = expression {
+            *expression = Expression::BlockExpression(Box::new(expr.block()), *span);
+        }
+    });
+    program
+}
@@ -0,0 +1,963 @@
+use std::collections::HashMap;
+
+use nom::combinator::{map, opt, verify};
+use nom::multi::{many0, separated_list0, separated_list1};
+use nom::sequence::{delimited, preceded, tuple};
+use nom::IResult;
+
+use crate::cancellation::{CancellationToken, Cancelled};
+use crate::lexer::tokens::Tokens;
+
+use super::ast::{
+    Block, Declaration, EnumDecl, Expression, Field, Identifier, ImplDecl, ImplItem, InfixExpr,
+    InfixOperator, Literal, MatchExpr, Method, Number, NumberBase, Parameter, Pattern, Program,
+    Span, Statement, StructDecl, StructExpr, Variant,
+};
+use super::types::TypeError;
+use super::visit::{walk_declaration, walk_declaration_mut, walk_statement_mut};
+use super::{
+    atoms::*,
+    function::parse_type,
+    parse_identifier, parse_literal, spanned,
+    structs::{parse_derive, parse_type_parameters},
+    types::substitute,
+};
+
+/// `enum Name { Variant, Variant(type, ...), Variant = 200, ... }`. Only enums without payloads
+/// or type parameters can have discriminants, and each one has to be different. Enums with
+/// `#[derive(Flags)]` can't have payloads either, and their discriminants have to be integers
+pub fn parse_enum_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    map(
+        verify(
+            tuple((
+                many0(verify(parse_derive, |derives: &Vec<Identifier>| {
+                    derives.iter().all(|derive| derive.0 == "Flags")
+                })),
+                enum_tag,
+                parse_identifier,
+                parse_type_parameters,
+                l_squirly_tag,
+                separated_list0(comma_tag, parse_variant),
+                opt(comma_tag),
+                r_squirly_tag,
+            )),
+            |(derives, _, _, type_parameters, _, variants, _, _)| {
+                let flags = !derives.is_empty();
+                (!flags
+                    && variants
+                        .iter()
+                        .all(|variant| variant.discriminant.is_none()))
+                    || (type_parameters.is_empty()
+                        && variants.iter().all(|variant| variant.fields.is_empty())
+                        && discriminants(variants, flags).is_some())
+            },
+        ),
+        |(derives, _, name, type_parameters, _, variants, _, _)| {
+            Declaration::EnumDeclaration(EnumDecl {
+                name,
+                type_parameters,
+                variants,
+                flags: !derives.is_empty(),
+            })
+        },
+    )(input)
+}
+
+fn parse_variant(input: Tokens) -> IResult<Tokens, Variant> {
+    map(
+        spanned(tuple((
+            parse_identifier,
+            opt(delimited(
+                l_paren_tag,
+                separated_list1(comma_tag, parse_type),
+                tuple((opt(comma_tag), r_paren_tag)),
+            )),
+            opt(preceded(
+                assign_tag,
+                verify(parse_literal, |literal| {
+                    !matches!(literal, Literal::BooleanLiteral(_))
+                }),
+            )),
+        ))),
+        |((name, fields, discriminant), span)| Variant {
+            name,
+            fields: fields.unwrap_or_default(),
+            discriminant,
+            span,
+        },
+    )(input)
+}
+
+impl EnumDecl {
+    /// The values of the variants if any of them has a discriminant. Variants without one count
+    /// up from the one in front of them, starting at 0 like in Rust
+    pub fn discriminants(&self) -> Option<Vec<Literal>> {
+        if !self.flags
+            && self
+                .variants
+                .iter()
+                .all(|variant| variant.discriminant.is_none())
+        {
+            return None;
+        }
+        discriminants(&self.variants, self.flags)
+    }
+
+    /// Values of an enum are `Name { tag: "Variant", values: [...] }` structs. A variant with a
+    /// payload gets an associated function that constructs it, one without a payload a constant.
+    /// The type parameters in the parameters of the constructors are `any`.
+    /// Values of enums with discriminants are just their discriminants, flags enums also get
+    /// `empty()`, `all()` and `contains(flags, other)`
+    pub fn lowered(&self) -> (StructDecl, Vec<ImplItem>) {
+        if let Some(discriminants) = self.discriminants() {
+            let decl = StructDecl {
+                name: self.name.clone(),
+                type_parameters: vec![],
+                fields: vec![],
+                derives: vec![],
+                is_pub: false,
+            };
+            let all = discriminants
+                .iter()
+                .map(|discriminant| match discriminant {
+                    Literal::NumberLiteral(Number::I { value, .. }) => *value,
+                    _ => 0,
+                })
+                .fold(0, |all, value| all | value);
+            let mut items = self
+                .variants
+                .iter()
+                .zip(discriminants)
+                .map(|(variant, discriminant)| {
+                    ImplItem::Const(
+                        variant.name.clone(),
+                        Expression::LiteralExpression(discriminant, variant.span),
+                    )
+                })
+                .collect::<Vec<_>>();
+            if self.flags {
+                items.extend(flags_functions(&self.name, all));
+            }
+            return (decl, items);
+        }
+        let decl = StructDecl {
+            name: self.name.clone(),
+            type_parameters: self.type_parameters.clone(),
+            fields: vec![
+                Field {
+                    name: Identifier("tag".to_string()),
+                    type_: Identifier("string".to_string()),
+                    span: Span::default(),
+                },
+                Field {
+                    name: Identifier("values".to_string()),
+                    type_: Identifier("any".to_string()),
+                    span: Span::default(),
+                },
+            ],
+            derives: vec![],
+            is_pub: false,
+        };
+        let items = self
+            .variants
+            .iter()
+            .map(|variant| {
+                let parameters = variant
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .map(|(index, type_)| Parameter {
+                        name: Identifier(format!("value_{}", index)),
+                        type_: Identifier(substitute(&type_.0, &self.type_parameters, &[])),
+                        span: Span::default(),
+                    })
+                    .collect::<Vec<_>>();
+                let value = Expression::StructExpression(
+                    StructExpr {
+                        name: self.name.clone(),
+                        fields: vec![
+                            (
+                                Identifier("tag".to_string()),
+                                Expression::LiteralExpression(
+                                    Literal::StringLiteral(variant.name.0.clone()),
+                                    Span::default(),
+                                ),
+                            ),
+                            (
+                                Identifier("values".to_string()),
+                                Expression::ArrayExpression(
+                                    parameters
+                                        .iter()
+                                        .map(|parameter| {
+                                            Expression::IdentifierExpression(
+                                                parameter.name.clone(),
+                                                Span::default(),
+                                            )
+                                        })
+                                        .collect(),
+                                    Span::default(),
+                                ),
+                            ),
+                        ],
+                    },
+                    variant.span,
+                );
+                if parameters.is_empty() {
+                    ImplItem::Const(variant.name.clone(), value)
+                } else {
+                    ImplItem::Method(Method {
+                        name: variant.name.clone(),
+                        has_self: false,
+                        is_async: false,
+                        parameters,
+                        return_type: Some(self.name.clone()),
+                        body: Block {
+                            statements: vec![],
+                            return_value: Some(value),
+                            span: variant.span,
+                        },
+                        span: variant.span,
+                    })
+                }
+            })
+            .collect();
+        (decl, items)
+    }
+}
+
+/// `empty()`, `all()` and `contains(flags, other)` of a flags enum
+fn flags_functions(name: &Identifier, all: i32) -> Vec<ImplItem> {
+    let function = |function: &str, parameters: &[&str], return_type: &str, value| {
+        ImplItem::Method(Method {
+            name: Identifier(function.to_string()),
+            has_self: false,
+            is_async: false,
+            parameters: parameters
+                .iter()
+                .map(|parameter| Parameter {
+                    name: Identifier(parameter.to_string()),
+                    type_: name.clone(),
+                    span: Span::default(),
+                })
+                .collect(),
+            return_type: Some(Identifier(return_type.to_string())),
+            body: Block {
+                statements: vec![],
+                return_value: Some(value),
+                span: Span::default(),
+            },
+            span: Span::default(),
+        })
+    };
+    let number = |value| {
+        Expression::LiteralExpression(
+            Literal::NumberLiteral(Number::I {
+                base: NumberBase::Dec,
+                value,
+            }),
+            Span::default(),
+        )
+    };
+    let parameter = |name: &str| {
+        Box::new(Expression::IdentifierExpression(
+            Identifier(name.to_string()),
+            Span::default(),
+        ))
+    };
+    vec![
+        function("empty", &[], &name.0, number(0)),
+        function("all", &[], &name.0, number(all)),
+        function(
+            "contains",
+            &["flags", "other"],
+            "bool",
+            Expression::InfixExpression(
+                InfixExpr {
+                    op: InfixOperator::Equal,
+                    lhs: Box::new(Expression::InfixExpression(
+                        InfixExpr {
+                            op: InfixOperator::BitwiseAnd,
+                            lhs: parameter("flags"),
+                            rhs: parameter("other"),
+                        },
+                        Span::default(),
+                    )),
+                    rhs: parameter("other"),
+                },
+                Span::default(),
+            ),
+        ),
+    ]
+}
+
+/// `None` if a variant has no discriminant and can't count up from the one in front of it, or if
+/// two variants have the same one. Variants of flags enums without a discriminant get the lowest
+/// bit that no variant in front of them has as its value. Their discriminants have to be positive
+/// 32 bit integers, which bitwise operators work on in javascript
+pub(super) fn discriminants(variants: &[Variant], flags: bool) -> Option<Vec<Literal>> {
+    let mut discriminants: Vec<Literal> = vec![];
+    for variant in variants {
+        let discriminant = match (&variant.discriminant, discriminants.last()) {
+            (Some(discriminant), _)
+                if flags
+                    && !matches!(
+                        discriminant,
+                        Literal::NumberLiteral(Number::I { value, .. }) if *value >= 0
+                    ) =>
+            {
+                return None
+            }
+            (Some(discriminant), _) => discriminant.clone(),
+            (None, _) if flags => {
+                let value = (0..31).map(|bit| 1 << bit).find(|bit| {
+                    !discriminants.iter().any(|discriminant| {
+                        matches!(
+                            discriminant,
+                            Literal::NumberLiteral(Number::I { value, .. }) if value == bit
+                        )
+                    })
+                })?;
+                Literal::NumberLiteral(Number::I {
+                    base: NumberBase::Dec,
+                    value,
+                })
+            }
+            (None, None) => Literal::NumberLiteral(Number::I {
+                base: NumberBase::Dec,
+                value: 0,
+            }),
+            (None, Some(Literal::NumberLiteral(Number::I { value, .. }))) => {
+                Literal::NumberLiteral(Number::I {
+                    base: NumberBase::Dec,
+                    value: value + 1,
+                })
+            }
+            // there is nothing to count up from after a string or a fraction
+            (None, Some(_)) => return None,
+        };
+        let same = |other: &Literal| match (other, &discriminant) {
+            (
+                Literal::NumberLiteral(Number::I { value, .. }),
+                Literal::NumberLiteral(Number::I { value: other, .. }),
+            ) => value == other,
+            (other, discriminant) => other == discriminant,
+        };
+        if discriminants.iter().any(same) {
+            return None;
+        }
+        discriminants.push(discriminant);
+    }
+    Some(discriminants)
+}
+
+/// Replaces the enums in a list of statements with their struct and an impl block of the
+/// constructors of their variants, for backends that only know structs
+pub fn lower_enums(statements: &[Statement]) -> Vec<Statement> {
+    statements
+        .iter()
+        .flat_map(|statement| match statement {
+            Statement::DeclarationStatement(Declaration::EnumDeclaration(decl), _) => {
+                let (lowered, constructors) = decl.lowered();
+                vec![
+                    Statement::DeclarationStatement(
+                        Declaration::StructDeclaration(lowered),
+                        Span::default(),
+                    ),
+                    Statement::DeclarationStatement(
+                        Declaration::ImplDeclaration(ImplDecl {
+                            target: decl.name.clone(),
+                            trait_: None,
+                            items: constructors,
+                        }),
+                        Span::default(),
+                    ),
+                ]
+            }
+            statement => vec![statement.clone()],
+        })
+        .collect()
+}
+
+/// Whether a list of statements declares an enum that has to be lowered
+pub fn declares_enum(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| {
+        matches!(
+            statement,
+            Statement::DeclarationStatement(Declaration::EnumDeclaration(_), _)
+        )
+    })
+}
+
+/// Matches over the variants of an enum without an arm matching everything need an arm for every
+/// variant. The parser doesn't know the variants of an enum, so this checks the whole program
+/// after parsing. Only enums declared in the same program can be checked
+pub fn check_enum_matches(program: &Program) -> Result<(), TypeError> {
+    check_enum_matches_with(program, None)
+        .expect("checking without cancellation token was cancelled")
+}
+
+/// Like [`check_enum_matches`], but checks `cancellation` before every top level statement
+pub fn check_enum_matches_cancellable(
+    program: &Program,
+    cancellation: &CancellationToken,
+) -> Result<Result<(), TypeError>, Cancelled> {
+    check_enum_matches_with(program, Some(cancellation))
+}
+
+fn check_enum_matches_with(
+    program: &Program,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Result<(), TypeError>, Cancelled> {
+    let mut enums = HashMap::new();
+    let declarations = program
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::DeclarationStatement(declaration, _) => Some(declaration),
+            Statement::ExpressionStatement { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    collect_enums(&declarations, &mut enums);
+    let mut result = Ok(());
+    for statement in program {
+        if cancellation.is_some_and(|cancellation| cancellation.is_cancelled()) {
+            return Err(Cancelled);
+        }
+        match statement {
+            Statement::DeclarationStatement(declaration, _) => {
+                check_declaration(declaration, &enums, &mut result)
+            }
+            Statement::ExpressionStatement { .. } => {
+                super::visit::walk_statement(statement, &mut |expression| {
+                    check_expression(expression, None, &enums, &mut result)
+                })
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Matching compares the tags of enum values, but the values of enums with discriminants are just
+/// their discriminants, so patterns for their variants become patterns for the discriminants. Like
+/// [`check_enum_matches`], this only knows the enums declared in the program, so it runs after
+/// the check
+pub fn lower_discriminants(mut program: Program) -> Program {
+    let mut enums = HashMap::new();
+    let declarations = program
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::DeclarationStatement(declaration, _) => Some(declaration),
+            Statement::ExpressionStatement { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    collect_enums(&declarations, &mut enums);
+    let discriminants = enums
+        .into_iter()
+        .filter_map(|(name, decl)| {
+            let variants = decl.variants.iter().map(|variant| variant.name.clone());
+            Some((name, variants.zip(decl.discriminants()?).collect()))
+        })
+        .collect::<HashMap<_, Vec<_>>>();
+    if discriminants.is_empty() {
+        return program;
+    }
+    for statement in &mut program {
+        match statement {
+            Statement::DeclarationStatement(declaration, _) => {
+                lower_declaration(declaration, &discriminants)
+            }
+            Statement::ExpressionStatement { .. } => {
+                walk_statement_mut(statement, &mut |expression| {
+                    lower_expression(expression, None, &discriminants)
+                })
+            }
+        }
+    }
+    program
+}
+
+type Discriminants = HashMap<String, Vec<(Identifier, Literal)>>;
+
+fn lower_declaration(declaration: &mut Declaration, discriminants: &Discriminants) {
+    match declaration {
+        Declaration::ModDeclaration(decl) => {
+            for item in &mut decl.items {
+                lower_declaration(&mut item.declaration, discriminants);
+            }
+        }
+        Declaration::ImplDeclaration(decl) => {
+            let self_type = decl.target.clone();
+            walk_declaration_mut(declaration, &mut |expression| {
+                lower_expression(expression, Some(&self_type), discriminants)
+            })
+        }
+        declaration => walk_declaration_mut(declaration, &mut |expression| {
+            lower_expression(expression, None, discriminants)
+        }),
+    }
+}
+
+fn lower_expression(
+    expression: &mut Expression,
+    self_type: Option<&Identifier>,
+    discriminants: &Discriminants,
+) {
+    if let Expression::MatchExpression(expr, _) = expression {
+        for arm in &mut expr.arms {
+            lower_pattern(&mut arm.pattern, self_type, discriminants);
+        }
+    }
+}
+
+fn lower_pattern(
+    pattern: &mut Pattern,
+    self_type: Option<&Identifier>,
+    discriminants: &Discriminants,
+) {
+    match pattern {
+        Pattern::Variant(path, fields) if fields.is_empty() => {
+            let name = match (&path[path.len() - 2], self_type) {
+                (name, Some(self_type)) if name.0 == "Self" => self_type,
+                (name, _) => name,
+            };
+            let discriminant = discriminants.get(&name.0).and_then(|variants| {
+                variants
+                    .iter()
+                    .find(|(variant, _)| *variant == path[path.len() - 1])
+            });
+            if let Some((_, discriminant)) = discriminant {
+                *pattern = Pattern::Literal(discriminant.clone());
+            }
+        }
+        Pattern::Or(patterns) => {
+            for pattern in patterns {
+                lower_pattern(pattern, self_type, discriminants);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_enums<'p>(declarations: &[&'p Declaration], enums: &mut HashMap<String, &'p EnumDecl>) {
+    for declaration in declarations {
+        match declaration {
+            Declaration::EnumDeclaration(decl) => {
+                enums.insert(decl.name.0.clone(), decl);
+            }
+            Declaration::ModDeclaration(decl) => collect_enums(
+                &decl
+                    .items
+                    .iter()
+                    .map(|item| &item.declaration)
+                    .collect::<Vec<_>>(),
+                enums,
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// `Self::Variant` patterns in impl blocks refer to the implemented enum
+fn check_declaration(
+    declaration: &Declaration,
+    enums: &HashMap<String, &EnumDecl>,
+    result: &mut Result<(), TypeError>,
+) {
+    match declaration {
+        Declaration::ModDeclaration(decl) => {
+            for item in &decl.items {
+                check_declaration(&item.declaration, enums, result);
+            }
+        }
+        Declaration::ImplDeclaration(decl) => walk_declaration(declaration, &mut |expression| {
+            check_expression(expression, Some(&decl.target), enums, result)
+        }),
+        declaration => walk_declaration(declaration, &mut |expression| {
+            check_expression(expression, None, enums, result)
+        }),
+    }
+}
+
+fn check_expression(
+    expression: &Expression,
+    self_type: Option<&Identifier>,
+    enums: &HashMap<String, &EnumDecl>,
+    result: &mut Result<(), TypeError>,
+) {
+    if let (Expression::MatchExpression(expr, span), Ok(())) = (expression, &result) {
+        *result = check_match(expr, self_type, enums).map_err(|message| TypeError {
+            message,
+            span: *span,
+        });
+    }
+}
+
+fn check_match(
+    expr: &MatchExpr,
+    self_type: Option<&Identifier>,
+    enums: &HashMap<String, &EnumDecl>,
+) -> Result<(), String> {
+    let mut variants = vec![];
+    for arm in &expr.arms {
+        variant_patterns(&arm.pattern, &mut variants);
+    }
+    let Some((first, _)) = variants.first() else {
+        return Ok(());
+    };
+    let enum_name = |path: &[Identifier]| {
+        let name = &path[path.len() - 2];
+        match self_type {
+            Some(self_type) if name.0 == "Self" => self_type.0.clone(),
+            _ => name.0.clone(),
+        }
+    };
+    let name = enum_name(first);
+    let Some(decl) = enums.get(&name) else {
+        return Err(format!(
+            "the variants of {} are unknown, because it isn't declared in this file",
+            name
+        ));
+    };
+    for (path, fields) in &variants {
+        if enum_name(path) != name {
+            return Err(format!(
+                "a match on {} has an arm for a variant of {}",
+                name,
+                enum_name(path)
+            ));
+        }
+        let variant_name = &path[path.len() - 1];
+        let Some(variant) = decl
+            .variants
+            .iter()
+            .find(|variant| variant.name == *variant_name)
+        else {
+            return Err(format!("{} has no variant {}", name, variant_name.0));
+        };
+        if variant.fields.len() != fields.len() {
+            return Err(format!(
+                "{}::{} holds {} value(s), but the pattern has {}",
+                name,
+                variant_name.0,
+                variant.fields.len(),
+                fields.len()
+            ));
+        }
+    }
+    let unguarded = expr.arms.iter().filter(|arm| arm.guard.is_none());
+    if unguarded.clone().any(|arm| arm.pattern.is_catch_all()) {
+        return Ok(());
+    }
+    if decl.flags {
+        return Err(format!(
+            "a match on {} needs an arm matching everything, since its flags can be combined",
+            name
+        ));
+    }
+    let mut covered = vec![];
+    for arm in unguarded {
+        variant_patterns(&arm.pattern, &mut covered);
+    }
+    let covered = covered
+        .into_iter()
+        .filter(|(_, fields)| fields.iter().all(Pattern::is_catch_all))
+        .map(|(path, _)| &path[path.len() - 1])
+        .collect::<Vec<_>>();
+    match decl
+        .variants
+        .iter()
+        .find(|variant| !covered.contains(&&variant.name))
+    {
+        Some(missing) => Err(format!(
+            "a match on {} has no arm for {}::{}",
+            name, name, missing.name.0
+        )),
+        None => Ok(()),
+    }
+}
+
+fn variant_patterns<'p>(
+    pattern: &'p Pattern,
+    variants: &mut Vec<(&'p [Identifier], &'p [Pattern])>,
+) {
+    match pattern {
+        Pattern::Variant(path, fields) => variants.push((path, fields)),
+        Pattern::Or(patterns) => {
+            for pattern in patterns {
+                variant_patterns(pattern, variants);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    use super::*;
+
+    fn parse(input: &str) -> Result<Program, String> {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        Parser::parse(Tokens::new(&tokens))
+            .map(|(_, program)| program)
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    #[test]
+    fn enum_declarations() {
+        let program =
+            parse("enum Shape { Circle(number), Rect(number, number,), Empty, }").unwrap();
+        let Some(Statement::DeclarationStatement(Declaration::EnumDeclaration(decl), _)) =
+            program.first()
+        else {
+            panic!("expected an enum, got {:?}", program);
+        };
+        assert_eq!(
+            decl.variants,
+            vec![
+                Variant {
+                    name: Identifier("Circle".to_string()),
+                    fields: vec![Identifier("number".to_string())],
+                    discriminant: None,
+                    span: Span::default(),
+                },
+                Variant {
+                    name: Identifier("Rect".to_string()),
+                    fields: vec![
+                        Identifier("number".to_string()),
+                        Identifier("number".to_string())
+                    ],
+                    discriminant: None,
+                    span: Span::default(),
+                },
+                Variant {
+                    name: Identifier("Empty".to_string()),
+                    fields: vec![],
+                    discriminant: None,
+                    span: Span::default(),
+                },
+            ]
+        );
+
+        assert_eq!(
+            lower_enums(&program),
+            parse(
+                r#"struct Shape { tag: string, values: any }
+                impl Shape {
+                    fn Circle(value_0: number) -> Shape { Shape { tag: "Circle", values: [value_0] } }
+                    fn Rect(value_0: number, value_1: number) -> Shape {
+                        Shape { tag: "Rect", values: [value_0, value_1] }
+                    }
+                    const Empty = Shape { tag: "Empty", values: [] };
+                }"#
+            )
+            .unwrap()
+        );
+
+        assert!(parse("enum Shape { Circle() }").is_err());
+    }
+
+    #[test]
+    fn enum_discriminants() {
+        let discriminants = |input: &str| {
+            let program = parse(input)?;
+            let Some(Statement::DeclarationStatement(Declaration::EnumDeclaration(decl), _)) =
+                program.first()
+            else {
+                panic!("expected an enum, got {:?}", program);
+            };
+            Ok::<_, String>(decl.discriminants())
+        };
+        let number = |value| {
+            Literal::NumberLiteral(Number::I {
+                base: NumberBase::Dec,
+                value,
+            })
+        };
+
+        assert_eq!(
+            discriminants("enum Shape { Circle(number), Empty }"),
+            Ok(None)
+        );
+        assert_eq!(
+            discriminants("enum Status { Ok = 200, Created, NotFound = 404 }"),
+            Ok(Some(vec![number(200), number(201), number(404)]))
+        );
+        assert_eq!(
+            discriminants("enum Level { Low, Medium, High = 10, Higher }"),
+            Ok(Some(vec![number(0), number(1), number(10), number(11)]))
+        );
+        assert_eq!(
+            discriminants(r#"enum Method { Get = "GET", Post = "POST" }"#),
+            Ok(Some(vec![
+                Literal::StringLiteral("GET".to_string()),
+                Literal::StringLiteral("POST".to_string())
+            ]))
+        );
+        // payloads, repeated discriminants and nothing to count up from
+        assert!(discriminants("enum Status { Ok = 200, Other(number) }").is_err());
+        assert!(discriminants("enum Status { Ok = 1, Created = 0, Accepted }").is_err());
+        assert!(discriminants(r#"enum Method { Get = "GET", Post }"#).is_err());
+        assert!(discriminants("enum Flag { On = true }").is_err());
+
+        assert_eq!(
+            lower_enums(&parse("enum Status { Ok = 200, NotFound = 404 }").unwrap()),
+            parse("struct Status {} impl Status { const Ok = 200; const NotFound = 404; }")
+                .unwrap()
+        );
+        assert_eq!(
+            lower_discriminants(
+                parse(
+                    "enum Status { Ok = 200, NotFound = 404 }
+                    impl Status { fn f(s: Status) { match s { Self::Ok => 1, _ => 0 } } }
+                    match s { Status::Ok | Status::NotFound => 1, Shape::Empty => 0 }
+                    s as number"
+                )
+                .unwrap()
+            ),
+            parse(
+                "enum Status { Ok = 200, NotFound = 404 }
+                impl Status { fn f(s: Status) { match s { 200 => 1, _ => 0 } } }
+                match s { 200 | 404 => 1, Shape::Empty => 0 }
+                s as number"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn flags() {
+        let discriminants = |input: &str| {
+            let program = parse(input)?;
+            let Some(Statement::DeclarationStatement(Declaration::EnumDeclaration(decl), _)) =
+                program.first()
+            else {
+                panic!("expected an enum, got {:?}", program);
+            };
+            Ok::<_, String>(decl.discriminants())
+        };
+        let numbers = |values: &[i32]| {
+            Some(
+                values
+                    .iter()
+                    .map(|value| {
+                        Literal::NumberLiteral(Number::I {
+                            base: NumberBase::Dec,
+                            value: *value,
+                        })
+                    })
+                    .collect(),
+            )
+        };
+
+        assert_eq!(
+            discriminants("#[derive(Flags)] enum Permissions { Read, Write, Execute }"),
+            Ok(numbers(&[1, 2, 4]))
+        );
+        assert_eq!(
+            discriminants(
+                "#[derive(Flags)] enum Permissions { Read, Write = 4, Execute, All = 7 }"
+            ),
+            Ok(numbers(&[1, 4, 2, 7]))
+        );
+        assert!(discriminants("#[derive(Flags)] enum Permissions { Read(number) }").is_err());
+        assert!(discriminants(r#"#[derive(Flags)] enum Permissions { Read = "r" }"#).is_err());
+        assert!(discriminants("#[derive(Flags)] enum Permissions { Read, Write = 1 }").is_err());
+        assert!(parse("#[derive(Serialize)] enum Permissions { Read }").is_err());
+        assert!(parse("#[derive(Flags)] struct Permissions { read: bool }").is_err());
+
+        assert_eq!(
+            lower_enums(&parse("#[derive(Flags)] enum Mode { Fast, Safe }").unwrap()),
+            parse(
+                "struct Mode {}
+                impl Mode {
+                    const Fast = 1;
+                    const Safe = 2;
+                    fn empty() -> Mode { 0 }
+                    fn all() -> Mode { 3 }
+                    fn contains(flags: Mode, other: Mode) -> bool { (flags & other) == other }
+                }"
+            )
+            .unwrap()
+        );
+
+        let check = |input: &str| {
+            let program = parse(&format!(
+                "#[derive(Flags)] enum Permissions {{ Read, Write }}
+                {}",
+                input
+            ))
+            .unwrap();
+            check_enum_matches(&program).map_err(|err| err.message)
+        };
+        assert!(check("match p { Permissions::Read => 1, _ => 0 }").is_ok());
+        assert_eq!(
+            check("match p { Permissions::Read => 1, Permissions::Write => 2 }"),
+            Err(
+                "a match on Permissions needs an arm matching everything, since its flags can be \
+                 combined"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn enum_matches() {
+        let check =
+            |input: &str| check_enum_matches(&parse(input).unwrap()).map_err(|err| err.message);
+        let shape = "enum Shape { Circle(number), Rect(number, number), Empty }";
+
+        assert!(check(&format!(
+            "{} match s {{ Shape::Circle(r) => r, Shape::Rect(w, _) => w, Shape::Empty => 0 }}",
+            shape
+        ))
+        .is_ok());
+        assert!(check(&format!(
+            "{} impl Shape {{ fn f(self) {{ match self {{ Self::Circle(_) | Self::Empty => 0, _ => 1 }} }} }}",
+            shape
+        ))
+        .is_ok());
+        assert!(check(&format!(
+            "mod shapes {{ pub {} }} match s {{ Shape::Circle(1) => 1, _ => 0 }}",
+            shape
+        ))
+        .is_ok());
+
+        assert_eq!(
+            check(&format!(
+                "{} match s {{ Shape::Circle(1) => 1, Shape::Rect(_, _) => 2, Shape::Empty => 0 }}",
+                shape
+            )),
+            Err("a match on Shape has no arm for Shape::Circle".to_string())
+        );
+        assert_eq!(
+            check(&format!(
+                "{} match s {{ Shape::Circle(r) if r > 1 => 1, Shape::Rect(_, _) | Shape::Empty => 0 }}",
+                shape
+            )),
+            Err("a match on Shape has no arm for Shape::Circle".to_string())
+        );
+        assert_eq!(
+            check(&format!(
+                "{} match s {{ Shape::Square => 1, _ => 0 }}",
+                shape
+            )),
+            Err("Shape has no variant Square".to_string())
+        );
+        assert_eq!(
+            check(&format!(
+                "{} match s {{ Shape::Empty(x) => x, _ => 0 }}",
+                shape
+            )),
+            Err("Shape::Empty holds 0 value(s), but the pattern has 1".to_string())
+        );
+        assert_eq!(
+            check("match s { Color::Red => 1, _ => 0 }"),
+            Err(
+                "the variants of Color are unknown, because it isn't declared in this file"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -4,15 +4,24 @@ use crate::lexer::token::Token;
 use crate::lexer::tokens::Tokens;
 use nom::combinator::opt;
 use nom::error::ErrorKind;
-use nom::multi::many0;
+use nom::multi::{many0, many1, separated_list1};
 use nom::sequence::{delimited, pair, preceded, tuple};
 use nom::Err;
 use nom::{branch::alt, combinator::map, error_position, IResult};
 
-use super::ast::{ElseIfExpr, ForExpr, IfExpr, Precedence, UnaryExpr, UnaryOperator};
-use super::function::parse_block;
+use super::ast::{
+    ElseIfExpr, ForExpr, Identifier, IfExpr, ImportExpr, Literal, PathExpr, Precedence, Span,
+    UnaryExpr, UnaryOperator, WhileExpr,
+};
+use super::dispose::parse_with_expression;
+use super::function::{parse_block, parse_closure_expression, parse_return_expression};
+use super::pattern::parse_match_expression;
 use super::pratt_expression::parse_pratt_expression;
-use super::{ast::Expression, atoms::*, parse_identifier, parse_literal};
+use super::structs::parse_struct_expression;
+use super::template::parse_template_expression;
+use super::{
+    ast::Expression, atoms::*, consumed_span, parse_identifier, parse_identifier_str, parse_literal,
+};
 
 pub fn parse_expression(input: Tokens) -> IResult<Tokens, Expression> {
     parse_pratt_expression(input, Precedence::PLowest)
@@ -29,8 +38,13 @@ pub fn parse_expressions(input: Tokens) -> IResult<Tokens, Vec<Expression>> {
 }
 
 pub fn parse_atom_expression(input: Tokens) -> IResult<Tokens, Expression> {
-    alt((
+    let (rest, mut expression) = alt((
         parse_literal_expression,
+        parse_template_expression,
+        parse_import_expression,
+        parse_struct_expression,
+        parse_path_expression,
+        parse_match_expression,
         parse_identifier_expression,
         parse_unary_expression,
         parse_paren_expression,
@@ -38,21 +52,60 @@ pub fn parse_atom_expression(input: Tokens) -> IResult<Tokens, Expression> {
         parse_block_expression,
         parse_if_expression,
         parse_for_expression,
-    ))(input)
+        parse_while_expression,
+        parse_with_expression,
+        parse_closure_expression,
+        parse_return_expression,
+        parse_break_expression,
+        parse_continue_expression,
+        parse_defer_expression,
+    ))(input)?;
+    *expression.span_mut() = consumed_span(input, rest);
+    Ok((rest, expression))
 }
 
 fn parse_literal_expression(input: Tokens) -> IResult<Tokens, Expression> {
     map(parse_literal, |literal| {
-        Expression::LiteralExpression(literal)
+        Expression::LiteralExpression(literal, Span::default())
     })(input)
 }
 
 fn parse_identifier_expression(input: Tokens) -> IResult<Tokens, Expression> {
     map(parse_identifier, |identifier| {
-        Expression::IdentifierExpression(identifier)
+        Expression::IdentifierExpression(identifier, Span::default())
     })(input)
 }
 
+/// Only string literals are allowed as path, so the bundler knows every imported module
+fn parse_import_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    let (rest, (_, _, path, _)) =
+        tuple((import_tag, l_paren_tag, parse_literal, r_paren_tag))(input)?;
+    match path {
+        Literal::StringLiteral(path) => Ok((
+            rest,
+            Expression::ImportExpression(ImportExpr { path }, Span::default()),
+        )),
+        _ => Err(Err::Error(error_position!(input, ErrorKind::Tag))),
+    }
+}
+
+fn parse_path_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(
+        pair(
+            parse_identifier_str,
+            many1(preceded(double_colon_tag, parse_identifier)),
+        ),
+        |(first, rest)| {
+            Expression::PathExpression(
+                PathExpr {
+                    segments: [vec![Identifier(first.to_string())], rest].concat(),
+                },
+                Span::default(),
+            )
+        },
+    )(input)
+}
+
 fn parse_unary_expression(input: Tokens) -> IResult<Tokens, Expression> {
     let (rest1, unary) = alt((plus_tag, minus_tag, logical_not_tag, bitwise_not_tag))(input)?;
     if unary.tokens.is_empty() {
@@ -62,39 +115,62 @@ fn parse_unary_expression(input: Tokens) -> IResult<Tokens, Expression> {
         match unary.tokens[0] {
             Token::Plus => Ok((
                 rest2,
-                Expression::UnaryExpression(UnaryExpr {
-                    op: UnaryOperator::Plus,
-                    rhs: Box::new(expression),
-                }),
+                Expression::UnaryExpression(
+                    UnaryExpr {
+                        op: UnaryOperator::Plus,
+                        rhs: Box::new(expression),
+                    },
+                    Span::default(),
+                ),
             )),
             Token::Minus => Ok((
                 rest2,
-                Expression::UnaryExpression(UnaryExpr {
-                    op: UnaryOperator::Minus,
-                    rhs: Box::new(expression),
-                }),
+                Expression::UnaryExpression(
+                    UnaryExpr {
+                        op: UnaryOperator::Minus,
+                        rhs: Box::new(expression),
+                    },
+                    Span::default(),
+                ),
             )),
             Token::LogicalNot => Ok((
                 rest2,
-                Expression::UnaryExpression(UnaryExpr {
-                    op: UnaryOperator::LogicalNot,
-                    rhs: Box::new(expression),
-                }),
+                Expression::UnaryExpression(
+                    UnaryExpr {
+                        op: UnaryOperator::LogicalNot,
+                        rhs: Box::new(expression),
+                    },
+                    Span::default(),
+                ),
             )),
             Token::BitwiseNot => Ok((
                 rest2,
-                Expression::UnaryExpression(UnaryExpr {
-                    op: UnaryOperator::BitwiseNot,
-                    rhs: Box::new(expression),
-                }),
+                Expression::UnaryExpression(
+                    UnaryExpr {
+                        op: UnaryOperator::BitwiseNot,
+                        rhs: Box::new(expression),
+                    },
+                    Span::default(),
+                ),
             )),
             _ => Err(Err::Error(error_position!(input, ErrorKind::Tag))),
         }
     }
 }
 
+/// `(value)`, or the tuple `(a, b)`. A single value is only a tuple with a comma after it, `(a,)`
 fn parse_paren_expression(input: Tokens) -> IResult<Tokens, Expression> {
-    delimited(l_paren_tag, parse_expression, r_paren_tag)(input)
+    map(
+        delimited(
+            l_paren_tag,
+            pair(separated_list1(comma_tag, parse_expression), opt(comma_tag)),
+            r_paren_tag,
+        ),
+        |(mut items, trailing_comma)| match (items.len(), trailing_comma) {
+            (1, None) => items.remove(0),
+            _ => Expression::TupleExpression(items, Span::default()),
+        },
+    )(input)
 }
 
 fn parse_array_expression(input: Tokens) -> IResult<Tokens, Expression> {
@@ -104,14 +180,14 @@ fn parse_array_expression(input: Tokens) -> IResult<Tokens, Expression> {
             alt((parse_expressions, empty_boxed_vec)),
             r_bracket_tag,
         ),
-        Expression::ArrayExpression,
+        |elements| Expression::ArrayExpression(elements, Span::default()),
     )(input)
 }
 
 fn parse_block_expression(input: Tokens) -> IResult<Tokens, Expression> {
     map(
         delimited(l_squirly_tag, parse_block, r_squirly_tag),
-        |block| Expression::BlockExpression(Box::new(block)),
+        |block| Expression::BlockExpression(Box::new(block), Span::default()),
     )(input)
 }
 
@@ -130,14 +206,14 @@ fn parse_if_expression(input: Tokens) -> IResult<Tokens, Expression> {
             opt(tuple((else_tag, parse_block_expression))),
         )),
         |(_if, condition, then_block_expr, else_ifs, else_)| {
-            if let Expression::BlockExpression(then_block) = then_block_expr {
+            if let Expression::BlockExpression(then_block, _) = then_block_expr {
                 Expression::IfExpression(IfExpr {
                     condition: Box::new(condition),
                     then_block: Box::new(*then_block),
                     else_if_blocks: else_ifs
                         .into_iter()
                         .map(|(_else, _if, condition, block_expr)| {
-                            if let Expression::BlockExpression(block) = block_expr {
+                            if let Expression::BlockExpression(block, _) = block_expr {
                                 ElseIfExpr {
                                     condition: Box::new(condition),
                                     then_block: *block
@@ -149,13 +225,13 @@ fn parse_if_expression(input: Tokens) -> IResult<Tokens, Expression> {
                         .collect(),
                     else_block: else_
                         .map(|(_, block_expr)| {
-                            if let Expression::BlockExpression(block) = block_expr {
+                            if let Expression::BlockExpression(block, _) = block_expr {
                                 block
                             } else {
                                 panic!("parse_block_expression parsed something other than a block expression");
                             }
                         }),
-                })
+                }, Span::default())
             } else {
                 panic!("parse_block_expression parsed something other than a block expression");
             }
@@ -173,12 +249,34 @@ fn parse_for_expression(input: Tokens) -> IResult<Tokens, Expression> {
             parse_block_expression,
         )),
         |(_for, lhs, _in, rhs, body)| {
-            if let Expression::BlockExpression(body) = body {
-                Expression::ForExpression(ForExpr {
-                    lhs,
-                    rhs: Box::new(rhs),
-                    body,
-                })
+            if let Expression::BlockExpression(body, _) = body {
+                Expression::ForExpression(
+                    ForExpr {
+                        lhs,
+                        rhs: Box::new(rhs),
+                        body,
+                    },
+                    Span::default(),
+                )
+            } else {
+                panic!("parse_block_expression parsed something other than a block expression");
+            }
+        },
+    )(input)
+}
+
+fn parse_while_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(
+        tuple((while_tag, parse_expression, parse_block_expression)),
+        |(_while, condition, body)| {
+            if let Expression::BlockExpression(body, _) = body {
+                Expression::WhileExpression(
+                    WhileExpr {
+                        condition: Box::new(condition),
+                        body,
+                    },
+                    Span::default(),
+                )
             } else {
                 panic!("parse_block_expression parsed something other than a block expression");
             }
@@ -186,6 +284,27 @@ fn parse_for_expression(input: Tokens) -> IResult<Tokens, Expression> {
     )(input)
 }
 
+fn parse_break_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(break_tag, |_| Expression::BreakExpression(Span::default()))(input)
+}
+
+fn parse_continue_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(continue_tag, |_| {
+        Expression::ContinueExpression(Span::default())
+    })(input)
+}
+
+/// `defer { ... }`, the block is run when the block around it is left
+fn parse_defer_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(
+        preceded(
+            defer_tag,
+            delimited(l_squirly_tag, parse_block, r_squirly_tag),
+        ),
+        |body| Expression::DeferExpression(Box::new(body), Span::default()),
+    )(input)
+}
+
 fn empty_boxed_vec(input: Tokens) -> IResult<Tokens, Vec<Expression>> {
     Ok((input, vec![]))
 }
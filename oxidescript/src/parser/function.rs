@@ -1,20 +1,30 @@
 use nom::{
     branch::alt,
-    combinator::{map, opt},
-    multi::many0,
-    sequence::{delimited, tuple},
+    combinator::{map, opt, verify},
+    multi::{many0, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, tuple},
     IResult,
 };
 
 use crate::lexer::tokens::Tokens;
 
 use super::{
-    ast::{Block, Expression, Parameter, Statement},
-    atoms::{colon_tag, return_tag, semicolon_tag},
+    ast::{
+        Block, ClosureExpr, Declaration, Expression, Identifier, Parameter, Pattern, Span,
+        Statement,
+    },
+    atoms::{
+        arrow_tag, async_tag, bitwise_or_tag, bitwise_right_shift_tag, colon_tag, dyn_tag,
+        greater_than_tag, impl_tag, l_bracket_tag, l_paren_tag, less_than_tag, logical_or_tag,
+        r_bracket_tag, r_paren_tag, return_tag,
+    },
     comma_tag,
     expression::parse_expression,
-    parse_identifier,
+    parse_identifier, parse_literal,
+    pattern::parse_destructuring_pattern,
+    spanned,
     statement::parse_statement,
+    types::{literal_as_type, tuple_type},
 };
 
 pub fn parse_parameters(input: Tokens) -> IResult<Tokens, Vec<Parameter>> {
@@ -36,38 +46,371 @@ pub fn parse_parameters(input: Tokens) -> IResult<Tokens, Vec<Parameter>> {
     )(input)
 }
 
+/// `name: type`, where the type of a callback is a function type like `impl Fn(string) -> number`
 pub fn parse_parameter(input: Tokens) -> IResult<Tokens, Parameter> {
     map(
-        tuple((parse_identifier, colon_tag, parse_identifier)),
-        |(name, _, type_)| Parameter { name, type_ },
+        spanned(tuple((
+            parse_identifier,
+            colon_tag,
+            alt((
+                verify(parse_impl_type, |type_: &Identifier| {
+                    type_.0.starts_with("impl Fn(")
+                }),
+                parse_type,
+            )),
+        ))),
+        |((name, _, type_), span)| Parameter { name, type_, span },
     )(input)
 }
 
-fn parse_return_expression(input: Tokens) -> IResult<Tokens, Expression> {
+/// The parameters of a function with a body, which can destructure their arguments like
+/// `(a, b): (number, number)` or `Point { x, y }: Point`. Such a parameter is named after its
+/// position, see [`destructured_parameter`], and the statements returned with the parameters
+/// destructure it like a `let` at the start of the body
+pub fn parse_destructured_parameters(
+    input: Tokens,
+) -> IResult<Tokens, (Vec<Parameter>, Vec<Statement>)> {
     map(
-        delimited(return_tag, parse_expression, semicolon_tag),
-        |expr| expr,
+        separated_list0(
+            comma_tag,
+            alt((
+                map(parse_parameter, |parameter| (parameter, None)),
+                map(
+                    spanned(tuple((parse_destructuring_pattern, colon_tag, parse_type))),
+                    |((pattern, _, type_), span)| {
+                        let parameter = Parameter {
+                            name: Identifier(String::new()),
+                            type_,
+                            span,
+                        };
+                        (parameter, Some(pattern))
+                    },
+                ),
+            )),
+        ),
+        destructure_parameters,
     )(input)
 }
 
-pub fn parse_block(input: Tokens) -> IResult<Tokens, Block> {
+/// Names the destructured parameters and writes the `let`s that destructure them
+fn destructure_parameters(
+    parameters: Vec<(Parameter, Option<Pattern>)>,
+) -> (Vec<Parameter>, Vec<Statement>) {
+    let mut destructurings = vec![];
+    let parameters = parameters
+        .into_iter()
+        .enumerate()
+        .map(|(index, (mut parameter, pattern))| {
+            if let Some(pattern) = pattern {
+                parameter.name = destructured_parameter(index);
+                destructurings.push(Statement::DeclarationStatement(
+                    Declaration::DestructuringDeclaration(
+                        pattern,
+                        Expression::IdentifierExpression(parameter.name.clone(), parameter.span),
+                        vec![],
+                        None,
+                    ),
+                    parameter.span,
+                ));
+            }
+            parameter
+        })
+        .collect();
+    (parameters, destructurings)
+}
+
+/// The name of the parameter at `index` that destructures its argument. It starts with `__`, so
+/// no name in the program can clash with it
+pub fn destructured_parameter(index: usize) -> Identifier {
+    Identifier(format!("__arg{}", index + 1))
+}
+
+/// How messages refer to a parameter, `` `name` ``, or the position of a destructured one and of
+/// the parameters of `impl Fn` types, which have no names
+pub fn parameter_label(name: &Identifier) -> String {
+    let position = name.0.strip_prefix("__arg").unwrap_or(&name.0);
+    match position.parse::<usize>() {
+        Ok(position) => position.to_string(),
+        Err(_) => format!("`{}`", name.0),
+    }
+}
+
+/// The body with the destructurings of its parameters in front of it
+pub fn with_destructurings(mut body: Block, mut destructurings: Vec<Statement>) -> Block {
+    destructurings.append(&mut body.statements);
+    body.statements = destructurings;
+    body
+}
+
+/// `|x, y: number| body` or `|| body`, or `async |x| body`. The body reaches as far as an
+/// expression can, like in Rust
+pub fn parse_closure_expression(input: Tokens) -> IResult<Tokens, Expression> {
     map(
         tuple((
-            many0(parse_statement),
-            opt(alt((parse_return_expression, parse_expression))),
+            opt(async_tag),
+            alt((
+                map(logical_or_tag, |_| vec![]),
+                delimited(
+                    bitwise_or_tag,
+                    separated_list0(comma_tag, parse_closure_parameter),
+                    bitwise_or_tag,
+                ),
+            )),
+            parse_expression,
+        )),
+        |(is_async, parameters, body)| {
+            let (parameters, destructurings) = destructure_parameters(parameters);
+            let body = match (destructurings.is_empty(), body) {
+                (true, body) => body,
+                (false, Expression::BlockExpression(block, span)) => Expression::BlockExpression(
+                    Box::new(with_destructurings(*block, destructurings)),
+                    span,
+                ),
+                (false, body) => {
+                    let span = body.span();
+                    Expression::BlockExpression(
+                        Box::new(Block {
+                            statements: destructurings,
+                            return_value: Some(body),
+                            span,
+                        }),
+                        span,
+                    )
+                }
+            };
+            Expression::ClosureExpression(
+                ClosureExpr {
+                    is_async: is_async.is_some(),
+                    parameters,
+                    body: Box::new(body),
+                },
+                Span::default(),
+            )
+        },
+    )(input)
+}
+
+/// `x`, `x: number` or a destructuring like `(a, b)`, which is named by
+/// [`parse_closure_expression`] like the ones of functions
+fn parse_closure_parameter(input: Tokens) -> IResult<Tokens, (Parameter, Option<Pattern>)> {
+    map(
+        spanned(pair(
+            parse_destructuring_pattern,
+            opt(preceded(colon_tag, parse_single_type)),
+        )),
+        |((pattern, type_), span)| {
+            let (name, pattern) = match pattern {
+                Pattern::Binding(name) => (name, None),
+                Pattern::Wildcard => (Identifier("_".to_string()), None),
+                pattern => (Identifier(String::new()), Some(pattern)),
+            };
+            let parameter = Parameter {
+                name,
+                type_: type_.unwrap_or_else(|| Identifier("any".to_string())),
+                span,
+            };
+            (parameter, pattern)
+        },
+    )(input)
+}
+
+/// Optional `-> type` annotation after a parameter list, or `: type` like in typescript
+pub fn parse_return_type(input: Tokens) -> IResult<Tokens, Option<Identifier>> {
+    opt(preceded(
+        alt((arrow_tag, colon_tag)),
+        alt((parse_impl_type, parse_type)),
+    ))(input)
+}
+
+/// `impl Trait`, some type implementing the trait, or `impl Fn(number) -> number`, some function
+/// taking and returning those types. Only return types can be `impl` types, and parameters
+/// `impl Fn` types, so they are never part of a union and a union after the `->` of `impl Fn` is
+/// its return type
+fn parse_impl_type(input: Tokens) -> IResult<Tokens, Identifier> {
+    preceded(
+        impl_tag,
+        alt((
+            map(
+                tuple((
+                    verify(parse_identifier, |name: &Identifier| name.0 == "Fn"),
+                    delimited(
+                        l_paren_tag,
+                        separated_list0(comma_tag, parse_type),
+                        r_paren_tag,
+                    ),
+                    parse_return_type,
+                )),
+                |(_, parameters, returns)| {
+                    let parameters = parameters
+                        .into_iter()
+                        .map(|parameter| parameter.0)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    Identifier(match returns {
+                        Some(returns) => format!("impl Fn({}) -> {}", parameters, returns.0),
+                        None => format!("impl Fn({})", parameters),
+                    })
+                },
+            ),
+            map(parse_identifier, |trait_| {
+                Identifier(format!("impl {}", trait_.0))
+            }),
         )),
-        |(mut statements, return_value)| {
+    )(input)
+}
+
+/// A type name, an array type like `[[number]]`, a union like `string | [number]` or a generic
+/// type like `Pair<number, string>`. Types are kept as their source text, with ` | ` between the
+/// members of a union and `, ` between type arguments
+pub fn parse_type(input: Tokens) -> IResult<Tokens, Identifier> {
+    map(
+        verify(parse_nested_type, |(_, closes_outer)| !closes_outer),
+        |(type_, _)| type_,
+    )(input)
+}
+
+/// A type without a union at its top, for places where `|` already means something else, like
+/// the parameters of closures. Unions are still allowed inside of array types and type arguments.
+/// Literals are types with only that value, like `"GET"`
+pub fn parse_single_type(input: Tokens) -> IResult<Tokens, Identifier> {
+    map(
+        verify(parse_nested_single_type, |(_, closes_outer)| !closes_outer),
+        |(type_, _)| type_,
+    )(input)
+}
+
+/// A type, and whether it ends in a `>>` whose second `>` closes the type arguments around it.
+/// `>>` is a single token, so `Option<Pair<A, B>>` can't be split at its `>`s
+fn parse_nested_type(input: Tokens) -> IResult<Tokens, (Identifier, bool)> {
+    let (mut input, (first, mut closes_outer)) = parse_nested_single_type(input)?;
+    let mut members = vec![first.0];
+    while !closes_outer {
+        let Ok((rest, (member, closes))) =
+            preceded(bitwise_or_tag, parse_nested_single_type)(input)
+        else {
+            break;
+        };
+        members.push(member.0);
+        closes_outer = closes;
+        input = rest;
+    }
+    Ok((input, (Identifier(members.join(" | ")), closes_outer)))
+}
+
+fn parse_nested_single_type(input: Tokens) -> IResult<Tokens, (Identifier, bool)> {
+    alt((
+        // `dyn Shape`, any value of a type implementing the trait
+        map(preceded(dyn_tag, parse_identifier), |name| {
+            (Identifier(format!("dyn {}", name.0)), false)
+        }),
+        parse_generic_type,
+        map(
+            alt((
+                map(parse_literal, |literal| {
+                    Identifier(literal_as_type(&literal))
+                }),
+                map(
+                    delimited(l_bracket_tag, parse_type, r_bracket_tag),
+                    |item| Identifier(format!("[{}]", item.0)),
+                ),
+                // `(number, string)`, or `(number,)` for a tuple of one item
+                map(
+                    delimited(
+                        l_paren_tag,
+                        pair(separated_list1(comma_tag, parse_type), opt(comma_tag)),
+                        r_paren_tag,
+                    ),
+                    |(mut items, trailing_comma)| match (items.len(), trailing_comma) {
+                        (1, None) => items.remove(0),
+                        _ => Identifier(tuple_type(
+                            &items.iter().map(|item| &item.0).collect::<Vec<_>>(),
+                        )),
+                    },
+                ),
+            )),
+            |type_| (type_, false),
+        ),
+    ))(input)
+}
+
+/// A type name with optional type arguments. A `<` that isn't followed by type arguments isn't
+/// part of the type, like in `value as number < limit`
+fn parse_generic_type(input: Tokens) -> IResult<Tokens, (Identifier, bool)> {
+    let (input, name) = parse_identifier(input)?;
+    match preceded(less_than_tag, parse_type_arguments)(input) {
+        Ok((rest, (arguments, closes_outer))) => Ok((
+            rest,
+            (
+                Identifier(format!("{}<{}>", name.0, arguments.join(", "))),
+                closes_outer,
+            ),
+        )),
+        Err(nom::Err::Error(_)) => Ok((input, (name, false))),
+        Err(err) => Err(err),
+    }
+}
+
+/// The type arguments after the `<`, up to and including the `>` or `>>` closing them
+fn parse_type_arguments(input: Tokens) -> IResult<Tokens, (Vec<String>, bool)> {
+    let mut input = input;
+    let mut arguments = vec![];
+    loop {
+        let (rest, (argument, closes)) = parse_nested_type(input)?;
+        arguments.push(argument.0);
+        // the argument ended in `>>`, which closed these arguments as well
+        if closes {
+            return Ok((rest, (arguments, false)));
+        }
+        if let Ok((rest, _)) = comma_tag(rest) {
+            input = rest;
+            continue;
+        }
+        return alt((
+            map(greater_than_tag, |_| (arguments.clone(), false)),
+            map(bitwise_right_shift_tag, |_| (arguments.clone(), true)),
+        ))(rest);
+    }
+}
+
+/// `return` with an optional value, which ends at the `;` or `}` after it
+pub fn parse_return_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(preceded(return_tag, opt(parse_expression)), |value| {
+        Expression::ReturnExpression(value.map(Box::new), Span::default())
+    })(input)
+}
+
+pub fn parse_block(input: Tokens) -> IResult<Tokens, Block> {
+    map(
+        spanned(tuple((many0(parse_statement), opt(parse_expression)))),
+        |((mut statements, mut return_value), span)| {
+            // a defer runs when the block is left, so it never gives the block its value
+            if let Some(defer @ Expression::DeferExpression(_, _)) = return_value.take() {
+                statements.push(Statement::ExpressionStatement {
+                    span: defer.span(),
+                    expression: defer,
+                    has_semicolon: false,
+                });
+            }
             // Automatically select last expression statement as return value if no return value exists
             if return_value.is_none() {
                 if let Some(last) = statements.last() {
                     if let Statement::ExpressionStatement {
                         expression: last,
                         has_semicolon: false,
+                        ..
                     } = last.clone()
                     {
+                        if matches!(last, Expression::DeferExpression(_, _)) {
+                            return Block {
+                                statements,
+                                return_value: None,
+                                span,
+                            };
+                        }
                         return Block {
                             statements: statements.drain(..statements.len() - 1).collect(),
                             return_value: Some(last),
+                            span,
                         };
                     }
                 }
@@ -75,6 +418,7 @@ pub fn parse_block(input: Tokens) -> IResult<Tokens, Block> {
             Block {
                 statements,
                 return_value,
+                span,
             }
         },
     )(input)
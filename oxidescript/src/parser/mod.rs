@@ -1,98 +1,264 @@
+pub mod assignment;
 pub mod ast;
 pub mod atoms;
 pub mod declaration;
+pub mod dispose;
+pub mod enums;
 pub mod expression;
 pub mod function;
+pub mod module;
+pub mod optional;
+pub mod pattern;
 pub mod pratt_expression;
 pub mod statement;
+pub mod structs;
+pub mod template;
+pub mod traits;
+pub mod types;
+pub mod visit;
+
+use std::cell::Cell;
 
 use nom::bytes::complete::take;
 use nom::error::{Error, ErrorKind};
 use nom::multi::many0;
 use nom::Err;
-use nom::{sequence::terminated, IResult};
+use nom::{sequence::terminated, IResult, InputLength, InputTake};
 
+use crate::cancellation::{CancellationToken, Cancelled};
 use crate::lexer::token::Token;
 use crate::lexer::tokens::Tokens;
 
-use self::ast::{Identifier, Literal, Number, NumberBase, Program};
+use self::ast::{Identifier, Literal, Program, Span};
 use self::atoms::*;
-use self::statement::parse_statement;
+use self::statement::parse_top_level_statement;
+
+thread_local! {
+    /// The tokens [`Parser::parse_recovering`] parses, by the address of the first one and how
+    /// many there are, and the index of the furthest one a parser looked at
+    static FURTHEST: Cell<Option<(usize, usize, usize)>> = const { Cell::new(None) };
+}
+
+/// Takes the next token and remembers the furthest one any parser looked at. Parsers backtrack to
+/// where their alternatives start, so the error of a statement that fails to parse is at its
+/// start, but the furthest token is where it went wrong. The tokens of templates are lexed on
+/// their own and don't count
+fn take_token(input: Tokens) -> IResult<Tokens, Tokens> {
+    FURTHEST.with(|furthest| {
+        let Some((start, len, index)) = furthest.get() else {
+            return;
+        };
+        let address = input.tokens.as_ptr() as usize;
+        let looked_at = address.wrapping_sub(start) / std::mem::size_of::<Token>();
+        if address >= start && looked_at < len && looked_at > index {
+            furthest.set(Some((start, len, looked_at)));
+        }
+    });
+    take(1usize)(input)
+}
 
 fn parse_literal(input: Tokens) -> IResult<Tokens, Literal> {
-    let (rest, found) = take(1usize)(input)?;
+    let (rest, found) = take_token(input)?;
     if found.tokens.is_empty() {
         Err(Err::Error(Error::new(input, ErrorKind::Tag)))
     } else {
-        match found.tokens[0].clone() {
-            Token::NumberLiteral(val) => {
-                let parsed: Number = if val.starts_with("0x") {
-                    Number::I {
-                        base: NumberBase::Hex,
-                        value: i32::from_str_radix(&val, 16).unwrap(),
-                    }
-                } else if val.starts_with("0b") {
-                    Number::I {
-                        base: NumberBase::Bin,
-                        value: i32::from_str_radix(&val, 2).unwrap(),
-                    }
-                } else if val.starts_with("0o") {
-                    Number::I {
-                        base: NumberBase::Oct,
-                        value: i32::from_str_radix(&val, 8).unwrap(),
-                    }
-                } else {
-                    let int = val.parse::<i32>();
-                    if let Ok(int) = int {
-                        Number::I {
-                            base: NumberBase::Dec,
-                            value: int,
-                        }
-                    } else {
-                        Number::F(val.parse().unwrap())
-                    }
-                };
-                Ok((rest, Literal::NumberLiteral(parsed)))
-            }
-            Token::StringLiteral(val) => Ok((rest, Literal::StringLiteral(val))),
-            Token::BooleanLiteral(val) => Ok((rest, Literal::BooleanLiteral(val))),
+        match &found.tokens[0] {
+            Token::NumberLiteral(val) => match val.parse() {
+                Ok(number) => Ok((rest, Literal::NumberLiteral(number))),
+                // the lexer only makes number tokens of numbers, so this is a number too large
+                Err(_) => Err(Err::Error(Error::new(input, ErrorKind::Float))),
+            },
+            Token::StringLiteral(val) => Ok((rest, Literal::StringLiteral(val.to_string()))),
+            Token::BooleanLiteral(val) => Ok((rest, Literal::BooleanLiteral(*val))),
             _ => Err(Err::Error(Error::new(input, ErrorKind::Tag))),
         }
     }
 }
 
 fn parse_identifier(input: Tokens) -> IResult<Tokens, Identifier> {
-    let (rest, found) = take(1usize)(input)?;
+    let (rest, name) = parse_identifier_str(input)?;
+    Ok((rest, Identifier(name.to_string())))
+}
+
+/// Borrows the name from the token, for parsers that might still backtrack after the identifier
+fn parse_identifier_str<'a>(input: Tokens<'a>) -> IResult<Tokens<'a>, &'a str> {
+    let (rest, found) = take_token(input)?;
     // dbg!(rest, found, input);
     if found.tokens.is_empty() {
         Err(Err::Error(Error::new(input, ErrorKind::Tag)))
     } else {
-        match found.tokens[0].clone() {
-            Token::Ident(name) => Ok((rest, Identifier(name))),
+        match found.tokens[0] {
+            Token::Ident(name) => Ok((rest, name)),
             _ => Err(Err::Error(Error::new(input, ErrorKind::Tag))),
         }
     }
 }
 
+/// The span of the tokens a parser consumed from `input` if it returned `rest`
+fn consumed_span(input: Tokens, rest: Tokens) -> Span {
+    let consumed = input.input_len() - rest.input_len();
+    match (input.spans.first(), consumed) {
+        (None, _) => Span::default(),
+        (Some(first), 0) => Span {
+            start: first.start,
+            end: first.start,
+            file_id: input.file_id,
+        },
+        (Some(first), _) => Span {
+            start: first.start,
+            end: input.spans[consumed - 1].end,
+            file_id: input.file_id,
+        },
+    }
+}
+
+/// Runs `parser` and also returns the span of what it consumed
+fn spanned<'a, O>(
+    mut parser: impl FnMut(Tokens<'a>) -> IResult<Tokens<'a>, O>,
+) -> impl FnMut(Tokens<'a>) -> IResult<Tokens<'a>, (O, Span)> {
+    move |input| {
+        let (rest, output) = parser(input)?;
+        Ok((rest, (output, consumed_span(input, rest))))
+    }
+}
+
 fn parse_program(input: Tokens) -> IResult<Tokens, Program> {
     // println!("parse_program");
-    terminated(many0(parse_statement), eof_tag)(input)
+    terminated(many0(parse_top_level_statement), eof_tag)(input)
 }
 
 pub struct Parser;
 
+/// A program parsed by [`Parser::parse_recovering`]
+#[derive(Debug)]
+pub struct Parsed {
+    pub program: Program,
+    /// The index of the first token of every statement of `program`
+    pub indices: Vec<usize>,
+    /// The index of the token every top level statement that failed to parse went wrong at, in
+    /// order
+    pub errors: Vec<usize>,
+}
+
 impl Parser {
     pub fn parse(tokens: Tokens) -> IResult<Tokens, Program> {
         parse_program(tokens)
     }
+
+    /// Like `parse`, but checks `cancellation` before every top level statement
+    pub fn parse_cancellable<'a>(
+        tokens: Tokens<'a>,
+        cancellation: &CancellationToken,
+    ) -> Result<IResult<Tokens<'a>, Program>, Cancelled> {
+        parse_statements(tokens, Some(cancellation))
+            .map(|result| result.map(|(rest, (program, _))| (rest, program)))
+    }
+
+    /// Like `parse`, but also returns the index of the first token of every top level statement,
+    /// to find the comments in front of it
+    pub fn parse_with_token_indices(tokens: Tokens) -> IResult<Tokens, (Program, Vec<usize>)> {
+        parse_statements(tokens, None).expect("parsing without cancellation token was cancelled")
+    }
+
+    /// Like `parse_with_token_indices`, but a top level statement that fails to parse is skipped
+    /// up to the `;` or the `}` of the block it ends with, and parsing goes on after it, so every
+    /// statement that fails is reported
+    pub fn parse_recovering(tokens: Tokens) -> Parsed {
+        let mut parsed = Parsed {
+            program: vec![],
+            indices: vec![],
+            errors: vec![],
+        };
+        let mut input = tokens;
+        while eof_tag(input).is_err() {
+            let index = tokens.input_len() - input.input_len();
+            let start = tokens.tokens.as_ptr() as usize;
+            FURTHEST.with(|furthest| furthest.set(Some((start, tokens.input_len(), index))));
+            let result = parse_top_level_statement(input);
+            let furthest = FURTHEST.with(|furthest| furthest.take());
+            match result {
+                Ok((rest, statement)) if rest.input_len() < input.input_len() => {
+                    parsed.program.push(statement);
+                    parsed.indices.push(index);
+                    input = rest;
+                }
+                _ => {
+                    parsed
+                        .errors
+                        .push(furthest.map_or(index, |(_, _, furthest)| furthest));
+                    input = skip_statement(input);
+                }
+            }
+        }
+        parsed
+    }
+}
+
+/// The tokens after the statement at the start of `input`, which ends with a `;` or with the `}`
+/// of its block outside of any other braces. `input` doesn't start at the end of the file, so at
+/// least one token is skipped
+fn skip_statement(input: Tokens) -> Tokens {
+    let mut depth = 0;
+    for (index, token) in input.tokens.iter().enumerate() {
+        let end = match token {
+            Token::EOF => index.max(1),
+            Token::LSquirly => {
+                depth += 1;
+                continue;
+            }
+            Token::RSquirly if depth > 1 => {
+                depth -= 1;
+                continue;
+            }
+            // the `}` of the statement's block, or one that closes nothing
+            Token::RSquirly => index + 1,
+            Token::SemiColon if depth == 0 => index + 1,
+            _ => continue,
+        };
+        return input.take_split(end).0;
+    }
+    input.take_split(input.input_len()).0
+}
+
+fn parse_statements<'a>(
+    tokens: Tokens<'a>,
+    cancellation: Option<&CancellationToken>,
+) -> Result<IResult<Tokens<'a>, (Program, Vec<usize>)>, Cancelled> {
+    let mut input = tokens;
+    let mut program = vec![];
+    let mut indices = vec![];
+    loop {
+        if cancellation.is_some_and(|cancellation| cancellation.is_cancelled()) {
+            return Err(Cancelled);
+        }
+        match parse_top_level_statement(input) {
+            // same infinite loop protection as `many0`
+            Ok((rest, _)) if rest.input_len() == input.input_len() => {
+                return Ok(Err(Err::Error(Error::new(input, ErrorKind::Many0))));
+            }
+            Ok((rest, statement)) => {
+                program.push(statement);
+                indices.push(tokens.input_len() - input.input_len());
+                input = rest;
+            }
+            Err(Err::Error(_)) => break,
+            Err(err) => return Ok(Err(err)),
+        }
+    }
+    Ok(eof_tag(input).map(|(rest, _)| (rest, (program, indices))))
 }
 
 #[cfg(test)]
 mod tests {
-    use ast::{CallExpr, ElseIfExpr, IfExpr, IndexExpr, InfixExpr, MemberAccessExpr};
+    use ast::{
+        AssignmentExpr, CallExpr, CastExpr, ClosureExpr, ElseIfExpr, Field, IfExpr, ImplDecl,
+        ImplItem, IndexExpr, InfixExpr, MemberAccessExpr, Method, MethodSignature, ModDecl,
+        ModItem, Number, NumberBase, Parameter, PathExpr, SliceExpr, Span, StructDecl, StructExpr,
+        TypeParameter, UseDecl, WhileExpr,
+    };
 
     use super::{
-        ast::{Block, Declaration, Expression, InfixOperator, Statement},
+        ast::{Block, Declaration, Expression, InfixOperator, Pattern, Statement},
         *,
     };
     use crate::lexer::*;
@@ -109,6 +275,36 @@ mod tests {
         assert_input_with_program(b"", vec![]);
     }
 
+    #[test]
+    fn number_literals() {
+        let number = |text: &str| text.parse::<Number>();
+        let int = |base, value| Ok(Number::I { base, value });
+        assert_eq!(number("1_000"), int(NumberBase::Dec, 1000));
+        assert_eq!(number("0xFF"), int(NumberBase::Hex, 255));
+        assert_eq!(number("0b1010"), int(NumberBase::Bin, 10));
+        assert_eq!(number("0o17"), int(NumberBase::Oct, 15));
+        assert_eq!(number("1e9"), int(NumberBase::Dec, 1_000_000_000));
+        assert_eq!(number("1.5"), Ok(Number::F("1.5".to_string())));
+        assert_eq!(number("1_0.0"), Ok(Number::F("10.0".to_string())));
+        assert_eq!(number("2.5E-3"), Ok(Number::F("0.0025".to_string())));
+        assert_eq!(
+            number("1e20"),
+            Ok(Number::F("100000000000000000000".to_string()))
+        );
+        assert_eq!(
+            number("0xFFFF_FFFF"),
+            Ok(Number::F("4294967295".to_string()))
+        );
+        assert_eq!(
+            number("3000000000"),
+            Ok(Number::F("3000000000".to_string()))
+        );
+        assert!(number("1e400").is_err());
+        assert!(number("0x1_0000_0000_0000_0000").is_err());
+        let (_, tokens) = Lexer::lex_tokens(b"let a = 1e400;").unwrap();
+        assert!(Parser::parse(Tokens::new(&tokens)).is_err());
+    }
+
     #[test]
     fn declaration_statement() {
         let input = "
@@ -118,11 +314,17 @@ mod tests {
         let program: Program = vec![Statement::DeclarationStatement(
             Declaration::LetDeclaration(
                 Identifier("test".to_string()),
-                Expression::LiteralExpression(Literal::NumberLiteral(Number::I {
-                    base: NumberBase::Dec,
-                    value: 5,
-                })),
+                Expression::LiteralExpression(
+                    Literal::NumberLiteral(Number::I {
+                        base: NumberBase::Dec,
+                        value: 5,
+                    }),
+                    Span::default(),
+                ),
+                vec![],
+                None,
             ),
+            Span::default(),
         )];
         assert_input_with_program(input, program);
     }
@@ -138,28 +340,59 @@ mod tests {
         .as_bytes();
 
         let program: Program = vec![
-            Statement::DeclarationStatement(Declaration::LetDeclaration(
-                Identifier("test".to_string()),
-                Expression::LiteralExpression(Literal::NumberLiteral(Number::I {
-                    base: NumberBase::Dec,
-                    value: 5,
-                })),
-            )),
-            Statement::DeclarationStatement(Declaration::ConstDeclaration(
-                Identifier("stuff".to_string()),
-                Expression::LiteralExpression(Literal::NumberLiteral(Number::I {
-                    base: NumberBase::Dec,
-                    value: 12,
-                })),
-            )),
-            Statement::DeclarationStatement(Declaration::LetDeclaration(
-                Identifier("things".to_string()),
-                Expression::LiteralExpression(Literal::BooleanLiteral(true)),
-            )),
-            Statement::DeclarationStatement(Declaration::ConstDeclaration(
-                Identifier("foo".to_string()),
-                Expression::LiteralExpression(Literal::StringLiteral("bar".to_string())),
-            )),
+            Statement::DeclarationStatement(
+                Declaration::LetDeclaration(
+                    Identifier("test".to_string()),
+                    Expression::LiteralExpression(
+                        Literal::NumberLiteral(Number::I {
+                            base: NumberBase::Dec,
+                            value: 5,
+                        }),
+                        Span::default(),
+                    ),
+                    vec![],
+                    None,
+                ),
+                Span::default(),
+            ),
+            Statement::DeclarationStatement(
+                Declaration::ConstDeclaration(
+                    Identifier("stuff".to_string()),
+                    Expression::LiteralExpression(
+                        Literal::NumberLiteral(Number::I {
+                            base: NumberBase::Dec,
+                            value: 12,
+                        }),
+                        Span::default(),
+                    ),
+                    false,
+                    vec![],
+                    None,
+                ),
+                Span::default(),
+            ),
+            Statement::DeclarationStatement(
+                Declaration::LetDeclaration(
+                    Identifier("things".to_string()),
+                    Expression::LiteralExpression(Literal::BooleanLiteral(true), Span::default()),
+                    vec![],
+                    None,
+                ),
+                Span::default(),
+            ),
+            Statement::DeclarationStatement(
+                Declaration::ConstDeclaration(
+                    Identifier("foo".to_string()),
+                    Expression::LiteralExpression(
+                        Literal::StringLiteral("bar".to_string()),
+                        Span::default(),
+                    ),
+                    false,
+                    vec![],
+                    None,
+                ),
+                Span::default(),
+            ),
         ];
 
         assert_input_with_program(input, program);
@@ -176,21 +409,36 @@ mod tests {
 
         let program: Program = vec![Statement::DeclarationStatement(
             Declaration::FunctionDeclaration {
+                attributes: vec![],
+                is_pub: false,
+                is_async: false,
+                is_const: false,
                 name: Identifier("test".to_string()),
+                type_parameters: vec![],
                 parameters: vec![],
+                return_type: None,
+                where_clause: vec![],
                 body: Block {
                     statements: vec![Statement::DeclarationStatement(
                         Declaration::LetDeclaration(
                             Identifier("variable".to_string()),
-                            Expression::LiteralExpression(Literal::NumberLiteral(Number::I {
-                                base: NumberBase::Dec,
-                                value: 5,
-                            })),
+                            Expression::LiteralExpression(
+                                Literal::NumberLiteral(Number::I {
+                                    base: NumberBase::Dec,
+                                    value: 5,
+                                }),
+                                Span::default(),
+                            ),
+                            vec![],
+                            None,
                         ),
+                        Span::default(),
                     )],
                     return_value: None,
+                    span: Span::default(),
                 },
             },
+            Span::default(),
         )];
 
         assert_input_with_program(input, program);
@@ -203,36 +451,561 @@ mod tests {
         let program: Program = vec![Statement::DeclarationStatement(
             Declaration::LetDeclaration(
                 Identifier("foo".to_string()),
-                Expression::InfixExpression(InfixExpr {
-                    op: InfixOperator::Minus,
-                    lhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                        Number::I {
-                            base: NumberBase::Dec,
-                            value: 5,
-                        },
-                    ))),
-                    rhs: Box::new(Expression::InfixExpression(InfixExpr {
-                        op: InfixOperator::Multiply,
-                        lhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                            Number::I {
-                                base: NumberBase::Dec,
-                                value: 10,
-                            },
-                        ))),
-                        rhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                            Number::I {
+                Expression::InfixExpression(
+                    InfixExpr {
+                        op: InfixOperator::Minus,
+                        lhs: Box::new(Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::I {
                                 base: NumberBase::Dec,
-                                value: 2,
+                                value: 5,
+                            }),
+                            Span::default(),
+                        )),
+                        rhs: Box::new(Expression::InfixExpression(
+                            InfixExpr {
+                                op: InfixOperator::Multiply,
+                                lhs: Box::new(Expression::LiteralExpression(
+                                    Literal::NumberLiteral(Number::I {
+                                        base: NumberBase::Dec,
+                                        value: 10,
+                                    }),
+                                    Span::default(),
+                                )),
+                                rhs: Box::new(Expression::LiteralExpression(
+                                    Literal::NumberLiteral(Number::I {
+                                        base: NumberBase::Dec,
+                                        value: 2,
+                                    }),
+                                    Span::default(),
+                                )),
                             },
-                        ))),
-                    })),
-                }),
+                            Span::default(),
+                        )),
+                    },
+                    Span::default(),
+                ),
+                vec![],
+                None,
             ),
+            Span::default(),
         )];
 
         assert_input_with_program(input, program);
     }
 
+    #[test]
+    fn assignment_expression() {
+        let ident = |name: &str| {
+            Box::new(Expression::IdentifierExpression(
+                Identifier(name.to_string()),
+                Span::default(),
+            ))
+        };
+        let input = "a = b.c[i] += x || y;".as_bytes();
+        let program: Program = vec![Statement::ExpressionStatement {
+            expression: Expression::AssignmentExpression(
+                AssignmentExpr {
+                    op: None,
+                    lhs: ident("a"),
+                    rhs: Box::new(Expression::AssignmentExpression(
+                        AssignmentExpr {
+                            op: Some(InfixOperator::Plus),
+                            lhs: Box::new(Expression::IndexExpression(
+                                IndexExpr {
+                                    lhs: Box::new(Expression::MemberAccessExpression(
+                                        MemberAccessExpr {
+                                            lhs: ident("b"),
+                                            ident: Identifier("c".to_string()),
+                                        },
+                                        Span::default(),
+                                    )),
+                                    index: ident("i"),
+                                },
+                                Span::default(),
+                            )),
+                            rhs: Box::new(Expression::InfixExpression(
+                                InfixExpr {
+                                    op: InfixOperator::LogicalOr,
+                                    lhs: ident("x"),
+                                    rhs: ident("y"),
+                                },
+                                Span::default(),
+                            )),
+                        },
+                        Span::default(),
+                    )),
+                },
+                Span::default(),
+            ),
+            has_semicolon: true,
+            span: Span::default(),
+        }];
+        assert_input_with_program(input, program);
+
+        for input in ["a + b = 1;", "f() = 1;", "1 -= 2;", "a = ;", "a?.b = 1;"] {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            assert!(Parser::parse(Tokens::new(&tokens)).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn slice_expression() {
+        let ident = |name: &str| {
+            Box::new(Expression::IdentifierExpression(
+                Identifier(name.to_string()),
+                Span::default(),
+            ))
+        };
+        let input = "a[i..][..j];".as_bytes();
+        let program: Program = vec![Statement::ExpressionStatement {
+            expression: Expression::SliceExpression(
+                SliceExpr {
+                    lhs: Box::new(Expression::SliceExpression(
+                        SliceExpr {
+                            lhs: ident("a"),
+                            start: Some(ident("i")),
+                            end: None,
+                        },
+                        Span::default(),
+                    )),
+                    start: None,
+                    end: Some(ident("j")),
+                },
+                Span::default(),
+            ),
+            has_semicolon: true,
+            span: Span::default(),
+        }];
+        assert_input_with_program(input, program);
+
+        for input in ["a[1..2..3];", "a[...];", "a[1..] = b;"] {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            assert!(Parser::parse(Tokens::new(&tokens)).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn range_expression() {
+        let range = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            let [Statement::ExpressionStatement {
+                expression: Expression::ForExpression(expr, _),
+                ..
+            }] = &program[..]
+            else {
+                panic!("expected a for loop, got {:?}", program);
+            };
+            let Expression::RangeExpression(range, _) = &*expr.rhs else {
+                panic!("expected a range, got {:?}", expr.rhs);
+            };
+            range.clone()
+        };
+        let exclusive = range("for i in 0..n + 1 {}");
+        assert!(!exclusive.inclusive);
+        // the end binds tighter than the range
+        assert!(matches!(*exclusive.end, Expression::InfixExpression(_, _)));
+        assert!(range("for i in a.len()..=b {}").inclusive);
+
+        // ranges don't chain, so neither do slices
+        for input in ["let r = 1..2..3;", "let r = 1..=2..3;"] {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            assert!(Parser::parse(Tokens::new(&tokens)).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn tuples() {
+        let parse = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            Parser::parse(Tokens::new(&tokens))
+                .map(|(_, program)| program)
+                .ok()
+        };
+        let program = parse("let (a, (_, c)) = ((1, 2), (3,)); (x); pair.0.1;").unwrap();
+        let [Statement::DeclarationStatement(
+            Declaration::DestructuringDeclaration(pattern, value, _, _),
+            _,
+        ), Statement::ExpressionStatement {
+            expression: parenthesized,
+            ..
+        }, Statement::ExpressionStatement {
+            expression: Expression::TupleIndexExpression(index, _),
+            ..
+        }] = &program[..]
+        else {
+            panic!(
+                "expected a destructuring and two expressions, got {:?}",
+                program
+            );
+        };
+        assert_eq!(
+            *pattern,
+            Pattern::Tuple(vec![
+                Pattern::Binding(Identifier("a".to_string())),
+                Pattern::Tuple(vec![
+                    Pattern::Wildcard,
+                    Pattern::Binding(Identifier("c".to_string()))
+                ]),
+            ])
+        );
+        let Expression::TupleExpression(items, _) = value else {
+            panic!("expected a tuple, got {:?}", value);
+        };
+        // a trailing comma makes a tuple of one item, parentheses alone don't
+        assert!(matches!(&items[1], Expression::TupleExpression(items, _) if items.len() == 1));
+        assert!(matches!(
+            parenthesized,
+            Expression::IdentifierExpression(_, _)
+        ));
+        assert_eq!(index.index, 1);
+        assert!(matches!(
+            &*index.lhs,
+            Expression::TupleIndexExpression(pair, _) if pair.index == 0
+        ));
+
+        // destructurings can't fail to match
+        assert!(parse("let (a, 1) = pair;").is_none());
+    }
+
+    #[test]
+    fn destructuring() {
+        let parse = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            Parser::parse(Tokens::new(&tokens))
+                .map(|(_, program)| program)
+                .ok()
+        };
+        let binding = |name: &str| Pattern::Binding(Identifier(name.to_string()));
+        let program = parse("let Point { x, y: (a, _), .. } = p;").unwrap();
+        let [Statement::DeclarationStatement(
+            Declaration::DestructuringDeclaration(pattern, _, _, _),
+            _,
+        )] = &program[..]
+        else {
+            panic!("expected a destructuring, got {:?}", program);
+        };
+        assert_eq!(
+            *pattern,
+            Pattern::Struct(
+                Identifier("Point".to_string()),
+                vec![
+                    (Identifier("x".to_string()), binding("x")),
+                    (
+                        Identifier("y".to_string()),
+                        Pattern::Tuple(vec![binding("a"), Pattern::Wildcard])
+                    ),
+                ]
+            )
+        );
+        // match arms don't take struct patterns, only destructurings do
+        assert!(parse("match p { Point { x } => x }").is_none());
+
+        // a destructured parameter is named after its position and destructured at the start of
+        // the body
+        let program = parse("fn f(n: number, (a, b): (number, number)) { a }").unwrap();
+        let [Statement::DeclarationStatement(
+            Declaration::FunctionDeclaration {
+                parameters, body, ..
+            },
+            _,
+        )] = &program[..]
+        else {
+            panic!("expected a function, got {:?}", program);
+        };
+        assert_eq!(parameters[1].name, Identifier("__arg2".to_string()));
+        assert_eq!(
+            parameters[1].type_,
+            Identifier("(number, number)".to_string())
+        );
+        assert!(matches!(
+                    &body.statements[..],
+                    [Statement::DeclarationStatement(
+                        Declaration::DestructuringDeclaration(
+                            Pattern::Tuple(_),
+                            Expression::IdentifierExpression(Identifier(name), _),
+                            _,
+                            None,
+        ),
+                        _,
+                    )] if name == "__arg2"
+                ));
+        // closures wrap a body that isn't a block in one
+        let program = parse("xs.map(|Point { x, .. }| x);").unwrap();
+        let [Statement::ExpressionStatement {
+            expression: Expression::CallExpression(call, _),
+            ..
+        }] = &program[..]
+        else {
+            panic!("expected a call, got {:?}", program);
+        };
+        let Expression::ClosureExpression(closure, _) = &call.arguments[0] else {
+            panic!("expected a closure, got {:?}", call.arguments[0]);
+        };
+        assert_eq!(closure.parameters[0].name, Identifier("__arg1".to_string()));
+        assert!(matches!(
+            &*closure.body,
+            Expression::BlockExpression(block, _)
+                if block.statements.len() == 1 && block.return_value.is_some()
+        ));
+    }
+
+    #[test]
+    fn closure_expression() {
+        let ident = |name: &str| {
+            Box::new(Expression::IdentifierExpression(
+                Identifier(name.to_string()),
+                Span::default(),
+            ))
+        };
+        let parameter = |name: &str, type_: &str| Parameter {
+            name: Identifier(name.to_string()),
+            type_: Identifier(type_.to_string()),
+            span: Span::default(),
+        };
+        let input = "f(|x, y: number| x | y, || g);".as_bytes();
+        let program: Program = vec![Statement::ExpressionStatement {
+            expression: Expression::CallExpression(
+                CallExpr {
+                    lhs: ident("f"),
+                    arguments: vec![
+                        Expression::ClosureExpression(
+                            ClosureExpr {
+                                is_async: false,
+                                parameters: vec![parameter("x", "any"), parameter("y", "number")],
+                                body: Box::new(Expression::InfixExpression(
+                                    InfixExpr {
+                                        op: InfixOperator::BitwiseOr,
+                                        lhs: ident("x"),
+                                        rhs: ident("y"),
+                                    },
+                                    Span::default(),
+                                )),
+                            },
+                            Span::default(),
+                        ),
+                        Expression::ClosureExpression(
+                            ClosureExpr {
+                                is_async: false,
+                                parameters: vec![],
+                                body: ident("g"),
+                            },
+                            Span::default(),
+                        ),
+                    ],
+                },
+                Span::default(),
+            ),
+            has_semicolon: true,
+            span: Span::default(),
+        }];
+        assert_input_with_program(input, program);
+
+        for input in ["|x y| x;", "|x|;", "|x, 1| x;"] {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            assert!(Parser::parse(Tokens::new(&tokens)).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn jump_expressions() {
+        let input = "while x { if y { break; } continue; } return; return x ?? return".as_bytes();
+        let ident = |name: &str| {
+            Box::new(Expression::IdentifierExpression(
+                Identifier(name.to_string()),
+                Span::default(),
+            ))
+        };
+        let program: Program = vec![
+            Statement::ExpressionStatement {
+                expression: Expression::WhileExpression(
+                    WhileExpr {
+                        condition: ident("x"),
+                        body: Box::new(Block {
+                            statements: vec![
+                                Statement::ExpressionStatement {
+                                    expression: Expression::IfExpression(
+                                        IfExpr {
+                                            condition: ident("y"),
+                                            then_block: Box::new(Block {
+                                                statements: vec![Statement::ExpressionStatement {
+                                                    expression: Expression::BreakExpression(
+                                                        Span::default(),
+                                                    ),
+                                                    has_semicolon: true,
+                                                    span: Span::default(),
+                                                }],
+                                                return_value: None,
+                                                span: Span::default(),
+                                            }),
+                                            else_if_blocks: vec![],
+                                            else_block: None,
+                                        },
+                                        Span::default(),
+                                    ),
+                                    has_semicolon: false,
+                                    span: Span::default(),
+                                },
+                                Statement::ExpressionStatement {
+                                    expression: Expression::ContinueExpression(Span::default()),
+                                    has_semicolon: true,
+                                    span: Span::default(),
+                                },
+                            ],
+                            return_value: None,
+                            span: Span::default(),
+                        }),
+                    },
+                    Span::default(),
+                ),
+                has_semicolon: false,
+                span: Span::default(),
+            },
+            Statement::ExpressionStatement {
+                expression: Expression::ReturnExpression(None, Span::default()),
+                has_semicolon: true,
+                span: Span::default(),
+            },
+            Statement::ExpressionStatement {
+                expression: Expression::ReturnExpression(
+                    Some(Box::new(Expression::InfixExpression(
+                        InfixExpr {
+                            op: InfixOperator::NullishCoalesce,
+                            lhs: ident("x"),
+                            rhs: Box::new(Expression::ReturnExpression(None, Span::default())),
+                        },
+                        Span::default(),
+                    ))),
+                    Span::default(),
+                ),
+                has_semicolon: false,
+                span: Span::default(),
+            },
+        ];
+        assert_input_with_program(input, program);
+    }
+
+    #[test]
+    fn cast_expression() {
+        let input = "a + Status::Ok as number * 2".as_bytes();
+        let program: Program = vec![Statement::ExpressionStatement {
+            expression: Expression::InfixExpression(
+                InfixExpr {
+                    op: InfixOperator::Plus,
+                    lhs: Box::new(Expression::IdentifierExpression(
+                        Identifier("a".to_string()),
+                        Span::default(),
+                    )),
+                    rhs: Box::new(Expression::InfixExpression(
+                        InfixExpr {
+                            op: InfixOperator::Multiply,
+                            lhs: Box::new(Expression::CastExpression(
+                                CastExpr {
+                                    value: Box::new(Expression::PathExpression(
+                                        PathExpr {
+                                            segments: vec![
+                                                Identifier("Status".to_string()),
+                                                Identifier("Ok".to_string()),
+                                            ],
+                                        },
+                                        Span::default(),
+                                    )),
+                                    type_: Identifier("number".to_string()),
+                                },
+                                Span::default(),
+                            )),
+                            rhs: Box::new(Expression::LiteralExpression(
+                                Literal::NumberLiteral(Number::I {
+                                    base: NumberBase::Dec,
+                                    value: 2,
+                                }),
+                                Span::default(),
+                            )),
+                        },
+                        Span::default(),
+                    )),
+                },
+                Span::default(),
+            ),
+            has_semicolon: false,
+            span: Span::default(),
+        }];
+        assert_input_with_program(input, program);
+    }
+
+    #[test]
+    fn mod_and_use_declarations() {
+        let input = "mod util; use util::double; use geometry::{area, SCALE};".as_bytes();
+        let program: Program = vec![
+            Statement::DeclarationStatement(
+                Declaration::ModFileDeclaration(Identifier("util".to_string())),
+                Span::default(),
+            ),
+            Statement::DeclarationStatement(
+                Declaration::UseDeclaration(UseDecl {
+                    path: vec![Identifier("util".to_string())],
+                    items: vec![Identifier("double".to_string())],
+                }),
+                Span::default(),
+            ),
+            Statement::DeclarationStatement(
+                Declaration::UseDeclaration(UseDecl {
+                    path: vec![Identifier("geometry".to_string())],
+                    items: vec![
+                        Identifier("area".to_string()),
+                        Identifier("SCALE".to_string()),
+                    ],
+                }),
+                Span::default(),
+            ),
+        ];
+        assert_input_with_program(input, program);
+    }
+
+    #[test]
+    fn pub_declarations() {
+        let parse = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            Parser::parse(Tokens::new(&tokens))
+                .map(|(_, program)| program)
+                .map_err(|err| err.to_string())
+        };
+        let program = parse(
+            r#"
+            pub const VERSION = "1.0";
+            #[derive(Serialize)] pub struct Point { x: number }
+            #[wasm] pub fn add(a: i32, b: i32) { a + b }
+            fn helper() { 1 }
+            mod shapes { pub fn area() { 0 } }
+        "#,
+        )
+        .unwrap();
+        let visibility = program
+            .iter()
+            .map(|statement| match statement {
+                Statement::DeclarationStatement(declaration, _) => declaration.is_pub(),
+                Statement::ExpressionStatement { .. } => false,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(visibility, vec![true, true, true, false, false]);
+        let Statement::DeclarationStatement(Declaration::StructDeclaration(point), _) = &program[1]
+        else {
+            panic!("expected a struct, got {:?}", program[1]);
+        };
+        assert_eq!(point.derives, vec![Identifier("Serialize".to_string())]);
+        let Statement::DeclarationStatement(Declaration::FunctionDeclaration { attributes, .. }, _) =
+            &program[2]
+        else {
+            panic!("expected a function, got {:?}", program[2]);
+        };
+        assert_eq!(attributes, &vec![Identifier("wasm".to_string())]);
+
+        // only top level functions, constants and structs can be `pub`
+        assert!(parse("pub let x = 1;").is_err());
+        assert!(parse("pub enum Shape { Empty }").is_err());
+        assert!(parse("fn f() { pub const X = 1; X }").is_err());
+    }
+
     #[test]
     fn function_implicit_return() {
         let input = "\
@@ -243,27 +1016,41 @@ mod tests {
         .as_bytes();
         let program: Program = vec![Statement::DeclarationStatement(
             Declaration::FunctionDeclaration {
+                attributes: vec![],
+                is_pub: false,
+                is_async: false,
+                is_const: false,
                 name: Identifier("test".to_string()),
+                type_parameters: vec![],
                 parameters: vec![],
+                return_type: None,
+                where_clause: vec![],
                 body: Block {
                     statements: vec![],
-                    return_value: Some(Expression::InfixExpression(InfixExpr {
-                        op: InfixOperator::Minus,
-                        lhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                            Number::I {
-                                base: NumberBase::Dec,
-                                value: 5,
-                            },
-                        ))),
-                        rhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                            Number::I {
-                                base: NumberBase::Dec,
-                                value: 10,
-                            },
-                        ))),
-                    })),
+                    return_value: Some(Expression::InfixExpression(
+                        InfixExpr {
+                            op: InfixOperator::Minus,
+                            lhs: Box::new(Expression::LiteralExpression(
+                                Literal::NumberLiteral(Number::I {
+                                    base: NumberBase::Dec,
+                                    value: 5,
+                                }),
+                                Span::default(),
+                            )),
+                            rhs: Box::new(Expression::LiteralExpression(
+                                Literal::NumberLiteral(Number::I {
+                                    base: NumberBase::Dec,
+                                    value: 10,
+                                }),
+                                Span::default(),
+                            )),
+                        },
+                        Span::default(),
+                    )),
+                    span: Span::default(),
                 },
             },
+            Span::default(),
         )];
 
         assert_input_with_program(input, program);
@@ -278,41 +1065,57 @@ mod tests {
 
         let program: Program = vec![
             Statement::ExpressionStatement {
-                expression: Expression::IndexExpression(IndexExpr {
-                    lhs: Box::new(Expression::IdentifierExpression(Identifier(
-                        "array".to_string(),
-                    ))),
-                    index: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                        Number::I {
-                            base: NumberBase::Dec,
-                            value: 1,
-                        },
-                    ))),
-                }),
+                expression: Expression::IndexExpression(
+                    IndexExpr {
+                        lhs: Box::new(Expression::IdentifierExpression(
+                            Identifier("array".to_string()),
+                            Span::default(),
+                        )),
+                        index: Box::new(Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::I {
+                                base: NumberBase::Dec,
+                                value: 1,
+                            }),
+                            Span::default(),
+                        )),
+                    },
+                    Span::default(),
+                ),
                 has_semicolon: true,
+                span: Span::default(),
             },
             Statement::ExpressionStatement {
-                expression: Expression::IndexExpression(IndexExpr {
-                    lhs: Box::new(Expression::IdentifierExpression(Identifier(
-                        "array".to_string(),
-                    ))),
-                    index: Box::new(Expression::InfixExpression(InfixExpr {
-                        op: InfixOperator::Plus,
-                        lhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                            Number::I {
-                                base: NumberBase::Dec,
-                                value: 1,
-                            },
-                        ))),
-                        rhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                            Number::I {
-                                base: NumberBase::Dec,
-                                value: 2,
+                expression: Expression::IndexExpression(
+                    IndexExpr {
+                        lhs: Box::new(Expression::IdentifierExpression(
+                            Identifier("array".to_string()),
+                            Span::default(),
+                        )),
+                        index: Box::new(Expression::InfixExpression(
+                            InfixExpr {
+                                op: InfixOperator::Plus,
+                                lhs: Box::new(Expression::LiteralExpression(
+                                    Literal::NumberLiteral(Number::I {
+                                        base: NumberBase::Dec,
+                                        value: 1,
+                                    }),
+                                    Span::default(),
+                                )),
+                                rhs: Box::new(Expression::LiteralExpression(
+                                    Literal::NumberLiteral(Number::I {
+                                        base: NumberBase::Dec,
+                                        value: 2,
+                                    }),
+                                    Span::default(),
+                                )),
                             },
-                        ))),
-                    })),
-                }),
+                            Span::default(),
+                        )),
+                    },
+                    Span::default(),
+                ),
                 has_semicolon: true,
+                span: Span::default(),
             },
         ];
 
@@ -324,33 +1127,46 @@ mod tests {
         let input = "foo(20, 30 - 2);".as_bytes();
 
         let program: Program = vec![Statement::ExpressionStatement {
-            expression: Expression::CallExpression(CallExpr {
-                lhs: Box::new(Expression::IdentifierExpression(Identifier(
-                    "foo".to_string(),
-                ))),
-                arguments: vec![
-                    Expression::LiteralExpression(Literal::NumberLiteral(Number::I {
-                        base: NumberBase::Dec,
-                        value: 20,
-                    })),
-                    Expression::InfixExpression(InfixExpr {
-                        op: InfixOperator::Minus,
-                        lhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                            Number::I {
-                                base: NumberBase::Dec,
-                                value: 30,
-                            },
-                        ))),
-                        rhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                            Number::I {
+            expression: Expression::CallExpression(
+                CallExpr {
+                    lhs: Box::new(Expression::IdentifierExpression(
+                        Identifier("foo".to_string()),
+                        Span::default(),
+                    )),
+                    arguments: vec![
+                        Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::I {
                                 base: NumberBase::Dec,
-                                value: 2,
+                                value: 20,
+                            }),
+                            Span::default(),
+                        ),
+                        Expression::InfixExpression(
+                            InfixExpr {
+                                op: InfixOperator::Minus,
+                                lhs: Box::new(Expression::LiteralExpression(
+                                    Literal::NumberLiteral(Number::I {
+                                        base: NumberBase::Dec,
+                                        value: 30,
+                                    }),
+                                    Span::default(),
+                                )),
+                                rhs: Box::new(Expression::LiteralExpression(
+                                    Literal::NumberLiteral(Number::I {
+                                        base: NumberBase::Dec,
+                                        value: 2,
+                                    }),
+                                    Span::default(),
+                                )),
                             },
-                        ))),
-                    }),
-                ],
-            }),
+                            Span::default(),
+                        ),
+                    ],
+                },
+                Span::default(),
+            ),
             has_semicolon: true,
+            span: Span::default(),
         }];
 
         assert_input_with_program(input, program);
@@ -365,25 +1181,35 @@ mod tests {
         "#;
 
         let program: Program = vec![Statement::ExpressionStatement {
-            expression: Expression::BlockExpression(Box::new(Block {
-                statements: vec![],
-                return_value: Some(Expression::InfixExpression(InfixExpr {
-                    op: InfixOperator::Minus,
-                    lhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                        Number::I {
-                            base: NumberBase::Dec,
-                            value: 5,
-                        },
-                    ))),
-                    rhs: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                        Number::I {
-                            base: NumberBase::Dec,
-                            value: 10,
+            expression: Expression::BlockExpression(
+                Box::new(Block {
+                    statements: vec![],
+                    return_value: Some(Expression::InfixExpression(
+                        InfixExpr {
+                            op: InfixOperator::Minus,
+                            lhs: Box::new(Expression::LiteralExpression(
+                                Literal::NumberLiteral(Number::I {
+                                    base: NumberBase::Dec,
+                                    value: 5,
+                                }),
+                                Span::default(),
+                            )),
+                            rhs: Box::new(Expression::LiteralExpression(
+                                Literal::NumberLiteral(Number::I {
+                                    base: NumberBase::Dec,
+                                    value: 10,
+                                }),
+                                Span::default(),
+                            )),
                         },
-                    ))),
-                })),
-            })),
+                        Span::default(),
+                    )),
+                    span: Span::default(),
+                }),
+                Span::default(),
+            ),
             has_semicolon: false,
+            span: Span::default(),
         }];
 
         assert_input_with_program(input.as_bytes(), program);
@@ -401,45 +1227,60 @@ mod tests {
         };
         "#;
         let program: Program = vec![Statement::ExpressionStatement {
-            expression: Expression::IfExpression(IfExpr {
-                condition: Box::new(Expression::LiteralExpression(Literal::BooleanLiteral(true))),
-                then_block: Box::new(Block {
-                    statements: vec![],
-                    return_value: Some(Expression::LiteralExpression(Literal::NumberLiteral(
-                        Number::I {
-                            base: NumberBase::Dec,
-                            value: 1,
-                        },
-                    ))),
-                }),
-                else_if_blocks: vec![ElseIfExpr {
-                    condition: Box::new(Expression::LiteralExpression(Literal::BooleanLiteral(
-                        false,
-                    ))),
-                    then_block: Block {
-                        statements: vec![Statement::ExpressionStatement {
-                            expression: Expression::LiteralExpression(Literal::NumberLiteral(
-                                Number::I {
-                                    base: NumberBase::Dec,
-                                    value: 2,
-                                },
-                            )),
-                            has_semicolon: true,
-                        }],
-                        return_value: None,
-                    },
-                }],
-                else_block: Some(Box::new(Block {
-                    statements: vec![],
-                    return_value: Some(Expression::LiteralExpression(Literal::NumberLiteral(
-                        Number::I {
-                            base: NumberBase::Dec,
-                            value: 3,
+            expression: Expression::IfExpression(
+                IfExpr {
+                    condition: Box::new(Expression::LiteralExpression(
+                        Literal::BooleanLiteral(true),
+                        Span::default(),
+                    )),
+                    then_block: Box::new(Block {
+                        statements: vec![],
+                        return_value: Some(Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::I {
+                                base: NumberBase::Dec,
+                                value: 1,
+                            }),
+                            Span::default(),
+                        )),
+                        span: Span::default(),
+                    }),
+                    else_if_blocks: vec![ElseIfExpr {
+                        condition: Box::new(Expression::LiteralExpression(
+                            Literal::BooleanLiteral(false),
+                            Span::default(),
+                        )),
+                        then_block: Block {
+                            statements: vec![Statement::ExpressionStatement {
+                                expression: Expression::LiteralExpression(
+                                    Literal::NumberLiteral(Number::I {
+                                        base: NumberBase::Dec,
+                                        value: 2,
+                                    }),
+                                    Span::default(),
+                                ),
+                                has_semicolon: true,
+                                span: Span::default(),
+                            }],
+                            return_value: None,
+                            span: Span::default(),
                         },
-                    ))),
-                })),
-            }),
+                    }],
+                    else_block: Some(Box::new(Block {
+                        statements: vec![],
+                        return_value: Some(Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::I {
+                                base: NumberBase::Dec,
+                                value: 3,
+                            }),
+                            Span::default(),
+                        )),
+                        span: Span::default(),
+                    })),
+                },
+                Span::default(),
+            ),
             has_semicolon: true,
+            span: Span::default(),
         }];
         assert_input_with_program(input.as_bytes(), program);
     }
@@ -458,121 +1299,1040 @@ mod tests {
         "#;
 
         let program: Program = vec![
-            Statement::DeclarationStatement(Declaration::FunctionDeclaration {
-                name: Identifier("test".to_string()),
-                parameters: vec![],
-                body: Block {
-                    statements: vec![
-                        Statement::ExpressionStatement {
-                            expression: Expression::InfixExpression(InfixExpr {
-                                op: InfixOperator::Plus,
-                                lhs: Box::new(Expression::InfixExpression(InfixExpr {
-                                    op: InfixOperator::Minus,
-                                    lhs: Box::new(Expression::LiteralExpression(
+            Statement::DeclarationStatement(
+                Declaration::FunctionDeclaration {
+                    attributes: vec![],
+                    is_pub: false,
+                    is_async: false,
+                    is_const: false,
+                    name: Identifier("test".to_string()),
+                    type_parameters: vec![],
+                    parameters: vec![],
+                    return_type: None,
+                    where_clause: vec![],
+                    body: Block {
+                        statements: vec![
+                            Statement::ExpressionStatement {
+                                expression: Expression::InfixExpression(
+                                    InfixExpr {
+                                        op: InfixOperator::Plus,
+                                        lhs: Box::new(Expression::InfixExpression(
+                                            InfixExpr {
+                                                op: InfixOperator::Minus,
+                                                lhs: Box::new(Expression::LiteralExpression(
+                                                    Literal::NumberLiteral(Number::I {
+                                                        base: NumberBase::Dec,
+                                                        value: 5,
+                                                    }),
+                                                    Span::default(),
+                                                )),
+                                                rhs: Box::new(Expression::InfixExpression(
+                                                    InfixExpr {
+                                                        op: InfixOperator::Multiply,
+                                                        lhs: Box::new(
+                                                            Expression::LiteralExpression(
+                                                                Literal::NumberLiteral(Number::I {
+                                                                    base: NumberBase::Dec,
+                                                                    value: 10,
+                                                                }),
+                                                                Span::default(),
+                                                            ),
+                                                        ),
+                                                        rhs: Box::new(
+                                                            Expression::LiteralExpression(
+                                                                Literal::NumberLiteral(Number::I {
+                                                                    base: NumberBase::Dec,
+                                                                    value: 2,
+                                                                }),
+                                                                Span::default(),
+                                                            ),
+                                                        ),
+                                                    },
+                                                    Span::default(),
+                                                )),
+                                            },
+                                            Span::default(),
+                                        )),
+                                        rhs: Box::new(Expression::CallExpression(
+                                            CallExpr {
+                                                lhs: Box::new(Expression::IdentifierExpression(
+                                                    Identifier("foo".to_string()),
+                                                    Span::default(),
+                                                )),
+                                                arguments: vec![
+                                                    Expression::LiteralExpression(
+                                                        Literal::NumberLiteral(Number::I {
+                                                            base: NumberBase::Dec,
+                                                            value: 20,
+                                                        }),
+                                                        Span::default(),
+                                                    ),
+                                                    Expression::InfixExpression(
+                                                        InfixExpr {
+                                                            op: InfixOperator::Minus,
+                                                            lhs: Box::new(
+                                                                Expression::LiteralExpression(
+                                                                    Literal::NumberLiteral(
+                                                                        Number::I {
+                                                                            base: NumberBase::Dec,
+                                                                            value: 30,
+                                                                        },
+                                                                    ),
+                                                                    Span::default(),
+                                                                ),
+                                                            ),
+                                                            rhs: Box::new(
+                                                                Expression::LiteralExpression(
+                                                                    Literal::NumberLiteral(
+                                                                        Number::I {
+                                                                            base: NumberBase::Dec,
+                                                                            value: 2,
+                                                                        },
+                                                                    ),
+                                                                    Span::default(),
+                                                                ),
+                                                            ),
+                                                        },
+                                                        Span::default(),
+                                                    ),
+                                                ],
+                                            },
+                                            Span::default(),
+                                        )),
+                                    },
+                                    Span::default(),
+                                ),
+                                has_semicolon: true,
+                                span: Span::default(),
+                            },
+                            Statement::DeclarationStatement(
+                                Declaration::LetDeclaration(
+                                    Identifier("variable".to_string()),
+                                    Expression::LiteralExpression(
                                         Literal::NumberLiteral(Number::I {
                                             base: NumberBase::Dec,
                                             value: 5,
                                         }),
-                                    )),
-                                    rhs: Box::new(Expression::InfixExpression(InfixExpr {
-                                        op: InfixOperator::Multiply,
-                                        lhs: Box::new(Expression::LiteralExpression(
-                                            Literal::NumberLiteral(Number::I {
-                                                base: NumberBase::Dec,
-                                                value: 10,
-                                            }),
-                                        )),
-                                        rhs: Box::new(Expression::LiteralExpression(
-                                            Literal::NumberLiteral(Number::I {
-                                                base: NumberBase::Dec,
-                                                value: 2,
-                                            }),
-                                        )),
-                                    })),
-                                })),
-                                rhs: Box::new(Expression::CallExpression(CallExpr {
-                                    lhs: Box::new(Expression::IdentifierExpression(Identifier(
-                                        "foo".to_string(),
-                                    ))),
-                                    arguments: vec![
-                                        Expression::LiteralExpression(Literal::NumberLiteral(
-                                            Number::I {
-                                                base: NumberBase::Dec,
-                                                value: 20,
-                                            },
-                                        )),
-                                        Expression::InfixExpression(InfixExpr {
-                                            op: InfixOperator::Minus,
-                                            lhs: Box::new(Expression::LiteralExpression(
+                                        Span::default(),
+                                    ),
+                                    vec![],
+                                    None,
+                                ),
+                                Span::default(),
+                            ),
+                        ],
+                        return_value: Some(Expression::IdentifierExpression(
+                            Identifier("variable".to_string()),
+                            Span::default(),
+                        )),
+                        span: Span::default(),
+                    },
+                },
+                Span::default(),
+            ),
+            Statement::ExpressionStatement {
+                expression: Expression::CallExpression(
+                    CallExpr {
+                        lhs: Box::new(Expression::IdentifierExpression(
+                            Identifier("test".to_string()),
+                            Span::default(),
+                        )),
+                        arguments: vec![],
+                    },
+                    Span::default(),
+                ),
+                has_semicolon: true,
+                span: Span::default(),
+            },
+            Statement::ExpressionStatement {
+                expression: Expression::CallExpression(
+                    CallExpr {
+                        lhs: Box::new(Expression::MemberAccessExpression(
+                            MemberAccessExpr {
+                                lhs: Box::new(Expression::IdentifierExpression(
+                                    Identifier("console".into()),
+                                    Span::default(),
+                                )),
+                                ident: Identifier("log".into()),
+                            },
+                            Span::default(),
+                        )),
+                        arguments: vec![Expression::LiteralExpression(
+                            Literal::StringLiteral("Hello world".into()),
+                            Span::default(),
+                        )],
+                    },
+                    Span::default(),
+                ),
+                has_semicolon: true,
+                span: Span::default(),
+            },
+            Statement::ExpressionStatement {
+                expression: Expression::IndexExpression(
+                    IndexExpr {
+                        lhs: Box::new(Expression::IdentifierExpression(
+                            Identifier("array".to_string()),
+                            Span::default(),
+                        )),
+                        index: Box::new(Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::I {
+                                base: NumberBase::Dec,
+                                value: 1,
+                            }),
+                            Span::default(),
+                        )),
+                    },
+                    Span::default(),
+                ),
+                has_semicolon: true,
+                span: Span::default(),
+            },
+        ];
+
+        assert_input_with_program(input.as_bytes(), program);
+    }
+
+    #[test]
+    fn struct_declaration() {
+        let input = r#"
+            struct Point {
+                x: number,
+                y: number,
+            }
+            let p = Point { x: 1, y: 2 };
+        "#;
+
+        let program: Program = vec![
+            Statement::DeclarationStatement(
+                Declaration::StructDeclaration(StructDecl {
+                    name: Identifier("Point".to_string()),
+                    type_parameters: vec![],
+                    fields: vec![
+                        Field {
+                            name: Identifier("x".to_string()),
+                            type_: Identifier("number".to_string()),
+                            span: Span::default(),
+                        },
+                        Field {
+                            name: Identifier("y".to_string()),
+                            type_: Identifier("number".to_string()),
+                            span: Span::default(),
+                        },
+                    ],
+                    derives: vec![],
+                    is_pub: false,
+                }),
+                Span::default(),
+            ),
+            Statement::DeclarationStatement(
+                Declaration::LetDeclaration(
+                    Identifier("p".to_string()),
+                    Expression::StructExpression(
+                        StructExpr {
+                            name: Identifier("Point".to_string()),
+                            fields: vec![
+                                (
+                                    Identifier("x".to_string()),
+                                    Expression::LiteralExpression(
+                                        Literal::NumberLiteral(Number::I {
+                                            base: NumberBase::Dec,
+                                            value: 1,
+                                        }),
+                                        Span::default(),
+                                    ),
+                                ),
+                                (
+                                    Identifier("y".to_string()),
+                                    Expression::LiteralExpression(
+                                        Literal::NumberLiteral(Number::I {
+                                            base: NumberBase::Dec,
+                                            value: 2,
+                                        }),
+                                        Span::default(),
+                                    ),
+                                ),
+                            ],
+                        },
+                        Span::default(),
+                    ),
+                    vec![],
+                    None,
+                ),
+                Span::default(),
+            ),
+        ];
+
+        assert_input_with_program(input.as_bytes(), program);
+    }
+
+    #[test]
+    fn struct_derives() {
+        let input = r#"
+            #[derive(Serialize)]
+            #[derive(Deserialize,)]
+            struct Point { x: number }
+            mod shapes {
+                #[derive(Serialize, Deserialize)] pub struct Circle { radius: number }
+            }
+        "#;
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        let Statement::DeclarationStatement(Declaration::StructDeclaration(point), _) = &program[0]
+        else {
+            panic!("expected a struct, got {:?}", program[0]);
+        };
+        assert_eq!(
+            point.derives,
+            vec![
+                Identifier("Serialize".to_string()),
+                Identifier("Deserialize".to_string())
+            ]
+        );
+        let Statement::DeclarationStatement(Declaration::ModDeclaration(shapes), _) = &program[1]
+        else {
+            panic!("expected a module, got {:?}", program[1]);
+        };
+        let Declaration::StructDeclaration(circle) = &shapes.items[0].declaration else {
+            panic!("expected a struct, got {:?}", shapes.items[0]);
+        };
+        assert!(shapes.items[0].is_pub);
+        assert_eq!(circle.derives.len(), 2);
+
+        let (_, tokens) =
+            Lexer::lex_tokens(b"#[derive(Debug)] struct Point { x: number }").unwrap();
+        assert!(Parser::parse(Tokens::new(&tokens)).is_err());
+    }
+
+    #[test]
+    fn impl_declaration() {
+        let input = r#"
+            impl Point {
+                fn origin() {
+                    Point { x: 0, y: 0 }
+                }
+                fn add(self, x: number) -> number {
+                    self.x + x
+                }
+            }
+        "#;
+
+        let program: Program = vec![Statement::DeclarationStatement(
+            Declaration::ImplDeclaration(ImplDecl {
+                target: Identifier("Point".to_string()),
+                trait_: None,
+                items: vec![
+                    ImplItem::Method(Method {
+                        name: Identifier("origin".to_string()),
+                        has_self: false,
+                        is_async: false,
+                        parameters: vec![],
+                        return_type: None,
+                        body: Block {
+                            statements: vec![],
+                            return_value: Some(Expression::StructExpression(
+                                StructExpr {
+                                    name: Identifier("Point".to_string()),
+                                    fields: vec![
+                                        (
+                                            Identifier("x".to_string()),
+                                            Expression::LiteralExpression(
                                                 Literal::NumberLiteral(Number::I {
                                                     base: NumberBase::Dec,
-                                                    value: 30,
+                                                    value: 0,
                                                 }),
-                                            )),
-                                            rhs: Box::new(Expression::LiteralExpression(
+                                                Span::default(),
+                                            ),
+                                        ),
+                                        (
+                                            Identifier("y".to_string()),
+                                            Expression::LiteralExpression(
                                                 Literal::NumberLiteral(Number::I {
                                                     base: NumberBase::Dec,
-                                                    value: 2,
+                                                    value: 0,
                                                 }),
-                                            )),
-                                        }),
+                                                Span::default(),
+                                            ),
+                                        ),
                                     ],
-                                })),
-                            }),
-                            has_semicolon: true,
+                                },
+                                Span::default(),
+                            )),
+                            span: Span::default(),
                         },
-                        Statement::DeclarationStatement(Declaration::LetDeclaration(
-                            Identifier("variable".to_string()),
-                            Expression::LiteralExpression(Literal::NumberLiteral(Number::I {
-                                base: NumberBase::Dec,
-                                value: 5,
-                            })),
-                        )),
+                        span: Span::default(),
+                    }),
+                    ImplItem::Method(Method {
+                        name: Identifier("add".to_string()),
+                        has_self: true,
+                        is_async: false,
+                        parameters: vec![Parameter {
+                            name: Identifier("x".to_string()),
+                            type_: Identifier("number".to_string()),
+                            span: Span::default(),
+                        }],
+                        return_type: Some(Identifier("number".to_string())),
+                        body: Block {
+                            statements: vec![],
+                            return_value: Some(Expression::InfixExpression(
+                                InfixExpr {
+                                    op: InfixOperator::Plus,
+                                    lhs: Box::new(Expression::MemberAccessExpression(
+                                        MemberAccessExpr {
+                                            lhs: Box::new(Expression::IdentifierExpression(
+                                                Identifier("self".to_string()),
+                                                Span::default(),
+                                            )),
+                                            ident: Identifier("x".to_string()),
+                                        },
+                                        Span::default(),
+                                    )),
+                                    rhs: Box::new(Expression::IdentifierExpression(
+                                        Identifier("x".to_string()),
+                                        Span::default(),
+                                    )),
+                                },
+                                Span::default(),
+                            )),
+                            span: Span::default(),
+                        },
+                        span: Span::default(),
+                    }),
+                ],
+            }),
+            Span::default(),
+        )];
+
+        assert_input_with_program(input.as_bytes(), program);
+    }
+
+    #[test]
+    fn impl_accessors() {
+        let input = r#"
+            impl Rect {
+                get area() -> number {
+                    self.width * self.height
+                }
+                set width(width: number) {
+                    console.log(width);
+                }
+            }
+        "#;
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+
+        let Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), _) = &program[0]
+        else {
+            panic!("expected an impl declaration, got {:?}", program);
+        };
+        assert!(matches!(
+            &decl.items[0],
+            ImplItem::Getter(Method { name, has_self: true, parameters, return_type: Some(_), .. })
+                if name.0 == "area" && parameters.is_empty()
+        ));
+        assert!(matches!(
+            &decl.items[1],
+            ImplItem::Setter(Method { name, has_self: true, parameters, .. })
+                if name.0 == "width" && parameters.len() == 1
+        ));
+    }
+
+    #[test]
+    fn traits_and_bounds() {
+        let input = r#"
+            trait Ord {
+                fn cmp(self, other: Self) -> number;
+                fn is_newer(self, other: Self) -> bool {
+                    self.cmp(other) > 0
+                }
+            }
+            impl Ord for Version {
+                fn cmp(self, other: Self) -> number {
+                    self.major - other.major
+                }
+            }
+            fn max<T: Ord + Display, U>(a: T, b: T) -> T where U: Ord, T: Clone, {
+                a
+            }
+        "#;
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+
+        let Statement::DeclarationStatement(Declaration::TraitDeclaration(decl), _) = &program[0]
+        else {
+            panic!("expected a trait declaration, got {:?}", program);
+        };
+        assert_eq!(decl.name.0, "Ord");
+        assert!(matches!(
+            &decl.methods[..],
+            [
+                MethodSignature { name, has_self: true, parameters, return_type: Some(_), default: None, .. },
+                MethodSignature { default: Some(default), .. },
+            ] if name.0 == "cmp" && parameters.len() == 1 && default.return_value.is_some()
+        ));
+        let Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), _) = &program[1]
+        else {
+            panic!("expected an impl declaration, got {:?}", program);
+        };
+        assert_eq!(decl.target.0, "Version");
+        assert_eq!(decl.trait_, Some(Identifier("Ord".to_string())));
+        let Statement::DeclarationStatement(
+            Declaration::FunctionDeclaration {
+                type_parameters,
+                return_type,
+                where_clause,
+                ..
+            },
+            _,
+        ) = &program[2]
+        else {
+            panic!("expected a function declaration, got {:?}", program);
+        };
+        assert_eq!(
+            type_parameters,
+            &vec![
+                TypeParameter {
+                    name: Identifier("T".to_string()),
+                    bounds: vec![
+                        Identifier("Ord".to_string()),
+                        Identifier("Display".to_string())
                     ],
-                    return_value: Some(Expression::IdentifierExpression(Identifier(
-                        "variable".to_string(),
-                    ))),
                 },
-            }),
-            Statement::ExpressionStatement {
-                expression: Expression::CallExpression(CallExpr {
-                    lhs: Box::new(Expression::IdentifierExpression(Identifier(
-                        "test".to_string(),
-                    ))),
-                    arguments: vec![],
+                TypeParameter {
+                    name: Identifier("U".to_string()),
+                    bounds: vec![],
+                },
+            ]
+        );
+        assert_eq!(return_type, &Some(Identifier("T".to_string())));
+        assert_eq!(
+            TypeParameter::with_where_clause(type_parameters, where_clause),
+            vec![
+                TypeParameter {
+                    name: Identifier("T".to_string()),
+                    bounds: vec![
+                        Identifier("Ord".to_string()),
+                        Identifier("Display".to_string()),
+                        Identifier("Clone".to_string())
+                    ],
+                },
+                TypeParameter {
+                    name: Identifier("U".to_string()),
+                    bounds: vec![Identifier("Ord".to_string())],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn colon_return_types() {
+        let input = r#"
+            trait Display {
+                fn fmt(self): string;
+            }
+            impl Display for Point {
+                fn fmt(self): string {
+                    f"({self.x}, {self.y})"
+                }
+            }
+            fn origin(): Point {
+                Point { x: 0, y: 0 }
+            }
+        "#;
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+
+        let string = Some(Identifier("string".to_string()));
+        let [Statement::DeclarationStatement(Declaration::TraitDeclaration(trait_), _), Statement::DeclarationStatement(Declaration::ImplDeclaration(impl_), _), Statement::DeclarationStatement(
+            Declaration::FunctionDeclaration { return_type, .. },
+            _,
+        )] = &program[..]
+        else {
+            panic!(
+                "expected a trait, an impl and a function, got {:?}",
+                program
+            );
+        };
+        assert_eq!(trait_.methods[0].return_type, string);
+        assert!(
+            matches!(&impl_.items[0], ImplItem::Method(method) if method.return_type == string)
+        );
+        assert_eq!(return_type, &Some(Identifier("Point".to_string())));
+    }
+
+    #[test]
+    fn defer_statements() {
+        let input = "fn f() -> number { defer { close(); } 1 } fn g() { defer { close(); } }";
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+
+        // a `defer` is a statement even as the last thing in its block
+        let return_values = program
+            .iter()
+            .map(|statement| match statement {
+                Statement::DeclarationStatement(
+                    Declaration::FunctionDeclaration { body, .. },
+                    _,
+                ) => {
+                    assert!(matches!(
+                        &body.statements[..],
+                        [Statement::ExpressionStatement {
+                            expression: Expression::DeferExpression(_, _),
+                            ..
+                        }]
+                    ));
+                    body.return_value.is_some()
+                }
+                statement => panic!("expected a function, got {:?}", statement),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(return_values, vec![true, false]);
+    }
+
+    #[test]
+    fn with_blocks() {
+        let input = "with file = fs::open(path) { file.read() }";
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+
+        let [Statement::ExpressionStatement {
+            expression: Expression::WithExpression(expr, _),
+            ..
+        }] = &program[..]
+        else {
+            panic!("expected a `with`, got {:?}", program);
+        };
+        assert_eq!(expr.name, Identifier("file".to_string()));
+        assert!(matches!(&*expr.value, Expression::CallExpression(_, _)));
+        assert!(expr.body.statements.is_empty());
+        assert!(expr.body.return_value.is_some());
+    }
+
+    #[test]
+    fn async_functions() {
+        let input = "async fn f() -> number { g().await + 1 } let h = async |x| x.await;";
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+
+        let Statement::DeclarationStatement(
+            Declaration::FunctionDeclaration { is_async, body, .. },
+            _,
+        ) = &program[0]
+        else {
+            panic!("expected a function declaration, got {:?}", program);
+        };
+        assert!(is_async);
+        // `.await` binds tighter than `+`
+        assert!(matches!(
+            &body.return_value,
+            Some(Expression::InfixExpression(InfixExpr { lhs, .. }, _))
+                if matches!(**lhs, Expression::AwaitExpression(_, _))
+        ));
+        assert!(matches!(
+            &program[1],
+            Statement::DeclarationStatement(
+                Declaration::LetDeclaration(_, Expression::ClosureExpression(closure, _), _, None),
+                _
+            ) if closure.is_async
+        ));
+    }
+
+    #[test]
+    fn trait_objects() {
+        let input = "let shapes: [dyn Shape] = []; fn f(shape: dyn Shape) -> dyn Shape { shape }";
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+
+        assert!(matches!(
+            &program[0],
+            Statement::DeclarationStatement(
+                Declaration::LetDeclaration(name, Expression::ArrayExpression(..), _, Some(type_)),
+                _
+            ) if name.0 == "shapes" && type_.0 == "[dyn Shape]"
+        ));
+        let Statement::DeclarationStatement(
+            Declaration::FunctionDeclaration {
+                parameters,
+                return_type,
+                ..
+            },
+            _,
+        ) = &program[1]
+        else {
+            panic!("expected a function declaration, got {:?}", program);
+        };
+        assert_eq!(parameters[0].type_.0, "dyn Shape");
+        assert_eq!(return_type, &Some(Identifier("dyn Shape".to_string())));
+    }
+
+    #[test]
+    fn impl_return_types() {
+        let return_type = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            let Statement::DeclarationStatement(
+                Declaration::FunctionDeclaration { return_type, .. },
+                _,
+            ) = &program[0]
+            else {
+                panic!("expected a function declaration, got {:?}", program);
+            };
+            return_type.clone().map(|type_| type_.0)
+        };
+        assert_eq!(
+            return_type("fn f() -> impl Fn(number, [string]) -> number | null { g }"),
+            Some("impl Fn(number, [string]) -> number | null".to_string())
+        );
+        assert_eq!(
+            return_type("fn f() -> impl Fn() -> impl Fn(number) { g }"),
+            Some("impl Fn() -> impl Fn(number)".to_string())
+        );
+        assert_eq!(
+            return_type("fn f() -> impl Shape { g }"),
+            Some("impl Shape".to_string())
+        );
+        // only return types can be `impl` types
+        let (_, tokens) = Lexer::lex_tokens(b"fn f(g: impl Shape) { g }").unwrap();
+        assert!(Parser::parse(Tokens::new(&tokens)).is_err());
+    }
+
+    #[test]
+    fn impl_accessor_parameter_count() {
+        for input in [
+            "impl Rect { get area(x: number) { 1 } }",
+            "impl Rect { set width() { 1 } }",
+            "impl Rect { set width(a: number, b: number) { 1 } }",
+        ] {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            assert!(Parser::parse(Tokens::new(&tokens)).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn associated_items() {
+        let input = r#"
+            impl Circle {
+                const PI = 3.14159;
+            }
+            Circle::new(2);
+        "#;
+
+        let program: Program = vec![
+            Statement::DeclarationStatement(
+                Declaration::ImplDeclaration(ImplDecl {
+                    target: Identifier("Circle".to_string()),
+                    trait_: None,
+                    items: vec![ImplItem::Const(
+                        Identifier("PI".to_string()),
+                        Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::F("3.14159".to_string())),
+                            Span::default(),
+                        ),
+                    )],
                 }),
+                Span::default(),
+            ),
+            Statement::ExpressionStatement {
+                expression: Expression::CallExpression(
+                    CallExpr {
+                        lhs: Box::new(Expression::PathExpression(
+                            PathExpr {
+                                segments: vec![
+                                    Identifier("Circle".to_string()),
+                                    Identifier("new".to_string()),
+                                ],
+                            },
+                            Span::default(),
+                        )),
+                        arguments: vec![Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::I {
+                                base: NumberBase::Dec,
+                                value: 2,
+                            }),
+                            Span::default(),
+                        )],
+                    },
+                    Span::default(),
+                ),
                 has_semicolon: true,
+                span: Span::default(),
             },
+        ];
+
+        assert_input_with_program(input.as_bytes(), program);
+    }
+
+    #[test]
+    fn path_expression() {
+        let input = r#"
+            geometry::shapes::Circle::PI.toFixed(2);
+            Self::new();
+        "#;
+
+        let program: Program = vec![
             Statement::ExpressionStatement {
-                expression: Expression::CallExpression(CallExpr {
-                    lhs: Box::new(Expression::MemberAccessExpression(MemberAccessExpr {
-                        lhs: Box::new(Expression::IdentifierExpression(Identifier(
-                            "console".into(),
-                        ))),
-                        ident: Identifier("log".into()),
-                    })),
-                    arguments: vec![Expression::LiteralExpression(Literal::StringLiteral(
-                        "Hello world".into(),
-                    ))],
-                }),
+                expression: Expression::CallExpression(
+                    CallExpr {
+                        lhs: Box::new(Expression::MemberAccessExpression(
+                            MemberAccessExpr {
+                                lhs: Box::new(Expression::PathExpression(
+                                    PathExpr {
+                                        segments: vec![
+                                            Identifier("geometry".to_string()),
+                                            Identifier("shapes".to_string()),
+                                            Identifier("Circle".to_string()),
+                                            Identifier("PI".to_string()),
+                                        ],
+                                    },
+                                    Span::default(),
+                                )),
+                                ident: Identifier("toFixed".to_string()),
+                            },
+                            Span::default(),
+                        )),
+                        arguments: vec![Expression::LiteralExpression(
+                            Literal::NumberLiteral(Number::I {
+                                base: NumberBase::Dec,
+                                value: 2,
+                            }),
+                            Span::default(),
+                        )],
+                    },
+                    Span::default(),
+                ),
                 has_semicolon: true,
+                span: Span::default(),
             },
             Statement::ExpressionStatement {
-                expression: Expression::IndexExpression(IndexExpr {
-                    lhs: Box::new(Expression::IdentifierExpression(Identifier(
-                        "array".to_string(),
-                    ))),
-                    index: Box::new(Expression::LiteralExpression(Literal::NumberLiteral(
-                        Number::I {
-                            base: NumberBase::Dec,
-                            value: 1,
-                        },
-                    ))),
-                }),
+                expression: Expression::CallExpression(
+                    CallExpr {
+                        lhs: Box::new(Expression::PathExpression(
+                            PathExpr {
+                                segments: vec![
+                                    Identifier("Self".to_string()),
+                                    Identifier("new".to_string()),
+                                ],
+                            },
+                            Span::default(),
+                        )),
+                        arguments: vec![],
+                    },
+                    Span::default(),
+                ),
                 has_semicolon: true,
+                span: Span::default(),
             },
         ];
 
         assert_input_with_program(input.as_bytes(), program);
     }
+
+    #[test]
+    fn mod_declaration() {
+        let input = r#"
+            mod geometry {
+                const SCALE = 2;
+
+                pub fn area(width: number, height: number) {
+                    width * height
+                }
+
+                pub mod shapes {}
+            }
+        "#;
+
+        let program: Program = vec![Statement::DeclarationStatement(
+            Declaration::ModDeclaration(ModDecl {
+                name: Identifier("geometry".to_string()),
+                items: vec![
+                    ModItem {
+                        is_pub: false,
+                        declaration: Declaration::ConstDeclaration(
+                            Identifier("SCALE".to_string()),
+                            Expression::LiteralExpression(
+                                Literal::NumberLiteral(Number::I {
+                                    base: NumberBase::Dec,
+                                    value: 2,
+                                }),
+                                Span::default(),
+                            ),
+                            false,
+                            vec![],
+                            None,
+                        ),
+                        span: Span::default(),
+                    },
+                    ModItem {
+                        is_pub: true,
+                        declaration: Declaration::FunctionDeclaration {
+                            attributes: vec![],
+                            is_pub: false,
+                            is_async: false,
+                            is_const: false,
+                            name: Identifier("area".to_string()),
+                            type_parameters: vec![],
+                            parameters: vec![
+                                Parameter {
+                                    name: Identifier("width".to_string()),
+                                    type_: Identifier("number".to_string()),
+                                    span: Span::default(),
+                                },
+                                Parameter {
+                                    name: Identifier("height".to_string()),
+                                    type_: Identifier("number".to_string()),
+                                    span: Span::default(),
+                                },
+                            ],
+                            return_type: None,
+                            where_clause: vec![],
+                            body: Block {
+                                statements: vec![],
+                                return_value: Some(Expression::InfixExpression(
+                                    InfixExpr {
+                                        op: InfixOperator::Multiply,
+                                        lhs: Box::new(Expression::IdentifierExpression(
+                                            Identifier("width".to_string()),
+                                            Span::default(),
+                                        )),
+                                        rhs: Box::new(Expression::IdentifierExpression(
+                                            Identifier("height".to_string()),
+                                            Span::default(),
+                                        )),
+                                    },
+                                    Span::default(),
+                                )),
+                                span: Span::default(),
+                            },
+                        },
+                        span: Span::default(),
+                    },
+                    ModItem {
+                        is_pub: true,
+                        declaration: Declaration::ModDeclaration(ModDecl {
+                            name: Identifier("shapes".to_string()),
+                            items: vec![],
+                        }),
+                        span: Span::default(),
+                    },
+                ],
+            }),
+            Span::default(),
+        )];
+
+        assert_input_with_program(input.as_bytes(), program);
+    }
+
+    #[test]
+    fn cancellation() {
+        let input = b"let x = 1; x";
+        let (_, tokens) = Lexer::lex_tokens(input).unwrap();
+
+        let cancellation = CancellationToken::new();
+        let (_, program) = Parser::parse_cancellable(Tokens::new(&tokens), &cancellation)
+            .unwrap()
+            .unwrap();
+        let (_, expected) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        assert_eq!(program, expected);
+
+        cancellation.clone().cancel();
+        assert_eq!(
+            Parser::parse_cancellable(Tokens::new(&tokens), &cancellation).map(|_| ()),
+            Err(Cancelled)
+        );
+    }
+
+    #[test]
+    fn token_indices() {
+        let input = b"let x = 1; fn f() { x } f();";
+        let (_, tokens) = Lexer::lex_tokens(input).unwrap();
+
+        let (_, (program, indices)) =
+            Parser::parse_with_token_indices(Tokens::new(&tokens)).unwrap();
+        let (_, expected) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        assert_eq!(program, expected);
+        assert_eq!(indices, vec![0, 5, 12]);
+    }
+
+    #[test]
+    fn recovery() {
+        let input = b"let x = ; let y = 1; fn f() { await g(); } let z = 2;";
+        let (_, tokens) = Lexer::lex_tokens(input).unwrap();
+
+        let parsed = Parser::parse_recovering(Tokens::new(&tokens));
+        let (_, expected) = Parser::parse(Tokens::new(
+            &tokens[4..9]
+                .iter()
+                .chain(&tokens[20..])
+                .cloned()
+                .collect::<Vec<_>>(),
+        ))
+        .unwrap();
+        assert_eq!(parsed.program, expected);
+        assert_eq!(parsed.indices, vec![4, 20]);
+        // the statements fail at `;` and `await`, not where they start
+        assert_eq!(parsed.errors, vec![3, 14]);
+        assert_eq!(tokens[3], Token::SemiColon);
+        assert_eq!(tokens[14], Token::Await);
+
+        let (_, tokens) = Lexer::lex_tokens(b"fn f() {").unwrap();
+        let parsed = Parser::parse_recovering(Tokens::new(&tokens));
+        assert!(parsed.program.is_empty());
+        assert_eq!(tokens[parsed.errors[0]], Token::EOF);
+    }
+
+    #[test]
+    fn spans() {
+        let input = b"let x = a + b * c;\nf(x)";
+        let (_, (tokens, spans)) = Lexer::lex_tokens_with_spans(input).unwrap();
+
+        let (_, program) = Parser::parse(Tokens::with_spans(&tokens, &spans, 3)).unwrap();
+        let (_, expected) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        assert_eq!(program, expected);
+        let source = |span: Span| {
+            assert_eq!(span.file_id, 3);
+            std::str::from_utf8(&input[span.start..span.end]).unwrap()
+        };
+        let Statement::DeclarationStatement(Declaration::LetDeclaration(_, value, _, _), span) =
+            &program[0]
+        else {
+            panic!("expected a let declaration, got {:?}", program[0]);
+        };
+        assert_eq!(source(*span), "let x = a + b * c;");
+        assert_eq!(source(value.span()), "a + b * c");
+        let Expression::InfixExpression(InfixExpr { rhs, .. }, _) = value else {
+            panic!("expected an infix expression, got {:?}", value);
+        };
+        assert_eq!(source(rhs.span()), "b * c");
+        assert_eq!(source(program[1].span()), "f(x)");
+    }
+
+    #[test]
+    fn walk_paths() {
+        let input = r#"
+            mod shapes {
+                pub fn area(size: number) {
+                    geometry::square(size) + if size > 0 { math::PI } else { 0 }
+                }
+            }
+            let total = [shapes::area(1), Self::new()];
+        "#;
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+
+        let mut paths = vec![];
+        visit::walk_program(&program, &mut |expression| {
+            if let Expression::PathExpression(path, _) = expression {
+                paths.push(
+                    path.segments
+                        .iter()
+                        .map(|segment| segment.0.as_str())
+                        .collect::<Vec<_>>()
+                        .join("::"),
+                );
+            }
+        });
+        assert_eq!(
+            paths,
+            vec!["geometry::square", "math::PI", "shapes::area", "Self::new"]
+        );
+    }
 }
@@ -0,0 +1,217 @@
+use nom::{
+    branch::alt,
+    combinator::{map, map_opt, opt},
+    multi::{many0, separated_list1},
+    sequence::{delimited, preceded, tuple},
+    IResult,
+};
+
+use crate::lexer::tokens::Tokens;
+
+use super::{
+    ast::{Declaration, Expression, ModDecl, ModItem, PathExpr, Program, Span, Statement, UseDecl},
+    atoms::*,
+    declaration::{parse_attribute, parse_declaration},
+    enums::discriminants,
+    parse_identifier, spanned,
+    structs::{is_struct_derive, parse_derive},
+    visit::walk_blocks_mut,
+};
+
+/// `mod name { ... }` or `mod name;`
+pub fn parse_mod_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    alt((
+        map(
+            tuple((
+                mod_tag,
+                parse_identifier,
+                l_squirly_tag,
+                many0(parse_mod_item),
+                r_squirly_tag,
+            )),
+            |(_, name, _, items, _)| Declaration::ModDeclaration(ModDecl { name, items }),
+        ),
+        map(
+            tuple((mod_tag, parse_identifier, semicolon_tag)),
+            |(_, name, _)| Declaration::ModFileDeclaration(name),
+        ),
+    ))(input)
+}
+
+/// Attributes and derives go in front of `pub`, like `#[wasm] pub fn`
+fn parse_mod_item(input: Tokens) -> IResult<Tokens, ModItem> {
+    map_opt(
+        spanned(tuple((
+            many0(parse_attribute),
+            many0(parse_derive),
+            opt(pub_tag),
+            parse_declaration,
+        ))),
+        |((mut attributes, derives, visibility, mut declaration), span)| {
+            if !attributes.is_empty() {
+                let (Declaration::FunctionDeclaration {
+                    attributes: declared,
+                    ..
+                }
+                | Declaration::ConstDeclaration(_, _, _, declared, _)
+                | Declaration::LetDeclaration(_, _, declared, _)
+                | Declaration::DestructuringDeclaration(_, _, declared, _)) = &mut declaration
+                else {
+                    return None;
+                };
+                attributes.append(declared);
+                *declared = attributes;
+            }
+            let mut derives = derives.into_iter().flatten().collect::<Vec<_>>();
+            match &mut declaration {
+                _ if derives.is_empty() => {}
+                Declaration::StructDeclaration(decl) if derives.iter().all(is_struct_derive) => {
+                    derives.append(&mut decl.derives);
+                    decl.derives = derives;
+                }
+                Declaration::EnumDeclaration(decl)
+                    if derives.iter().all(|derive| derive.0 == "Flags") =>
+                {
+                    decl.flags = discriminants(&decl.variants, true).is_some();
+                    if !decl.flags {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+            Some(ModItem {
+                is_pub: visibility.is_some(),
+                declaration,
+                span,
+            })
+        },
+    )(input)
+}
+
+/// `pub` in front of a top level function, constant or struct, with the attributes and derives
+/// in front of `pub` like in a module
+pub fn parse_pub_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    map_opt(parse_mod_item, |item| {
+        let mut declaration = item.declaration;
+        match &mut declaration {
+            _ if !item.is_pub => return None,
+            Declaration::FunctionDeclaration { is_pub, .. }
+            | Declaration::ConstDeclaration(_, _, is_pub, _, _) => *is_pub = true,
+            Declaration::StructDeclaration(decl) => decl.is_pub = true,
+            _ => return None,
+        }
+        Some(declaration)
+    })(input)
+}
+
+/// `use module::item;` or `use module::{item, ...};`
+pub fn parse_use_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    map_opt(
+        tuple((
+            use_tag,
+            parse_identifier,
+            many0(preceded(double_colon_tag, parse_identifier)),
+            opt(preceded(
+                double_colon_tag,
+                delimited(
+                    l_squirly_tag,
+                    separated_list1(comma_tag, parse_identifier),
+                    tuple((opt(comma_tag), r_squirly_tag)),
+                ),
+            )),
+            semicolon_tag,
+        )),
+        |(_, first, mut rest, group, _)| {
+            let items = match group {
+                Some(items) => items,
+                None => vec![rest.pop()?],
+            };
+            let mut path = vec![first];
+            path.append(&mut rest);
+            Some(Declaration::UseDeclaration(UseDecl { path, items }))
+        },
+    )(input)
+}
+
+impl UseDecl {
+    /// `const item = module::item;` for every item
+    pub fn bindings(&self) -> Vec<Declaration> {
+        self.items
+            .iter()
+            .map(|item| {
+                let mut segments = self.path.clone();
+                segments.push(item.clone());
+                Declaration::ConstDeclaration(
+                    item.clone(),
+                    Expression::PathExpression(PathExpr { segments }, Span::default()),
+                    false,
+                    vec![],
+                    None,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Replaces every `use` declaration with the constants it binds, for backends that resolve the
+/// paths themselves
+pub fn lower_uses(program: Program) -> Program {
+    let mut program = lower_use_statements(program);
+    for statement in &mut program {
+        if let Statement::DeclarationStatement(Declaration::ModDeclaration(decl), _) = statement {
+            decl.lower_uses();
+        }
+        walk_blocks_mut(statement, &mut |block| {
+            block.statements = lower_use_statements(std::mem::take(&mut block.statements));
+        });
+    }
+    program
+}
+
+fn lower_use_statements(statements: Vec<Statement>) -> Vec<Statement> {
+    statements
+        .into_iter()
+        .flat_map(|statement| match statement {
+            Statement::DeclarationStatement(Declaration::UseDeclaration(decl), span) => decl
+                .bindings()
+                .into_iter()
+                .map(|binding| Statement::DeclarationStatement(binding, span))
+                .collect(),
+            statement => vec![statement],
+        })
+        .collect()
+}
+
+impl ModDecl {
+    /// Replaces the `use` items of the module and its inner modules with the constants they bind,
+    /// `pub use` items are exported like other `pub` items
+    pub fn lower_uses(&mut self) {
+        self.items = std::mem::take(&mut self.items)
+            .into_iter()
+            .flat_map(|item| match item.declaration {
+                Declaration::UseDeclaration(decl) => decl
+                    .bindings()
+                    .into_iter()
+                    .map(|declaration| ModItem {
+                        is_pub: item.is_pub,
+                        declaration,
+                        span: item.span,
+                    })
+                    .collect(),
+                Declaration::ModDeclaration(mut decl) => {
+                    decl.lower_uses();
+                    vec![ModItem {
+                        is_pub: item.is_pub,
+                        declaration: Declaration::ModDeclaration(decl),
+                        span: item.span,
+                    }]
+                }
+                declaration => vec![ModItem {
+                    is_pub: item.is_pub,
+                    declaration,
+                    span: item.span,
+                }],
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,187 @@
+use nom::branch::alt;
+use nom::combinator::map;
+use nom::sequence::{pair, terminated};
+use nom::IResult;
+
+use crate::lexer::tokens::Tokens;
+
+use super::ast::{
+    CallExpr, Expression, IndexExpr, MemberAccessExpr, OptionalAccess, OptionalExpr, Span,
+};
+use super::atoms::{question_bracket_tag, question_period_tag, r_bracket_tag};
+use super::expression::parse_expression;
+use super::parse_identifier;
+
+/// `lhs?.ident` or `lhs?[index]`
+pub fn parse_optional_expression(input: Tokens, left: Expression) -> IResult<Tokens, Expression> {
+    map(
+        alt((
+            map(pair(question_period_tag, parse_identifier), |(_, ident)| {
+                OptionalAccess::Member(ident)
+            }),
+            map(
+                pair(
+                    question_bracket_tag,
+                    terminated(parse_expression, r_bracket_tag),
+                ),
+                |(_, index)| OptionalAccess::Index(Box::new(index)),
+            ),
+        )),
+        |access| {
+            Expression::OptionalExpression(
+                OptionalExpr {
+                    lhs: Box::new(left.clone()),
+                    access,
+                },
+                Span::default(),
+            )
+        },
+    )(input)
+}
+
+/// Whether the expression is a chain of calls, member accesses and indexing with a `?.` or `?[`
+pub fn has_optional(expression: &Expression) -> bool {
+    match expression {
+        Expression::OptionalExpression(_, _) => true,
+        Expression::CallExpression(expr, _) => has_optional(&expr.lhs),
+        Expression::MemberAccessExpression(expr, _) => has_optional(&expr.lhs),
+        Expression::IndexExpression(expr, _) => has_optional(&expr.lhs),
+        _ => false,
+    }
+}
+
+impl OptionalExpr {
+    /// Splits a chain of calls, member accesses and indexing at its last `?.` or `?[`, for
+    /// backends without optional chaining. `a?.b?[0].c()` is split into `a?.b` and `value[0].c()`,
+    /// which is evaluated with `value` bound to `a?.b` unless that is `None`
+    pub fn split_chain(
+        expression: &Expression,
+        value: &Expression,
+    ) -> Option<(Expression, Expression)> {
+        match expression {
+            Expression::OptionalExpression(expr, _) => {
+                let value = Box::new(value.clone());
+                let rest = match &expr.access {
+                    OptionalAccess::Member(ident) => Expression::MemberAccessExpression(
+                        MemberAccessExpr {
+                            lhs: value,
+                            ident: ident.clone(),
+                        },
+                        Span::default(),
+                    ),
+                    OptionalAccess::Index(index) => Expression::IndexExpression(
+                        IndexExpr {
+                            lhs: value,
+                            index: index.clone(),
+                        },
+                        Span::default(),
+                    ),
+                };
+                Some((*expr.lhs.clone(), rest))
+            }
+            Expression::CallExpression(expr, _) => {
+                let (receiver, rest) = OptionalExpr::split_chain(&expr.lhs, value)?;
+                Some((
+                    receiver,
+                    Expression::CallExpression(
+                        CallExpr {
+                            lhs: Box::new(rest),
+                            arguments: expr.arguments.clone(),
+                        },
+                        Span::default(),
+                    ),
+                ))
+            }
+            Expression::MemberAccessExpression(expr, _) => {
+                let (receiver, rest) = OptionalExpr::split_chain(&expr.lhs, value)?;
+                Some((
+                    receiver,
+                    Expression::MemberAccessExpression(
+                        MemberAccessExpr {
+                            lhs: Box::new(rest),
+                            ident: expr.ident.clone(),
+                        },
+                        Span::default(),
+                    ),
+                ))
+            }
+            Expression::IndexExpression(expr, _) => {
+                let (receiver, rest) = OptionalExpr::split_chain(&expr.lhs, value)?;
+                Some((
+                    receiver,
+                    Expression::IndexExpression(
+                        IndexExpr {
+                            lhs: Box::new(rest),
+                            index: expr.index.clone(),
+                        },
+                        Span::default(),
+                    ),
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::{
+        ast::{Identifier, Statement},
+        Parser,
+    };
+
+    use super::*;
+
+    fn expression(input: &str) -> Expression {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        match Parser::parse(Tokens::new(&tokens)).unwrap().1.pop() {
+            Some(Statement::ExpressionStatement { expression, .. }) => expression,
+            statement => panic!("expected an expression, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn optional_chains() {
+        assert_eq!(
+            expression("map?.get(key)"),
+            Expression::CallExpression(
+                CallExpr {
+                    lhs: Box::new(Expression::OptionalExpression(
+                        OptionalExpr {
+                            lhs: Box::new(expression("map")),
+                            access: OptionalAccess::Member(Identifier("get".to_string())),
+                        },
+                        Span::default()
+                    )),
+                    arguments: vec![expression("key")],
+                },
+                Span::default()
+            )
+        );
+        assert_eq!(
+            expression("items?[i + 1]"),
+            Expression::OptionalExpression(
+                OptionalExpr {
+                    lhs: Box::new(expression("items")),
+                    access: OptionalAccess::Index(Box::new(expression("i + 1"))),
+                },
+                Span::default()
+            )
+        );
+
+        let value = expression("value");
+        assert_eq!(
+            OptionalExpr::split_chain(&expression("a?.b?[0].c(d?.e)"), &value),
+            Some((expression("a?.b"), expression("value[0].c(d?.e)")))
+        );
+        assert_eq!(
+            OptionalExpr::split_chain(&expression("a.b[0]"), &value),
+            None
+        );
+        assert_eq!(
+            OptionalExpr::split_chain(&expression("(a?.b) + 1"), &value),
+            None
+        );
+    }
+}
@@ -0,0 +1,784 @@
+use nom::combinator::{map, map_opt, opt, verify};
+use nom::multi::{many1, separated_list0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, tuple};
+use nom::{branch::alt, IResult};
+
+use crate::lexer::tokens::Tokens;
+
+use super::ast::{
+    Block, CallExpr, Declaration, ElseIfExpr, Expression, Identifier, IfExpr, IndexExpr, InfixExpr,
+    InfixOperator, Literal, MatchArm, MatchExpr, MemberAccessExpr, Number, NumberBase, Pattern,
+    Span, Statement, TupleIndexExpr,
+};
+use super::expression::parse_expression;
+use super::visit::walk_expression_mut;
+use super::{atoms::*, consumed_span, parse_identifier, parse_identifier_str, parse_literal};
+
+/// `match value { pattern => body, ... }`. `match` is only a keyword in front of an expression and
+/// arms, so `text.match(pattern)` still calls a method of that name
+pub fn parse_match_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(
+        tuple((
+            match_tag,
+            parse_expression,
+            l_squirly_tag,
+            parse_match_arms,
+            r_squirly_tag,
+        )),
+        |(_, value, _, arms, _)| {
+            Expression::MatchExpression(
+                MatchExpr {
+                    value: Box::new(value),
+                    arms,
+                },
+                Span::default(),
+            )
+        },
+    )(input)
+}
+
+/// Arms are separated by commas, which are optional after bodies ending in `}` like in Rust
+fn parse_match_arms(input: Tokens) -> IResult<Tokens, Vec<MatchArm>> {
+    let mut input = input;
+    let mut arms = vec![];
+    while let Ok((rest, (pattern, guard, _, body))) = tuple((
+        parse_pattern,
+        opt(preceded(if_tag, parse_expression)),
+        fat_arrow_tag,
+        parse_expression,
+    ))(input)
+    {
+        let needs_comma = !matches!(
+            body,
+            Expression::BlockExpression(_, _)
+                | Expression::IfExpression(_, _)
+                | Expression::ForExpression(_, _)
+                | Expression::WhileExpression(_, _)
+                | Expression::MatchExpression(_, _)
+        );
+        arms.push(MatchArm {
+            pattern,
+            guard,
+            body,
+            span: consumed_span(input, rest),
+        });
+        input = rest;
+        match comma_tag(input) {
+            Ok((rest, _)) => input = rest,
+            Err(_) if needs_comma => break,
+            Err(_) => {}
+        }
+    }
+    Ok((input, arms))
+}
+
+/// `_`, a name, a tuple of patterns, or literals, string prefixes and enum variants separated by
+/// `|`. Only a pattern on its own can bind names, as the other alternatives wouldn't bind them
+pub fn parse_pattern(input: Tokens) -> IResult<Tokens, Pattern> {
+    alt((
+        map(
+            verify(parse_identifier_str, |name: &str| name == "_"),
+            |_| Pattern::Wildcard,
+        ),
+        parse_tuple_pattern,
+        map(
+            verify(
+                separated_list1(
+                    bitwise_or_tag,
+                    alt((
+                        parse_prefix_pattern,
+                        parse_variant_pattern,
+                        map(parse_literal, Pattern::Literal),
+                    )),
+                ),
+                |patterns: &[Pattern]| {
+                    patterns.len() == 1 || patterns.iter().all(|pattern| !pattern.binds())
+                },
+            ),
+            |mut patterns| {
+                if patterns.len() == 1 {
+                    patterns.remove(0)
+                } else {
+                    Pattern::Or(patterns)
+                }
+            },
+        ),
+        map(parse_identifier, Pattern::Binding),
+    ))(input)
+}
+
+/// `(x, _)`. A single pattern in parentheses is only a tuple with a comma after it, `(x,)`
+fn parse_tuple_pattern(input: Tokens) -> IResult<Tokens, Pattern> {
+    tuple_of(parse_pattern)(input)
+}
+
+fn tuple_of<'a>(
+    item: fn(Tokens<'a>) -> IResult<Tokens<'a>, Pattern>,
+) -> impl FnMut(Tokens<'a>) -> IResult<Tokens<'a>, Pattern> {
+    map(
+        delimited(
+            l_paren_tag,
+            pair(separated_list1(comma_tag, item), opt(comma_tag)),
+            r_paren_tag,
+        ),
+        |(mut items, trailing_comma)| match (items.len(), trailing_comma) {
+            (1, None) => items.remove(0),
+            _ => Pattern::Tuple(items),
+        },
+    )
+}
+
+/// The patterns a `let` or a parameter destructures its value with: `_`, a name, a struct pattern
+/// or a tuple of them. They can't fail to match, so there are no literals or variants
+pub fn parse_destructuring_pattern(input: Tokens) -> IResult<Tokens, Pattern> {
+    alt((
+        map(
+            verify(parse_identifier_str, |name: &str| name == "_"),
+            |_| Pattern::Wildcard,
+        ),
+        tuple_of(parse_destructuring_pattern),
+        parse_struct_pattern,
+        map(parse_identifier, Pattern::Binding),
+    ))(input)
+}
+
+/// `Point { x, y: (a, b), .. }`. The `..` is allowed for readers, fields that aren't named are
+/// never read anyway
+fn parse_struct_pattern(input: Tokens) -> IResult<Tokens, Pattern> {
+    map(
+        tuple((
+            parse_identifier,
+            l_squirly_tag,
+            separated_list0(
+                comma_tag,
+                pair(
+                    parse_identifier,
+                    opt(preceded(colon_tag, parse_destructuring_pattern)),
+                ),
+            ),
+            opt(comma_tag),
+            opt(pair(double_period_tag, opt(comma_tag))),
+            r_squirly_tag,
+        )),
+        |(name, _, fields, _, _, _)| {
+            Pattern::Struct(
+                name,
+                fields
+                    .into_iter()
+                    .map(|(field, pattern)| {
+                        let pattern = pattern.unwrap_or_else(|| Pattern::Binding(field.clone()));
+                        (field, pattern)
+                    })
+                    .collect(),
+            )
+        },
+    )(input)
+}
+
+/// `Shape::Circle(r)` or `Shape::Empty`. Which variants an enum has is checked after parsing
+fn parse_variant_pattern(input: Tokens) -> IResult<Tokens, Pattern> {
+    map(
+        tuple((
+            parse_identifier,
+            many1(preceded(double_colon_tag, parse_identifier)),
+            opt(delimited(
+                l_paren_tag,
+                separated_list0(comma_tag, parse_pattern),
+                pair(opt(comma_tag), r_paren_tag),
+            )),
+        )),
+        |(first, rest, fields)| {
+            Pattern::Variant(
+                [first].into_iter().chain(rest).collect(),
+                fields.unwrap_or_default(),
+            )
+        },
+    )(input)
+}
+
+/// `"GET /" ..` or `"GET /" .. rest`, where `_` binds nothing like it does on its own
+fn parse_prefix_pattern(input: Tokens) -> IResult<Tokens, Pattern> {
+    map(
+        tuple((
+            map_opt(parse_literal, |literal| match literal {
+                Literal::StringLiteral(prefix) => Some(prefix),
+                _ => None,
+            }),
+            double_period_tag,
+            opt(parse_identifier),
+        )),
+        |(prefix, _, rest)| Pattern::Prefix(prefix, rest.filter(|name| name.0 != "_")),
+    )(input)
+}
+
+/// Without a type to check the patterns against, only a `_` or a name matches every value, except
+/// for `true` and `false` which cover all booleans. Arms with a guard can always fail to match, so
+/// they don't count. Matches on enum variants are checked by
+/// [`check_enum_matches`](super::enums::check_enum_matches), which knows the variants, and matches
+/// on literal types by [`check_types`](super::types::check_types)
+pub fn is_exhaustive(arms: &[MatchArm]) -> bool {
+    let mut literals = vec![];
+    for arm in arms.iter().filter(|arm| arm.guard.is_none()) {
+        if arm.pattern.is_catch_all() || arm.pattern.has_variant() {
+            return true;
+        }
+        arm.pattern.literals(&mut literals);
+    }
+    literals.contains(&&Literal::BooleanLiteral(true))
+        && literals.contains(&&Literal::BooleanLiteral(false))
+}
+
+impl Pattern {
+    pub fn is_catch_all(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Binding(_) => true,
+            Pattern::Literal(_) | Pattern::Prefix(..) | Pattern::Variant(..) => false,
+            Pattern::Or(patterns) => patterns.iter().any(Pattern::is_catch_all),
+            Pattern::Tuple(items) => items.iter().all(Pattern::is_catch_all),
+            Pattern::Struct(_, fields) => fields.iter().all(|(_, field)| field.is_catch_all()),
+        }
+    }
+
+    fn has_variant(&self) -> bool {
+        match self {
+            Pattern::Variant(..) => true,
+            Pattern::Or(patterns) => patterns.iter().any(Pattern::has_variant),
+            _ => false,
+        }
+    }
+
+    fn binds(&self) -> bool {
+        match self {
+            Pattern::Binding(_) | Pattern::Prefix(_, Some(_)) => true,
+            Pattern::Variant(_, fields) | Pattern::Tuple(fields) => {
+                fields.iter().any(Pattern::binds)
+            }
+            Pattern::Struct(_, fields) => fields.iter().any(|(_, field)| field.binds()),
+            _ => false,
+        }
+    }
+
+    /// The names the pattern binds, and the values they are bound to when matching `value`
+    pub fn bindings(&self, value: &Expression) -> Vec<(&Identifier, Expression)> {
+        match self {
+            Pattern::Binding(name) => vec![(name, value.clone())],
+            Pattern::Prefix(prefix, Some(name)) => vec![(
+                name,
+                method_call(
+                    value,
+                    "slice",
+                    Expression::MemberAccessExpression(
+                        MemberAccessExpr {
+                            lhs: Box::new(Expression::LiteralExpression(
+                                Literal::StringLiteral(prefix.clone()),
+                                Span::default(),
+                            )),
+                            ident: Identifier("length".to_string()),
+                        },
+                        Span::default(),
+                    ),
+                ),
+            )],
+            Pattern::Variant(_, fields) => fields
+                .iter()
+                .enumerate()
+                .flat_map(|(index, field)| field.bindings(&variant_value(value, index)))
+                .collect(),
+            Pattern::Tuple(items) => items
+                .iter()
+                .enumerate()
+                .flat_map(|(index, item)| item.bindings(&tuple_item(value, index)))
+                .collect(),
+            Pattern::Struct(_, fields) => fields
+                .iter()
+                .flat_map(|(name, field)| field.bindings(&struct_field(value, name)))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The literals the pattern compares the value with
+    pub fn literals<'p>(&'p self, literals: &mut Vec<&'p Literal>) {
+        match self {
+            Pattern::Wildcard
+            | Pattern::Binding(_)
+            | Pattern::Prefix(..)
+            | Pattern::Variant(..)
+            | Pattern::Tuple(_)
+            | Pattern::Struct(..) => {}
+            Pattern::Literal(literal) => literals.push(literal),
+            Pattern::Or(patterns) => {
+                for pattern in patterns {
+                    pattern.literals(literals);
+                }
+            }
+        }
+    }
+
+    /// `value == 1 || value.startsWith("a")` or `value.tag == "Circle" && ...`, or `None` if the pattern matches every value
+    fn condition(&self, value: &Expression) -> Option<Expression> {
+        match self {
+            Pattern::Wildcard | Pattern::Binding(_) => None,
+            Pattern::Literal(literal) => Some(Expression::InfixExpression(
+                InfixExpr {
+                    op: InfixOperator::Equal,
+                    lhs: Box::new(value.clone()),
+                    rhs: Box::new(Expression::LiteralExpression(
+                        literal.clone(),
+                        Span::default(),
+                    )),
+                },
+                Span::default(),
+            )),
+            Pattern::Prefix(prefix, _) => Some(method_call(
+                value,
+                "startsWith",
+                Expression::LiteralExpression(
+                    Literal::StringLiteral(prefix.clone()),
+                    Span::default(),
+                ),
+            )),
+            Pattern::Variant(path, fields) => {
+                let tag = Expression::InfixExpression(
+                    InfixExpr {
+                        op: InfixOperator::Equal,
+                        lhs: Box::new(Expression::MemberAccessExpression(
+                            MemberAccessExpr {
+                                lhs: Box::new(value.clone()),
+                                ident: Identifier("tag".to_string()),
+                            },
+                            Span::default(),
+                        )),
+                        rhs: Box::new(Expression::LiteralExpression(
+                            Literal::StringLiteral(path[path.len() - 1].0.clone()),
+                            Span::default(),
+                        )),
+                    },
+                    Span::default(),
+                );
+                Some(
+                    fields
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(index, field)| field.condition(&variant_value(value, index)))
+                        .fold(tag, |lhs, rhs| {
+                            Expression::InfixExpression(
+                                InfixExpr {
+                                    op: InfixOperator::LogicalAnd,
+                                    lhs: Box::new(lhs),
+                                    rhs: Box::new(rhs),
+                                },
+                                Span::default(),
+                            )
+                        }),
+                )
+            }
+            Pattern::Or(patterns) => patterns
+                .iter()
+                .map(|pattern| pattern.condition(value))
+                .reduce(|lhs, rhs| {
+                    Some(Expression::InfixExpression(
+                        InfixExpr {
+                            op: InfixOperator::LogicalOr,
+                            lhs: Box::new(lhs?),
+                            rhs: Box::new(rhs?),
+                        },
+                        Span::default(),
+                    ))
+                })
+                .flatten(),
+            Pattern::Tuple(items) => items
+                .iter()
+                .enumerate()
+                .filter_map(|(index, item)| item.condition(&tuple_item(value, index)))
+                .reduce(logical_and),
+            Pattern::Struct(_, fields) => fields
+                .iter()
+                .filter_map(|(name, field)| field.condition(&struct_field(value, name)))
+                .reduce(logical_and),
+        }
+    }
+}
+
+impl MatchExpr {
+    /// The value to compare the patterns with, and the statement that stores it if it isn't a
+    /// variable. Matching reads the value more than once, but it must only be evaluated once
+    pub fn stored_value(value: Expression) -> (Expression, Option<Statement>) {
+        match value {
+            Expression::IdentifierExpression(_, _) => (value, None),
+            value => MatchExpr::store(value),
+        }
+    }
+
+    fn store(value: Expression) -> (Expression, Option<Statement>) {
+        let name = Identifier("__match".to_string());
+        (
+            Expression::IdentifierExpression(name.clone(), Span::default()),
+            Some(Statement::DeclarationStatement(
+                Declaration::ConstDeclaration(name, value, false, vec![], None),
+                Span::default(),
+            )),
+        )
+    }
+
+    /// The body of an arm as a block, which starts by declaring the names the pattern binds
+    pub fn arm_body(pattern: &Pattern, body: Expression, value: &Expression) -> Block {
+        let mut block = match body {
+            Expression::BlockExpression(block, _) => *block,
+            body => Block {
+                statements: vec![],
+                span: body.span(),
+                return_value: Some(body),
+            },
+        };
+        // `x => ...` when matching `x` itself would declare `x` with its own value
+        let declarations = pattern
+            .bindings(value)
+            .into_iter()
+            .filter(|(name, bound)| {
+                *bound != Expression::IdentifierExpression((*name).clone(), Span::default())
+            })
+            .map(|(name, bound)| {
+                Statement::DeclarationStatement(
+                    Declaration::ConstDeclaration(name.clone(), bound, false, vec![], None),
+                    Span::default(),
+                )
+            });
+        block.statements.splice(0..0, declarations);
+        block
+    }
+
+    /// The condition of an arm: the one of its pattern and its guard. The guard is evaluated
+    /// before the body declares the bound names, so they are replaced by what they are bound to
+    fn arm_condition(arm: &MatchArm, value: &Expression) -> Option<Expression> {
+        let guard = arm.guard.clone().map(|mut guard| {
+            for (name, bound) in arm.pattern.bindings(value) {
+                let name = Expression::IdentifierExpression(name.clone(), Span::default());
+                if bound != name {
+                    walk_expression_mut(&mut guard, &mut |expression| {
+                        if *expression == name {
+                            *expression = bound.clone();
+                        }
+                    });
+                }
+            }
+            guard
+        });
+        match (arm.pattern.condition(value), guard) {
+            (Some(condition), Some(guard)) => Some(Expression::InfixExpression(
+                InfixExpr {
+                    op: InfixOperator::LogicalAnd,
+                    lhs: Box::new(condition),
+                    rhs: Box::new(guard),
+                },
+                Span::default(),
+            )),
+            (condition, guard) => condition.or(guard),
+        }
+    }
+
+    /// Lowers the match to an `if` chain comparing the value with `==`, for backends that don't
+    /// have anything closer to a `match`. Arms after one that matches everything are never
+    /// reached and dropped
+    pub fn into_if(self) -> Expression {
+        // `match rest { "a" .. rest if rest != "" => ... }` would replace `rest` in the guard with
+        // `rest.slice(...)` over and over, so the value is stored under another name
+        let shadowed = self.arms.iter().any(|arm| {
+            arm.guard.is_some()
+                && arm
+                    .pattern
+                    .bindings(&self.value)
+                    .iter()
+                    .any(|(name, bound)| {
+                        *self.value
+                            == Expression::IdentifierExpression((*name).clone(), Span::default())
+                            && *bound != *self.value
+                    })
+        });
+        let (value, store) = if shadowed {
+            MatchExpr::store(*self.value)
+        } else {
+            MatchExpr::stored_value(*self.value)
+        };
+        let mut branches = vec![];
+        for arm in self.arms {
+            let condition = MatchExpr::arm_condition(&arm, &value);
+            let catch_all = condition.is_none();
+            branches.push((
+                condition,
+                MatchExpr::arm_body(&arm.pattern, arm.body, &value),
+            ));
+            if catch_all {
+                break;
+            }
+        }
+        // without a catch-all the arms cover `true` and `false` or every value of a literal type, so the
+        // last one is the `else`
+        let (_, else_block) = branches.pop().expect("matches have at least one arm");
+        let mut branches = branches.into_iter();
+        let lowered = match branches.next() {
+            Some((condition, then_block)) => Expression::IfExpression(
+                IfExpr {
+                    condition: Box::new(condition.expect("only the last arm matches everything")),
+                    then_block: Box::new(then_block),
+                    else_if_blocks: branches
+                        .map(|(condition, then_block)| ElseIfExpr {
+                            condition: Box::new(
+                                condition.expect("only the last arm matches everything"),
+                            ),
+                            then_block,
+                        })
+                        .collect(),
+                    else_block: Some(Box::new(else_block)),
+                },
+                Span::default(),
+            ),
+            None => Expression::BlockExpression(Box::new(else_block), Span::default()),
+        };
+        match store {
+            Some(store) => Expression::BlockExpression(
+                Box::new(Block {
+                    statements: vec![store],
+                    return_value: Some(lowered),
+                    span: Span::default(),
+                }),
+                Span::default(),
+            ),
+            None => lowered,
+        }
+    }
+}
+
+/// `value.values[index]`, the value a variant holds at `index`
+fn variant_value(value: &Expression, index: usize) -> Expression {
+    Expression::IndexExpression(
+        IndexExpr {
+            lhs: Box::new(Expression::MemberAccessExpression(
+                MemberAccessExpr {
+                    lhs: Box::new(value.clone()),
+                    ident: Identifier("values".to_string()),
+                },
+                Span::default(),
+            )),
+            index: Box::new(Expression::LiteralExpression(
+                Literal::NumberLiteral(Number::I {
+                    base: NumberBase::Dec,
+                    value: index as i32,
+                }),
+                Span::default(),
+            )),
+        },
+        Span::default(),
+    )
+}
+
+/// `value.0`, the item of a tuple
+fn tuple_item(value: &Expression, index: usize) -> Expression {
+    Expression::TupleIndexExpression(
+        TupleIndexExpr {
+            lhs: Box::new(value.clone()),
+            index,
+        },
+        Span::default(),
+    )
+}
+
+/// `value.name`, the value a struct pattern matches the field `name` against
+fn struct_field(value: &Expression, name: &Identifier) -> Expression {
+    Expression::MemberAccessExpression(
+        MemberAccessExpr {
+            lhs: Box::new(value.clone()),
+            ident: name.clone(),
+        },
+        Span::default(),
+    )
+}
+
+fn logical_and(lhs: Expression, rhs: Expression) -> Expression {
+    Expression::InfixExpression(
+        InfixExpr {
+            op: InfixOperator::LogicalAnd,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+        Span::default(),
+    )
+}
+
+/// `value.method(argument)`, which the backends without the method map to their own
+fn method_call(value: &Expression, method: &str, argument: Expression) -> Expression {
+    Expression::CallExpression(
+        CallExpr {
+            lhs: Box::new(Expression::MemberAccessExpression(
+                MemberAccessExpr {
+                    lhs: Box::new(value.clone()),
+                    ident: Identifier(method.to_string()),
+                },
+                Span::default(),
+            )),
+            arguments: vec![argument],
+        },
+        Span::default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::{ast::Program, Parser},
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Result<Program, String> {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        Parser::parse(Tokens::new(&tokens))
+            .map(|(_, program)| program)
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    fn expression(input: &str) -> Expression {
+        match parse(input).unwrap().pop() {
+            Some(Statement::ExpressionStatement { expression, .. }) => expression,
+            statement => panic!("expected an expression, got {:?}", statement),
+        }
+    }
+
+    fn exhaustive(input: &str) -> bool {
+        match expression(input) {
+            Expression::MatchExpression(expr, _) => is_exhaustive(&expr.arms),
+            expression => panic!("expected a match, got {:?}", expression),
+        }
+    }
+
+    #[test]
+    fn exhaustiveness() {
+        assert!(exhaustive("match n { 1 | 2 => a, _ => b }"));
+        assert!(exhaustive("match n { 1 => { a } x => x }"));
+        assert!(exhaustive("match ok { true => 1, false => 0, }"));
+        assert!(parse("text.match(pattern);").is_ok());
+
+        assert!(!exhaustive("match n { 1 => a, 2 => b }"));
+        assert!(!exhaustive("match ok { true => 1 }"));
+        assert!(parse("match n { 1 => a _ => b }").is_err());
+        assert!(!exhaustive("match n { x if x > 1 => a }"));
+        assert!(!exhaustive("match ok { true if a => 1, false => 0 }"));
+        assert!(!exhaustive(r#"match path { "/" .. rest => rest }"#));
+        assert!(exhaustive(r#"match path { "a" .. | "b" .. => 1, _ => 0 }"#));
+        assert!(parse(r#"match path { "a" .. rest | "b" => 1, _ => 0 }"#).is_err());
+    }
+
+    #[test]
+    fn lowers_to_if() {
+        let Expression::MatchExpression(expr, _) =
+            expression("match f() { 1 | 2 => a, n => n, _ => b }")
+        else {
+            panic!("expected a match");
+        };
+        assert_eq!(
+            expr.into_if(),
+            expression(
+                "{ const __match = f(); if __match == 1 || __match == 2 { a } else { const n = __match; n } }"
+            )
+        );
+
+        let Expression::MatchExpression(expr, _) = expression("match ok { true => 1, false => 0 }")
+        else {
+            panic!("expected a match");
+        };
+        assert_eq!(expr.into_if(), expression("if ok == true { 1 } else { 0 }"));
+    }
+
+    #[test]
+    fn prefixes_and_guards() {
+        let Expression::MatchExpression(expr, _) = expression(
+            r#"match path { "/users/" .. id if id != "" => id, p if p == "/" => 0, _ => 1 }"#,
+        ) else {
+            panic!("expected a match");
+        };
+        assert_eq!(
+            expr.into_if(),
+            expression(
+                r#"if path.startsWith("/users/") && path.slice("/users/".length) != "" {
+                    const id = path.slice("/users/".length);
+                    id
+                } else if path == "/" {
+                    const p = path;
+                    0
+                } else {
+                    1
+                }"#
+            )
+        );
+
+        // the guard would read the rest of the string instead of the value
+        let Expression::MatchExpression(expr, _) =
+            expression(r#"match rest { "/" .. rest if rest != "" => rest, _ => rest }"#)
+        else {
+            panic!("expected a match");
+        };
+        assert_eq!(
+            expr.into_if(),
+            expression(
+                r#"{
+                    const __match = rest;
+                    if __match.startsWith("/") && __match.slice("/".length) != "" {
+                        const rest = __match.slice("/".length);
+                        rest
+                    } else {
+                        rest
+                    }
+                }"#
+            )
+        );
+    }
+
+    #[test]
+    fn variants() {
+        assert!(parse("match s { Shape::Circle(r) => r, Shape::Empty => 0 }").is_ok());
+        assert!(parse("match s { Shape::Circle(_) | Shape::Empty => 0, _ => 1 }").is_ok());
+        assert!(parse("match s { Shape::Circle(r) | Shape::Empty => 0, _ => 1 }").is_err());
+
+        let Expression::MatchExpression(expr, _) = expression(
+            "match shape { Shape::Rect(w, 1 | 2) if w > h => w, Shape::Circle(r) => r, _ => 0 }",
+        ) else {
+            panic!("expected a match");
+        };
+        assert_eq!(
+            expr.into_if(),
+            expression(
+                r#"if shape.tag == "Rect" && (shape.values[1] == 1 || shape.values[1] == 2) && shape.values[0] > h {
+                    const w = shape.values[0];
+                    w
+                } else if shape.tag == "Circle" {
+                    const r = shape.values[0];
+                    r
+                } else {
+                    0
+                }"#
+            )
+        );
+
+        // `shape` in the guard would be replaced with a value read from `shape` over and over
+        let Expression::MatchExpression(expr, _) =
+            expression("match shape { Shape::Inner(shape) if shape > 1 => shape, _ => 0 }")
+        else {
+            panic!("expected a match");
+        };
+        assert_eq!(
+            expr.into_if(),
+            expression(
+                r#"{
+                    const __match = shape;
+                    if __match.tag == "Inner" && __match.values[0] > 1 {
+                        const shape = __match.values[0];
+                        shape
+                    } else {
+                        0
+                    }
+                }"#
+            )
+        );
+    }
+}
@@ -1,21 +1,33 @@
 use crate::parser::Err;
-use nom::combinator::{map, opt};
+use nom::branch::alt;
+use nom::combinator::{map, map_opt, opt};
 use nom::error::ErrorKind;
 use nom::error_position;
 use nom::sequence::{delimited, tuple};
-use nom::{bytes::complete::take, IResult};
+use nom::IResult;
 
+use crate::lexer::token::Token;
 use crate::lexer::tokens::Tokens;
 
-use super::ast::{CallExpr, IndexExpr, InfixExpr, MemberAccessExpr};
-use super::atoms::{l_bracket_tag, l_paren_tag, period_tag, r_bracket_tag, r_paren_tag};
+use super::assignment::parse_assignment_expression;
+use super::ast::{
+    CallExpr, CastExpr, IndexExpr, InfixExpr, IsExpr, Literal, MemberAccessExpr, Number, RangeExpr,
+    SliceExpr, Span, TupleIndexExpr,
+};
+use super::atoms::{
+    as_tag, await_tag, double_period_equal_tag, double_period_tag, is_tag, l_bracket_tag,
+    l_paren_tag, period_tag, r_bracket_tag, r_paren_tag,
+};
 use super::expression::{parse_expression, parse_expressions};
-use super::parse_identifier;
+use super::function::{parse_single_type, parse_type};
+use super::optional::parse_optional_expression;
+use super::template::parse_tagged_template;
 use super::{
     ast::{Expression, Precedence},
     atoms::infix_operator,
     expression::parse_atom_expression,
 };
+use super::{consumed_span, parse_identifier, parse_literal, take_token};
 
 pub fn parse_pratt_expression(
     input: Tokens,
@@ -30,36 +42,56 @@ fn parse_pratt_expression1(
     precedence: Precedence,
     left: Expression,
 ) -> IResult<Tokens, Expression> {
-    let (rest, found) = take(1usize)(input)?;
+    let (rest, found) = take_token(input)?;
     if found.tokens.is_empty() {
         Ok((rest, left))
     } else {
         let preview = &found.tokens[0];
         let p = infix_operator(preview);
-        match p {
-            (Precedence::PCall, _) if precedence < Precedence::PCall => {
-                let (rest2, left2) = parse_pratt_call_expression(input, left)?;
-                parse_pratt_expression1(rest2, precedence, left2)
+        let left_span = left.span();
+        let (rest2, mut left2) = match p {
+            (Precedence::PCall, _) if precedence < Precedence::PCall => match preview {
+                Token::Template(_) => parse_tagged_template(input, left)?,
+                _ => parse_pratt_call_expression(input, left)?,
+            },
+            (Precedence::PMemberAccess | Precedence::PIndex, _)
+                if matches!(preview, Token::QuestionPeriod | Token::QuestionBracket)
+                    && precedence < p.0 =>
+            {
+                parse_optional_expression(input, left)?
             }
             (Precedence::PMemberAccess, _) if precedence < Precedence::PMemberAccess => {
-                let (rest2, left2) = parse_pratt_member_access_expression(input, left)?;
-                parse_pratt_expression1(rest2, precedence, left2)
+                parse_pratt_member_access_expression(input, left)?
             }
             (Precedence::PIndex, _) if precedence < Precedence::PIndex => {
-                let (rest2, left2) = parse_pratt_index_expression(input, left)?;
-                parse_pratt_expression1(rest2, precedence, left2)
+                parse_pratt_index_expression(input, left)?
+            }
+            (Precedence::PCast, _) if precedence < Precedence::PCast => match preview {
+                Token::Ident("is") => parse_pratt_is_expression(input, left)?,
+                _ => parse_pratt_cast_expression(input, left)?,
+            },
+            (Precedence::PAssign, _) if precedence < Precedence::PAssign => {
+                parse_assignment_expression(input, left)?
+            }
+            // ranges don't chain, `a..b..c` is no expression
+            (Precedence::PRange, _)
+                if precedence < Precedence::PRange
+                    && !matches!(left, Expression::RangeExpression(_, _)) =>
+            {
+                parse_range_expression(input, left)?
             }
             (peek_precedence, _) if precedence < peek_precedence => {
-                let (rest2, left2) = parse_infix_expression(input, left)?;
-                parse_pratt_expression1(rest2, precedence, left2)
+                parse_infix_expression(input, left)?
             }
-            _ => Ok((input, left)),
-        }
+            _ => return Ok((input, left)),
+        };
+        *left2.span_mut() = left_span.to(consumed_span(input, rest2));
+        parse_pratt_expression1(rest2, precedence, left2)
     }
 }
 
 fn parse_infix_expression(input: Tokens, left: Expression) -> IResult<Tokens, Expression> {
-    let (rest, operator) = take(1usize)(input)?;
+    let (rest, operator) = take_token(input)?;
     if operator.tokens.is_empty() {
         Err(Err::Error(error_position!(input, ErrorKind::Tag)))
     } else {
@@ -71,26 +103,104 @@ fn parse_infix_expression(input: Tokens, left: Expression) -> IResult<Tokens, Ex
                 let (rest2, right) = parse_pratt_expression(rest, precedence)?;
                 Ok((
                     rest2,
-                    Expression::InfixExpression(InfixExpr {
-                        op,
-                        lhs: Box::new(left),
-                        rhs: Box::new(right),
-                    }),
+                    Expression::InfixExpression(
+                        InfixExpr {
+                            op,
+                            lhs: Box::new(left),
+                            rhs: Box::new(right),
+                        },
+                        Span::default(),
+                    ),
                 ))
             }
         }
     }
 }
 
+/// `left..end` or `left..=end`. The end binds tighter, so `0..n + 1` ends at `n + 1`
+fn parse_range_expression(input: Tokens, left: Expression) -> IResult<Tokens, Expression> {
+    map(
+        tuple((
+            alt((
+                map(double_period_tag, |_| false),
+                map(double_period_equal_tag, |_| true),
+            )),
+            |input| parse_pratt_expression(input, Precedence::PRange),
+        )),
+        |(inclusive, end)| {
+            Expression::RangeExpression(
+                RangeExpr {
+                    start: Box::new(left.clone()),
+                    end: Box::new(end),
+                    inclusive,
+                },
+                Span::default(),
+            )
+        },
+    )(input)
+}
+
+/// `lhs.name`, or `lhs.await`
 fn parse_pratt_member_access_expression(
     input: Tokens,
     left: Expression,
 ) -> IResult<Tokens, Expression> {
-    map(tuple((period_tag, parse_identifier)), |(_, right)| {
-        Expression::MemberAccessExpression(MemberAccessExpr {
-            lhs: Box::new(left.clone()),
-            ident: right,
-        })
+    alt((
+        map(tuple((period_tag, await_tag)), |_| {
+            Expression::AwaitExpression(Box::new(left.clone()), Span::default())
+        }),
+        map(tuple((period_tag, parse_identifier)), |(_, right)| {
+            Expression::MemberAccessExpression(
+                MemberAccessExpr {
+                    lhs: Box::new(left.clone()),
+                    ident: right,
+                },
+                Span::default(),
+            )
+        }),
+        // `pair.0`
+        map(
+            tuple((
+                period_tag,
+                map_opt(parse_literal, |literal| match literal {
+                    Literal::NumberLiteral(Number::I { value, .. }) => usize::try_from(value).ok(),
+                    _ => None,
+                }),
+            )),
+            |(_, index)| {
+                Expression::TupleIndexExpression(
+                    TupleIndexExpr {
+                        lhs: Box::new(left.clone()),
+                        index,
+                    },
+                    Span::default(),
+                )
+            },
+        ),
+    ))(input)
+}
+
+fn parse_pratt_cast_expression(input: Tokens, left: Expression) -> IResult<Tokens, Expression> {
+    map(tuple((as_tag, parse_single_type)), |(_, type_)| {
+        Expression::CastExpression(
+            CastExpr {
+                value: Box::new(left.clone()),
+                type_,
+            },
+            Span::default(),
+        )
+    })(input)
+}
+
+fn parse_pratt_is_expression(input: Tokens, left: Expression) -> IResult<Tokens, Expression> {
+    map(tuple((is_tag, parse_type)), |(_, type_)| {
+        Expression::IsExpression(
+            IsExpr {
+                value: Box::new(left.clone()),
+                type_,
+            },
+            Span::default(),
+        )
     })(input)
 }
 
@@ -98,22 +208,52 @@ fn parse_pratt_call_expression(input: Tokens, left: Expression) -> IResult<Token
     map(
         delimited(l_paren_tag, opt(parse_expressions), r_paren_tag),
         |args| {
-            Expression::CallExpression(CallExpr {
-                lhs: Box::new(left.clone()),
-                arguments: args.unwrap_or_default(),
-            })
+            Expression::CallExpression(
+                CallExpr {
+                    lhs: Box::new(left.clone()),
+                    arguments: args.unwrap_or_default(),
+                },
+                Span::default(),
+            )
         },
     )(input)
 }
 
 fn parse_pratt_index_expression(input: Tokens, left: Expression) -> IResult<Tokens, Expression> {
-    map(
-        delimited(l_bracket_tag, parse_expression, r_bracket_tag),
-        |index_expr| {
-            Expression::IndexExpression(IndexExpr {
-                lhs: Box::new(left.clone()),
-                index: Box::new(index_expr),
-            })
-        },
-    )(input)
+    alt((
+        map(
+            delimited(
+                l_bracket_tag,
+                tuple((
+                    // the bounds aren't ranges themselves, the start would take the `..`
+                    opt(|input| parse_pratt_expression(input, Precedence::PRange)),
+                    double_period_tag,
+                    opt(|input| parse_pratt_expression(input, Precedence::PRange)),
+                )),
+                r_bracket_tag,
+            ),
+            |(start, _, end)| {
+                Expression::SliceExpression(
+                    SliceExpr {
+                        lhs: Box::new(left.clone()),
+                        start: start.map(Box::new),
+                        end: end.map(Box::new),
+                    },
+                    Span::default(),
+                )
+            },
+        ),
+        map(
+            delimited(l_bracket_tag, parse_expression, r_bracket_tag),
+            |index_expr| {
+                Expression::IndexExpression(
+                    IndexExpr {
+                        lhs: Box::new(left.clone()),
+                        index: Box::new(index_expr),
+                    },
+                    Span::default(),
+                )
+            },
+        ),
+    ))(input)
 }
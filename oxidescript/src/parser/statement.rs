@@ -8,19 +8,47 @@ use nom::{
 use crate::lexer::tokens::Tokens;
 
 use super::{
-    ast::Statement, atoms::semicolon_tag, declaration::parse_declaration,
+    ast::{Span, Statement},
+    atoms::semicolon_tag,
+    consumed_span,
+    declaration::parse_declaration,
     expression::parse_expression,
+    module::parse_pub_declaration,
+    traits::parse_trait_declaration,
+    types::{parse_extern_declaration, parse_type_declaration},
 };
 
 pub fn parse_statement(input: Tokens) -> IResult<Tokens, Statement> {
     // println!("parse_statement");
-    alt((parse_declaration_statement, parse_expression_statement))(input)
+    let (rest, mut statement) =
+        alt((parse_declaration_statement, parse_expression_statement))(input)?;
+    *statement.span_mut() = consumed_span(input, rest);
+    Ok((rest, statement))
+}
+
+/// Like `parse_statement`, but declarations can be `pub`, and types, externs and traits can only
+/// be declared at the top level
+pub fn parse_top_level_statement(input: Tokens) -> IResult<Tokens, Statement> {
+    let (rest, mut statement) = alt((
+        map(
+            alt((
+                parse_pub_declaration,
+                parse_type_declaration,
+                parse_extern_declaration,
+                parse_trait_declaration,
+            )),
+            |declaration| Statement::DeclarationStatement(declaration, Span::default()),
+        ),
+        parse_statement,
+    ))(input)?;
+    *statement.span_mut() = consumed_span(input, rest);
+    Ok((rest, statement))
 }
 
 fn parse_declaration_statement(input: Tokens) -> IResult<Tokens, Statement> {
     // println!("parse_declaration_statement");
     map(parse_declaration, |declaration| {
-        Statement::DeclarationStatement(declaration)
+        Statement::DeclarationStatement(declaration, Span::default())
     })(input)
 }
 
@@ -32,6 +60,7 @@ fn parse_expression_statement(input: Tokens) -> IResult<Tokens, Statement> {
         |(expression, semicolon)| Statement::ExpressionStatement {
             expression,
             has_semicolon: semicolon.is_some(),
+            span: Span::default(),
         },
     )(input)
 }
@@ -0,0 +1,280 @@
+use nom::{
+    branch::alt,
+    combinator::{map, opt, verify},
+    multi::{many0, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult,
+};
+
+use crate::lexer::tokens::Tokens;
+
+use super::{
+    ast::{
+        Declaration, Expression, Field, Identifier, ImplDecl, ImplItem, Method, Parameter, Span,
+        StructDecl, StructExpr,
+    },
+    atoms::*,
+    expression::parse_expression,
+    function::{
+        parse_block, parse_destructured_parameters, parse_parameter, parse_parameters,
+        parse_return_type, parse_type, with_destructurings,
+    },
+    parse_identifier, parse_identifier_str, spanned,
+};
+
+pub fn parse_struct_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    map(
+        tuple((
+            many0(verify(parse_derive, |derives: &Vec<Identifier>| {
+                derives.iter().all(is_struct_derive)
+            })),
+            struct_tag,
+            parse_identifier,
+            parse_type_parameters,
+            l_squirly_tag,
+            separated_list0(comma_tag, parse_field),
+            opt(comma_tag),
+            r_squirly_tag,
+        )),
+        |(derives, _, name, type_parameters, _, fields, _, _)| {
+            Declaration::StructDeclaration(StructDecl {
+                name,
+                type_parameters,
+                fields,
+                derives: derives.into_iter().flatten().collect(),
+                is_pub: false,
+            })
+        },
+    )(input)
+}
+
+/// `<A, B>` after the name of a struct or enum, or nothing
+pub fn parse_type_parameters(input: Tokens) -> IResult<Tokens, Vec<Identifier>> {
+    map(
+        opt(delimited(
+            less_than_tag,
+            terminated(separated_list1(comma_tag, parse_identifier), opt(comma_tag)),
+            greater_than_tag,
+        )),
+        Option::unwrap_or_default,
+    )(input)
+}
+
+/// Structs can derive `Serialize` and `Deserialize`, enums only `Flags`
+pub fn is_struct_derive(derive: &Identifier) -> bool {
+    matches!(derive.0.as_str(), "Serialize" | "Deserialize")
+}
+
+/// `#[derive(Serialize, Deserialize, Flags)]`, other traits can't be derived
+pub fn parse_derive(input: Tokens) -> IResult<Tokens, Vec<Identifier>> {
+    delimited(
+        tuple((
+            hash_tag,
+            l_bracket_tag,
+            verify(parse_identifier_str, |name: &str| name == "derive"),
+            l_paren_tag,
+        )),
+        terminated(
+            separated_list1(
+                comma_tag,
+                verify(parse_identifier, |name: &Identifier| {
+                    matches!(name.0.as_str(), "Serialize" | "Deserialize" | "Flags")
+                }),
+            ),
+            opt(comma_tag),
+        ),
+        pair(r_paren_tag, r_bracket_tag),
+    )(input)
+}
+
+pub fn parse_field(input: Tokens) -> IResult<Tokens, Field> {
+    map(
+        spanned(tuple((parse_identifier, colon_tag, parse_type))),
+        |((name, _, type_), span)| Field { name, type_, span },
+    )(input)
+}
+
+/// `impl Point { ... }` or `impl Ord for Point { ... }`
+pub fn parse_impl_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    map(
+        tuple((
+            impl_tag,
+            parse_identifier,
+            opt(preceded(for_tag, parse_identifier)),
+            l_squirly_tag,
+            many0(parse_impl_item),
+            r_squirly_tag,
+        )),
+        |(_, name, target, _, items, _)| {
+            let (target, trait_) = match target {
+                Some(target) => (target, Some(name)),
+                None => (name, None),
+            };
+            Declaration::ImplDeclaration(ImplDecl {
+                target,
+                trait_,
+                items,
+            })
+        },
+    )(input)
+}
+
+fn parse_impl_item(input: Tokens) -> IResult<Tokens, ImplItem> {
+    alt((
+        map(parse_method, ImplItem::Method),
+        map(parse_getter, ImplItem::Getter),
+        map(parse_setter, ImplItem::Setter),
+        parse_associated_const,
+    ))(input)
+}
+
+fn parse_associated_const(input: Tokens) -> IResult<Tokens, ImplItem> {
+    map(
+        tuple((
+            const_tag,
+            parse_identifier,
+            assign_tag,
+            parse_expression,
+            semicolon_tag,
+        )),
+        |(_, name, _, value, _)| ImplItem::Const(name, value),
+    )(input)
+}
+
+fn parse_method(input: Tokens) -> IResult<Tokens, Method> {
+    map(
+        spanned(tuple((
+            opt(async_tag),
+            function_tag,
+            parse_identifier,
+            l_paren_tag,
+            method_parameters(parse_destructured_parameters),
+            r_paren_tag,
+            parse_return_type,
+            l_squirly_tag,
+            parse_block,
+            r_squirly_tag,
+        ))),
+        |(
+            (
+                is_async,
+                _,
+                name,
+                _,
+                (has_self, (parameters, destructurings)),
+                _,
+                return_type,
+                _,
+                body,
+                _,
+            ),
+            span,
+        )| {
+            Method {
+                name,
+                has_self,
+                is_async: is_async.is_some(),
+                parameters,
+                return_type,
+                body: with_destructurings(body, destructurings),
+                span,
+            }
+        },
+    )(input)
+}
+
+fn parse_getter(input: Tokens) -> IResult<Tokens, Method> {
+    map(
+        spanned(tuple((
+            get_tag,
+            parse_identifier,
+            l_paren_tag,
+            r_paren_tag,
+            parse_return_type,
+            l_squirly_tag,
+            parse_block,
+            r_squirly_tag,
+        ))),
+        |((_, name, _, _, return_type, _, body, _), span)| Method {
+            name,
+            has_self: true,
+            is_async: false,
+            parameters: vec![],
+            return_type,
+            body,
+            span,
+        },
+    )(input)
+}
+
+fn parse_setter(input: Tokens) -> IResult<Tokens, Method> {
+    map(
+        spanned(tuple((
+            set_tag,
+            parse_identifier,
+            l_paren_tag,
+            parse_parameter,
+            r_paren_tag,
+            parse_return_type,
+            l_squirly_tag,
+            parse_block,
+            r_squirly_tag,
+        ))),
+        |((_, name, _, parameter, _, return_type, _, body, _), span)| Method {
+            name,
+            has_self: true,
+            is_async: false,
+            parameters: vec![parameter],
+            return_type,
+            body,
+            span,
+        },
+    )(input)
+}
+
+pub fn parse_method_parameters(input: Tokens) -> IResult<Tokens, (bool, Vec<Parameter>)> {
+    method_parameters(parse_parameters)(input)
+}
+
+/// `self`, `self, parameters` or just the parameters, and whether there is a `self`
+fn method_parameters<'a, P: Default>(
+    parameters: fn(Tokens<'a>) -> IResult<Tokens<'a>, P>,
+) -> impl FnMut(Tokens<'a>) -> IResult<Tokens<'a>, (bool, P)> {
+    alt((
+        map(
+            tuple((self_tag, opt(preceded(comma_tag, parameters)))),
+            |(_, parameters)| (true, parameters.unwrap_or_default()),
+        ),
+        map(parameters, |parameters| (false, parameters)),
+    ))
+}
+
+/// Struct literals like `Point { x: 1, y: 2 }`. Only identifiers starting with an uppercase letter
+/// are considered struct names, so that `if foo { ... }` still parses as a block
+pub fn parse_struct_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    map(
+        tuple((
+            verify(parse_identifier_str, |name: &str| {
+                name.starts_with(|c: char| c.is_ascii_uppercase())
+            }),
+            l_squirly_tag,
+            separated_list0(
+                comma_tag,
+                map(
+                    tuple((parse_identifier, colon_tag, parse_expression)),
+                    |(name, _, value)| (name, value),
+                ),
+            ),
+            terminated(opt(comma_tag), r_squirly_tag),
+        )),
+        |(name, _, fields, _)| {
+            Expression::StructExpression(
+                StructExpr {
+                    name: Identifier(name.to_string()),
+                    fields,
+                },
+                Span::default(),
+            )
+        },
+    )(input)
+}
@@ -0,0 +1,225 @@
+use std::mem;
+
+use nom::error::ErrorKind;
+use nom::sequence::terminated;
+use nom::{error_position, Err, IResult};
+
+use crate::lexer::token::Token;
+use crate::lexer::tokens::Tokens;
+use crate::lexer::{escaped_char, Lexer};
+
+use super::ast::{
+    CallExpr, Expression, InfixExpr, InfixOperator, Literal, Span, TaggedTemplateExpr, TemplateExpr,
+};
+use super::atoms::eof_tag;
+use super::expression::parse_expression;
+use super::take_token;
+
+/// ``tag`text {value} text` ``. The template binds like the arguments of a call, so
+/// ``db.sql`...` `` is tagged with `db.sql`
+pub fn parse_tagged_template(input: Tokens, tag: Expression) -> IResult<Tokens, Expression> {
+    let (rest, found) = take_token(input)?;
+    let Some(Token::Template(template)) = found.tokens.first() else {
+        return Err(Err::Error(error_position!(input, ErrorKind::Tag)));
+    };
+    let (strings, values) =
+        split_template(template).ok_or(Err::Error(error_position!(input, ErrorKind::Verify)))?;
+    Ok((
+        rest,
+        Expression::TaggedTemplateExpression(
+            TaggedTemplateExpr {
+                tag: Box::new(tag),
+                strings,
+                values,
+            },
+            Span::default(),
+        ),
+    ))
+}
+
+/// `f"text {value} text"`
+pub fn parse_template_expression(input: Tokens) -> IResult<Tokens, Expression> {
+    let (rest, found) = take_token(input)?;
+    let Some(Token::FormatString(template)) = found.tokens.first() else {
+        return Err(Err::Error(error_position!(input, ErrorKind::Tag)));
+    };
+    let (strings, values) =
+        split_template(template).ok_or(Err::Error(error_position!(input, ErrorKind::Verify)))?;
+    Ok((
+        rest,
+        Expression::TemplateExpression(TemplateExpr { strings, values }, Span::default()),
+    ))
+}
+
+/// The strings between the `{interpolations}` of a template and the values of the
+/// interpolations. Escapes are the ones of strings, and `\{` is a brace
+fn split_template(template: &str) -> Option<(Vec<String>, Vec<Expression>)> {
+    let mut strings = vec![];
+    let mut values = vec![];
+    let mut string = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\\' => {
+                let mut chars = rest.chars();
+                string.push(escaped_char(&mut chars)?);
+                rest = chars.as_str();
+            }
+            '{' => {
+                let end = interpolation_end(rest)?;
+                values.push(parse_interpolation(&rest[..end])?);
+                strings.push(mem::take(&mut string));
+                rest = &rest[end + 1..];
+            }
+            c => string.push(c),
+        }
+    }
+    strings.push(string);
+    Some((strings, values))
+}
+
+/// The index of the `}` closing an interpolation, skipping the braces of blocks and strings in it
+fn interpolation_end(source: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, byte) in source.bytes().enumerate() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'"' => in_string = !in_string,
+            _ if in_string => {}
+            b'{' => depth += 1,
+            b'}' if depth == 0 => return Some(index),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// An interpolation has to be exactly one expression
+fn parse_interpolation(source: &str) -> Option<Expression> {
+    let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).ok()?;
+    let (_, value) = terminated(parse_expression, eof_tag)(Tokens::new(&tokens)).ok()?;
+    Some(value)
+}
+
+impl TaggedTemplateExpr {
+    /// `tag(["text ", " text"], value)`, the call a tagged template makes in javascript, for
+    /// backends without tagged templates
+    pub fn into_call(self) -> Expression {
+        let strings = self
+            .strings
+            .into_iter()
+            .map(|string| {
+                Expression::LiteralExpression(Literal::StringLiteral(string), Span::default())
+            })
+            .collect();
+        Expression::CallExpression(
+            CallExpr {
+                lhs: self.tag,
+                arguments: [Expression::ArrayExpression(strings, Span::default())]
+                    .into_iter()
+                    .chain(self.values)
+                    .collect(),
+            },
+            Span::default(),
+        )
+    }
+}
+
+impl TemplateExpr {
+    /// `"text " + value + " text"`, for backends without template literals. `+` turns the values
+    /// into strings because the first operand always is one
+    pub fn into_concatenation(self) -> Expression {
+        let string =
+            |string| Expression::LiteralExpression(Literal::StringLiteral(string), Span::default());
+        let concat = |lhs, rhs| {
+            Expression::InfixExpression(
+                InfixExpr {
+                    op: InfixOperator::Plus,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+                Span::default(),
+            )
+        };
+        let mut strings = self.strings.into_iter();
+        let mut result = string(strings.next().unwrap_or_default());
+        for (value, next) in self.values.into_iter().zip(strings) {
+            result = concat(result, value);
+            if !next.is_empty() {
+                result = concat(result, string(next));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{ast::Statement, Parser};
+
+    use super::*;
+
+    fn expression(input: &str) -> Result<Expression, String> {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        match Parser::parse(Tokens::new(&tokens)) {
+            Ok((_, mut program)) => match program.pop() {
+                Some(Statement::ExpressionStatement { expression, .. }) => Ok(expression),
+                statement => Err(format!("expected an expression, got {:?}", statement)),
+            },
+            Err(err) => Err(format!("{:?}", err)),
+        }
+    }
+
+    #[test]
+    fn tagged_templates() {
+        let Ok(Expression::TaggedTemplateExpression(expr, _)) =
+            expression(r#"db.sql`SELECT * WHERE id = {id + 1} AND name = {name({ "}" })} \{x\}`"#)
+        else {
+            panic!("expected a tagged template");
+        };
+        assert_eq!(*expr.tag, expression("db.sql").unwrap());
+        assert_eq!(
+            expr.strings,
+            vec!["SELECT * WHERE id = ", " AND name = ", " {x}"]
+        );
+        assert_eq!(
+            expr.values,
+            vec![
+                expression("id + 1").unwrap(),
+                expression(r#"name({ "}" })"#).unwrap()
+            ]
+        );
+        assert_eq!(
+            expr.into_call(),
+            expression(
+                r#"db.sql(["SELECT * WHERE id = ", " AND name = ", " {x}"], id + 1, name({ "}" }))"#
+            )
+            .unwrap()
+        );
+
+        assert!(expression("tag`{}`").is_err());
+        assert!(expression("tag`{a b}`").is_err());
+        assert!(expression("tag`{a`").is_err());
+    }
+
+    #[test]
+    fn templates() {
+        let Ok(Expression::TemplateExpression(expr, _)) =
+            expression(r#"f"{greeting}, {names[0] + 1}! \{x\}""#)
+        else {
+            panic!("expected a template");
+        };
+        assert_eq!(expr.strings, vec!["", ", ", "! {x}"]);
+        assert_eq!(
+            expr.into_concatenation(),
+            expression(r#""" + greeting + ", " + (names[0] + 1) + "! {x}""#).unwrap()
+        );
+
+        assert!(expression(r#"f"{}""#).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use nom::{
+    branch::alt,
+    combinator::{map, value},
+    multi::many0,
+    sequence::{delimited, tuple},
+    IResult,
+};
+
+use crate::lexer::tokens::Tokens;
+
+use super::{
+    ast::{Declaration, MethodSignature, TraitDecl},
+    atoms::*,
+    function::{parse_block, parse_return_type},
+    parse_identifier, spanned,
+    structs::parse_method_parameters,
+};
+
+/// `trait Name { fn method(self, other: Self) -> type; fn default(self) { ... } ... }`
+pub fn parse_trait_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    map(
+        tuple((
+            trait_tag,
+            parse_identifier,
+            delimited(l_squirly_tag, many0(parse_method_signature), r_squirly_tag),
+        )),
+        |(_, name, methods)| Declaration::TraitDeclaration(TraitDecl { name, methods }),
+    )(input)
+}
+
+fn parse_method_signature(input: Tokens) -> IResult<Tokens, MethodSignature> {
+    map(
+        spanned(tuple((
+            function_tag,
+            parse_identifier,
+            delimited(l_paren_tag, parse_method_parameters, r_paren_tag),
+            parse_return_type,
+            alt((
+                value(None, semicolon_tag),
+                map(delimited(l_squirly_tag, parse_block, r_squirly_tag), Some),
+            )),
+        ))),
+        |((_, name, (has_self, parameters), return_type, default), span)| MethodSignature {
+            name,
+            has_self,
+            parameters,
+            return_type,
+            default,
+            span,
+        },
+    )(input)
+}
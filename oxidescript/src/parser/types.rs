@@ -0,0 +1,5850 @@
+use std::collections::{HashMap, HashSet};
+
+use nom::{
+    branch::alt,
+    combinator::{map, opt},
+    multi::separated_list0,
+    sequence::{delimited, terminated, tuple},
+    IResult,
+};
+
+use crate::{
+    cancellation::{CancellationToken, Cancelled},
+    lexer::{escaped_char, quote_string, tokens::Tokens},
+    optimizer::assigned_names,
+};
+
+use super::{
+    ast::{
+        Block, CallExpr, CastExpr, ClosureExpr, Declaration, Expression, ExternDecl, Field,
+        ForExpr, Identifier, IfExpr, ImplDecl, ImplItem, IndexExpr, InfixExpr, InfixOperator,
+        IsExpr, Literal, MatchExpr, MemberAccessExpr, MethodSignature, Number, NumberBase,
+        OptionalAccess, Parameter, PathExpr, Pattern, Program, Span, Statement, StructDecl,
+        TypeDecl, TypeParameter, UnaryOperator, Variant, WhileExpr,
+    },
+    atoms::*,
+    function::{parameter_label, parse_parameters, parse_return_type, parse_type},
+    parse_identifier,
+    pattern::is_exhaustive,
+    structs::parse_field,
+    visit::{walk_block, walk_blocks_mut, walk_program_mut},
+};
+
+/// `type Name = { field: type, ... };` or `type Name = type;`
+pub fn parse_type_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    map(
+        tuple((
+            type_tag,
+            parse_identifier,
+            assign_tag,
+            alt((
+                map(
+                    delimited(
+                        l_squirly_tag,
+                        terminated(separated_list0(comma_tag, parse_field), opt(comma_tag)),
+                        r_squirly_tag,
+                    ),
+                    |fields| (fields, None),
+                ),
+                map(parse_type, |alias| (vec![], Some(alias))),
+            )),
+            semicolon_tag,
+        )),
+        |(_, name, _, (fields, alias), _)| {
+            Declaration::TypeDeclaration(TypeDecl {
+                name,
+                fields,
+                alias,
+            })
+        },
+    )(input)
+}
+
+/// `extern fn name(parameter: type, ...) -> type;`, without a return type the function returns
+/// nothing that can be checked
+pub fn parse_extern_declaration(input: Tokens) -> IResult<Tokens, Declaration> {
+    map(
+        tuple((
+            extern_tag,
+            function_tag,
+            parse_identifier,
+            l_paren_tag,
+            parse_parameters,
+            r_paren_tag,
+            parse_return_type,
+            semicolon_tag,
+        )),
+        |(_, _, name, _, parameters, _, return_type, _)| {
+            Declaration::ExternDeclaration(ExternDecl {
+                name,
+                parameters,
+                return_type,
+            })
+        },
+    )(input)
+}
+
+/// What is known about the types of a program
+#[derive(Clone, Default)]
+struct Types {
+    /// The fields of every `type` and struct, and whether it is a `type`. Structs have methods, so
+    /// only member accesses on values of `type`s are checked
+    shapes: HashMap<String, (Vec<Field>, bool)>,
+    /// The types `type Name = type;` names
+    aliases: HashMap<String, Identifier>,
+    /// The variants of every enum
+    enums: HashMap<String, Vec<Variant>>,
+    /// The enums with `#[derive(Flags)]`, whose values only combine with flags of the same enum
+    flags: HashSet<String>,
+    /// The type parameters of generic structs and enums
+    generics: HashMap<String, Vec<Identifier>>,
+    externs: HashMap<String, ExternDecl>,
+    /// The parameters of top level functions
+    functions: HashMap<String, Vec<Parameter>>,
+    /// The type parameters of generic top level functions, with the bounds of their where clause
+    type_parameters: HashMap<String, Vec<TypeParameter>>,
+    /// The where clauses of top level functions, for pointing at the bound an argument misses
+    where_clauses: HashMap<String, Vec<TypeParameter>>,
+    /// The return types of top level functions that declare one, or whose body ends with a value
+    /// of a known type and has no `return`
+    returns: HashMap<String, Identifier>,
+    /// The methods of every trait
+    traits: HashMap<String, Vec<MethodSignature>>,
+    /// The traits every type implements with `impl Trait for Type`
+    implementations: HashMap<String, Vec<Identifier>>,
+    /// The methods and associated functions of the impl blocks of every type
+    methods: HashMap<String, Vec<MethodSignature>>,
+    /// The names in scope where the program is being walked, innermost last, with the type of
+    /// their values if it is known. Names whose values have no known type still hide the names of
+    /// outer scopes. See [`ScopedWalk`]
+    scopes: Vec<HashMap<String, Option<String>>>,
+    /// The type `Self` stands for in the impl block being checked
+    self_type: Option<String>,
+    /// The name and declared return type of the function whose body is being walked, which
+    /// `return` returns from. `None` in closures, which `return` returns from instead
+    function: Option<(Identifier, Option<Identifier>)>,
+}
+
+impl Types {
+    /// Whether the type is a `type`, struct, enum or alias, or a trait object of a trait, or an
+    /// array of them
+    fn knows(&self, type_: &str) -> bool {
+        if let Some(item) = array_item(type_) {
+            return self.knows(item);
+        }
+        if let Some(trait_) = trait_object(type_) {
+            return self.traits.contains_key(trait_);
+        }
+        let (name, _) = type_arguments(type_);
+        self.shapes.contains_key(name)
+            || self.aliases.contains_key(name)
+            || self.enums.contains_key(name)
+    }
+
+    /// The fields of a `type` or struct and whether it is a `type`, with the type arguments of a
+    /// generic struct in place of its type parameters
+    fn shape(&self, type_: &str) -> Option<(Vec<Field>, bool)> {
+        let (name, arguments) = type_arguments(type_);
+        let (fields, is_type) = self.shapes.get(name)?;
+        let Some(parameters) = self.generics.get(name) else {
+            return Some((fields.clone(), *is_type));
+        };
+        let fields = fields
+            .iter()
+            .map(|field| Field {
+                type_: Identifier(substitute(&field.type_.0, parameters, &arguments)),
+                ..field.clone()
+            })
+            .collect();
+        Some((fields, *is_type))
+    }
+
+    /// The types of the values of a variant like `Option::Some`, with the type arguments of a
+    /// generic enum in place of its type parameters
+    fn variant_fields(&self, type_: &str, variant: &Identifier) -> Option<Vec<String>> {
+        let (name, arguments) = type_arguments(type_);
+        let variant = self
+            .enums
+            .get(name)?
+            .iter()
+            .find(|candidate| candidate.name == *variant)?;
+        let parameters = self.generics.get(name).map(Vec::as_slice).unwrap_or(&[]);
+        Some(
+            variant
+                .fields
+                .iter()
+                .map(|field| substitute(&field.0, parameters, &arguments))
+                .collect(),
+        )
+    }
+
+    /// The type of a generic struct or enum built from values with the types of `fields`, with
+    /// its type arguments inferred like `infer_arguments` does
+    fn infer<'v>(
+        &self,
+        name: &str,
+        fields: impl Iterator<Item = (&'v Identifier, &'v Expression)> + Clone,
+    ) -> String {
+        let Some(parameters) = self.generics.get(name) else {
+            return name.to_string();
+        };
+        format!(
+            "{}<{}>",
+            name,
+            self.infer_arguments(parameters, fields).join(", ")
+        )
+    }
+
+    /// Each type parameter inferred from the first field whose type mentions the parameter and
+    /// whose value has a known type, `T` is a string for a `Box<T>` field whose value is a
+    /// `Box<string>`. Parameters that can't be inferred are `any`
+    fn infer_arguments<'v>(
+        &self,
+        parameters: &[Identifier],
+        fields: impl Iterator<Item = (&'v Identifier, &'v Expression)> + Clone,
+    ) -> Vec<String> {
+        parameters
+            .iter()
+            .map(|parameter| {
+                fields
+                    .clone()
+                    .find_map(|(type_, value)| {
+                        let value = value_type(value, self)?;
+                        match_parameter(&type_.0, &value, &parameter.0)
+                            .filter(|argument| argument != "any")
+                    })
+                    .unwrap_or_else(|| "any".to_string())
+            })
+            .collect()
+    }
+
+    /// The parameters of a top level function called with `arguments`, with the type arguments
+    /// inferred from them in place of the type parameters of a generic function, and the type
+    /// arguments
+    fn call_parameters(
+        &self,
+        function: &str,
+        arguments: &[Expression],
+    ) -> Option<(Vec<Parameter>, Vec<String>)> {
+        let parameters = self.functions.get(function)?;
+        let Some(type_parameters) = self.type_parameters.get(function) else {
+            return Some((parameters.clone(), vec![]));
+        };
+        let names = type_parameters
+            .iter()
+            .map(|parameter| parameter.name.clone())
+            .collect::<Vec<_>>();
+        let type_arguments = self.infer_arguments(
+            &names,
+            parameters
+                .iter()
+                .map(|parameter| &parameter.type_)
+                .zip(arguments),
+        );
+        let substituted = type_arguments
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let parameters = parameters
+            .iter()
+            .map(|parameter| Parameter {
+                type_: Identifier(substitute(&parameter.type_.0, &names, &substituted)),
+                ..parameter.clone()
+            })
+            .collect();
+        Some((parameters, type_arguments))
+    }
+
+    /// Whether values of a type implement a trait. Types and traits that aren't declared in the
+    /// program might, primitives, arrays and `type`s never do
+    fn implements(&self, type_: &str, trait_: &Identifier) -> bool {
+        let (name, _) = type_arguments(type_);
+        if !self.traits.contains_key(&trait_.0)
+            || self
+                .implementations
+                .get(name)
+                .is_some_and(|traits| traits.contains(trait_))
+        {
+            return true;
+        }
+        !self.knows(type_) && !is_known(type_) && !type_.starts_with('[')
+    }
+
+    /// Registers what a top level declaration tells about the types of the program. Fails for
+    /// where clauses bounding something that isn't a type parameter
+    fn declare(&mut self, declaration: &Declaration) -> Result<(), String> {
+        match declaration {
+            Declaration::TypeDeclaration(decl) => {
+                if let Some(alias) = &decl.alias {
+                    self.aliases.insert(decl.name.0.clone(), alias.clone());
+                } else {
+                    self.shapes
+                        .insert(decl.name.0.clone(), (decl.fields.clone(), true));
+                }
+            }
+            Declaration::StructDeclaration(decl) => {
+                self.shapes
+                    .insert(decl.name.0.clone(), (decl.fields.clone(), false));
+                if !decl.type_parameters.is_empty() {
+                    self.generics
+                        .insert(decl.name.0.clone(), decl.type_parameters.clone());
+                }
+            }
+            Declaration::EnumDeclaration(decl) => {
+                self.enums
+                    .insert(decl.name.0.clone(), decl.variants.clone());
+                // `empty()`, `all()` and `contains(flags, other)`
+                if decl.flags {
+                    self.flags.insert(decl.name.0.clone());
+                    let (_, items) = decl.lowered();
+                    self.methods.entry(decl.name.0.clone()).or_default().extend(
+                        items.into_iter().filter_map(|item| match item {
+                            ImplItem::Method(method) => Some(MethodSignature {
+                                name: method.name,
+                                has_self: method.has_self,
+                                parameters: method.parameters,
+                                return_type: method.return_type,
+                                default: None,
+                                span: method.span,
+                            }),
+                            _ => None,
+                        }),
+                    );
+                }
+                if !decl.type_parameters.is_empty() {
+                    self.generics
+                        .insert(decl.name.0.clone(), decl.type_parameters.clone());
+                }
+            }
+            Declaration::ExternDeclaration(decl) => {
+                self.externs.insert(decl.name.0.clone(), decl.clone());
+            }
+            Declaration::FunctionDeclaration {
+                name,
+                is_async,
+                type_parameters,
+                parameters,
+                return_type,
+                where_clause,
+                ..
+            } => {
+                if let Some(predicate) = where_clause.iter().find(|predicate| {
+                    type_parameters
+                        .iter()
+                        .all(|type_parameter| type_parameter.name != predicate.name)
+                }) {
+                    return Err(format!(
+                        "`{}` in the where clause of `{}` isn't a type parameter of it",
+                        predicate.name.0, name.0
+                    ));
+                }
+                self.functions.insert(name.0.clone(), parameters.clone());
+                if !type_parameters.is_empty() {
+                    self.type_parameters.insert(
+                        name.0.clone(),
+                        TypeParameter::with_where_clause(type_parameters, where_clause),
+                    );
+                }
+                if !where_clause.is_empty() {
+                    self.where_clauses
+                        .insert(name.0.clone(), where_clause.clone());
+                }
+                if let Some(return_type) = return_type {
+                    self.returns
+                        .insert(name.0.clone(), call_type(*is_async, return_type));
+                }
+            }
+            Declaration::TraitDeclaration(decl) => {
+                self.traits
+                    .insert(decl.name.0.clone(), decl.methods.clone());
+            }
+            Declaration::ImplDeclaration(decl) => {
+                if let Some(trait_) = &decl.trait_ {
+                    self.implementations
+                        .entry(decl.target.0.clone())
+                        .or_default()
+                        .push(trait_.clone());
+                }
+                self.methods
+                    .entry(decl.target.0.clone())
+                    .or_default()
+                    .extend(decl.items.iter().filter_map(|item| {
+                        match item {
+                            ImplItem::Method(method) => Some(MethodSignature {
+                                name: method.name.clone(),
+                                has_self: method.has_self,
+                                parameters: method.parameters.clone(),
+                                return_type: method
+                                    .return_type
+                                    .as_ref()
+                                    .map(|type_| call_type(method.is_async, type_)),
+                                default: None,
+                                span: method.span,
+                            }),
+                            _ => None,
+                        }
+                    }));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Gives the functions and methods without a return type the type of the value their body
+    /// ends with, see [`InferredReturns`]. Names bound to their results only get a type once they
+    /// have one, which can give more functions a return type, so the program is walked until no
+    /// more return types are inferred. The top level names of the last walk stay in scope
+    fn bind_names(&mut self, program: &Program) {
+        let mut passes = program.len();
+        loop {
+            let mut inferred = InferredReturns::default();
+            ScopedWalk::new(self, &mut inferred).program(&mut program.clone());
+            if !self.infer_returns(inferred) || passes == 0 {
+                break;
+            }
+            passes -= 1;
+        }
+    }
+
+    /// Records the inferred return types of functions and methods that don't have one yet, and
+    /// returns whether there were any
+    fn infer_returns(&mut self, inferred: InferredReturns) -> bool {
+        let mut changed = false;
+        for (target, name, type_) in inferred.0 {
+            match target {
+                None if !self.returns.contains_key(&name.0) => {
+                    self.returns.insert(name.0, type_);
+                    changed = true;
+                }
+                None => {}
+                Some(target) => {
+                    let signature = self.methods.get_mut(&target).and_then(|signatures| {
+                        signatures.iter_mut().find(|signature| {
+                            signature.name == name && signature.return_type.is_none()
+                        })
+                    });
+                    if let Some(signature) = signature {
+                        signature.return_type = Some(type_);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    /// The module object of `import("./path")` as a `type` with a field for every `pub` function
+    /// and constant of the module. Functions of generic functions are `any`, their parameters are
+    /// only known in the module
+    fn import(&mut self, path: &str, module: &Program) {
+        let fields = module
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::DeclarationStatement(declaration, span) if declaration.is_pub() => {
+                    Some((declaration, *span))
+                }
+                _ => None,
+            })
+            .filter_map(|(declaration, span)| {
+                let (name, type_) = match declaration {
+                    Declaration::FunctionDeclaration {
+                        name,
+                        type_parameters,
+                        ..
+                    } if !type_parameters.is_empty() => (name, "any".to_string()),
+                    Declaration::FunctionDeclaration {
+                        name,
+                        is_async,
+                        parameters,
+                        return_type,
+                        ..
+                    } => {
+                        let parameters = parameters
+                            .iter()
+                            .map(|parameter| parameter.type_.0.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        let type_ = match return_type {
+                            Some(type_) => format!(
+                                "impl Fn({}) -> {}",
+                                parameters,
+                                call_type(*is_async, type_).0
+                            ),
+                            None => format!("impl Fn({})", parameters),
+                        };
+                        (name, type_)
+                    }
+                    Declaration::ConstDeclaration(name, value, _, _, type_) => {
+                        let type_ = match type_ {
+                            Some(type_) => type_.0.clone(),
+                            None => value_type(value, &Types::default())
+                                .map(|type_| widened(&type_).to_string())
+                                .unwrap_or("any".to_string()),
+                        };
+                        (name, type_)
+                    }
+                    _ => return None,
+                };
+                Some(Field {
+                    name: name.clone(),
+                    type_: Identifier(type_),
+                    span,
+                })
+            })
+            .collect();
+        self.shapes.insert(module_type(path), (fields, true));
+    }
+
+    /// The type `Self` stands for if `name` is `Self`, or `name`
+    fn resolve_self<'a>(&'a self, name: &'a str) -> &'a str {
+        match &self.self_type {
+            Some(self_type) if name == "Self" => self_type,
+            _ => name,
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Whether a name is in scope, with the type of its value if it is known
+    fn lookup(&self, name: &str) -> Option<&Option<String>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn bind(&mut self, name: &Identifier, type_: Option<String>) {
+        if self.scopes.is_empty() {
+            self.push_scope();
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.0.clone(), type_);
+        }
+    }
+
+    /// The type of values annotated with a type, with `Self` replaced by the type of the impl
+    /// block. Names keep types the checker doesn't know, like `any` or a type parameter, so their
+    /// values count as declared, see [`check_receiver`]
+    fn annotation(&self, type_: &str) -> String {
+        match &self.self_type {
+            Some(self_type) => substitute(type_, &[Identifier("Self".to_string())], &[self_type]),
+            None => type_.to_string(),
+        }
+    }
+
+    /// Binds the names of a pattern matching a value of the type to the types of the parts of the
+    /// value they match: the items of a tuple, the fields of a struct or the values of a variant
+    fn bind_pattern(&mut self, pattern: &Pattern, type_: Option<&str>) {
+        match pattern {
+            Pattern::Binding(name) => self.bind(name, type_.map(str::to_string)),
+            Pattern::Prefix(_, Some(name)) => self.bind(name, Some("string".to_string())),
+            Pattern::Or(patterns) => {
+                for pattern in patterns {
+                    self.bind_pattern(pattern, type_);
+                }
+            }
+            Pattern::Variant(path, patterns) => {
+                let fields = type_
+                    .zip(path.last())
+                    .and_then(|(type_, variant)| self.variant_fields(type_, variant))
+                    .unwrap_or_default();
+                for (index, pattern) in patterns.iter().enumerate() {
+                    self.bind_pattern(pattern, fields.get(index).map(String::as_str));
+                }
+            }
+            Pattern::Tuple(patterns) => {
+                let items = type_.and_then(tuple_items);
+                for (index, pattern) in patterns.iter().enumerate() {
+                    let item = items.as_ref().and_then(|items| items.get(index).copied());
+                    self.bind_pattern(pattern, item);
+                }
+            }
+            // the value of a generic struct has the type arguments the pattern doesn't
+            Pattern::Struct(name, patterns) => {
+                let struct_type = type_
+                    .filter(|type_| type_arguments(type_).0 == name.0)
+                    .unwrap_or(&name.0);
+                let fields = self
+                    .shape(struct_type)
+                    .map(|(fields, _)| fields)
+                    .unwrap_or_default();
+                for (field, pattern) in patterns {
+                    let type_ = fields
+                        .iter()
+                        .find(|known| known.name == *field)
+                        .map(|known| known.type_.0.as_str());
+                    self.bind_pattern(pattern, type_);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Prefix(_, None) => {}
+        }
+    }
+
+    /// The types of a name inside of the blocks of `if name is type { ... } else { ... }`: the
+    /// checked type, and the members of the type of the name that aren't
+    fn narrow(&self, name: &Identifier, type_: &str) -> (Option<String>, Option<String>) {
+        let checked = self.annotation(type_);
+        let Some(Some(known)) = self.lookup(&name.0) else {
+            return (Some(checked), None);
+        };
+        let checked = &checked;
+        let checked_members = self.expand(checked);
+        let rest = self
+            .expand(known)
+            .into_iter()
+            .filter(|member| !checked_members.contains(member))
+            .collect::<Vec<_>>();
+        (
+            Some(checked.clone()),
+            (!rest.is_empty()).then(|| rest.join(" | ")),
+        )
+    }
+
+    /// The type of a top level function or extern as a value, like `impl Fn(number) -> number`.
+    /// Generic functions have none, the types of their parameters depend on the call
+    fn function_type(&self, name: &str) -> Option<String> {
+        if let Some(decl) = self.externs.get(name) {
+            return Some(signature_type(&decl.parameters, decl.return_type.as_ref()));
+        }
+        if self.type_parameters.contains_key(name) {
+            return None;
+        }
+        let parameters = self.functions.get(name)?;
+        Some(signature_type(parameters, self.returns.get(name)))
+    }
+
+    /// The type of a closure, an `impl Fn` with the types of its parameters and of the value its
+    /// body evaluates to with them in scope, or `any`
+    fn closure_type(&self, expr: &ClosureExpr) -> String {
+        let mut types = self.clone();
+        types.push_scope();
+        for parameter in &expr.parameters {
+            let type_ = types.annotation(&parameter.type_.0);
+            types.bind(&parameter.name, Some(type_));
+        }
+        let mut body = expr.body_block();
+        ScopedWalk::new(&mut types, &mut |_: &mut Expression, _: &Types| {})
+            .block_statements(&mut body);
+        let returns = body
+            .return_value
+            .as_ref()
+            .and_then(|value| value_type(value, &types))
+            .map(|returns| call_type(expr.is_async, &Identifier(returns)).0);
+        let parameters = expr
+            .parameters
+            .iter()
+            .map(|parameter| parameter.type_.0.as_str())
+            .collect::<Vec<_>>();
+        format!(
+            "impl Fn({}) -> {}",
+            parameters.join(", "),
+            returns.as_deref().unwrap_or("any")
+        )
+    }
+
+    /// The method or associated function `name` of the impl blocks of a type
+    fn method(&self, type_: &str, name: &Identifier) -> Option<&MethodSignature> {
+        let (name_, _) = type_arguments(type_);
+        self.methods
+            .get(name_)?
+            .iter()
+            .find(|method| method.name == *name)
+    }
+
+    /// The method taking `self` that `value.name(...)` calls on a value of the type. A field of
+    /// the same name takes precedence, its method is only called as `Type::name(value)`
+    fn receiver_method(&self, type_: &str, name: &Identifier) -> Option<&MethodSignature> {
+        let is_field = self
+            .shape(type_)
+            .is_some_and(|(fields, _)| fields.iter().any(|field| field.name == *name));
+        self.method(type_, name)
+            .filter(|method| method.has_self && !is_field)
+    }
+
+    /// Whether a type has an impl of one of the traits the language calls methods of, `Index`,
+    /// `Iterator`, `IntoIterator`, `Disposable` or `AsyncIterator`, which don't have to be declared
+    fn implements_builtin(&self, type_: &str, trait_: &str) -> bool {
+        let (name, _) = type_arguments(type_);
+        self.implementations
+            .get(name)
+            .is_some_and(|traits| traits.iter().any(|implemented| implemented.0 == trait_))
+    }
+
+    /// The type of the items `for` gets from a value of the type: the items of an array, the
+    /// characters of a string, what `next` of an `Iterator` returns besides `null`, what the
+    /// promise `next` of an `AsyncIterator` returns resolves to besides `null`, or the items of the
+    /// iterator `into_iter` of an `IntoIterator` returns
+    fn iterated(&self, type_: &str) -> Option<String> {
+        if let Some(item) = array_item(type_) {
+            return Some(item.to_string());
+        }
+        if type_ == "string" {
+            return Some("string".to_string());
+        }
+        if self.implements_builtin(type_, "IntoIterator") {
+            let iterator = self.method_return(type_, &Identifier("into_iter".to_string()))?;
+            return self.iterated(&iterator);
+        }
+        let asynchronous = self.implements_builtin(type_, "AsyncIterator");
+        if !asynchronous && !self.implements_builtin(type_, "Iterator") {
+            return None;
+        }
+        let mut next = self.method_return(type_, &Identifier("next".to_string()))?;
+        if asynchronous {
+            next = promised(&next)?.to_string();
+        }
+        let items = union_members(&next)
+            .into_iter()
+            .filter(|member| *member != "null")
+            .collect::<Vec<_>>();
+        (!items.is_empty()).then(|| items.join(" | "))
+    }
+
+    /// `container[key]` on a value of a type implementing `Index` as the call of its `get`
+    /// method, `Type::get(container, key)`
+    fn index_call(&self, expr: &IndexExpr, span: Span) -> Option<CallExpr> {
+        let container = value_type(&expr.lhs, self)?;
+        let (name, _) = type_arguments(&container);
+        if !self.implements_builtin(&container, "Index") {
+            return None;
+        }
+        let get = Identifier("get".to_string());
+        self.method(&container, &get)
+            .filter(|method| method.has_self)?;
+        Some(CallExpr {
+            lhs: Box::new(Expression::PathExpression(
+                PathExpr {
+                    segments: vec![Identifier(name.to_string()), get],
+                },
+                span,
+            )),
+            arguments: vec![*expr.lhs.clone(), *expr.index.clone()],
+        })
+    }
+
+    /// The return type of the method `name` of a type, see [`Types::method_type`]
+    fn method_return(&self, type_: &str, name: &Identifier) -> Option<String> {
+        let return_type = &self.method(type_, name)?.return_type.as_ref()?.0;
+        Some(self.method_type(type_, return_type))
+    }
+
+    /// The parameters of the method `name` of a type, with a `self` parameter of the type in front
+    /// if it takes `self`, see [`Types::method_type`]
+    fn method_parameters(&self, type_: &str, name: &Identifier) -> Option<Vec<Parameter>> {
+        let method = self.method(type_, name)?;
+        let self_ = method.has_self.then(|| Parameter {
+            name: Identifier("self".to_string()),
+            type_: Identifier(type_.to_string()),
+            span: method.span,
+        });
+        let parameters = method.parameters.iter().map(|parameter| Parameter {
+            type_: Identifier(self.method_type(type_, &parameter.type_.0)),
+            ..parameter.clone()
+        });
+        Some(self_.into_iter().chain(parameters).collect())
+    }
+
+    /// A type in the signature of a method of `type_`, with `Self` and the type parameters of a
+    /// generic type replaced by that type and its type arguments
+    fn method_type(&self, type_: &str, method_type: &str) -> String {
+        let method_type = substitute(method_type, &[Identifier("Self".to_string())], &[type_]);
+        let (name, arguments) = type_arguments(type_);
+        match self.generics.get(name) {
+            Some(parameters) => substitute(&method_type, parameters, &arguments),
+            None => method_type,
+        }
+    }
+
+    /// The members of a union, with the members of the aliases in it instead of the aliases
+    fn expand(&self, type_: &str) -> Vec<String> {
+        let mut members = vec![];
+        self.expand_into(type_, &mut members, &mut HashSet::new());
+        members
+    }
+
+    fn expand_into(&self, type_: &str, members: &mut Vec<String>, seen: &mut HashSet<String>) {
+        for member in union_members(type_) {
+            match self.aliases.get(member) {
+                // aliases that refer to themselves end up with no members
+                Some(alias) if seen.insert(member.to_string()) => {
+                    self.expand_into(&alias.0, members, seen)
+                }
+                Some(_) => {}
+                None => members.push(member.to_string()),
+            }
+        }
+    }
+}
+
+/// An error of [`check_types`], with the span of the expression or declaration it is about
+#[derive(Debug, PartialEq)]
+pub struct TypeError {
+    pub message: String,
+    pub span: Span,
+}
+
+/// Checks values against the `type`s and structs they are used as, by their fields. Arguments of
+/// `extern fn`s and top level functions have to match their parameters, struct expressions of a
+/// `type` need exactly its fields, and values of a `type` only have its fields. Calls of locals and
+/// parameters of `impl Fn` types are checked against their type, operators against the primitives
+/// they take, and tuple patterns and `.0` against the tuples they are used on. Names have the type
+/// of the binding they refer to in their scope, see [`ScopedWalk`], so a name checked with
+/// `if name is Type { ... }` has the type inside of that block, and names bound by variant patterns
+/// have the types of the values of the variant inside of their arm. Values of generic structs and
+/// enums get their type arguments from the values they are built from.
+/// Arguments for a bounded type parameter have to implement its traits, values of a type parameter
+/// only have the methods of its bounds, and an `impl Trait for Type` needs exactly the trait's methods.
+/// A value fits a union if it fits one of its members, and a literal type only takes its own value.
+/// Matches over a union of literals need an arm for each of them, other matches need an arm that
+/// matches everything. `is` only takes types that can be checked at runtime
+pub fn check_types(program: &Program) -> Result<(), TypeError> {
+    check_types_with_imports(program, &HashMap::new())
+        .map_err(|errors| errors.into_iter().next().unwrap())
+}
+
+/// Like [`check_types`], with the programs of the modules loaded with `import("./path")` by their
+/// path, and with every error instead of the first. `import("./path")` is a promise of a module
+/// object with the `pub` functions and constants of the module, calls of its functions are checked
+/// like calls of `impl Fn` values and it has no other members. Imports of paths that aren't given
+/// aren't checked. The declarations are checked before the code using them, and only if they have
+/// no errors
+pub fn check_types_with_imports(
+    program: &Program,
+    imports: &HashMap<String, Program>,
+) -> Result<(), Vec<TypeError>> {
+    check_types_with(program, imports, None)
+        .expect("checking without cancellation token was cancelled")
+}
+
+/// Like [`check_types_with_imports`], but checks `cancellation` before every top level statement
+/// in each of its passes
+pub fn check_types_cancellable(
+    program: &Program,
+    imports: &HashMap<String, Program>,
+    cancellation: &CancellationToken,
+) -> Result<Result<(), Vec<TypeError>>, Cancelled> {
+    check_types_with(program, imports, Some(cancellation))
+}
+
+fn check_types_with(
+    program: &Program,
+    imports: &HashMap<String, Program>,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Result<(), Vec<TypeError>>, Cancelled> {
+    let cancelled = || cancellation.is_some_and(|cancellation| cancellation.is_cancelled());
+    let mut types = Types::default();
+    for (path, module) in imports {
+        types.import(path, module);
+    }
+    let mut checker = Checker::default();
+    for statement in program {
+        if cancelled() {
+            return Err(Cancelled);
+        }
+        if let Statement::DeclarationStatement(declaration, span) = statement {
+            checker.report(types.declare(declaration), *span);
+        }
+    }
+    if let Err(errors) = checker.finish() {
+        return Ok(Err(errors));
+    }
+    for statement in program {
+        if cancelled() {
+            return Err(Cancelled);
+        }
+        if let Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), span) = statement
+        {
+            let result = check_implementation(decl, &types)
+                .and_then(|()| check_method_names(decl, &types))
+                .and_then(|()| check_builtin_traits(decl));
+            checker.report(result, *span);
+        }
+    }
+    if let Err(errors) = checker.finish() {
+        return Ok(Err(errors));
+    }
+    let buildable = buildable_types(&types);
+    for statement in program {
+        if cancelled() {
+            return Err(Cancelled);
+        }
+        let Statement::DeclarationStatement(declaration, span) = statement else {
+            continue;
+        };
+        let declared_types = match declaration {
+            Declaration::FunctionDeclaration {
+                parameters,
+                return_type,
+                ..
+            } => parameters
+                .iter()
+                .map(|parameter| &parameter.type_)
+                .chain(return_type)
+                .collect(),
+            Declaration::StructDeclaration(decl) => {
+                decl.fields.iter().map(|field| &field.type_).collect()
+            }
+            _ => vec![],
+        };
+        let result = check_recursion(declaration, &types, &buildable).and_then(|()| {
+            declared_types
+                .into_iter()
+                .try_for_each(|type_| check_object_safety(&type_.0, &types))
+        });
+        checker.report(result, *span);
+        if let Declaration::FunctionDeclaration {
+            name,
+            return_type: Some(return_type),
+            body,
+            ..
+        } = declaration
+        {
+            checker.report(check_impl_return(name, return_type, body, &types), *span);
+        }
+    }
+    if let Err(errors) = checker.finish() {
+        return Ok(Err(errors));
+    }
+    types.bind_names(program);
+    let mut walk = ScopedWalk::new(&mut types, &mut checker);
+    walk.cancellation = cancellation;
+    walk.program(&mut program.clone());
+    for statement in program {
+        if cancelled() {
+            return Err(Cancelled);
+        }
+        let result = check_type_parameter_uses(statement, &types);
+        checker.report(result, statement.span());
+    }
+    Ok(checker.finish())
+}
+
+/// Checks the expressions, patterns and returned values of a program with the names in scope
+/// where they are. An error inside of the code of an error already found is the same mistake
+/// found again, like the call around a wrong argument, and is left out
+#[derive(Default)]
+struct Checker {
+    errors: Vec<TypeError>,
+}
+
+impl Checker {
+    fn report(&mut self, result: Result<(), String>, span: Span) {
+        let Err(message) = result else {
+            return;
+        };
+        let overlaps = |error: &TypeError| {
+            error.span.start < span.end.max(span.start + 1)
+                && span.start < error.span.end.max(error.span.start + 1)
+        };
+        if !self.errors.iter().any(overlaps) {
+            self.errors.push(TypeError { message, span });
+        }
+    }
+
+    /// The errors found so far, which the checks after them would only find again
+    fn finish(&mut self) -> Result<(), Vec<TypeError>> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(std::mem::take(&mut self.errors)),
+        }
+    }
+}
+
+impl Visit for Checker {
+    fn expression(&mut self, expression: &mut Expression, types: &Types) {
+        self.report(check_expression(expression, types), expression.span());
+        // `return value` has to fit the return type like the value the body ends with
+        if let (Expression::ReturnExpression(Some(value), _), Some((name, Some(return_type)))) =
+            (expression, &types.function)
+        {
+            let result = check_return(name, return_type, value, types);
+            self.report(result, value.span());
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, types: &Types) {
+        let Statement::DeclarationStatement(declaration, span) = statement else {
+            return;
+        };
+        match declaration {
+            Declaration::ConstDeclaration(name, value, _, _, Some(type_))
+            | Declaration::LetDeclaration(name, value, _, Some(type_)) => {
+                let name = format!("`{}`", name.0);
+                self.report(check_annotation(&name, value, type_, types), value.span());
+            }
+            Declaration::DestructuringDeclaration(pattern, value, _, annotation) => {
+                if let Some(type_) = annotation {
+                    let result = check_annotation("the destructured value", value, type_, types);
+                    self.report(result, value.span());
+                }
+                let type_ = match annotation {
+                    Some(type_) => Some(types.annotation(&type_.0)),
+                    None => value_type(value, types),
+                };
+                self.report(check_pattern(pattern, type_.as_deref(), types), *span);
+            }
+            _ => {}
+        }
+    }
+
+    fn body(&mut self, function: &Function, body: &Block, types: &Types) {
+        if let (Some(return_type), Some(value)) = (function.return_type, &body.return_value) {
+            let result = check_return(function.name, return_type, value, types);
+            self.report(result, value.span());
+        }
+    }
+}
+
+/// A tuple pattern needs a tuple with as many items as it has patterns, and a struct pattern a
+/// value of that struct and fields it has. Values of unknown types and structs the program doesn't
+/// declare can't be checked
+fn check_pattern(pattern: &Pattern, type_: Option<&str>, types: &Types) -> Result<(), String> {
+    match pattern {
+        Pattern::Tuple(patterns) => {
+            let items = match type_.map(|type_| (type_, tuple_items(type_))) {
+                Some((type_, Some(items))) if items.len() != patterns.len() => {
+                    return Err(format!(
+                        "a pattern of {} doesn't fit {}",
+                        items_count(patterns.len()),
+                        article(type_)
+                    ));
+                }
+                Some((type_, None)) => {
+                    return Err(format!(
+                        "only tuples can be destructured like `(a, b)`, not {}",
+                        article(widened(type_))
+                    ));
+                }
+                Some((_, items)) => items,
+                None => None,
+            };
+            patterns
+                .iter()
+                .enumerate()
+                .try_for_each(|(index, pattern)| {
+                    let item = items.as_ref().map(|items| items[index]);
+                    check_pattern(pattern, item, types)
+                })
+        }
+        Pattern::Struct(name, patterns) => {
+            if let Some(type_) = type_.filter(|type_| type_arguments(type_).0 != name.0) {
+                return Err(format!(
+                    "a `{} {{ .. }}` pattern doesn't fit {}",
+                    name.0,
+                    article(type_)
+                ));
+            }
+            let fields = types
+                .shape(type_.unwrap_or(&name.0))
+                .map(|(fields, _)| fields);
+            patterns.iter().try_for_each(|(field, pattern)| {
+                let type_ = match &fields {
+                    Some(fields) => {
+                        let Some(declared) = fields.iter().find(|known| known.name == *field)
+                        else {
+                            return Err(format!("{} has no field `{}`", name.0, field.0));
+                        };
+                        Some(declared.type_.0.as_str()).filter(|type_| known(type_, types))
+                    }
+                    None => None,
+                };
+                check_pattern(pattern, type_, types)
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The structs, `type`s and enums that have values. Recursion needs a way out: a struct whose
+/// field always holds another one of it, like `struct Node { next: Node }`, never ends, and neither
+/// does an enum whose variants all hold one, but `Node | null`, `[Node]` or a variant without it do
+fn buildable_types(types: &Types) -> HashSet<&str> {
+    let mut buildable = HashSet::new();
+    loop {
+        let before = buildable.len();
+        for (name, (fields, _)) in &types.shapes {
+            if fields
+                .iter()
+                .all(|field| builds(&field.type_.0, types, &buildable))
+            {
+                buildable.insert(name.as_str());
+            }
+        }
+        for (name, variants) in &types.enums {
+            if variants.iter().any(|variant| {
+                variant
+                    .fields
+                    .iter()
+                    .all(|field| builds(&field.0, types, &buildable))
+            }) {
+                buildable.insert(name.as_str());
+            }
+        }
+        if buildable.len() == before {
+            return buildable;
+        }
+    }
+}
+
+/// Whether a value of the type can be built from the types in `buildable`, arrays can be empty and
+/// everything that isn't a struct, `type` or enum of the program is assumed to have values
+fn builds(type_: &str, types: &Types, buildable: &HashSet<&str>) -> bool {
+    types.expand(type_).iter().any(|member| {
+        let (name, _) = type_arguments(member);
+        buildable.contains(name)
+            || !types.shapes.contains_key(name) && !types.enums.contains_key(name)
+    })
+}
+
+/// Aliases need a type that isn't an alias of themselves, and structs, `type`s and enums have to
+/// be buildable, see `buildable_types`
+fn check_recursion(
+    declaration: &Declaration,
+    types: &Types,
+    buildable: &HashSet<&str>,
+) -> Result<(), String> {
+    match declaration {
+        Declaration::TypeDeclaration(decl)
+            if decl.alias.is_some() && types.expand(&decl.name.0).is_empty() =>
+        {
+            Err(format!(
+                "`{}` only names aliases of itself, so it has no values",
+                decl.name.0
+            ))
+        }
+        Declaration::TypeDeclaration(TypeDecl {
+            name,
+            fields,
+            alias: None,
+            ..
+        })
+        | Declaration::StructDeclaration(StructDecl { name, fields, .. })
+            if !buildable.contains(name.0.as_str()) =>
+        {
+            let field = fields
+                .iter()
+                .find(|field| !builds(&field.type_.0, types, buildable))
+                .expect("types without values have a field without values");
+            Err(format!(
+                "`{}` can't have any values, because its field `{}` always needs {}, make it `{} | null` or an array",
+                name.0,
+                field.name.0,
+                article(&field.type_.0),
+                field.type_.0
+            ))
+        }
+        Declaration::EnumDeclaration(decl) if !buildable.contains(decl.name.0.as_str()) => {
+            let needed = decl
+                .variants
+                .iter()
+                .flat_map(|variant| &variant.fields)
+                .find(|field| !builds(&field.0, types, buildable))
+                .expect("enums without values have a variant field without values");
+            Err(format!(
+                "`{}` can't have any values, because every variant needs {}, add a variant that doesn't",
+                decl.name.0,
+                article(&needed.0)
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// An impl of a trait declared in the program has exactly the methods of the trait, with the same
+/// parameters, and only structs and enums can implement traits
+fn check_implementation(decl: &ImplDecl, types: &Types) -> Result<(), String> {
+    let Some(trait_) = &decl.trait_ else {
+        return Ok(());
+    };
+    let target = &decl.target.0;
+    if is_known(target) || matches!(types.shapes.get(target), Some((_, true))) {
+        return Err(format!(
+            "only structs and enums can implement {}, not {}",
+            trait_.0,
+            article(target)
+        ));
+    }
+    let Some(signatures) = types.traits.get(&trait_.0) else {
+        return Ok(());
+    };
+    let methods = decl
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ImplItem::Method(method) => Some(method),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for signature in signatures {
+        let Some(method) = methods.iter().find(|method| method.name == signature.name) else {
+            if signature.default.is_some() {
+                continue;
+            }
+            return Err(format!(
+                "impl {} for {} is missing the method `{}`",
+                trait_.0, target, signature.name.0
+            ));
+        };
+        if method.has_self != signature.has_self
+            || method.parameters.len() != signature.parameters.len()
+        {
+            return Err(format!(
+                "`{}` of impl {} for {} has to take the parameters of `{}` in {}",
+                method.name.0, trait_.0, target, signature.name.0, trait_.0
+            ));
+        }
+    }
+    match methods.iter().find(|method| {
+        signatures
+            .iter()
+            .all(|signature| signature.name != method.name)
+    }) {
+        Some(method) => Err(format!(
+            "`{}` isn't a method of {}, so it can't be in impl {} for {}",
+            method.name.0, trait_.0, trait_.0, target
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Getters, setters and the methods of traits are called through the value, so they can't be
+/// named like a field. Other methods can, `value.name` is the field and `Type::name(value)` the
+/// method
+fn check_method_names(decl: &ImplDecl, types: &Types) -> Result<(), String> {
+    let Some((fields, false)) = types.shapes.get(&decl.target.0) else {
+        return Ok(());
+    };
+    for item in &decl.items {
+        let (ImplItem::Method(method) | ImplItem::Getter(method) | ImplItem::Setter(method)) = item
+        else {
+            continue;
+        };
+        let through_value = match item {
+            ImplItem::Method(_) => method.has_self && decl.trait_.is_some(),
+            _ => true,
+        };
+        if through_value && fields.iter().any(|field| field.name == method.name) {
+            return Err(format!(
+                "`{}` is a field of {}, so it can't be {} too",
+                method.name.0,
+                decl.target.0,
+                match item {
+                    ImplItem::Getter(_) => "a getter",
+                    ImplItem::Setter(_) => "a setter",
+                    _ => "a trait method",
+                }
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The traits the language calls a method of, with the method, how many parameters it takes
+/// besides `self`, and what calls it
+const BUILTIN_TRAITS: [(&str, &str, usize, &str); 5] = [
+    ("Index", "get(self, key)", 1, "`container[key]`"),
+    ("Iterator", "next(self)", 0, "`for item in iterator`"),
+    (
+        "IntoIterator",
+        "into_iter(self)",
+        0,
+        "`for item in collection`",
+    ),
+    (
+        "Disposable",
+        "close(self)",
+        0,
+        "`with name = value { ... }`",
+    ),
+    ("AsyncIterator", "next(self)", 0, "`for item in stream`"),
+];
+
+/// An impl of `Index`, `Iterator`, `IntoIterator`, `Disposable` or `AsyncIterator` needs the
+/// method the language calls
+fn check_builtin_traits(decl: &ImplDecl) -> Result<(), String> {
+    let Some((trait_, signature, parameters, caller)) = BUILTIN_TRAITS
+        .iter()
+        .find(|(trait_, ..)| decl.trait_.as_ref().is_some_and(|name| name.0 == *trait_))
+    else {
+        return Ok(());
+    };
+    let (name, _) = signature.split_once('(').unwrap_or_default();
+    let has_method = decl.items.iter().any(|item| {
+        matches!(item, ImplItem::Method(method)
+            if method.name.0 == name && method.has_self && method.parameters.len() == *parameters)
+    });
+    if has_method {
+        return Ok(());
+    }
+    Err(format!(
+        "impl {} for {} needs a method `{}`, which {} calls",
+        trait_, decl.target.0, signature, caller
+    ))
+}
+
+/// The value a function or method ends with has to fit its return type, in which `Self` is the
+/// type of the impl block. Values of unknown types and return types that aren't known, like type
+/// parameters and `impl Trait` types, aren't checked
+fn check_return(
+    name: &Identifier,
+    return_type: &Identifier,
+    value: &Expression,
+    types: &Types,
+) -> Result<(), String> {
+    let expected = match &types.self_type {
+        Some(self_type) => types.method_type(self_type, &return_type.0),
+        None => return_type.0.clone(),
+    };
+    if !known(&expected, types) {
+        return Ok(());
+    }
+    check_value(value, &Identifier(expected), types)
+        .map_err(|err| format!("`{}` has to return {}", name.0, err))
+}
+
+/// `value.method(...)` and `Type::function(...)` have to pass one argument for every parameter of
+/// the method, and the arguments with a known type have to fit them. `value` is the `self` of
+/// `Type::method(value, ...)`, which also calls a method sharing its name with a field
+fn check_method_call(expr: &CallExpr, types: &Types) -> Result<(), String> {
+    let Some((type_, name, parameters)) = method_call_parameters(expr, types) else {
+        return Ok(());
+    };
+    let (type_, _) = type_arguments(&type_);
+    let method = format!("{}::{}", type_, name.0);
+    if expr.arguments.len() != parameters.len() {
+        return Err(format!(
+            "`{}` takes {}, not {}",
+            method,
+            arguments_count(parameters.len()),
+            expr.arguments.len()
+        ));
+    }
+    for (argument, parameter) in expr.arguments.iter().zip(&parameters) {
+        check_value(argument, &parameter.type_, types).map_err(|err| {
+            format!(
+                "argument {} of `{}` has to be {}",
+                parameter_label(&parameter.name),
+                method,
+                err
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Calls of a field holding a function, like the functions of the module object of
+/// `import("./path")`, are checked against the `impl Fn` type of the field
+fn check_field_call(expr: &CallExpr, types: &Types) -> Result<(), String> {
+    let Expression::MemberAccessExpression(access, _) = &*expr.lhs else {
+        return Ok(());
+    };
+    let Some(receiver) = value_type(&access.lhs, types) else {
+        return Ok(());
+    };
+    if types.receiver_method(&receiver, &access.ident).is_some() {
+        return Ok(());
+    }
+    match value_type(&expr.lhs, types) {
+        Some(type_) if impl_fn(&type_).is_some() => {
+            let callee = match &*access.lhs {
+                Expression::IdentifierExpression(name, _) => {
+                    format!("`{}.{}`", name.0, access.ident.0)
+                }
+                _ => format!("`{}`", access.ident.0),
+            };
+            check_function_call(&callee, &type_, expr, types)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The methods of arrays, strings and promises, which values of unknown types are taken to call
+const BUILTIN_METHODS: &[&str] = &[
+    "push",
+    "pop",
+    "shift",
+    "unshift",
+    "map",
+    "filter",
+    "forEach",
+    "find",
+    "findIndex",
+    "some",
+    "every",
+    "reduce",
+    "includes",
+    "indexOf",
+    "join",
+    "slice",
+    "splice",
+    "concat",
+    "sort",
+    "reverse",
+    "flat",
+    "flatMap",
+    "fill",
+    "at",
+    "keys",
+    "values",
+    "entries",
+    "toString",
+    "trim",
+    "split",
+    "replace",
+    "startsWith",
+    "endsWith",
+    "toUpperCase",
+    "toLowerCase",
+    "charAt",
+    "padStart",
+    "padEnd",
+    "repeat",
+    "then",
+    "catch",
+    "finally",
+];
+
+/// `value.method()` only calls a method of an impl block if the type of `value` is known, the
+/// backends call it as `Type::method(value)`. A name whose type couldn't be inferred, like a
+/// closure parameter without an annotation, can't call a method only impl blocks have. Names
+/// declared with a type the checker doesn't know, like `any` or a type parameter, are taken at
+/// their word
+fn check_receiver(expr: &CallExpr, types: &Types) -> Result<(), String> {
+    let Expression::MemberAccessExpression(access, _) = &*expr.lhs else {
+        return Ok(());
+    };
+    let Expression::IdentifierExpression(receiver, _) = &*access.lhs else {
+        return Ok(());
+    };
+    let method = &access.ident;
+    if !matches!(types.lookup(&receiver.0), Some(None))
+        || BUILTIN_METHODS.contains(&method.0.as_str())
+    {
+        return Ok(());
+    }
+    let is_trait_method = types
+        .traits
+        .values()
+        .flatten()
+        .any(|signature| signature.name == *method);
+    let is_field = types
+        .shapes
+        .values()
+        .any(|(fields, _)| fields.iter().any(|field| field.name == *method));
+    let mut owners = types
+        .methods
+        .iter()
+        .filter(|(_, signatures)| {
+            signatures
+                .iter()
+                .any(|signature| signature.has_self && signature.name == *method)
+        })
+        .map(|(type_, _)| type_.as_str())
+        .collect::<Vec<_>>();
+    if is_trait_method || is_field || owners.is_empty() {
+        return Ok(());
+    }
+    owners.sort_unstable();
+    Err(format!(
+        "the type of `{}` isn't known, so `{}.{}()` can't call the method of {}, annotate `{}` with its type",
+        receiver.0,
+        receiver.0,
+        method.0,
+        owners.join(" or "),
+        receiver.0
+    ))
+}
+
+/// Primitives and `null` have no methods of impl blocks, so a value of a known type made of them,
+/// like the `number | null` of `checked_add(a, b)`, can't call one. In particular such a value
+/// isn't an `Option`, even where it stands for a value that might be missing
+fn check_primitive_receiver(expr: &CallExpr, types: &Types) -> Result<(), String> {
+    let Expression::MemberAccessExpression(access, _) = &*expr.lhs else {
+        return Ok(());
+    };
+    let method = &access.ident;
+    let Some(type_) = value_type(&access.lhs, types) else {
+        return Ok(());
+    };
+    let members = union_members(&type_);
+    if !members.iter().all(|member| is_known(member))
+        || BUILTIN_METHODS.contains(&method.0.as_str())
+        || types
+            .traits
+            .values()
+            .flatten()
+            .any(|signature| signature.name == *method)
+    {
+        return Ok(());
+    }
+    let mut owners = types
+        .methods
+        .iter()
+        .filter(|(_, signatures)| {
+            signatures
+                .iter()
+                .any(|signature| signature.has_self && signature.name == *method)
+        })
+        .map(|(type_, _)| type_.as_str())
+        .collect::<Vec<_>>();
+    if owners.is_empty() {
+        return Ok(());
+    }
+    owners.sort_unstable();
+    let hint = match members.iter().any(|member| is_empty(member)) {
+        true => ", a value that can be `null` takes `??` or a comparison with `null` instead",
+        false => "",
+    };
+    Err(format!(
+        "`{}` is a method of {}, not of {}{}",
+        method.0,
+        owners.join(" or "),
+        article(&type_),
+        hint
+    ))
+}
+
+/// The variants of an enum only match values of that enum, so `Option::Some(v)` doesn't match the
+/// `number | null` of `checked_add(a, b)`. Values of types the program doesn't declare can't be
+/// checked
+fn check_variant_patterns(expr: &MatchExpr, types: &Types) -> Result<(), String> {
+    let Some(type_) = value_type(&expr.value, types) else {
+        return Ok(());
+    };
+    let members = union_members(&type_);
+    if !members
+        .iter()
+        .all(|member| is_known(member) || types.knows(member))
+    {
+        return Ok(());
+    }
+    // the first arm that doesn't fit is reported
+    let mut patterns = expr
+        .arms
+        .iter()
+        .rev()
+        .map(|arm| &arm.pattern)
+        .collect::<Vec<_>>();
+    while let Some(pattern) = patterns.pop() {
+        match pattern {
+            Pattern::Or(alternatives) => patterns.extend(alternatives.iter().rev()),
+            Pattern::Variant(path, _) if path.len() == 2 => {
+                let enum_ = types.resolve_self(&path[0].0);
+                if !members
+                    .iter()
+                    .any(|member| type_arguments(member).0 == enum_)
+                {
+                    return Err(format!(
+                        "a `{}::{}` pattern doesn't fit {}",
+                        path[0].0,
+                        path[1].0,
+                        article(&type_)
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The type and the name of the method a call calls, through a value or a path like
+/// `Type::method`, and the parameters the arguments are for
+fn method_call_parameters<'e>(
+    expr: &'e CallExpr,
+    types: &Types,
+) -> Option<(String, &'e Identifier, Vec<Parameter>)> {
+    match &*expr.lhs {
+        Expression::MemberAccessExpression(access, _) => {
+            let receiver = value_type(&access.lhs, types)?;
+            types.receiver_method(&receiver, &access.ident)?;
+            let mut parameters = types
+                .method_parameters(&receiver, &access.ident)
+                .unwrap_or_default();
+            parameters.remove(0);
+            Some((receiver, &access.ident, parameters))
+        }
+        Expression::PathExpression(path, _) => {
+            let [type_, name] = &path.segments[..] else {
+                return None;
+            };
+            let type_ = types.resolve_self(&type_.0);
+            let parameters = types.method_parameters(type_, name)?;
+            Some((type_.to_string(), name, parameters))
+        }
+        _ => None,
+    }
+}
+
+/// Values of a type parameter only have the methods of the traits in its bounds, so a generic
+/// function works with every type argument that satisfies them
+fn check_type_parameter_uses(statement: &Statement, types: &Types) -> Result<(), String> {
+    let Statement::DeclarationStatement(
+        Declaration::FunctionDeclaration {
+            type_parameters,
+            parameters,
+            where_clause,
+            body,
+            ..
+        },
+        _,
+    ) = statement
+    else {
+        return Ok(());
+    };
+    let type_parameters = TypeParameter::with_where_clause(type_parameters, where_clause);
+    // the bounds of every parameter whose type is a type parameter, unless a trait of them isn't
+    // declared in the program
+    let mut bounded = parameters
+        .iter()
+        .filter_map(|parameter| {
+            let type_parameter = type_parameters
+                .iter()
+                .find(|type_parameter| type_parameter.name == parameter.type_)?;
+            let methods = type_parameter
+                .bounds
+                .iter()
+                .map(|bound| types.traits.get(&bound.0))
+                .collect::<Option<Vec<_>>>()?;
+            Some((parameter.name.0.as_str(), (type_parameter, methods)))
+        })
+        .collect::<HashMap<_, _>>();
+    // locals can hide the parameters
+    walk_blocks_mut(&mut statement.clone(), &mut |block| {
+        for statement in &block.statements {
+            if let Statement::DeclarationStatement(
+                Declaration::LetDeclaration(name, _, _, _)
+                | Declaration::ConstDeclaration(name, ..),
+                _,
+            ) = statement
+            {
+                bounded.remove(name.0.as_str());
+            }
+        }
+    });
+    let mut result = Ok(());
+    walk_block(body, &mut |expression| {
+        let Expression::MemberAccessExpression(expr, _) = expression else {
+            return;
+        };
+        let Expression::IdentifierExpression(name, _) = &*expr.lhs else {
+            return;
+        };
+        let Some((type_parameter, methods)) = bounded.get(name.0.as_str()) else {
+            return;
+        };
+        if result.is_err()
+            || methods
+                .iter()
+                .flat_map(|methods| methods.iter())
+                .any(|method| method.name == expr.ident)
+        {
+            return;
+        }
+        result = Err(if type_parameter.bounds.is_empty() {
+            format!(
+                "{} has no bounds, so `{}` has no method `{}`",
+                type_parameter.name.0, name.0, expr.ident.0
+            )
+        } else {
+            format!(
+                "{} only has the methods of {}, not `{}`",
+                type_parameter.name.0,
+                type_parameter
+                    .bounds
+                    .iter()
+                    .map(|bound| bound.0.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" + "),
+                expr.ident.0
+            )
+        });
+    });
+    result
+}
+
+fn check_expression(expression: &Expression, types: &Types) -> Result<(), String> {
+    match expression {
+        // `task::join_all` and `task::race` wait for an array of tasks
+        Expression::CallExpression(expr, _)
+            if matches!(task_function(expr), Some("join_all" | "race")) =>
+        {
+            match value_type(&expr.arguments[0], types) {
+                Some(tasks) if array_item(&tasks).is_none() => Err(format!(
+                    "`task::{}` takes an array of tasks, not {}",
+                    task_function(expr).unwrap_or_default(),
+                    article(&tasks)
+                )),
+                _ => Ok(()),
+            }
+        }
+        // `make_adder(1)(2)` calls the function `make_adder` returns
+        Expression::CallExpression(expr, _) if matches!(&*expr.lhs, Expression::CallExpression(call, _) if matches!(&*call.lhs, Expression::IdentifierExpression(..))) =>
+        {
+            let (Expression::CallExpression(call, _), Some(type_)) =
+                (&*expr.lhs, value_type(&expr.lhs, types))
+            else {
+                return Ok(());
+            };
+            let Expression::IdentifierExpression(name, _) = &*call.lhs else {
+                return Ok(());
+            };
+            let callee = format!("the function `{}` returns", name.0);
+            check_function_call(&callee, &type_, expr, types)
+        }
+        Expression::CallExpression(expr, _)
+            if !matches!(&*expr.lhs, Expression::IdentifierExpression(..)) =>
+        {
+            check_receiver(expr, types)?;
+            check_primitive_receiver(expr, types)?;
+            check_method_call(expr, types)?;
+            check_field_call(expr, types)
+        }
+        Expression::IndexExpression(expr, span) => match types.index_call(expr, *span) {
+            Some(call) => check_method_call(&call, types),
+            None => Ok(()),
+        },
+        // `Index` only reads, there is nothing `container[key] = value` could call
+        Expression::AssignmentExpression(expr, _) => {
+            let Expression::IndexExpression(target, span) = &*expr.lhs else {
+                return Ok(());
+            };
+            match types.index_call(target, *span) {
+                Some(_) => Err(format!(
+                    "{} implements Index, which can only read `container[key]`, not assign it",
+                    value_type(&target.lhs, types).unwrap_or_default()
+                )),
+                None => Ok(()),
+            }
+        }
+        Expression::CallExpression(expr, _) => {
+            let Expression::IdentifierExpression(name, _) = &*expr.lhs else {
+                return Ok(());
+            };
+            // a local name can hide the function
+            if let Some(type_) = types.lookup(&name.0) {
+                return match type_ {
+                    Some(type_) if known(type_, types) => {
+                        check_function_call(&format!("`{}`", name.0), type_, expr, types)
+                    }
+                    _ => Ok(()),
+                };
+            }
+            let call_parameters = types.call_parameters(&name.0, &expr.arguments);
+            let (parameters, type_arguments) = match (types.externs.get(&name.0), call_parameters) {
+                (Some(decl), _) => (decl.parameters.clone(), vec![]),
+                (None, Some(call_parameters)) => call_parameters,
+                _ => return Ok(()),
+            };
+            if expr.arguments.len() != parameters.len() {
+                return Err(format!(
+                    "`{}` takes {}, not {}",
+                    name.0,
+                    arguments_count(parameters.len()),
+                    expr.arguments.len()
+                ));
+            }
+            for (
+                argument,
+                Parameter {
+                    name: parameter,
+                    type_,
+                    ..
+                },
+            ) in expr.arguments.iter().zip(&parameters)
+            {
+                check_value(argument, type_, types).map_err(|err| {
+                    format!(
+                        "argument {} of `{}` has to be {}",
+                        parameter_label(parameter),
+                        name.0,
+                        err
+                    )
+                })?;
+            }
+            // the type arguments inferred from the arguments have to satisfy the bounds
+            let type_parameters = types.type_parameters.get(&name.0).into_iter().flatten();
+            for (type_parameter, type_argument) in type_parameters.zip(&type_arguments) {
+                let Some(bound) = type_parameter
+                    .bounds
+                    .iter()
+                    .find(|bound| !types.implements(type_argument, bound))
+                else {
+                    continue;
+                };
+                let parameter = types.functions[&name.0]
+                    .iter()
+                    .find(|parameter| parameter.type_ == type_parameter.name)
+                    .map_or(String::new(), |parameter| parameter_label(&parameter.name));
+                let in_where_clause =
+                    types
+                        .where_clauses
+                        .get(&name.0)
+                        .into_iter()
+                        .flatten()
+                        .any(|predicate| {
+                            predicate.name == type_parameter.name
+                                && predicate.bounds.contains(bound)
+                        });
+                return Err(format!(
+                    "argument {} of `{}` has to implement {}, not be {}, because of {}`{}: {}`",
+                    parameter,
+                    name.0,
+                    bound.0,
+                    article(type_argument),
+                    if in_where_clause { "" } else { "the bound " },
+                    if in_where_clause {
+                        format!("where {}", type_parameter.name.0)
+                    } else {
+                        type_parameter.name.0.clone()
+                    },
+                    bound.0
+                ));
+            }
+            Ok(())
+        }
+        Expression::StructExpression(expr, _) if types.generics.contains_key(&expr.name.0) => {
+            // the fields whose type uses a type parameter have to agree on its type argument
+            let (Some(type_), Some((fields, _))) = (
+                value_type(expression, types),
+                types.shapes.get(&expr.name.0),
+            ) else {
+                return Ok(());
+            };
+            let parameters = &types.generics[&expr.name.0];
+            let (instantiated, _) = types.shape(&type_).unwrap_or_default();
+            for (name, value) in &expr.fields {
+                let Some(index) = fields.iter().position(|field| field.name == *name) else {
+                    continue;
+                };
+                if substitute(&fields[index].type_.0, parameters, &[]) != fields[index].type_.0 {
+                    check_value(value, &instantiated[index].type_, types).map_err(|err| {
+                        format!("field `{}` of {} has to be {}", name.0, type_, err)
+                    })?;
+                }
+            }
+            Ok(())
+        }
+        Expression::StructExpression(expr, _) => {
+            let Some((fields, true)) = types.shapes.get(&expr.name.0) else {
+                return Ok(());
+            };
+            if let Some(field) = fields
+                .iter()
+                .find(|field| expr.fields.iter().all(|(name, _)| *name != field.name))
+            {
+                return Err(format!(
+                    "{} is missing the field `{}`",
+                    expr.name.0, field.name.0
+                ));
+            }
+            for (name, value) in &expr.fields {
+                let Some(field) = fields.iter().find(|field| field.name == *name) else {
+                    return Err(format!("{} has no field `{}`", expr.name.0, name.0));
+                };
+                check_value(value, &field.type_, types).map_err(|err| {
+                    format!("field `{}` of {} has to be {}", name.0, expr.name.0, err)
+                })?;
+            }
+            Ok(())
+        }
+        Expression::IsExpression(expr, _) => {
+            match types
+                .expand(&expr.type_.0)
+                .into_iter()
+                .find(|member| !runtime_type(member, types))
+            {
+                Some(member) => Err(format!("`{}` can't be checked at runtime", member)),
+                None => Ok(()),
+            }
+        }
+        Expression::MatchExpression(expr, _) => {
+            check_variant_patterns(expr, types)?;
+            check_literal_match(expr, types)
+        }
+        Expression::CastExpression(expr, _) => check_trait_object_cast(expr, types),
+        // values of types the program doesn't declare, like the ones of the host, might have a
+        // `close` method
+        Expression::WithExpression(expr, _) => match value_type(&expr.value, types) {
+            Some(type_)
+                if (types.knows(&type_) || is_known(&type_))
+                    && !types.implements_builtin(&type_, "Disposable") =>
+            {
+                Err(format!(
+                    "{} doesn't implement Disposable, which `with` needs to close it",
+                    type_
+                ))
+            }
+            _ => Ok(()),
+        },
+        // structs are only looped over with an impl of one of the iteration traits, a `next`
+        // method alone isn't called
+        Expression::ForExpression(expr, _) => {
+            let Some(type_) = value_type(&expr.rhs, types) else {
+                return Ok(());
+            };
+            if ["number", "boolean"].contains(&widened(&type_)) {
+                return Err(format!(
+                    "`for` can't loop over {}, only over arrays, strings and iterators",
+                    article(widened(&type_))
+                ));
+            }
+            let iterable = ["Iterator", "IntoIterator", "AsyncIterator"]
+                .iter()
+                .any(|trait_| types.implements_builtin(&type_, trait_));
+            if iterable || types.shape(&type_).is_none() {
+                return Ok(());
+            }
+            match types.method(&type_, &Identifier("next".to_string())) {
+                Some(_) => Err(format!(
+                    "{} has a `next` method, but `for` only calls it with `impl Iterator for {}`",
+                    type_,
+                    type_arguments(&type_).0
+                )),
+                None => Err(format!(
+                    "`for` can't loop over {}, it doesn't implement Iterator, IntoIterator or AsyncIterator",
+                    type_
+                )),
+            }
+        }
+        Expression::InfixExpression(expr, _) => check_operands(expr, types),
+        Expression::UnaryExpression(expr, _) if expr.op != UnaryOperator::LogicalNot => {
+            match value_type(&expr.rhs, types) {
+                Some(type_) if is_known(&type_) && widened(&type_) != "number" => Err(format!(
+                    "`{}` takes a number, not {}",
+                    match expr.op {
+                        UnaryOperator::Minus => "-",
+                        UnaryOperator::Plus => "+",
+                        _ => "~",
+                    },
+                    article(widened(&type_))
+                )),
+                _ => Ok(()),
+            }
+        }
+        Expression::RangeExpression(expr, _) => {
+            for bound in [&expr.start, &expr.end] {
+                match value_type(bound, types) {
+                    Some(type_) if widened(&type_) != "number" => {
+                        return Err(format!(
+                            "the bounds of a range have to be numbers, not {}",
+                            article(widened(&type_))
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+        Expression::TupleIndexExpression(expr, _) => {
+            let Some(type_) = value_type(&expr.lhs, types) else {
+                return Ok(());
+            };
+            match tuple_items(&type_) {
+                Some(items) if expr.index >= items.len() => Err(format!(
+                    "{} has no item {}, only {}",
+                    article(&type_),
+                    expr.index,
+                    items_count(items.len())
+                )),
+                None if is_known(&type_) || array_item(&type_).is_some() => Err(format!(
+                    "only tuples have items like `.{}`, not {}",
+                    expr.index,
+                    article(widened(&type_))
+                )),
+                _ => Ok(()),
+            }
+        }
+        // `?.` and `?[` skip the rest of the chain if the value is `null`, which only values of a
+        // union with `null` can be. Options are enum values, which are never `null`
+        Expression::OptionalExpression(expr, _) => {
+            let access = match expr.access {
+                OptionalAccess::Member(_) => "?.",
+                OptionalAccess::Index(_) => "?[",
+            };
+            match value_type(&expr.lhs, types) {
+                Some(type_) if type_arguments(&type_).0 == "Option" => Err(format!(
+                    "`{}` is for values that can be `null`, {} is never `null`, `match` it or call `unwrap_or`",
+                    access,
+                    article(&type_)
+                )),
+                Some(type_) if !is_nullable(&type_) => Err(format!(
+                    "`{}` is for values that can be `null`, like a `{} | null`, not {}",
+                    access,
+                    widened(&type_),
+                    article(widened(&type_))
+                )),
+                _ => Ok(()),
+            }
+        }
+        Expression::MemberAccessExpression(expr, _) => {
+            let Some(type_) = value_type(&expr.lhs, types) else {
+                return Ok(());
+            };
+            // trait objects only have the methods of their trait
+            if let Some(trait_) = trait_object(&type_) {
+                let methods = types.traits.get(trait_).into_iter().flatten();
+                return match methods.into_iter().any(|method| method.name == expr.ident) {
+                    true => Ok(()),
+                    false => Err(format!(
+                        "{} only has the methods of {}, not `{}`",
+                        type_, trait_, expr.ident.0
+                    )),
+                };
+            }
+            match types.shape(&type_) {
+                Some((fields, true)) if fields.iter().all(|field| field.name != expr.ident) => {
+                    match type_
+                        .strip_prefix("import(\"")
+                        .and_then(|path| path.strip_suffix("\")"))
+                    {
+                        Some(path) => Err(format!(
+                            "the module `{}` has no `pub` function or constant `{}`",
+                            path, expr.ident.0
+                        )),
+                        None => Err(format!("{} has no field `{}`", type_, expr.ident.0)),
+                    }
+                }
+                _ => Ok(()),
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Arithmetic and bitwise operators take numbers, and `+` numbers or a string and a primitive it
+/// is concatenated with. Flags of an enum only combine with flags of the same enum with `|`, `&`
+/// and `^`, other operands are only checked if they are of primitive types
+fn check_operands(expr: &InfixExpr, types: &Types) -> Result<(), String> {
+    let (Some(lhs), Some(rhs)) = (value_type(&expr.lhs, types), value_type(&expr.rhs, types))
+    else {
+        return Ok(());
+    };
+    let (lhs, rhs) = (widened(&lhs), widened(&rhs));
+    if let Some(name) = [lhs, rhs]
+        .into_iter()
+        .find(|type_| types.flags.contains(*type_))
+        .filter(|_| {
+            matches!(
+                expr.op,
+                InfixOperator::BitwiseOr | InfixOperator::BitwiseAnd | InfixOperator::BitwiseXor
+            )
+        })
+    {
+        let other = if lhs == name { rhs } else { lhs };
+        return match other {
+            _ if other == name || other == "any" => Ok(()),
+            _ if types.flags.contains(other) => {
+                Err(format!("flags of {} and {} can't be combined", name, other))
+            }
+            _ => Err(format!(
+                "flags of {} can't be combined with other values, cast them with `as number` first",
+                name
+            )),
+        };
+    }
+    if !is_known(lhs) || !is_known(rhs) {
+        return Ok(());
+    }
+    match expr.op {
+        InfixOperator::Plus
+            if (lhs == "number" && rhs == "number")
+                || (lhs == "string" && is_primitive(rhs))
+                || (rhs == "string" && is_primitive(lhs)) =>
+        {
+            Ok(())
+        }
+        InfixOperator::Plus => Err(format!(
+            "`+` adds numbers or concatenates strings, not {} and {}",
+            article(lhs),
+            article(rhs)
+        )),
+        InfixOperator::Minus
+        | InfixOperator::Multiply
+        | InfixOperator::Divide
+        | InfixOperator::Modulo
+        | InfixOperator::BitwiseOr
+        | InfixOperator::BitwiseXor
+        | InfixOperator::BitwiseAnd
+        | InfixOperator::BitwiseLeftShift
+        | InfixOperator::BitwiseRightShift => {
+            match [lhs, rhs].into_iter().find(|type_| *type_ != "number") {
+                Some(type_) => Err(format!(
+                    "`{}` takes numbers, not {}",
+                    expr.op.symbol(),
+                    article(type_)
+                )),
+                None => Ok(()),
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Calls of values of an `impl Fn` type, like parameters, locals bound to closures and the
+/// functions other functions return, have to pass one argument for every parameter of the type,
+/// and the arguments have to fit them
+fn check_function_call(
+    callee: &str,
+    type_: &str,
+    expr: &CallExpr,
+    types: &Types,
+) -> Result<(), String> {
+    let Some((parameters, _)) = impl_fn(type_) else {
+        return Err(format!(
+            "{} is {}, not a function",
+            callee,
+            article(widened(type_))
+        ));
+    };
+    if expr.arguments.len() != parameters.len() {
+        return Err(format!(
+            "{} takes {}, not {}",
+            callee,
+            arguments_count(parameters.len()),
+            expr.arguments.len()
+        ));
+    }
+    for (index, (argument, parameter)) in expr.arguments.iter().zip(parameters).enumerate() {
+        check_value(argument, &Identifier(parameter.to_string()), types)
+            .map_err(|err| format!("argument {} of {} has to be {}", index + 1, callee, err))?;
+    }
+    Ok(())
+}
+
+/// The value of `let name: type = value` has to fit the type. A value that becomes a trait object
+/// is checked like the cast `value as dyn Trait`
+fn check_annotation(
+    name: &str,
+    value: &Expression,
+    type_: &Identifier,
+    types: &Types,
+) -> Result<(), String> {
+    let type_ = Identifier(types.annotation(&type_.0));
+    if !trait_objects(&type_.0).is_empty() {
+        let cast = CastExpr {
+            value: Box::new(value.clone()),
+            type_,
+        };
+        return check_trait_object_cast(&cast, types);
+    }
+    check_value(value, &type_, types).map_err(|err| format!("{} has to be {}", name, err))
+}
+
+/// A cast to a trait object needs an object safe trait, and a value implementing it. Every item of
+/// an array cast to an array of trait objects has to implement the trait
+fn check_trait_object_cast(expr: &CastExpr, types: &Types) -> Result<(), String> {
+    check_object_safety(&expr.type_.0, types)?;
+    let (item_type, values) = match (array_item(&expr.type_.0), &*expr.value) {
+        (Some(item_type), Expression::ArrayExpression(items, _)) => {
+            (item_type, items.iter().collect())
+        }
+        _ => (expr.type_.0.as_str(), vec![&*expr.value]),
+    };
+    let Some(trait_) = trait_object(item_type) else {
+        return Ok(());
+    };
+    for value in values {
+        let actual = match value {
+            Expression::LiteralExpression(literal, _) => Some(literal_type(literal).to_string()),
+            Expression::TemplateExpression(_, _) => Some("string".to_string()),
+            value => value_type(value, types),
+        };
+        if let Some(actual) = actual.filter(|actual| fits(actual, item_type, types).is_err()) {
+            return Err(format!(
+                "{} doesn't implement {}, so it can't be a `{}`",
+                actual, trait_, item_type
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Methods of a trait object are called without knowing the type of the value, so its trait can
+/// only have methods that take `self` and don't take or return another `Self`
+fn check_object_safety(type_: &str, types: &Types) -> Result<(), String> {
+    for trait_ in trait_objects(type_) {
+        let Some(methods) = types.traits.get(trait_) else {
+            if types.knows(trait_) || is_known(trait_) {
+                return Err(format!(
+                    "`dyn {}` needs a trait, not {}",
+                    trait_,
+                    article(trait_)
+                ));
+            }
+            continue;
+        };
+        for method in methods {
+            let reason = if !method.has_self {
+                "doesn't take `self`"
+            } else if method
+                .parameters
+                .iter()
+                .map(|parameter| &parameter.type_)
+                .chain(&method.return_type)
+                .any(|type_| mentions_self(&type_.0))
+            {
+                "uses `Self`, which isn't known for a `dyn` value"
+            } else {
+                continue;
+            };
+            return Err(format!(
+                "{} can't be used as `dyn {}`, because `{}` {}",
+                trait_, trait_, method.name.0, reason
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// An `impl Shape` return type needs a trait, and the value the function returns has to implement
+/// it. `impl Fn` return types are checked like other values by [`check_return`]
+fn check_impl_return(
+    name: &Identifier,
+    return_type: &Identifier,
+    body: &Block,
+    types: &Types,
+) -> Result<(), String> {
+    let Some(trait_) = trait_object(&return_type.0).filter(|_| return_type.0.starts_with("impl "))
+    else {
+        return Ok(());
+    };
+    if !types.traits.contains_key(trait_) {
+        if types.knows(trait_) || is_known(trait_) {
+            return Err(format!(
+                "`impl {}` needs a trait, not {}",
+                trait_,
+                article(trait_)
+            ));
+        }
+        return Ok(());
+    }
+    match &body.return_value {
+        Some(value) => check_value(value, return_type, types)
+            .map_err(|message| format!("`{}` has to return {}", name.0, message)),
+        None => Ok(()),
+    }
+}
+
+/// The traits of the trait objects in a type, `Shape` for `[dyn Shape] | null`
+fn trait_objects(type_: &str) -> Vec<&str> {
+    let mut words = type_
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty());
+    let mut traits = vec![];
+    while let Some(word) = words.next() {
+        if word == "dyn" {
+            traits.extend(words.next());
+        }
+    }
+    traits
+}
+
+fn mentions_self(type_: &str) -> bool {
+    type_
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .any(|word| word == "Self")
+}
+
+/// Checks that a value can be used as a value of `expected`, the error completes "has to be"
+fn check_value(value: &Expression, expected: &Identifier, types: &Types) -> Result<(), String> {
+    if let Some((parameters, returns)) = impl_fn(&expected.0) {
+        let Some(actual) = value_type(value, types) else {
+            return Ok(());
+        };
+        return match function_mismatch(value, &actual, &parameters, returns, types) {
+            Some(reason) => Err(format!("{}, {}", article(&expected.0), reason)),
+            None => Ok(()),
+        };
+    }
+    let members = types.expand(&expected.0);
+    let actual = match value {
+        // the fields of struct expressions of a `type` are checked on their own
+        Expression::StructExpression(expr, _) if members.contains(&expr.name.0) => return Ok(()),
+        Expression::LiteralExpression(literal, _) => Some(literal_as_type(literal)),
+        Expression::TemplateExpression(_, _) => Some("string".to_string()),
+        value => value_type(value, types),
+    };
+    let Some(actual) = actual else {
+        return Ok(());
+    };
+    // a value of an alias can be any of its members, which all have to fit
+    let fits_any = |actual: &String| {
+        members
+            .iter()
+            .any(|member| fits(actual, member, types).is_ok())
+    };
+    if types.expand(&actual).iter().all(fits_any) {
+        return Ok(());
+    }
+    match &members[..] {
+        [expected] if !types.aliases.contains_key(&actual) => fits(&actual, expected, types),
+        members => Err(format!(
+            "{}, not {}",
+            alternatives(
+                &members
+                    .iter()
+                    .map(|member| article(primitive_name(member)))
+                    .collect::<Vec<_>>()
+            ),
+            article(shown_type(&actual, members))
+        )),
+    }
+}
+
+/// Why a value of `actual` isn't a function taking arguments of the expected parameter types and
+/// returning the expected type, like "but the function takes 2 arguments", or `None` if it is one.
+/// Parameters and results of type `any` fit everything
+fn function_mismatch(
+    value: &Expression,
+    actual: &str,
+    expected_parameters: &[&str],
+    expected_returns: Option<&str>,
+    types: &Types,
+) -> Option<String> {
+    let Some((parameters, returns)) = impl_fn(actual) else {
+        return Some(format!("not {}", article(widened(actual))));
+    };
+    if parameters.len() != expected_parameters.len() {
+        return Some(format!(
+            "but the function takes {}",
+            arguments_count(parameters.len())
+        ));
+    }
+    let mismatch = parameters
+        .iter()
+        .zip(expected_parameters)
+        .position(|(actual, expected)| {
+            *actual != "any" && *expected != "any" && !fits_type(expected, actual, types)
+        });
+    if let Some(index) = mismatch {
+        let label = match value {
+            Expression::ClosureExpression(closure, _) => {
+                parameter_label(&closure.parameters[index].name)
+            }
+            _ => (index + 1).to_string(),
+        };
+        return Some(format!(
+            "but parameter {} of the function is {}",
+            label,
+            article(parameters[index])
+        ));
+    }
+    match (returns, expected_returns) {
+        (Some(actual), Some(expected))
+            if actual != "any" && expected != "any" && !fits_type(actual, expected, types) =>
+        {
+            Some(format!(
+                "but the function evaluates to {}",
+                article(widened(actual))
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Whether every value of `actual` fits `expected`
+fn fits_type(actual: &str, expected: &str, types: &Types) -> bool {
+    let members = types.expand(expected);
+    types.expand(actual).iter().all(|actual| {
+        members
+            .iter()
+            .any(|member| fits(actual, member, types).is_ok())
+    })
+}
+
+/// Checks that values of `actual` fit a single type, the error completes "has to be". Literals fit
+/// themselves and their primitive type, and arrays and tuples fit if their items do
+fn fits(actual: &str, expected: &str, types: &Types) -> Result<(), String> {
+    let (actual, expected) = (primitive_name(actual), primitive_name(expected));
+    if actual == expected
+        || type_as_literal(actual).is_some_and(|literal| literal_type(&literal) == expected)
+    {
+        return Ok(());
+    }
+    // values of every type implementing the trait fit its trait objects
+    if let Some(trait_) = trait_object(expected) {
+        if types.implements(actual, &Identifier(trait_.to_string())) {
+            return Ok(());
+        }
+        return Err(format!(
+            "{}, but {} doesn't implement {}",
+            article(expected),
+            shown_type(actual, &[]),
+            trait_
+        ));
+    }
+    let ((actual_name, actual_arguments), (expected_name, expected_arguments)) =
+        (type_arguments(actual), type_arguments(expected));
+    // values of a generic type fit another instance of it if their type arguments fit, and `any`
+    // or leaving them out fits everything
+    if actual_name == expected_name && types.generics.contains_key(actual_name) {
+        let arguments_fit =
+            actual_arguments
+                .iter()
+                .zip(&expected_arguments)
+                .all(|(actual, expected)| {
+                    *actual == "any" || *expected == "any" || fits(actual, expected, types).is_ok()
+                });
+        if arguments_fit {
+            return Ok(());
+        }
+        return Err(format!("{}, not {}", article(expected), article(actual)));
+    }
+    // a union fits if all of its members do
+    if union_members(expected).len() > 1 || union_members(actual).len() > 1 {
+        return match fits_type(actual, expected, types) {
+            true => Ok(()),
+            false => Err(format!("{}, not {}", article(expected), article(actual))),
+        };
+    }
+    let items_fit = match (array_item(actual), array_item(expected)) {
+        (Some(actual), Some(expected)) => Some(fits_type(actual, expected, types)),
+        _ if promised(actual).is_some() && promised(expected).is_some() => {
+            let (actual, expected) = (promised(actual), promised(expected));
+            Some(fits_type(
+                actual.unwrap_or_default(),
+                expected.unwrap_or_default(),
+                types,
+            ))
+        }
+        _ => match (tuple_items(actual), tuple_items(expected)) {
+            (Some(actual), Some(expected)) => Some(
+                actual.len() == expected.len()
+                    && actual
+                        .iter()
+                        .zip(&expected)
+                        .all(|(actual, expected)| fits_type(actual, expected, types)),
+            ),
+            _ => None,
+        },
+    };
+    // the parameters of functions are checked where the value is known
+    let items_fit = items_fit.or(impl_fn(actual).zip(impl_fn(expected)).map(|_| true));
+    match (items_fit, types.shape(actual), types.shape(expected)) {
+        (Some(true), _, _) => Ok(()),
+        (Some(false), _, _) => Err(format!("{}, not {}", article(expected), article(actual))),
+        (None, Some(_), Some(_)) => compatible(actual, expected, types, &mut HashSet::new())
+            .map_err(|err| format!("{}, but {}", article(expected), err)),
+        (None, None, None) if !known(expected, types) || !known(actual, types) => Ok(()),
+        _ => Err(format!(
+            "{}, not {}",
+            article(expected),
+            article(shown_type(actual, &[expected.to_string()]))
+        )),
+    }
+}
+
+/// Literals are only shown in errors about literal types, otherwise their primitive type is
+fn shown_type<'a>(actual: &'a str, expected: &[String]) -> &'a str {
+    if impl_fn(actual).is_some() {
+        return "function";
+    }
+    match type_as_literal(actual) {
+        Some(literal)
+            if !expected
+                .iter()
+                .any(|member| type_as_literal(member).is_some()) =>
+        {
+            literal_type(&literal)
+        }
+        _ => actual,
+    }
+}
+
+/// A match over a value of an alias for literals, like `type Method = "GET" | "POST";`, needs an
+/// arm for each of them unless an arm matches everything, and can't have arms for other literals.
+/// Every other match needs an arm that matches everything
+fn check_literal_match(expr: &MatchExpr, types: &Types) -> Result<(), String> {
+    let literal_alias = value_type(&expr.value, types)
+        .filter(|type_| types.aliases.contains_key(type_))
+        .map(|name| (types.expand(&name), name))
+        .filter(|(literals, _)| {
+            literals
+                .iter()
+                .all(|literal| type_as_literal(literal).is_some())
+        });
+    let Some((literals, name)) = literal_alias else {
+        if is_exhaustive(&expr.arms) {
+            return Ok(());
+        }
+        return Err("a match needs an arm that matches everything, like `_`".to_string());
+    };
+    let mut patterns = vec![];
+    for arm in &expr.arms {
+        literal_patterns(&arm.pattern, &mut patterns);
+    }
+    if let Some(other) = patterns.iter().find(|pattern| !literals.contains(pattern)) {
+        return Err(format!(
+            "a match on {} has an arm for {}, which isn't {}",
+            name,
+            other,
+            article(&name)
+        ));
+    }
+    let unguarded = expr.arms.iter().filter(|arm| arm.guard.is_none());
+    if unguarded.clone().any(|arm| arm.pattern.is_catch_all()) {
+        return Ok(());
+    }
+    let mut covered = vec![];
+    for arm in unguarded {
+        literal_patterns(&arm.pattern, &mut covered);
+    }
+    match literals.iter().find(|literal| !covered.contains(literal)) {
+        Some(missing) => Err(format!("a match on {} has no arm for {}", name, missing)),
+        None => Ok(()),
+    }
+}
+
+/// What the type parameter `parameter` stands for in `type_` if a value of `type_` is a `value`,
+/// `number` for `T` in `[Pair<T, string>]` and `[Pair<number, string>]`
+fn match_parameter(type_: &str, value: &str, parameter: &str) -> Option<String> {
+    if type_ == parameter {
+        return Some(value.to_string());
+    }
+    if let (Some(type_), Some(value)) = (array_item(type_), array_item(value)) {
+        return match_parameter(type_, value, parameter);
+    }
+    // the parameters of a closure without annotations are `any`, which doesn't tell anything
+    if let (Some((parameters, returns)), Some((values, value_returns))) =
+        (impl_fn(type_), impl_fn(value))
+    {
+        return returns
+            .zip(value_returns)
+            .into_iter()
+            .chain(parameters.into_iter().zip(values))
+            .filter_map(|(type_, value)| match_parameter(type_, value, parameter))
+            .find(|argument| argument != "any");
+    }
+    let ((name, arguments), (value_name, values)) = (type_arguments(type_), type_arguments(value));
+    if arguments.is_empty() || name != value_name || arguments.len() != values.len() {
+        return None;
+    }
+    arguments
+        .iter()
+        .zip(&values)
+        .find_map(|(type_, value)| match_parameter(type_.trim(), value.trim(), parameter))
+}
+
+/// The literals of a pattern, written as literal types
+fn literal_patterns(pattern: &Pattern, literals: &mut Vec<String>) {
+    match pattern {
+        Pattern::Literal(literal) => literals.push(literal_as_type(literal)),
+        Pattern::Or(patterns) => {
+            for pattern in patterns {
+                literal_patterns(pattern, literals);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether values of `actual` have every field of `expected`, with values that fit the fields of
+/// `expected`. Types that are already being compared are assumed to be compatible
+fn compatible(
+    actual: &str,
+    expected: &str,
+    types: &Types,
+    comparing: &mut HashSet<(String, String)>,
+) -> Result<(), String> {
+    if actual == expected || !comparing.insert((actual.to_string(), expected.to_string())) {
+        return Ok(());
+    }
+    let (Some((actual_fields, _)), Some((expected_fields, _))) =
+        (types.shape(actual), types.shape(expected))
+    else {
+        return Ok(());
+    };
+    for field in &expected_fields {
+        let Some(actual_field) = actual_fields
+            .iter()
+            .find(|actual_field| actual_field.name == field.name)
+        else {
+            return Err(format!("{} has no field `{}`", actual, field.name.0));
+        };
+        let (actual_type, expected_type) = (
+            primitive_name(&actual_field.type_.0),
+            primitive_name(&field.type_.0),
+        );
+        match (
+            types.shape(actual_type).is_some(),
+            types.shape(expected_type).is_some(),
+        ) {
+            (true, true) => compatible(actual_type, expected_type, types, comparing)?,
+            _ if actual_type == expected_type
+                || !is_primitive(actual_type)
+                || !is_primitive(expected_type) => {}
+            _ => {
+                return Err(format!(
+                    "field `{}` of {} is {}",
+                    field.name.0,
+                    actual,
+                    article(actual_type)
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The type of a value, if it is known. The type arguments of generic structs and enums are
+/// inferred from the values they are built from, and the fields of values with a known type have
+/// the type of the field. Literals and operators give the primitive they evaluate to, names the
+/// type they are bound to where the value is, and a closure is an `impl Fn` returning the type of
+/// its body, so `T` of `fn computed<T>(compute: impl Fn() -> T)` is what the closure evaluates to
+fn value_type(value: &Expression, types: &Types) -> Option<String> {
+    // `value?.field` and the rest of the chain after it give what they give for the value if it
+    // isn't `null`, or `null`
+    if is_optional_chain(value) {
+        let present = present_chain(value.clone(), types)?;
+        return value_type(&present, types).map(|type_| optional(&type_));
+    }
+    let type_ = match value {
+        Expression::StructExpression(expr, _) => {
+            let name = types.resolve_self(&expr.name.0);
+            let (fields, _) = types.shapes.get(name)?;
+            let values = expr
+                .fields
+                .iter()
+                .filter_map(|(name, value)| {
+                    let field = fields.iter().find(|field| field.name == *name)?;
+                    Some((&field.type_, value))
+                })
+                .collect::<Vec<_>>();
+            types.infer(name, values.into_iter())
+        }
+        Expression::CastExpression(expr, _) => expr.type_.0.clone(),
+        // `container[key]` of a type implementing `Index`, an item of an array or a character
+        Expression::IndexExpression(expr, span) => match types.index_call(expr, *span) {
+            Some(call) => return value_type(&Expression::CallExpression(call, *span), types),
+            None => {
+                let container = value_type(&expr.lhs, types)?;
+                match array_item(&container) {
+                    Some(item) => item.to_string(),
+                    None if container == "string" => container,
+                    None => return None,
+                }
+            }
+        },
+        Expression::RangeExpression(_, _) => "[number]".to_string(),
+        // `[a, b]` whose items all have the same type
+        Expression::ArrayExpression(items, _) => {
+            let mut item_types = items.iter().map(|item| value_type(item, types));
+            let first = item_types.next()??;
+            if !item_types.all(|type_| type_.as_ref() == Some(&first)) {
+                return None;
+            }
+            format!("[{}]", first)
+        }
+        Expression::TupleExpression(items, _) => {
+            let items = items
+                .iter()
+                .map(|item| value_type(item, types))
+                .collect::<Option<Vec<_>>>()?;
+            tuple_type(&items)
+        }
+        Expression::TupleIndexExpression(expr, _) => {
+            let tuple = value_type(&expr.lhs, types)?;
+            tuple_items(&tuple)?.get(expr.index)?.to_string()
+        }
+        // what the promise resolves to, awaiting anything else gives the value itself
+        Expression::AwaitExpression(value, _) => {
+            let type_ = value_type(value, types)?;
+            promised(&type_).map_or(type_.clone(), str::to_string)
+        }
+        Expression::CallExpression(expr, _) => match &*expr.lhs {
+            // a local name can hide the function
+            Expression::IdentifierExpression(name, _) if types.lookup(&name.0).is_some() => {
+                impl_fn(types.lookup(&name.0)?.as_deref()?)?.1?.to_string()
+            }
+            // the checked operations of the prelude give `null` for results out of range
+            Expression::IdentifierExpression(name, _)
+                if InfixOperator::checked(&name.0).is_some()
+                    && expr.arguments.len() == 2
+                    && !types.functions.contains_key(&name.0)
+                    && !types.externs.contains_key(&name.0) =>
+            {
+                "number | null".to_string()
+            }
+            // `clone(value)` copies the value, unless the program declares its own `clone`
+            Expression::IdentifierExpression(name, _)
+                if name.0 == "clone"
+                    && expr.arguments.len() == 1
+                    && !types.functions.contains_key(&name.0)
+                    && !types.externs.contains_key(&name.0) =>
+            {
+                value_type(&expr.arguments[0], types)?
+            }
+            Expression::IdentifierExpression(name, _) => match types.externs.get(&name.0) {
+                Some(decl) => decl.return_type.clone()?.0,
+                None => {
+                    let return_type = types.returns.get(&name.0)?;
+                    match types.type_parameters.get(&name.0) {
+                        Some(type_parameters) => {
+                            let (_, type_arguments) =
+                                types.call_parameters(&name.0, &expr.arguments)?;
+                            let names = type_parameters
+                                .iter()
+                                .map(|parameter| parameter.name.clone())
+                                .collect::<Vec<_>>();
+                            let type_arguments = type_arguments
+                                .iter()
+                                .map(String::as_str)
+                                .collect::<Vec<_>>();
+                            substitute(&return_type.0, &names, &type_arguments)
+                        }
+                        None => return_type.0.clone(),
+                    }
+                }
+            },
+            // `Counter::new()` or `Self::new()`
+            Expression::PathExpression(path, _)
+                if path.segments.len() == 2
+                    && types
+                        .method(types.resolve_self(&path.segments[0].0), &path.segments[1])
+                        .is_some() =>
+            {
+                types.method_return(types.resolve_self(&path.segments[0].0), &path.segments[1])?
+            }
+            // `counter.get()`
+            Expression::MemberAccessExpression(access, _) => {
+                let receiver = value_type(&access.lhs, types)?;
+                match types.receiver_method(&receiver, &access.ident) {
+                    Some(_) => types.method_return(&receiver, &access.ident)?,
+                    // `value.clone()` of a type without a `clone` method copies the value
+                    None if access.ident.0 == "clone" && expr.arguments.is_empty() => receiver,
+                    // a field holding a function, `handler.on_click(event)`
+                    None => impl_fn(&value_type(&expr.lhs, types)?)?.1?.to_string(),
+                }
+            }
+            // the tasks of the prelude give promises of what their functions return
+            Expression::PathExpression(_, span) if task_function(expr).is_some() => {
+                let argument = &expr.arguments[0];
+                if task_function(expr) == Some("spawn") {
+                    let call = CallExpr {
+                        lhs: Box::new(argument.clone()),
+                        arguments: vec![],
+                    };
+                    let returns = value_type(&Expression::CallExpression(call, *span), types)?;
+                    let returns = promised(&returns).unwrap_or(&returns);
+                    format!("Promise<{}>", returns)
+                } else {
+                    let tasks = value_type(argument, types)?;
+                    let task = array_item(&tasks)?;
+                    let result = promised(task).unwrap_or(task);
+                    match task_function(expr) {
+                        Some("race") => format!("Promise<{}>", result),
+                        _ => format!("Promise<[{}]>", result),
+                    }
+                }
+            }
+            // `array::new_2d(width, height, init)` gives rows of items like `init`
+            Expression::PathExpression(path, _)
+                if path.segments.len() == 2
+                    && path.segments[0].0 == "array"
+                    && path.segments[1].0 == "new_2d" =>
+            {
+                let item = value_type(expr.arguments.get(2)?, types)?;
+                format!("[[{}]]", widened(&item))
+            }
+            // `Option::Some(value)`
+            Expression::PathExpression(path, _) => {
+                let [name, variant] = &path.segments[..] else {
+                    return None;
+                };
+                let name = types.resolve_self(&name.0);
+                let fields = types
+                    .enums
+                    .get(name)?
+                    .iter()
+                    .find(|candidate| candidate.name == *variant)?
+                    .fields
+                    .iter()
+                    .zip(&expr.arguments)
+                    .collect::<Vec<_>>();
+                types.infer(name, fields.into_iter())
+            }
+            // a function bound to a name or returned by a call, `add(2)` or `make_adder(1)(2)`
+            callee => impl_fn(&value_type(callee, types)?)?.1?.to_string(),
+        },
+        // a promise of the module object, if the module is known
+        Expression::ImportExpression(expr, _) => {
+            let module = module_type(&expr.path);
+            types.shapes.contains_key(&module).then_some(())?;
+            format!("Promise<{}>", module)
+        }
+        // `Option::None`
+        Expression::PathExpression(path, _) => match &path.segments[..] {
+            [name, _] if types.enums.contains_key(&name.0) => types.infer(&name.0, [].into_iter()),
+            _ => return None,
+        },
+        Expression::MemberAccessExpression(expr, _) => {
+            let (fields, _) = types.shape(&value_type(&expr.lhs, types)?)?;
+            fields
+                .into_iter()
+                .find(|field| field.name == expr.ident)?
+                .type_
+                .0
+        }
+        Expression::IdentifierExpression(name, _) => match types.lookup(&name.0) {
+            Some(type_) => type_.clone().filter(|type_| known(type_, types))?,
+            None => types.function_type(&name.0)?,
+        },
+        Expression::LiteralExpression(literal, _) => literal_type(literal).to_string(),
+        Expression::TemplateExpression(_, _) => "string".to_string(),
+        Expression::IsExpression(_, _) => "boolean".to_string(),
+        Expression::UnaryExpression(expr, _) => match expr.op {
+            UnaryOperator::LogicalNot => "boolean".to_string(),
+            _ => "number".to_string(),
+        },
+        Expression::InfixExpression(expr, _) => infix_type(
+            &expr.op,
+            value_type(&expr.lhs, types).as_deref(),
+            value_type(&expr.rhs, types).as_deref(),
+        )?,
+        Expression::ClosureExpression(expr, _) => types.closure_type(expr),
+        Expression::SliceExpression(expr, _) => value_type(&expr.lhs, types)?,
+        _ => return None,
+    };
+    known(&type_, types).then_some(type_)
+}
+
+/// The type an operator gives for operands of the types, if they are known: comparisons give
+/// booleans and arithmetic on numbers numbers, `+` concatenates if either side is a string, and
+/// `&&`, `||` and `??` give the type both sides have. Bitwise operators on flags of an enum give
+/// flags of the enum
+fn infix_type(op: &InfixOperator, lhs: Option<&str>, rhs: Option<&str>) -> Option<String> {
+    let (lhs, rhs) = (lhs.map(widened), rhs.map(widened));
+    let type_ = match op {
+        InfixOperator::Equal
+        | InfixOperator::NotEqual
+        | InfixOperator::GreaterThan
+        | InfixOperator::LessThan
+        | InfixOperator::GreaterThanEqual
+        | InfixOperator::LessThanEqual => "boolean",
+        InfixOperator::Plus => match (lhs?, rhs?) {
+            ("string", _) | (_, "string") => "string",
+            ("number", "number") => "number",
+            _ => return None,
+        },
+        InfixOperator::LogicalOr | InfixOperator::LogicalAnd => {
+            return lhs.filter(|lhs| Some(*lhs) == rhs).map(str::to_string);
+        }
+        // `value ?? fallback` gives the value if it isn't `null` or the fallback of the same type
+        InfixOperator::NullishCoalesce => {
+            let present = present(lhs?)?;
+            return (Some(widened(&present)) == rhs).then_some(present);
+        }
+        InfixOperator::BitwiseOr | InfixOperator::BitwiseAnd | InfixOperator::BitwiseXor
+            if lhs.is_some_and(|lhs| !is_known(lhs)) && lhs == rhs =>
+        {
+            return lhs.map(str::to_string);
+        }
+        _ if [lhs, rhs]
+            .into_iter()
+            .flatten()
+            .any(|type_| !is_known(type_)) =>
+        {
+            return None
+        }
+        _ => "number",
+    };
+    Some(type_.to_string())
+}
+
+/// Whether values of the type can be `null`: a union with `null`, or `any`. Options are enum
+/// values, which are never `null`
+fn is_nullable(type_: &str) -> bool {
+    type_ == "any" || union_members(type_).contains(&"null")
+}
+
+/// The type of the values of a union with `null` that aren't `null`
+fn present(type_: &str) -> Option<String> {
+    let members = union_members(type_);
+    let present = members
+        .iter()
+        .filter(|member| **member != "null")
+        .copied()
+        .collect::<Vec<_>>();
+    (present.len() < members.len() && !present.is_empty()).then(|| present.join(" | "))
+}
+
+/// The type or `null`, or the type if it can be `null` already
+fn optional(type_: &str) -> String {
+    match is_nullable(type_) {
+        true => type_.to_string(),
+        false => format!("{} | null", type_),
+    }
+}
+
+/// Whether the expression is `value?.field` or `value?[index]`, or a member access, call or index
+/// on one, like `map?.get(key)`
+fn is_optional_chain(value: &Expression) -> bool {
+    match value {
+        Expression::OptionalExpression(_, _) => true,
+        Expression::MemberAccessExpression(expr, _) => is_optional_chain(&expr.lhs),
+        Expression::TupleIndexExpression(expr, _) => is_optional_chain(&expr.lhs),
+        Expression::IndexExpression(expr, _) => is_optional_chain(&expr.lhs),
+        Expression::CallExpression(expr, _) => is_optional_chain(&expr.lhs),
+        _ => false,
+    }
+}
+
+/// The optional chain with its outermost `?.` or `?[` accessing the value it is on as a value that
+/// isn't `null`, `(map as Map).get(key)` for `map?.get(key)` on a `Map | null`
+fn present_chain(mut value: Expression, types: &Types) -> Option<Expression> {
+    let mut link = &mut value;
+    loop {
+        link = match link {
+            Expression::OptionalExpression(_, _) => break,
+            Expression::MemberAccessExpression(expr, _) => &mut expr.lhs,
+            Expression::TupleIndexExpression(expr, _) => &mut expr.lhs,
+            Expression::IndexExpression(expr, _) => &mut expr.lhs,
+            Expression::CallExpression(expr, _) => &mut expr.lhs,
+            _ => return None,
+        };
+    }
+    let Expression::OptionalExpression(expr, span) = link else {
+        return None;
+    };
+    let type_ = present(&value_type(&expr.lhs, types)?)?;
+    let cast = CastExpr {
+        value: expr.lhs.clone(),
+        type_: Identifier(type_),
+    };
+    let lhs = Box::new(Expression::CastExpression(cast, *span));
+    *link = match &expr.access {
+        OptionalAccess::Member(ident) => Expression::MemberAccessExpression(
+            MemberAccessExpr {
+                lhs,
+                ident: ident.clone(),
+            },
+            *span,
+        ),
+        OptionalAccess::Index(index) => Expression::IndexExpression(
+            IndexExpr {
+                lhs,
+                index: index.clone(),
+            },
+            *span,
+        ),
+    };
+    Some(value)
+}
+
+/// The primitive type of a literal type, or the type
+fn widened(type_: &str) -> &str {
+    match type_as_literal(type_) {
+        Some(literal) => literal_type(&literal),
+        None => type_,
+    }
+}
+
+/// What is known about the types of a program where a statement is, for lints that need to know
+/// what expressions give
+pub struct ProgramTypes<'t> {
+    types: &'t Types,
+    /// The top level `async` functions, whose calls give promises even without a known return type
+    async_functions: &'t HashSet<String>,
+}
+
+impl ProgramTypes<'_> {
+    /// Calls `f` with every statement of the program, nested ones included, in their order, and
+    /// the types of the names in scope there
+    pub fn walk(program: &[Statement], mut f: impl FnMut(&Statement, &ProgramTypes)) {
+        let program = program.to_vec();
+        let mut types = Types::default();
+        let mut async_functions = HashSet::new();
+        for statement in &program {
+            let Statement::DeclarationStatement(declaration, _) = statement else {
+                continue;
+            };
+            // the checks report broken declarations, lints make do without them
+            let _ = types.declare(declaration);
+            if let Declaration::FunctionDeclaration {
+                name,
+                is_async: true,
+                ..
+            } = declaration
+            {
+                async_functions.insert(name.0.clone());
+            }
+        }
+        types.bind_names(&program);
+        let mut visitor = Statements {
+            f: &mut f,
+            async_functions: &async_functions,
+        };
+        ScopedWalk::new(&mut types, &mut visitor).program(&mut program.clone());
+    }
+
+    /// Whether the expression gives a `Promise`, like calls of `async` functions and of externs
+    /// returning one, or of `async` methods with a known return type
+    pub fn is_promise(&self, expression: &Expression) -> bool {
+        if let Expression::CallExpression(call, _) = expression {
+            if let Expression::IdentifierExpression(name, _) = &*call.lhs {
+                if self.async_functions.contains(&name.0) {
+                    return true;
+                }
+                if let Some(decl) = self.types.externs.get(&name.0) {
+                    return decl
+                        .return_type
+                        .as_ref()
+                        .is_some_and(|type_| promised(&type_.0).is_some());
+                }
+            }
+        }
+        value_type(expression, self.types).is_some_and(|type_| promised(&type_).is_some())
+    }
+}
+
+/// Calls a function with the statements [`ProgramTypes::walk`] walks
+struct Statements<'f, F> {
+    f: &'f mut F,
+    async_functions: &'f HashSet<String>,
+}
+
+impl<F: FnMut(&Statement, &ProgramTypes)> Visit for Statements<'_, F> {
+    fn statement(&mut self, statement: &Statement, types: &Types) {
+        let types = ProgramTypes {
+            types,
+            async_functions: self.async_functions,
+        };
+        (self.f)(statement, &types)
+    }
+}
+
+/// `spawn`, `join_all` or `race` for a call of `task::spawn(f)`, `task::join_all(tasks)` or
+/// `task::race(tasks)` from the prelude
+pub fn task_function(call: &CallExpr) -> Option<&str> {
+    let Expression::PathExpression(path, _) = &*call.lhs else {
+        return None;
+    };
+    match &path.segments[..] {
+        [module, name]
+            if module.0 == "task"
+                && ["spawn", "join_all", "race"].contains(&name.0.as_str())
+                && call.arguments.len() == 1 =>
+        {
+            Some(&name.0)
+        }
+        _ => None,
+    }
+}
+
+/// A function or method whose body [`ScopedWalk`] walks
+struct Function<'a> {
+    name: &'a Identifier,
+    is_async: bool,
+    kind: FunctionKind,
+    type_parameters: &'a [TypeParameter],
+    return_type: Option<&'a Identifier>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    TopLevel,
+    Method,
+    Getter,
+    /// Setters, default methods of traits, and functions in blocks and modules
+    Other,
+}
+
+/// What [`ScopedWalk`] calls while it walks a program
+trait Visit {
+    /// Called with every expression before its children, which are the children of what the
+    /// expression is replaced with
+    fn expression(&mut self, _expression: &mut Expression, _types: &Types) {}
+
+    /// Called with every statement before it is walked
+    fn statement(&mut self, _statement: &Statement, _types: &Types) {}
+
+    /// Called with the body of every function and method after it has been walked, while its
+    /// parameters and locals are still in scope
+    fn body(&mut self, _function: &Function, _body: &Block, _types: &Types) {}
+}
+
+impl<F: FnMut(&mut Expression, &Types)> Visit for F {
+    fn expression(&mut self, expression: &mut Expression, types: &Types) {
+        self(expression, types)
+    }
+}
+
+/// Walks a program like [`walk_program_mut`], with the names in scope at every expression bound in
+/// `types` to the types of their values, so a name has the type of the binding it refers to.
+/// Parameters have the type of their annotation, the parameters of closures passed as callbacks
+/// the parameter types of the callback, `let`s the type of their value unless they are assigned
+/// somewhere, loop variables the type of the items, and the names of patterns the types of the
+/// parts of the value they match. `if name is Type { ... } else { ... }` narrows the name in its
+/// blocks
+struct ScopedWalk<'w, V> {
+    types: &'w mut Types,
+    visitor: &'w mut V,
+    /// The names assigned somewhere in the program, whose values can change their type
+    assigned: HashSet<String>,
+    /// Checked before every top level statement, the walk stops once it is cancelled
+    cancellation: Option<&'w CancellationToken>,
+}
+
+impl<'w, V: Visit> ScopedWalk<'w, V> {
+    fn new(types: &'w mut Types, visitor: &'w mut V) -> Self {
+        ScopedWalk {
+            types,
+            visitor,
+            assigned: HashSet::new(),
+            cancellation: None,
+        }
+    }
+
+    fn program(&mut self, program: &mut Program) {
+        self.types.scopes = vec![HashMap::new()];
+        self.assigned = assigned_names(program);
+        self.statements(program);
+    }
+
+    /// The inferred type of the value of a name, which names assigned somewhere only keep if it
+    /// is a type of the program, whose values a primitive isn't assigned in place of
+    fn inferred(&self, name: &Identifier, type_: Option<String>) -> Option<String> {
+        type_.filter(|type_| !self.assigned.contains(&name.0) || self.types.knows(type_))
+    }
+
+    fn statements(&mut self, statements: &mut [Statement]) {
+        // functions in blocks can be called before they are declared, top level functions are
+        // known to `types` already
+        if self.types.scopes.len() > 1 {
+            for statement in statements.iter() {
+                if let Statement::DeclarationStatement(
+                    Declaration::FunctionDeclaration {
+                        name,
+                        parameters,
+                        return_type,
+                        ..
+                    },
+                    _,
+                ) = statement
+                {
+                    let type_ = signature_type(parameters, return_type.as_ref());
+                    self.types.bind(name, Some(type_));
+                }
+            }
+        }
+        let cancellation = self.cancellation.filter(|_| self.types.scopes.len() == 1);
+        for statement in statements {
+            if cancellation.is_some_and(|cancellation| cancellation.is_cancelled()) {
+                break;
+            }
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statement) {
+        self.visitor.statement(statement, self.types);
+        match statement {
+            Statement::ExpressionStatement { expression, .. } => self.expression(expression),
+            Statement::DeclarationStatement(declaration, _) => self.declaration(declaration),
+        }
+    }
+
+    fn declaration(&mut self, declaration: &mut Declaration) {
+        let top_level = self.types.scopes.len() == 1;
+        match declaration {
+            // a name declared with a type has it, even if it is assigned somewhere
+            Declaration::ConstDeclaration(name, value, _, _, Some(type_))
+            | Declaration::LetDeclaration(name, value, _, Some(type_)) => {
+                self.expression(value);
+                let type_ = self.types.annotation(&type_.0);
+                self.types.bind(name, Some(type_));
+            }
+            Declaration::ConstDeclaration(name, value, _, _, None)
+            | Declaration::LetDeclaration(name, value, _, None) => {
+                self.expression(value);
+                let type_ = self.inferred(name, value_type(value, self.types));
+                self.types.bind(name, type_);
+            }
+            Declaration::DestructuringDeclaration(pattern, value, _, type_) => {
+                self.expression(value);
+                let type_ = match type_ {
+                    Some(type_) => Some(self.types.annotation(&type_.0)),
+                    None => value_type(value, self.types),
+                };
+                self.types.bind_pattern(pattern, type_.as_deref());
+            }
+            Declaration::FunctionDeclaration {
+                name,
+                is_async,
+                type_parameters,
+                parameters,
+                return_type,
+                body,
+                ..
+            } => {
+                let function = Function {
+                    name,
+                    is_async: *is_async,
+                    kind: match top_level {
+                        true => FunctionKind::TopLevel,
+                        false => FunctionKind::Other,
+                    },
+                    type_parameters,
+                    return_type: return_type.as_ref(),
+                };
+                self.function(&function, parameters, body, None);
+            }
+            Declaration::ImplDeclaration(decl) => {
+                let target = decl.target.0.clone();
+                let outer = self.types.self_type.replace(target.clone());
+                for item in &mut decl.items {
+                    let (kind, method) = match item {
+                        ImplItem::Method(method) => (FunctionKind::Method, method),
+                        ImplItem::Getter(method) => (FunctionKind::Getter, method),
+                        ImplItem::Setter(method) => (FunctionKind::Other, method),
+                        ImplItem::Const(_, value) => {
+                            self.expression(value);
+                            continue;
+                        }
+                    };
+                    // getters and setters take `self` without declaring it
+                    let self_ = (method.has_self || kind != FunctionKind::Method)
+                        .then(|| Some(target.clone()));
+                    let function = Function {
+                        name: &method.name,
+                        is_async: method.is_async,
+                        kind,
+                        type_parameters: &[],
+                        return_type: method.return_type.as_ref(),
+                    };
+                    self.function(&function, &method.parameters, &mut method.body, self_);
+                }
+                self.types.self_type = outer;
+            }
+            Declaration::TraitDeclaration(decl) => {
+                for method in &mut decl.methods {
+                    let Some(default) = &mut method.default else {
+                        continue;
+                    };
+                    let function = Function {
+                        name: &method.name,
+                        is_async: false,
+                        kind: FunctionKind::Other,
+                        type_parameters: &[],
+                        return_type: method.return_type.as_ref(),
+                    };
+                    // `self` is a value of any type implementing the trait
+                    let self_ = method.has_self.then_some(None);
+                    self.function(&function, &method.parameters, default, self_);
+                }
+            }
+            Declaration::ModDeclaration(decl) => {
+                self.types.push_scope();
+                for item in &mut decl.items {
+                    self.declaration(&mut item.declaration);
+                }
+                self.types.pop_scope();
+            }
+            Declaration::StructDeclaration(_)
+            | Declaration::EnumDeclaration(_)
+            | Declaration::ModFileDeclaration(_)
+            | Declaration::UseDeclaration(_)
+            | Declaration::TypeDeclaration(_)
+            | Declaration::ExternDeclaration(_) => {}
+        }
+    }
+
+    /// Walks the body of a function with its parameters and `self`, if it takes one, in scope
+    fn function(
+        &mut self,
+        function: &Function,
+        parameters: &[Parameter],
+        body: &mut Block,
+        self_: Option<Option<String>>,
+    ) {
+        self.types.push_scope();
+        if let Some(self_) = self_ {
+            self.types.bind(&Identifier("self".to_string()), self_);
+        }
+        for parameter in parameters {
+            let type_ = self.types.annotation(&parameter.type_.0);
+            self.types.bind(&parameter.name, Some(type_));
+        }
+        let outer = self
+            .types
+            .function
+            .replace((function.name.clone(), function.return_type.cloned()));
+        self.block_statements(body);
+        self.types.function = outer;
+        self.visitor.body(function, body, self.types);
+        self.types.pop_scope();
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        self.types.push_scope();
+        self.block_statements(block);
+        self.types.pop_scope();
+    }
+
+    /// Walks a block in the current scope, so its locals stay in scope
+    fn block_statements(&mut self, block: &mut Block) {
+        self.statements(&mut block.statements);
+        if let Some(value) = &mut block.return_value {
+            self.expression(value);
+        }
+    }
+
+    /// Walks a block with a name bound to a type, or without binding anything if `name` is `None`
+    fn narrowed_block(&mut self, block: &mut Block, name: Option<(&Identifier, Option<String>)>) {
+        self.types.push_scope();
+        if let Some((name, type_)) = name {
+            self.types.bind(name, type_);
+        }
+        self.block(block);
+        self.types.pop_scope();
+    }
+
+    fn expression(&mut self, expression: &mut Expression) {
+        self.callback(expression, None);
+    }
+
+    /// Walks an expression passed as an argument, whose parameters have the types of
+    /// `parameters` if it is a closure without annotations
+    fn callback(&mut self, expression: &mut Expression, parameters: Option<&[String]>) {
+        self.visitor.expression(expression, self.types);
+        match expression {
+            Expression::IdentifierExpression(_, _)
+            | Expression::LiteralExpression(_, _)
+            | Expression::PathExpression(_, _)
+            | Expression::ImportExpression(_, _)
+            | Expression::BreakExpression(_)
+            | Expression::ContinueExpression(_) => {}
+            Expression::UnaryExpression(expr, _) => self.expression(&mut expr.rhs),
+            Expression::AwaitExpression(value, _) => self.expression(value),
+            Expression::InfixExpression(expr, _) => {
+                self.expression(&mut expr.lhs);
+                self.expression(&mut expr.rhs);
+            }
+            Expression::AssignmentExpression(expr, _) => {
+                self.expression(&mut expr.lhs);
+                self.expression(&mut expr.rhs);
+            }
+            Expression::ArrayExpression(items, _) | Expression::TupleExpression(items, _) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            Expression::IfExpression(expr, _) => {
+                self.expression(&mut expr.condition);
+                let narrowed = match &*expr.condition {
+                    Expression::IsExpression(condition, _) => match &*condition.value {
+                        Expression::IdentifierExpression(name, _) => {
+                            Some((name.clone(), self.types.narrow(name, &condition.type_.0)))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let (name, then_type, else_type) = match narrowed {
+                    Some((name, (then_type, else_type))) => (Some(name), then_type, else_type),
+                    None => (None, None, None),
+                };
+                self.narrowed_block(
+                    &mut expr.then_block,
+                    name.as_ref().map(|name| (name, then_type)),
+                );
+                for else_if in &mut expr.else_if_blocks {
+                    self.expression(&mut else_if.condition);
+                    self.block(&mut else_if.then_block);
+                }
+                if let Some(else_block) = &mut expr.else_block {
+                    let name = name.as_ref().filter(|_| expr.else_if_blocks.is_empty());
+                    self.narrowed_block(else_block, name.map(|name| (name, else_type)));
+                }
+            }
+            Expression::ForExpression(expr, _) => {
+                self.expression(&mut expr.rhs);
+                let item =
+                    value_type(&expr.rhs, self.types).and_then(|type_| self.types.iterated(&type_));
+                let item = self.inferred(&expr.lhs, item);
+                self.narrowed_block(&mut expr.body, Some((&expr.lhs, item)));
+            }
+            Expression::WhileExpression(expr, _) => {
+                self.expression(&mut expr.condition);
+                self.block(&mut expr.body);
+            }
+            Expression::MatchExpression(expr, _) => {
+                self.expression(&mut expr.value);
+                let value = value_type(&expr.value, self.types);
+                for arm in &mut expr.arms {
+                    self.types.push_scope();
+                    self.types.bind_pattern(&arm.pattern, value.as_deref());
+                    if let Some(guard) = &mut arm.guard {
+                        self.expression(guard);
+                    }
+                    self.expression(&mut arm.body);
+                    self.types.pop_scope();
+                }
+            }
+            Expression::WithExpression(expr, _) => {
+                self.expression(&mut expr.value);
+                let value = value_type(&expr.value, self.types);
+                self.narrowed_block(&mut expr.body, Some((&expr.name, value)));
+            }
+            Expression::BlockExpression(block, _) | Expression::DeferExpression(block, _) => {
+                self.block(block)
+            }
+            Expression::TaggedTemplateExpression(expr, _) => {
+                self.expression(&mut expr.tag);
+                for value in &mut expr.values {
+                    self.expression(value);
+                }
+            }
+            Expression::TemplateExpression(expr, _) => {
+                for value in &mut expr.values {
+                    self.expression(value);
+                }
+            }
+            Expression::CallExpression(expr, _) => {
+                self.expression(&mut expr.lhs);
+                let callbacks = callback_parameters(expr, self.types);
+                for (index, argument) in expr.arguments.iter_mut().enumerate() {
+                    let parameters = callbacks.get(index).and_then(Option::as_deref);
+                    self.callback(argument, parameters);
+                }
+            }
+            Expression::IndexExpression(expr, _) => {
+                self.expression(&mut expr.lhs);
+                self.expression(&mut expr.index);
+            }
+            Expression::SliceExpression(expr, _) => {
+                self.expression(&mut expr.lhs);
+                for bound in [&mut expr.start, &mut expr.end].into_iter().flatten() {
+                    self.expression(bound);
+                }
+            }
+            Expression::RangeExpression(expr, _) => {
+                self.expression(&mut expr.start);
+                self.expression(&mut expr.end);
+            }
+            Expression::MemberAccessExpression(expr, _) => self.expression(&mut expr.lhs),
+            Expression::TupleIndexExpression(expr, _) => self.expression(&mut expr.lhs),
+            Expression::CastExpression(expr, _) => self.expression(&mut expr.value),
+            Expression::IsExpression(expr, _) => self.expression(&mut expr.value),
+            Expression::OptionalExpression(expr, _) => {
+                self.expression(&mut expr.lhs);
+                if let OptionalAccess::Index(index) = &mut expr.access {
+                    self.expression(index);
+                }
+            }
+            Expression::StructExpression(expr, _) => {
+                for (_, value) in &mut expr.fields {
+                    self.expression(value);
+                }
+            }
+            Expression::ClosureExpression(expr, _) => {
+                self.types.push_scope();
+                for (index, parameter) in expr.parameters.iter().enumerate() {
+                    // a parameter without an annotation has the type of the callback parameter,
+                    // or none that is known
+                    let type_ = match &parameter.type_.0[..] {
+                        "any" => parameters
+                            .and_then(|parameters| parameters.get(index))
+                            .map(|type_| self.types.annotation(type_))
+                            .filter(|type_| known(type_, self.types)),
+                        type_ => Some(self.types.annotation(type_)),
+                    };
+                    self.types.bind(&parameter.name, type_);
+                }
+                let outer = self.types.function.take();
+                self.expression(&mut expr.body);
+                self.types.function = outer;
+                self.types.pop_scope();
+            }
+            Expression::ReturnExpression(value, _) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+        }
+    }
+}
+
+/// The return types of the functions and methods without one whose body ends with a value of a
+/// known type and has no `return` that could return something else, with the impl block of
+/// methods. Generic functions aren't inferred
+#[derive(Default)]
+struct InferredReturns(Vec<(Option<String>, Identifier, Identifier)>);
+
+impl Visit for InferredReturns {
+    fn body(&mut self, function: &Function, body: &Block, types: &Types) {
+        let target = match function.kind {
+            FunctionKind::TopLevel => None,
+            FunctionKind::Method => types.self_type.clone(),
+            FunctionKind::Getter | FunctionKind::Other => return,
+        };
+        if function.return_type.is_some() || !function.type_parameters.is_empty() {
+            return;
+        }
+        let mut returns = false;
+        walk_block(body, &mut |expression| {
+            returns |= matches!(expression, Expression::ReturnExpression(..));
+        });
+        let Some(value) = body.return_value.as_ref().filter(|_| !returns) else {
+            return;
+        };
+        if let Some(type_) = value_type(value, types) {
+            let type_ = call_type(function.is_async, &Identifier(type_));
+            self.0.push((target, function.name.clone(), type_));
+        }
+    }
+}
+
+/// The parameter types of the callbacks a call passes as its arguments, for every argument: the
+/// parameters of the `impl Fn` type of the parameter it is for, or the items of the array and
+/// their index for the callbacks of array methods like `items.map(|item| ...)`
+fn callback_parameters(expr: &CallExpr, types: &Types) -> Vec<Option<Vec<String>>> {
+    if let Expression::MemberAccessExpression(access, _) = &*expr.lhs {
+        if let Some(items) = value_type(&access.lhs, types)
+            .as_deref()
+            .and_then(array_item)
+            .filter(|_| {
+                types
+                    .receiver_method(&access.ident.0, &access.ident)
+                    .is_none()
+            })
+        {
+            let item = items.to_string();
+            let parameters = match access.ident.0.as_str() {
+                "map" | "filter" | "forEach" | "find" | "findIndex" | "findLast"
+                | "findLastIndex" | "some" | "every" | "flatMap" => {
+                    vec![item, "number".to_string()]
+                }
+                "sort" => vec![item.clone(), item],
+                "reduce" => {
+                    let accumulator = expr
+                        .arguments
+                        .get(1)
+                        .and_then(|initial| value_type(initial, types))
+                        .unwrap_or_else(|| "any".to_string());
+                    vec![accumulator, item, "number".to_string()]
+                }
+                _ => return vec![],
+            };
+            return vec![Some(parameters)];
+        }
+    }
+    let parameters = match &*expr.lhs {
+        Expression::IdentifierExpression(name, _) => match types.lookup(&name.0) {
+            Some(type_) => type_.as_deref().and_then(impl_fn).map(|(parameters, _)| {
+                parameters
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            }),
+            None => types
+                .call_parameters(&name.0, &expr.arguments)
+                .map(|(parameters, _)| parameter_types(&parameters))
+                .or_else(|| {
+                    let decl = types.externs.get(&name.0)?;
+                    Some(parameter_types(&decl.parameters))
+                }),
+        },
+        _ => method_call_parameters(expr, types)
+            .map(|(_, _, parameters)| parameter_types(&parameters)),
+    };
+    parameters
+        .unwrap_or_default()
+        .iter()
+        .map(|parameter| {
+            impl_fn(parameter)
+                .map(|(parameters, _)| parameters.into_iter().map(str::to_string).collect())
+        })
+        .collect()
+}
+
+fn parameter_types(parameters: &[Parameter]) -> Vec<String> {
+    parameters
+        .iter()
+        .map(|parameter| parameter.type_.0.clone())
+        .collect()
+}
+
+/// The type of a function with the parameters and the return type as a value, like
+/// `impl Fn(number) -> string`
+fn signature_type(parameters: &[Parameter], return_type: Option<&Identifier>) -> String {
+    format!(
+        "impl Fn({}) -> {}",
+        parameter_types(parameters).join(", "),
+        return_type.map_or("any", |return_type| return_type.0.as_str())
+    )
+}
+
+/// Whether values of the type are known: the `type`s, structs and enums of the program,
+/// primitives, literals and functions, and promises, arrays and tuples of them
+fn known(type_: &str, types: &Types) -> bool {
+    if impl_fn(type_).is_some() {
+        return true;
+    }
+    let members = union_members(type_);
+    if members.len() > 1 {
+        return members.iter().all(|member| known(member, types));
+    }
+    match (promised(type_), array_item(type_), tuple_items(type_)) {
+        (Some(inner), _, _) | (_, Some(inner), _) => known(inner, types),
+        (_, _, Some(items)) => items.iter().all(|item| known(item, types)),
+        _ => types.knows(type_) || is_known(type_),
+    }
+}
+
+/// The type a call of a function or method with the return type gives, a `Promise` of it for an
+/// `async` function
+fn call_type(is_async: bool, return_type: &Identifier) -> Identifier {
+    match is_async {
+        true => Identifier(format!("Promise<{}>", return_type.0)),
+        false => return_type.clone(),
+    }
+}
+
+/// The members of a union type like `string | [number]`, or just the type if it is no union.
+/// Unions inside of array types and type arguments stay where they are, and a union after the
+/// `->` of `impl Fn` is its return type
+pub fn union_members(type_: &str) -> Vec<&str> {
+    if type_.starts_with("impl ") {
+        return vec![type_];
+    }
+    split_top_level(type_, '|')
+}
+
+/// The trait of a trait object type, `Shape` for `dyn Shape`, or of the `impl Shape` a function
+/// returns, which callers can only use like a `dyn Shape`
+pub fn trait_object(type_: &str) -> Option<&str> {
+    type_.strip_prefix("dyn ").or_else(|| {
+        type_
+            .strip_prefix("impl ")
+            .filter(|_| impl_fn(type_).is_none())
+    })
+}
+
+/// The parameter types and the return type of `impl Fn(number, string) -> number`, the return type
+/// of functions returning a closure
+pub fn impl_fn(type_: &str) -> Option<(Vec<&str>, Option<&str>)> {
+    let rest = type_.strip_prefix("impl Fn(")?;
+    // the parameters can't be `impl` types with parentheses of their own
+    let (mut in_string, mut escaped) = (false, false);
+    let end = rest.char_indices().find_map(|(i, c)| {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ')' if !in_string => return Some(i),
+            _ => {}
+        }
+        None
+    })?;
+    let parameters = match rest[..end].trim() {
+        "" => vec![],
+        parameters => split_top_level(parameters, ','),
+    };
+    let returns = match rest[end + 1..].trim() {
+        "" => None,
+        returns => Some(returns.strip_prefix("->")?.trim()),
+    };
+    Some((parameters, returns))
+}
+
+/// The type of the module object of `import("./path")`
+fn module_type(path: &str) -> String {
+    format!("import(\"{}\")", path)
+}
+
+/// `number` for `[number]`
+pub fn array_item(type_: &str) -> Option<&str> {
+    type_.strip_prefix('[')?.strip_suffix(']')
+}
+
+/// The types of the items of a tuple type like `(number, string)` or `(number,)`, `None` for
+/// other types
+pub fn tuple_items(type_: &str) -> Option<Vec<&str>> {
+    let items = type_.strip_prefix('(')?.strip_suffix(')')?;
+    let items = items.trim_end().strip_suffix(',').unwrap_or(items);
+    (union_members(type_).len() == 1).then(|| split_top_level(items, ','))
+}
+
+/// The tuple type of some item types, with the trailing comma of `(number,)` for one item
+pub fn tuple_type(items: &[impl AsRef<str>]) -> String {
+    let items = items.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+    match &items[..] {
+        [item] => format!("({},)", item),
+        items => format!("({})", items.join(", ")),
+    }
+}
+
+/// What a `Promise<T>` resolves to, `None` for other types
+pub fn promised(type_: &str) -> Option<&str> {
+    match type_arguments(type_) {
+        ("Promise", arguments) if arguments.len() == 1 => Some(arguments[0]),
+        _ => None,
+    }
+}
+
+/// The name of a type and its type arguments, `("Pair", ["number", "[string]"])` for
+/// `Pair<number, [string]>`. Types without arguments have none
+pub fn type_arguments(type_: &str) -> (&str, Vec<&str>) {
+    match type_.split_once('<') {
+        Some((name, arguments))
+            if !name.starts_with(['[', '"', '('])
+                && !name.starts_with("impl ")
+                && type_.ends_with('>')
+                && union_members(type_).len() == 1 =>
+        {
+            (
+                name,
+                split_top_level(&arguments[..arguments.len() - 1], ','),
+            )
+        }
+        _ => (type_, vec![]),
+    }
+}
+
+/// Replaces the type parameters in a type with the type arguments at the same position, or with
+/// `any` if there are fewer arguments than parameters
+pub fn substitute(type_: &str, parameters: &[Identifier], arguments: &[&str]) -> String {
+    let mut substituted = String::new();
+    let mut name = String::new();
+    let (mut in_string, mut escaped) = (false, false);
+    let flush = |name: &mut String, substituted: &mut String| {
+        match parameters.iter().position(|parameter| parameter.0 == *name) {
+            Some(index) => substituted.push_str(arguments.get(index).copied().unwrap_or("any")),
+            None => substituted.push_str(name),
+        }
+        name.clear();
+    };
+    for c in type_.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            c if !in_string && (c.is_alphanumeric() || c == '_') => {
+                name.push(c);
+                continue;
+            }
+            _ => {}
+        }
+        flush(&mut name, &mut substituted);
+        substituted.push(c);
+    }
+    flush(&mut name, &mut substituted);
+    substituted
+}
+
+/// Splits a type at the `separator`s that aren't inside of an array type, type arguments or a
+/// string literal
+fn split_top_level(type_: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let (mut depth, mut start) = (0, 0);
+    let (mut in_string, mut escaped) = (false, false);
+    for (i, c) in type_.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '[' | '<' | '(' => depth += 1,
+            // the arrow of `impl Fn() -> T` closes nothing
+            '>' if type_[..i].ends_with('-') => {}
+            ']' | '>' | ')' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(type_[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(type_[start..].trim());
+    parts
+}
+
+/// `a, b or c`, for the members of a union in messages
+pub fn alternatives(members: &[String]) -> String {
+    match members {
+        [] => String::new(),
+        [member] => member.clone(),
+        [rest @ .., last] => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+/// Replaces the aliases in the types of `is` expressions with the types they name, for backends
+/// that don't know the `type`s of the program
+pub fn lower_type_aliases(mut program: Program) -> Program {
+    let aliases = program
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::DeclarationStatement(
+                Declaration::TypeDeclaration(TypeDecl {
+                    name,
+                    alias: Some(alias),
+                    ..
+                }),
+                _,
+            ) => Some((name.0.clone(), alias.clone())),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    if aliases.is_empty() {
+        return program;
+    }
+    let types = Types {
+        aliases,
+        ..Types::default()
+    };
+    walk_program_mut(&mut program, &mut |expression| {
+        if let Expression::IsExpression(expr, _) = expression {
+            expr.type_ = Identifier(types.expand(&expr.type_.0).join(" | "));
+        }
+    });
+    program
+}
+
+/// Replaces calls of methods through values, like `counter.bumped(2)`, with calls of the method
+/// on the type of the value that pass the value as `self`, like `Counter::bumped(counter, 2)`, for
+/// backends whose struct values don't carry the methods of their impl blocks. Only calls on values
+/// whose type is known are lowered, and `self` has the type of the impl block it is used in
+pub fn lower_method_calls(program: Program) -> Program {
+    lower_method_calls_where(program, |_| true)
+}
+
+/// Like [`lower_method_calls`], only for the methods `lower` returns true for
+pub fn lower_method_calls_where(
+    mut program: Program,
+    lower: impl Fn(&Identifier) -> bool,
+) -> Program {
+    let mut types = Types::default();
+    for statement in &program {
+        if let Statement::DeclarationStatement(declaration, _) = statement {
+            // the checker reports declarations that don't make sense
+            let _ = types.declare(declaration);
+        }
+    }
+    if types.methods.is_empty() {
+        return program;
+    }
+    types.bind_names(&program);
+    ScopedWalk::new(
+        &mut types,
+        &mut |expression: &mut Expression, types: &Types| {
+            let Expression::CallExpression(expr, _) = expression else {
+                return;
+            };
+            let Expression::MemberAccessExpression(access, span) = &*expr.lhs else {
+                return;
+            };
+            let Some(receiver) = value_type(&access.lhs, types) else {
+                return;
+            };
+            if !lower(&access.ident) || types.receiver_method(&receiver, &access.ident).is_none() {
+                return;
+            }
+            let (name, _) = type_arguments(&receiver);
+            let path = Expression::PathExpression(
+                PathExpr {
+                    segments: vec![Identifier(name.to_string()), access.ident.clone()],
+                },
+                *span,
+            );
+            let Expression::MemberAccessExpression(access, _) =
+                std::mem::replace(&mut *expr.lhs, path)
+            else {
+                unreachable!("the callee was checked to be a member access");
+            };
+            expr.arguments.insert(0, *access.lhs);
+        },
+    )
+    .program(&mut program);
+    program
+}
+
+/// Replaces `container[key]` on values of types implementing `Index` with calls of their `get`
+/// method, `Grid::get(grid, key)`. Indexing values of other or unknown types stays as it is
+pub fn lower_index_operators(mut program: Program) -> Program {
+    let mut types = Types::default();
+    for statement in &program {
+        if let Statement::DeclarationStatement(declaration, _) = statement {
+            // the checker reports declarations that don't make sense
+            let _ = types.declare(declaration);
+        }
+    }
+    if !types
+        .implementations
+        .values()
+        .flatten()
+        .any(|trait_| trait_.0 == "Index")
+    {
+        return program;
+    }
+    types.bind_names(&program);
+    ScopedWalk::new(
+        &mut types,
+        &mut |expression: &mut Expression, types: &Types| {
+            let Expression::IndexExpression(expr, span) = expression else {
+                return;
+            };
+            if let Some(call) = types.index_call(expr, *span) {
+                *expression = Expression::CallExpression(call, *span);
+            }
+        },
+    )
+    .program(&mut program);
+    program
+}
+
+/// Replaces `for` loops over values of types implementing `Iterator`, `IntoIterator` or
+/// `AsyncIterator` with `while` loops that call `next` and stop once it returns `null` or nothing,
+/// awaiting what `next` of an `AsyncIterator` returns:
+///
+/// ```text
+/// { const __iterator_0 = bag.into_iter(); while true { let item = __iterator_0.next(); ... } }
+/// ```
+///
+/// The method calls are lowered like every other one. Loops over arrays and values of unknown
+/// types stay as they are
+pub fn lower_iterators(mut program: Program) -> Program {
+    let mut types = Types::default();
+    for statement in &program {
+        if let Statement::DeclarationStatement(declaration, _) = statement {
+            // the checker reports declarations that don't make sense
+            let _ = types.declare(declaration);
+        }
+    }
+    if !types
+        .implementations
+        .values()
+        .flatten()
+        .any(|trait_| ["Iterator", "IntoIterator", "AsyncIterator"].contains(&trait_.0.as_str()))
+    {
+        return program;
+    }
+    types.bind_names(&program);
+    let mut count = 0;
+    ScopedWalk::new(
+        &mut types,
+        &mut |expression: &mut Expression, types: &Types| {
+            let Expression::ForExpression(expr, span) = expression else {
+                return;
+            };
+            let Some(type_) = value_type(&expr.rhs, types) else {
+                return;
+            };
+            let into_iter = types.implements_builtin(&type_, "IntoIterator");
+            let asynchronous = types.implements_builtin(&type_, "AsyncIterator");
+            if !into_iter && !asynchronous && !types.implements_builtin(&type_, "Iterator") {
+                return;
+            }
+            let span = *span;
+            let call = |value: Expression, method: &str| {
+                Expression::CallExpression(
+                    CallExpr {
+                        lhs: Box::new(Expression::MemberAccessExpression(
+                            MemberAccessExpr {
+                                lhs: Box::new(value),
+                                ident: Identifier(method.to_string()),
+                            },
+                            span,
+                        )),
+                        arguments: vec![],
+                    },
+                    span,
+                )
+            };
+            let iterator = Identifier(format!("__iterator_{}", count));
+            count += 1;
+            let Expression::ForExpression(ForExpr { lhs, rhs, body }, _) =
+                std::mem::replace(expression, Expression::BreakExpression(span))
+            else {
+                unreachable!("the expression was checked to be a for loop");
+            };
+            let value = if into_iter {
+                call(*rhs, "into_iter")
+            } else {
+                *rhs
+            };
+            let item = Expression::IdentifierExpression(lhs.clone(), span);
+            let done = Expression::IfExpression(
+                IfExpr {
+                    condition: Box::new(Expression::IsExpression(
+                        IsExpr {
+                            value: Box::new(item),
+                            type_: Identifier("null | undefined".to_string()),
+                        },
+                        span,
+                    )),
+                    then_block: Box::new(Block {
+                        statements: vec![Statement::ExpressionStatement {
+                            expression: Expression::BreakExpression(span),
+                            has_semicolon: true,
+                            span,
+                        }],
+                        return_value: None,
+                        span,
+                    }),
+                    else_if_blocks: vec![],
+                    else_block: None,
+                },
+                span,
+            );
+            let mut next = call(
+                Expression::IdentifierExpression(iterator.clone(), span),
+                "next",
+            );
+            if asynchronous {
+                next = Expression::AwaitExpression(Box::new(next), span);
+            }
+            let mut statements = vec![
+                Statement::DeclarationStatement(
+                    Declaration::LetDeclaration(lhs, next, vec![], None),
+                    span,
+                ),
+                Statement::ExpressionStatement {
+                    expression: done,
+                    has_semicolon: false,
+                    span,
+                },
+            ];
+            statements.extend(body.statements);
+            let looped = Expression::WhileExpression(
+                WhileExpr {
+                    condition: Box::new(Expression::LiteralExpression(
+                        Literal::BooleanLiteral(true),
+                        span,
+                    )),
+                    body: Box::new(Block {
+                        statements,
+                        return_value: body.return_value,
+                        span: body.span,
+                    }),
+                },
+                span,
+            );
+            *expression = Expression::BlockExpression(
+                Box::new(Block {
+                    statements: vec![Statement::DeclarationStatement(
+                        Declaration::ConstDeclaration(iterator, value, false, vec![], None),
+                        span,
+                    )],
+                    return_value: Some(looped),
+                    span,
+                }),
+                span,
+            );
+        },
+    )
+    .program(&mut program);
+    program
+}
+
+/// A literal type like `"GET"`, with strings escaped like in the source
+pub fn literal_as_type(literal: &Literal) -> String {
+    match literal {
+        Literal::StringLiteral(value) => quote_string(value),
+        Literal::NumberLiteral(Number::I { value, .. }) => value.to_string(),
+        Literal::NumberLiteral(Number::F(value)) => value.clone(),
+        Literal::BooleanLiteral(value) => value.to_string(),
+    }
+}
+
+/// The value of a literal type, or `None` if the type is no literal
+pub fn type_as_literal(type_: &str) -> Option<Literal> {
+    if let Some(quoted) = type_
+        .strip_prefix('"')
+        .and_then(|type_| type_.strip_suffix('"'))
+    {
+        let mut value = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => value.extend(escaped_char(&mut chars)),
+                c => value.push(c),
+            }
+        }
+        return Some(Literal::StringLiteral(value));
+    }
+    match type_ {
+        "true" => Some(Literal::BooleanLiteral(true)),
+        "false" => Some(Literal::BooleanLiteral(false)),
+        _ if !type_.starts_with(|c: char| c.is_ascii_digit()) => None,
+        _ => match type_.parse() {
+            Ok(value) => Some(Literal::NumberLiteral(Number::I {
+                base: NumberBase::Dec,
+                value,
+            })),
+            Err(_) => Some(Literal::NumberLiteral(Number::F(type_.to_string()))),
+        },
+    }
+}
+
+/// Whether `is` can check a type at runtime: primitives, `null`, literals, arrays, `type`s and
+/// structs
+fn runtime_type(type_: &str, types: &Types) -> bool {
+    is_primitive(type_)
+        || is_empty(type_)
+        || type_as_literal(type_).is_some()
+        || type_.starts_with('[')
+        || types.shape(type_).is_some()
+}
+
+/// `null` and `undefined`, which no literal or `type` is
+fn is_empty(type_: &str) -> bool {
+    matches!(type_, "null" | "undefined")
+}
+
+/// Types whose values are known without looking at the program
+fn is_known(type_: &str) -> bool {
+    is_primitive(type_) || is_empty(type_) || type_as_literal(type_).is_some()
+}
+
+fn literal_type(literal: &Literal) -> &'static str {
+    match literal {
+        Literal::NumberLiteral(_) => "number",
+        Literal::StringLiteral(_) => "string",
+        Literal::BooleanLiteral(_) => "boolean",
+    }
+}
+
+fn is_primitive(type_: &str) -> bool {
+    matches!(primitive_name(type_), "number" | "string" | "boolean")
+}
+
+/// `bool` is another name for `boolean`
+fn primitive_name(type_: &str) -> &str {
+    match type_ {
+        "bool" => "boolean",
+        type_ => type_,
+    }
+}
+
+/// "1 argument", "2 arguments"
+fn arguments_count(count: usize) -> String {
+    match count {
+        1 => "1 argument".to_string(),
+        count => format!("{} arguments", count),
+    }
+}
+
+/// "1 item", "2 items"
+fn items_count(count: usize) -> String {
+    match count {
+        1 => "1 item".to_string(),
+        count => format!("{} items", count),
+    }
+}
+
+/// `a number` or `an Item`, `null`, `undefined` and literals stay as they are
+pub fn article(type_: &str) -> String {
+    if is_empty(type_) || type_as_literal(type_).is_some() {
+        return type_.to_string();
+    }
+    if tuple_items(type_).is_some() {
+        return format!("a tuple {}", type_);
+    }
+    match type_.chars().next() {
+        Some(first) if "aeiouAEIOU".contains(first) => format!("an {}", type_),
+        _ => format!("a {}", type_),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::{ast::Span, dispose::lower_with, Parser};
+
+    use super::*;
+
+    fn parse(input: &str) -> Result<Program, String> {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        Parser::parse(Tokens::new(&tokens))
+            .map(|(_, program)| program)
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    #[test]
+    fn type_and_extern_declarations() {
+        let program = parse(
+            "type Config = { host: string, port: number, };
+            extern fn load_config(path: string) -> Config;
+            extern fn log(message: string);",
+        )
+        .unwrap();
+        let field = |name: &str, type_: &str| Field {
+            name: Identifier(name.to_string()),
+            type_: Identifier(type_.to_string()),
+            span: Span::default(),
+        };
+        assert_eq!(
+            program,
+            vec![
+                Statement::DeclarationStatement(
+                    Declaration::TypeDeclaration(TypeDecl {
+                        name: Identifier("Config".to_string()),
+                        fields: vec![field("host", "string"), field("port", "number")],
+                        alias: None,
+                    }),
+                    Span::default()
+                ),
+                Statement::DeclarationStatement(
+                    Declaration::ExternDeclaration(ExternDecl {
+                        name: Identifier("load_config".to_string()),
+                        parameters: vec![Parameter {
+                            name: Identifier("path".to_string()),
+                            type_: Identifier("string".to_string()),
+                            span: Span::default(),
+                        }],
+                        return_type: Some(Identifier("Config".to_string())),
+                    }),
+                    Span::default()
+                ),
+                Statement::DeclarationStatement(
+                    Declaration::ExternDeclaration(ExternDecl {
+                        name: Identifier("log".to_string()),
+                        parameters: vec![Parameter {
+                            name: Identifier("message".to_string()),
+                            type_: Identifier("string".to_string()),
+                            span: Span::default(),
+                        }],
+                        return_type: None,
+                    }),
+                    Span::default()
+                ),
+            ]
+        );
+        let parses_fully = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            Parser::parse_with_token_indices(Tokens::new(&tokens)).is_ok()
+        };
+        assert!(!parses_fully("fn f() { type Inner = { x: number }; }"));
+    }
+
+    #[test]
+    fn structural_checks() {
+        let check = |input: &str| {
+            let program = parse(&format!(
+                "type Config = {{ host: string, port: number }};
+                type Server = {{ name: string, config: Config }};
+                struct Point {{ x: number }}
+                extern fn load_config(path: string) -> Config;
+                extern fn start(server: Server);
+                {}",
+                input
+            ))
+            .unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check(r#"start(Server { name: "a", config: load_config("b") });"#).is_ok());
+        assert!(check(r#"let c = load_config("a"); c.port"#).is_ok());
+        assert!(check(r#"let c = Config { host: "h", port: 1 } as Config; start(Server { name: "a", config: c });"#).is_ok());
+        assert!(check(r#"let c = load_config("a"); let c = 5; c.hots"#).is_ok());
+        assert_eq!(
+            check(r#"load_config("a", "b");"#),
+            Err("`load_config` takes 1 argument, not 2".to_string())
+        );
+        assert_eq!(
+            check("load_config(42);"),
+            Err("argument `path` of `load_config` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check(r#"let c = load_config("a"); c.hots"#),
+            Err("Config has no field `hots`".to_string())
+        );
+        assert_eq!(
+            check(r#"start(Server { name: "x" });"#),
+            Err("Server is missing the field `config`".to_string())
+        );
+        assert_eq!(
+            check(r#"start(Server { name: "x", config: Config { host: "h", port: "80" } });"#),
+            Err("field `port` of Config has to be a number, not a string".to_string())
+        );
+        assert_eq!(
+            check("start(Point { x: 1 });"),
+            Err(
+                "argument `server` of `start` has to be a Server, but Point has no field `name`"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn union_types() {
+        assert_eq!(
+            union_members("string | [number | null] | Config"),
+            vec!["string", "[number | null]", "Config"]
+        );
+        let check = |input: &str| {
+            let program = parse(&format!(
+                "type Config = {{ host: string, port: number }};
+                extern fn log(message: string | number | null);
+                {}",
+                input
+            ))
+            .unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check(r#"log("a"); log(1); log(null);"#).is_ok());
+        assert!(check("fn f(c: any) { if c is Config { c.port } else { 0 } }").is_ok());
+        assert!(check("fn f(c: any) { c is string | [number] }").is_ok());
+        assert_eq!(
+            check("log(true);"),
+            Err(
+                "argument `message` of `log` has to be a string, a number or null, not a boolean"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("fn f(c: any) { if c is Config { c.hots } else { 0 } }"),
+            Err("Config has no field `hots`".to_string())
+        );
+        assert_eq!(
+            check("fn f(c: any) { c is string | Unknown }"),
+            Err("`Unknown` can't be checked at runtime".to_string())
+        );
+    }
+
+    #[test]
+    fn literal_types() {
+        let check = |input: &str| {
+            let program = parse(&format!(
+                r#"type Method = "GET" | "POST";
+                fn send(method: Method, status: 200 | 404) {{ method }}
+                {}"#,
+                input
+            ))
+            .unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check(r#"send("GET", 404);"#).is_ok());
+        assert!(check(r#"fn f(m: Method) { match m { "GET" => 1, "POST" => 2 } }"#).is_ok());
+        assert!(check(r#"fn f(m: Method) { match m { "GET" => 1, _ => 2 } }"#).is_ok());
+        assert!(check(r#"fn f(send: any) { send("PUT", 500) }"#).is_ok());
+        assert_eq!(
+            check(r#"send("PUT", 200);"#),
+            Err(r#"argument `method` of `send` has to be "GET" or "POST", not "PUT""#.to_string())
+        );
+        assert_eq!(
+            check(r#"send("GET", 500);"#),
+            Err("argument `status` of `send` has to be 200 or 404, not 500".to_string())
+        );
+        assert_eq!(
+            check(r#"fn f(m: Method) { match m { "GET" => 1 } }"#),
+            Err(r#"a match on Method has no arm for "POST""#.to_string())
+        );
+        assert_eq!(
+            check(r#"fn f(m: Method) { match m { "GET" => 1, "PUT" => 2, _ => 3 } }"#),
+            Err(r#"a match on Method has an arm for "PUT", which isn't a Method"#.to_string())
+        );
+        assert_eq!(
+            check("fn f(n: number) { match n { 1 => 2 } }"),
+            Err("a match needs an arm that matches everything, like `_`".to_string())
+        );
+    }
+
+    #[test]
+    fn generic_types() {
+        assert_eq!(
+            type_arguments("Pair<Option<number>, [string | null]>"),
+            ("Pair", vec!["Option<number>", "[string | null]"])
+        );
+        assert_eq!(type_arguments("[Pair<A>]"), ("[Pair<A>]", vec![]));
+        assert_eq!(
+            substitute(
+                "[Tree<T>] | \"T\" | Total",
+                &[Identifier("T".to_string())],
+                &["number"]
+            ),
+            "[Tree<number>] | \"T\" | Total"
+        );
+        assert_eq!(
+            union_members("Pair<A | B, C> | null"),
+            vec!["Pair<A | B, C>", "null"]
+        );
+
+        let check = |input: &str| {
+            let program = parse(&format!(
+                "type Config = {{ host: string, port: number }};
+                struct Pair<A, B> {{ first: A, second: B }}
+                enum Option<T> {{ Some(T), None }}
+                extern fn log(message: string);
+                fn show(pair: Pair<number, Option<Config>>) {{ pair.second }}
+                {}",
+                input
+            ))
+            .unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check(r#"let p = Pair { first: 1, second: "a" }; log(p.second);"#).is_ok());
+        assert!(check("show(Pair { first: 1, second: Option::None });").is_ok());
+        assert!(
+            check("fn f(o: Option<Config>) { match o { Option::Some(c) => c.port, _ => 0 } }")
+                .is_ok()
+        );
+        assert_eq!(
+            check(r#"let p = Pair { first: 1, second: "a" }; let n = p.first; log(n);"#),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check(r#"show(Pair { first: 1, second: Option::Some("a") });"#),
+            Err(
+                "argument `pair` of `show` has to be a Pair<number, Option<Config>>, not a Pair<number, Option<string>>"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("fn f(o: Option<Config>) { match o { Option::Some(c) => c.hots, _ => 0 } }"),
+            Err("Config has no field `hots`".to_string())
+        );
+        assert_eq!(
+            check(
+                r#"struct Same<T> { a: T, b: T }
+                let same = Same { a: 1, b: "b" };"#
+            ),
+            Err("field `b` of Same<number> has to be a number, not a string".to_string())
+        );
+    }
+
+    #[test]
+    fn generic_functions() {
+        let check = |input: &str| {
+            let program = parse(&format!(
+                "struct Box<T> {{ value: T }}
+                struct Pair<A, B> {{ first: A, second: B }}
+                extern fn log(message: string);
+                fn id<T>(x: T): T {{ x }}
+                fn boxed<T>(value: T) -> Box<T> {{ Box {{ value: value }} }}
+                fn unbox<T>(b: Box<T>): T {{ b.value }}
+                fn first<A, B>(pairs: [Pair<A, B>]) -> A {{ pairs[0].first }}
+                {}",
+                input
+            ))
+            .unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check(r#"log(id("a")); log(unbox(boxed("a")));"#).is_ok());
+        assert_eq!(
+            check("log(id(1));"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        // type arguments are also inferred from the type arguments of the arguments
+        assert_eq!(
+            check("log(unbox(boxed(1)));"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check(r#"log(first([Pair { first: 1, second: "a" }]));"#),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            match_parameter("[Pair<T, string>]", "[Pair<number, string>]", "T"),
+            Some("number".to_string())
+        );
+        assert_eq!(match_parameter("Box<T>", "Pair<number, string>", "T"), None);
+    }
+
+    #[test]
+    fn trait_bounds() {
+        let check = |input: &str| {
+            let program = parse(&format!(
+                "trait Ord {{ fn cmp(self, other: Self) -> number; }}
+                struct Version {{ major: number }}
+                struct Name {{ text: string }}
+                impl Ord for Version {{
+                    fn cmp(self, other: Self) -> number {{ self.major - other.major }}
+                }}
+                extern fn log(message: string);
+                fn max<T: Ord>(a: T, b: T) -> T {{ if a.cmp(b) > 0 {{ a }} else {{ b }} }}
+                {}",
+                input
+            ))
+            .unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check("let v = max(Version { major: 1 }, Version { major: 2 });").is_ok());
+        assert!(check("fn first<T: Ord>(a: T) { let a = 1; a.toFixed() }").is_ok());
+        assert_eq!(
+            check("max(1, 2);"),
+            Err(
+                "argument `a` of `max` has to implement Ord, not be a number, because of the bound `T: Ord`"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(r#"max(Name { text: "a" }, Name { text: "b" });"#),
+            Err(
+                "argument `a` of `max` has to implement Ord, not be a Name, because of the bound `T: Ord`"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(r#"max(Version { major: 1 }, Name { text: "b" });"#),
+            Err(
+                "argument `b` of `max` has to be a Version, but Name has no field `major`"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("let v = max(Version { major: 1 }, Version { major: 2 }); log(v);"),
+            Err("argument `message` of `log` has to be a string, not a Version".to_string())
+        );
+        assert_eq!(
+            check("fn newest<T: Ord>(a: T) { a.major }"),
+            Err("T only has the methods of Ord, not `major`".to_string())
+        );
+        assert_eq!(
+            check("fn show<T>(a: T) { a.cmp(a) }"),
+            Err("T has no bounds, so `a` has no method `cmp`".to_string())
+        );
+        assert_eq!(
+            check("impl Ord for Name { fn compare(self, other: Self) -> number { 0 } }"),
+            Err("impl Ord for Name is missing the method `cmp`".to_string())
+        );
+        let describe = "trait Describe {
+            fn name(self) -> string;
+            fn describe(self) -> string { self.name() }
+        }";
+        assert!(check(&format!(
+            "{} impl Describe for Name {{ fn name(self) -> string {{ self.text }} }}",
+            describe
+        ))
+        .is_ok());
+        assert_eq!(
+            check(&format!(
+                r#"{} impl Describe for Name {{ fn describe(self) -> string {{ "" }} }}"#,
+                describe
+            )),
+            Err("impl Describe for Name is missing the method `name`".to_string())
+        );
+        assert_eq!(
+            check("impl Ord for Name { fn cmp(self) -> number { 0 } }"),
+            Err(
+                "`cmp` of impl Ord for Name has to take the parameters of `cmp` in Ord".to_string()
+            )
+        );
+        assert_eq!(
+            check("impl Ord for number { fn cmp(self, other: Self) -> number { 0 } }"),
+            Err("only structs and enums can implement Ord, not a number".to_string())
+        );
+    }
+
+    #[test]
+    fn where_clauses() {
+        let source = "trait Ord { fn cmp(self, other: Self) -> number; }
+            trait Display { fn show(self) -> string; }
+            struct Version { major: number }
+            impl Ord for Version { fn cmp(self, other: Self) -> number { self.major - other.major } }
+            fn max<T>(a: T, b: T) -> T where T: Ord + Display { if a.cmp(b) > 0 { a } else { b } }
+            ";
+        let check = |input: &str| {
+            let input = format!("{}{}", source, input);
+            let (_, (tokens, spans)) = Lexer::lex_tokens_with_spans(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::with_spans(&tokens, &spans, 0)).unwrap();
+            check_types(&program).map_err(|err| (err.message, err.span.start..err.span.end))
+        };
+        assert!(check("fn show<T>(a: T) where T: Display, { a.show() }").is_ok());
+        let call = "max(Version { major: 1 }, Version { major: 2 })";
+        assert_eq!(
+            check(&format!("{};", call)),
+            Err((
+                "argument `a` of `max` has to implement Display, not be a Version, because of `where T: Display`".to_string(),
+                source.len()..source.len() + call.len()
+            ))
+        );
+        assert_eq!(
+            check("fn f<T>(a: T) where U: Ord { a }").map_err(|(message, _)| message),
+            Err("`U` in the where clause of `f` isn't a type parameter of it".to_string())
+        );
+        assert_eq!(
+            check("fn f<T>(a: T) where T: Ord { a.major }").map_err(|(message, _)| message),
+            Err("T only has the methods of Ord, not `major`".to_string())
+        );
+    }
+
+    #[test]
+    fn trait_objects() {
+        let source = "trait Shape { fn area(self) -> number; }
+            struct Circle { radius: number }
+            struct Tri { base: number }
+            impl Shape for Circle { fn area(self) -> number { self.radius } }
+            fn total(shapes: [dyn Shape]) -> number { let sum = 0; for shape in shapes { sum += shape.area(); } sum }
+            ";
+        let check = |input: &str| {
+            let input = format!("{}{}", source, input);
+            let (_, (tokens, spans)) = Lexer::lex_tokens_with_spans(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::with_spans(&tokens, &spans, 0)).unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check("let shapes: [dyn Shape] = [Circle { radius: 1 }]; total(shapes);").is_ok());
+        assert_eq!(
+            check("let shape: dyn Shape = Tri { base: 1 };"),
+            Err("Tri doesn't implement Shape, so it can't be a `dyn Shape`".to_string())
+        );
+        assert_eq!(
+            check("let shape: dyn Shape = Circle { radius: 1 }; shape.radius;"),
+            Err("dyn Shape only has the methods of Shape, not `radius`".to_string())
+        );
+        assert_eq!(
+            check("let shape: dyn Circle = Circle { radius: 1 };"),
+            Err("`dyn Circle` needs a trait, not a Circle".to_string())
+        );
+        assert_eq!(
+            check("fn f(s: dyn Shape) -> number { s.area() } f(Tri { base: 1 });"),
+            Err(
+                "argument `s` of `f` has to be a dyn Shape, but Tri doesn't implement Shape"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("trait Ord { fn cmp(self, other: Self) -> number; } fn f(o: dyn Ord) { o }"),
+            Err("Ord can't be used as `dyn Ord`, because `cmp` uses `Self`, which isn't known for a `dyn` value".to_string())
+        );
+        assert_eq!(
+            check("trait Make { fn make() -> number; } fn f(o: dyn Make) { o }"),
+            Err("Make can't be used as `dyn Make`, because `make` doesn't take `self`".to_string())
+        );
+    }
+
+    #[test]
+    fn impl_trait_returns() {
+        let source = "trait Shape { fn area(self) -> number; }
+            struct Square { side: number }
+            struct Tri { base: number }
+            impl Shape for Square { fn area(self) -> number { self.side * self.side } }
+            ";
+        let check = |input: &str| {
+            let input = format!("{}{}", source, input);
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check("fn unit() -> impl Shape { Square { side: 1 } } unit().area();").is_ok());
+        assert_eq!(
+            check("fn unit() -> impl Shape { Tri { base: 1 } }"),
+            Err("`unit` has to return an impl Shape, but Tri doesn't implement Shape".to_string())
+        );
+        assert_eq!(
+            check("fn unit() -> impl Shape { Square { side: 1 } } unit().side;"),
+            Err("impl Shape only has the methods of Shape, not `side`".to_string())
+        );
+        assert_eq!(
+            check("fn unit() -> impl Square { Square { side: 1 } }"),
+            Err("`impl Square` needs a trait, not a Square".to_string())
+        );
+    }
+
+    #[test]
+    fn recursive_types() {
+        let check = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        let source = "enum Tree { Leaf(number), Node(Tree, Tree) }
+            struct List { value: number, next: List | null }
+            struct Dir { name: string, children: [Dir] }
+            type Json = number | string | [Json];
+            fn sum(tree: Tree) -> number {
+                match tree { Tree::Leaf(value) => value, Tree::Node(left, right) => sum(left) + sum(right) }
+            }
+            let tree = Tree::Node(Tree::Leaf(1), Tree::Node(Tree::Leaf(2), Tree::Leaf(3)));
+            let list = List { value: 1, next: List { value: 2, next: null } };
+            let root = Dir { name: \"/\", children: [Dir { name: \"home\", children: [] }] };
+            let data: Json = [1, \"a\", [2]];
+            sum(tree) + list.next.value;";
+        assert!(check(source).is_ok());
+        assert!(check("enum Even { Zero, Next(Odd) } enum Odd { Next(Even) }").is_ok());
+        assert_eq!(
+            check("struct Node { value: number, next: Node }"),
+            Err(
+                "`Node` can't have any values, because its field `next` always needs a Node, make it `Node | null` or an array"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("struct A { b: B } struct B { a: A | A }"),
+            Err(
+                "`A` can't have any values, because its field `b` always needs a B, make it `B | null` or an array"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("enum Stream { Next(number, Stream) }"),
+            Err(
+                "`Stream` can't have any values, because every variant needs a Stream, add a variant that doesn't"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("type A = B; type B = A;"),
+            Err("`A` only names aliases of itself, so it has no values".to_string())
+        );
+    }
+
+    #[test]
+    fn method_calls_through_values() {
+        let declarations = "struct Counter { count: number, step: any }
+            impl Counter {
+                fn new() -> Counter { Counter { count: 0, step: |x: number| x + 1 } }
+                fn get(self) -> number { self.count }
+                fn bumped(self, by: number) -> Self { Counter { count: self.get() + by, step: self.step } }
+            }";
+        // the struct and impl block are the first two statements
+        let lowered = |input: &str| {
+            lower_method_calls(parse(&format!("{} {}", declarations, input)).unwrap()).split_off(2)
+        };
+        let explicit = |input: &str| parse(input).unwrap();
+        assert_eq!(
+            lowered("let c = Counter::new(); let d = c.bumped(2).get();"),
+            explicit("let c = Counter::new(); let d = Counter::get(Counter::bumped(c, 2));")
+        );
+        assert_eq!(
+            lowered("let get = |counter: Counter| counter.get(); let step = Counter::new().step(1);"),
+            explicit("let get = |counter: Counter| Counter::get(counter); let step = Counter::new().step(1);")
+        );
+        // `self` inside the impl block is a Counter too
+        let program = lower_method_calls(parse(declarations).unwrap());
+        let Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), _) = &program[1]
+        else {
+            panic!("the impl block is the second statement");
+        };
+        let ImplItem::Method(bumped) = &decl.items[2] else {
+            panic!("bumped is a method");
+        };
+        let mut callees = vec![];
+        walk_block(&bumped.body, &mut |expression| {
+            if let Expression::CallExpression(expr, _) = expression {
+                callees.push(expr.lhs.as_ref().clone());
+            }
+        });
+        let Expression::PathExpression(path, _) = &callees[0] else {
+            panic!("self.get() calls Counter::get");
+        };
+        assert_eq!(path.segments[1], Identifier("get".to_string()));
+        // values of unknown types keep calling the method on the value
+        assert_eq!(
+            lowered("fn f(value: any) { value.get() }"),
+            explicit("fn f(value: any) { value.get() }")
+        );
+        // a name has the type of the binding in scope, not of other bindings of the same name
+        assert_eq!(
+            lowered("fn a(c: Counter) -> number { c.get() } fn b() -> number { let c = 5; c }"),
+            explicit(
+                "fn a(c: Counter) -> number { Counter::get(c) } fn b() -> number { let c = 5; c }"
+            )
+        );
+        assert_eq!(
+            lowered("fn f(cs: [Counter]) { let c = 1; cs.map(|c| c.get()) }"),
+            explicit("fn f(cs: [Counter]) { let c = 1; cs.map(|c| Counter::get(c)) }")
+        );
+        // names assigned a value of the same struct keep its type
+        assert_eq!(
+            lowered("let c = Counter::new(); c = c.bumped(1); c.get();"),
+            explicit("let c = Counter::new(); c = Counter::bumped(c, 1); Counter::get(c);")
+        );
+    }
+
+    #[test]
+    fn unknown_receivers() {
+        let declarations = "struct Counter { count: number }
+            impl Counter { fn get(self) -> number { self.count } }
+            trait Named { fn name(self) -> string; }";
+        let check = |input: &str| check(&format!("{} {}", declarations, input));
+        assert_eq!(
+            check("fn f(cs: any) { cs.map(|c| c.get()) }"),
+            Err("the type of `c` isn't known, so `c.get()` can't call the method of Counter, annotate `c` with its type".to_string())
+        );
+        assert_eq!(
+            check("let c = []; c = 1; c.get();"),
+            Err("the type of `c` isn't known, so `c.get()` can't call the method of Counter, annotate `c` with its type".to_string())
+        );
+        // values declared with a type the checker doesn't know are taken at their word
+        assert!(check("fn f(c: any) { c.get() } fn g(c: Response) { c.get() }").is_ok());
+        assert!(check("fn f(cs: any) { cs.map(|c: Counter| c.get()) }").is_ok());
+        // the methods of traits and of arrays can be called on anything
+        assert!(check("fn f(cs: any) { cs.map(|c| c.name()); cs.map(|c| c.map(|x| x)) }").is_ok());
+        // copies have the type of the value they copy
+        assert!(
+            check("let c = Counter { count: 1 }; clone(c).get(); let d = c.clone(); d.get();")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn inferred_receivers() {
+        let declarations = "struct Point { x: number, y: number }
+            impl Point {
+                fn len(self) { self.x + self.y }
+                fn scaled(self, by: number) { Point { x: self.x * by, y: self.y * by } }
+            }
+            fn at(x: number) { Point { x: x, y: 0 } }";
+        // the struct, impl block and function are the first three statements
+        let lowered = |input: &str| {
+            lower_method_calls(parse(&format!("{} {}", declarations, input)).unwrap()).split_off(3)
+        };
+        let explicit = |input: &str| parse(input).unwrap();
+        // methods and functions without a return type return the type of their last value
+        assert_eq!(
+            lowered("let n = at(1).scaled(2).len();"),
+            explicit("let n = Point::len(Point::scaled(at(1), 2));")
+        );
+        // and the items of arrays of them are that type too
+        assert_eq!(
+            lowered("for p in [at(1), at(2)] { p.len() }"),
+            explicit("for p in [at(1), at(2)] { Point::len(p) }")
+        );
+        // a `return` could return something else, so those aren't inferred
+        assert_eq!(
+            lowered("fn maybe(x: number) { if x > 0 { return 0; } at(x) } let n = maybe(1).len();"),
+            explicit(
+                "fn maybe(x: number) { if x > 0 { return 0; } at(x) } let n = maybe(1).len();"
+            )
+        );
+    }
+
+    #[test]
+    fn index_operator() {
+        let declarations = "struct Row { cells: [number] }
+            impl Index for Row {
+                fn get(self, key: number) -> number { self.cells[key] }
+            }
+            struct Grid { rows: [Row] }
+            impl Index for Grid {
+                fn get(self, key: number) -> Row { self.rows[key] }
+            }";
+        let check = |input: &str| {
+            check_types(&parse(&format!("{} {}", declarations, input)).unwrap())
+                .map_err(|err| err.message)
+        };
+        // the first four statements are the declarations
+        let lowered = |input: &str| {
+            lower_index_operators(parse(&format!("{} {}", declarations, input)).unwrap())
+                .split_off(4)
+        };
+        let explicit = |input: &str| parse(input).unwrap();
+        assert_eq!(
+            lowered("fn at(grid: Grid) -> number { grid[1][2] }"),
+            explicit("fn at(grid: Grid) -> number { Row::get(Grid::get(grid, 1), 2) }")
+        );
+        // arrays and values of unknown types are indexed like before
+        assert_eq!(
+            lowered("fn first(cells: [number], row: any) { cells[0] + row[0] }"),
+            explicit("fn first(cells: [number], row: any) { cells[0] + row[0] }")
+        );
+        assert!(check("fn at(grid: Grid) -> number { grid[1][2] }").is_ok());
+        assert_eq!(
+            check("fn at(grid: Grid) -> string { grid[1][2] }"),
+            Err("`at` has to return a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn at(row: Row) { row[\"a\"] }"),
+            Err("argument `key` of `Row::get` has to be a number, not a string".to_string())
+        );
+        assert_eq!(
+            check("fn set(row: Row) { row[0] = 1; }"),
+            Err(
+                "Row implements Index, which can only read `container[key]`, not assign it"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("struct Column { cells: [number] } impl Index for Column { fn at(self, key: number) { 0 } }"),
+            Err("impl Index for Column needs a method `get(self, key)`, which `container[key]` calls".to_string())
+        );
+    }
+
+    #[test]
+    fn iterators() {
+        let declarations = "struct Countdown { left: number }
+            impl Iterator for Countdown {
+                fn next(self) -> number | null {
+                    if self.left == 0 { return; }
+                    self.left = self.left - 1;
+                    self.left + 1
+                }
+            }
+            struct Timer { seconds: number }
+            impl IntoIterator for Timer {
+                fn into_iter(self) -> Countdown { Countdown { left: self.seconds } }
+            }
+            extern fn log(message: string);";
+        let check = |input: &str| {
+            check_types(&parse(&format!("{} {}", declarations, input)).unwrap())
+                .map_err(|err| err.message)
+        };
+        // the first five statements are the declarations
+        let lowered = |input: &str| {
+            lower_iterators(parse(&format!("{} {}", declarations, input)).unwrap()).split_off(5)
+        };
+        let explicit = |input: &str| parse(input).unwrap();
+        assert_eq!(
+            lowered("fn f(timer: Timer) { for n in timer { n } }"),
+            explicit(
+                "fn f(timer: Timer) {
+                    {
+                        const __iterator_0 = timer.into_iter();
+                        while true {
+                            let n = __iterator_0.next();
+                            if n is null | undefined { break; }
+                            n
+                        }
+                    }
+                }"
+            )
+        );
+        // arrays and values of unknown types are looped over like before
+        assert_eq!(
+            lowered(
+                "fn f(items: [number], other: any) { for n in items { n } for n in other { n } }"
+            ),
+            explicit(
+                "fn f(items: [number], other: any) { for n in items { n } for n in other { n } }"
+            )
+        );
+        // the items are what `next` returns besides `null`
+        assert_eq!(
+            check("for n in Countdown { left: 2 } { log(n); }"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("for n in Timer { seconds: 2 } { log(n); }"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("struct Steps { left: number } impl Steps { fn next(self) -> number | null { null } } for n in Steps { left: 2 } { n }"),
+            Err("Steps has a `next` method, but `for` only calls it with `impl Iterator for Steps`".to_string())
+        );
+        assert_eq!(
+            check("struct Point { x: number } for n in Point { x: 2 } { n }"),
+            Err("`for` can't loop over Point, it doesn't implement Iterator, IntoIterator or AsyncIterator".to_string())
+        );
+        assert_eq!(
+            check("struct Empty { } impl IntoIterator for Empty { fn iter(self) -> Countdown { Countdown { left: 0 } } }"),
+            Err("impl IntoIterator for Empty needs a method `into_iter(self)`, which `for item in collection` calls".to_string())
+        );
+    }
+
+    #[test]
+    fn disposables() {
+        let declarations = "struct File { name: string }
+            impl File {
+                fn open(name: string) -> File { File { name: name } }
+            }
+            impl Disposable for File {
+                fn close(self) {}
+            }";
+        let check = |input: &str| {
+            check_types(&parse(&format!("{} {}", declarations, input)).unwrap())
+                .map_err(|err| err.message)
+        };
+        assert!(check("fn f() { with file = File::open(\"a\") { file.name } }").is_ok());
+        // values of unknown types might have a `close` method
+        assert!(check("fn f(handle: Handle) { with h = handle { h.size() } }").is_ok());
+        assert_eq!(
+            check(
+                "struct Socket { port: number } fn f() { with s = Socket { port: 80 } { s.port } }"
+            ),
+            Err("Socket doesn't implement Disposable, which `with` needs to close it".to_string())
+        );
+        assert_eq!(
+            check("struct Socket { port: number } impl Disposable for Socket { fn dispose(self) {} }"),
+            Err("impl Disposable for Socket needs a method `close(self)`, which `with name = value { ... }` calls".to_string())
+        );
+        assert_eq!(
+            lower_with(parse("fn f() { with file = open() { file.read() } }").unwrap()),
+            parse("fn f() { { let file = open(); defer { file.close(); } file.read() } }").unwrap()
+        );
+    }
+
+    #[test]
+    fn async_functions() {
+        let declarations = "async fn count() -> number { 1 }
+            fn log(message: string) {}
+            struct Ticks { left: number }
+            impl AsyncIterator for Ticks {
+                fn next(self) -> Promise<number | null> { count() }
+            }";
+        let check = |input: &str| {
+            check_types(&parse(&format!("{} {}", declarations, input)).unwrap())
+                .map_err(|err| err.message)
+        };
+        // calls of `async` functions give a promise, awaiting it gives the value
+        assert_eq!(
+            check("async fn f() { log(count().await); }"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("async fn f() { for n in Ticks { left: 2 } { log(n); } }"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        // the first four statements are the declarations
+        assert_eq!(
+            lower_iterators(
+                parse(&format!(
+                    "{} async fn f(ticks: Ticks) {{ for n in ticks {{ n }} }}",
+                    declarations
+                ))
+                .unwrap()
+            )
+            .split_off(4),
+            parse(
+                "async fn f(ticks: Ticks) {
+                    {
+                        const __iterator_0 = ticks;
+                        while true {
+                            let n = __iterator_0.next().await;
+                            if n is null | undefined { break; }
+                            n
+                        }
+                    }
+                }"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn tasks() {
+        let declarations = "async fn count() -> number { 1 }
+            fn name() -> string { \"a\" }
+            fn log(message: string) {}";
+        let check = |input: &str| {
+            check_types(&parse(&format!("{} {}", declarations, input)).unwrap())
+                .map_err(|err| err.message)
+        };
+        // a spawned task gives a promise of what its function returns, `async` or not
+        assert_eq!(
+            check("async fn f() { log(task::spawn(count).await); }"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert!(check("async fn f() { log(task::spawn(name).await); }").is_ok());
+        assert_eq!(
+            check(
+                "async fn f() {
+                    for n in task::join_all([task::spawn(count), count()]).await { log(n); }
+                }"
+            ),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("async fn f() { log(task::race([count(), count()]).await); }"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("async fn f() { task::join_all(count()).await; }"),
+            Err("`task::join_all` takes an array of tasks, not a Promise<number>".to_string())
+        );
+    }
+
+    #[test]
+    fn fluent_methods() {
+        let check = |input: &str| {
+            let source = format!(
+                "struct Request {{ url: string, headers: [string], wait: number }}
+                impl Request {{
+                    fn new(url: string) -> Self {{ Self {{ url: url, headers: [], wait: 0 }} }}
+                    fn header(self, header: string) -> Self {{
+                        Self {{ url: self.url, headers: self.headers.concat([header]), wait: self.wait }}
+                    }}
+                    fn timeout(self, wait: number) -> Self {{
+                        self.wait = wait;
+                        self
+                    }}
+                    fn send(self) -> string {{ self.url }}
+                }}
+                fn log(message: string) {{ println(message); }}
+                {}",
+                input
+            );
+            let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check("log(Request::new(\"/\").header(\"a\").timeout(5).send());").is_ok());
+        assert_eq!(
+            check("let request = Request::new(\"/\").header(\"a\").timeout(\"5\");"),
+            Err(
+                "argument `wait` of `Request::timeout` has to be a number, not a string"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("Request::header(Request::new(\"/\"), \"a\", \"b\");"),
+            Err("`Request::header` takes 2 arguments, not 3".to_string())
+        );
+        assert_eq!(
+            check("log(Request::new(\"/\").timeout(1));"),
+            Err("argument `message` of `log` has to be a string, not a Request".to_string())
+        );
+        // `Self` is the type of the impl block, and methods return what their signature says
+        assert!(check("impl Request { fn copy(self) -> Request { Self::new(self.url) } }").is_ok());
+        assert_eq!(
+            check("impl Request { fn retry(self) -> Self { 1 } }"),
+            Err("`retry` has to return a Request, not a number".to_string())
+        );
+        assert_eq!(
+            check("impl Request { fn length(self) -> number { Self::new(self.url) } }"),
+            Err("`length` has to return a number, not a Request".to_string())
+        );
+        // a method sharing its name with a field is called through the type
+        assert!(check(
+            "impl Request { fn wait(self, by: number) -> Self { self.timeout(self.wait + by) } }
+            let r = Request::new(\"/\"); let w: number = r.wait; let s = Request::wait(r, 1).send();"
+        )
+        .is_ok());
+        assert_eq!(
+            check("impl Request { fn wait(self, by: number) -> Self { self } } Request::wait(Request::new(\"/\"));"),
+            Err("`Request::wait` takes 2 arguments, not 1".to_string())
+        );
+        assert_eq!(
+            check("impl Request { get url() -> string { self.url } }"),
+            Err("`url` is a field of Request, so it can't be a getter too".to_string())
+        );
+    }
+
+    #[test]
+    fn callback_parameters() {
+        let check = |input: &str| {
+            let source = format!(
+                "struct Click {{ x: number, y: number }}
+                struct Emitter<T> {{ handlers: [any] }}
+                impl Emitter {{
+                    fn on(self, handler: impl Fn(T)) {{ self.handlers.push(handler); }}
+                }}
+                fn on_click(handler: impl Fn(Click, string)) {{}}
+                fn log(message: string) {{ println(message); }}
+                {}",
+                input
+            );
+            let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check("on_click(|click, button| log(button));").is_ok());
+        // the parameters of a closure passed as a callback have the types of the callback's
+        // parameters
+        assert_eq!(
+            check("on_click(|click, button| log(click));"),
+            Err("argument `message` of `log` has to be a string, not a Click".to_string())
+        );
+        // with the type arguments of the value a method is called on
+        assert_eq!(
+            check(
+                "let clicks = Emitter { handlers: [] } as Emitter<Click>;
+                clicks.on(|click| log(click.x));"
+            ),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert!(check("on_click(|click: Click, button: string| log(button));").is_ok());
+    }
+
+    #[test]
+    fn signals() {
+        let check = |input: &str| {
+            let source = format!(
+                "struct Signal<T> {{ value: T, subscribers: [any] }}
+                impl Signal {{
+                    fn get(self) -> T {{ self.value }}
+                    fn set(self, value: T) {{ self.value = value; }}
+                }}
+                fn signal<T>(value: T) -> Signal<T> {{ Signal {{ value: value, subscribers: [] }} }}
+                fn computed<T>(compute: impl Fn() -> T) -> Signal<T> {{ signal(compute()) }}
+                let count = signal(1);
+                {}",
+                input
+            );
+            let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check("count.set(2);").is_ok());
+        assert_eq!(
+            check("count.set(\"2\");"),
+            Err("argument `value` of `Signal::set` has to be a number, not a string".to_string())
+        );
+        // the type argument of a computed signal is what its function evaluates to
+        assert_eq!(
+            check("let big = computed(|| count.get() > 10); big.set(1);"),
+            Err("argument `value` of `Signal::set` has to be a boolean, not a number".to_string())
+        );
+        assert_eq!(
+            check("let label = computed(|| \"n = \" + count.get()); label.set(1);"),
+            Err("argument `value` of `Signal::set` has to be a string, not a number".to_string())
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        let check = |input: &str| {
+            let source = format!(
+                "struct Point {{ x: number, y: number }}
+                struct Pair<A, B> {{ first: A, second: B }}
+                fn log(message: string) {{ println(message); }}
+                {}",
+                input
+            );
+            let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            check_types(&program).map_err(|err| err.message)
+        };
+        assert!(check("fn f(Point { x, .. }: Point) -> number { x }").is_ok());
+        assert_eq!(
+            check("fn f(Point { x, z }: Point) -> number { x }"),
+            Err("Point has no field `z`".to_string())
+        );
+        assert_eq!(
+            check("let (a, Point { w }) = (1, Point { x: 1, y: 2 });"),
+            Err("Point has no field `w`".to_string())
+        );
+        // the fields have the types of the struct, with the type arguments of the value
+        assert_eq!(
+            check("let Point { x, y } = Point { x: 1, y: 2 }; log(y);"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check(
+                "let p = Pair { first: 1, second: \"a\" } as Pair<number, string>;
+                let Pair { first, second } = p; log(second); log(first);"
+            ),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn f(Point { x, y }: Point) { log(x); }"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn g(n: number, Point { x, .. }: Point) {} g(1, \"a\");"),
+            Err("argument 2 of `g` has to be a Point, not a string".to_string())
+        );
+    }
+
+    fn check(input: &str) -> Result<(), String> {
+        check_types(&parse(input).unwrap()).map_err(|err| err.message)
+    }
+
+    #[test]
+    fn every_error() {
+        let errors = |input: &str| {
+            let (_, (tokens, spans)) = Lexer::lex_tokens_with_spans(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::with_spans(&tokens, &spans, 0)).unwrap();
+            check_types_with_imports(&program, &HashMap::new())
+                .unwrap_err()
+                .into_iter()
+                .map(|err| (err.message, err.span.start))
+                .collect::<Vec<_>>()
+        };
+        let wrong_argument = "argument `x` of `f` has to be a number, not a string".to_string();
+        assert_eq!(
+            errors("fn f(x: number) {} f(\"x\"); fn g() { f(\"y\"); }"),
+            vec![(wrong_argument.clone(), 19), (wrong_argument, 36)]
+        );
+        // the call around a wrong argument isn't another error
+        assert_eq!(
+            errors("fn f(x: number) -> number { x } f(f(\"x\"));").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn cancellation() {
+        let program = parse("fn f(x: number) {} f(\"x\"); f(1); f(2);").unwrap();
+        let cancellation = CancellationToken::new();
+        assert_eq!(
+            check_types_cancellable(&program, &HashMap::new(), &cancellation),
+            Ok(check_types_with_imports(&program, &HashMap::new()))
+        );
+
+        // cancelled while the first call is checked, the other statements aren't walked
+        let mut types = Types::default();
+        let mut calls = 0;
+        let mut visitor = |expression: &mut Expression, _: &Types| {
+            if let Expression::CallExpression(..) = expression {
+                calls += 1;
+                cancellation.cancel();
+            }
+        };
+        let mut walk = ScopedWalk::new(&mut types, &mut visitor);
+        walk.cancellation = Some(&cancellation);
+        walk.program(&mut program.clone());
+        assert_eq!(calls, 1);
+
+        assert_eq!(
+            check_types_cancellable(&program, &HashMap::new(), &cancellation),
+            Err(Cancelled)
+        );
+    }
+
+    #[test]
+    fn imports() {
+        let charts = parse(
+            "pub fn draw(values: [number]) -> string { \"\" }
+            pub async fn load(url: string) -> number { 1 }
+            pub const MAX = 10;
+            fn hidden() { 1 }",
+        )
+        .unwrap();
+        let imports = HashMap::from([("./charts".to_string(), charts)]);
+        let check = |input: &str| {
+            check_types_with_imports(&parse(input).unwrap(), &imports)
+                .map_err(|errors| errors[0].message.clone())
+        };
+        assert!(check(
+            "async fn show() -> string {
+                let charts = import(\"./charts\").await;
+                let size: number = charts.load(\"/data\").await + charts.MAX;
+                charts.draw([size])
+            }"
+        )
+        .is_ok());
+        assert_eq!(
+            check("async fn show() { import(\"./charts\").await.hidden() }"),
+            Err("the module `./charts` has no `pub` function or constant `hidden`".to_string())
+        );
+        assert_eq!(
+            check("async fn show() { let charts = import(\"./charts\").await; charts.draw(1) }"),
+            Err("argument 1 of `charts.draw` has to be a [number], not a number".to_string())
+        );
+        assert_eq!(
+            check("async fn show() -> number { import(\"./charts\").await.draw([1]) }"),
+            Err("`show` has to return a number, not a string".to_string())
+        );
+        // modules that aren't given have no known type
+        assert!(check("async fn show() { import(\"./other\").await.anything() }").is_ok());
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(
+            check("let f: number = \"x\";"),
+            Err("`f` has to be a number, not a string".to_string())
+        );
+        assert_eq!(
+            check("let y: string = 1 + 2;"),
+            Err("`y` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn h(x: number) -> number { x } const Z: string = h(1);"),
+            Err("`Z` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("let (a, b): (number, string) = (1, 2);"),
+            Err("the destructured value has to be a tuple (number, string), not a tuple (number, number)".to_string())
+        );
+        assert!(
+            check("let a: number | null = null; let b: [string] = []; let c: any = 1;").is_ok()
+        );
+        // the name has the type it is annotated with, even where it is assigned
+        assert_eq!(
+            check("fn log(s: string) {} let n: number = 1; n = 2; log(n);"),
+            Err("argument `s` of `log` has to be a string, not a number".to_string())
+        );
+    }
+
+    #[test]
+    fn flags() {
+        let check = |input: &str| {
+            check(&format!(
+                "#[derive(Flags)] enum Permissions {{ Read, Write }}
+                #[derive(Flags)] enum Mode {{ Fast }}
+                {}",
+                input
+            ))
+        };
+        assert!(check(
+            "let p = Permissions::Read | Permissions::Write & Permissions::all();
+            let q: Permissions = p ^ Permissions::empty();
+            let read: boolean = Permissions::contains(q, Permissions::Read);
+            let n = (p as number) | 4;"
+        )
+        .is_ok());
+        assert_eq!(
+            check("Permissions::Read | Mode::Fast"),
+            Err("flags of Permissions and Mode can't be combined".to_string())
+        );
+        assert_eq!(
+            check("fn f(p: Permissions, m: Mode) { p & m }"),
+            Err("flags of Permissions and Mode can't be combined".to_string())
+        );
+        // flags are checked by their type, not only where they are built
+        assert_eq!(
+            check("let y = Permissions::Read; let z = y | 8;"),
+            Err(
+                "flags of Permissions can't be combined with other values, cast them with \
+                 `as number` first"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("Permissions::empty() ^ 4"),
+            Err(
+                "flags of Permissions can't be combined with other values, cast them with \
+                 `as number` first"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("fn f(p: Permissions) { p } f(5);"),
+            Err("argument `p` of `f` has to be a Permissions, not a number".to_string())
+        );
+        assert_eq!(
+            check("Permissions::contains(Permissions::all(), Mode::Fast)"),
+            Err(
+                "argument `other` of `Permissions::contains` has to be a Permissions, not a Mode"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn checked_operations() {
+        let check = |input: &str| {
+            check(&format!(
+                "enum Option<T> {{ Some(T), None }}
+                impl Option {{ fn unwrap_or(self, fallback: T) -> T {{ fallback }} }}
+                let x = 5;
+                {}",
+                input
+            ))
+        };
+        // the result is `null` if it is out of range, not an Option
+        assert_eq!(
+            check("checked_add(1, 2).unwrap_or(0);"),
+            Err("`unwrap_or` is a method of Option, not of a number | null, a value that can be `null` takes `??` or a comparison with `null` instead".to_string())
+        );
+        assert_eq!(
+            check("match checked_add(x, 2) { Option::Some(v) => v, Option::None => 0 };"),
+            Err("a `Option::Some` pattern doesn't fit a number | null".to_string())
+        );
+        assert_eq!(
+            check("let r: Option<number> = checked_mul(x, 2);"),
+            Err("`r` has to be an Option<number>, not a number | null".to_string())
+        );
+        assert_eq!(
+            check("fn f() -> number { checked_sub(x, 1) }"),
+            Err("`f` has to return a number, not a number | null".to_string())
+        );
+        assert!(check(
+            "let total: number = checked_add(x, 2) ?? 0;
+            let r = checked_div(x, 0);
+            if r != null { println(r); }
+            match Option::Some(x) { Option::Some(v) => v, Option::None => 0 };
+            fn checked_add(a: number, b: number) -> Option<number> { Option::Some(a + b) }
+            checked_add(1, 2).unwrap_or(0);"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn safe_navigation() {
+        let check = |input: &str| {
+            check(&format!(
+                "enum Option<T> {{ Some(T), None }}
+                struct P {{ n: number }}
+                impl P {{ fn get(self) -> number {{ self.n }} }}
+                {}",
+                input
+            ))
+        };
+        assert_eq!(
+            check("fn f(p: P) -> number { p?.n }"),
+            Err("`?.` is for values that can be `null`, like a `P | null`, not a P".to_string())
+        );
+        assert_eq!(
+            check("fn f(xs: [number]) { xs?[0] }"),
+            Err(
+                "`?[` is for values that can be `null`, like a `[number] | null`, not a [number]"
+                    .to_string()
+            )
+        );
+        // Options are enum values, which are never `null`
+        assert_eq!(
+            check("fn f(p: Option<P>) { p?.n }"),
+            Err(
+                "`?.` is for values that can be `null`, an Option<P> is never `null`, `match` it or call `unwrap_or`"
+                    .to_string()
+            )
+        );
+        // the chain gives what it gives for a value that isn't `null`, or `null`
+        assert_eq!(
+            check("fn f(p: P | null) -> number { p?.n }"),
+            Err("`f` has to return a number, not a number | null".to_string())
+        );
+        assert_eq!(
+            check("fn f(p: P | null) -> string | null { p?.get() }"),
+            Err("`f` has to return a string or null, not a number | null".to_string())
+        );
+        // and not an Option, whose methods and variants don't fit it
+        assert_eq!(
+            check("fn f(xs: [number] | null) { xs?[0].unwrap_or(0) }
+                impl Option { fn unwrap_or(self, fallback: T) -> T { fallback } }"),
+            Err("`unwrap_or` is a method of Option, not of a number | null, a value that can be `null` takes `??` or a comparison with `null` instead".to_string())
+        );
+        assert_eq!(
+            check("fn f(p: P | null) { match p?.n { Option::Some(n) => n, _ => 0 } }"),
+            Err("a `Option::Some` pattern doesn't fit a number | null".to_string())
+        );
+        assert_eq!(
+            check("fn f(p: P | null) { let n: Option<number> = p?.get(); }"),
+            Err("`n` has to be an Option<number>, not a number | null".to_string())
+        );
+        assert!(check(
+            "fn f(p: P | null) -> number { p?.n ?? 0 }
+            fn g(ps: [P] | null) -> number | null { ps?[0].n }
+            fn h(p: any) { p?.n }"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn argument_counts() {
+        assert_eq!(
+            check("fn add(a: number, b: number) { a + b } add(1);"),
+            Err("`add` takes 2 arguments, not 1".to_string())
+        );
+        assert_eq!(
+            check("let double = |x: number| x * 2; double(1, 2);"),
+            Err("`double` takes 1 argument, not 2".to_string())
+        );
+        // functions are known before they are declared
+        assert!(check("run(); fn run() { 1 }").is_ok());
+        // a parameter hides the function
+        assert!(check("fn f() { 1 } fn g(f: any) { f(1, 2) }").is_ok());
+    }
+
+    #[test]
+    fn early_returns() {
+        assert_eq!(
+            check("fn g() -> string { return 5; }"),
+            Err("`g` has to return a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn f(x: number) -> number { if x > 1 { return \"big\"; } x }"),
+            Err("`f` has to return a number, not a string".to_string())
+        );
+        assert_eq!(
+            check(
+                "struct P { n: number }
+                impl P { fn twice(self) -> Self { if self.n > 1 { return 2; } self } }"
+            ),
+            Err("`twice` has to return a P, not a number".to_string())
+        );
+        // locals of inner blocks are in scope, and `return` in a closure returns from the closure
+        assert!(check(
+            "fn f(x: number) -> number {
+                let label = |y: number| { return \"small\"; };
+                if x > 1 { let y = x * 2; return y; }
+                x
+            }"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn inferred_values() {
+        let check = |call: &str| {
+            check(&format!(
+                "type Id = number;
+                fn find(id: Id, items: [string], label: string | null) {{ label }}
+                {}",
+                call
+            ))
+        };
+        assert!(check("find(1 + 2, [\"a\"], null);").is_ok());
+        assert!(check("let n = -1; find(n, [], f\"{n}\");").is_ok());
+        assert_eq!(
+            check("let label = \"a\" + 1; find(label, [], null);"),
+            Err("argument `id` of `find` has to be a number, not a string".to_string())
+        );
+        assert_eq!(
+            check("find(1, [1, 2], null);"),
+            Err("argument `items` of `find` has to be a [string], not a [number]".to_string())
+        );
+        assert_eq!(
+            check("find(1, [], 1 > 2);"),
+            Err("argument `label` of `find` has to be a string or null, not a boolean".to_string())
+        );
+        assert_eq!(
+            check("find(|x: number| x, [], null);"),
+            Err("argument `id` of `find` has to be a number, not a function".to_string())
+        );
+        assert_eq!(
+            check("let total = 0; total(1);"),
+            Err("`total` is a number, not a function".to_string())
+        );
+        assert_eq!(
+            check(
+                "fn len(s: string) -> number { s.length }
+                let twice = |n: number| n * 2;
+                len(twice(len(\"ab\")));"
+            ),
+            Err("argument `s` of `len` has to be a string, not a number".to_string())
+        );
+        // the type of names that are assigned can change
+        assert!(check("let id = \"a\"; id = 1; find(id, [], null);").is_ok());
+        assert!(check(
+            "fn show(value: number | string) -> string {
+                if value is string { shout(value) } else { f\"{value}\" }
+            }
+            fn shout(s: string) -> string { s }
+            for item in [1, 2] { show(item); }"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn scopes() {
+        // a name has the type of the binding it refers to, wherever else it is bound
+        let getter = "struct P { n: number }
+            impl P { fn get(self) -> number { self.n } }
+            fn log(message: string) {}";
+        assert_eq!(
+            check(&format!(
+                "{} fn a(p: P) -> number {{ p.get() }} fn b() -> number {{ let p = 5; p }}
+                fn c(p: P) {{ log(p.get()); }}",
+                getter
+            )),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn log(message: string) {} let x = \"a\"; { let x = 1; log(x); }"),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+        assert!(check("fn log(message: string) {} let x = \"a\"; { let x = 1; } log(x);").is_ok());
+        // the parameters of callbacks of array methods are their items
+        assert_eq!(
+            check(&format!(
+                "{} fn f(ps: [P]) {{ ps.map(|q| log(q.get())); }}",
+                getter
+            )),
+            Err("argument `message` of `log` has to be a string, not a number".to_string())
+        );
+    }
+
+    #[test]
+    fn impl_fn_values() {
+        let check = |input: &str| {
+            check(&format!(
+                "fn make_adder(n: number) -> impl Fn(number) -> number {{ |x: number| x + n }}
+                {}",
+                input
+            ))
+        };
+        assert!(check("let add = make_adder(1); add(2); make_adder(1)(2);").is_ok());
+        assert!(check(
+            "fn curry(a: number) -> impl Fn(number) -> impl Fn(number) -> number {
+                |b: number| |c: number| a + b + c
+            }
+            curry(1)(2);"
+        )
+        .is_ok());
+        assert_eq!(
+            check("let add = make_adder(1); add(\"a\");"),
+            Err("argument 1 of `add` has to be a number, not a string".to_string())
+        );
+        assert_eq!(
+            check("make_adder(1)(2, 3);"),
+            Err("the function `make_adder` returns takes 1 argument, not 2".to_string())
+        );
+        assert_eq!(
+            check("fn f(n: number) -> impl Fn(number) -> number { n }"),
+            Err("`f` has to return an impl Fn(number) -> number, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn f() -> impl Fn(number) -> number { let g = || 1; g }"),
+            Err(
+                "`f` has to return an impl Fn(number) -> number, but the function takes 0 \
+                 arguments"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("fn f() -> impl Fn(number) -> number { |s: string| s }"),
+            Err(
+                "`f` has to return an impl Fn(number) -> number, but parameter `s` of the \
+                 function is a string"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("fn f() -> impl Fn(number) -> number { |x: number| f\"{x}\" }"),
+            Err(
+                "`f` has to return an impl Fn(number) -> number, but the function evaluates to \
+                 a string"
+                    .to_string()
+            )
+        );
+        // callbacks are called like functions of their type, and take only functions that fit it
+        assert_eq!(
+            check("fn apply(f: impl Fn(number) -> number) -> number { f(\"a\") }"),
+            Err("argument 1 of `f` has to be a number, not a string".to_string())
+        );
+        assert_eq!(
+            check("fn apply(f: impl Fn(number)) { f(1, 2) }"),
+            Err("`f` takes 1 argument, not 2".to_string())
+        );
+        let apply = "fn apply(f: impl Fn(number) -> number) {}
+            fn double(n: number) -> number { n * 2 }";
+        assert!(check(&format!("{} apply(|n| n); apply(double);", apply)).is_ok());
+        assert_eq!(
+            check(&format!("{} apply(|a, b| a + b);", apply)),
+            Err(
+                "argument `f` of `apply` has to be an impl Fn(number) -> number, but the \
+                 function takes 2 arguments"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(&format!("{} apply(|n: number| \"a\");", apply)),
+            Err(
+                "argument `f` of `apply` has to be an impl Fn(number) -> number, but the \
+                 function evaluates to a string"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(&format!("{} apply(5);", apply)),
+            Err(
+                "argument `f` of `apply` has to be an impl Fn(number) -> number, not a number"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            check("1 + true;"),
+            Err("`+` adds numbers or concatenates strings, not a number and a boolean".to_string())
+        );
+        assert_eq!(
+            check("fn f(s: string) -> number { s * 2 }"),
+            Err("`*` takes numbers, not a string".to_string())
+        );
+        assert_eq!(
+            check("-\"a\";"),
+            Err("`-` takes a number, not a string".to_string())
+        );
+        assert!(check("fn f(s: string, n: number) -> string { s + n + true }").is_ok());
+    }
+
+    #[test]
+    fn indexes() {
+        assert_eq!(
+            check("fn f(g: [[number]]) -> string { g[0][0] }"),
+            Err("`f` has to return a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn f(s: string) -> number { s[0] }"),
+            Err("`f` has to return a number, not a string".to_string())
+        );
+        // the rows of a grid and the items of the rows
+        assert_eq!(
+            check("fn f() -> string { let grid = array::new_2d(3, 2, 0); grid[1][2] }"),
+            Err("`f` has to return a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn f(g: [[string]]) -> [number] { g[0] }"),
+            Err("`f` has to return a [number], not a [string]".to_string())
+        );
+        assert_eq!(
+            check("fn log(s: string) {} fn f(g: [[number]]) { let row = g[0]; log(row[0]); }"),
+            Err("argument `s` of `log` has to be a string, not a number".to_string())
+        );
+        assert!(check("fn f(g: [[[number]]]) -> number { g[0][1][2] + 1 }").is_ok());
+    }
+
+    #[test]
+    fn loops_and_ranges() {
+        assert_eq!(
+            check("for i in 10 { }"),
+            Err(
+                "`for` can't loop over a number, only over arrays, strings and iterators"
+                    .to_string()
+            )
+        );
+        // loops over a string get its characters, loops over a range count numbers
+        assert_eq!(
+            check("fn g(n: number) {} for c in \"abc\" { g(c); }"),
+            Err("argument `n` of `g` has to be a number, not a string".to_string())
+        );
+        assert_eq!(
+            check("fn g(s: string) {} for i in 0..3 { g(i); }"),
+            Err("argument `s` of `g` has to be a string, not a number".to_string())
+        );
+        assert!(check("fn f(n: number) { for i in 0..=n { g(i); } } fn g(i: number) {}").is_ok());
+        assert_eq!(
+            check("let r = 0..\"10\";"),
+            Err("the bounds of a range have to be numbers, not a string".to_string())
+        );
+    }
+
+    #[test]
+    fn tuples() {
+        assert!(check(
+            "fn divide(a: number, b: number) -> (number, number) { (a / b, a % b) }
+            fn g(n: number) {}
+            let (q, r) = divide(7, 2);
+            g(r);"
+        )
+        .is_ok());
+        assert_eq!(
+            check("fn g(s: string) {} let pair = (1, \"a\"); g(pair.0);"),
+            Err("argument `s` of `g` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("fn g(s: string) {} let (n, s) = (1, \"a\"); g(n);"),
+            Err("argument `s` of `g` has to be a string, not a number".to_string())
+        );
+        assert_eq!(
+            check("let pair = (1, \"a\"); pair.2;"),
+            Err("a tuple (number, string) has no item 2, only 2 items".to_string())
+        );
+        assert_eq!(
+            check("let (a, b, c) = (1, 2);"),
+            Err("a pattern of 3 items doesn't fit a tuple (number, number)".to_string())
+        );
+        assert_eq!(
+            check("let n = 1; let (a, b) = n;"),
+            Err("only tuples can be destructured like `(a, b)`, not a number".to_string())
+        );
+        assert_eq!(
+            check("struct Point { x: number } let Point { x } = (1, 2);"),
+            Err("a `Point { .. }` pattern doesn't fit a tuple (number, number)".to_string())
+        );
+        assert_eq!(
+            check("fn f(pair: (number, string)) {} f((1, 2));"),
+            Err(
+                "argument `pair` of `f` has to be a tuple (number, string), not a tuple \
+                 (number, number)"
+                    .to_string()
+            )
+        );
+        // destructured parameters have no name, only a position
+        assert_eq!(
+            check("fn f((a, b): (number, number)) {} f((1, \"a\"));"),
+            Err(
+                "argument 1 of `f` has to be a tuple (number, number), not a tuple \
+                 (number, string)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("fn g(s: string) {} let f = |(a, b): (number, number)| g(a);"),
+            Err("argument `s` of `g` has to be a string, not a number".to_string())
+        );
+    }
+
+    #[test]
+    fn with_values() {
+        assert_eq!(
+            check("fn f(path: string) { with file = path { file.size() } }"),
+            Err("string doesn't implement Disposable, which `with` needs to close it".to_string())
+        );
+        assert_eq!(
+            check("fn g(a: number) {} fn f(open: any) { with file = open() { g(file, 1); } }"),
+            Err("`g` takes 1 argument, not 2".to_string())
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use super::ast::{Block, Declaration, Expression, ImplItem, OptionalAccess, Program, Statement};
+
+/// Calls `f` for every expression in the program, including the ones nested in other expressions,
+/// declarations and blocks. Outer expressions are visited before their children
+pub fn walk_program(program: &Program, f: &mut impl FnMut(&Expression)) {
+    for statement in program {
+        walk_statement(statement, f);
+    }
+}
+
+pub fn walk_statement(statement: &Statement, f: &mut impl FnMut(&Expression)) {
+    match statement {
+        Statement::ExpressionStatement { expression, .. } => walk_expression(expression, f),
+        Statement::DeclarationStatement(declaration, _) => walk_declaration(declaration, f),
+    }
+}
+
+pub fn walk_declaration(declaration: &Declaration, f: &mut impl FnMut(&Expression)) {
+    match declaration {
+        Declaration::ConstDeclaration(_, expression, _, _, _)
+        | Declaration::LetDeclaration(_, expression, _, _)
+        | Declaration::DestructuringDeclaration(_, expression, _, _) => {
+            walk_expression(expression, f)
+        }
+        Declaration::FunctionDeclaration { body, .. } => walk_block(body, f),
+        Declaration::StructDeclaration(_)
+        | Declaration::EnumDeclaration(_)
+        | Declaration::ModFileDeclaration(_)
+        | Declaration::UseDeclaration(_)
+        | Declaration::TypeDeclaration(_)
+        | Declaration::ExternDeclaration(_) => {}
+        Declaration::TraitDeclaration(decl) => {
+            for default in decl
+                .methods
+                .iter()
+                .filter_map(|method| method.default.as_ref())
+            {
+                walk_block(default, f);
+            }
+        }
+        Declaration::ImplDeclaration(decl) => {
+            for item in &decl.items {
+                match item {
+                    ImplItem::Method(method)
+                    | ImplItem::Getter(method)
+                    | ImplItem::Setter(method) => walk_block(&method.body, f),
+                    ImplItem::Const(_, expression) => walk_expression(expression, f),
+                }
+            }
+        }
+        Declaration::ModDeclaration(decl) => {
+            for item in &decl.items {
+                walk_declaration(&item.declaration, f);
+            }
+        }
+    }
+}
+
+pub fn walk_block(block: &Block, f: &mut impl FnMut(&Expression)) {
+    for statement in &block.statements {
+        walk_statement(statement, f);
+    }
+    if let Some(return_value) = &block.return_value {
+        walk_expression(return_value, f);
+    }
+}
+
+pub fn walk_expression(expression: &Expression, f: &mut impl FnMut(&Expression)) {
+    f(expression);
+    match expression {
+        Expression::IdentifierExpression(_, _)
+        | Expression::LiteralExpression(_, _)
+        | Expression::PathExpression(_, _)
+        | Expression::ImportExpression(_, _)
+        | Expression::BreakExpression(_)
+        | Expression::ContinueExpression(_) => {}
+        Expression::UnaryExpression(expr, _) => walk_expression(&expr.rhs, f),
+        Expression::AwaitExpression(value, _) => walk_expression(value, f),
+        Expression::InfixExpression(expr, _) => {
+            walk_expression(&expr.lhs, f);
+            walk_expression(&expr.rhs, f);
+        }
+        Expression::AssignmentExpression(expr, _) => {
+            walk_expression(&expr.lhs, f);
+            walk_expression(&expr.rhs, f);
+        }
+        Expression::ArrayExpression(elements, _) | Expression::TupleExpression(elements, _) => {
+            for element in elements {
+                walk_expression(element, f);
+            }
+        }
+        Expression::IfExpression(expr, _) => {
+            walk_expression(&expr.condition, f);
+            walk_block(&expr.then_block, f);
+            for else_if in &expr.else_if_blocks {
+                walk_expression(&else_if.condition, f);
+                walk_block(&else_if.then_block, f);
+            }
+            if let Some(else_block) = &expr.else_block {
+                walk_block(else_block, f);
+            }
+        }
+        Expression::ForExpression(expr, _) => {
+            walk_expression(&expr.rhs, f);
+            walk_block(&expr.body, f);
+        }
+        Expression::MatchExpression(expr, _) => {
+            walk_expression(&expr.value, f);
+            for arm in &expr.arms {
+                if let Some(guard) = &arm.guard {
+                    walk_expression(guard, f);
+                }
+                walk_expression(&arm.body, f);
+            }
+        }
+        Expression::WhileExpression(expr, _) => {
+            walk_expression(&expr.condition, f);
+            walk_block(&expr.body, f);
+        }
+        Expression::WithExpression(expr, _) => {
+            walk_expression(&expr.value, f);
+            walk_block(&expr.body, f);
+        }
+        Expression::BlockExpression(block, _) | Expression::DeferExpression(block, _) => {
+            walk_block(block, f)
+        }
+        Expression::TaggedTemplateExpression(expr, _) => {
+            walk_expression(&expr.tag, f);
+            for value in &expr.values {
+                walk_expression(value, f);
+            }
+        }
+        Expression::TemplateExpression(expr, _) => {
+            for value in &expr.values {
+                walk_expression(value, f);
+            }
+        }
+        Expression::CallExpression(expr, _) => {
+            walk_expression(&expr.lhs, f);
+            for argument in &expr.arguments {
+                walk_expression(argument, f);
+            }
+        }
+        Expression::IndexExpression(expr, _) => {
+            walk_expression(&expr.lhs, f);
+            walk_expression(&expr.index, f);
+        }
+        Expression::SliceExpression(expr, _) => {
+            walk_expression(&expr.lhs, f);
+            for bound in [&expr.start, &expr.end].into_iter().flatten() {
+                walk_expression(bound, f);
+            }
+        }
+        Expression::RangeExpression(expr, _) => {
+            walk_expression(&expr.start, f);
+            walk_expression(&expr.end, f);
+        }
+        Expression::MemberAccessExpression(expr, _) => walk_expression(&expr.lhs, f),
+        Expression::TupleIndexExpression(expr, _) => walk_expression(&expr.lhs, f),
+        Expression::CastExpression(expr, _) => walk_expression(&expr.value, f),
+        Expression::IsExpression(expr, _) => walk_expression(&expr.value, f),
+        Expression::OptionalExpression(expr, _) => {
+            walk_expression(&expr.lhs, f);
+            if let OptionalAccess::Index(index) = &expr.access {
+                walk_expression(index, f);
+            }
+        }
+        Expression::StructExpression(expr, _) => {
+            for (_, value) in &expr.fields {
+                walk_expression(value, f);
+            }
+        }
+        Expression::ClosureExpression(expr, _) => walk_expression(&expr.body, f),
+        Expression::ReturnExpression(value, _) => {
+            if let Some(value) = value {
+                walk_expression(value, f);
+            }
+        }
+    }
+}
+
+/// Like [`walk_program`], but `f` can change the expressions. Children are visited after `f`
+/// returns, so they are the ones of the changed expression
+pub fn walk_program_mut(program: &mut Program, f: &mut impl FnMut(&mut Expression)) {
+    for statement in program {
+        walk_statement_mut(statement, f);
+    }
+}
+
+pub fn walk_statement_mut(statement: &mut Statement, f: &mut impl FnMut(&mut Expression)) {
+    match statement {
+        Statement::ExpressionStatement { expression, .. } => walk_expression_mut(expression, f),
+        Statement::DeclarationStatement(declaration, _) => walk_declaration_mut(declaration, f),
+    }
+}
+
+pub fn walk_declaration_mut(declaration: &mut Declaration, f: &mut impl FnMut(&mut Expression)) {
+    match declaration {
+        Declaration::ConstDeclaration(_, expression, _, _, _)
+        | Declaration::LetDeclaration(_, expression, _, _)
+        | Declaration::DestructuringDeclaration(_, expression, _, _) => {
+            walk_expression_mut(expression, f)
+        }
+        Declaration::FunctionDeclaration { body, .. } => walk_block_mut(body, f),
+        Declaration::StructDeclaration(_)
+        | Declaration::EnumDeclaration(_)
+        | Declaration::ModFileDeclaration(_)
+        | Declaration::UseDeclaration(_)
+        | Declaration::TypeDeclaration(_)
+        | Declaration::ExternDeclaration(_) => {}
+        Declaration::TraitDeclaration(decl) => {
+            for default in decl
+                .methods
+                .iter_mut()
+                .filter_map(|method| method.default.as_mut())
+            {
+                walk_block_mut(default, f);
+            }
+        }
+        Declaration::ImplDeclaration(decl) => {
+            for item in &mut decl.items {
+                match item {
+                    ImplItem::Method(method)
+                    | ImplItem::Getter(method)
+                    | ImplItem::Setter(method) => walk_block_mut(&mut method.body, f),
+                    ImplItem::Const(_, expression) => walk_expression_mut(expression, f),
+                }
+            }
+        }
+        Declaration::ModDeclaration(decl) => {
+            for item in &mut decl.items {
+                walk_declaration_mut(&mut item.declaration, f);
+            }
+        }
+    }
+}
+
+pub fn walk_block_mut(block: &mut Block, f: &mut impl FnMut(&mut Expression)) {
+    for statement in &mut block.statements {
+        walk_statement_mut(statement, f);
+    }
+    if let Some(return_value) = &mut block.return_value {
+        walk_expression_mut(return_value, f);
+    }
+}
+
+pub fn walk_expression_mut(expression: &mut Expression, f: &mut impl FnMut(&mut Expression)) {
+    f(expression);
+    match expression {
+        Expression::IdentifierExpression(_, _)
+        | Expression::LiteralExpression(_, _)
+        | Expression::PathExpression(_, _)
+        | Expression::ImportExpression(_, _)
+        | Expression::BreakExpression(_)
+        | Expression::ContinueExpression(_) => {}
+        Expression::UnaryExpression(expr, _) => walk_expression_mut(&mut expr.rhs, f),
+        Expression::AwaitExpression(value, _) => walk_expression_mut(value, f),
+        Expression::InfixExpression(expr, _) => {
+            walk_expression_mut(&mut expr.lhs, f);
+            walk_expression_mut(&mut expr.rhs, f);
+        }
+        Expression::AssignmentExpression(expr, _) => {
+            walk_expression_mut(&mut expr.lhs, f);
+            walk_expression_mut(&mut expr.rhs, f);
+        }
+        Expression::ArrayExpression(elements, _) | Expression::TupleExpression(elements, _) => {
+            for element in elements {
+                walk_expression_mut(element, f);
+            }
+        }
+        Expression::IfExpression(expr, _) => {
+            walk_expression_mut(&mut expr.condition, f);
+            walk_block_mut(&mut expr.then_block, f);
+            for else_if in &mut expr.else_if_blocks {
+                walk_expression_mut(&mut else_if.condition, f);
+                walk_block_mut(&mut else_if.then_block, f);
+            }
+            if let Some(else_block) = &mut expr.else_block {
+                walk_block_mut(else_block, f);
+            }
+        }
+        Expression::ForExpression(expr, _) => {
+            walk_expression_mut(&mut expr.rhs, f);
+            walk_block_mut(&mut expr.body, f);
+        }
+        Expression::MatchExpression(expr, _) => {
+            walk_expression_mut(&mut expr.value, f);
+            for arm in &mut expr.arms {
+                if let Some(guard) = &mut arm.guard {
+                    walk_expression_mut(guard, f);
+                }
+                walk_expression_mut(&mut arm.body, f);
+            }
+        }
+        Expression::WhileExpression(expr, _) => {
+            walk_expression_mut(&mut expr.condition, f);
+            walk_block_mut(&mut expr.body, f);
+        }
+        Expression::WithExpression(expr, _) => {
+            walk_expression_mut(&mut expr.value, f);
+            walk_block_mut(&mut expr.body, f);
+        }
+        Expression::BlockExpression(block, _) | Expression::DeferExpression(block, _) => {
+            walk_block_mut(block, f)
+        }
+        Expression::TaggedTemplateExpression(expr, _) => {
+            walk_expression_mut(&mut expr.tag, f);
+            for value in &mut expr.values {
+                walk_expression_mut(value, f);
+            }
+        }
+        Expression::TemplateExpression(expr, _) => {
+            for value in &mut expr.values {
+                walk_expression_mut(value, f);
+            }
+        }
+        Expression::CallExpression(expr, _) => {
+            walk_expression_mut(&mut expr.lhs, f);
+            for argument in &mut expr.arguments {
+                walk_expression_mut(argument, f);
+            }
+        }
+        Expression::IndexExpression(expr, _) => {
+            walk_expression_mut(&mut expr.lhs, f);
+            walk_expression_mut(&mut expr.index, f);
+        }
+        Expression::SliceExpression(expr, _) => {
+            walk_expression_mut(&mut expr.lhs, f);
+            for bound in [&mut expr.start, &mut expr.end].into_iter().flatten() {
+                walk_expression_mut(bound, f);
+            }
+        }
+        Expression::RangeExpression(expr, _) => {
+            walk_expression_mut(&mut expr.start, f);
+            walk_expression_mut(&mut expr.end, f);
+        }
+        Expression::MemberAccessExpression(expr, _) => walk_expression_mut(&mut expr.lhs, f),
+        Expression::TupleIndexExpression(expr, _) => walk_expression_mut(&mut expr.lhs, f),
+        Expression::CastExpression(expr, _) => walk_expression_mut(&mut expr.value, f),
+        Expression::IsExpression(expr, _) => walk_expression_mut(&mut expr.value, f),
+        Expression::OptionalExpression(expr, _) => {
+            walk_expression_mut(&mut expr.lhs, f);
+            if let OptionalAccess::Index(index) = &mut expr.access {
+                walk_expression_mut(index, f);
+            }
+        }
+        Expression::StructExpression(expr, _) => {
+            for (_, value) in &mut expr.fields {
+                walk_expression_mut(value, f);
+            }
+        }
+        Expression::ClosureExpression(expr, _) => walk_expression_mut(&mut expr.body, f),
+        Expression::ReturnExpression(value, _) => {
+            if let Some(value) = value {
+                walk_expression_mut(value, f);
+            }
+        }
+    }
+}
+
+/// Calls `f` for every block in the statement, including function and method bodies. Inner blocks
+/// are visited before the blocks containing them, so `f` sees them after they changed
+pub fn walk_blocks_mut(statement: &mut Statement, f: &mut impl FnMut(&mut Block)) {
+    match statement {
+        Statement::ExpressionStatement { expression, .. } => {
+            walk_expression_blocks_mut(expression, f)
+        }
+        Statement::DeclarationStatement(declaration, _) => {
+            walk_declaration_blocks_mut(declaration, f)
+        }
+    }
+}
+
+fn walk_declaration_blocks_mut(declaration: &mut Declaration, f: &mut impl FnMut(&mut Block)) {
+    match declaration {
+        Declaration::ConstDeclaration(_, expression, _, _, _)
+        | Declaration::LetDeclaration(_, expression, _, _)
+        | Declaration::DestructuringDeclaration(_, expression, _, _) => {
+            walk_expression_blocks_mut(expression, f)
+        }
+        Declaration::FunctionDeclaration { body, .. } => walk_block_blocks_mut(body, f),
+        Declaration::StructDeclaration(_)
+        | Declaration::EnumDeclaration(_)
+        | Declaration::ModFileDeclaration(_)
+        | Declaration::UseDeclaration(_)
+        | Declaration::TypeDeclaration(_)
+        | Declaration::ExternDeclaration(_) => {}
+        Declaration::TraitDeclaration(decl) => {
+            for default in decl
+                .methods
+                .iter_mut()
+                .filter_map(|method| method.default.as_mut())
+            {
+                walk_block_blocks_mut(default, f);
+            }
+        }
+        Declaration::ImplDeclaration(decl) => {
+            for item in &mut decl.items {
+                match item {
+                    ImplItem::Method(method)
+                    | ImplItem::Getter(method)
+                    | ImplItem::Setter(method) => walk_block_blocks_mut(&mut method.body, f),
+                    ImplItem::Const(_, expression) => walk_expression_blocks_mut(expression, f),
+                }
+            }
+        }
+        Declaration::ModDeclaration(decl) => {
+            for item in &mut decl.items {
+                walk_declaration_blocks_mut(&mut item.declaration, f);
+            }
+        }
+    }
+}
+
+fn walk_block_blocks_mut(block: &mut Block, f: &mut impl FnMut(&mut Block)) {
+    for statement in &mut block.statements {
+        walk_blocks_mut(statement, f);
+    }
+    if let Some(return_value) = &mut block.return_value {
+        walk_expression_blocks_mut(return_value, f);
+    }
+    f(block);
+}
+
+fn walk_expression_blocks_mut(expression: &mut Expression, f: &mut impl FnMut(&mut Block)) {
+    match expression {
+        Expression::IdentifierExpression(_, _)
+        | Expression::LiteralExpression(_, _)
+        | Expression::PathExpression(_, _)
+        | Expression::ImportExpression(_, _)
+        | Expression::BreakExpression(_)
+        | Expression::ContinueExpression(_) => {}
+        Expression::UnaryExpression(expr, _) => walk_expression_blocks_mut(&mut expr.rhs, f),
+        Expression::AwaitExpression(value, _) => walk_expression_blocks_mut(value, f),
+        Expression::InfixExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.lhs, f);
+            walk_expression_blocks_mut(&mut expr.rhs, f);
+        }
+        Expression::AssignmentExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.lhs, f);
+            walk_expression_blocks_mut(&mut expr.rhs, f);
+        }
+        Expression::ArrayExpression(elements, _) | Expression::TupleExpression(elements, _) => {
+            for element in elements {
+                walk_expression_blocks_mut(element, f);
+            }
+        }
+        Expression::IfExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.condition, f);
+            walk_block_blocks_mut(&mut expr.then_block, f);
+            for else_if in &mut expr.else_if_blocks {
+                walk_expression_blocks_mut(&mut else_if.condition, f);
+                walk_block_blocks_mut(&mut else_if.then_block, f);
+            }
+            if let Some(else_block) = &mut expr.else_block {
+                walk_block_blocks_mut(else_block, f);
+            }
+        }
+        Expression::ForExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.rhs, f);
+            walk_block_blocks_mut(&mut expr.body, f);
+        }
+        Expression::MatchExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.value, f);
+            for arm in &mut expr.arms {
+                if let Some(guard) = &mut arm.guard {
+                    walk_expression_blocks_mut(guard, f);
+                }
+                walk_expression_blocks_mut(&mut arm.body, f);
+            }
+        }
+        Expression::WhileExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.condition, f);
+            walk_block_blocks_mut(&mut expr.body, f);
+        }
+        Expression::WithExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.value, f);
+            walk_block_blocks_mut(&mut expr.body, f);
+        }
+        Expression::BlockExpression(block, _) | Expression::DeferExpression(block, _) => {
+            walk_block_blocks_mut(block, f)
+        }
+        Expression::TaggedTemplateExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.tag, f);
+            for value in &mut expr.values {
+                walk_expression_blocks_mut(value, f);
+            }
+        }
+        Expression::TemplateExpression(expr, _) => {
+            for value in &mut expr.values {
+                walk_expression_blocks_mut(value, f);
+            }
+        }
+        Expression::CallExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.lhs, f);
+            for argument in &mut expr.arguments {
+                walk_expression_blocks_mut(argument, f);
+            }
+        }
+        Expression::IndexExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.lhs, f);
+            walk_expression_blocks_mut(&mut expr.index, f);
+        }
+        Expression::SliceExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.lhs, f);
+            for bound in [&mut expr.start, &mut expr.end].into_iter().flatten() {
+                walk_expression_blocks_mut(bound, f);
+            }
+        }
+        Expression::RangeExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.start, f);
+            walk_expression_blocks_mut(&mut expr.end, f);
+        }
+        Expression::MemberAccessExpression(expr, _) => walk_expression_blocks_mut(&mut expr.lhs, f),
+        Expression::TupleIndexExpression(expr, _) => walk_expression_blocks_mut(&mut expr.lhs, f),
+        Expression::CastExpression(expr, _) => walk_expression_blocks_mut(&mut expr.value, f),
+        Expression::IsExpression(expr, _) => walk_expression_blocks_mut(&mut expr.value, f),
+        Expression::OptionalExpression(expr, _) => {
+            walk_expression_blocks_mut(&mut expr.lhs, f);
+            if let OptionalAccess::Index(index) = &mut expr.access {
+                walk_expression_blocks_mut(index, f);
+            }
+        }
+        Expression::StructExpression(expr, _) => {
+            for (_, value) in &mut expr.fields {
+                walk_expression_blocks_mut(value, f);
+            }
+        }
+        Expression::ClosureExpression(expr, _) => walk_expression_blocks_mut(&mut expr.body, f),
+        Expression::ReturnExpression(value, _) => {
+            if let Some(value) = value {
+                walk_expression_blocks_mut(value, f);
+            }
+        }
+    }
+}
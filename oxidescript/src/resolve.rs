@@ -0,0 +1,1337 @@
+//! Resolves the names and paths a program uses to the declarations they refer to, so a name that
+//! is used but never declared fails before anything is compiled instead of when the output runs
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    cancellation::{CancellationToken, Cancelled},
+    diagnostic::Diagnostic,
+    parser::{
+        ast::{
+            Block, Declaration, Expression, Identifier, ImplItem, OptionalAccess, Parameter,
+            PathExpr, Pattern, Program, Span, Statement,
+        },
+        visit::{walk_blocks_mut, walk_program},
+    },
+};
+
+/// Names every backend compiles on its own, like `clone(value)`
+pub const INTRINSICS: &[&str] = &["clone"];
+
+/// The names of prelude functions, which are in scope unless the prelude is turned off. Prelude
+/// items with a path, like `Vec::new`, aren't names
+pub const PRELUDE: &[&str] = &[
+    "println",
+    "eprintln",
+    "checked_add",
+    "checked_sub",
+    "checked_mul",
+    "checked_div",
+    "panic",
+];
+
+/// The paths of the prelude, which every backend compiles on its own. Paths starting like them
+/// have to be one of them
+pub const PRELUDE_PATHS: &[&str] = &[
+    "Vec::new",
+    "Vec::from",
+    "array::new_2d",
+    "promise::new",
+    "task::spawn",
+    "task::join_all",
+    "task::race",
+    "worker::spawn",
+];
+
+/// What a name in scope is, for the paths starting with it
+#[derive(Clone, Debug)]
+enum Namespace {
+    /// A `mod` block, with its items and whether they are `pub`
+    Module(HashMap<String, (bool, Namespace)>),
+    /// A struct, enum or trait, whose associated items are found by its name
+    Type(String),
+    /// A module of another file or a name of the host, whose items aren't known here
+    Open,
+    /// A function, variable, parameter or binding
+    Value,
+}
+
+impl Namespace {
+    fn of(declaration: &Declaration) -> Self {
+        match declaration {
+            Declaration::ModDeclaration(decl) => Namespace::Module(
+                decl.items
+                    .iter()
+                    .flat_map(|item| {
+                        let namespace = Namespace::of(&item.declaration);
+                        declared_names(&item.declaration)
+                            .into_iter()
+                            .map(move |name| (name.0.clone(), (item.is_pub, namespace.clone())))
+                    })
+                    .collect(),
+            ),
+            Declaration::StructDeclaration(decl) => Namespace::Type(decl.name.0.clone()),
+            Declaration::EnumDeclaration(decl) => Namespace::Type(decl.name.0.clone()),
+            Declaration::TraitDeclaration(decl) => Namespace::Type(decl.name.0.clone()),
+            Declaration::ModFileDeclaration(_) | Declaration::UseDeclaration(_) => Namespace::Open,
+            _ => Namespace::Value,
+        }
+    }
+}
+
+/// The names declared in a block, parameter list or pattern
+struct Scope {
+    names: HashMap<String, Namespace>,
+    /// The `let`s and `const`s of the block that come after the statement being checked
+    pending: HashSet<String>,
+    /// The functions and closures around the scope. Only functions declared in the block can use
+    /// its pending names, as they run later
+    functions: usize,
+}
+
+struct Resolver<'g> {
+    /// Names of the host, like `console` or `Math`, which are in scope without being declared
+    globals: &'g HashSet<String>,
+    /// The scopes around the current expression, innermost last
+    scopes: Vec<Scope>,
+    /// The associated items of the structs, enums and traits, with the variants of the enums
+    associated: HashMap<String, HashSet<String>>,
+    /// The names of the enums, which have variants instead of only associated items
+    enums: HashSet<String>,
+    /// The type `Self` refers to in the impl block being checked
+    self_type: Option<String>,
+    /// The functions and closures around the current expression
+    functions: usize,
+    /// Whether the [`PRELUDE_PATHS`] are in scope
+    prelude: bool,
+    /// Set in the bodies of methods taking `self` and the closures in them. Functions declared in
+    /// a method don't get its `self`
+    has_self: bool,
+    /// The loops around the current expression in the current function or closure
+    loops: usize,
+    /// Set in the body of a `defer` to the loops around it, as the body can't leave them or the
+    /// function
+    defer_loops: Option<usize>,
+    /// Set in the bodies of `async` functions and closures, which can use `.await`
+    is_async: bool,
+    /// The errors of the statements checked so far, one for each statement
+    errors: Vec<Diagnostic>,
+    /// Checked before every top level statement, which stops the check once it is cancelled
+    cancellation: Option<&'g CancellationToken>,
+}
+
+/// Checks that every name the program uses is declared in a scope around it, is a parameter, a
+/// binding of a pattern or a loop, or is one of `globals`, [`INTRINSICS`] or the [`PRELUDE`] if
+/// `prelude` is set. A name can only be declared once in the same scope, in the same block or
+/// parameter list. Names are in scope in the whole block declaring them, so functions can use
+/// names declared after them, but the code of the block itself can only use a `let` or `const`
+/// after its declaration. Paths go through `mod` blocks to their `pub` items, and through types
+/// to their associated items and variants. Paths starting with a module of another file or a
+/// global aren't checked further. Every statement with an error is reported, with the first error
+/// in it
+pub fn check_names(
+    program: &Program,
+    globals: &HashSet<String>,
+    prelude: bool,
+) -> Result<(), Vec<Diagnostic>> {
+    check_names_with(program, globals, prelude, None)
+        .expect("checking without cancellation token was cancelled")
+}
+
+/// Like [`check_names`], but checks `cancellation` before every top level statement
+pub fn check_names_cancellable(
+    program: &Program,
+    globals: &HashSet<String>,
+    prelude: bool,
+    cancellation: &CancellationToken,
+) -> Result<Result<(), Vec<Diagnostic>>, Cancelled> {
+    check_names_with(program, globals, prelude, Some(cancellation))
+}
+
+fn check_names_with(
+    program: &Program,
+    globals: &HashSet<String>,
+    prelude: bool,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Result<(), Vec<Diagnostic>>, Cancelled> {
+    let mut builtins = INTRINSICS
+        .iter()
+        .chain(if prelude { PRELUDE } else { &[] })
+        .map(|name| name.to_string())
+        .collect::<HashSet<_>>();
+    builtins.extend(globals.iter().cloned());
+    let mut enums = HashSet::new();
+    let associated = associated_items(program, &mut enums);
+    let mut resolver = Resolver {
+        globals: &builtins,
+        scopes: vec![],
+        associated,
+        enums,
+        self_type: None,
+        functions: 0,
+        prelude,
+        has_self: false,
+        loops: 0,
+        defer_loops: None,
+        is_async: false,
+        errors: vec![],
+        cancellation,
+    };
+    if let Err(error) = resolver.check_statements(program, None, false) {
+        resolver.errors.push(error);
+    }
+    if cancellation.is_some_and(|cancellation| cancellation.is_cancelled()) {
+        return Err(Cancelled);
+    }
+    // the errors in a statement are found before the error of the statement around them
+    resolver
+        .errors
+        .sort_by_key(|error| error.labels.first().map(|label| label.span.start));
+    Ok(match resolver.errors.is_empty() {
+        true => Ok(()),
+        false => Err(resolver.errors),
+    })
+}
+
+/// The [`PRELUDE`] names the program declares somewhere, as a declaration, parameter or binding.
+/// Backends call functions with these names like any other function instead of compiling them as
+/// intrinsics, so a program can declare its own `println`
+pub fn shadowed_prelude(program: &Program) -> HashSet<String> {
+    shadowed_names(program, PRELUDE)
+}
+
+/// Which of `candidates` the program declares somewhere, like [`shadowed_prelude`]
+pub fn shadowed_names(program: &Program, candidates: &[&str]) -> HashSet<String> {
+    let mut names = vec![];
+    let mut declaration_names = |declaration: &Declaration| {
+        names.extend(declared_names(declaration).into_iter().cloned());
+        match declaration {
+            Declaration::FunctionDeclaration { parameters, .. } => {
+                names.extend(parameters.iter().map(|parameter| parameter.name.clone()))
+            }
+            Declaration::ImplDeclaration(decl) => {
+                for item in &decl.items {
+                    if let ImplItem::Method(method)
+                    | ImplItem::Getter(method)
+                    | ImplItem::Setter(method) = item
+                    {
+                        names.extend(method.parameters.iter().map(|p| p.name.clone()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    };
+    for statement in program {
+        if let Statement::DeclarationStatement(declaration, _) = statement {
+            declaration_names(declaration);
+        }
+        walk_blocks_mut(&mut statement.clone(), &mut |block| {
+            for statement in &block.statements {
+                if let Statement::DeclarationStatement(declaration, _) = statement {
+                    declaration_names(declaration);
+                }
+            }
+        });
+    }
+    walk_program(program, &mut |expression| match expression {
+        Expression::ClosureExpression(expr, _) => names.extend(
+            expr.parameters
+                .iter()
+                .map(|parameter| parameter.name.clone()),
+        ),
+        Expression::ForExpression(expr, _) => names.push(expr.lhs.clone()),
+        Expression::MatchExpression(expr, _) => {
+            let mut bindings = vec![];
+            for arm in &expr.arms {
+                pattern_names(&arm.pattern, &mut bindings);
+            }
+            names.extend(bindings.into_iter().cloned());
+        }
+        _ => {}
+    });
+    names
+        .into_iter()
+        .map(|name| name.0)
+        .filter(|name| candidates.contains(&name.as_str()))
+        .collect()
+}
+
+impl Resolver<'_> {
+    /// What `name` is in the innermost scope declaring it, an error for a `let` or `const` that
+    /// comes later in the same code, and `None` if it isn't declared
+    fn lookup(&self, name: &Identifier, span: Span) -> Result<Option<&Namespace>, Diagnostic> {
+        let Some(scope) = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.names.contains_key(&name.0))
+        else {
+            return Ok(self.globals.contains(&name.0).then_some(&Namespace::Open));
+        };
+        if scope.pending.contains(&name.0) && scope.functions == self.functions {
+            return Err(
+                Diagnostic::error(format!("`{}` is used before its declaration", name.0))
+                    .with_label(span, "used here")
+                    .with_help("move the declaration in front of the code using it"),
+            );
+        }
+        Ok(scope.names.get(&name.0))
+    }
+
+    /// Opens a scope with `names` declared in it, with the span of the code declaring them and
+    /// what they are
+    fn push_scope<'n>(
+        &mut self,
+        names: impl IntoIterator<Item = (&'n Identifier, Span, Namespace)>,
+    ) -> Result<(), Diagnostic> {
+        let mut scope = HashMap::new();
+        let mut spans = HashMap::new();
+        for (name, span, namespace) in names {
+            if let Some(first) = spans.insert(name.0.clone(), span) {
+                return Err(Diagnostic::error(format!(
+                    "`{}` is declared twice in the same scope",
+                    name.0
+                ))
+                .with_label(span, "declared again here")
+                .with_label(first, "first declared here"));
+            }
+            scope.insert(name.0.clone(), namespace);
+        }
+        self.scopes.push(Scope {
+            names: scope,
+            pending: HashSet::new(),
+            functions: self.functions,
+        });
+        Ok(())
+    }
+
+    /// Checks `f` in a new scope with `names` declared in it
+    fn scoped<'n>(
+        &mut self,
+        names: impl IntoIterator<Item = (&'n Identifier, Span, Namespace)>,
+        f: impl FnOnce(&mut Self) -> Result<(), Diagnostic>,
+    ) -> Result<(), Diagnostic> {
+        self.push_scope(names)?;
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn check_statements(
+        &mut self,
+        statements: &[Statement],
+        return_value: Option<&Expression>,
+        in_block: bool,
+    ) -> Result<(), Diagnostic> {
+        let names = statements
+            .iter()
+            .flat_map(|statement| match statement {
+                Statement::DeclarationStatement(declaration, span) => {
+                    let namespace = Namespace::of(declaration);
+                    declared_names(declaration)
+                        .into_iter()
+                        .map(|name| (name, *span, namespace.clone()))
+                        .collect()
+                }
+                Statement::ExpressionStatement { .. } => vec![],
+            })
+            .collect::<Vec<_>>();
+        self.scoped(names, |resolver| {
+            resolver.scopes.last_mut().unwrap().pending = statements
+                .iter()
+                .filter_map(|statement| match statement {
+                    Statement::DeclarationStatement(declaration, _) => Some(declaration),
+                    Statement::ExpressionStatement { .. } => None,
+                })
+                .flat_map(variable_names)
+                .collect();
+            for statement in statements {
+                let cancellation = resolver.cancellation.filter(|_| resolver.scopes.len() == 1);
+                if cancellation.is_some_and(|cancellation| cancellation.is_cancelled()) {
+                    break;
+                }
+                let result = match statement {
+                    // the top level of a module is never left, so a defer there wouldn't run
+                    Statement::ExpressionStatement {
+                        expression: Expression::DeferExpression(_, span),
+                        ..
+                    } if !in_block => Err(Diagnostic::error(
+                        "`defer` can only be used in a block, not at the top level",
+                    )
+                    .with_label(*span, "would never run")
+                    .with_help("move it into the function or block it cleans up after")),
+                    Statement::ExpressionStatement {
+                        expression: Expression::DeferExpression(body, _),
+                        ..
+                    } => {
+                        let outer = resolver.defer_loops.replace(resolver.loops);
+                        let result = resolver.check_block(body);
+                        resolver.defer_loops = outer;
+                        result
+                    }
+                    Statement::ExpressionStatement { expression, .. } => {
+                        resolver.check_expression(expression)
+                    }
+                    Statement::DeclarationStatement(declaration, span) => {
+                        resolver.check_declaration(declaration, *span)
+                    }
+                };
+                if let Statement::DeclarationStatement(declaration, _) = statement {
+                    resolver.declared(declaration);
+                }
+                // the next statements are checked on their own
+                if let Err(error) = result {
+                    resolver.errors.push(error);
+                }
+            }
+            match return_value {
+                Some(value) => resolver.check_expression(value),
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// Marks the `let`s and `const`s of a checked declaration as declared for the code after it
+    fn declared(&mut self, declaration: &Declaration) {
+        let scope = self.scopes.last_mut().unwrap();
+        for name in variable_names(declaration) {
+            scope.pending.remove(&name);
+        }
+    }
+
+    fn check_block(&mut self, block: &Block) -> Result<(), Diagnostic> {
+        self.check_statements(&block.statements, block.return_value.as_ref(), true)
+    }
+
+    /// Checks the body of a loop
+    fn check_loop(&mut self, body: &Block) -> Result<(), Diagnostic> {
+        self.loops += 1;
+        let result = self.check_block(body);
+        self.loops -= 1;
+        result
+    }
+
+    /// Checks `f` as the body of a function or closure, which `return`, `break` and `continue`
+    /// in it can't leave
+    fn in_function(
+        &mut self,
+        is_async: bool,
+        f: impl FnOnce(&mut Self) -> Result<(), Diagnostic>,
+    ) -> Result<(), Diagnostic> {
+        let loops = std::mem::take(&mut self.loops);
+        let defer_loops = self.defer_loops.take();
+        let outer_async = std::mem::replace(&mut self.is_async, is_async);
+        self.functions += 1;
+        let result = f(self);
+        self.functions -= 1;
+        self.loops = loops;
+        self.defer_loops = defer_loops;
+        self.is_async = outer_async;
+        result
+    }
+
+    /// Checks the body of a function or method with its parameters in scope, and `self` if it is
+    /// a method taking `self`
+    fn check_function(
+        &mut self,
+        has_self: bool,
+        is_async: bool,
+        parameters: &[Parameter],
+        body: &Block,
+    ) -> Result<(), Diagnostic> {
+        let outer = std::mem::replace(&mut self.has_self, has_self);
+        let names = parameters
+            .iter()
+            .map(|parameter| (&parameter.name, parameter.span, Namespace::Value));
+        let result = self.in_function(is_async, |resolver| {
+            resolver.scoped(names, |resolver| resolver.check_block(body))
+        });
+        self.has_self = outer;
+        result
+    }
+
+    /// Checks a declaration, with the span of the statement or `mod` item declaring it
+    fn check_declaration(
+        &mut self,
+        declaration: &Declaration,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        match declaration {
+            Declaration::ConstDeclaration(_, value, _, _, _)
+            | Declaration::LetDeclaration(_, value, _, _)
+            | Declaration::DestructuringDeclaration(_, value, _, _) => self.check_expression(value),
+            Declaration::FunctionDeclaration {
+                is_async,
+                parameters,
+                body,
+                ..
+            } => self.check_function(false, *is_async, parameters, body),
+            Declaration::ImplDeclaration(decl) => {
+                let target = type_name(&decl.target).to_string();
+                let outer = self.self_type.replace(target);
+                let result = self.check_impl_items(&decl.items);
+                self.self_type = outer;
+                result
+            }
+            Declaration::TraitDeclaration(decl) => {
+                // `Self` in the default methods is whatever type implements the trait
+                let outer = self.self_type.take();
+                let result = decl.defaults().iter().try_for_each(|method| {
+                    self.check_function(method.has_self, false, &method.parameters, &method.body)
+                });
+                self.self_type = outer;
+                result
+            }
+            Declaration::ModDeclaration(decl) => {
+                let names = decl.items.iter().flat_map(|item| {
+                    let namespace = Namespace::of(&item.declaration);
+                    declared_names(&item.declaration)
+                        .into_iter()
+                        .map(move |name| (name, item.span, namespace.clone()))
+                });
+                self.scoped(names.collect::<Vec<_>>(), |resolver| {
+                    resolver.scopes.last_mut().unwrap().pending = decl
+                        .items
+                        .iter()
+                        .flat_map(|item| variable_names(&item.declaration))
+                        .collect();
+                    decl.items.iter().try_for_each(|item| {
+                        resolver.check_declaration(&item.declaration, item.span)?;
+                        resolver.declared(&item.declaration);
+                        Ok(())
+                    })
+                })
+            }
+            Declaration::UseDeclaration(decl) => decl.items.iter().try_for_each(|item| {
+                let segments = decl.path.iter().chain([item]).cloned().collect();
+                self.resolve_path(&PathExpr { segments }, span)
+            }),
+            Declaration::StructDeclaration(_)
+            | Declaration::EnumDeclaration(_)
+            | Declaration::ModFileDeclaration(_)
+            | Declaration::TypeDeclaration(_)
+            | Declaration::ExternDeclaration(_) => Ok(()),
+        }
+    }
+
+    fn check_impl_items(&mut self, items: &[ImplItem]) -> Result<(), Diagnostic> {
+        items.iter().try_for_each(|item| match item {
+            ImplItem::Method(method) => self.check_function(
+                method.has_self,
+                method.is_async,
+                &method.parameters,
+                &method.body,
+            ),
+            ImplItem::Getter(method) | ImplItem::Setter(method) => {
+                self.check_function(true, false, &method.parameters, &method.body)
+            }
+            ImplItem::Const(_, value) => self.check_expression(value),
+        })
+    }
+
+    /// Resolves every segment of a path: the first one is a name in scope, a global or the start
+    /// of a [`PRELUDE_PATHS`] path, the next ones are `pub` items of `mod` blocks, or the
+    /// associated items or variants of types
+    fn resolve_path(&self, path: &PathExpr, span: Span) -> Result<(), Diagnostic> {
+        let (first, rest) = path.segments.split_first().unwrap();
+        let full = || {
+            path.segments
+                .iter()
+                .map(|segment| segment.0.as_str())
+                .collect::<Vec<_>>()
+                .join("::")
+        };
+        // the backends compile the paths of the prelude even if a name shadows them
+        if self.prelude && PRELUDE_PATHS.contains(&full().as_str()) {
+            return Ok(());
+        }
+        let mut namespace = match self.lookup(first, span)? {
+            _ if first.0 == "Self" => match &self.self_type {
+                Some(type_) => Namespace::Type(type_.clone()),
+                None => Namespace::Open,
+            },
+            Some(namespace) => namespace.clone(),
+            // `cfg::name` is a build flag, and `false` if it isn't set
+            None if first.0 == "cfg" => Namespace::Open,
+            // an impl block without a struct declares the type in the output too
+            None if self.associated.contains_key(&first.0) => Namespace::Type(first.0.clone()),
+            None if self.prelude
+                && PRELUDE_PATHS
+                    .iter()
+                    .any(|prelude| prelude.split("::").next() == Some(&first.0)) =>
+            {
+                return Err(
+                    Diagnostic::error(format!("`{}` isn't in the prelude", full()))
+                        .with_label(span, "not found")
+                        .with_help(format!(
+                            "the prelude has {}",
+                            PRELUDE_PATHS
+                                .iter()
+                                .map(|path| format!("`{}`", path))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )),
+                );
+            }
+            None => return Err(undeclared(first, span)),
+        };
+        let mut parent = first;
+        for segment in rest {
+            namespace = match namespace {
+                Namespace::Module(items) => match items.get(&segment.0) {
+                    // the object of a module only has its `pub` items, even for its own code
+                    Some((false, _)) => {
+                        return Err(Diagnostic::error(format!(
+                            "`{}` is private to the module `{}`",
+                            segment.0, parent.0
+                        ))
+                        .with_label(span, "private item")
+                        .with_help(format!(
+                            "mark it `pub` in `mod {}`, or use `{}` without a path inside of the \
+                             module",
+                            parent.0, segment.0
+                        )));
+                    }
+                    Some((_, item)) => item.clone(),
+                    None => {
+                        return Err(Diagnostic::error(format!(
+                            "the module `{}` has no item `{}`",
+                            parent.0, segment.0
+                        ))
+                        .with_label(span, format!("not found in `{}`", parent.0)));
+                    }
+                },
+                Namespace::Type(type_) => {
+                    let items = self.associated.get(&type_);
+                    if !items.is_some_and(|items| items.contains(&segment.0)) {
+                        let message = match self.enums.contains(&type_) {
+                            true => format!("the enum `{}` has no variant `{}`", type_, segment.0),
+                            false => format!(
+                                "`{}` has no associated function or constant `{}`",
+                                type_, segment.0
+                            ),
+                        };
+                        return Err(Diagnostic::error(message)
+                            .with_label(span, format!("not found in `{}`", type_)));
+                    }
+                    Namespace::Value
+                }
+                Namespace::Open => return Ok(()),
+                Namespace::Value => {
+                    return Err(Diagnostic::error(format!(
+                        "`{}` isn't a module or a type, so `{}` can't be found in it",
+                        parent.0,
+                        full()
+                    ))
+                    .with_label(span, "not a module or a type")
+                    .with_help(format!(
+                        "use `{}.{}` for a field of the value",
+                        parent.0, segment.0
+                    )));
+                }
+            };
+            parent = segment;
+        }
+        Ok(())
+    }
+
+    fn check_expression(&mut self, expression: &Expression) -> Result<(), Diagnostic> {
+        match expression {
+            // `self` is a keyword, so a global of the host named `self` isn't in scope either
+            Expression::IdentifierExpression(name, span) if name.0 == "self" && !self.has_self => {
+                Err(
+                    Diagnostic::error("`self` is only available in methods taking `self`")
+                        .with_label(*span, "not a method taking `self`")
+                        .with_help(
+                            "add `self` as the first parameter of the method, or pass the value \
+                             as a parameter",
+                        ),
+                )
+            }
+            Expression::IdentifierExpression(name, _) if name.0 == "self" => Ok(()),
+            Expression::IdentifierExpression(name, span) => match self.lookup(name, *span)? {
+                Some(_) => Ok(()),
+                None => Err(undeclared(name, *span)),
+            },
+            Expression::PathExpression(path, span) => self.resolve_path(path, *span),
+            Expression::BreakExpression(_) | Expression::ContinueExpression(_)
+                if self.defer_loops == Some(self.loops) =>
+            {
+                Err(
+                    Diagnostic::error("a `defer` can't leave the loop around it")
+                        .with_label(expression.span(), "leaves the loop")
+                        .with_help(
+                            "only loops inside the `defer` can be left with `break` or `continue`",
+                        ),
+                )
+            }
+            Expression::ReturnExpression(_, span) if self.defer_loops.is_some() => Err(
+                Diagnostic::error("a `defer` can't return from the function around it")
+                    .with_label(*span, "returns from the function"),
+            ),
+            Expression::DeferExpression(_, span) => Err(Diagnostic::error(
+                "`defer` can only be a statement of a block",
+            )
+            .with_label(*span, "used as a value")),
+            Expression::LiteralExpression(_, _)
+            | Expression::ImportExpression(_, _)
+            | Expression::BreakExpression(_)
+            | Expression::ContinueExpression(_) => Ok(()),
+            Expression::UnaryExpression(expr, _) => self.check_expression(&expr.rhs),
+            Expression::InfixExpression(expr, _) => {
+                self.check_expression(&expr.lhs)?;
+                self.check_expression(&expr.rhs)
+            }
+            Expression::AssignmentExpression(expr, _) => {
+                self.check_expression(&expr.lhs)?;
+                self.check_expression(&expr.rhs)
+            }
+            Expression::ArrayExpression(items, _) | Expression::TupleExpression(items, _) => items
+                .iter()
+                .try_for_each(|item| self.check_expression(item)),
+            Expression::IfExpression(expr, _) => {
+                self.check_expression(&expr.condition)?;
+                self.check_block(&expr.then_block)?;
+                for else_if in &expr.else_if_blocks {
+                    self.check_expression(&else_if.condition)?;
+                    self.check_block(&else_if.then_block)?;
+                }
+                match &expr.else_block {
+                    Some(else_block) => self.check_block(else_block),
+                    None => Ok(()),
+                }
+            }
+            Expression::ForExpression(expr, span) => {
+                self.check_expression(&expr.rhs)?;
+                self.scoped([(&expr.lhs, *span, Namespace::Value)], |resolver| {
+                    resolver.check_loop(&expr.body)
+                })
+            }
+            Expression::WhileExpression(expr, _) => {
+                self.check_expression(&expr.condition)?;
+                self.check_loop(&expr.body)
+            }
+            // the body is in the scope of the name, so it can't declare the name again
+            Expression::WithExpression(expr, _) => self.check_block(&expr.block()),
+            // patterns have no spans, the names they bind are declared by the arm
+            Expression::MatchExpression(expr, _) => {
+                self.check_expression(&expr.value)?;
+                for arm in &expr.arms {
+                    let mut names = vec![];
+                    pattern_names(&arm.pattern, &mut names);
+                    let span = arm.body.span();
+                    let names = names.into_iter().map(|name| (name, span, Namespace::Value));
+                    self.scoped(names, |resolver| {
+                        arm.guard
+                            .iter()
+                            .chain([&arm.body])
+                            .try_for_each(|expression| resolver.check_expression(expression))
+                    })?;
+                }
+                Ok(())
+            }
+            Expression::BlockExpression(block, _) => self.check_block(block),
+            Expression::CallExpression(expr, _) => {
+                self.check_expression(&expr.lhs)?;
+                expr.arguments
+                    .iter()
+                    .try_for_each(|argument| self.check_expression(argument))
+            }
+            Expression::TaggedTemplateExpression(expr, _) => {
+                self.check_expression(&expr.tag)?;
+                expr.values
+                    .iter()
+                    .try_for_each(|value| self.check_expression(value))
+            }
+            Expression::TemplateExpression(expr, _) => expr
+                .values
+                .iter()
+                .try_for_each(|value| self.check_expression(value)),
+            Expression::IndexExpression(expr, _) => {
+                self.check_expression(&expr.lhs)?;
+                self.check_expression(&expr.index)
+            }
+            Expression::SliceExpression(expr, _) => {
+                self.check_expression(&expr.lhs)?;
+                [&expr.start, &expr.end]
+                    .into_iter()
+                    .flatten()
+                    .try_for_each(|bound| self.check_expression(bound))
+            }
+            Expression::RangeExpression(expr, _) => {
+                self.check_expression(&expr.start)?;
+                self.check_expression(&expr.end)
+            }
+            Expression::MemberAccessExpression(expr, _) => self.check_expression(&expr.lhs),
+            Expression::TupleIndexExpression(expr, _) => self.check_expression(&expr.lhs),
+            Expression::CastExpression(expr, _) => self.check_expression(&expr.value),
+            Expression::IsExpression(expr, _) => self.check_expression(&expr.value),
+            Expression::OptionalExpression(expr, _) => {
+                self.check_expression(&expr.lhs)?;
+                match &expr.access {
+                    OptionalAccess::Index(index) => self.check_expression(index),
+                    OptionalAccess::Member(_) => Ok(()),
+                }
+            }
+            Expression::StructExpression(expr, _) => expr
+                .fields
+                .iter()
+                .try_for_each(|(_, value)| self.check_expression(value)),
+            Expression::ClosureExpression(expr, _) => {
+                let names = expr
+                    .parameters
+                    .iter()
+                    .map(|parameter| (&parameter.name, parameter.span, Namespace::Value));
+                self.in_function(expr.is_async, |resolver| {
+                    resolver.scoped(names, |resolver| resolver.check_expression(&expr.body))
+                })
+            }
+            // javascript only allows `await` at the top level of modules, which the other
+            // backends don't have
+            Expression::AwaitExpression(value, span) => {
+                if !self.is_async {
+                    return Err(Diagnostic::error(
+                        "`.await` can only be used in `async` functions and closures",
+                    )
+                    .with_label(*span, "not in an `async` function")
+                    .with_help("make the function around it `async fn`"));
+                }
+                self.check_expression(value)
+            }
+            Expression::ReturnExpression(value, _) => match value {
+                Some(value) => self.check_expression(value),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+fn undeclared(name: &Identifier, span: Span) -> Diagnostic {
+    Diagnostic::error(format!("`{}` isn't declared", name.0))
+        .with_label(span, "not found in this scope")
+        .with_help(
+            "declare it before using it, or add it to `globals` in oxide.toml if the host \
+             provides it",
+        )
+}
+
+/// The names of a `let` or `const`, which the code around them can't use before them
+fn variable_names(declaration: &Declaration) -> Vec<String> {
+    match declaration {
+        Declaration::ConstDeclaration(..)
+        | Declaration::LetDeclaration(..)
+        | Declaration::DestructuringDeclaration(..) => declared_names(declaration)
+            .into_iter()
+            .map(|name| name.0.clone())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// `Pair` for `Pair<T>`
+fn type_name(type_: &Identifier) -> &str {
+    type_.0.split('<').next().unwrap_or_default().trim()
+}
+
+/// The associated items of every struct, enum and trait the program declares, in any block or
+/// `mod` block: the items of their impls, the methods of the traits they implement, what their
+/// derives add, and the variants of enums. Adds the names of the enums to `enums`
+fn associated_items(
+    program: &Program,
+    enums: &mut HashSet<String>,
+) -> HashMap<String, HashSet<String>> {
+    fn declare(
+        declaration: &Declaration,
+        items: &mut HashMap<String, HashSet<String>>,
+        enums: &mut HashSet<String>,
+        impls: &mut Vec<(String, String)>,
+    ) {
+        let item_name = |item: &ImplItem| match item {
+            ImplItem::Method(method) | ImplItem::Getter(method) | ImplItem::Setter(method) => {
+                method.name.0.clone()
+            }
+            ImplItem::Const(name, _) => name.0.clone(),
+        };
+        match declaration {
+            Declaration::StructDeclaration(decl) => {
+                let derived = decl
+                    .derives
+                    .iter()
+                    .filter_map(|derive| match derive.0.as_str() {
+                        "Serialize" => Some("to_json".to_string()),
+                        "Deserialize" => Some("from_json".to_string()),
+                        _ => None,
+                    });
+                items
+                    .entry(decl.name.0.clone())
+                    .or_default()
+                    .extend(derived);
+            }
+            Declaration::EnumDeclaration(decl) => {
+                enums.insert(decl.name.0.clone());
+                let (_, lowered) = decl.lowered();
+                let variants = decl.variants.iter().map(|variant| variant.name.0.clone());
+                items
+                    .entry(decl.name.0.clone())
+                    .or_default()
+                    .extend(lowered.iter().map(item_name).chain(variants));
+            }
+            Declaration::TraitDeclaration(decl) => {
+                let methods = decl.methods.iter().map(|method| method.name.0.clone());
+                items
+                    .entry(decl.name.0.clone())
+                    .or_default()
+                    .extend(methods);
+            }
+            Declaration::ImplDeclaration(decl) => {
+                let target = type_name(&decl.target).to_string();
+                if let Some(trait_) = &decl.trait_ {
+                    impls.push((target.clone(), trait_.0.clone()));
+                }
+                items
+                    .entry(target)
+                    .or_default()
+                    .extend(decl.items.iter().map(item_name));
+            }
+            Declaration::ModDeclaration(decl) => {
+                for item in &decl.items {
+                    declare(&item.declaration, items, enums, impls);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut items = HashMap::new();
+    let mut impls = vec![];
+    for statement in program {
+        if let Statement::DeclarationStatement(declaration, _) = statement {
+            declare(declaration, &mut items, enums, &mut impls);
+        }
+        walk_blocks_mut(&mut statement.clone(), &mut |block| {
+            for statement in &block.statements {
+                if let Statement::DeclarationStatement(declaration, _) = statement {
+                    declare(declaration, &mut items, enums, &mut impls);
+                }
+            }
+        });
+    }
+    // the default methods of a trait are methods of the types implementing it
+    for (target, trait_) in impls {
+        let methods = items.get(&trait_).cloned().unwrap_or_default();
+        items.entry(target).or_default().extend(methods);
+    }
+    items
+}
+
+/// The names a declaration adds to the scope it is in
+pub(crate) fn declared_names(declaration: &Declaration) -> Vec<&Identifier> {
+    match declaration {
+        Declaration::ConstDeclaration(name, _, _, _, _)
+        | Declaration::LetDeclaration(name, _, _, _)
+        | Declaration::FunctionDeclaration { name, .. }
+        | Declaration::ModFileDeclaration(name) => vec![name],
+        Declaration::StructDeclaration(decl) => vec![&decl.name],
+        Declaration::EnumDeclaration(decl) => vec![&decl.name],
+        Declaration::ModDeclaration(decl) => vec![&decl.name],
+        Declaration::TypeDeclaration(decl) => vec![&decl.name],
+        Declaration::ExternDeclaration(decl) => vec![&decl.name],
+        Declaration::TraitDeclaration(decl) => vec![&decl.name],
+        Declaration::UseDeclaration(decl) => decl.items.iter().collect(),
+        Declaration::DestructuringDeclaration(pattern, _, _, _) => {
+            let mut names = vec![];
+            pattern_names(pattern, &mut names);
+            names
+        }
+        Declaration::ImplDeclaration(_) => vec![],
+    }
+}
+
+/// The names a pattern binds. Every alternative of an or pattern binds the same names
+fn pattern_names<'p>(pattern: &'p Pattern, names: &mut Vec<&'p Identifier>) {
+    match pattern {
+        Pattern::Binding(name) | Pattern::Prefix(_, Some(name)) => {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        Pattern::Variant(_, patterns) | Pattern::Or(patterns) | Pattern::Tuple(patterns) => {
+            for pattern in patterns {
+                pattern_names(pattern, names);
+            }
+        }
+        Pattern::Struct(_, fields) => {
+            for (_, pattern) in fields {
+                pattern_names(pattern, names);
+            }
+        }
+        Pattern::Wildcard | Pattern::Literal(_) | Pattern::Prefix(_, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+        stdlib::used_items,
+    };
+
+    use super::*;
+
+    fn diagnostics(input: &str) -> Result<(), Vec<Diagnostic>> {
+        let (_, (tokens, spans)) = Lexer::lex_tokens_with_spans(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::with_spans(&tokens, &spans, 0)).unwrap();
+        let globals = HashSet::from(["console".to_string()]);
+        // the standard library items the program uses come first, like in the compiler
+        let program = used_items(&program, false)
+            .into_iter()
+            .chain(program)
+            .collect::<Program>();
+        check_names(&program, &globals, true)
+    }
+
+    fn diagnostic(input: &str) -> Result<(), Diagnostic> {
+        diagnostics(input).map_err(|errors| errors.into_iter().next().unwrap())
+    }
+
+    fn check(input: &str) -> Result<(), String> {
+        diagnostic(input).map_err(|err| err.message)
+    }
+
+    #[test]
+    fn undeclared_names() {
+        assert_eq!(check("foo(bar);"), Err("`foo` isn't declared".to_string()));
+        let err = diagnostic("let a = 1;\nprintln(b);").unwrap_err();
+        let span = err.labels[0].span;
+        assert_eq!((span.start, span.end), (19, 20));
+        assert!(err.help.is_some());
+        // every statement is checked, the one after an error too
+        let messages = diagnostics("println(a);\nfn f() { let b = c; println(d); }")
+            .unwrap_err()
+            .into_iter()
+            .map(|err| err.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "`a` isn't declared",
+                "`c` isn't declared",
+                "`d` isn't declared"
+            ]
+        );
+        assert_eq!(
+            check("fn foo(x: number) { x } foo(bar);"),
+            Err("`bar` isn't declared".to_string())
+        );
+        assert_eq!(
+            check("fn f() { let a = 1; } a = 2;"),
+            Err("`a` isn't declared".to_string())
+        );
+        assert_eq!(
+            check("for item in [1] { item } println(item);"),
+            Err("`item` isn't declared".to_string())
+        );
+        assert!(check(
+            "println(area(2));
+            fn area(size: number) { size * scale }
+            const scale = 2;
+            let double = |x: number| x * 2;
+            console.log(clone([double(1)]));"
+        )
+        .is_ok());
+        assert!(check(
+            "struct Counter { count: number }
+            impl Counter {
+                fn increment(self, by: number) { self.count += by; }
+            }
+            match Option::Some(1) {
+                Option::Some(n) if n > 0 => n,
+                \"a\" .. rest => rest,
+                _ => 0,
+            }"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn duplicate_declarations() {
+        assert_eq!(
+            check("let x = 1; let x = 2;"),
+            Err("`x` is declared twice in the same scope".to_string())
+        );
+        assert_eq!(
+            check("fn f(a: number, a: number) { a }"),
+            Err("`a` is declared twice in the same scope".to_string())
+        );
+        assert_eq!(
+            check("mod shapes { pub fn area() { 1 } fn area() { 2 } }"),
+            Err("`area` is declared twice in the same scope".to_string())
+        );
+        // the second declaration is the error, the first one explains it
+        let err = diagnostic("let x = 1;\nlet x = 2;").unwrap_err();
+        let spans = err
+            .labels
+            .iter()
+            .map(|label| (label.span.start, label.span.end))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![(11, 21), (0, 10)]);
+        // an inner scope can declare the names of outer ones again
+        assert!(check("let x = 1; fn f(x: number) { let y = x; { let y = 2; y } }").is_ok());
+    }
+
+    #[test]
+    fn paths() {
+        let geo = "mod geo {
+                pub fn area(size: number) { size * SCALE }
+                fn hidden() { 1 }
+                const SCALE = 2;
+                pub mod shapes {
+                    pub fn square() { geo::area(1) }
+                }
+            }
+            struct P { x: number }
+            impl P { fn new() -> P { Self::origin() } fn origin() -> P { P { x: 0 } } const ZERO = 0; }
+            enum E { A, B }
+            trait Named { fn name(self) -> string { \"named\" } }
+            impl Named for P {}
+            #[derive(Flags)]
+            enum Permissions { Read, Write }
+            #[derive(Deserialize)]
+            struct Point { x: number }
+            ";
+        let check = |input: &str| check(&format!("{}{}", geo, input));
+        assert!(check(
+            "geo::area(geo::shapes::square());
+            let p = P::new();
+            let zero = P::ZERO;
+            let e = E::A;
+            let name = P::name(p);
+            let read = Permissions::contains(Permissions::all(), Permissions::Read);
+            let point = Point::from_json(\"{}\");
+            let v = Vec::new();
+            let task = task::spawn(async || 1);
+            let o = Option::Some(1);
+            if cfg::debug { println(1); }
+            use geo::area;"
+        )
+        .is_ok());
+        assert_eq!(
+            check("geo::nonexistent();"),
+            Err("the module `geo` has no item `nonexistent`".to_string())
+        );
+        assert_eq!(
+            check("geo::hidden();"),
+            Err("`hidden` is private to the module `geo`".to_string())
+        );
+        // the module object only has the `pub` items, so the module itself can't use the others
+        // through a path either
+        assert_eq!(
+            check("mod inner { fn hidden() { 1 } pub fn f() { inner::hidden() } }"),
+            Err("`hidden` is private to the module `inner`".to_string())
+        );
+        assert_eq!(
+            check("geo::shapes::hidden();"),
+            Err("the module `shapes` has no item `hidden`".to_string())
+        );
+        assert_eq!(
+            check("use geo::SCALE;"),
+            Err("`SCALE` is private to the module `geo`".to_string())
+        );
+        assert_eq!(
+            check("P::nope();"),
+            Err("`P` has no associated function or constant `nope`".to_string())
+        );
+        assert_eq!(
+            check("let c = E::C;"),
+            Err("the enum `E` has no variant `C`".to_string())
+        );
+        assert_eq!(
+            check("util::double(2);"),
+            Err("`util` isn't declared".to_string())
+        );
+        assert_eq!(
+            check("let p = 1; p::x;"),
+            Err("`p` isn't a module or a type, so `p::x` can't be found in it".to_string())
+        );
+        assert_eq!(
+            check("Vec::create();"),
+            Err("`Vec::create` isn't in the prelude".to_string())
+        );
+        // types in `mod` blocks and `Self` resolve like the types they are
+        assert!(check(
+            "mod shapes { pub enum Shape { Circle(number), Empty } }
+            let s = shapes::Shape::Circle(1);"
+        )
+        .is_ok());
+        assert_eq!(
+            check(
+                "mod shapes { pub enum Shape { Circle(number), Empty } }
+                let s = shapes::Shape::Square;"
+            ),
+            Err("the enum `Shape` has no variant `Square`".to_string())
+        );
+        assert_eq!(
+            check("impl P { fn other() -> P { Self::nope() } }"),
+            Err("`P` has no associated function or constant `nope`".to_string())
+        );
+        // an impl block declares its type even without a struct
+        assert!(check("impl Circle { fn new() -> number { 1 } } Circle::new();").is_ok());
+        // the items of other files and of the host aren't known here
+        assert!(check("mod util; util::double(2); console::log(1);").is_ok());
+    }
+
+    #[test]
+    fn use_before_declaration() {
+        assert_eq!(
+            check("println(x); let x = 1;"),
+            Err("`x` is used before its declaration".to_string())
+        );
+        assert_eq!(
+            check("fn f() { let y = x; let x = 1; }"),
+            Err("`x` is used before its declaration".to_string())
+        );
+        assert_eq!(
+            check("let x = x + 1;"),
+            Err("`x` is used before its declaration".to_string())
+        );
+        assert_eq!(
+            check("mod m { pub const A = B; const B = 1; }"),
+            Err("`B` is used before its declaration".to_string())
+        );
+        // functions and closures run later
+        assert!(check("fn f() { x } let g = || x; const x = 1; println(f());").is_ok());
+        // the `y` of the function is in scope in all of it, in front of its declaration too
+        assert_eq!(
+            check("let y = 1; fn h() { println(y); let y = 2; }"),
+            Err("`y` is used before its declaration".to_string())
+        );
+    }
+
+    #[test]
+    fn self_in_methods() {
+        let self_error = Err("`self` is only available in methods taking `self`".to_string());
+        assert_eq!(check("fn free() { self }"), self_error);
+        assert_eq!(
+            check(
+                "struct Counter { count: number }
+                impl Counter {
+                    fn new() -> Counter { self }
+                }"
+            ),
+            self_error
+        );
+        // functions declared in a method don't get its `self`, closures do
+        assert_eq!(
+            check(
+                "struct Counter { count: number }
+                impl Counter {
+                    fn get(self) -> number {
+                        fn inner() -> number { self.count }
+                        inner()
+                    }
+                }"
+            ),
+            self_error
+        );
+        let globals = HashSet::from(["self".to_string()]);
+        let (_, (tokens, spans)) = Lexer::lex_tokens_with_spans(b"self").unwrap();
+        let (_, program) = Parser::parse(Tokens::with_spans(&tokens, &spans, 0)).unwrap();
+        assert!(check_names(&program, &globals, true).is_err());
+        assert!(check(
+            "struct Counter { count: number }
+            impl Counter {
+                fn get(self) -> number { self.count }
+                fn adder(self) -> number {
+                    let add = |by: number| self.count + by;
+                    add(1)
+                }
+            }
+            trait Named {
+                fn name(self) -> string { \"counter\" }
+            }"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn defer() {
+        assert!(check(
+            "fn read(path: string) -> string {
+                let file = open(path);
+                defer { close(file); }
+                for line in lines(file) {
+                    defer {
+                        for _ in [1] { break; }
+                    }
+                    if line == \"\" { break; }
+                }
+                contents(file)
+            }
+            fn open(path: string) -> number { 1 }
+            fn close(file: number) {}
+            fn lines(file: number) -> [string] { [] }
+            fn contents(file: number) -> string { \"\" }"
+        )
+        .is_ok());
+        assert_eq!(
+            check("defer { println(1); }"),
+            Err("`defer` can only be used in a block, not at the top level".to_string())
+        );
+        assert_eq!(
+            check("let a = defer { println(1); };"),
+            Err("`defer` can only be a statement of a block".to_string())
+        );
+        assert_eq!(
+            check("fn f() { defer { return; } }"),
+            Err("a `defer` can't return from the function around it".to_string())
+        );
+        assert_eq!(
+            check("for i in [1] { defer { continue; } }"),
+            Err("a `defer` can't leave the loop around it".to_string())
+        );
+        // closures in a `defer` can return from themselves
+        assert!(check("fn f() { defer { let g = |x: number| { return x; }; } }").is_ok());
+    }
+
+    #[test]
+    fn awaiting() {
+        assert!(check(
+            "async fn f() -> number { g().await } async fn g() -> number { 1 }
+            let h = async || f().await;"
+        )
+        .is_ok());
+        assert_eq!(
+            check("fn f() { g().await } async fn g() {}"),
+            Err("`.await` can only be used in `async` functions and closures".to_string())
+        );
+        // a closure in an `async` function isn't `async` itself
+        assert_eq!(
+            check("async fn f() { let h = || f().await; }"),
+            Err("`.await` can only be used in `async` functions and closures".to_string())
+        );
+    }
+
+    #[test]
+    fn shadowing_the_prelude() {
+        let shadowed = |input: &str| {
+            let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+            let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+            let mut names = shadowed_prelude(&program).into_iter().collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            shadowed("fn println(x: number) {} println(1);"),
+            vec!["println"]
+        );
+        assert_eq!(
+            shadowed(
+                "fn f(panic: number) { let checked_add = |eprintln: number| eprintln; }
+                for println in [1] {}"
+            ),
+            vec!["checked_add", "eprintln", "panic", "println"]
+        );
+        assert!(shadowed("println(1); let print = 1;").is_empty());
+    }
+}
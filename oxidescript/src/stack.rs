@@ -0,0 +1,66 @@
+use std::{panic, thread};
+
+/// The stack the compiler runs on. Parsing, checking and compiling recurse once per level of
+/// nesting, and debug builds need around 50KB per level of nested `if`s, so this is enough for a
+/// few thousand levels. Only the part that is used is ever allocated
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Runs `f` on a thread with a [`STACK_SIZE`] stack, so deeply nested programs don't overflow the
+/// smaller stack of the main thread or of test threads. Panics of `f` are passed on
+pub fn with_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("unable to spawn a thread for the compiler")
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use crate::{
+        lexer::{tokens::Tokens, Lexer},
+        optimizer::PassManager,
+        parser::{types::check_types, Parser},
+        resolve::check_names,
+    };
+
+    use super::*;
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 1000;
+        let sources = [
+            format!("let a = {}1{};", "[".repeat(depth), "]".repeat(depth)),
+            format!(
+                "let x = true; let a = {}1{};",
+                "if x { ".repeat(depth),
+                " } else { 0 }".repeat(depth)
+            ),
+            format!("let a = {}1{};", "(".repeat(depth), ")".repeat(depth)),
+        ];
+        for source in sources {
+            with_stack(|| {
+                let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).unwrap();
+                let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+                check_names(&program, &HashSet::new(), true).unwrap();
+                check_types(&program).unwrap();
+                let optimized = PassManager::new("", &[]).unwrap().run(
+                    program,
+                    &HashMap::new(),
+                    true,
+                    true,
+                    |_, _| {},
+                );
+                assert_eq!(
+                    optimized.into_iter().flatten().count(),
+                    source.matches(';').count()
+                );
+            });
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! The standard library, written in oxidescript in `std.os`. Like the prelude, its items are in
+//! scope without being declared: the ones a program uses are added in front of it. The DOM
+//! bindings in `dom.os` are only in scope for the browser
+
+use std::collections::HashSet;
+
+use crate::{
+    lexer::{tokens::Tokens, Lexer},
+    parser::{
+        ast::{Declaration, Expression, Identifier, ImplItem, Pattern, Program, Statement},
+        visit::{walk_blocks_mut, walk_program},
+        Parser,
+    },
+    resolve::declared_names,
+};
+
+const SOURCE: &str = include_str!("std.os");
+
+const DOM_SOURCE: &str = include_str!("dom.os");
+
+/// The names of the items of the standard library
+pub const STD: &[&str] = &[
+    "Option",
+    "Result",
+    "assert",
+    "Channel",
+    "Emitter",
+    "Signal",
+    "SIGNAL_READS",
+    "signal",
+    "computed",
+];
+
+/// The names of the DOM bindings, which `browser` adds to the standard library
+pub const DOM: &[&str] = &[
+    "DomEvent",
+    "DomMouseEvent",
+    "DomKeyboardEvent",
+    "Dom",
+    "HtmlElement",
+];
+
+/// The items of the standard library the program uses but doesn't declare itself, and the ones
+/// they use in turn, in the order the standard library declares them. An impl block belongs to
+/// the item it implements. With `browser`, the DOM bindings are items of the standard library too
+pub fn used_items(program: &Program, browser: bool) -> Vec<Statement> {
+    let declared = top_level_names(program);
+    let std = parse(SOURCE)
+        .into_iter()
+        .chain(browser.then(|| parse(DOM_SOURCE)).into_iter().flatten())
+        .collect::<Program>();
+    let is_std = |name: &str| STD.contains(&name) || (browser && DOM.contains(&name));
+    let mut used = HashSet::new();
+    let mut pending = used_names(program);
+    while let Some(name) = pending.pop() {
+        if !is_std(&name) || declared.contains(name.as_str()) {
+            continue;
+        }
+        if used.insert(name.clone()) {
+            let item = std
+                .iter()
+                .filter(|statement| item_name(statement) == Some(&name))
+                .cloned()
+                .collect();
+            pending.extend(used_names(&item));
+        }
+    }
+    std.into_iter()
+        .filter(|statement| item_name(statement).is_some_and(|name| used.contains(name)))
+        .collect()
+}
+
+/// An error for the first DOM binding the program uses without declaring it, which is only in scope
+/// for the browser
+pub fn check_browser_items(program: &Program) -> Result<(), String> {
+    let declared = top_level_names(program);
+    match used_names(program)
+        .into_iter()
+        .find(|name| DOM.contains(&name.as_str()) && !declared.contains(name.as_str()))
+    {
+        Some(name) => Err(format!(
+            "`{}` is only in scope when building with `--target browser`",
+            name
+        )),
+        None => Ok(()),
+    }
+}
+
+fn top_level_names(program: &Program) -> HashSet<&str> {
+    program
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::DeclarationStatement(declaration, _) => Some(declaration),
+            Statement::ExpressionStatement { .. } => None,
+        })
+        .flat_map(declared_names)
+        .map(|name| name.0.as_str())
+        .collect()
+}
+
+fn parse(source: &str) -> Program {
+    let (_, tokens) = Lexer::lex_tokens(source.as_bytes()).expect("the standard library lexes");
+    let (unparsed, program) =
+        Parser::parse(Tokens::new(&tokens)).expect("the standard library parses");
+    debug_assert!(unparsed.tokens.is_empty(), "the standard library parses");
+    program
+}
+
+/// The name of the item a statement of the standard library declares, or implements
+fn item_name(statement: &Statement) -> Option<&String> {
+    match statement {
+        Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), _) => {
+            Some(&decl.target.0)
+        }
+        Statement::DeclarationStatement(declaration, _) => {
+            declared_names(declaration).first().map(|name| &name.0)
+        }
+        Statement::ExpressionStatement { .. } => None,
+    }
+}
+
+/// The names the program refers to: names, the first segment of paths and of the variants that
+/// patterns match, and the names in the types of top level functions, methods, struct fields,
+/// closures, casts and annotated `let`s, as methods of a value of such a type are called through
+/// it
+fn used_names(program: &Program) -> Vec<String> {
+    let mut names = vec![];
+    for statement in program {
+        if let Statement::DeclarationStatement(declaration, _) = statement {
+            declaration_type_names(declaration, &mut names);
+        }
+    }
+    for statement in &mut program.clone() {
+        walk_blocks_mut(statement, &mut |block| {
+            for statement in &block.statements {
+                if let Statement::DeclarationStatement(declaration, _) = statement {
+                    declaration_type_names(declaration, &mut names);
+                }
+            }
+        });
+    }
+    walk_program(program, &mut |expression| match expression {
+        Expression::IdentifierExpression(name, _) => names.push(name.0.clone()),
+        Expression::PathExpression(path, _) => names.push(path.segments[0].0.clone()),
+        Expression::MatchExpression(expr, _) => {
+            for arm in &expr.arms {
+                pattern_names(&arm.pattern, &mut names);
+            }
+        }
+        Expression::ClosureExpression(expr, _) => {
+            for parameter in &expr.parameters {
+                type_names(&parameter.type_, &mut names);
+            }
+        }
+        Expression::CastExpression(expr, _) => type_names(&expr.type_, &mut names),
+        _ => {}
+    });
+    names
+}
+
+fn declaration_type_names(declaration: &Declaration, names: &mut Vec<String>) {
+    match declaration {
+        Declaration::FunctionDeclaration {
+            parameters,
+            return_type,
+            ..
+        } => {
+            for parameter in parameters {
+                type_names(&parameter.type_, names);
+            }
+            if let Some(return_type) = return_type {
+                type_names(return_type, names);
+            }
+        }
+        Declaration::ImplDeclaration(decl) => {
+            for item in &decl.items {
+                if let ImplItem::Method(method)
+                | ImplItem::Getter(method)
+                | ImplItem::Setter(method) = item
+                {
+                    for parameter in &method.parameters {
+                        type_names(&parameter.type_, names);
+                    }
+                    if let Some(return_type) = &method.return_type {
+                        type_names(return_type, names);
+                    }
+                }
+            }
+        }
+        Declaration::StructDeclaration(decl) => {
+            for field in &decl.fields {
+                type_names(&field.type_, names);
+            }
+        }
+        Declaration::ConstDeclaration(_, _, _, _, Some(type_))
+        | Declaration::LetDeclaration(_, _, _, Some(type_))
+        | Declaration::DestructuringDeclaration(_, _, _, Some(type_)) => type_names(type_, names),
+        _ => {}
+    }
+}
+
+/// The names in a type like `Option<[Result<number, string>]>`
+fn type_names(type_: &Identifier, names: &mut Vec<String>) {
+    names.extend(
+        type_
+            .0
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .filter(|name| !name.is_empty())
+            .map(str::to_string),
+    );
+}
+
+fn pattern_names(pattern: &Pattern, names: &mut Vec<String>) {
+    match pattern {
+        Pattern::Variant(path, patterns) => {
+            names.push(path[0].0.clone());
+            for pattern in patterns {
+                pattern_names(pattern, names);
+            }
+        }
+        Pattern::Or(patterns) | Pattern::Tuple(patterns) => {
+            for pattern in patterns {
+                pattern_names(pattern, names);
+            }
+        }
+        Pattern::Struct(name, fields) => {
+            names.push(name.0.clone());
+            for (_, pattern) in fields {
+                pattern_names(pattern, names);
+            }
+        }
+        Pattern::Wildcard | Pattern::Binding(_) | Pattern::Literal(_) | Pattern::Prefix(_, _) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    fn used(input: &str) -> Vec<String> {
+        used_in(input, false)
+    }
+
+    fn used_in(input: &str, browser: bool) -> Vec<String> {
+        used_items(&program(input), browser)
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), _) => {
+                    Some(format!("impl {}", decl.target.0))
+                }
+                statement => item_name(statement).cloned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn items_in_use() {
+        assert_eq!(used("assert(true, \"fine\");"), vec!["assert"]);
+        assert_eq!(
+            used("let a = Option::Some(1);"),
+            vec!["Option", "impl Option"]
+        );
+        // `Result::ok` returns an `Option`
+        assert_eq!(
+            used("fn f(r: Result<number, string>) { match r { Result::Ok(v) => v, _ => 0 } }"),
+            vec!["Option", "impl Option", "Result", "impl Result"]
+        );
+        // items the program declares itself aren't added
+        assert_eq!(
+            used("enum Option<T> { Some(T), None } let a = Option::None; assert(true, \"\");"),
+            vec!["assert"]
+        );
+        // methods of a parameter's type are called through the type
+        assert_eq!(
+            used("fn f(o: [Option<number>]) { o[0].unwrap() }"),
+            vec!["Option", "impl Option"]
+        );
+        // impls of built-in traits belong to the type too
+        assert_eq!(
+            used("let channel: Channel<number> = Channel::new();"),
+            vec!["Channel", "impl Channel", "impl Channel", "impl Channel"]
+        );
+        assert_eq!(
+            used("fn f(clicks: Emitter<number>) { clicks.emit(1); }"),
+            vec!["Emitter", "impl Emitter"]
+        );
+        // `computed` makes a `Signal` with `signal` and tracks reads in `SIGNAL_READS`
+        assert_eq!(
+            used("let doubled = computed(|| 2);"),
+            vec![
+                "SIGNAL_READS",
+                "Signal",
+                "impl Signal",
+                "signal",
+                "computed"
+            ]
+        );
+        assert!(used("let a = 1;").is_empty());
+    }
+
+    #[test]
+    fn dom_items() {
+        let input = "Dom::query(\"#title\").unwrap().on_click(|event| console.log(event.clientX));";
+        // the DOM bindings are only in scope for the browser
+        assert!(used(input).is_empty());
+        assert_eq!(
+            check_browser_items(&program(input)),
+            Err("`Dom` is only in scope when building with `--target browser`".to_string())
+        );
+        assert!(check_browser_items(&program("struct Dom {} let d = Dom {};")).is_ok());
+        assert_eq!(
+            used_in(input, true),
+            vec![
+                "Option",
+                "impl Option",
+                "DomEvent",
+                "DomMouseEvent",
+                "DomKeyboardEvent",
+                "Dom",
+                "impl Dom",
+                "HtmlElement",
+                "impl HtmlElement"
+            ]
+        );
+    }
+
+    #[test]
+    fn standard_library_checks() {
+        let std = parse(SOURCE).into_iter().chain(parse(DOM_SOURCE)).collect();
+        assert_eq!(
+            crate::parser::types::check_types(&std).map_err(|err| err.message),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Parses every `tests/grammar/*.os` snippet and compares the AST against the committed
+//! `*.ast` dump next to it. Run with `UPDATE_GOLDEN=1` to rewrite the dumps after an intended
+//! parser change, then review the diff of the `.ast` files. The dumps include the span of every
+//! node.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use oxidescript::{
+    lexer::{tokens::Tokens, Lexer},
+    parser::Parser,
+};
+
+const CORPUS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/grammar");
+
+fn dump_ast(source: &str) -> String {
+    let (unlexed, (tokens, spans)) = match Lexer::lex_tokens_with_spans(source.as_bytes()) {
+        Ok(lexed) => lexed,
+        Err(err) => return format!("lexer error: {:?}\n", err),
+    };
+    if !unlexed.is_empty() {
+        return format!("unlexed input: {:?}\n", String::from_utf8_lossy(unlexed));
+    }
+    match Parser::parse(Tokens::with_spans(&tokens, &spans, 0)) {
+        Ok((_, program)) => format!("{:#?}\n", program),
+        Err(err) => format!("parser error: {:?}\n", err),
+    }
+}
+
+/// Lists every line that differs between the golden dump and the actual one
+fn diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    let mut report = String::new();
+    for line in 0..expected.len().max(actual.len()) {
+        match (expected.get(line), actual.get(line)) {
+            (Some(expected), Some(actual)) if expected == actual => {}
+            (expected, actual) => {
+                report.push_str(&format!("  line {}:\n", line + 1));
+                if let Some(expected) = expected {
+                    report.push_str(&format!("    - {}\n", expected));
+                }
+                if let Some(actual) = actual {
+                    report.push_str(&format!("    + {}\n", actual));
+                }
+            }
+        }
+    }
+    report
+}
+
+fn corpus() -> Vec<PathBuf> {
+    let mut sources = fs::read_dir(CORPUS_DIR)
+        .expect("grammar corpus directory is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "os"))
+        .collect::<Vec<_>>();
+    sources.sort();
+    sources
+}
+
+fn check(source_path: &Path, update: bool) -> Option<String> {
+    let source = fs::read_to_string(source_path).unwrap();
+    let actual = dump_ast(&source);
+    let golden_path = source_path.with_extension("ast");
+    if update {
+        fs::write(&golden_path, actual).unwrap();
+        return None;
+    }
+    match fs::read_to_string(&golden_path) {
+        Ok(expected) if expected == actual => None,
+        Ok(expected) => Some(format!(
+            "{} does not match {}:\n{}",
+            source_path.display(),
+            golden_path.display(),
+            diff(&expected, &actual)
+        )),
+        Err(_) => Some(format!(
+            "{} has no golden dump, run with UPDATE_GOLDEN=1 to create it",
+            source_path.display()
+        )),
+    }
+}
+
+#[test]
+fn grammar_corpus() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let failures = corpus()
+        .iter()
+        .filter_map(|source_path| check(source_path, update))
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
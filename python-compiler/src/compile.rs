@@ -0,0 +1,1433 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use oxidescript::lexer::quote_string;
+use oxidescript::parser::ast::{
+    AssignmentExpr, Block, CallExpr, ClosureExpr, Declaration, Expression, ForExpr, Identifier,
+    IfExpr, ImplDecl, ImplItem, InfixOperator, Literal, Method, ModDecl, Number, OptionalExpr,
+    Parameter, Pattern, RangeExpr, Span, Statement, TraitDecl, UnaryOperator, WhileExpr,
+};
+use oxidescript::parser::enums::{declares_enum, lower_enums};
+use oxidescript::parser::optional::has_optional;
+use oxidescript::parser::types::{type_as_literal, union_members};
+
+use crate::PythonCompilerOptions;
+
+const INDENT: &str = "    ";
+
+/// Identifiers that are keywords in Python but not in oxidescript get a `_` appended
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+/// Builtins and imported names the compiled code and its helpers use, which declarations get a `_`
+/// appended for like keywords, so they don't hide them from that code. Names that aren't declared
+/// still refer to the builtin
+const PYTHON_BUILTINS: &[&str] = &[
+    "print",
+    "str",
+    "int",
+    "float",
+    "bool",
+    "list",
+    "len",
+    "range",
+    "abs",
+    "type",
+    "isinstance",
+    "hasattr",
+    "IndexError",
+    "RuntimeError",
+    "NotImplementedError",
+    "copy",
+    "importlib",
+    "sys",
+    "SimpleNamespace",
+];
+
+/// Modules imported at the top of the program when the compiled code uses them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Import {
+    Copy,
+    Importlib,
+    Sys,
+    SimpleNamespace,
+}
+
+impl Import {
+    pub fn statement(self) -> &'static str {
+        match self {
+            Import::Copy => "import copy\n",
+            Import::Importlib => "import importlib\n",
+            Import::Sys => "import sys\n",
+            Import::SimpleNamespace => "from types import SimpleNamespace\n",
+        }
+    }
+}
+
+/// Functions emitted in front of the program when the compiled code uses them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Helper {
+    /// `+` concatenates if either side is a string, like in javascript
+    Add,
+    /// `value[start..end]` with bounds checks, raises an `IndexError` instead of clamping the bounds
+    Slice,
+    /// `array::new_2d(width, height, init)`, a list of `height` rows of `width` items
+    New2d,
+    /// `a % b` with the sign of `a` like in javascript, Python's `%` has the sign of `b`
+    Remainder,
+    /// The result of a checked operation, or `None` if it is beyond `Number.MAX_SAFE_INTEGER`
+    Checked,
+    /// `checked_div(a, b)`, which is `None` instead of raising if `b` is zero. Has to come after
+    /// [`Helper::Checked`], which it calls
+    CheckedDiv,
+    /// `panic(message)`, raises a `RuntimeError` with the message. `raise` is a statement, so
+    /// it is wrapped in a function that can be called in expressions
+    Panic,
+}
+
+impl Helper {
+    pub fn definition(self) -> &'static str {
+        match self {
+            Helper::Add => {
+                r#"def _oxidescript_add(a, b):
+    if isinstance(a, str) or isinstance(b, str):
+        return str(a) + str(b)
+    return a + b
+"#
+            }
+            Helper::Slice => {
+                r#"def _oxidescript_slice(value, start, end):
+    stop = len(value) if end is None else end
+    if start < 0 or start > stop or stop > len(value):
+        raise IndexError(f"slice {start}..{stop} is out of bounds of length {len(value)}")
+    return value[start:stop]
+"#
+            }
+            Helper::New2d => {
+                r#"def _oxidescript_new_2d(width, height, init):
+    return [[init] * width for _ in range(height)]
+"#
+            }
+            Helper::Checked => {
+                r#"def _oxidescript_checked(result):
+    return result if abs(result) <= 9007199254740991 else None
+"#
+            }
+            Helper::CheckedDiv => {
+                r#"def _oxidescript_checked_div(a, b):
+    if b == 0:
+        return None
+    return _oxidescript_checked(a / b)
+"#
+            }
+            Helper::Panic => {
+                r#"def _oxidescript_panic(message):
+    raise RuntimeError(message)
+"#
+            }
+            Helper::Remainder => {
+                r#"def _oxidescript_remainder(a, b):
+    remainder = a % b
+    if remainder != 0 and (remainder < 0) != (a < 0):
+        return remainder - b
+    return remainder
+"#
+            }
+        }
+    }
+}
+
+/// What happens with the value of the last expression of a block
+#[derive(Clone, Copy)]
+enum Tail<'t> {
+    Return,
+    Discard,
+    /// Assigned to a temporary, for blocks that are used as values
+    Assign(&'t str),
+}
+
+/// A `def` being compiled
+struct Function {
+    /// The index of its scope in `scopes`
+    scope: usize,
+    /// Variables of enclosing functions it assigns, which have to be declared `nonlocal`
+    nonlocal: BTreeSet<String>,
+    /// Variables of the module it assigns, which have to be declared `global`
+    global: BTreeSet<String>,
+}
+
+pub struct PythonCompilerContext<'a> {
+    options: &'a PythonCompilerOptions,
+    pub imports: BTreeSet<Import>,
+    pub helpers: BTreeSet<Helper>,
+    /// The type `Self` refers to while compiling the items of an impl block
+    self_type: Option<Identifier>,
+    /// The traits compiled so far, whose default methods impls that don't override them share
+    traits: HashMap<String, TraitDecl>,
+    /// Python names of the declared variables, innermost block last. Python variables are scoped
+    /// to the whole function, so shadowing declarations get a fresh name instead
+    scopes: Vec<HashMap<String, String>>,
+    /// The `def`s around the code being compiled, innermost last
+    functions: Vec<Function>,
+    temporaries: usize,
+    /// Prelude functions the program declares itself, which are called instead of compiled
+    pub shadowed_prelude: HashSet<String>,
+}
+
+impl<'a> PythonCompilerContext<'a> {
+    pub fn new(options: &'a PythonCompilerOptions) -> Self {
+        PythonCompilerContext {
+            options,
+            imports: BTreeSet::new(),
+            helpers: BTreeSet::new(),
+            self_type: None,
+            traits: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            functions: vec![],
+            temporaries: 0,
+            shadowed_prelude: HashSet::new(),
+        }
+    }
+
+    /// Functions are hoisted in javascript, but `def` only binds the function once it runs, so
+    /// functions and impls are defined before the other statements. They are compiled last, so
+    /// their bodies see every variable the statements declare
+    pub fn statements(&mut self, statements: &[Statement], indent: usize, code: &mut String) {
+        if declares_enum(statements) {
+            return self.statements(&lower_enums(statements), indent, code);
+        }
+        let hoisted = statements
+            .iter()
+            .filter(|statement| is_hoisted(statement))
+            .collect::<Vec<_>>();
+        for statement in &hoisted {
+            if let Statement::DeclarationStatement(
+                Declaration::FunctionDeclaration { name, .. }
+                | Declaration::ImplDeclaration(ImplDecl { target: name, .. })
+                | Declaration::TraitDeclaration(TraitDecl { name, .. }),
+                _,
+            ) = statement
+            {
+                let python_name = mangle_declared(name);
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(name.0.clone(), python_name);
+            }
+        }
+
+        let mut rest = String::new();
+        for statement in statements.iter().filter(|statement| !is_hoisted(statement)) {
+            self.statement(statement, indent, &mut rest);
+        }
+
+        // impl blocks of the same type share one class, which is defined after the classes of
+        // the traits whose defaults it inherits
+        let mut impls: Vec<(&Identifier, Vec<&ImplDecl>)> = vec![];
+        for statement in &hoisted {
+            match statement {
+                Statement::DeclarationStatement(Declaration::ImplDeclaration(decl), _) => {
+                    match impls
+                        .iter_mut()
+                        .find(|(existing, _)| **existing == decl.target)
+                    {
+                        Some((_, existing)) => existing.push(decl),
+                        None => impls.push((&decl.target, vec![decl])),
+                    }
+                }
+                statement => self.statement(statement, indent, code),
+            }
+        }
+        for (target, decls) in impls {
+            self.class(target, &decls, indent, code);
+        }
+        code.push_str(&rest);
+    }
+
+    fn statement(&mut self, statement: &Statement, indent: usize, code: &mut String) {
+        match statement {
+            Statement::ExpressionStatement { expression, .. } => {
+                self.tail(expression, Tail::Discard, indent, code)
+            }
+            Statement::DeclarationStatement(declaration, _) => match declaration {
+                Declaration::LetDeclaration(ident, value, _, _)
+                | Declaration::ConstDeclaration(ident, value, _, _, _) => {
+                    let value = self.expression(value, indent, code);
+                    let name = self.declare(ident);
+                    code.push_str(&line(indent, &format!("{} = {}", name, value)));
+                }
+                // `a, (_, c) = value`, tuples are python tuples
+                // attributes can't be assignment targets, so a struct pattern assigns its
+                // fields one by one
+                Declaration::DestructuringDeclaration(pattern, value, _, _)
+                    if has_struct(pattern) =>
+                {
+                    let value = match value {
+                        Expression::IdentifierExpression(_, _) => value.clone(),
+                        value => {
+                            let value = self.expression(value, indent, code);
+                            let temporary = self.temporary();
+                            code.push_str(&line(indent, &format!("{} = {}", temporary, value)));
+                            Expression::IdentifierExpression(Identifier(temporary), Span::default())
+                        }
+                    };
+                    let (names, values): (Vec<_>, Vec<_>) = pattern
+                        .bindings(&value)
+                        .into_iter()
+                        .map(|(name, value)| (name, self.expression(&value, indent, code)))
+                        .unzip();
+                    if !names.is_empty() {
+                        let names = names
+                            .into_iter()
+                            .map(|name| self.declare(name))
+                            .collect::<Vec<_>>();
+                        code.push_str(&line(
+                            indent,
+                            &format!("{} = {}", names.join(", "), values.join(", ")),
+                        ));
+                    }
+                }
+                Declaration::DestructuringDeclaration(pattern, value, _, _) => {
+                    let value = self.expression(value, indent, code);
+                    let target = match pattern {
+                        Pattern::Tuple(items) => self.targets(items),
+                        pattern => self.target(pattern),
+                    };
+                    code.push_str(&line(indent, &format!("{} = {}", target, value)));
+                }
+                Declaration::FunctionDeclaration {
+                    name,
+                    parameters,
+                    body,
+                    ..
+                } => {
+                    let name = self.resolve(name);
+                    self.function(&name, false, parameters, body, indent, code);
+                }
+                // struct values are namespaces, only impl blocks produce code
+                // types and externs only exist for checking, externs are globals of the host
+                Declaration::StructDeclaration(_)
+                | Declaration::TypeDeclaration(_)
+                | Declaration::ExternDeclaration(_) => {}
+                // traits only produce a class for their default methods
+                Declaration::TraitDeclaration(decl) => {
+                    self.traits.insert(decl.name.0.clone(), decl.clone());
+                    if decl.has_defaults() {
+                        let defaults = ImplDecl {
+                            target: decl.name.clone(),
+                            trait_: None,
+                            items: decl.defaults().into_iter().map(ImplItem::Method).collect(),
+                        };
+                        self.class(&decl.name, &[&defaults], indent, code);
+                    }
+                }
+                Declaration::EnumDeclaration(_) => unreachable!("enums are lowered to structs"),
+                Declaration::ImplDeclaration(decl) => {
+                    self.class(&decl.target, &[decl], indent, code)
+                }
+                Declaration::ModDeclaration(decl) => self.module(decl, indent, code),
+                Declaration::ModFileDeclaration(name) => {
+                    let name = self.declare(name);
+                    code.push_str(&line(indent, &format!("import {}", name)));
+                }
+                Declaration::UseDeclaration(_) => {
+                    unreachable!("use declarations are lowered to constants")
+                }
+            },
+        }
+    }
+
+    /// The assignment target of a pattern that matches every value, `_` for the ones that bind
+    /// nothing
+    fn target(&mut self, pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Binding(name) => self.declare(name),
+            Pattern::Tuple(items) => format!("({})", self.targets(items)),
+            Pattern::Wildcard | Pattern::Or(_) => "_".to_string(),
+            Pattern::Literal(_)
+            | Pattern::Prefix(..)
+            | Pattern::Variant(..)
+            | Pattern::Struct(..) => {
+                unreachable!("destructurings only have patterns that match every value")
+            }
+        }
+    }
+
+    /// `a, b` or `a,` for a tuple of one item
+    fn targets(&mut self, items: &[Pattern]) -> String {
+        let targets = items
+            .iter()
+            .map(|item| self.target(item))
+            .collect::<Vec<_>>();
+        match &targets[..] {
+            [target] => format!("{},", target),
+            targets => targets.join(", "),
+        }
+    }
+
+    /// Binds the name in the innermost scope, renaming it if a visible variable already uses it
+    fn declare(&mut self, ident: &Identifier) -> String {
+        let base = mangle_declared(ident);
+        let taken = |name: &String| {
+            self.scopes
+                .iter()
+                .any(|scope| scope.values().any(|existing| existing == name))
+        };
+        let mut name = base.clone();
+        let mut suffix = 0;
+        while taken(&name) {
+            suffix += 1;
+            name = format!("{}_{}", base, suffix);
+        }
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(ident.0.clone(), name.clone());
+        name
+    }
+
+    /// Undeclared names are globals or builtins
+    fn resolve(&self, ident: &Identifier) -> String {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&ident.0).cloned())
+            .unwrap_or_else(|| mangle(ident))
+    }
+
+    /// Assigning a variable of an enclosing `def` or the module needs a declaration in the
+    /// current `def`, otherwise Python creates a new local
+    fn assign_variable(&mut self, ident: &Identifier) -> String {
+        let name = self.resolve(ident);
+        let declared_in = self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.contains_key(&ident.0));
+        let outermost = self.functions.first().map(|function| function.scope);
+        if let Some(function) = self.functions.last_mut() {
+            match declared_in {
+                Some(scope) if scope >= function.scope => {}
+                Some(scope) if outermost.is_some_and(|outermost| scope >= outermost) => {
+                    function.nonlocal.insert(name.clone());
+                }
+                _ => {
+                    function.global.insert(name.clone());
+                }
+            }
+        }
+        name
+    }
+
+    /// Generated names start with a single underscore, names with two are mangled inside classes
+    fn temporary(&mut self) -> String {
+        let name = format!("_value_{}", self.temporaries);
+        self.temporaries += 1;
+        name
+    }
+
+    fn function(
+        &mut self,
+        name: &str,
+        has_self: bool,
+        parameters: &[Parameter],
+        body: &Block,
+        indent: usize,
+        code: &mut String,
+    ) {
+        // parameters are local to the function, so they never need to be renamed
+        let scope = parameters
+            .iter()
+            .map(|parameter| (parameter.name.0.clone(), mangle_declared(&parameter.name)))
+            .collect::<HashMap<_, _>>();
+        let parameters = has_self
+            .then(|| "self".to_string())
+            .into_iter()
+            .chain(
+                parameters
+                    .iter()
+                    .map(|parameter| scope[&parameter.name.0].clone()),
+            )
+            .collect::<Vec<_>>();
+        code.push_str(&line(
+            indent,
+            &format!("def {}({}):", name, parameters.join(", ")),
+        ));
+        let body = self.def_body(scope, indent + 1, |ctx, code| {
+            ctx.body(body, Tail::Return, indent + 1, code)
+        });
+        code.push_str(&body);
+    }
+
+    /// A `lambda` if the body compiles to a single `return`, otherwise a `def` in front of the
+    /// current statement, since a `lambda` can't contain statements
+    fn closure(&mut self, expr: &ClosureExpr, indent: usize, code: &mut String) -> String {
+        let scope = expr
+            .parameters
+            .iter()
+            .map(|parameter| (parameter.name.0.clone(), mangle_declared(&parameter.name)))
+            .collect::<HashMap<_, _>>();
+        let parameters = expr
+            .parameters
+            .iter()
+            .map(|parameter| scope[&parameter.name.0].clone())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let block = expr.body_block();
+        let body = self.def_body(scope, indent + 1, |ctx, code| {
+            ctx.body(&block, Tail::Return, indent + 1, code)
+        });
+        let value = body
+            .strip_prefix(&format!("{}return ", INDENT.repeat(indent + 1)))
+            .and_then(|value| value.strip_suffix('\n'))
+            .filter(|value| !value.contains('\n'));
+        if let Some(value) = value {
+            if parameters.is_empty() {
+                return format!("(lambda: {})", value);
+            }
+            return format!("(lambda {}: {})", parameters, value);
+        }
+        let name = format!("_closure_{}", self.temporaries);
+        self.temporaries += 1;
+        code.push_str(&line(indent, &format!("def {}({}):", name, parameters)));
+        code.push_str(&body);
+        name
+    }
+
+    /// The body of a `def` with its `nonlocal` and `global` declarations, which are only known
+    /// after the body is compiled
+    fn def_body(
+        &mut self,
+        scope: HashMap<String, String>,
+        indent: usize,
+        compile: impl FnOnce(&mut Self, &mut String),
+    ) -> String {
+        self.scopes.push(scope);
+        self.functions.push(Function {
+            scope: self.scopes.len() - 1,
+            nonlocal: BTreeSet::new(),
+            global: BTreeSet::new(),
+        });
+        let mut body = String::new();
+        compile(self, &mut body);
+        let function = self.functions.pop().unwrap();
+        self.scopes.pop();
+        let mut code = String::new();
+        for (keyword, names) in [("global", function.global), ("nonlocal", function.nonlocal)] {
+            if !names.is_empty() {
+                let names = names.into_iter().collect::<Vec<_>>().join(", ");
+                code.push_str(&line(indent, &format!("{} {}", keyword, names)));
+            }
+        }
+        code.push_str(&body);
+        code
+    }
+
+    /// Methods are plain functions of the class, called like `Point.sum(point)` as in the object
+    /// mode of the javascript compiler. Inherited default methods are the functions of the class
+    /// of the trait, `method = Trait.method`. Constants are assigned after the class, so they can
+    /// refer to it
+    fn class(
+        &mut self,
+        target: &Identifier,
+        decls: &[&ImplDecl],
+        indent: usize,
+        code: &mut String,
+    ) {
+        let outer = self.self_type.replace(target.clone());
+        let class = self.resolve(target);
+        code.push_str(&line(indent, &format!("class {}:", class)));
+        let items = decls
+            .iter()
+            .flat_map(|decl| &decl.items)
+            .collect::<Vec<_>>();
+        let mut methods = 0;
+        for item in &items {
+            if let ImplItem::Method(method) | ImplItem::Getter(method) | ImplItem::Setter(method) =
+                item
+            {
+                let Method {
+                    name,
+                    has_self,
+                    parameters,
+                    body,
+                    ..
+                } = method;
+                self.function(&mangle(name), *has_self, parameters, body, indent + 1, code);
+                methods += 1;
+            }
+        }
+        for decl in decls {
+            let Some(trait_) = decl
+                .trait_
+                .as_ref()
+                .and_then(|trait_| self.traits.get(&trait_.0))
+            else {
+                continue;
+            };
+            let trait_class = self.resolve(&trait_.name);
+            for signature in trait_.inherited(decl) {
+                code.push_str(&line(
+                    indent + 1,
+                    &format!(
+                        "{} = {}.{}",
+                        mangle(&signature.name),
+                        trait_class,
+                        mangle(&signature.name)
+                    ),
+                ));
+                methods += 1;
+            }
+        }
+        if methods == 0 {
+            code.push_str(&line(indent + 1, "pass"));
+        }
+        for item in items {
+            if let ImplItem::Const(name, value) = item {
+                let value = self.expression(value, indent, code);
+                code.push_str(&line(
+                    indent,
+                    &format!("{}.{} = {}", class, mangle(name), value),
+                ));
+            }
+        }
+        self.self_type = outer;
+    }
+
+    /// `def _module_name(): ...items; return SimpleNamespace(...pub items)`, so private items
+    /// stay scoped to the module, like in the javascript compiler
+    fn module(&mut self, decl: &ModDecl, indent: usize, code: &mut String) {
+        let statements = decl
+            .items
+            .iter()
+            .map(|item| Statement::DeclarationStatement(item.declaration.clone(), Span::default()))
+            .collect::<Vec<_>>();
+        let name = self.declare(&decl.name);
+        code.push_str(&line(indent, &format!("def _module_{}():", name)));
+        let body = self.def_body(HashMap::new(), indent + 1, |ctx, code| {
+            ctx.statements(&statements, indent + 1, code);
+            let exports = decl
+                .items
+                .iter()
+                .filter(|item| item.is_pub)
+                .flat_map(|item| declared_names(&item.declaration))
+                .map(|ident| format!("{}={}", mangle(ident), ctx.resolve(ident)))
+                .collect::<Vec<_>>();
+            code.push_str(&line(
+                indent + 1,
+                &format!("return SimpleNamespace({})", exports.join(", ")),
+            ));
+        });
+        code.push_str(&body);
+        self.imports.insert(Import::SimpleNamespace);
+        code.push_str(&line(indent, &format!("{} = _module_{}()", name, name)));
+    }
+
+    /// Blocks don't introduce a scope in Python, so their statements are inlined
+    fn block(&mut self, block: &Block, tail: Tail, indent: usize, code: &mut String) {
+        self.scopes.push(HashMap::new());
+        match block.split_at_defer() {
+            // the rest of the block runs in a `try:` whose `finally:` runs the `defer`
+            Some(deferred) => {
+                self.statements(&deferred.before, indent, code);
+                code.push_str(&line(indent, "try:"));
+                self.body(&deferred.rest, tail, indent + 1, code);
+                code.push_str(&line(indent, "finally:"));
+                self.body(&deferred.cleanup, Tail::Discard, indent + 1, code);
+            }
+            None => {
+                self.statements(&block.statements, indent, code);
+                if let Some(return_value) = &block.return_value {
+                    self.tail(return_value, tail, indent, code);
+                }
+            }
+        }
+        self.scopes.pop();
+    }
+
+    /// The indented body of a `def`, `if` or `for`, which can't be empty
+    fn body(&mut self, block: &Block, tail: Tail, indent: usize, code: &mut String) {
+        let start = code.len();
+        self.block(block, tail, indent, code);
+        if code.len() == start {
+            code.push_str(&line(indent, "pass"));
+        }
+    }
+
+    fn tail(&mut self, expression: &Expression, tail: Tail, indent: usize, code: &mut String) {
+        match (expression, tail) {
+            (
+                Expression::ReturnExpression(_, _)
+                | Expression::BreakExpression(_)
+                | Expression::ContinueExpression(_),
+                _,
+            ) => self.jump(expression, indent, code),
+            (Expression::IfExpression(expr, _), tail) => {
+                self.if_statement(expr, tail, indent, code)
+            }
+            (Expression::BlockExpression(block, _), tail) => self.block(block, tail, indent, code),
+            (Expression::ForExpression(expr, _), Tail::Discard) => {
+                self.for_statement(expr, None, indent, code)
+            }
+            (Expression::WhileExpression(expr, _), Tail::Discard) => {
+                self.while_statement(expr, None, indent, code)
+            }
+            (Expression::MatchExpression(expr, _), tail) => {
+                self.tail(&expr.clone().into_if(), tail, indent, code)
+            }
+            (Expression::AssignmentExpression(expr, _), Tail::Discard) => {
+                self.assignment(expr, false, indent, code);
+            }
+            (expression, tail) => {
+                let value = self.expression(expression, indent, code);
+                code.push_str(&line(
+                    indent,
+                    &match tail {
+                        Tail::Return => format!("return {}", value),
+                        Tail::Discard => value,
+                        Tail::Assign(target) => format!("{} = {}", target, value),
+                    },
+                ));
+            }
+        }
+    }
+
+    /// `return`, `break` and `continue` are statements in Python as well. Used as values, they are
+    /// emitted in front of the current statement like lifted expressions
+    fn jump(&mut self, jump: &Expression, indent: usize, code: &mut String) {
+        let statement = match jump {
+            Expression::ReturnExpression(Some(value), _) => {
+                format!("return {}", self.expression(value, indent, code))
+            }
+            Expression::ReturnExpression(None, _) => "return".to_string(),
+            Expression::BreakExpression(_) => "break".to_string(),
+            Expression::ContinueExpression(_) => "continue".to_string(),
+            _ => unreachable!("only jumps are compiled to jump statements"),
+        };
+        code.push_str(&line(indent, &statement));
+    }
+
+    /// Conditions of `else if`s that need statements of their own are nested into an `else`, so
+    /// they are only evaluated if the previous conditions were false
+    fn if_statement(&mut self, expr: &IfExpr, tail: Tail, indent: usize, code: &mut String) {
+        let mut indent = indent;
+        let condition = self.expression(&expr.condition, indent, code);
+        code.push_str(&line(indent, &format!("if {}:", condition)));
+        self.body(&expr.then_block, tail, indent + 1, code);
+        for else_if in &expr.else_if_blocks {
+            let mut lifted = String::new();
+            let condition = self.expression(&else_if.condition, indent + 1, &mut lifted);
+            if lifted.is_empty() {
+                code.push_str(&line(indent, &format!("elif {}:", condition)));
+            } else {
+                code.push_str(&line(indent, "else:"));
+                code.push_str(&lifted);
+                indent += 1;
+                code.push_str(&line(indent, &format!("if {}:", condition)));
+            }
+            self.body(&else_if.then_block, tail, indent + 1, code);
+        }
+        if let Some(else_block) = &expr.else_block {
+            code.push_str(&line(indent, "else:"));
+            self.body(else_block, tail, indent + 1, code);
+        }
+    }
+
+    /// `range(start, end)`, which leaves out its end like `start..end`
+    fn range(&mut self, range: &RangeExpr, indent: usize, code: &mut String) -> String {
+        let start = self.expression(&range.start, indent, code);
+        let end = match (&*range.end, range.inclusive) {
+            (end, false) => self.expression(end, indent, code),
+            (
+                Expression::LiteralExpression(Literal::NumberLiteral(Number::I { value, .. }), _),
+                true,
+            ) => (value + 1).to_string(),
+            (end, true) => format!("{} + 1", self.expression(end, indent, code)),
+        };
+        format!("range({}, {})", start, end)
+    }
+
+    /// Appends the value of every iteration to `output` if given, like the javascript compiler
+    /// collects them into an array when a `for` is used as a value. Ranges are looped over
+    /// without making a list of them first
+    fn for_statement(
+        &mut self,
+        expr: &ForExpr,
+        output: Option<&str>,
+        indent: usize,
+        code: &mut String,
+    ) {
+        let iterable = match &*expr.rhs {
+            Expression::RangeExpression(range, _) => self.range(range, indent, code),
+            rhs => self.expression(rhs, indent, code),
+        };
+        self.scopes.push(HashMap::new());
+        let item = self.declare(&expr.lhs);
+        code.push_str(&line(indent, &format!("for {} in {}:", item, iterable)));
+        let start = code.len();
+        let body = loop_block(&expr.body);
+        self.statements(&body.statements, indent + 1, code);
+        match (&body.return_value, output) {
+            (Some(value), Some(output)) => {
+                let value = self.expression(value, indent + 1, code);
+                code.push_str(&line(indent + 1, &format!("{}.append({})", output, value)));
+            }
+            (Some(value), None) => self.tail(value, Tail::Discard, indent + 1, code),
+            (None, _) => {}
+        }
+        if code.len() == start {
+            code.push_str(&line(indent + 1, "pass"));
+        }
+        self.scopes.pop();
+    }
+
+    /// Like [`Self::for_statement`]. A condition that needs statements of its own is evaluated at
+    /// the start of every iteration, in a `while True:` that breaks when it is false
+    fn while_statement(
+        &mut self,
+        expr: &WhileExpr,
+        output: Option<&str>,
+        indent: usize,
+        code: &mut String,
+    ) {
+        let mut lifted = String::new();
+        let condition = self.expression(&expr.condition, indent + 1, &mut lifted);
+        if lifted.is_empty() {
+            code.push_str(&line(indent, &format!("while {}:", condition)));
+        } else {
+            code.push_str(&line(indent, "while True:"));
+            code.push_str(&lifted);
+            code.push_str(&line(indent + 1, &format!("if not {}:", condition)));
+            code.push_str(&line(indent + 2, "break"));
+        }
+        let start = code.len();
+        self.scopes.push(HashMap::new());
+        let body = loop_block(&expr.body);
+        self.statements(&body.statements, indent + 1, code);
+        match (&body.return_value, output) {
+            (Some(value), Some(output)) => {
+                let value = self.expression(value, indent + 1, code);
+                code.push_str(&line(indent + 1, &format!("{}.append({})", output, value)));
+            }
+            (Some(value), None) => self.tail(value, Tail::Discard, indent + 1, code),
+            (None, _) => {}
+        }
+        if code.len() == start && lifted.is_empty() {
+            code.push_str(&line(indent + 1, "pass"));
+        }
+        self.scopes.pop();
+    }
+
+    /// Returns a Python expression. `if`, `for`, `while` and blocks have no expression form, so they are
+    /// lifted into statements in front of the current statement that assign a temporary
+    fn expression(&mut self, expression: &Expression, indent: usize, code: &mut String) -> String {
+        if has_optional(expression) {
+            return self.optional_chain(expression, indent, code);
+        }
+        match expression {
+            Expression::IdentifierExpression(ident, _) => self.resolve(ident),
+            Expression::LiteralExpression(literal, _) => match literal {
+                Literal::StringLiteral(value) => quote_string(value),
+                Literal::NumberLiteral(Number::F(value)) => value.clone(),
+                Literal::NumberLiteral(Number::I { value, .. }) => value.to_string(),
+                Literal::BooleanLiteral(true) => "True".to_string(),
+                Literal::BooleanLiteral(false) => "False".to_string(),
+            },
+            Expression::UnaryExpression(expr, _) => {
+                let rhs = self.expression(&expr.rhs, indent, code);
+                match expr.op {
+                    UnaryOperator::LogicalNot => format!("(not {})", rhs),
+                    UnaryOperator::BitwiseNot => format!("~{}", rhs),
+                    UnaryOperator::Minus => format!("-{}", rhs),
+                    UnaryOperator::Plus => format!("+{}", rhs),
+                }
+            }
+            Expression::InfixExpression(expr, _)
+                if matches!(
+                    expr.op,
+                    InfixOperator::LogicalAnd | InfixOperator::LogicalOr
+                ) =>
+            {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                let mut lifted = String::new();
+                let rhs = self.expression(&expr.rhs, indent + 1, &mut lifted);
+                let op = if expr.op == InfixOperator::LogicalAnd {
+                    "and"
+                } else {
+                    "or"
+                };
+                if lifted.is_empty() {
+                    return format!("({} {} {})", lhs, op, rhs);
+                }
+                // the lifted statements of the right side only run if it is evaluated
+                let result = self.temporary();
+                code.push_str(&line(indent, &format!("{} = {}", result, lhs)));
+                let condition = if expr.op == InfixOperator::LogicalAnd {
+                    result.clone()
+                } else {
+                    format!("not {}", result)
+                };
+                code.push_str(&line(indent, &format!("if {}:", condition)));
+                code.push_str(&lifted);
+                code.push_str(&line(indent + 1, &format!("{} = {}", result, rhs)));
+                result
+            }
+            // the right side only runs if the left side is `None`
+            Expression::InfixExpression(expr, _) if expr.op == InfixOperator::NullishCoalesce => {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                let mut lifted = String::new();
+                let rhs = self.expression(&expr.rhs, indent + 1, &mut lifted);
+                if lifted.is_empty() && matches!(*expr.lhs, Expression::IdentifierExpression(_, _))
+                {
+                    return format!("({} if {} is None else {})", rhs, lhs, lhs);
+                }
+                let result = self.temporary();
+                code.push_str(&line(indent, &format!("{} = {}", result, lhs)));
+                code.push_str(&line(indent, &format!("if {} is None:", result)));
+                code.push_str(&lifted);
+                code.push_str(&line(indent + 1, &format!("{} = {}", result, rhs)));
+                result
+            }
+            Expression::AssignmentExpression(expr, _) => self.assignment(expr, true, indent, code),
+            Expression::InfixExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                let rhs = self.expression(&expr.rhs, indent, code);
+                let op = match expr.op {
+                    InfixOperator::Plus
+                        if (is_number(&expr.lhs) && is_number(&expr.rhs))
+                            || (is_string(&expr.lhs) && is_string(&expr.rhs)) =>
+                    {
+                        "+"
+                    }
+                    InfixOperator::Plus => {
+                        self.helpers.insert(Helper::Add);
+                        return format!("_oxidescript_add({}, {})", lhs, rhs);
+                    }
+                    InfixOperator::Minus => "-",
+                    InfixOperator::Multiply => "*",
+                    InfixOperator::Divide => "/",
+                    InfixOperator::Modulo => {
+                        self.helpers.insert(Helper::Remainder);
+                        return format!("_oxidescript_remainder({}, {})", lhs, rhs);
+                    }
+                    InfixOperator::Equal => "==",
+                    InfixOperator::NotEqual => "!=",
+                    InfixOperator::GreaterThan => ">",
+                    InfixOperator::LessThan => "<",
+                    InfixOperator::GreaterThanEqual => ">=",
+                    InfixOperator::LessThanEqual => "<=",
+                    InfixOperator::BitwiseOr => "|",
+                    InfixOperator::BitwiseXor => "^",
+                    InfixOperator::BitwiseAnd => "&",
+                    InfixOperator::BitwiseLeftShift => "<<",
+                    InfixOperator::BitwiseRightShift => ">>",
+                    InfixOperator::LogicalAnd
+                    | InfixOperator::LogicalOr
+                    | InfixOperator::NullishCoalesce => {
+                        unreachable!("logical operators are handled above")
+                    }
+                };
+                format!("({} {} {})", lhs, op, rhs)
+            }
+            Expression::ArrayExpression(elements, _) => {
+                format!("[{}]", self.expressions(elements, indent, code).join(", "))
+            }
+            Expression::TupleExpression(elements, _) => {
+                match &self.expressions(elements, indent, code)[..] {
+                    [element] => format!("({},)", element),
+                    elements => format!("({})", elements.join(", ")),
+                }
+            }
+            Expression::TupleIndexExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                format!("{}[{}]", lhs, expr.index)
+            }
+            Expression::IfExpression(expr, _) => {
+                let result = self.temporary();
+                if expr.else_block.is_none() {
+                    code.push_str(&line(indent, &format!("{} = None", result)));
+                }
+                self.if_statement(expr, Tail::Assign(&result), indent, code);
+                result
+            }
+            Expression::BlockExpression(block, _) if block.return_value.is_none() => {
+                self.block(block, Tail::Discard, indent, code);
+                "None".to_string()
+            }
+            Expression::BlockExpression(block, _) => {
+                let result = self.temporary();
+                self.block(block, Tail::Assign(&result), indent, code);
+                result
+            }
+            Expression::ForExpression(expr, _) => {
+                let result = self.temporary();
+                code.push_str(&line(indent, &format!("{} = []", result)));
+                self.for_statement(expr, Some(&result), indent, code);
+                result
+            }
+            Expression::WhileExpression(expr, _) => {
+                let result = self.temporary();
+                code.push_str(&line(indent, &format!("{} = []", result)));
+                self.while_statement(expr, Some(&result), indent, code);
+                result
+            }
+            Expression::MatchExpression(expr, _) => {
+                self.expression(&expr.clone().into_if(), indent, code)
+            }
+            Expression::CallExpression(call, _) => self.call(call, indent, code),
+            Expression::TaggedTemplateExpression(expr, _) => {
+                self.expression(&expr.clone().into_call(), indent, code)
+            }
+            Expression::TemplateExpression(expr, _) => {
+                self.expression(&expr.clone().into_concatenation(), indent, code)
+            }
+            Expression::IndexExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                let index = self.expression(&expr.index, indent, code);
+                format!("{}[{}]", lhs, index)
+            }
+            Expression::ClosureExpression(expr, _) => self.closure(expr, indent, code),
+            Expression::ReturnExpression(_, _)
+            | Expression::BreakExpression(_)
+            | Expression::ContinueExpression(_) => {
+                self.jump(expression, indent, code);
+                "None".to_string()
+            }
+            Expression::DeferExpression(_, _) => {
+                unreachable!("`defer` is only a statement of a block, which blocks compile")
+            }
+            Expression::WithExpression(_, _) => {
+                unreachable!("`with` is lowered to a block with a `defer`")
+            }
+            Expression::AwaitExpression(_, _) => {
+                code.push_str(&line(
+                    indent,
+                    "raise NotImplementedError(\".await is not supported by the python target\")",
+                ));
+                "None".to_string()
+            }
+            Expression::RangeExpression(expr, _) => {
+                format!("list({})", self.range(expr, indent, code))
+            }
+            Expression::SliceExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                let mut bound = |bound: &Option<Box<Expression>>| match bound {
+                    Some(bound) => self.expression(bound, indent, code),
+                    None => String::new(),
+                };
+                let (start, end) = (bound(&expr.start), bound(&expr.end));
+                if !self.options.bounds_checks || (start.is_empty() && end.is_empty()) {
+                    return format!("{}[{}:{}]", lhs, start, end);
+                }
+                self.helpers.insert(Helper::Slice);
+                format!(
+                    "_oxidescript_slice({}, {}, {})",
+                    lhs,
+                    if start.is_empty() { "0" } else { &start },
+                    if end.is_empty() { "None" } else { &end }
+                )
+            }
+            Expression::MemberAccessExpression(expr, _) if expr.ident.0 == "length" => {
+                format!("len({})", self.expression(&expr.lhs, indent, code))
+            }
+            Expression::MemberAccessExpression(expr, _) => {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                format!("{}.{}", lhs, mangle(&expr.ident))
+            }
+            Expression::OptionalExpression(_, _) => {
+                unreachable!("optional chains are handled above")
+            }
+            // casts only change the type
+            Expression::CastExpression(expr, _) => self.expression(&expr.value, indent, code),
+            Expression::IsExpression(expr, _) => {
+                let mut value = self.expression(&expr.value, indent, code);
+                if !matches!(*expr.value, Expression::IdentifierExpression(_, _)) {
+                    let result = self.temporary();
+                    code.push_str(&line(indent, &format!("{} = {}", result, value)));
+                    value = result;
+                }
+                type_check(&value, &expr.type_.0)
+            }
+            Expression::StructExpression(expr, _) => {
+                self.imports.insert(Import::SimpleNamespace);
+                let fields = expr
+                    .fields
+                    .iter()
+                    .map(|(field, value)| {
+                        format!("{}={}", mangle(field), self.expression(value, indent, code))
+                    })
+                    .collect::<Vec<_>>();
+                format!("SimpleNamespace({})", fields.join(", "))
+            }
+            Expression::PathExpression(expr, _) => {
+                let first = match &self.self_type {
+                    Some(self_type) if expr.segments[0].0 == "Self" => self.resolve(self_type),
+                    _ => self.resolve(&expr.segments[0]),
+                };
+                std::iter::once(first)
+                    .chain(expr.segments[1..].iter().map(mangle))
+                    .collect::<Vec<_>>()
+                    .join(".")
+            }
+            // loaded synchronously through the module name instead of evaluating to a promise
+            Expression::ImportExpression(expr, _) => {
+                self.imports.insert(Import::Importlib);
+                format!(
+                    "importlib.import_module({})",
+                    quote_string(&expr.path.trim_start_matches("./").replace('/', "."))
+                )
+            }
+        }
+    }
+
+    /// `a?.b.c` is `None` if `a` is, so the rest of the chain is only evaluated if it isn't
+    fn optional_chain(
+        &mut self,
+        expression: &Expression,
+        indent: usize,
+        code: &mut String,
+    ) -> String {
+        let result = self.temporary();
+        let (receiver, rest) = OptionalExpr::split_chain(
+            expression,
+            &Expression::IdentifierExpression(Identifier(result.clone()), Span::default()),
+        )
+        .expect("the chain has a `?.` or `?[`");
+        let receiver = self.expression(&receiver, indent, code);
+        code.push_str(&line(indent, &format!("{} = {}", result, receiver)));
+        code.push_str(&line(indent, &format!("if {} is not None:", result)));
+        let rest = self.expression(&rest, indent + 1, code);
+        code.push_str(&line(indent + 1, &format!("{} = {}", result, rest)));
+        result
+    }
+
+    /// Assignments are statements in Python, so they are emitted in front of the current
+    /// statement and the target is read again where the value is used. Variables whose value is
+    /// used are assigned with `:=` instead, which keeps them in evaluation order
+    fn assignment(
+        &mut self,
+        expr: &AssignmentExpr,
+        result: bool,
+        indent: usize,
+        code: &mut String,
+    ) -> String {
+        let (target, value) = expr.clone().spelled_out(|value| {
+            let value = self.expression(&value, indent, code);
+            let temporary = self.temporary();
+            code.push_str(&line(indent, &format!("{} = {}", temporary, value)));
+            Expression::IdentifierExpression(Identifier(temporary), Span::default())
+        });
+        let value = self.expression(&value, indent, code);
+        let target = match &target {
+            Expression::IdentifierExpression(ident, _) => {
+                let target = self.assign_variable(ident);
+                if result {
+                    return format!("({} := {})", target, value);
+                }
+                target
+            }
+            target => self.expression(target, indent, code),
+        };
+        code.push_str(&line(indent, &format!("{} = {}", target, value)));
+        target
+    }
+
+    fn expressions(
+        &mut self,
+        expressions: &[Expression],
+        indent: usize,
+        code: &mut String,
+    ) -> Vec<String> {
+        expressions
+            .iter()
+            .map(|expression| self.expression(expression, indent, code))
+            .collect()
+    }
+
+    /// The same calls as the intrinsics of the javascript compiler are lowered to Python builtins
+    fn call(&mut self, call: &CallExpr, indent: usize, code: &mut String) -> String {
+        let prelude = self.options.prelude
+            && !matches!(&*call.lhs, Expression::IdentifierExpression(ident, _)
+                if self.shadowed_prelude.contains(&ident.0));
+        match (&*call.lhs, call.arguments.as_slice()) {
+            (Expression::IdentifierExpression(ident, _), [value]) if ident.0 == "clone" => {
+                self.clone_value(value, indent, code)
+            }
+            (Expression::MemberAccessExpression(expr, _), []) if expr.ident.0 == "clone" => {
+                self.clone_value(&expr.lhs, indent, code)
+            }
+            // what string prefix patterns of `match` are lowered to
+            (Expression::MemberAccessExpression(expr, _), [prefix])
+                if expr.ident.0 == "startsWith" =>
+            {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                let prefix = self.expression(prefix, indent, code);
+                format!("{}.startswith({})", lhs, prefix)
+            }
+            (Expression::MemberAccessExpression(expr, _), [start]) if expr.ident.0 == "slice" => {
+                let lhs = self.expression(&expr.lhs, indent, code);
+                let start = self.expression(start, indent, code);
+                format!("{}[{}:]", lhs, start)
+            }
+            (Expression::IdentifierExpression(ident, _), arguments)
+                if prelude && ident.0 == "println" =>
+            {
+                format!(
+                    "print({})",
+                    self.expressions(arguments, indent, code).join(", ")
+                )
+            }
+            (Expression::IdentifierExpression(ident, _), arguments)
+                if prelude && ident.0 == "eprintln" =>
+            {
+                self.imports.insert(Import::Sys);
+                let mut arguments = self.expressions(arguments, indent, code);
+                arguments.push("file=sys.stderr".to_string());
+                format!("print({})", arguments.join(", "))
+            }
+            (Expression::IdentifierExpression(ident, _), [message])
+                if prelude && ident.0 == "panic" =>
+            {
+                self.helpers.insert(Helper::Panic);
+                format!(
+                    "_oxidescript_panic({})",
+                    self.expression(message, indent, code)
+                )
+            }
+            (Expression::IdentifierExpression(ident, _), [lhs, rhs])
+                if prelude && InfixOperator::checked(&ident.0).is_some() =>
+            {
+                let lhs = self.expression(lhs, indent, code);
+                let rhs = self.expression(rhs, indent, code);
+                self.helpers.insert(Helper::Checked);
+                match InfixOperator::checked(&ident.0).unwrap() {
+                    InfixOperator::Plus => format!("_oxidescript_checked({} + {})", lhs, rhs),
+                    InfixOperator::Minus => format!("_oxidescript_checked({} - {})", lhs, rhs),
+                    InfixOperator::Multiply => format!("_oxidescript_checked({} * {})", lhs, rhs),
+                    _ => {
+                        self.helpers.insert(Helper::CheckedDiv);
+                        format!("_oxidescript_checked_div({}, {})", lhs, rhs)
+                    }
+                }
+            }
+            (Expression::PathExpression(path, _), arguments)
+                if prelude && path.segments.len() == 2 =>
+            {
+                match (
+                    path.segments[0].0.as_str(),
+                    path.segments[1].0.as_str(),
+                    arguments,
+                ) {
+                    ("Vec", "new", []) => "[]".to_string(),
+                    ("Vec", "from", [iterable]) => {
+                        format!("list({})", self.expression(iterable, indent, code))
+                    }
+                    ("array", "new_2d", [_, _, _]) => {
+                        self.helpers.insert(Helper::New2d);
+                        format!(
+                            "_oxidescript_new_2d({})",
+                            self.expressions(arguments, indent, code).join(", ")
+                        )
+                    }
+                    ("worker", "spawn", [_]) => {
+                        code.push_str(&line(
+                            indent,
+                            "raise NotImplementedError(\"worker::spawn is not supported by the python target\")",
+                        ));
+                        "None".to_string()
+                    }
+                    ("promise", "new", [_]) => {
+                        code.push_str(&line(
+                            indent,
+                            "raise NotImplementedError(\"promise::new is not supported by the python target\")",
+                        ));
+                        "None".to_string()
+                    }
+                    ("task", name @ ("spawn" | "join_all" | "race"), [_]) => {
+                        code.push_str(&line(
+                            indent,
+                            &format!(
+                                "raise NotImplementedError(\"task::{} is not supported by the python target\")",
+                                name
+                            ),
+                        ));
+                        "None".to_string()
+                    }
+                    _ => self.regular_call(call, indent, code),
+                }
+            }
+            _ => self.regular_call(call, indent, code),
+        }
+    }
+
+    fn regular_call(&mut self, call: &CallExpr, indent: usize, code: &mut String) -> String {
+        let lhs = self.expression(&call.lhs, indent, code);
+        let arguments = self.expressions(&call.arguments, indent, code);
+        format!("{}({})", lhs, arguments.join(", "))
+    }
+
+    fn clone_value(&mut self, value: &Expression, indent: usize, code: &mut String) -> String {
+        self.imports.insert(Import::Copy);
+        format!("copy.deepcopy({})", self.expression(value, indent, code))
+    }
+}
+
+/// The body of a loop, whose statements and value are compiled on their own. A body with a
+/// `defer` becomes a block as its value, as the `defer` needs the whole body
+fn loop_block(body: &Block) -> Cow<'_, Block> {
+    match body.split_at_defer() {
+        Some(_) => Cow::Owned(Block {
+            statements: vec![],
+            return_value: Some(Expression::BlockExpression(
+                Box::new(body.clone()),
+                body.span,
+            )),
+            span: body.span,
+        }),
+        None => Cow::Borrowed(body),
+    }
+}
+
+fn is_hoisted(statement: &Statement) -> bool {
+    match statement {
+        Statement::DeclarationStatement(Declaration::TraitDeclaration(decl), _) => {
+            decl.has_defaults()
+        }
+        statement => matches!(
+            statement,
+            Statement::DeclarationStatement(
+                Declaration::FunctionDeclaration { .. } | Declaration::ImplDeclaration(_),
+                _
+            )
+        ),
+    }
+}
+
+/// The bindings a declaration introduces in its module, structs and impl blocks don't introduce
+/// one and destructurings can introduce several. An enum is the class of its constructors and a
+/// trait with default methods the class of the defaults
+fn declared_names(declaration: &Declaration) -> Vec<&Identifier> {
+    match declaration {
+        Declaration::TraitDeclaration(decl) => decl
+            .has_defaults()
+            .then_some(&decl.name)
+            .into_iter()
+            .collect(),
+        Declaration::ConstDeclaration(name, ..) | Declaration::LetDeclaration(name, _, _, _) => {
+            vec![name]
+        }
+        Declaration::DestructuringDeclaration(pattern, value, _, _) => pattern
+            .bindings(value)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+        Declaration::FunctionDeclaration { name, .. } => vec![name],
+        Declaration::ModDeclaration(decl) => vec![&decl.name],
+        Declaration::ModFileDeclaration(name) => vec![name],
+        Declaration::EnumDeclaration(decl) => vec![&decl.name],
+        Declaration::StructDeclaration(_)
+        | Declaration::ImplDeclaration(_)
+        | Declaration::UseDeclaration(_)
+        | Declaration::TypeDeclaration(_)
+        | Declaration::ExternDeclaration(_) => vec![],
+    }
+}
+
+/// Whether a destructuring reads a field of a struct
+fn has_struct(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Struct(..) => true,
+        Pattern::Tuple(items) => items.iter().any(has_struct),
+        _ => false,
+    }
+}
+
+fn mangle(ident: &Identifier) -> String {
+    if PYTHON_KEYWORDS.contains(&ident.0.as_str()) {
+        format!("{}_", ident.0)
+    } else {
+        ident.0.clone()
+    }
+}
+
+/// Like [`mangle`], for names the program declares, which also mustn't hide [`PYTHON_BUILTINS`]
+fn mangle_declared(ident: &Identifier) -> String {
+    if PYTHON_BUILTINS.contains(&ident.0.as_str()) {
+        format!("{}_", ident.0)
+    } else {
+        mangle(ident)
+    }
+}
+
+/// Whether the expression is known to be a number without knowing the types of variables, so `+`
+/// can be emitted without the helper
+fn is_number(expression: &Expression) -> bool {
+    match expression {
+        Expression::LiteralExpression(Literal::NumberLiteral(_), _) => true,
+        Expression::UnaryExpression(expr, _) => {
+            matches!(expr.op, UnaryOperator::Minus | UnaryOperator::Plus)
+        }
+        Expression::InfixExpression(expr, _) => match expr.op {
+            InfixOperator::Plus => is_number(&expr.lhs) && is_number(&expr.rhs),
+            InfixOperator::Minus
+            | InfixOperator::Multiply
+            | InfixOperator::Divide
+            | InfixOperator::Modulo => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_string(expression: &Expression) -> bool {
+    match expression {
+        Expression::LiteralExpression(Literal::StringLiteral(_), _) => true,
+        Expression::InfixExpression(expr, _) => {
+            expr.op == InfixOperator::Plus && (is_string(&expr.lhs) || is_string(&expr.rhs))
+        }
+        _ => false,
+    }
+}
+
+/// The condition of `value is type`. `bool` is a subclass of `int`, so numbers check for the exact
+/// types, literals are compared with `==`, and every other type that isn't a primitive or an array
+/// only checks for an object
+fn type_check(value: &str, type_: &str) -> String {
+    let members = union_members(type_);
+    if members.len() > 1 {
+        let mut checks = members
+            .into_iter()
+            .map(|member| type_check(value, member))
+            .collect::<Vec<_>>();
+        // `null` and `undefined` are checked the same way
+        checks.dedup();
+        if let [check] = &checks[..] {
+            return check.clone();
+        }
+        return format!("({})", checks.join(" or "));
+    }
+    match type_ {
+        "number" => format!("(type({}) in (int, float))", value),
+        "string" => format!("isinstance({}, str)", value),
+        "boolean" | "bool" => format!("isinstance({}, bool)", value),
+        "null" | "undefined" => format!("({} is None)", value),
+        literal if type_as_literal(literal).is_some() => {
+            let literal = match type_as_literal(literal).unwrap() {
+                Literal::StringLiteral(string) => quote_string(&string),
+                Literal::NumberLiteral(number) => number.to_string(),
+                Literal::BooleanLiteral(true) => "True".to_string(),
+                Literal::BooleanLiteral(false) => "False".to_string(),
+            };
+            format!("({} == {})", value, literal)
+        }
+        array if array.starts_with('[') => format!("isinstance({}, list)", value),
+        _ => format!("hasattr({}, \"__dict__\")", value),
+    }
+}
+
+fn line(indent: usize, code: &str) -> String {
+    format!("{}{}\n", INDENT.repeat(indent), code)
+}
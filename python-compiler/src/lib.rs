@@ -0,0 +1,1121 @@
+use oxidescript::{
+    compiler::Compiler,
+    parser::{
+        ast::Program,
+        dispose::lower_with,
+        enums::lower_discriminants,
+        module::lower_uses,
+        types::{lower_index_operators, lower_iterators, lower_method_calls, lower_type_aliases},
+    },
+    resolve::shadowed_prelude,
+};
+
+mod compile;
+
+use compile::PythonCompilerContext;
+
+#[derive(Clone, Debug)]
+pub struct PythonCompilerOptions {
+    /// Whether prelude items like `println` and `Vec::new` are in scope without being declared
+    pub prelude: bool,
+    /// Whether slices like `items[1..3]` raise an `IndexError` if a bound is outside of the list or
+    /// string, instead of clamping it. On with the `debug` flag of `oxide.toml`
+    pub bounds_checks: bool,
+}
+
+impl Default for PythonCompilerOptions {
+    fn default() -> Self {
+        PythonCompilerOptions {
+            prelude: true,
+            bounds_checks: false,
+        }
+    }
+}
+
+/// Names of the Python builtins that programs can use without declaring them
+pub const GLOBALS: &[&str] = &[
+    "print",
+    "input",
+    "open",
+    "len",
+    "range",
+    "enumerate",
+    "zip",
+    "map",
+    "filter",
+    "sorted",
+    "reversed",
+    "sum",
+    "min",
+    "max",
+    "abs",
+    "round",
+    "any",
+    "all",
+    "iter",
+    "next",
+    "isinstance",
+    "repr",
+    "str",
+    "int",
+    "float",
+    "bool",
+    "list",
+    "dict",
+    "set",
+    "tuple",
+    "Exception",
+    "ValueError",
+    "TypeError",
+    "KeyError",
+    "IndexError",
+];
+
+/// Compiles to Python 3 source, for scripts that should run on a Python runtime.
+///
+/// Arrays are lists and struct values are `SimpleNamespace`s, so fields are attributes like in
+/// javascript. Impl blocks become classes of plain functions taking `self` as their first
+/// parameter, like the object mode of the javascript compiler. Getters and setters become regular
+/// functions of the class
+pub struct PythonCompiler {
+    options: PythonCompilerOptions,
+}
+
+impl PythonCompiler {
+    pub fn with_options(options: PythonCompilerOptions) -> Self {
+        PythonCompiler { options }
+    }
+}
+
+impl Compiler for PythonCompiler {
+    fn new() -> Self {
+        Self::with_options(PythonCompilerOptions::default())
+    }
+
+    fn compile(&self, program: Program) -> String {
+        let mut ctx = PythonCompilerContext::new(&self.options);
+        ctx.shadowed_prelude = shadowed_prelude(&program);
+        let mut body = String::new();
+        ctx.statements(
+            &lower_uses(lower_type_aliases(lower_discriminants(lower_method_calls(
+                lower_iterators(lower_index_operators(lower_with(program))),
+            )))),
+            0,
+            &mut body,
+        );
+        // imports and helpers are only emitted if the program needs them
+        let mut code = ctx
+            .imports
+            .iter()
+            .map(|import| import.statement())
+            .chain(ctx.helpers.iter().map(|helper| helper.definition()))
+            .collect::<String>();
+        code.push_str(&body);
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use oxidescript::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+        stack::with_stack,
+    };
+
+    use super::*;
+
+    fn compile(input: &str) -> String {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        PythonCompiler::new().compile(program)
+    }
+
+    #[test]
+    fn functions_are_hoisted() {
+        assert_eq!(
+            compile(
+                "let x = double(2);
+                fn double(n: number) {
+                    n * 2
+                }
+                fn noop() {}"
+            ),
+            "def double(n):
+    return (n * 2)
+def noop():
+    pass
+x = double(2)
+"
+        );
+    }
+
+    #[test]
+    fn expressions_are_lifted() {
+        assert_eq!(
+            compile(
+                r#"fn describe(n: number, from: string) {
+                    let kind = if n < 0 { "negative" } else if n == 0 { "zero" } else { "positive" };
+                    let n = { let doubled = n * 2; doubled + 1 };
+                    let squares = for i in [1, 2] { i * i };
+                    kind + from
+                }
+                let ok = true && { let x = 1; x > 0 };
+                let query = sql`SELECT * WHERE ok = {ok}`;"#
+            ),
+            r#"def _oxidescript_add(a, b):
+    if isinstance(a, str) or isinstance(b, str):
+        return str(a) + str(b)
+    return a + b
+def describe(n, from_):
+    if (n < 0):
+        _value_2 = "negative"
+    elif (n == 0):
+        _value_2 = "zero"
+    else:
+        _value_2 = "positive"
+    kind = _value_2
+    doubled = (n * 2)
+    _value_3 = _oxidescript_add(doubled, 1)
+    n_1 = _value_3
+    _value_4 = []
+    for i in [1, 2]:
+        _value_4.append((i * i))
+    squares = _value_4
+    return _oxidescript_add(kind, from_)
+_value_1 = True
+if _value_1:
+    x = 1
+    _value_0 = (x > 0)
+    _value_1 = _value_0
+ok = _value_1
+query = sql(["SELECT * WHERE ok = ", ""], ok)
+"#
+        );
+    }
+
+    #[test]
+    fn while_and_match() {
+        assert_eq!(
+            compile(
+                r#"while queue.length > 0 { queue.pop(); }
+                while if ready() { false } else { true } { wait(); }
+                let results = while more() { next() };
+                let label = match next() { 1 => "one", _ => "other" };
+                let user = match path { "/users/" .. id if id != "" => id, _ => "" };"#
+            ),
+            r#"while (len(queue) > 0):
+    queue.pop()
+while True:
+    if ready():
+        _value_0 = False
+    else:
+        _value_0 = True
+    if not _value_0:
+        break
+    wait()
+_value_1 = []
+while more():
+    _value_1.append(next())
+results = _value_1
+__match = next()
+if (__match == 1):
+    _value_2 = "one"
+else:
+    _value_2 = "other"
+label = _value_2
+if (path.startswith("/users/") and (path[len("/users/"):] != "")):
+    id = path[len("/users/"):]
+    _value_3 = id
+else:
+    _value_3 = ""
+user = _value_3
+"#
+        );
+    }
+
+    #[test]
+    fn structs_and_modules() {
+        assert_eq!(
+            compile(
+                "struct Point { x: number, y: number }
+                impl Point {
+                    const ORIGIN = Self { x: 0, y: 0 };
+                    fn new(x: number, y: number) { Self { x: x, y: y } }
+                    fn sum(self) { self.x + self.y }
+                }
+                mod geometry {
+                    const SCALE = 2;
+                    pub fn scale(p: Point) { Point::new(p.x * SCALE, p.y * SCALE) }
+                }
+                let p = geometry::scale(clone(Point::ORIGIN));
+                eprintln(p.x, \"done\");"
+            ),
+            r#"import copy
+import sys
+from types import SimpleNamespace
+def _oxidescript_add(a, b):
+    if isinstance(a, str) or isinstance(b, str):
+        return str(a) + str(b)
+    return a + b
+class Point:
+    def new(x, y):
+        return SimpleNamespace(x=x, y=y)
+    def sum(self):
+        return _oxidescript_add(self.x, self.y)
+Point.ORIGIN = SimpleNamespace(x=0, y=0)
+def _module_geometry():
+    def scale(p):
+        return Point.new((p.x * SCALE), (p.y * SCALE))
+    SCALE = 2
+    return SimpleNamespace(scale=scale)
+geometry = _module_geometry()
+p = geometry.scale(copy.deepcopy(Point.ORIGIN))
+print(p.x, "done", file=sys.stderr)
+"#
+        );
+    }
+
+    #[test]
+    fn enums() {
+        assert_eq!(
+            compile(
+                "enum Shape { Circle(number), Empty }
+                impl Shape {
+                    fn area(self) {
+                        match self {
+                            Self::Circle(r) if r > 0 => r * r,
+                            Self::Circle(_) | Self::Empty => 0,
+                        }
+                    }
+                }
+                let a = Shape::area(Shape::Circle(2));"
+            ),
+            r#"from types import SimpleNamespace
+class Shape:
+    def Circle(value_0):
+        return SimpleNamespace(tag="Circle", values=[value_0])
+    def area(self):
+        if ((self.tag == "Circle") and (self.values[0] > 0)):
+            r = self.values[0]
+            return (r * r)
+        else:
+            return 0
+Shape.Empty = SimpleNamespace(tag="Empty", values=[])
+a = Shape.area(Shape.Circle(2))
+"#
+        );
+    }
+
+    #[test]
+    fn recursive_data_types() {
+        assert_eq!(
+            compile(
+                "enum Tree { Leaf(number), Node(Tree, Tree) }
+                fn sum(tree: Tree) -> number {
+                    match tree {
+                        Tree::Leaf(value) => value,
+                        Tree::Node(left, right) => sum(left) + sum(right),
+                    }
+                }
+                let total = sum(Tree::Node(Tree::Leaf(1), Tree::Node(Tree::Leaf(2), Tree::Leaf(3))));"
+            ),
+            r#"from types import SimpleNamespace
+def _oxidescript_add(a, b):
+    if isinstance(a, str) or isinstance(b, str):
+        return str(a) + str(b)
+    return a + b
+def sum(tree):
+    if (tree.tag == "Leaf"):
+        value = tree.values[0]
+        return value
+    else:
+        left = tree.values[0]
+        right = tree.values[1]
+        return _oxidescript_add(sum(left), sum(right))
+class Tree:
+    def Leaf(value_0):
+        return SimpleNamespace(tag="Leaf", values=[value_0])
+    def Node(value_0, value_1):
+        return SimpleNamespace(tag="Node", values=[value_0, value_1])
+total = sum(Tree.Node(Tree.Leaf(1), Tree.Node(Tree.Leaf(2), Tree.Leaf(3))))
+"#
+        );
+    }
+
+    #[test]
+    fn method_calls_through_values() {
+        // struct values are plain namespaces, so methods are called on the type with the value as
+        // `self`
+        assert_eq!(
+            compile(
+                "struct Counter { count: number }
+                impl Counter {
+                    fn new(count: number) -> Counter { Counter { count: count } }
+                    fn get(self) -> number { self.count }
+                    fn scaled(self, by: number) -> Counter { Counter::new(self.get() * by) }
+                }
+                let counter = Counter::new(2);
+                let scaled = counter.scaled(3);
+                let count = scaled.scaled(2).get();
+                let get = |value: Counter| value.get();
+                println(get(scaled));"
+            ),
+            r#"from types import SimpleNamespace
+class Counter:
+    def new(count):
+        return SimpleNamespace(count=count)
+    def get(self):
+        return self.count
+    def scaled(self, by):
+        return Counter.new((Counter.get(self) * by))
+counter = Counter.new(2)
+scaled = Counter.scaled(counter, 3)
+count = Counter.get(Counter.scaled(scaled, 2))
+get = (lambda value: Counter.get(value))
+print(get(scaled))
+"#
+        );
+    }
+
+    #[test]
+    fn builder_chains() {
+        // methods returning `Self` have the type of the impl block, so their results are called
+        // through it too
+        assert_eq!(
+            compile(
+                "struct Query { depth: number }
+                impl Query {
+                    fn new() -> Self { Self { depth: 0 } }
+                    fn and(self) -> Self { Self { depth: self.depth - 1 } }
+                    fn twice(self) -> Self { self.and().and() }
+                }
+                let depth = Query::new().and().twice().depth;"
+            ),
+            r#"from types import SimpleNamespace
+class Query:
+    def new():
+        return SimpleNamespace(depth=0)
+    def and_(self):
+        return SimpleNamespace(depth=(self.depth - 1))
+    def twice(self):
+        return Query.and_(Query.and_(self))
+depth = Query.twice(Query.and_(Query.new())).depth
+"#
+        );
+    }
+
+    #[test]
+    fn inferred_method_receivers() {
+        // functions and methods without a return type return the type of their last value, so
+        // methods are called through the type on their results and on arrays of them too
+        assert_eq!(
+            compile(
+                "struct Point { x: number, y: number }
+                impl Point {
+                    fn area(self) { self.x * self.y }
+                    fn scaled(self, by: number) { Point { x: self.x * by, y: self.y * by } }
+                }
+                fn at(x: number) { Point { x: x, y: 4 } }
+                let area = at(3).scaled(2).area();
+                for point in [at(1), at(2)] { println(point.area()); }"
+            ),
+            r#"from types import SimpleNamespace
+def at(x):
+    return SimpleNamespace(x=x, y=4)
+class Point:
+    def area(self):
+        return (self.x * self.y)
+    def scaled(self, by):
+        return SimpleNamespace(x=(self.x * by), y=(self.y * by))
+area = Point.area(Point.scaled(at(3), 2))
+for point in [at(1), at(2)]:
+    print(Point.area(point))
+"#
+        );
+    }
+
+    #[test]
+    fn methods_named_like_fields() {
+        // `value.area` is the field, the method is called through the type
+        assert_eq!(
+            compile(
+                "struct Shape { area: number, side: number }
+                impl Shape {
+                    fn area(self) -> number { self.side * self.side }
+                }
+                let shape = Shape { area: 0, side: 3 };
+                let stored = shape.area;
+                let computed = Shape::area(shape);"
+            ),
+            r#"from types import SimpleNamespace
+class Shape:
+    def area(self):
+        return (self.side * self.side)
+shape = SimpleNamespace(area=0, side=3)
+stored = shape.area
+computed = Shape.area(shape)
+"#
+        );
+    }
+
+    #[test]
+    fn index_operator() {
+        // indexing a type implementing `Index` calls its `get`, arrays inside `get` are still indexed directly
+        assert_eq!(
+            compile(
+                "struct Rounds { points: [number] }
+                impl Index for Rounds {
+                    fn get(self, round: number) -> number { self.points[round - 1] }
+                }
+                let rounds = Rounds { points: [3, 5] };
+                let first = rounds[1];"
+            ),
+            r#"from types import SimpleNamespace
+class Rounds:
+    def get(self, round):
+        return self.points[(round - 1)]
+rounds = SimpleNamespace(points=[3, 5])
+first = Rounds.get(rounds, 1)
+"#
+        );
+    }
+
+    #[test]
+    fn iterators() {
+        // `for` over an `Iterator` calls `next` until it returns nothing
+        assert_eq!(
+            compile(
+                "struct Countdown { left: number }
+                impl Iterator for Countdown {
+                    fn next(self) -> number | null {
+                        if self.left == 0 { return; }
+                        self.left = self.left - 1;
+                        self.left * 10
+                    }
+                }
+                for n in Countdown { left: 3 } { print(n); }"
+            ),
+            r#"from types import SimpleNamespace
+class Countdown:
+    def next(self):
+        if (self.left == 0):
+            return
+        self.left = (self.left - 1)
+        return (self.left * 10)
+__iterator_0 = SimpleNamespace(left=3)
+while True:
+    n = Countdown.next(__iterator_0)
+    if (n is None):
+        break
+    print(n)
+"#
+        );
+    }
+
+    #[test]
+    fn defer() {
+        // the rest of the block runs before the `defer`, even if it returns early or fails
+        assert_eq!(
+            compile(
+                "fn read(file: number) -> number {
+                    defer { close(file); }
+                    if file == 0 { return 0; }
+                    file * 2
+                }
+                for file in files {
+                    defer { close(file); }
+                    print(file);
+                }"
+            ),
+            r#"def read(file):
+    try:
+        if (file == 0):
+            return 0
+        return (file * 2)
+    finally:
+        close(file)
+for file in files:
+    try:
+        print(file)
+    finally:
+        close(file)
+"#
+        );
+    }
+
+    #[test]
+    fn panic() {
+        assert_eq!(
+            compile("let size = if n > 0 { n } else { panic(\"negative\") };"),
+            r#"def _oxidescript_panic(message):
+    raise RuntimeError(message)
+if (n > 0):
+    _value_0 = n
+else:
+    _value_0 = _oxidescript_panic("negative")
+size = _value_0
+"#
+        );
+    }
+
+    #[test]
+    fn shadowed_prelude() {
+        assert_eq!(
+            compile("fn println(x: number) { x } println(1); eprintln(2);"),
+            r#"import sys
+def println(x):
+    return x
+println(1)
+print(2, file=sys.stderr)
+"#
+        );
+    }
+
+    #[test]
+    fn with_blocks() {
+        // the value is closed when the body is left, like with a `defer`
+        assert_eq!(
+            compile(
+                "fn first_line(path: string) -> string {
+                    with file = open(path) {
+                        file.read_line()
+                    }
+                }"
+            ),
+            r#"def first_line(path):
+    file = open(path)
+    try:
+        return file.read_line()
+    finally:
+        file.close()
+"#
+        );
+    }
+
+    #[test]
+    fn async_functions() {
+        // `async` functions compile to regular functions, there is nothing to wait with
+        assert_eq!(
+            compile("async fn load(path: string) -> string { fetch(path).await }"),
+            r#"def load(path):
+    raise NotImplementedError(".await is not supported by the python target")
+    return None
+"#
+        );
+    }
+
+    #[test]
+    fn generic_data_types() {
+        assert_eq!(
+            compile(
+                r#"struct Pair<A, B> { first: A, second: B }
+                enum Option<T> { Some(T), None }
+                fn first(o: Option<Pair<number, string>>) {
+                    match o {
+                        Option::Some(pair) => pair.first,
+                        Option::None => 0,
+                    }
+                }
+                let a = first(Option::Some(Pair { first: 1, second: "a" }));"#
+            ),
+            r#"from types import SimpleNamespace
+def first(o):
+    if (o.tag == "Some"):
+        pair = o.values[0]
+        return pair.first
+    else:
+        return 0
+class Option:
+    def Some(value_0):
+        return SimpleNamespace(tag="Some", values=[value_0])
+Option.None_ = SimpleNamespace(tag="None", values=[])
+a = first(Option.Some(SimpleNamespace(first=1, second="a")))
+"#
+        );
+    }
+
+    #[test]
+    fn traits() {
+        assert_eq!(
+            compile(
+                r#"trait Ord { fn cmp(self, other: Self) -> number; }
+                struct Version { major: number }
+                impl Ord for Version {
+                    fn cmp(self, other: Version) -> number { self.major - other.major }
+                }
+                fn max<T: Ord>(a: T, b: T) -> T {
+                    if a.cmp(b) > 0 { a } else { b }
+                }"#
+            ),
+            r#"def max(a, b):
+    if (a.cmp(b) > 0):
+        return a
+    else:
+        return b
+class Version:
+    def cmp(self, other):
+        return (self.major - other.major)
+"#
+        );
+    }
+
+    #[test]
+    fn default_trait_methods() {
+        assert_eq!(
+            compile(
+                r#"trait Describe {
+                    fn name(self) -> string;
+                    fn describe(self) -> string { self.name() }
+                }
+                struct Cat { age: number }
+                struct Dog { age: number }
+                impl Describe for Cat {
+                    fn name(self) -> string { "cat" }
+                }
+                impl Describe for Dog {
+                    fn name(self) -> string { "dog" }
+                    fn describe(self) -> string { "a good dog" }
+                }"#
+            ),
+            r#"class Describe:
+    def describe(self):
+        return self.name()
+class Cat:
+    def name(self):
+        return "cat"
+    describe = Describe.describe
+class Dog:
+    def name(self):
+        return "dog"
+    def describe(self):
+        return "a good dog"
+"#
+        );
+    }
+
+    #[test]
+    fn mod_and_use_declarations() {
+        assert_eq!(
+            compile(
+                "mod util;
+                use util::double;
+                mod geometry { pub const SCALE = 2; }
+                fn scaled(x: number) {
+                    use geometry::SCALE;
+                    double(x) * SCALE
+                }"
+            ),
+            r#"from types import SimpleNamespace
+def scaled(x):
+    SCALE = geometry.SCALE
+    return (double(x) * SCALE)
+import util
+double = util.double
+def _module_geometry():
+    SCALE = 2
+    return SimpleNamespace(SCALE=SCALE)
+geometry = _module_geometry()
+"#
+        );
+    }
+
+    #[test]
+    fn enum_discriminants() {
+        assert_eq!(
+            compile(
+                "enum Status { Ok = 200, NotFound = 404 }
+                let code = match s { Status::Ok => Status::Ok as number, Status::NotFound => 0 };"
+            ),
+            r#"class Status:
+    pass
+Status.Ok = 200
+Status.NotFound = 404
+if (s == 200):
+    _value_0 = Status.Ok
+else:
+    _value_0 = 0
+code = _value_0
+"#
+        );
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            compile(
+                "#[derive(Flags)] enum Mode { Fast, Safe }
+                let safe = Mode::contains(Mode::all(), Mode::Safe);"
+            ),
+            r#"class Mode:
+    def empty():
+        return 0
+    def all():
+        return 3
+    def contains(flags, other):
+        return ((flags & other) == other)
+Mode.Fast = 1
+Mode.Safe = 2
+safe = Mode.contains(Mode.all(), Mode.Safe)
+"#
+        );
+    }
+
+    #[test]
+    fn optional_chains() {
+        assert_eq!(
+            compile(
+                "fn first(items: any, map: any) {
+                    let x = items?[0];
+                    map?.get(x)
+                }"
+            ),
+            r#"def first(items, map):
+    _value_0 = items
+    if _value_0 is not None:
+        _value_0 = _value_0[0]
+    x = _value_0
+    _value_1 = map
+    if _value_1 is not None:
+        _value_1 = _value_1.get(x)
+    return _value_1
+"#
+        );
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(
+            compile(r#"let greeting = f"hello {name}, {count + 1} times";"#),
+            r#"def _oxidescript_add(a, b):
+    if isinstance(a, str) or isinstance(b, str):
+        return str(a) + str(b)
+    return a + b
+greeting = (_oxidescript_add((_oxidescript_add("hello ", name) + ", "), _oxidescript_add(count, 1)) + " times")
+"#
+        );
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(
+            compile(
+                "fn main() {
+                    let count = 0;
+                    fn reset() {
+                        count = 0;
+                    }
+                    lookup(key).value *= 2;
+                    let last = count -= 1;
+                }"
+            ),
+            "def main():
+    def reset():
+        nonlocal count
+        count = 0
+    count = 0
+    _value_0 = lookup(key)
+    _value_0.value = (_value_0.value * 2)
+    last = (count := (count - 1))
+"
+        );
+    }
+
+    #[test]
+    fn slices() {
+        let input = "let middle = items[1..n]; let tail = name[2..];";
+        assert_eq!(
+            compile(input),
+            "middle = items[1:n]
+tail = name[2:]
+"
+        );
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        let checked = PythonCompiler::with_options(PythonCompilerOptions {
+            prelude: true,
+            bounds_checks: true,
+        });
+        assert!(checked.compile(program).ends_with(
+            "middle = _oxidescript_slice(items, 1, n)
+tail = _oxidescript_slice(name, 2, None)
+"
+        ));
+    }
+
+    #[test]
+    fn ranges() {
+        // loops use the range directly, ranges used as values are lists
+        assert_eq!(
+            compile("for i in 0..n { f(i); } for i in 1..=3 { f(i); } let all = 0..=n;"),
+            "for i in range(0, n):
+    f(i)
+for i in range(1, 4):
+    f(i)
+all = list(range(0, n + 1))
+"
+        );
+    }
+
+    #[test]
+    fn tuples() {
+        assert_eq!(
+            compile(
+                "let pair = (1, \"a\"); let (first, second) = pair; let ((a, _), c) = f(); \
+                 log(pair.1, (5,).0);"
+            ),
+            r#"pair = (1, "a")
+first, second = pair
+(a, _), c = f()
+log(pair[1], (5,)[0])
+"#
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        assert_eq!(
+            compile(
+                "let Point { x, y: (a, _) } = p; let Point { x: left, .. } = f(); \
+                 fn area(Point { x, y }: Point) -> number { x * y }"
+            ),
+            r#"def area(__arg1):
+    x_1, y = __arg1.x, __arg1.y
+    return (x_1 * y)
+x, a = p.x, p.y[0]
+_value_0 = f()
+left = _value_0.x
+"#
+        );
+    }
+
+    #[test]
+    fn grids() {
+        assert_eq!(
+            compile("let grid = array::new_2d(width, height, 0); grid[y][x] = 1;"),
+            "def _oxidescript_new_2d(width, height, init):
+    return [[init] * width for _ in range(height)]
+grid = _oxidescript_new_2d(width, height, 0)
+_value_0 = grid[y]
+_value_0[x] = 1
+"
+        );
+    }
+
+    #[test]
+    fn closures() {
+        assert_eq!(
+            compile(
+                "fn counter() {
+                    let count = 0;
+                    let take = |n: number| { count -= n; count };
+                    sort(|a, b| a - b)
+                }"
+            ),
+            "def counter():
+    count = 0
+    def _closure_0(n):
+        nonlocal count
+        count = (count - n)
+        return count
+    take = _closure_0
+    return sort((lambda a, b: (a - b)))
+"
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(
+            compile(
+                "fn first_positive(items: [number]) {
+                    for item in items {
+                        if item < 0 { continue; }
+                        if item == 0 { break; }
+                        return item;
+                    }
+                    let fallback = items[0] ?? return;
+                    fallback
+                }"
+            ),
+            "def first_positive(items):
+    for item in items:
+        if (item < 0):
+            continue
+        if (item == 0):
+            break
+        return item
+    _value_0 = items[0]
+    if _value_0 is None:
+        return
+        _value_0 = None
+    fallback = _value_0
+    return fallback
+"
+        );
+    }
+
+    #[test]
+    fn remainder_has_sign_of_dividend() {
+        assert_eq!(
+            compile("let last = index % length;"),
+            "def _oxidescript_remainder(a, b):
+    remainder = a % b
+    if remainder != 0 and (remainder < 0) != (a < 0):
+        return remainder - b
+    return remainder
+last = _oxidescript_remainder(index, length)
+"
+        );
+    }
+
+    #[test]
+    fn checked_operations() {
+        assert_eq!(
+            compile("let total = checked_add(a, b); let ratio = checked_div(a, -b);"),
+            "def _oxidescript_checked(result):
+    return result if abs(result) <= 9007199254740991 else None
+def _oxidescript_checked_div(a, b):
+    if b == 0:
+        return None
+    return _oxidescript_checked(a / b)
+total = _oxidescript_checked(a + b)
+ratio = _oxidescript_checked_div(a, -b)
+"
+        );
+    }
+
+    #[test]
+    fn nullish_coalescing() {
+        assert_eq!(
+            compile(r#"let port = config.port ?? 8080; let name = name ?? "anonymous";"#),
+            r#"_value_0 = config.port
+if _value_0 is None:
+    _value_0 = 8080
+port = _value_0
+name = ("anonymous" if name is None else name)
+"#
+        );
+    }
+
+    #[test]
+    fn escaped_strings() {
+        assert_eq!(
+            compile(r#"let s = "say \"hi\"\n\tC:\\ \u{1F496}";"#),
+            r#"s = "say \"hi\"\n\tC:\\ 💖"
+"#
+        );
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(
+            compile(
+                "let a = 1_000; let b = 0xFF; let c = 0b1010; let d = 1e9; let e = 2.5e-3;
+                let f = 0xFFFF_FFFF;"
+            ),
+            "a = 1000\nb = 255\nc = 10\nd = 1000000000\ne = 0.0025\nf = 4294967295\n"
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 1000;
+        let arrays = with_stack(|| {
+            compile(&format!(
+                "let a = {}1{};",
+                "[".repeat(depth),
+                "]".repeat(depth)
+            ))
+        });
+        assert_eq!(
+            arrays,
+            format!("a = {}1{}\n", "[".repeat(depth), "]".repeat(depth))
+        );
+        let ifs = with_stack(|| {
+            compile(&format!(
+                "let a = {}1{};",
+                "if x { ".repeat(depth),
+                " } else { 0 }".repeat(depth)
+            ))
+        });
+        assert!(ifs.contains(&format!("\n{}_value_0 = 1\n", "    ".repeat(depth))));
+    }
+
+    #[test]
+    fn is_expressions() {
+        assert_eq!(
+            compile(
+                r#"let a = value is string | null;
+                let b = items[0] is number;
+                let c = config is [Config];
+                type Method = "GET" | "POST";
+                let d = method is Method | 404;"#
+            ),
+            r#"a = (isinstance(value, str) or (value is None))
+_value_0 = items[0]
+b = (type(_value_0) in (int, float))
+c = isinstance(config, list)
+d = ((method == "GET") or (method == "POST") or (method == 404))
+"#
+        );
+    }
+
+    #[test]
+    fn types_and_externs() {
+        assert_eq!(
+            compile(
+                r#"type Config = { host: string, port: number };
+                extern fn load_config(path: string) -> Config;
+                let port = load_config("app.json").port;"#
+            ),
+            "port = load_config(\"app.json\").port\n"
+        );
+    }
+
+    /// Needs a Python interpreter, so it is skipped without one
+    #[test]
+    fn runs_with_python() {
+        let code = compile(
+            r#"fn fibonacci(n: number) {
+                if n < 2 { n } else { fibonacci(n - 1) + fibonacci(n - 2) }
+            }
+            let evens = for n in [1, 2, 3, 4] { if n % 2 == 0 { n } else { 0 } };
+            println("fibonacci " + fibonacci(10), evens.length, evens[1]);"#,
+        );
+        let Ok(output) = Command::new("python3").arg("-c").arg(&code).output() else {
+            return;
+        };
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "fibonacci 55 4 2\n",
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    /// Declarations named like builtins the output uses are renamed, so they don't hide them.
+    /// Needs a Python interpreter, so it is skipped without one
+    #[test]
+    fn builtins_are_not_hidden() {
+        let code = compile(
+            r#"let str = "s";
+            let print = 5;
+            fn len(list: number) -> number { list + 1 }
+            let isinstance = |int: number| int * 2;
+            println(str + print, len(2), isinstance(3));"#,
+        );
+        assert!(code.contains("str_ = \"s\"\nprint_ = 5\n"), "{}", code);
+        let Ok(output) = Command::new("python3").arg("-c").arg(&code).output() else {
+            return;
+        };
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "s5 3 6\n",
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
@@ -0,0 +1,601 @@
+use std::{collections::HashMap, fmt::Display};
+
+use oxidescript::parser::ast::{
+    Block, CallExpr, Declaration, Expression, Identifier, InfixOperator, Literal, MemberAccessExpr,
+    Program, Span, Statement, UnaryOperator,
+};
+use wasm_encoder::{
+    BlockType, CodeSection, ExportKind, ExportSection, Function, FunctionSection, Instruction,
+    Module, TypeSection, ValType,
+};
+
+/// Attribute selecting the functions that are compiled to WebAssembly
+pub const WASM_ATTRIBUTE: &str = "wasm";
+
+/// The javascript binding holding the exports of the instance
+const INSTANCE_NAME: &str = "__oxidescript_wasm";
+
+pub struct WasmModule {
+    pub bytes: Vec<u8>,
+}
+
+impl WasmModule {
+    /// Javascript instantiating the module from the bytes inlined as base64. It has to come before
+    /// any call to a `#[wasm]` function. Browsers only compile small modules synchronously on the
+    /// main thread, bigger ones need to run in a worker
+    pub fn glue(&self) -> String {
+        format!(
+            "const {} = new WebAssembly.Instance(new WebAssembly.Module(Uint8Array.from(atob(\"{}\"), (c) => c.charCodeAt(0)))).exports;\n",
+            INSTANCE_NAME,
+            base64(&self.bytes)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct WasmError {
+    pub function: String,
+    pub message: String,
+}
+
+impl Display for WasmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "#[wasm] function {} can't be compiled: {}",
+            self.function, self.message
+        )
+    }
+}
+
+/// Compiles every `#[wasm]` function of the program, including the ones in `mod` blocks, into one
+/// module, and replaces their bodies with a call to the export of the same name. Returns `None`
+/// if there are no such functions.
+///
+/// This is an experiment for numeric code: parameters and return values are numbers, and the
+/// body can only use numbers, booleans, local `let`s, `if` and calls to other `#[wasm]` functions
+pub fn compile(program: &mut Program) -> Result<Option<WasmModule>, WasmError> {
+    let mut declarations = vec![];
+    for statement in program.iter_mut() {
+        if let Statement::DeclarationStatement(declaration, _) = statement {
+            collect_wasm_functions(declaration, &mut declarations);
+        }
+    }
+    if declarations.is_empty() {
+        return Ok(None);
+    }
+
+    let mut signatures = HashMap::new();
+    for (index, declaration) in declarations.iter().enumerate() {
+        let Declaration::FunctionDeclaration {
+            name, parameters, ..
+        } = &**declaration
+        else {
+            unreachable!("only functions are collected");
+        };
+        let error = |message: String| WasmError {
+            function: name.0.clone(),
+            message,
+        };
+        if let Some(parameter) = parameters
+            .iter()
+            .find(|parameter| parameter.type_.0 != "number")
+        {
+            return Err(error(format!(
+                "parameter {} is a {}, only numbers are supported",
+                parameter.name.0, parameter.type_.0
+            )));
+        }
+        if signatures
+            .insert(name.0.clone(), (index as u32, parameters.len()))
+            .is_some()
+        {
+            return Err(error(
+                "there is another #[wasm] function of the same name".to_string(),
+            ));
+        }
+    }
+
+    let mut types = TypeSection::new();
+    let mut functions = FunctionSection::new();
+    let mut exports = ExportSection::new();
+    let mut code = CodeSection::new();
+    for (index, declaration) in declarations.iter_mut().enumerate() {
+        let Declaration::FunctionDeclaration {
+            name,
+            parameters,
+            body,
+            ..
+        } = &mut **declaration
+        else {
+            unreachable!("only functions are collected");
+        };
+        let mut compiler = FunctionCompiler {
+            signatures: &signatures,
+            parameter_count: parameters.len() as u32,
+            locals: vec![],
+            scopes: vec![parameters
+                .iter()
+                .enumerate()
+                .map(|(index, parameter)| (parameter.name.0.clone(), (index as u32, Type::Number)))
+                .collect()],
+            instructions: vec![],
+        };
+        match compiler.block(body) {
+            Ok(Type::Number) => {}
+            Ok(_) => {
+                return Err(WasmError {
+                    function: name.0.clone(),
+                    message: "it has to return a number".to_string(),
+                })
+            }
+            Err(message) => {
+                return Err(WasmError {
+                    function: name.0.clone(),
+                    message,
+                })
+            }
+        }
+
+        types
+            .ty()
+            .function(vec![ValType::F64; parameters.len()], [ValType::F64]);
+        functions.function(index as u32);
+        exports.export(&name.0, ExportKind::Func, index as u32);
+        let mut function = Function::new_with_locals_types(compiler.locals);
+        for instruction in &compiler.instructions {
+            function.instruction(instruction);
+        }
+        function.instruction(&Instruction::End);
+        code.function(&function);
+
+        *body = Block {
+            statements: vec![],
+            return_value: Some(Expression::CallExpression(
+                CallExpr {
+                    lhs: Box::new(Expression::MemberAccessExpression(
+                        MemberAccessExpr {
+                            lhs: Box::new(Expression::IdentifierExpression(
+                                Identifier(INSTANCE_NAME.to_string()),
+                                Span::default(),
+                            )),
+                            ident: name.clone(),
+                        },
+                        Span::default(),
+                    )),
+                    arguments: parameters
+                        .iter()
+                        .map(|parameter| {
+                            Expression::IdentifierExpression(
+                                parameter.name.clone(),
+                                Span::default(),
+                            )
+                        })
+                        .collect(),
+                },
+                Span::default(),
+            )),
+            span: Span::default(),
+        };
+    }
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&functions)
+        .section(&exports)
+        .section(&code);
+    Ok(Some(WasmModule {
+        bytes: module.finish(),
+    }))
+}
+
+fn collect_wasm_functions<'p>(
+    declaration: &'p mut Declaration,
+    declarations: &mut Vec<&'p mut Declaration>,
+) {
+    match declaration {
+        Declaration::FunctionDeclaration { attributes, .. }
+            if attributes
+                .iter()
+                .any(|attribute| attribute.0 == WASM_ATTRIBUTE) =>
+        {
+            declarations.push(declaration);
+        }
+        Declaration::ModDeclaration(decl) => {
+            for item in &mut decl.items {
+                collect_wasm_functions(&mut item.declaration, declarations);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Type {
+    /// `f64`, like javascript numbers
+    Number,
+    /// `i32` that is either 0 or 1
+    Boolean,
+    /// No value on the stack
+    Unit,
+}
+
+impl Type {
+    fn block_type(self) -> BlockType {
+        match self {
+            Type::Number => BlockType::Result(ValType::F64),
+            Type::Boolean => BlockType::Result(ValType::I32),
+            Type::Unit => BlockType::Empty,
+        }
+    }
+}
+
+struct FunctionCompiler<'s> {
+    /// Index and parameter count of every `#[wasm]` function
+    signatures: &'s HashMap<String, (u32, usize)>,
+    parameter_count: u32,
+    /// Locals after the parameters
+    locals: Vec<ValType>,
+    scopes: Vec<HashMap<String, (u32, Type)>>,
+    instructions: Vec<Instruction<'static>>,
+}
+
+impl FunctionCompiler<'_> {
+    fn new_local(&mut self, type_: Type) -> u32 {
+        self.locals.push(match type_ {
+            Type::Boolean => ValType::I32,
+            _ => ValType::F64,
+        });
+        self.parameter_count + self.locals.len() as u32 - 1
+    }
+
+    fn block(&mut self, block: &Block) -> Result<Type, String> {
+        self.scopes.push(HashMap::new());
+        for statement in &block.statements {
+            self.statement(statement)?;
+        }
+        let type_ = match &block.return_value {
+            Some(return_value) => self.expression(return_value)?,
+            None => Type::Unit,
+        };
+        self.scopes.pop();
+        Ok(type_)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::ExpressionStatement { expression, .. } => {
+                if self.expression(expression)? != Type::Unit {
+                    self.instructions.push(Instruction::Drop);
+                }
+            }
+            Statement::DeclarationStatement(
+                Declaration::LetDeclaration(name, value, _, _)
+                | Declaration::ConstDeclaration(name, value, _, _, _),
+                _,
+            ) => {
+                let type_ = self.expression(value)?;
+                if type_ == Type::Unit {
+                    return Err(format!("{} has no value", name.0));
+                }
+                let local = self.new_local(type_);
+                self.instructions.push(Instruction::LocalSet(local));
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(name.0.clone(), (local, type_));
+            }
+            Statement::DeclarationStatement(_, _) => {
+                return Err("only let and const declarations are supported".to_string())
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<Type, String> {
+        match expression {
+            Expression::LiteralExpression(Literal::NumberLiteral(number), _) => {
+                let value = f64::try_from(number.clone())
+                    .map_err(|_| format!("invalid number {}", number))?;
+                self.instructions.push(Instruction::F64Const(value));
+                Ok(Type::Number)
+            }
+            Expression::LiteralExpression(Literal::BooleanLiteral(value), _) => {
+                self.instructions.push(Instruction::I32Const(*value as i32));
+                Ok(Type::Boolean)
+            }
+            Expression::IdentifierExpression(ident, _) => {
+                let (local, type_) = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(&ident.0))
+                    .ok_or_else(|| format!("{} is not a parameter or local", ident.0))?;
+                self.instructions.push(Instruction::LocalGet(*local));
+                Ok(*type_)
+            }
+            Expression::UnaryExpression(expr, _) => {
+                let type_ = self.expression(&expr.rhs)?;
+                match (&expr.op, type_) {
+                    (UnaryOperator::Plus, Type::Number) => {}
+                    (UnaryOperator::Minus, Type::Number) => {
+                        self.instructions.push(Instruction::F64Neg)
+                    }
+                    (UnaryOperator::LogicalNot, Type::Boolean) => {
+                        self.instructions.push(Instruction::I32Eqz)
+                    }
+                    (op, type_) => return Err(format!("{:?} of {:?} is not supported", op, type_)),
+                }
+                Ok(type_)
+            }
+            Expression::InfixExpression(expr, _) => self.infix(&expr.op, &expr.lhs, &expr.rhs),
+            Expression::IfExpression(expr, _) => {
+                let else_ifs = expr
+                    .else_if_blocks
+                    .iter()
+                    .map(|else_if| (&*else_if.condition, &else_if.then_block))
+                    .collect::<Vec<_>>();
+                self.if_chain(
+                    &expr.condition,
+                    &expr.then_block,
+                    &else_ifs,
+                    expr.else_block.as_deref(),
+                )
+            }
+            Expression::BlockExpression(block, _) => self.block(block),
+            Expression::CallExpression(CallExpr { lhs, arguments }, _) => {
+                let Expression::IdentifierExpression(name, _) = &**lhs else {
+                    return Err("only calls to other #[wasm] functions are supported".to_string());
+                };
+                let (index, parameter_count) = *self
+                    .signatures
+                    .get(&name.0)
+                    .ok_or_else(|| format!("{} is not a #[wasm] function", name.0))?;
+                if arguments.len() != parameter_count {
+                    return Err(format!(
+                        "{} takes {} arguments, but {} were given",
+                        name.0,
+                        parameter_count,
+                        arguments.len()
+                    ));
+                }
+                for argument in arguments {
+                    self.expect(argument, Type::Number)?;
+                }
+                self.instructions.push(Instruction::Call(index));
+                Ok(Type::Number)
+            }
+            _ => Err(format!("{:?} is not supported", expression)),
+        }
+    }
+
+    fn expect(&mut self, expression: &Expression, expected: Type) -> Result<(), String> {
+        match self.expression(expression)? {
+            type_ if type_ == expected => Ok(()),
+            type_ => Err(format!("expected a {:?}, found a {:?}", expected, type_)),
+        }
+    }
+
+    fn infix(
+        &mut self,
+        op: &InfixOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+    ) -> Result<Type, String> {
+        // short circuiting, like in javascript
+        if matches!(op, InfixOperator::LogicalAnd | InfixOperator::LogicalOr) {
+            self.expect(lhs, Type::Boolean)?;
+            self.instructions
+                .push(Instruction::If(BlockType::Result(ValType::I32)));
+            if *op == InfixOperator::LogicalAnd {
+                self.expect(rhs, Type::Boolean)?;
+                self.instructions.push(Instruction::Else);
+                self.instructions.push(Instruction::I32Const(0));
+            } else {
+                self.instructions.push(Instruction::I32Const(1));
+                self.instructions.push(Instruction::Else);
+                self.expect(rhs, Type::Boolean)?;
+            }
+            self.instructions.push(Instruction::End);
+            return Ok(Type::Boolean);
+        }
+
+        let type_ = self.expression(lhs)?;
+        if type_ == Type::Unit {
+            return Err(format!("{:?} of values without a type", op));
+        }
+        self.expect(rhs, type_)?;
+        let (instruction, result) = match (op, type_) {
+            (InfixOperator::Equal, Type::Boolean) => (Instruction::I32Eq, Type::Boolean),
+            (InfixOperator::NotEqual, Type::Boolean) => (Instruction::I32Ne, Type::Boolean),
+            (InfixOperator::Equal, Type::Number) => (Instruction::F64Eq, Type::Boolean),
+            (InfixOperator::NotEqual, Type::Number) => (Instruction::F64Ne, Type::Boolean),
+            (InfixOperator::LessThan, Type::Number) => (Instruction::F64Lt, Type::Boolean),
+            (InfixOperator::GreaterThan, Type::Number) => (Instruction::F64Gt, Type::Boolean),
+            (InfixOperator::LessThanEqual, Type::Number) => (Instruction::F64Le, Type::Boolean),
+            (InfixOperator::GreaterThanEqual, Type::Number) => (Instruction::F64Ge, Type::Boolean),
+            (InfixOperator::Plus, Type::Number) => (Instruction::F64Add, Type::Number),
+            (InfixOperator::Minus, Type::Number) => (Instruction::F64Sub, Type::Number),
+            (InfixOperator::Multiply, Type::Number) => (Instruction::F64Mul, Type::Number),
+            (InfixOperator::Divide, Type::Number) => (Instruction::F64Div, Type::Number),
+            // wasm has no float remainder, `a - trunc(a / b) * b` has the sign of `a` like `%` in
+            // javascript, but can be off in the last bits for large quotients
+            (InfixOperator::Modulo, Type::Number) => {
+                let divisor = self.new_local(Type::Number);
+                let dividend = self.new_local(Type::Number);
+                self.instructions.extend([
+                    Instruction::LocalSet(divisor),
+                    Instruction::LocalTee(dividend),
+                    Instruction::LocalGet(dividend),
+                    Instruction::LocalGet(divisor),
+                    Instruction::F64Div,
+                    Instruction::F64Trunc,
+                    Instruction::LocalGet(divisor),
+                    Instruction::F64Mul,
+                ]);
+                (Instruction::F64Sub, Type::Number)
+            }
+            (op, type_) => return Err(format!("{:?} of {:?} is not supported", op, type_)),
+        };
+        self.instructions.push(instruction);
+        Ok(result)
+    }
+
+    /// `else if`s are nested into the else branch. The type of the `if` is only known after its
+    /// branches are compiled, so the block type is filled in afterwards
+    fn if_chain(
+        &mut self,
+        condition: &Expression,
+        then_block: &Block,
+        else_ifs: &[(&Expression, &Block)],
+        else_block: Option<&Block>,
+    ) -> Result<Type, String> {
+        self.expect(condition, Type::Boolean)?;
+        let if_index = self.instructions.len();
+        self.instructions.push(Instruction::If(BlockType::Empty));
+        let mut type_ = self.block(then_block)?;
+
+        let else_type = match (else_ifs.split_first(), else_block) {
+            (Some(((condition, then_block), rest)), _) => {
+                self.instructions.push(Instruction::Else);
+                Some(self.if_chain(condition, then_block, rest, else_block)?)
+            }
+            (None, Some(else_block)) => {
+                self.instructions.push(Instruction::Else);
+                Some(self.block(else_block)?)
+            }
+            (None, None) => None,
+        };
+        match else_type {
+            Some(else_type) if else_type != type_ => {
+                return Err(format!(
+                    "the branches of an if are a {:?} and a {:?}",
+                    type_, else_type
+                ))
+            }
+            // without an else branch there is no value in the other case
+            None if type_ != Type::Unit => {
+                self.instructions.push(Instruction::Drop);
+                type_ = Type::Unit;
+            }
+            _ => {}
+        }
+        self.instructions.push(Instruction::End);
+        self.instructions[if_index] = Instruction::If(type_.block_type());
+        Ok(type_)
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | (*byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use oxidescript::{
+        lexer::{tokens::Tokens, Lexer},
+        parser::Parser,
+    };
+
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let (_, tokens) = Lexer::lex_tokens(input.as_bytes()).unwrap();
+        let (_, program) = Parser::parse(Tokens::new(&tokens)).unwrap();
+        program
+    }
+
+    #[test]
+    fn compiles_valid_module() {
+        let mut program = parse(
+            r#"
+            #[wasm]
+            fn fibonacci(n: number) {
+                if n < 2 { n } else { fibonacci(n - 1) + fibonacci(n - 2) }
+            }
+
+            mod math {
+                #[wasm] pub fn wrap(x: number, size: number) {
+                    let wrapped = x % size;
+                    if wrapped < 0 && !(size < 0) { wrapped + size } else if wrapped == 0 { 0 } else { wrapped }
+                }
+            }
+
+            fn plain() { 1 }
+            "#,
+        );
+        let module = compile(&mut program).unwrap().unwrap();
+        wasmparser::validate(&module.bytes).unwrap();
+
+        let Statement::DeclarationStatement(Declaration::FunctionDeclaration { body, .. }, _) =
+            &program[0]
+        else {
+            panic!("expected a function");
+        };
+        assert_eq!(
+            body.return_value,
+            Some(Expression::CallExpression(
+                CallExpr {
+                    lhs: Box::new(Expression::MemberAccessExpression(
+                        MemberAccessExpr {
+                            lhs: Box::new(Expression::IdentifierExpression(
+                                Identifier(INSTANCE_NAME.to_string()),
+                                Span::default()
+                            )),
+                            ident: Identifier("fibonacci".to_string()),
+                        },
+                        Span::default()
+                    )),
+                    arguments: vec![Expression::IdentifierExpression(
+                        Identifier("n".to_string()),
+                        Span::default()
+                    )],
+                },
+                Span::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn without_wasm_functions() {
+        let mut program = parse("fn plain() { 1 }");
+        assert!(compile(&mut program).unwrap().is_none());
+    }
+
+    #[test]
+    fn unsupported_functions() {
+        for input in [
+            "#[wasm] fn greet(name: string) { 1 }",
+            "#[wasm] fn greet(x: number) { \"hello\" }",
+            "#[wasm] fn check(x: number) { x > 1 }",
+            "#[wasm] fn log(x: number) { console.log(x); x }",
+            "#[wasm] fn branches(x: number) { if x > 1 { 1 } else { true } }",
+        ] {
+            assert!(compile(&mut parse(input)).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"\0asm"), "AGFzbQ==");
+    }
+}